@@ -0,0 +1,1169 @@
+use std::str::FromStr;
+
+use ordered_float::NotNan;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    ActuatorId, BatchId, CommandId, DeviceId, DispatcherId, FarmId, FieldId, FirmwareId,
+    OrganizationId, PlotId, ReadingId, SensorId, StatusId, wire,
+};
+
+// We use `Box<str>` and `Box<[T]>` for structures that don't need to be
+// dynamically sized. This helps us keep allocations compact and avoid
+// accidental cloning of large values.
+pub type BoxStr = Box<str>;
+pub type BoxList<T> = Box<[T]>;
+
+/// H3 cell index (hex-like 64-bit integer) representing a spatial cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct H3Cell(pub u64);
+
+/// Percentage value in the range 0–100 (inclusive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Percentage(pub u8);
+
+impl Percentage {
+    /// Whether the value is within 0–100.
+    pub fn is_valid(&self) -> bool {
+        self.0 <= 100
+    }
+}
+
+/// A registered edge device in the platform.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    /// Stable identity of this device.
+    pub id: DeviceId,
+    /// Type of the device.
+    pub kind: DeviceKind,
+    /// Operational state of device.
+    pub state: DeviceState,
+    /// Canonical location cell for the device.
+    pub location: H3Cell,
+    /// Manufacturer or vendor string.
+    pub manufacturer: Option<BoxStr>,
+    /// Provisioning timestamp.
+    pub provisioned_at: jiff::Timestamp,
+    /// Sensors attached to this device.
+    pub sensors: BoxList<Sensor>,
+    /// Actuators driven by this device.
+    #[serde(default)]
+    pub actuators: BoxList<Actuator>,
+    /// Organization owning this device, if any.
+    #[serde(default)]
+    pub organization: Option<OrganizationId>,
+}
+
+/// Pre-shared key a device uses to authenticate to its dispatcher.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceKey(pub [u8; 32]);
+
+impl std::fmt::Debug for DeviceKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DeviceKey(..)")
+    }
+}
+
+/// A device together with its pre-shared key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceCredential {
+    pub device_id: DeviceId,
+    pub key: DeviceKey,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sensor {
+    pub id: SensorId,
+    pub metric: SensorMetric,
+    pub kind: SensorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SensorStatus {
+    pub sensor_id: SensorId,
+    pub state: SensorState,
+    pub last_reading: Option<jiff::Timestamp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SensorState {
+    Active,
+    Faulty,
+    Inactive,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SensorKind {
+    SoilMoisture,
+    SoilTemp,
+    AirTemp,
+    Humidity,
+    Rainfall,
+    SoilEc,
+    SoilPh,
+    LeafWetness,
+    WindSpeed,
+    WindDirection,
+    SolarRadiation,
+    BarometricPressure,
+    WaterLevel,
+    FlowRate,
+}
+
+/// Device classification.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DeviceKind {
+    Sensor,
+    /// Drives valves or pumps, and may report readings as well.
+    Actuator,
+}
+
+/// Longest any actuator may run on a single command, in seconds, whatever
+/// its own limit.
+pub const MAX_ACTUATOR_RUN_SECS: u32 = 4 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actuator {
+    pub id: ActuatorId,
+    pub kind: ActuatorKind,
+    /// Longest the actuator may stay open on one command, in seconds.
+    pub max_run_secs: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActuatorKind {
+    Valve,
+    Pump,
+}
+
+/// Position of an actuator. Actuators are closed unless commanded open, and
+/// close again on their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActuatorState {
+    Closed,
+    Open { until: jiff::Timestamp },
+}
+
+/// What a command asks an actuator to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActuatorAction {
+    /// Open for the given time, limited by the actuator's maximum run time.
+    Open {
+        duration_secs: u32,
+    },
+    Close,
+}
+
+/// A command for one actuator, issued through ersha-prime.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActuatorCommand {
+    pub id: CommandId,
+    pub device_id: DeviceId,
+    pub actuator_id: ActuatorId,
+    pub action: ActuatorAction,
+    pub issued_at: jiff::Timestamp,
+    /// Commands not acknowledged by then time out and are no longer sent.
+    pub expires_at: jiff::Timestamp,
+}
+
+/// How a command ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandResult {
+    /// The device carried out the command and reports the resulting state.
+    Applied { state: ActuatorState },
+    /// The device could not drive the actuator and closed it.
+    Failed,
+    /// The device never acknowledged the command.
+    TimedOut,
+}
+
+/// A command together with its result, once known.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub command: ActuatorCommand,
+    pub result: Option<CommandResult>,
+    pub completed_at: Option<jiff::Timestamp>,
+}
+
+/// Device state.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DeviceState {
+    /// Device is permitted to upload telemetry.
+    Active,
+    /// Device is blocked (e.g., compromised) until it is reactivated.
+    Suspended,
+    /// Device is decommissioned for good. Its readings and statuses are kept.
+    Retired,
+}
+
+/// A single sensor reading emitted by an edge device and forwarded by a dispatcher.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SensorReading {
+    /// Unique id for this reading.
+    pub id: ReadingId,
+    /// Source device that generated this reading.
+    pub device_id: DeviceId,
+    /// Dispatcher that forwarded this reading to central.
+    pub dispatcher_id: DispatcherId,
+    /// The measured quantity and value.
+    pub metric: SensorMetric,
+    /// H3 cell where the reading was taken.
+    pub location: H3Cell,
+    /// Quality of this reading.
+    pub confidence: Percentage,
+    /// Timestamp of the reading event.
+    pub timestamp: jiff::Timestamp,
+    /// The specific sensor that produced this reading
+    pub sensor_id: SensorId,
+    /// Value before calibration, set when a calibration profile was applied.
+    #[serde(default)]
+    pub raw_value: Option<NotNan<f64>>,
+}
+
+/// Supported sensor metrics.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SensorMetric {
+    /// Soil moisture as a percentage.
+    SoilMoisture { value: Percentage },
+    /// Soil temperature in degrees Celsius.
+    SoilTemp { value: NotNan<f64> },
+    /// Air temperature in degrees Celsius.
+    AirTemp { value: NotNan<f64> },
+    /// Relative humidity as a percentage.
+    Humidity { value: Percentage },
+    /// Rainfall in millimeters.
+    Rainfall { value: NotNan<f64> },
+    /// Soil electrical conductivity in decisiemens per meter.
+    SoilEc { value: NotNan<f64> },
+    /// Soil pH.
+    SoilPh { value: NotNan<f64> },
+    /// Share of the leaf surface that is wet, as a percentage.
+    LeafWetness { value: Percentage },
+    /// Wind speed in meters per second.
+    WindSpeed { value: NotNan<f64> },
+    /// Direction the wind blows from, in degrees clockwise from north.
+    WindDirection { value: NotNan<f64> },
+    /// Solar radiation in watts per square meter.
+    SolarRadiation { value: NotNan<f64> },
+    /// Barometric pressure in hectopascals.
+    BarometricPressure { value: NotNan<f64> },
+    /// Water level of a reservoir or canal in meters above the gauge zero.
+    WaterLevel { value: NotNan<f64> },
+    /// Flow rate in liters per second.
+    FlowRate { value: NotNan<f64> },
+}
+
+impl SensorMetric {
+    /// Numeric value of the metric in its unit.
+    pub fn value(&self) -> f64 {
+        match self {
+            SensorMetric::SoilMoisture { value }
+            | SensorMetric::Humidity { value }
+            | SensorMetric::LeafWetness { value } => value.0 as f64,
+            SensorMetric::SoilTemp { value }
+            | SensorMetric::AirTemp { value }
+            | SensorMetric::Rainfall { value }
+            | SensorMetric::SoilEc { value }
+            | SensorMetric::SoilPh { value }
+            | SensorMetric::WindSpeed { value }
+            | SensorMetric::WindDirection { value }
+            | SensorMetric::SolarRadiation { value }
+            | SensorMetric::BarometricPressure { value }
+            | SensorMetric::WaterLevel { value }
+            | SensorMetric::FlowRate { value } => value.into_inner(),
+        }
+    }
+
+    /// Unit the metric's value is in.
+    pub fn unit(&self) -> MetricUnit {
+        match self {
+            SensorMetric::SoilMoisture { .. }
+            | SensorMetric::Humidity { .. }
+            | SensorMetric::LeafWetness { .. } => MetricUnit::Percent,
+            SensorMetric::SoilTemp { .. } | SensorMetric::AirTemp { .. } => MetricUnit::Celsius,
+            SensorMetric::Rainfall { .. } => MetricUnit::Mm,
+            SensorMetric::SoilEc { .. } => MetricUnit::DeciSiemensPerMeter,
+            SensorMetric::SoilPh { .. } => MetricUnit::Ph,
+            SensorMetric::WindSpeed { .. } => MetricUnit::MetersPerSecond,
+            SensorMetric::WindDirection { .. } => MetricUnit::Degrees,
+            SensorMetric::SolarRadiation { .. } => MetricUnit::WattsPerSquareMeter,
+            SensorMetric::BarometricPressure { .. } => MetricUnit::Hectopascal,
+            SensorMetric::WaterLevel { .. } => MetricUnit::Meters,
+            SensorMetric::FlowRate { .. } => MetricUnit::LitersPerSecond,
+        }
+    }
+
+    /// Physically plausible range of the metric, inclusive.
+    pub fn bounds(&self) -> (f64, f64) {
+        match self {
+            SensorMetric::SoilMoisture { .. }
+            | SensorMetric::Humidity { .. }
+            | SensorMetric::LeafWetness { .. } => (0.0, 100.0),
+            SensorMetric::SoilTemp { .. } => (-30.0, 80.0),
+            SensorMetric::AirTemp { .. } => (-90.0, 60.0),
+            SensorMetric::Rainfall { .. } => (0.0, 500.0),
+            SensorMetric::SoilEc { .. } => (0.0, 20.0),
+            SensorMetric::SoilPh { .. } => (0.0, 14.0),
+            SensorMetric::WindSpeed { .. } => (0.0, 120.0),
+            SensorMetric::WindDirection { .. } => (0.0, 360.0),
+            SensorMetric::SolarRadiation { .. } => (0.0, 2000.0),
+            SensorMetric::BarometricPressure { .. } => (300.0, 1100.0),
+            SensorMetric::WaterLevel { .. } => (-10.0, 100.0),
+            SensorMetric::FlowRate { .. } => (0.0, 100_000.0),
+        }
+    }
+
+    /// Whether the value lies within [`SensorMetric::bounds`].
+    pub fn is_plausible(&self) -> bool {
+        let (min, max) = self.bounds();
+        (min..=max).contains(&self.value())
+    }
+
+    /// The same metric with another value.
+    ///
+    /// Percentages are rounded and clamped to 0–100. A NaN value leaves the
+    /// metric unchanged.
+    pub fn with_value(&self, value: f64) -> SensorMetric {
+        let Ok(value) = NotNan::new(value) else {
+            return self.clone();
+        };
+        let percentage = || Percentage(value.round().clamp(0.0, 100.0) as u8);
+
+        match self {
+            SensorMetric::SoilMoisture { .. } => SensorMetric::SoilMoisture {
+                value: percentage(),
+            },
+            SensorMetric::SoilTemp { .. } => SensorMetric::SoilTemp { value },
+            SensorMetric::AirTemp { .. } => SensorMetric::AirTemp { value },
+            SensorMetric::Humidity { .. } => SensorMetric::Humidity {
+                value: percentage(),
+            },
+            SensorMetric::Rainfall { .. } => SensorMetric::Rainfall { value },
+            SensorMetric::SoilEc { .. } => SensorMetric::SoilEc { value },
+            SensorMetric::SoilPh { .. } => SensorMetric::SoilPh { value },
+            SensorMetric::LeafWetness { .. } => SensorMetric::LeafWetness {
+                value: percentage(),
+            },
+            SensorMetric::WindSpeed { .. } => SensorMetric::WindSpeed { value },
+            SensorMetric::WindDirection { .. } => SensorMetric::WindDirection { value },
+            SensorMetric::SolarRadiation { .. } => SensorMetric::SolarRadiation { value },
+            SensorMetric::BarometricPressure { .. } => SensorMetric::BarometricPressure { value },
+            SensorMetric::WaterLevel { .. } => SensorMetric::WaterLevel { value },
+            SensorMetric::FlowRate { .. } => SensorMetric::FlowRate { value },
+        }
+    }
+}
+
+/// Enum variant discriminator for SensorMetric, used for filtering by metric type without values.
+///
+/// [`SensorMetricType::as_str`] and its [`FromStr`] impl give the one name
+/// of each metric used by the HTTP API, MQTT topics and logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SensorMetricType {
+    SoilMoisture,
+    SoilTemp,
+    AirTemp,
+    Humidity,
+    Rainfall,
+    SoilEc,
+    SoilPh,
+    LeafWetness,
+    WindSpeed,
+    WindDirection,
+    SolarRadiation,
+    BarometricPressure,
+    WaterLevel,
+    FlowRate,
+}
+
+impl SensorMetricType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensorMetricType::SoilMoisture => "soil_moisture",
+            SensorMetricType::SoilTemp => "soil_temp",
+            SensorMetricType::AirTemp => "air_temp",
+            SensorMetricType::Humidity => "humidity",
+            SensorMetricType::Rainfall => "rainfall",
+            SensorMetricType::SoilEc => "soil_ec",
+            SensorMetricType::SoilPh => "soil_ph",
+            SensorMetricType::LeafWetness => "leaf_wetness",
+            SensorMetricType::WindSpeed => "wind_speed",
+            SensorMetricType::WindDirection => "wind_direction",
+            SensorMetricType::SolarRadiation => "solar_radiation",
+            SensorMetricType::BarometricPressure => "barometric_pressure",
+            SensorMetricType::WaterLevel => "water_level",
+            SensorMetricType::FlowRate => "flow_rate",
+        }
+    }
+}
+
+impl FromStr for SensorMetricType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "soil_moisture" => Ok(SensorMetricType::SoilMoisture),
+            "soil_temp" => Ok(SensorMetricType::SoilTemp),
+            "air_temp" => Ok(SensorMetricType::AirTemp),
+            "humidity" => Ok(SensorMetricType::Humidity),
+            "rainfall" => Ok(SensorMetricType::Rainfall),
+            "soil_ec" => Ok(SensorMetricType::SoilEc),
+            "soil_ph" => Ok(SensorMetricType::SoilPh),
+            "leaf_wetness" => Ok(SensorMetricType::LeafWetness),
+            "wind_speed" => Ok(SensorMetricType::WindSpeed),
+            "wind_direction" => Ok(SensorMetricType::WindDirection),
+            "solar_radiation" => Ok(SensorMetricType::SolarRadiation),
+            "barometric_pressure" => Ok(SensorMetricType::BarometricPressure),
+            "water_level" => Ok(SensorMetricType::WaterLevel),
+            "flow_rate" => Ok(SensorMetricType::FlowRate),
+            other => Err(format!("Invalid metric: {other}")),
+        }
+    }
+}
+
+impl From<&SensorMetric> for SensorMetricType {
+    fn from(metric: &SensorMetric) -> Self {
+        match metric {
+            SensorMetric::SoilMoisture { .. } => SensorMetricType::SoilMoisture,
+            SensorMetric::SoilTemp { .. } => SensorMetricType::SoilTemp,
+            SensorMetric::AirTemp { .. } => SensorMetricType::AirTemp,
+            SensorMetric::Humidity { .. } => SensorMetricType::Humidity,
+            SensorMetric::Rainfall { .. } => SensorMetricType::Rainfall,
+            SensorMetric::SoilEc { .. } => SensorMetricType::SoilEc,
+            SensorMetric::SoilPh { .. } => SensorMetricType::SoilPh,
+            SensorMetric::LeafWetness { .. } => SensorMetricType::LeafWetness,
+            SensorMetric::WindSpeed { .. } => SensorMetricType::WindSpeed,
+            SensorMetric::WindDirection { .. } => SensorMetricType::WindDirection,
+            SensorMetric::SolarRadiation { .. } => SensorMetricType::SolarRadiation,
+            SensorMetric::BarometricPressure { .. } => SensorMetricType::BarometricPressure,
+            SensorMetric::WaterLevel { .. } => SensorMetricType::WaterLevel,
+            SensorMetric::FlowRate { .. } => SensorMetricType::FlowRate,
+        }
+    }
+}
+
+impl From<&SensorKind> for SensorMetricType {
+    fn from(kind: &SensorKind) -> Self {
+        match kind {
+            SensorKind::SoilMoisture => SensorMetricType::SoilMoisture,
+            SensorKind::SoilTemp => SensorMetricType::SoilTemp,
+            SensorKind::AirTemp => SensorMetricType::AirTemp,
+            SensorKind::Humidity => SensorMetricType::Humidity,
+            SensorKind::Rainfall => SensorMetricType::Rainfall,
+            SensorKind::SoilEc => SensorMetricType::SoilEc,
+            SensorKind::SoilPh => SensorMetricType::SoilPh,
+            SensorKind::LeafWetness => SensorMetricType::LeafWetness,
+            SensorKind::WindSpeed => SensorMetricType::WindSpeed,
+            SensorKind::WindDirection => SensorMetricType::WindDirection,
+            SensorKind::SolarRadiation => SensorMetricType::SolarRadiation,
+            SensorKind::BarometricPressure => SensorMetricType::BarometricPressure,
+            SensorKind::WaterLevel => SensorMetricType::WaterLevel,
+            SensorKind::FlowRate => SensorMetricType::FlowRate,
+        }
+    }
+}
+
+impl From<SensorMetricType> for SensorKind {
+    fn from(metric_type: SensorMetricType) -> Self {
+        match metric_type {
+            SensorMetricType::SoilMoisture => SensorKind::SoilMoisture,
+            SensorMetricType::SoilTemp => SensorKind::SoilTemp,
+            SensorMetricType::AirTemp => SensorKind::AirTemp,
+            SensorMetricType::Humidity => SensorKind::Humidity,
+            SensorMetricType::Rainfall => SensorKind::Rainfall,
+            SensorMetricType::SoilEc => SensorKind::SoilEc,
+            SensorMetricType::SoilPh => SensorKind::SoilPh,
+            SensorMetricType::LeafWetness => SensorKind::LeafWetness,
+            SensorMetricType::WindSpeed => SensorKind::WindSpeed,
+            SensorMetricType::WindDirection => SensorKind::WindDirection,
+            SensorMetricType::SolarRadiation => SensorKind::SolarRadiation,
+            SensorMetricType::BarometricPressure => SensorKind::BarometricPressure,
+            SensorMetricType::WaterLevel => SensorKind::WaterLevel,
+            SensorMetricType::FlowRate => SensorKind::FlowRate,
+        }
+    }
+}
+
+impl SensorMetric {
+    /// The metric in its wire encoding, rounded to the nearest step, or
+    /// `None` when the value does not fit the encoding. Metrics converted
+    /// from the wire convert back unchanged.
+    pub fn to_wire(&self) -> Option<wire::SensorMetric> {
+        use wire::SensorMetric as Wire;
+
+        let steps = |encoding: Wire| (self.value() * encoding.scale() as f64).round() as i64;
+
+        Some(match self {
+            SensorMetric::SoilMoisture { value } => Wire::SoilMoisture(value.0),
+            SensorMetric::SoilTemp { .. } => {
+                Wire::SoilTemp(steps(Wire::SoilTemp(0)).try_into().ok()?)
+            }
+            SensorMetric::AirTemp { .. } => Wire::AirTemp(steps(Wire::AirTemp(0)).try_into().ok()?),
+            SensorMetric::Humidity { value } => Wire::Humidity(value.0),
+            SensorMetric::Rainfall { .. } => {
+                Wire::Rainfall(steps(Wire::Rainfall(0)).try_into().ok()?)
+            }
+            SensorMetric::SoilEc { .. } => Wire::SoilEc(steps(Wire::SoilEc(0)).try_into().ok()?),
+            SensorMetric::SoilPh { .. } => Wire::SoilPh(steps(Wire::SoilPh(0)).try_into().ok()?),
+            SensorMetric::LeafWetness { value } => Wire::LeafWetness(value.0),
+            SensorMetric::WindSpeed { .. } => {
+                Wire::WindSpeed(steps(Wire::WindSpeed(0)).try_into().ok()?)
+            }
+            SensorMetric::WindDirection { .. } => {
+                Wire::WindDirection(steps(Wire::WindDirection(0)).try_into().ok()?)
+            }
+            SensorMetric::SolarRadiation { .. } => {
+                Wire::SolarRadiation(steps(Wire::SolarRadiation(0)).try_into().ok()?)
+            }
+            SensorMetric::BarometricPressure { .. } => {
+                Wire::BarometricPressure(steps(Wire::BarometricPressure(0)).try_into().ok()?)
+            }
+            SensorMetric::WaterLevel { .. } => {
+                Wire::WaterLevel(steps(Wire::WaterLevel(0)).try_into().ok()?)
+            }
+            SensorMetric::FlowRate { .. } => {
+                Wire::FlowRate(steps(Wire::FlowRate(0)).try_into().ok()?)
+            }
+        })
+    }
+}
+
+impl From<wire::SensorMetric> for SensorMetric {
+    fn from(metric: wire::SensorMetric) -> Self {
+        let value = metric.raw() as f64 / metric.scale() as f64;
+        let number = NotNan::new(value).expect("fixed-point values are never NaN");
+
+        match metric {
+            wire::SensorMetric::SoilMoisture(v) => SensorMetric::SoilMoisture {
+                value: Percentage(v),
+            },
+            wire::SensorMetric::SoilTemp(_) => SensorMetric::SoilTemp { value: number },
+            wire::SensorMetric::AirTemp(_) => SensorMetric::AirTemp { value: number },
+            wire::SensorMetric::Humidity(v) => SensorMetric::Humidity {
+                value: Percentage(v),
+            },
+            wire::SensorMetric::Rainfall(_) => SensorMetric::Rainfall { value: number },
+            wire::SensorMetric::SoilEc(_) => SensorMetric::SoilEc { value: number },
+            wire::SensorMetric::SoilPh(_) => SensorMetric::SoilPh { value: number },
+            wire::SensorMetric::LeafWetness(v) => SensorMetric::LeafWetness {
+                value: Percentage(v),
+            },
+            wire::SensorMetric::WindSpeed(_) => SensorMetric::WindSpeed { value: number },
+            wire::SensorMetric::WindDirection(_) => SensorMetric::WindDirection { value: number },
+            wire::SensorMetric::SolarRadiation(_) => SensorMetric::SolarRadiation { value: number },
+            wire::SensorMetric::BarometricPressure(_) => {
+                SensorMetric::BarometricPressure { value: number }
+            }
+            wire::SensorMetric::WaterLevel(_) => SensorMetric::WaterLevel { value: number },
+            wire::SensorMetric::FlowRate(_) => SensorMetric::FlowRate { value: number },
+        }
+    }
+}
+
+/// Correction turning a sensor's raw values into calibrated values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Calibration {
+    /// `calibrated = raw * gain + offset`.
+    Linear {
+        gain: NotNan<f64>,
+        offset: NotNan<f64>,
+    },
+    /// Piecewise-linear interpolation between `(raw, calibrated)` points
+    /// sorted by raw value. Raw values outside the table take the calibrated
+    /// value of the nearest end point.
+    Lookup {
+        points: BoxList<(NotNan<f64>, NotNan<f64>)>,
+    },
+}
+
+impl Calibration {
+    /// Whether the calibration can be applied: lookup tables need at least
+    /// two points with strictly increasing raw values.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Calibration::Linear { .. } => true,
+            Calibration::Lookup { points } => {
+                points.len() >= 2 && points.windows(2).all(|w| w[0].0 < w[1].0)
+            }
+        }
+    }
+
+    /// Calibrate a raw value.
+    pub fn apply(&self, raw: f64) -> f64 {
+        match self {
+            Calibration::Linear { gain, offset } => raw * gain.into_inner() + offset.into_inner(),
+            Calibration::Lookup { points } => {
+                let (Some(first), Some(last)) = (points.first(), points.last()) else {
+                    return raw;
+                };
+                if raw <= first.0.into_inner() {
+                    return first.1.into_inner();
+                }
+                if raw >= last.0.into_inner() {
+                    return last.1.into_inner();
+                }
+
+                points
+                    .windows(2)
+                    .find(|w| raw <= w[1].0.into_inner())
+                    .map(|w| {
+                        let (x0, y0) = (w[0].0.into_inner(), w[0].1.into_inner());
+                        let (x1, y1) = (w[1].0.into_inner(), w[1].1.into_inner());
+                        y0 + (raw - x0) * (y1 - y0) / (x1 - x0)
+                    })
+                    .unwrap_or(raw)
+            }
+        }
+    }
+}
+
+/// Calibration assigned to a sensor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalibrationProfile {
+    pub sensor_id: SensorId,
+    pub calibration: Calibration,
+    /// When the profile was last changed.
+    pub updated_at: jiff::Timestamp,
+}
+
+/// Units used by metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetricUnit {
+    /// Percent (%) values.
+    Percent,
+    /// Degrees Celsius (°C).
+    Celsius,
+    /// Millimeters (mm).
+    Mm,
+    /// Decisiemens per meter (dS/m).
+    DeciSiemensPerMeter,
+    /// pH, without a unit.
+    Ph,
+    /// Meters per second (m/s).
+    MetersPerSecond,
+    /// Degrees (°) clockwise from north.
+    Degrees,
+    /// Watts per square meter (W/m²).
+    WattsPerSquareMeter,
+    /// Hectopascals (hPa).
+    Hectopascal,
+    /// Meters (m).
+    Meters,
+    /// Liters per second (L/s).
+    LitersPerSecond,
+}
+
+/// A status report emitted by a device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceStatus {
+    /// Unique id for this status record.
+    pub id: StatusId,
+    /// Source device that generated this status report.
+    pub device_id: DeviceId,
+    /// Dispatcher that forwarded this status report.
+    pub dispatcher_id: DispatcherId,
+    /// Battery charge level expressed as a percentage.
+    pub battery_percent: Percentage,
+    /// Device uptime (seconds since last reboot).
+    pub uptime_seconds: u64,
+    /// Received signal strength indicator (RSSI).
+    pub signal_rssi: i16,
+    /// Any errors reported by device firmware.
+    pub errors: BoxList<DeviceError>,
+    /// Timestamp when status was captured.
+    pub timestamp: jiff::Timestamp,
+    /// The status of each sensor attached to this device
+    pub sensor_statuses: BoxList<SensorStatus>,
+    /// Running firmware and update progress, for devices that report it.
+    #[serde(default)]
+    pub firmware: Option<FirmwareStatus>,
+}
+
+/// Firmware a device is running and the state of any update.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareStatus {
+    /// Device model the running firmware was built for.
+    pub model: BoxStr,
+    /// Version of the running firmware.
+    pub version: u32,
+    pub update: FirmwareUpdate,
+}
+
+/// The firmware a device reported last.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceFirmware {
+    pub device_id: DeviceId,
+    pub firmware: FirmwareStatus,
+    /// When the device reported it.
+    pub reported_at: jiff::Timestamp,
+}
+
+/// Progress of a firmware update on a device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FirmwareUpdate {
+    Idle,
+    Downloading {
+        version: u32,
+        progress: Percentage,
+    },
+    /// The image was verified and staged; the device is rebooting into it.
+    Staged {
+        version: u32,
+    },
+    /// The device booted into the update and confirmed it.
+    Confirmed {
+        version: u32,
+    },
+    Failed {
+        version: u32,
+        reason: FirmwareFailure,
+    },
+}
+
+/// Why a firmware update was abandoned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FirmwareFailure {
+    /// The received image does not match the published digest.
+    HashMismatch,
+    /// The image is not signed by the release key.
+    BadSignature,
+    /// Writing the staging partition failed.
+    Storage,
+    /// The transfer was interrupted or out of order.
+    Transfer,
+}
+
+/// Firmware image published for a device model. The image bytes are kept
+/// separately and transferred in chunks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareImage {
+    pub id: FirmwareId,
+    /// Device model the image is built for.
+    pub model: BoxStr,
+    /// Devices only install versions newer than the one they run.
+    pub version: u32,
+    /// Image size in bytes.
+    pub size: u32,
+    /// SHA-256 digest of the image.
+    pub sha256: [u8; 32],
+    /// Ed25519 signature of the release key over the big-endian version,
+    /// the digest and the model name, checked by devices before installing.
+    pub signature: BoxList<u8>,
+    /// When the image was published.
+    pub created_at: jiff::Timestamp,
+}
+
+/// A structured error from a device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceError {
+    /// Canonical error category.
+    pub code: DeviceErrorCode,
+    /// Optional human-readable message from firmware.
+    pub message: Option<BoxStr>,
+}
+
+/// Device error codes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviceErrorCode {
+    LowBattery,
+    SensorFault,
+    RadioFault,
+    Unknown,
+}
+
+/// A registered dispatcher in the platform.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dispatcher {
+    /// Stable identity of this dispatcher.
+    pub id: DispatcherId,
+    /// Dispatcher location cell.
+    pub location: H3Cell,
+    /// Operational state.
+    pub state: DispatcherState,
+    /// Provisioning timestamp.
+    pub provisioned_at: jiff::Timestamp,
+    /// Organization owning this dispatcher, if any.
+    #[serde(default)]
+    pub organization: Option<OrganizationId>,
+}
+
+/// A tenant of the platform, such as a cooperative, NGO or farmer, owning
+/// devices and dispatchers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Organization {
+    /// Stable identity of this organization.
+    pub id: OrganizationId,
+    /// Display name, unique across the platform.
+    pub name: BoxStr,
+    /// When the organization was created.
+    pub created_at: jiff::Timestamp,
+}
+
+/// A point on the ground, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub lat: NotNan<f64>,
+    pub lng: NotNan<f64>,
+}
+
+/// Outline of a farm, field or plot and the H3 cells covering it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Boundary {
+    /// Outer ring of the polygon, without repeating the first point.
+    pub ring: BoxList<GeoPoint>,
+    /// Cells whose centers fall inside the ring, sorted.
+    pub cells: BoxList<H3Cell>,
+}
+
+/// A farm, the top of the farm, field and plot hierarchy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Farm {
+    /// Stable identity of this farm.
+    pub id: FarmId,
+    pub name: BoxStr,
+    pub boundary: Boundary,
+    /// Organization owning this farm and everything in it, if any.
+    pub organization: Option<OrganizationId>,
+    /// When the farm was created.
+    pub created_at: jiff::Timestamp,
+}
+
+/// A field of a farm.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    /// Stable identity of this field.
+    pub id: FieldId,
+    /// Farm the field belongs to.
+    pub farm_id: FarmId,
+    pub name: BoxStr,
+    pub boundary: Boundary,
+    /// Crop grown on the field, such as "maize".
+    pub crop: Option<BoxStr>,
+    /// When the current crop was planted.
+    pub planted_on: Option<jiff::civil::Date>,
+    /// When the field was created.
+    pub created_at: jiff::Timestamp,
+}
+
+/// A plot of a field, the unit devices are assigned to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plot {
+    /// Stable identity of this plot.
+    pub id: PlotId,
+    /// Field the plot belongs to.
+    pub field_id: FieldId,
+    pub name: BoxStr,
+    pub boundary: Boundary,
+    /// Crop grown on the plot, such as "maize".
+    pub crop: Option<BoxStr>,
+    /// When the current crop was planted.
+    pub planted_on: Option<jiff::civil::Date>,
+    /// When the plot was created.
+    pub created_at: jiff::Timestamp,
+}
+
+/// Dispatcher State
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DispatcherState {
+    /// Dispatcher is permitted to upload data.
+    Active,
+    /// Dispatcher is blocked (e.g., compromised) until it is reactivated.
+    Suspended,
+    /// Dispatcher is decommissioned for good. What it uploaded is kept.
+    Retired,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BatchUploadRequest {
+    /// Unique id for this batch.
+    pub id: BatchId,
+    /// Dispatcher that created and is uploading this batch.
+    pub dispatcher_id: DispatcherId,
+    /// Telemetry readings included in this batch.
+    pub readings: BoxList<SensorReading>,
+    /// Device status records included in this batch.
+    pub statuses: BoxList<DeviceStatus>,
+    /// Timestamp when the batch was created by dispatcher.
+    pub timestamp: jiff::Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchUploadResponse {
+    pub id: BatchId,
+    pub readings_stored: u32,
+    pub readings_rejected: u32,
+    pub statuses_stored: u32,
+    pub statuses_rejected: u32,
+    /// Each rejected reading and status, and why it was rejected.
+    #[serde(default)]
+    pub rejections: BoxList<IngestRejection>,
+    /// Prime could not store the batch, or refused it because the
+    /// dispatcher is not active. Nothing is stored and the dispatcher should
+    /// upload the batch again.
+    #[serde(default)]
+    pub failed: bool,
+}
+
+impl BatchUploadResponse {
+    /// Response for a batch that prime could not store.
+    pub fn failed(id: BatchId) -> Self {
+        Self {
+            id,
+            readings_stored: 0,
+            readings_rejected: 0,
+            statuses_stored: 0,
+            statuses_rejected: 0,
+            rejections: Vec::new().into_boxed_slice(),
+            failed: true,
+        }
+    }
+}
+
+/// A reading or status record that prime refused to store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IngestRejection {
+    Reading {
+        id: ReadingId,
+        reason: IngestRejectionReason,
+    },
+    Status {
+        id: StatusId,
+        reason: IngestRejectionReason,
+    },
+}
+
+/// Why prime refused to store a record from a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum IngestRejectionReason {
+    /// The device is not registered.
+    UnknownDevice,
+    /// The device is suspended.
+    DeviceSuspended,
+    /// The device has been decommissioned.
+    DeviceRetired,
+    /// The record names a different dispatcher than the one uploading it.
+    DispatcherMismatch,
+    /// The uploading dispatcher is unknown, suspended or decommissioned.
+    DispatcherInactive,
+    /// The reading failed prime's bounds checks.
+    Implausible,
+    /// Prime could not look up the device.
+    InternalError,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HelloRequest {
+    /// Unique id for this dispatcher.
+    pub dispatcher_id: DispatcherId,
+    /// Dispatcher location cell.
+    pub location: H3Cell,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum HelloResponse {
+    Accepted { dispatcher_id: DispatcherId },
+    Rejected { reason: HelloRejectionReason },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum HelloRejectionReason {
+    /// The dispatcher is not registered.
+    UnknownDispatcher,
+    /// The dispatcher is suspended.
+    DispatcherSuspended,
+    /// The dispatcher has been decommissioned.
+    DispatcherRetired,
+    /// Prime could not look up the dispatcher.
+    InternalError,
+}
+
+// Alert types for urgent notifications from dispatch to prime
+
+/// Unique identifier for an alert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AlertId(pub Ulid);
+
+/// Severity level of an alert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AlertSeverity {
+    Critical,
+    Warning,
+    Info,
+}
+
+/// Type of alert being reported.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AlertType {
+    CriticalBattery,
+    SensorFailure,
+    DeviceOffline,
+    CommunicationError,
+    SecurityEvent,
+    Custom(BoxStr),
+}
+
+/// Request to report an urgent alert.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AlertRequest {
+    pub id: AlertId,
+    pub dispatcher_id: DispatcherId,
+    pub device_id: Option<DeviceId>,
+    pub severity: AlertSeverity,
+    pub alert_type: AlertType,
+    pub message: BoxStr,
+    pub timestamp: jiff::Timestamp,
+}
+
+/// Response to an alert request.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AlertResponse {
+    pub alert_id: AlertId,
+    pub acknowledged: bool,
+}
+
+/// Request to report dispatcher status/health.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DispatcherStatusRequest {
+    pub dispatcher_id: DispatcherId,
+    pub connected_devices: u32,
+    pub uptime_seconds: u64,
+    pub pending_uploads: u32,
+    /// Duplicate readings dropped since the dispatcher started.
+    pub duplicates_dropped: u64,
+    pub timestamp: jiff::Timestamp,
+}
+
+/// Response to a dispatcher status request.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DispatcherStatusResponse {
+    pub dispatcher_id: DispatcherId,
+}
+
+/// Request for the calibration profiles a dispatcher should apply.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationRequest {
+    pub dispatcher_id: DispatcherId,
+}
+
+/// Calibration profiles for all calibrated sensors.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationResponse {
+    pub profiles: BoxList<CalibrationProfile>,
+}
+
+/// Request for the keys of devices allowed to connect to a dispatcher.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DeviceKeysRequest {
+    pub dispatcher_id: DispatcherId,
+}
+
+/// Keys of all devices that have one provisioned.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DeviceKeysResponse {
+    pub credentials: BoxList<DeviceCredential>,
+}
+
+/// Request for the newest firmware image of every device model.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareManifestRequest {
+    pub dispatcher_id: DispatcherId,
+}
+
+/// Newest firmware image of every device model.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareManifestResponse {
+    pub images: BoxList<FirmwareImage>,
+}
+
+/// Request for part of a firmware image.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareChunkRequest {
+    pub dispatcher_id: DispatcherId,
+    pub firmware_id: FirmwareId,
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// Part of a firmware image. Empty if the image is unknown or the offset is
+/// past its end.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareChunkResponse {
+    pub firmware_id: FirmwareId,
+    pub offset: u32,
+    pub data: BoxList<u8>,
+}
+
+/// Request for the actuator commands waiting to be delivered.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ActuatorCommandsRequest {
+    pub dispatcher_id: DispatcherId,
+}
+
+/// Commands not yet acknowledged or expired, for all devices.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ActuatorCommandsResponse {
+    pub commands: BoxList<ActuatorCommand>,
+}
+
+/// Request to record how a command ended on a device.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CommandOutcomeRequest {
+    pub command_id: CommandId,
+    pub dispatcher_id: DispatcherId,
+    pub device_id: DeviceId,
+    pub result: CommandResult,
+    pub timestamp: jiff::Timestamp,
+}
+
+/// Response to a command outcome.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CommandOutcomeResponse {
+    pub command_id: CommandId,
+}
+
+/// Request to record the firmware a device reported, kept apart from its
+/// status reports.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareReportRequest {
+    pub dispatcher_id: DispatcherId,
+    pub device_id: DeviceId,
+    pub firmware: FirmwareStatus,
+    pub timestamp: jiff::Timestamp,
+}
+
+/// Response to a firmware report.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareReportResponse {
+    pub device_id: DeviceId,
+}
+
+/// Reason why a device disconnected.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum DisconnectionReason {
+    Timeout,
+    GracefulClose,
+    Error(BoxStr),
+    Unknown,
+    /// The device has no key provisioned.
+    UnknownDevice,
+    /// A frame from the device failed authentication.
+    AuthenticationFailed,
+    /// The device sent a frame counter it had already used.
+    ReplayDetected,
+}
+
+/// Request to notify that an edge device has disconnected.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DeviceDisconnectionRequest {
+    pub device_id: DeviceId,
+    pub dispatcher_id: DispatcherId,
+    pub timestamp: jiff::Timestamp,
+    pub reason: Option<DisconnectionReason>,
+}
+
+/// Response to a device disconnection notification.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DeviceDisconnectionResponse {
+    pub device_id: DeviceId,
+}
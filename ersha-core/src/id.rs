@@ -0,0 +1,132 @@
+use core::fmt;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ulid::{ULID_LEN, Ulid};
+
+// Ids are ULID strings in human readable formats like JSON and their raw
+// `u128` in binary ones like postcard, which keeps edge frames small. This
+// is written out here because ulid's own serde support needs `std`.
+macro_rules! impl_id {
+    ($($id:ident),* $(,)?) => {
+        $(
+            impl Serialize for $id {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serialize_ulid(&self.0, serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $id {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserialize_ulid(deserializer).map(Self)
+                }
+            }
+
+            #[cfg(feature = "defmt")]
+            impl defmt::Format for $id {
+                fn format(&self, f: defmt::Formatter) {
+                    defmt::write!(f, "{=u128:X}", self.0.0)
+                }
+            }
+        )*
+    };
+}
+
+fn serialize_ulid<S: Serializer>(ulid: &Ulid, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        let mut buffer = [0; ULID_LEN];
+        serializer.serialize_str(ulid.array_to_str(&mut buffer))
+    } else {
+        serializer.serialize_u128(ulid.0)
+    }
+}
+
+fn deserialize_ulid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Ulid, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(UlidVisitor)
+    } else {
+        u128::deserialize(deserializer).map(Ulid)
+    }
+}
+
+struct UlidVisitor;
+
+impl Visitor<'_> for UlidVisitor {
+    type Value = Ulid;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a ULID string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Ulid, E> {
+        Ulid::from_string(value).map_err(E::custom)
+    }
+}
+
+/// Unique identifier for an edge device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceId(pub Ulid);
+
+/// Unique identifier for a telemetry reading event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReadingId(pub Ulid);
+
+/// Unique identifier for a device status report event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StatusId(pub Ulid);
+
+/// Unique identifier for a dispatcher device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DispatcherId(pub Ulid);
+
+/// Unique identifier for an upload batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BatchId(pub Ulid);
+
+/// Unique identifier for a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SensorId(pub Ulid);
+
+/// Unique identifier for a firmware image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FirmwareId(pub Ulid);
+
+/// Unique identifier for an actuator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActuatorId(pub Ulid);
+
+/// Unique identifier for an actuator command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CommandId(pub Ulid);
+
+/// Unique identifier for an organization owning devices and dispatchers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OrganizationId(pub Ulid);
+
+/// Unique identifier for a farm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FarmId(pub Ulid);
+
+/// Unique identifier for a field within a farm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldId(pub Ulid);
+
+/// Unique identifier for a plot within a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlotId(pub Ulid);
+
+impl_id!(
+    DeviceId,
+    ReadingId,
+    StatusId,
+    DispatcherId,
+    BatchId,
+    SensorId,
+    FirmwareId,
+    ActuatorId,
+    CommandId,
+    OrganizationId,
+    FarmId,
+    FieldId,
+    PlotId,
+);
@@ -0,0 +1,227 @@
+//! Compact forms of core types as edge devices put them on the wire.
+//!
+//! Everything here builds without `std` so firmware shares these
+//! definitions with the dispatchers. Enabling `std` adds lossless
+//! conversions to the domain types.
+
+use serde::{Deserialize, Serialize};
+
+/// A reading's metric as edge devices send it, with each value an integer
+/// in a fixed-point encoding: the value in the metric's unit times
+/// [`SensorMetric::scale`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SensorMetric {
+    /// Percentage 0-100 (1 byte in Postcard)
+    SoilMoisture(u8),
+    /// Degrees Celsius scaled by 100 (e.g., 25.43 -> 2543).
+    /// Fits in 2 bytes instead of 4.
+    SoilTemp(i16),
+    AirTemp(i16),
+    Humidity(u8),
+    /// Rainfall in mm scaled by 100.
+    Rainfall(u16),
+    /// Soil electrical conductivity in dS/m scaled by 100.
+    SoilEc(u16),
+    /// Soil pH scaled by 100 (e.g., 6.85 -> 685).
+    SoilPh(u16),
+    /// Percentage 0-100 of the leaf surface that is wet.
+    LeafWetness(u8),
+    /// Wind speed in m/s scaled by 100.
+    WindSpeed(u16),
+    /// Degrees 0-359 clockwise from north the wind blows from.
+    WindDirection(u16),
+    /// Solar radiation in W/m².
+    SolarRadiation(u16),
+    /// Barometric pressure in hPa scaled by 10.
+    BarometricPressure(u16),
+    /// Water level in m scaled by 1000 (mm) above the gauge zero, negative
+    /// below it.
+    WaterLevel(i32),
+    /// Flow rate in L/s scaled by 100.
+    FlowRate(u32),
+}
+
+impl SensorMetric {
+    /// How many encoded steps make one unit of the metric.
+    pub const fn scale(&self) -> u32 {
+        match self {
+            SensorMetric::SoilMoisture(_)
+            | SensorMetric::Humidity(_)
+            | SensorMetric::LeafWetness(_)
+            | SensorMetric::WindDirection(_)
+            | SensorMetric::SolarRadiation(_) => 1,
+            SensorMetric::BarometricPressure(_) => 10,
+            SensorMetric::SoilTemp(_)
+            | SensorMetric::AirTemp(_)
+            | SensorMetric::Rainfall(_)
+            | SensorMetric::SoilEc(_)
+            | SensorMetric::SoilPh(_)
+            | SensorMetric::WindSpeed(_)
+            | SensorMetric::FlowRate(_) => 100,
+            SensorMetric::WaterLevel(_) => 1000,
+        }
+    }
+
+    /// The encoded integer.
+    pub const fn raw(&self) -> i64 {
+        match *self {
+            SensorMetric::SoilMoisture(v)
+            | SensorMetric::Humidity(v)
+            | SensorMetric::LeafWetness(v) => v as i64,
+            SensorMetric::SoilTemp(v) | SensorMetric::AirTemp(v) => v as i64,
+            SensorMetric::Rainfall(v)
+            | SensorMetric::SoilEc(v)
+            | SensorMetric::SoilPh(v)
+            | SensorMetric::WindSpeed(v)
+            | SensorMetric::WindDirection(v)
+            | SensorMetric::SolarRadiation(v)
+            | SensorMetric::BarometricPressure(v) => v as i64,
+            SensorMetric::WaterLevel(v) => v as i64,
+            SensorMetric::FlowRate(v) => v as i64,
+        }
+    }
+
+    /// Shift the value by `offset` encoded steps, saturating at the limits
+    /// of the metric.
+    pub fn calibrate(self, offset: i16) -> Self {
+        match self {
+            SensorMetric::SoilMoisture(val) => {
+                let v = val as i16 + offset;
+                let v = v.clamp(0, 100);
+                SensorMetric::SoilMoisture(v as u8)
+            }
+
+            SensorMetric::Humidity(val) => {
+                let v = val as i16 + offset;
+                let v = v.clamp(0, 100);
+                SensorMetric::Humidity(v as u8)
+            }
+
+            SensorMetric::SoilTemp(val) => SensorMetric::SoilTemp(val.saturating_add(offset)),
+
+            SensorMetric::AirTemp(val) => SensorMetric::AirTemp(val.saturating_add(offset)),
+
+            SensorMetric::Rainfall(val) => SensorMetric::Rainfall(offset_u16(val, offset)),
+
+            SensorMetric::SoilEc(val) => SensorMetric::SoilEc(offset_u16(val, offset)),
+
+            SensorMetric::SoilPh(val) => {
+                let v = val as i32 + offset as i32;
+                let v = v.clamp(0, 1400);
+                SensorMetric::SoilPh(v as u16)
+            }
+
+            SensorMetric::LeafWetness(val) => {
+                let v = val as i16 + offset;
+                let v = v.clamp(0, 100);
+                SensorMetric::LeafWetness(v as u8)
+            }
+
+            SensorMetric::WindSpeed(val) => SensorMetric::WindSpeed(offset_u16(val, offset)),
+
+            // a direction wraps around instead of saturating
+            SensorMetric::WindDirection(val) => {
+                let v = (val as i32 + offset as i32).rem_euclid(360);
+                SensorMetric::WindDirection(v as u16)
+            }
+
+            SensorMetric::SolarRadiation(val) => {
+                SensorMetric::SolarRadiation(offset_u16(val, offset))
+            }
+
+            SensorMetric::BarometricPressure(val) => {
+                SensorMetric::BarometricPressure(offset_u16(val, offset))
+            }
+
+            SensorMetric::WaterLevel(val) => {
+                SensorMetric::WaterLevel(val.saturating_add(offset as i32))
+            }
+
+            SensorMetric::FlowRate(val) => {
+                SensorMetric::FlowRate(val.saturating_add_signed(offset as i32))
+            }
+        }
+    }
+}
+
+fn offset_u16(val: u16, offset: i16) -> u16 {
+    let v = val as i32 + offset as i32;
+    v.clamp(0, u16::MAX as i32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SensorMetric;
+
+    #[test]
+    fn calibration_keeps_metrics_in_range() {
+        assert_eq!(
+            SensorMetric::WindDirection(350).calibrate(15),
+            SensorMetric::WindDirection(5)
+        );
+        assert_eq!(
+            SensorMetric::WindDirection(5).calibrate(-10),
+            SensorMetric::WindDirection(355)
+        );
+        assert_eq!(
+            SensorMetric::SoilPh(1390).calibrate(20),
+            SensorMetric::SoilPh(1400)
+        );
+        assert_eq!(
+            SensorMetric::FlowRate(10).calibrate(-20),
+            SensorMetric::FlowRate(0)
+        );
+        assert_eq!(
+            SensorMetric::WaterLevel(-5).calibrate(-10),
+            SensorMetric::WaterLevel(-15)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn converts_to_domain_metrics_losslessly() {
+        use ordered_float::NotNan;
+
+        let encoded = [
+            SensorMetric::SoilMoisture(40),
+            SensorMetric::SoilTemp(-1234),
+            SensorMetric::AirTemp(i16::MAX),
+            SensorMetric::Humidity(65),
+            SensorMetric::Rainfall(u16::MAX),
+            SensorMetric::SoilEc(157),
+            SensorMetric::SoilPh(685),
+            SensorMetric::LeafWetness(20),
+            SensorMetric::WindSpeed(333),
+            SensorMetric::WindDirection(359),
+            SensorMetric::SolarRadiation(1020),
+            SensorMetric::BarometricPressure(10132),
+            SensorMetric::WaterLevel(-2_000_001),
+            SensorMetric::FlowRate(u32::MAX),
+        ];
+        for metric in encoded {
+            let domain = crate::SensorMetric::from(metric);
+            assert_eq!(domain.to_wire(), Some(metric));
+        }
+
+        assert_eq!(
+            crate::SensorMetric::from(SensorMetric::SoilTemp(2543)),
+            crate::SensorMetric::SoilTemp {
+                value: NotNan::new(25.43).unwrap()
+            }
+        );
+
+        // values between steps round, values beyond the encoding do not fit
+        let pressure = crate::SensorMetric::BarometricPressure {
+            value: NotNan::new(1013.26).unwrap(),
+        };
+        assert_eq!(
+            pressure.to_wire(),
+            Some(SensorMetric::BarometricPressure(10133))
+        );
+        let heat = crate::SensorMetric::AirTemp {
+            value: NotNan::new(400.0).unwrap(),
+        };
+        assert_eq!(heat.to_wire(), None);
+    }
+}
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS prime_events (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    id TEXT NOT NULL UNIQUE,
+    event_json TEXT NOT NULL,
+    priority INTEGER NOT NULL,
+    state TEXT NOT NULL CHECK (state IN ('pending', 'delivered')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_prime_events_state_priority
+ON prime_events(state, priority, seq);
+
+CREATE INDEX IF NOT EXISTS idx_prime_events_delivered_at
+ON prime_events(delivered_at);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS sensor_downlinks (
+    device_id TEXT NOT NULL,
+    sensor_id TEXT NOT NULL,
+    downlink_json TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (device_id, sensor_id)
+);
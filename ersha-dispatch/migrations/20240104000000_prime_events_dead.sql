@@ -0,0 +1,25 @@
+-- Events that ersha-prime rejected, or that failed too often, are kept in the
+-- 'dead' state instead of being retried forever.
+CREATE TABLE prime_events_new (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    id TEXT NOT NULL UNIQUE,
+    event_json TEXT NOT NULL,
+    priority INTEGER NOT NULL,
+    state TEXT NOT NULL CHECK (state IN ('pending', 'delivered', 'dead')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMP
+);
+
+INSERT INTO prime_events_new (seq, id, event_json, priority, state, attempts, created_at, delivered_at)
+SELECT seq, id, event_json, priority, state, attempts, created_at, delivered_at FROM prime_events;
+
+DROP TABLE prime_events;
+
+ALTER TABLE prime_events_new RENAME TO prime_events;
+
+CREATE INDEX IF NOT EXISTS idx_prime_events_state_priority
+ON prime_events(state, priority, seq);
+
+CREATE INDEX IF NOT EXISTS idx_prime_events_delivered_at
+ON prime_events(delivered_at);
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ersha_core::{Calibration, CalibrationProfile, SensorId, SensorReading};
+use ordered_float::NotNan;
+use tokio::sync::RwLock;
+
+/// Calibration profiles received from ersha-prime, shared between the
+/// uploader that refreshes them and the collector that applies them.
+#[derive(Clone, Default)]
+pub struct Calibrations {
+    inner: Arc<RwLock<HashMap<SensorId, Calibration>>>,
+}
+
+impl Calibrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace all known profiles with the ones ersha-prime sent.
+    pub async fn replace(&self, profiles: impl IntoIterator<Item = CalibrationProfile>) {
+        let table = profiles
+            .into_iter()
+            .filter(|p| p.calibration.is_valid())
+            .map(|p| (p.sensor_id, p.calibration))
+            .collect();
+
+        *self.inner.write().await = table;
+    }
+
+    /// Number of calibrated sensors.
+    pub async fn len(&self) -> usize {
+        self.inner.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.inner.read().await.is_empty()
+    }
+
+    /// Calibrate a reading in place, keeping the original value in
+    /// `raw_value`. Returns whether a profile was applied.
+    pub async fn apply(&self, reading: &mut SensorReading) -> bool {
+        let table = self.inner.read().await;
+        let Some(calibration) = table.get(&reading.sensor_id) else {
+            return false;
+        };
+
+        let raw = reading.metric.value();
+        let Ok(raw_value) = NotNan::new(raw) else {
+            return false;
+        };
+
+        reading.raw_value = Some(raw_value);
+        reading.metric = reading.metric.with_value(calibration.apply(raw));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Calibrations;
+    use ersha_core::*;
+    use ordered_float::NotNan;
+    use ulid::Ulid;
+
+    fn soil_moisture(sensor_id: SensorId, value: u8) -> SensorReading {
+        SensorReading {
+            id: ReadingId(Ulid::new()),
+            device_id: DeviceId(Ulid::new()),
+            dispatcher_id: DispatcherId(Ulid::new()),
+            metric: SensorMetric::SoilMoisture {
+                value: Percentage(value),
+            },
+            location: H3Cell(0x8a2a1072b59ffff),
+            confidence: Percentage(100),
+            timestamp: jiff::Timestamp::now(),
+            sensor_id,
+            raw_value: None,
+        }
+    }
+
+    fn profile(sensor_id: SensorId, calibration: Calibration) -> CalibrationProfile {
+        CalibrationProfile {
+            sensor_id,
+            calibration,
+            updated_at: jiff::Timestamp::now(),
+        }
+    }
+
+    fn points(points: &[(f64, f64)]) -> Calibration {
+        Calibration::Lookup {
+            points: points
+                .iter()
+                .map(|(raw, cal)| (NotNan::new(*raw).unwrap(), NotNan::new(*cal).unwrap()))
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn applies_profile_and_keeps_raw_value() {
+        let calibrations = Calibrations::new();
+        let sensor = SensorId(Ulid::new());
+        calibrations
+            .replace([profile(
+                sensor,
+                points(&[(0.0, 0.0), (50.0, 40.0), (100.0, 100.0)]),
+            )])
+            .await;
+
+        let mut reading = soil_moisture(sensor, 25);
+        assert!(calibrations.apply(&mut reading).await);
+        assert_eq!(
+            reading.metric,
+            SensorMetric::SoilMoisture {
+                value: Percentage(20)
+            }
+        );
+        assert_eq!(reading.raw_value, Some(NotNan::new(25.0).unwrap()));
+
+        let mut uncalibrated = soil_moisture(SensorId(Ulid::new()), 25);
+        assert!(!calibrations.apply(&mut uncalibrated).await);
+        assert_eq!(uncalibrated.raw_value, None);
+    }
+
+    #[tokio::test]
+    async fn replace_drops_stale_and_invalid_profiles() {
+        let calibrations = Calibrations::new();
+        let old = SensorId(Ulid::new());
+        let new = SensorId(Ulid::new());
+        let linear = Calibration::Linear {
+            gain: NotNan::new(2.0).unwrap(),
+            offset: NotNan::new(1.0).unwrap(),
+        };
+
+        calibrations.replace([profile(old, linear.clone())]).await;
+        calibrations
+            .replace([
+                profile(new, linear),
+                profile(SensorId(Ulid::new()), points(&[(1.0, 1.0)])),
+            ])
+            .await;
+
+        assert_eq!(calibrations.len().await, 1);
+        assert!(!calibrations.apply(&mut soil_moisture(old, 10)).await);
+
+        let mut reading = soil_moisture(new, 10);
+        assert!(calibrations.apply(&mut reading).await);
+        assert_eq!(reading.metric.value(), 21.0);
+    }
+}
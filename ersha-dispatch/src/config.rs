@@ -2,7 +2,9 @@ use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
 use ersha_tls::TlsConfig;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+
+use crate::edge::mqtt::PayloadFormat;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -10,7 +12,13 @@ pub struct Config {
     pub server: ServerConfig,
     pub storage: StorageConfig,
     pub prime: PrimeConfig,
-    pub edge: EdgeConfig,
+    /// Edge receivers to run, either a single `[edge]` table or an `[[edge]]` list
+    #[serde(deserialize_with = "one_or_many")]
+    pub edge: Vec<EdgeConfig>,
+    #[serde(default)]
+    pub dedup: DedupConfig,
+    #[serde(default)]
+    pub validation: ValidationConfig,
     pub tls: TlsConfig,
 }
 
@@ -26,6 +34,10 @@ pub struct DispatcherConfig {
 pub struct ServerConfig {
     /// Address for the HTTP server to listen on
     pub http_addr: SocketAddr,
+    /// Bearer token required to queue sensor configuration changes. Without
+    /// one, changes are only accepted from the local host.
+    #[serde(default)]
+    pub api_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,6 +53,53 @@ pub struct PrimeConfig {
     pub rpc_addr: SocketAddr,
     /// Interval in seconds between upload attempts
     pub upload_interval_secs: u64,
+    /// Interval in seconds between checks for new actuator commands, which
+    /// wait at most this long before going out to their device
+    #[serde(default = "default_command_poll_secs")]
+    pub command_poll_secs: u64,
+    /// Operator API key for ersha-prime's HTTP API, used to register mock
+    /// entities
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DedupConfig {
+    /// How long in seconds a reading's sequence number is remembered
+    pub window_secs: u64,
+    /// Maximum number of readings remembered at once
+    pub capacity: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 300,
+            capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ValidationConfig {
+    /// Number of recent values per sensor that spikes are measured against
+    pub window_size: usize,
+    /// Number of identical values in a row after which a sensor is stuck
+    pub stuck_after: usize,
+    /// Signal strength in dBm below which readings lose confidence
+    pub weak_rssi_dbm: i16,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 10,
+            stuck_after: 12,
+            weak_rssi_dbm: -100,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,6 +116,60 @@ pub enum EdgeConfig {
     Tcp {
         addr: SocketAddr,
     },
+    Mqtt {
+        /// Hostname or IP address of the MQTT broker
+        host: String,
+        /// Port of the MQTT broker
+        #[serde(default = "default_mqtt_port")]
+        port: u16,
+        /// Prefix of the topics devices publish on
+        #[serde(default = "default_topic_prefix")]
+        topic_prefix: String,
+        /// Encoding of reading and status payloads
+        #[serde(default)]
+        payload_format: PayloadFormat,
+    },
+}
+
+impl EdgeConfig {
+    /// Short name of the receiver kind, used to label its data.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EdgeConfig::Mock { .. } => "mock",
+            EdgeConfig::Tcp { .. } => "tcp",
+            EdgeConfig::Mqtt { .. } => "mqtt",
+        }
+    }
+}
+
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_topic_prefix() -> String {
+    String::from("ersha")
+}
+
+fn default_command_poll_secs() -> u64 {
+    5
 }
 
 impl Config {
@@ -76,18 +189,84 @@ impl Default for Config {
             },
             server: ServerConfig {
                 http_addr: "0.0.0.0:8081".parse().unwrap(),
+                api_token: None,
             },
             storage: StorageConfig::Memory,
             prime: PrimeConfig {
                 rpc_addr: "127.0.0.1:9000".parse().unwrap(),
                 upload_interval_secs: 60,
+                command_poll_secs: default_command_poll_secs(),
+                api_key: None,
             },
-            edge: EdgeConfig::Mock {
+            edge: vec![EdgeConfig::Mock {
                 reading_interval_secs: 5,
                 status_interval_secs: 30,
                 device_count: 100,
-            },
+            }],
+            dedup: DedupConfig::default(),
+            validation: ValidationConfig::default(),
             tls: TlsConfig::client_default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, EdgeConfig};
+
+    const BASE: &str = r#"
+        [dispatcher]
+        id = "01JJNQ1KQCNZ8X9PQRV5ABCD12"
+        location = 0x8a529b4c8daffff
+
+        [server]
+        http_addr = "0.0.0.0:8081"
+
+        [storage]
+        type = "memory"
+
+        [prime]
+        rpc_addr = "127.0.0.1:9000"
+        upload_interval_secs = 60
+
+        [tls]
+        cert = "./keys/client.crt"
+        key = "./keys/client.key"
+        root_ca = "./keys/root_ca.crt"
+        domain = "localhost"
+    "#;
+
+    #[test]
+    fn single_edge_table() {
+        let toml = format!(
+            "{BASE}
+            [edge]
+            type = \"tcp\"
+            addr = \"0.0.0.0:9001\"
+            "
+        );
+        let config: Config = toml::from_str(&toml).unwrap();
+
+        assert_eq!(config.edge.len(), 1);
+        assert!(matches!(config.edge[0], EdgeConfig::Tcp { .. }));
+    }
+
+    #[test]
+    fn edge_list() {
+        let toml = format!(
+            "{BASE}
+            [[edge]]
+            type = \"tcp\"
+            addr = \"0.0.0.0:9001\"
+
+            [[edge]]
+            type = \"mqtt\"
+            host = \"localhost\"
+            "
+        );
+        let config: Config = toml::from_str(&toml).unwrap();
+
+        let kinds: Vec<_> = config.edge.iter().map(EdgeConfig::kind).collect();
+        assert_eq!(kinds, vec!["tcp", "mqtt"]);
+    }
+}
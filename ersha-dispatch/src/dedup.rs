@@ -0,0 +1,223 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use ersha_core::{DeviceId, SensorId};
+
+/// Identity of a reading as sent by the edge device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ReadingKey {
+    device_id: DeviceId,
+    boot_id: u32,
+    sensor_id: SensorId,
+    seq: u16,
+}
+
+/// Drops readings the dispatcher has already seen.
+///
+/// A reading is a duplicate when the same device and sensor reported the same
+/// edge sequence number within `window`, during the same boot. Devices restart
+/// their sequence numbers when they reboot and pick a new boot id, so a
+/// reused number under a new boot id is a new reading. Sequence numbers also
+/// wrap, so anything older than the window is treated as a new reading. At
+/// most `capacity` keys are remembered; the oldest are forgotten first.
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: Duration,
+    capacity: usize,
+    seen: HashSet<ReadingKey>,
+    order: VecDeque<(ReadingKey, Instant)>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Record a reading, returning `false` if it is a duplicate that should
+    /// be dropped.
+    pub fn check(
+        &mut self,
+        device_id: DeviceId,
+        boot_id: u32,
+        sensor_id: SensorId,
+        seq: u16,
+    ) -> bool {
+        self.check_at(device_id, boot_id, sensor_id, seq, Instant::now())
+    }
+
+    fn check_at(
+        &mut self,
+        device_id: DeviceId,
+        boot_id: u32,
+        sensor_id: SensorId,
+        seq: u16,
+        now: Instant,
+    ) -> bool {
+        self.expire(now);
+
+        let key = ReadingKey {
+            device_id,
+            boot_id,
+            sensor_id,
+            seq,
+        };
+
+        if self.seen.contains(&key) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        if self.capacity == 0 {
+            return true;
+        }
+
+        while self.order.len() >= self.capacity {
+            self.evict_oldest();
+        }
+
+        self.seen.insert(key);
+        self.order.push_back((key, now));
+        true
+    }
+
+    /// Forget a recorded reading so it is accepted if the device sends it
+    /// again, e.g. because storing it failed.
+    pub fn forget(&mut self, device_id: DeviceId, boot_id: u32, sensor_id: SensorId, seq: u16) {
+        let key = ReadingKey {
+            device_id,
+            boot_id,
+            sensor_id,
+            seq,
+        };
+
+        if self.seen.remove(&key) {
+            self.order.retain(|(k, _)| *k != key);
+        }
+    }
+
+    /// Number of duplicate readings dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Shared count behind [`Deduplicator::dropped`], for reporting it from
+    /// another task.
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.dropped)
+    }
+
+    /// Number of readings currently remembered.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((_, seen_at)) = self.order.front() {
+            if now.duration_since(*seen_at) < self.window {
+                break;
+            }
+            self.evict_oldest();
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some((key, _)) = self.order.pop_front() {
+            self.seen.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Deduplicator;
+    use ersha_core::{DeviceId, SensorId};
+    use std::time::{Duration, Instant};
+    use ulid::Ulid;
+
+    #[test]
+    fn repeated_sequence_number_is_dropped() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(60), 16);
+        let device = DeviceId(Ulid::new());
+        let sensor = SensorId(Ulid::new());
+        let other_sensor = SensorId(Ulid::new());
+        let now = Instant::now();
+
+        assert!(dedup.check_at(device, 1, sensor, 7, now));
+        assert!(!dedup.check_at(device, 1, sensor, 7, now + Duration::from_secs(1)));
+        assert!(dedup.check_at(device, 1, sensor, 8, now + Duration::from_secs(1)));
+        assert!(dedup.check_at(device, 1, other_sensor, 7, now + Duration::from_secs(1)));
+        assert_eq!(dedup.dropped(), 1);
+    }
+
+    #[test]
+    fn sequence_number_is_reused_after_window() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(60), 16);
+        let device = DeviceId(Ulid::new());
+        let sensor = SensorId(Ulid::new());
+        let now = Instant::now();
+
+        assert!(dedup.check_at(device, 1, sensor, 7, now));
+        assert!(dedup.check_at(device, 1, sensor, 7, now + Duration::from_secs(60)));
+        assert_eq!(dedup.dropped(), 0);
+        assert_eq!(dedup.len(), 1);
+    }
+
+    #[test]
+    fn memory_is_bounded_by_capacity() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(60), 4);
+        let device = DeviceId(Ulid::new());
+        let sensor = SensorId(Ulid::new());
+        let now = Instant::now();
+
+        for seq in 0..10 {
+            assert!(dedup.check_at(device, 1, sensor, seq, now));
+        }
+        assert_eq!(dedup.len(), 4);
+
+        // the oldest keys were forgotten, the newest are still caught
+        assert!(dedup.check_at(device, 1, sensor, 0, now));
+        assert!(!dedup.check_at(device, 1, sensor, 9, now));
+    }
+
+    #[test]
+    fn forgotten_reading_is_accepted_again() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(60), 16);
+        let device = DeviceId(Ulid::new());
+        let sensor = SensorId(Ulid::new());
+        let now = Instant::now();
+
+        assert!(dedup.check_at(device, 1, sensor, 7, now));
+        dedup.forget(device, 1, sensor, 7);
+        assert!(dedup.is_empty());
+        assert!(dedup.check_at(device, 1, sensor, 7, now));
+        assert_eq!(dedup.dropped(), 0);
+    }
+
+    #[test]
+    fn sequence_number_reused_after_reboot_is_accepted() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(60), 16);
+        let device = DeviceId(Ulid::new());
+        let sensor = SensorId(Ulid::new());
+        let now = Instant::now();
+
+        assert!(dedup.check_at(device, 1, sensor, 0, now));
+        // a retry on a new connection from the same boot is still caught
+        assert!(!dedup.check_at(device, 1, sensor, 0, now + Duration::from_secs(1)));
+        // after a reboot the sequence starts over under a new boot id
+        assert!(dedup.check_at(device, 2, sensor, 0, now + Duration::from_secs(2)));
+        assert_eq!(dedup.dropped(), 1);
+    }
+}
@@ -171,6 +171,7 @@ impl MockDevice {
             confidence: Percentage(rng.random_range(85..100)),
             timestamp: jiff::Timestamp::now(),
             sensor_id,
+            raw_value: None,
         }
     }
 
@@ -210,6 +211,7 @@ impl MockDevice {
             errors: errors.into_boxed_slice(),
             timestamp: jiff::Timestamp::now(),
             sensor_statuses: sensor_statuses.into_boxed_slice(),
+            firmware: None,
         }
     }
 }
@@ -253,7 +255,12 @@ impl EdgeReceiver for MockEdgeReceiver {
                     _ = interval.tick() => {
                         for device in devices_for_readings.iter() {
                             let reading = device.generate_reading(dispatcher_id);
-                            if tx_readings.send(EdgeData::Reading(reading)).await.is_err() {
+                            let data = EdgeData::Reading {
+                                reading,
+                                seq: None,
+                                ack: None,
+                            };
+                            if tx_readings.send(data).await.is_err() {
                                 info!("Channel closed, reading generator shutting down");
                                 return;
                             }
@@ -1,18 +1,114 @@
 pub mod mock;
+pub mod mqtt;
 pub mod tcp;
 
+use std::fmt;
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use ersha_core::{DeviceStatus, SensorReading};
+use ersha_core::{
+    CommandId, CommandResult, DeviceId, DeviceStatus, DisconnectionReason, FirmwareStatus,
+    SensorReading,
+};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::state::DispatcherState;
 
 /// Data received from edge devices.
 #[derive(Debug, Clone)]
 pub enum EdgeData {
     /// A sensor reading from a device.
-    Reading(SensorReading),
+    Reading {
+        reading: SensorReading,
+        /// Sequence number assigned by the edge device, if the transport
+        /// carries one. Used to drop readings that arrive more than once.
+        seq: Option<ReadingSeq>,
+        /// Acknowledges the reading to the device once it has been stored,
+        /// if the transport supports acknowledgements.
+        ack: Option<ReadingAck>,
+    },
     /// A device status report.
     Status(DeviceStatus),
+    /// Firmware status from a device that reports nothing else about
+    /// itself. Stored as a device status with full battery and zero signal,
+    /// and kept out of validation.
+    Firmware {
+        device_id: DeviceId,
+        firmware: FirmwareStatus,
+        timestamp: jiff::Timestamp,
+    },
+    /// An actuator command was acknowledged by its device, or never was.
+    CommandOutcome {
+        device_id: DeviceId,
+        command_id: CommandId,
+        result: CommandResult,
+        timestamp: jiff::Timestamp,
+    },
+    /// A device closed or lost its connection to the receiver.
+    Disconnection {
+        device_id: DeviceId,
+        reason: DisconnectionReason,
+        timestamp: jiff::Timestamp,
+    },
+}
+
+/// Where a reading falls in what its device has sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadingSeq {
+    /// Chosen by the device on every boot, as `seq` starts over then.
+    pub boot_id: u32,
+    pub seq: u16,
+}
+
+/// Handle for acknowledging a reading back to the connection it came in on.
+///
+/// Devices hold on to readings until they are acknowledged and send them
+/// again otherwise, so this must only be used once the reading is safely
+/// stored or has been deliberately discarded.
+#[derive(Debug, Clone)]
+pub struct ReadingAck {
+    seq: u16,
+    tx: mpsc::UnboundedSender<u16>,
+}
+
+impl ReadingAck {
+    pub fn new(seq: u16, tx: mpsc::UnboundedSender<u16>) -> Self {
+        Self { seq, tx }
+    }
+
+    /// Acknowledge the reading. Does nothing if the device has gone.
+    pub fn send(self) {
+        let _ = self.tx.send(self.seq);
+    }
+}
+
+/// Name of the edge receiver that produced a piece of [`EdgeData`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EdgeSource(Arc<str>);
+
+impl EdgeSource {
+    pub fn new(name: impl Into<Arc<str>>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for EdgeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// [`EdgeData`] tagged with the receiver it came from.
+#[derive(Debug, Clone)]
+pub struct SourcedEdgeData {
+    pub source: EdgeSource,
+    pub data: EdgeData,
 }
 
 /// Trait for receiving data from edge devices.
@@ -33,3 +129,104 @@ pub trait EdgeReceiver: Send + Sync + 'static {
         cancel: CancellationToken,
     ) -> Result<mpsc::Receiver<EdgeData>, Self::Error>;
 }
+
+/// Record a device disconnection and forward it to the collector so it can
+/// be queued for delivery to ersha-prime.
+pub(crate) async fn disconnect(
+    state: &DispatcherState,
+    tx: &mpsc::Sender<EdgeData>,
+    device_id: DeviceId,
+    reason: DisconnectionReason,
+) {
+    state.device_disconnected(device_id).await;
+
+    let event = EdgeData::Disconnection {
+        device_id,
+        reason,
+        timestamp: jiff::Timestamp::now(),
+    };
+    if tx.send(event).await.is_err() {
+        warn!(
+            ?device_id,
+            "Internal dispatcher channel closed, dropping disconnection"
+        );
+    }
+}
+
+/// Merge the channels of several started receivers into one, tagging each
+/// item with the receiver it came from.
+///
+/// The merged channel closes once every input channel has closed.
+pub fn merge_receivers(
+    receivers: Vec<(EdgeSource, mpsc::Receiver<EdgeData>)>,
+) -> mpsc::Receiver<SourcedEdgeData> {
+    let (tx, rx) = mpsc::channel(100);
+
+    for (source, mut receiver) in receivers {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while let Some(data) = receiver.recv().await {
+                let item = SourcedEdgeData {
+                    source: source.clone(),
+                    data,
+                };
+                if tx.send(item).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EdgeData, EdgeSource, merge_receivers};
+    use ersha_core::{DeviceId, DisconnectionReason};
+    use tokio::sync::mpsc;
+    use ulid::Ulid;
+
+    fn disconnection(device_id: DeviceId) -> EdgeData {
+        EdgeData::Disconnection {
+            device_id,
+            reason: DisconnectionReason::GracefulClose,
+            timestamp: jiff::Timestamp::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn merged_data_is_tagged_with_source() {
+        let (tcp_tx, tcp_rx) = mpsc::channel(4);
+        let (lora_tx, lora_rx) = mpsc::channel(4);
+
+        let mut merged = merge_receivers(vec![
+            (EdgeSource::new("tcp-0"), tcp_rx),
+            (EdgeSource::new("lora-1"), lora_rx),
+        ]);
+
+        let tcp_device = DeviceId(Ulid::new());
+        let lora_device = DeviceId(Ulid::new());
+        tcp_tx.send(disconnection(tcp_device)).await.unwrap();
+        lora_tx.send(disconnection(lora_device)).await.unwrap();
+        drop(tcp_tx);
+        drop(lora_tx);
+
+        let mut received = Vec::new();
+        while let Some(item) = merged.recv().await {
+            let EdgeData::Disconnection { device_id, .. } = item.data else {
+                panic!("unexpected edge data");
+            };
+            received.push((item.source.as_str().to_string(), device_id));
+        }
+        received.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            received,
+            vec![
+                ("lora-1".to_string(), lora_device),
+                ("tcp-0".to_string(), tcp_device),
+            ]
+        );
+    }
+}
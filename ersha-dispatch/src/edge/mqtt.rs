@@ -0,0 +1,663 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ersha_core::{
+    DeviceError, DeviceId, DeviceStatus, DisconnectionReason, DispatcherId, H3Cell, Percentage,
+    ReadingId, SensorId, SensorMetric, SensorMetricType, SensorReading, SensorStatus, StatusId,
+};
+use ordered_float::NotNan;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use ulid::Ulid;
+
+use super::{EdgeData, EdgeReceiver, ReadingSeq, disconnect};
+use crate::state::DispatcherState;
+
+/// Encoding used by devices for MQTT message payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    #[default]
+    Json,
+    Postcard,
+}
+
+/// Payload published on `{prefix}/{device_id}/{sensor_id}/{metric}`.
+///
+/// JSON payloads may also be a bare number, which is taken as `value`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttReading {
+    pub value: f64,
+    pub confidence: Option<u8>,
+    pub timestamp: Option<jiff::Timestamp>,
+    /// Sequence number of the reading, used to drop redelivered messages.
+    pub seq: Option<u16>,
+    /// Chosen by the device on every boot, for devices whose sequence
+    /// numbers start over when they restart.
+    #[serde(default)]
+    pub boot_id: Option<u32>,
+}
+
+/// Payload published on `{prefix}/{device_id}/status`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttStatus {
+    pub battery_percent: u8,
+    pub uptime_seconds: u64,
+    pub signal_rssi: i16,
+    #[serde(default)]
+    pub errors: Vec<DeviceError>,
+    #[serde(default)]
+    pub sensor_statuses: Vec<SensorStatus>,
+    pub timestamp: Option<jiff::Timestamp>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MqttMessageError {
+    #[error("Topic does not match the ersha scheme: {0}")]
+    UnknownTopic(String),
+
+    #[error("Invalid ULID in topic: {0}")]
+    InvalidId(String),
+
+    #[error("Unknown metric: {0}")]
+    UnknownMetric(String),
+
+    #[error("Value {value} out of range for {metric}")]
+    OutOfRange { metric: &'static str, value: f64 },
+
+    #[error("JSON deserialization failed: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Postcard deserialization failed: {0}")]
+    Postcard(#[from] postcard::Error),
+}
+
+/// Build the metric of a reading topic from its payload value.
+fn metric_from_value(
+    metric_type: SensorMetricType,
+    value: f64,
+) -> Result<SensorMetric, MqttMessageError> {
+    let out_of_range = || MqttMessageError::OutOfRange {
+        metric: metric_type.as_str(),
+        value,
+    };
+
+    let percentage = || {
+        if (0.0..=100.0).contains(&value) {
+            Ok(Percentage(value.round() as u8))
+        } else {
+            Err(out_of_range())
+        }
+    };
+
+    let number = || NotNan::new(value).map_err(|_| out_of_range());
+
+    Ok(match metric_type {
+        SensorMetricType::SoilMoisture => SensorMetric::SoilMoisture {
+            value: percentage()?,
+        },
+        SensorMetricType::SoilTemp => SensorMetric::SoilTemp { value: number()? },
+        SensorMetricType::AirTemp => SensorMetric::AirTemp { value: number()? },
+        SensorMetricType::Humidity => SensorMetric::Humidity {
+            value: percentage()?,
+        },
+        SensorMetricType::Rainfall => SensorMetric::Rainfall { value: number()? },
+        SensorMetricType::SoilEc => SensorMetric::SoilEc { value: number()? },
+        SensorMetricType::SoilPh => SensorMetric::SoilPh { value: number()? },
+        SensorMetricType::LeafWetness => SensorMetric::LeafWetness {
+            value: percentage()?,
+        },
+        SensorMetricType::WindSpeed => SensorMetric::WindSpeed { value: number()? },
+        SensorMetricType::WindDirection => SensorMetric::WindDirection { value: number()? },
+        SensorMetricType::SolarRadiation => SensorMetric::SolarRadiation { value: number()? },
+        SensorMetricType::BarometricPressure => {
+            SensorMetric::BarometricPressure { value: number()? }
+        }
+        SensorMetricType::WaterLevel => SensorMetric::WaterLevel { value: number()? },
+        SensorMetricType::FlowRate => SensorMetric::FlowRate { value: number()? },
+    })
+}
+
+/// A topic under the configured prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Topic {
+    /// `{prefix}/{device_id}/{sensor_id}/{metric}`
+    Reading {
+        device_id: DeviceId,
+        sensor_id: SensorId,
+        metric: SensorMetricType,
+    },
+    /// `{prefix}/{device_id}/status`
+    Status { device_id: DeviceId },
+    /// `{prefix}/{device_id}/connection`, usually also the device's last will.
+    Connection { device_id: DeviceId },
+}
+
+fn parse_ulid(s: &str) -> Result<Ulid, MqttMessageError> {
+    Ulid::from_str(s).map_err(|_| MqttMessageError::InvalidId(s.to_string()))
+}
+
+fn parse_topic(prefix: &str, topic: &str) -> Result<Topic, MqttMessageError> {
+    let unknown = || MqttMessageError::UnknownTopic(topic.to_string());
+
+    let rest = topic
+        .strip_prefix(prefix)
+        .and_then(|r| r.strip_prefix('/'))
+        .ok_or_else(unknown)?;
+    let segments: Vec<&str> = rest.split('/').collect();
+
+    match segments.as_slice() {
+        [device, "status"] => Ok(Topic::Status {
+            device_id: DeviceId(parse_ulid(device)?),
+        }),
+        [device, "connection"] => Ok(Topic::Connection {
+            device_id: DeviceId(parse_ulid(device)?),
+        }),
+        [device, sensor, metric] => Ok(Topic::Reading {
+            device_id: DeviceId(parse_ulid(device)?),
+            sensor_id: SensorId(parse_ulid(sensor)?),
+            metric: metric
+                .parse()
+                .map_err(|_| MqttMessageError::UnknownMetric(metric.to_string()))?,
+        }),
+        _ => Err(unknown()),
+    }
+}
+
+fn decode_reading(format: PayloadFormat, payload: &[u8]) -> Result<MqttReading, MqttMessageError> {
+    match format {
+        PayloadFormat::Json => match serde_json::from_slice::<MqttReading>(payload) {
+            Ok(reading) => Ok(reading),
+            Err(e) => match serde_json::from_slice::<f64>(payload) {
+                Ok(value) => Ok(MqttReading {
+                    value,
+                    confidence: None,
+                    timestamp: None,
+                    seq: None,
+                    boot_id: None,
+                }),
+                Err(_) => Err(e.into()),
+            },
+        },
+        PayloadFormat::Postcard => Ok(postcard::from_bytes(payload)?),
+    }
+}
+
+fn decode_status(format: PayloadFormat, payload: &[u8]) -> Result<MqttStatus, MqttMessageError> {
+    match format {
+        PayloadFormat::Json => Ok(serde_json::from_slice(payload)?),
+        PayloadFormat::Postcard => Ok(postcard::from_bytes(payload)?),
+    }
+}
+
+/// Map a payload on the connection topic to a connection change.
+///
+/// Returns `None` when the device announced itself as online. `offline` is
+/// the conventional last-will payload, which the broker publishes when the
+/// device stops answering keep-alives.
+fn connection_change(payload: &[u8]) -> Option<DisconnectionReason> {
+    match std::str::from_utf8(payload).map(str::trim) {
+        Ok("online") => None,
+        Ok("offline") => Some(DisconnectionReason::Timeout),
+        Ok("closed") => Some(DisconnectionReason::GracefulClose),
+        _ => Some(DisconnectionReason::Unknown),
+    }
+}
+
+/// Edge receiver that subscribes to an MQTT broker.
+///
+/// Devices publish readings on `{prefix}/{device_id}/{sensor_id}/{metric}`,
+/// status reports on `{prefix}/{device_id}/status`, and `online`/`offline`/
+/// `closed` on `{prefix}/{device_id}/connection`.
+pub struct MqttEdgeReceiver {
+    host: String,
+    port: u16,
+    topic_prefix: String,
+    payload_format: PayloadFormat,
+    keep_alive: Duration,
+    dispatcher_id: DispatcherId,
+    location: H3Cell,
+    state: DispatcherState,
+}
+
+impl MqttEdgeReceiver {
+    pub fn new(
+        host: impl Into<String>,
+        port: u16,
+        dispatcher_id: DispatcherId,
+        location: H3Cell,
+        state: DispatcherState,
+    ) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            topic_prefix: String::from("ersha"),
+            payload_format: PayloadFormat::default(),
+            keep_alive: Duration::from_secs(30),
+            dispatcher_id,
+            location,
+            state,
+        }
+    }
+
+    pub fn with_topic_prefix(mut self, topic_prefix: impl Into<String>) -> Self {
+        self.topic_prefix = topic_prefix.into();
+        self
+    }
+
+    pub fn with_payload_format(mut self, payload_format: PayloadFormat) -> Self {
+        self.payload_format = payload_format;
+        self
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+}
+
+#[async_trait]
+impl EdgeReceiver for MqttEdgeReceiver {
+    type Error = rumqttc::ClientError;
+
+    async fn start(
+        &self,
+        cancel: CancellationToken,
+    ) -> Result<mpsc::Receiver<EdgeData>, Self::Error> {
+        let (tx, rx) = mpsc::channel(100);
+
+        let client_id = format!("ersha-dispatch-{}", self.dispatcher_id.0);
+        let mut options = MqttOptions::new(client_id, self.host.clone(), self.port);
+        options.set_keep_alive(self.keep_alive);
+
+        let (client, eventloop) = AsyncClient::new(options, 100);
+
+        info!(host = %self.host, port = self.port, prefix = %self.topic_prefix, "MQTT edge receiver started");
+
+        let session = Session {
+            topic_prefix: self.topic_prefix.clone(),
+            payload_format: self.payload_format,
+            dispatcher_id: self.dispatcher_id,
+            location: self.location,
+            state: self.state.clone(),
+            tx,
+            devices: HashSet::new(),
+        };
+
+        tokio::spawn(run_event_loop(client, eventloop, session, cancel));
+
+        Ok(rx)
+    }
+}
+
+/// State of the subscription, shared across broker reconnects.
+struct Session {
+    topic_prefix: String,
+    payload_format: PayloadFormat,
+    dispatcher_id: DispatcherId,
+    location: H3Cell,
+    state: DispatcherState,
+    tx: mpsc::Sender<EdgeData>,
+    /// Devices currently considered connected through this receiver.
+    devices: HashSet<DeviceId>,
+}
+
+async fn run_event_loop(
+    client: AsyncClient,
+    mut eventloop: rumqttc::EventLoop,
+    mut session: Session,
+    cancel: CancellationToken,
+) {
+    let filter = format!("{}/#", session.topic_prefix);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                info!("Closing MQTT edge receiver");
+                let _ = client.disconnect().await;
+                break;
+            }
+            event = eventloop.poll() => {
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!(%filter, "Connected to MQTT broker, subscribing");
+                        if let Err(e) = client.subscribe(filter.as_str(), QoS::AtLeastOnce).await {
+                            warn!(error = %e, "Failed to subscribe to MQTT topics");
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        if let Err(e) = session.handle_publish(&publish).await {
+                            warn!(error = %e, topic = %publish.topic, "Dropping MQTT message");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!(error = %e, "MQTT broker connection lost, will retry");
+                        session
+                            .disconnect_all(DisconnectionReason::Error(e.to_string().into()))
+                            .await;
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    session
+        .disconnect_all(DisconnectionReason::GracefulClose)
+        .await;
+}
+
+impl Session {
+    async fn handle_publish(&mut self, publish: &Publish) -> Result<(), MqttMessageError> {
+        let topic = parse_topic(&self.topic_prefix, &publish.topic)?;
+
+        let data = match topic {
+            Topic::Reading {
+                device_id,
+                sensor_id,
+                metric,
+            } => {
+                let reading = decode_reading(self.payload_format, &publish.payload)?;
+                let metric = metric_from_value(metric, reading.value)?;
+                self.device_seen(device_id).await;
+
+                EdgeData::Reading {
+                    reading: SensorReading {
+                        id: ReadingId(Ulid::new()),
+                        device_id,
+                        dispatcher_id: self.dispatcher_id,
+                        metric,
+                        location: self.location,
+                        confidence: Percentage(reading.confidence.unwrap_or(100).min(100)),
+                        timestamp: reading.timestamp.unwrap_or_else(jiff::Timestamp::now),
+                        sensor_id,
+                        raw_value: None,
+                    },
+                    seq: reading.seq.map(|seq| ReadingSeq {
+                        boot_id: reading.boot_id.unwrap_or_default(),
+                        seq,
+                    }),
+                    // the broker handles delivery guarantees for MQTT
+                    ack: None,
+                }
+            }
+            Topic::Status { device_id } => {
+                let status = decode_status(self.payload_format, &publish.payload)?;
+                if status.battery_percent > 100 {
+                    return Err(MqttMessageError::OutOfRange {
+                        metric: "battery_percent",
+                        value: status.battery_percent as f64,
+                    });
+                }
+                self.device_seen(device_id).await;
+
+                EdgeData::Status(DeviceStatus {
+                    id: StatusId(Ulid::new()),
+                    device_id,
+                    dispatcher_id: self.dispatcher_id,
+                    battery_percent: Percentage(status.battery_percent),
+                    uptime_seconds: status.uptime_seconds,
+                    signal_rssi: status.signal_rssi,
+                    errors: status.errors.into_boxed_slice(),
+                    timestamp: status.timestamp.unwrap_or_else(jiff::Timestamp::now),
+                    sensor_statuses: status.sensor_statuses.into_boxed_slice(),
+                    firmware: None,
+                })
+            }
+            Topic::Connection { device_id } => {
+                match connection_change(&publish.payload) {
+                    None => self.device_seen(device_id).await,
+                    Some(reason) => {
+                        if self.devices.remove(&device_id) {
+                            disconnect(&self.state, &self.tx, device_id, reason).await;
+                        }
+                    }
+                }
+                return Ok(());
+            }
+        };
+
+        if self.tx.send(data).await.is_err() {
+            warn!("Internal dispatcher channel closed, dropping MQTT message");
+        }
+
+        Ok(())
+    }
+
+    async fn device_seen(&mut self, device_id: DeviceId) {
+        if self.devices.insert(device_id) {
+            debug!(?device_id, "Device connected over MQTT");
+            self.state.device_connected(device_id).await;
+        }
+    }
+
+    async fn disconnect_all(&mut self, reason: DisconnectionReason) {
+        for device_id in self.devices.drain() {
+            disconnect(&self.state, &self.tx, device_id, reason.clone()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MqttEdgeReceiver, MqttReading, PayloadFormat, Topic, connection_change, decode_reading,
+        metric_from_value, parse_topic,
+    };
+    use crate::edge::{EdgeData, EdgeReceiver};
+    use crate::state::DispatcherState;
+    use bytes::BytesMut;
+    use ersha_core::*;
+    use rumqttc::{ConnAck, ConnectReturnCode, Packet, Publish, QoS, SubAck, SubscribeReasonCode};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+    use ulid::Ulid;
+
+    const MAX_PACKET: usize = 64 * 1024;
+
+    async fn read_packet(stream: &mut TcpStream, buf: &mut BytesMut) -> Packet {
+        loop {
+            if let Ok(packet) = Packet::read(buf, MAX_PACKET) {
+                return packet;
+            }
+            let n = stream.read_buf(buf).await.unwrap();
+            assert!(n > 0, "client closed connection");
+        }
+    }
+
+    async fn write_packet(stream: &mut TcpStream, packet: Packet) {
+        let mut out = BytesMut::new();
+        packet.write(&mut out, MAX_PACKET).unwrap();
+        stream.write_all(&out).await.unwrap();
+    }
+
+    /// Accept a single client, complete CONNECT/SUBSCRIBE and publish
+    /// `messages` to it, standing in for a broker forwarding device traffic.
+    async fn run_broker(listener: TcpListener, messages: Vec<(String, Vec<u8>)>) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = BytesMut::new();
+
+        assert!(matches!(
+            read_packet(&mut stream, &mut buf).await,
+            Packet::Connect(..)
+        ));
+        let connack = ConnAck::new(ConnectReturnCode::Success, false);
+        write_packet(&mut stream, Packet::ConnAck(connack)).await;
+
+        let Packet::Subscribe(subscribe) = read_packet(&mut stream, &mut buf).await else {
+            panic!("expected SUBSCRIBE");
+        };
+        assert_eq!(subscribe.filters[0].path, "ersha/#");
+        let suback = SubAck::new(
+            subscribe.pkid,
+            vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)],
+        );
+        write_packet(&mut stream, Packet::SubAck(suback)).await;
+
+        for (topic, payload) in messages {
+            let publish = Publish::new(topic, QoS::AtMostOnce, payload);
+            write_packet(&mut stream, Packet::Publish(publish)).await;
+        }
+
+        // keep the connection open until the client goes away
+        let mut rest = [0u8; 64];
+        while matches!(stream.read(&mut rest).await, Ok(n) if n > 0) {}
+    }
+
+    async fn next(rx: &mut mpsc::Receiver<EdgeData>) -> EdgeData {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("timed out waiting for edge data")
+            .expect("channel closed")
+    }
+
+    #[test]
+    fn parses_topics() {
+        let device = Ulid::new();
+        let sensor = Ulid::new();
+
+        assert_eq!(
+            parse_topic("ersha", &format!("ersha/{device}/{sensor}/soil_temp")).unwrap(),
+            Topic::Reading {
+                device_id: DeviceId(device),
+                sensor_id: SensorId(sensor),
+                metric: SensorMetricType::SoilTemp,
+            }
+        );
+        assert_eq!(
+            parse_topic("farm/ersha", &format!("farm/ersha/{device}/status")).unwrap(),
+            Topic::Status {
+                device_id: DeviceId(device),
+            }
+        );
+        assert!(parse_topic("ersha", &format!("other/{device}/status")).is_err());
+        assert!(parse_topic("ersha", "ersha/not-a-ulid/status").is_err());
+        assert!(parse_topic("ersha", &format!("ersha/{device}/{sensor}/pressure")).is_err());
+        assert_eq!(
+            parse_topic(
+                "ersha",
+                &format!("ersha/{device}/{sensor}/barometric_pressure")
+            )
+            .unwrap(),
+            Topic::Reading {
+                device_id: DeviceId(device),
+                sensor_id: SensorId(sensor),
+                metric: SensorMetricType::BarometricPressure,
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_json_and_postcard_readings() {
+        let bare = decode_reading(PayloadFormat::Json, b"21.5").unwrap();
+        assert_eq!(bare.value, 21.5);
+        assert_eq!(bare.confidence, None);
+
+        let full =
+            decode_reading(PayloadFormat::Json, br#"{"value": 40, "confidence": 80}"#).unwrap();
+        assert_eq!(full.value, 40.0);
+        assert_eq!(full.confidence, Some(80));
+
+        let reading = MqttReading {
+            value: 3.25,
+            confidence: Some(90),
+            timestamp: None,
+            seq: Some(12),
+            boot_id: Some(3),
+        };
+        let bytes = postcard::to_allocvec(&reading).unwrap();
+        assert_eq!(
+            decode_reading(PayloadFormat::Postcard, &bytes).unwrap(),
+            reading
+        );
+
+        assert!(metric_from_value(SensorMetricType::Humidity, 140.0).is_err());
+        assert!(metric_from_value(SensorMetricType::LeafWetness, -5.0).is_err());
+    }
+
+    #[test]
+    fn maps_connection_payloads() {
+        assert_eq!(connection_change(b"online"), None);
+        assert_eq!(
+            connection_change(b"offline"),
+            Some(DisconnectionReason::Timeout)
+        );
+        assert_eq!(
+            connection_change(b"closed"),
+            Some(DisconnectionReason::GracefulClose)
+        );
+        assert_eq!(
+            connection_change(b"???"),
+            Some(DisconnectionReason::Unknown)
+        );
+    }
+
+    #[tokio::test]
+    async fn receives_from_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let device = Ulid::new();
+        let sensor = Ulid::new();
+        let messages = vec![
+            (
+                format!("ersha/{device}/{sensor}/soil_moisture"),
+                b"42".to_vec(),
+            ),
+            (
+                format!("ersha/{device}/status"),
+                br#"{"battery_percent": 77, "uptime_seconds": 120, "signal_rssi": -70}"#.to_vec(),
+            ),
+            (format!("ersha/{device}/connection"), b"offline".to_vec()),
+        ];
+        tokio::spawn(run_broker(listener, messages));
+
+        let state = DispatcherState::new();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        let receiver =
+            MqttEdgeReceiver::new("127.0.0.1", port, dispatcher_id, H3Cell(1), state.clone());
+        let cancel = CancellationToken::new();
+        let mut rx = receiver.start(cancel.clone()).await.unwrap();
+
+        let EdgeData::Reading { reading, .. } = next(&mut rx).await else {
+            panic!("expected a reading");
+        };
+        assert_eq!(reading.device_id, DeviceId(device));
+        assert_eq!(reading.sensor_id, SensorId(sensor));
+        assert_eq!(reading.dispatcher_id, dispatcher_id);
+        assert!(matches!(
+            reading.metric,
+            SensorMetric::SoilMoisture {
+                value: Percentage(42)
+            }
+        ));
+
+        let EdgeData::Status(status) = next(&mut rx).await else {
+            panic!("expected a status");
+        };
+        assert_eq!(status.battery_percent, Percentage(77));
+        assert_eq!(status.signal_rssi, -70);
+
+        let EdgeData::Disconnection {
+            device_id, reason, ..
+        } = next(&mut rx).await
+        else {
+            panic!("expected a disconnection");
+        };
+        assert_eq!(device_id, DeviceId(device));
+        assert_eq!(reason, DisconnectionReason::Timeout);
+        assert_eq!(state.connected_count().await, 0);
+
+        cancel.cancel();
+    }
+}
@@ -1,32 +1,68 @@
 use async_trait::async_trait;
-use ordered_float::NotNan;
-use std::{net::SocketAddr, time::Duration};
+use jiff::{SignedDuration, Timestamp};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
 use tokio::{
     io::{self, AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
     sync::mpsc,
-    time::sleep,
+    time::{Instant, sleep},
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{Span, error, field, info, instrument, warn};
 use ulid::Ulid;
 
-use super::{EdgeData, EdgeReceiver};
+use super::{EdgeData, EdgeReceiver, ReadingAck, ReadingSeq, disconnect};
 use crate::state::DispatcherState;
 use ersha_core::{
-    DeviceId, DisconnectionReason, DispatcherId, H3Cell, Percentage, ReadingId, SensorId,
-    SensorReading,
+    ActuatorAction, ActuatorCommand, ActuatorState, BoxStr, CommandId, CommandResult, DeviceId,
+    DisconnectionReason, DispatcherId, FirmwareFailure, FirmwareStatus, FirmwareUpdate, H3Cell,
+    Percentage, ReadingId, SensorId, SensorReading,
 };
 use ersha_edge::{
-    ReadingPacket,
-    transport::{Msg, MsgType, PACKET_PREAMBLE},
+    ReadingPacket, SensorConfigUpdate,
+    actuator::{self, CommandAck},
+    ota::{
+        CHUNK_SIZE, ChunkRequest, FirmwareChunk, FirmwareOffer, FirmwareReport, UpdateFailure,
+        UpdateState,
+    },
+    transport::{
+        HANDSHAKE_ACCEPTED, HANDSHAKE_UNKNOWN_DEVICE, HELLO, Msg, MsgType, PACKET_PREAMBLE,
+        PROTOCOL_VERSION, Role, SecureError, Session,
+        secure::{NONCE_SIZE, TAG_SIZE},
+    },
 };
 
+/// How long a device may take to authenticate after connecting.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often queued configuration changes and actuator commands are checked
+/// for a connected device.
+const COMMAND_POLL: Duration = Duration::from_secs(1);
+
+/// How long a device may take to acknowledge an actuator command.
+const COMMAND_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug, thiserror::Error)]
 pub enum EdgeConnectionError {
     #[error("Handshake failed: expected HELLO, got {0:?}")]
     HandshakeMismatch([u8; 5]),
 
+    #[error("Handshake timed out")]
+    HandshakeTimeout,
+
+    #[error("No key provisioned for device {0}")]
+    UnknownDevice(Ulid),
+
+    #[error("Frame failed authentication")]
+    AuthenticationFailed,
+
+    #[error("Replayed frame counter {0}")]
+    Replay(u64),
+
+    #[error("Unexpected {0:?} message")]
+    UnexpectedMessage(MsgType),
+
     #[error("Postcard deserialization failed: {0}")]
     Postcard(#[from] postcard::Error),
 
@@ -40,6 +76,63 @@ pub enum EdgeConnectionError {
     ChannelClosed,
 }
 
+impl EdgeConnectionError {
+    fn disconnection_reason(&self) -> DisconnectionReason {
+        match self {
+            EdgeConnectionError::HandshakeTimeout => DisconnectionReason::Timeout,
+            EdgeConnectionError::UnknownDevice(_) => DisconnectionReason::UnknownDevice,
+            EdgeConnectionError::AuthenticationFailed => DisconnectionReason::AuthenticationFailed,
+            EdgeConnectionError::Replay(_) => DisconnectionReason::ReplayDetected,
+            other => DisconnectionReason::Error(other.to_string().into()),
+        }
+    }
+}
+
+/// A frame taken off the stream, not yet authenticated.
+struct Frame {
+    msg_type: MsgType,
+    counter: u64,
+    payload: Vec<u8>,
+    tag: [u8; TAG_SIZE],
+}
+
+impl Frame {
+    /// Take the next complete frame from the front of `buf`, if there is one.
+    fn take(buf: &mut Vec<u8>) -> Result<Option<Frame>, EdgeConnectionError> {
+        let (frame, used) = match postcard::take_from_bytes::<Msg>(buf) {
+            Ok((msg, rest)) => {
+                if msg.preamble != PACKET_PREAMBLE {
+                    return Err(EdgeConnectionError::InvalidPreamble(msg.preamble));
+                }
+                let frame = Frame {
+                    msg_type: msg.msg_type,
+                    counter: msg.counter,
+                    payload: msg.payload.to_vec(),
+                    tag: msg.tag,
+                };
+                (frame, buf.len() - rest.len())
+            }
+            Err(postcard::Error::DeserializeUnexpectedEnd) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        buf.drain(..used);
+        Ok(Some(frame))
+    }
+
+    /// Authenticate and decrypt the frame with the device's session.
+    fn open(mut self, session: &mut Session) -> Result<Frame, EdgeConnectionError> {
+        let counter = self.counter;
+        session
+            .open(&self.msg_type, counter, &mut self.payload, &self.tag)
+            .map_err(|e| match e {
+                SecureError::Replay => EdgeConnectionError::Replay(counter),
+                _ => EdgeConnectionError::AuthenticationFailed,
+            })?;
+        Ok(self)
+    }
+}
+
 pub struct TcpEdgeReceiver {
     addr: SocketAddr,
     dispatcher_id: DispatcherId,
@@ -152,32 +245,46 @@ async fn handle_edge_device(
     dispatcher_id: DispatcherId,
     state: DispatcherState,
 ) -> Result<(), EdgeConnectionError> {
-    let mut hello = [0u8; 5];
-    stream.read_exact(&mut hello).await?;
-
-    if &hello != b"HELLO" {
-        return Err(EdgeConnectionError::HandshakeMismatch(hello));
-    }
-
-    let mut location = [0u8; 8];
-    stream.read_exact(&mut location).await?;
-    let location_raw = u64::from_be_bytes(location);
+    let (device_id, device_nonce) =
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, read_hello(&mut stream)).await {
+            Ok(result) => result?,
+            Err(_) => return Err(EdgeConnectionError::HandshakeTimeout),
+        };
+    Span::current().record("device_id", field::display(&device_id.0));
 
-    let device_ulid = Ulid::new();
-
-    Span::current().record("device_id", field::display(&device_ulid));
-
-    let device_id = DeviceId(device_ulid);
+    let mut buf: Vec<u8> = Vec::with_capacity(128);
+    let authenticated = tokio::time::timeout(
+        HANDSHAKE_TIMEOUT,
+        authenticate(&mut stream, &state, device_id, &device_nonce, &mut buf),
+    )
+    .await
+    .unwrap_or(Err(EdgeConnectionError::HandshakeTimeout));
 
-    stream.write_all(&device_ulid.0.to_be_bytes()).await?;
-    info!("Handshake complete, device ID assigned");
+    let (location_raw, boot_id, mut session) = match authenticated {
+        Ok(v) => v,
+        // the claimed id is not one of ours, so there is nothing to report
+        Err(e @ EdgeConnectionError::UnknownDevice(_)) => return Err(e),
+        Err(e) => {
+            disconnect(&state, &tx, device_id, e.disconnection_reason()).await;
+            return Err(e);
+        }
+    };
+    info!("Handshake complete, device authenticated");
 
     // Track device connection
     state.device_connected(device_id).await;
 
-    let mut buf: Vec<u8> = Vec::with_capacity(128);
+    // the collector acknowledges readings here once they are stored
+    let (ack_tx, mut ack_rx) = mpsc::unbounded_channel::<u16>();
+
     let mut tmp = [0u8; 256];
     let mut disconnection_reason = DisconnectionReason::GracefulClose;
+    // learned from the device's firmware report, chunks are served for it
+    let mut firmware_model: Option<BoxStr> = None;
+    // commands sent to the device, with when their acknowledgement is due
+    let mut unacked: HashMap<CommandId, Instant> = HashMap::new();
+    // the first tick is immediate, so queued changes go out right away
+    let mut command_poll = tokio::time::interval(COMMAND_POLL);
 
     loop {
         tokio::select! {
@@ -185,6 +292,55 @@ async fn handle_edge_device(
                 info!("Shutdown signal received");
                 break;
             }
+            _ = command_poll.tick() => {
+                let now = Instant::now();
+                let overdue: Vec<CommandId> = unacked
+                    .iter()
+                    .filter(|(_, due)| **due <= now)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for command_id in overdue {
+                    unacked.remove(&command_id);
+                    // prime replaces this if the device acknowledges it later
+                    warn!(?command_id, "Actuator command not acknowledged in time");
+                    let outcome = EdgeData::CommandOutcome {
+                        device_id,
+                        command_id,
+                        result: CommandResult::TimedOut,
+                        timestamp: Timestamp::now(),
+                    };
+                    if tx.send(outcome).await.is_err() {
+                        error!("Internal dispatcher channel closed");
+                        disconnection_reason = DisconnectionReason::Error("Channel closed".into());
+                        disconnect(&state, &tx, device_id, disconnection_reason).await;
+                        return Err(EdgeConnectionError::ChannelClosed);
+                    }
+                }
+
+                if let Err(e) = deliver_downlinks(&mut stream, &mut session, &state, device_id).await {
+                    disconnect(&state, &tx, device_id, e.disconnection_reason()).await;
+                    return Err(e);
+                }
+
+                match deliver_commands(&mut stream, &mut session, &state, device_id).await {
+                    Ok(sent) => {
+                        let due = now + COMMAND_ACK_TIMEOUT;
+                        unacked.extend(sent.into_iter().map(|id| (id, due)));
+                    }
+                    Err(e) => {
+                        disconnect(&state, &tx, device_id, e.disconnection_reason()).await;
+                        return Err(e);
+                    }
+                }
+            }
+            Some(seq) = ack_rx.recv() => {
+                let mut payload = seq.to_be_bytes();
+                if let Err(e) = send_sealed(&mut stream, &mut session, MsgType::Ack, &mut payload).await {
+                    disconnection_reason = DisconnectionReason::Error(e.to_string().into());
+                    disconnect(&state, &tx, device_id, disconnection_reason).await;
+                    return Err(e);
+                }
+            }
             read = stream.read(&mut tmp) => {
                 let n = match read {
                     Ok(0) => {
@@ -194,88 +350,1014 @@ async fn handle_edge_device(
                     Ok(n) => n,
                     Err(e) => {
                         disconnection_reason = DisconnectionReason::Error(e.to_string().into());
-                        state.device_disconnected(device_id, disconnection_reason).await;
+                        disconnect(&state, &tx, device_id, disconnection_reason).await;
                         return Err(EdgeConnectionError::Io(e));
                     }
                 };
 
                 buf.extend_from_slice(&tmp[..n]);
 
-                while !buf.is_empty() {
-                    let (msg, rest) = match postcard::take_from_bytes::<Msg>(&buf) {
-                        Ok(v) => v,
-                        Err(postcard::Error::DeserializeUnexpectedEnd) => break,
+                loop {
+                    let frame = match Frame::take(&mut buf)
+                        .and_then(|frame| frame.map(|f| f.open(&mut session)).transpose())
+                    {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => break,
                         Err(e) => {
-                            disconnection_reason = DisconnectionReason::Error(e.to_string().into());
-                            state.device_disconnected(device_id, disconnection_reason).await;
-                            return Err(EdgeConnectionError::Postcard(e));
+                            warn!(error = %e, "Rejected frame from device");
+                            disconnect(&state, &tx, device_id, e.disconnection_reason()).await;
+                            return Err(e);
                         }
                     };
 
-                    if msg.preamble != PACKET_PREAMBLE {
-                        disconnection_reason = DisconnectionReason::Error("Invalid preamble".into());
-                        state.device_disconnected(device_id, disconnection_reason).await;
-                        return Err(EdgeConnectionError::InvalidPreamble(msg.preamble));
-                    }
+                    // a bad payload ends the connection the same way a bad frame does
+                    let handled: Result<(), EdgeConnectionError> = async {
+                        match frame.msg_type {
+                            MsgType::Reading => {
+                                let packet: ReadingPacket = postcard::from_bytes(&frame.payload)
+                                    .inspect_err(|e| {
+                                        warn!(error = %e, "Malformed payload for ReadingPacket")
+                                    })?;
 
-                    match msg.msg_type {
-                        MsgType::Reading => {
-                            let packet: ReadingPacket = postcard::from_bytes(msg.payload)
-                                .map_err(|e| {
-                                    warn!(error = %e, "Malformed payload for ReadingPacket");
-                                    e
-                                })?;
-
-                            let reading = EdgeData::Reading(SensorReading {
-                                id: ReadingId(Ulid::new()),
-                                device_id,
-                                dispatcher_id,
-                                metric: convert_metric(packet.metric),
-                                location: H3Cell(location_raw),
-                                confidence: Percentage(100),
-                                timestamp: jiff::Timestamp::now(),
-                                sensor_id: SensorId(Ulid(packet.sensor_id)),
-                            });
-
-                            if tx.send(reading).await.is_err() {
-                                error!("Internal dispatcher channel closed");
-                                disconnection_reason = DisconnectionReason::Error("Channel closed".into());
-                                state.device_disconnected(device_id, disconnection_reason).await;
-                                return Err(EdgeConnectionError::ChannelClosed);
+                                let (timestamp, confidence) =
+                                    reading_timestamp(packet.timestamp_ms, Timestamp::now());
+                                if confidence != Percentage(100) {
+                                    warn!(
+                                        timestamp_ms = packet.timestamp_ms,
+                                        "Reading timestamp not trusted, confidence lowered"
+                                    );
+                                }
+
+                                let sensor_id = packet.sensor_id;
+                                let seq = ReadingSeq {
+                                    boot_id,
+                                    seq: packet.reading_id,
+                                };
+                                let reading = EdgeData::Reading {
+                                    reading: SensorReading {
+                                        id: reading_id(
+                                            device_id,
+                                            sensor_id,
+                                            seq,
+                                            packet.timestamp_ms,
+                                        ),
+                                        device_id,
+                                        dispatcher_id,
+                                        metric: packet.metric.into(),
+                                        location: H3Cell(location_raw),
+                                        confidence,
+                                        timestamp,
+                                        sensor_id,
+                                        raw_value: None,
+                                    },
+                                    seq: Some(seq),
+                                    ack: Some(ReadingAck::new(
+                                        packet.reading_id,
+                                        ack_tx.clone(),
+                                    )),
+                                };
+
+                                tx.send(reading)
+                                    .await
+                                    .map_err(|_| EdgeConnectionError::ChannelClosed)?;
+                            }
+                            MsgType::FirmwareStatus => {
+                                let report: FirmwareReport = postcard::from_bytes(&frame.payload)
+                                    .inspect_err(|e| {
+                                        warn!(error = %e, "Malformed payload for FirmwareReport")
+                                    })?;
+                                firmware_model = Some(report.model.into());
+
+                                let offer = firmware_offer(&state, &report).await;
+                                let status = EdgeData::Firmware {
+                                    device_id,
+                                    firmware: convert_firmware(&report),
+                                    timestamp: Timestamp::now(),
+                                };
+
+                                tx.send(status)
+                                    .await
+                                    .map_err(|_| EdgeConnectionError::ChannelClosed)?;
+
+                                if let Some(offer) = offer {
+                                    let mut payload = postcard::to_allocvec(&offer)?;
+                                    send_sealed(
+                                        &mut stream,
+                                        &mut session,
+                                        MsgType::FirmwareOffer,
+                                        &mut payload,
+                                    )
+                                    .await?;
+                                    info!(version = offer.version, "Offered firmware update");
+                                }
+                            }
+                            MsgType::FirmwareRequest => {
+                                let request: ChunkRequest = postcard::from_bytes(&frame.payload)
+                                    .inspect_err(|e| {
+                                        warn!(error = %e, "Malformed payload for ChunkRequest")
+                                    })?;
+
+                                let data =
+                                    firmware_chunk(&state, firmware_model.as_deref(), &request)
+                                        .await;
+                                let chunk = FirmwareChunk {
+                                    version: request.version,
+                                    offset: request.offset,
+                                    data: &data,
+                                };
+                                let mut payload = postcard::to_allocvec(&chunk)?;
+                                send_sealed(
+                                    &mut stream,
+                                    &mut session,
+                                    MsgType::FirmwareChunk,
+                                    &mut payload,
+                                )
+                                .await?;
+                            }
+                            MsgType::CommandAck => {
+                                let ack: CommandAck = postcard::from_bytes(&frame.payload)
+                                    .inspect_err(|e| {
+                                        warn!(error = %e, "Malformed payload for CommandAck")
+                                    })?;
+
+                                // also forwarded when sent on an earlier connection
+                                let command_id = ack.command_id;
+                                unacked.remove(&command_id);
+                                let now = Timestamp::now();
+                                let outcome = EdgeData::CommandOutcome {
+                                    device_id,
+                                    command_id,
+                                    result: convert_command_result(ack.result, now),
+                                    timestamp: now,
+                                };
+
+                                tx.send(outcome)
+                                    .await
+                                    .map_err(|_| EdgeConnectionError::ChannelClosed)?;
+                            }
+                            other => {
+                                warn!(msg_type = ?other, "Unexpected message after handshake");
                             }
                         }
-                    };
+                        Ok(())
+                    }
+                    .await;
 
-                    buf = rest.to_vec();
+                    if let Err(e) = handled {
+                        error!(error = %e, "Failed to handle message from device");
+                        disconnect(&state, &tx, device_id, e.disconnection_reason()).await;
+                        return Err(e);
+                    }
                 }
             }
         }
     }
 
     // Track device disconnection (for graceful close or shutdown)
-    state
-        .device_disconnected(device_id, disconnection_reason)
-        .await;
+    disconnect(&state, &tx, device_id, disconnection_reason).await;
+
+    Ok(())
+}
+
+/// Read the device's `HELLO`, the id it claims and its session nonce.
+async fn read_hello(
+    stream: &mut TcpStream,
+) -> Result<(DeviceId, [u8; NONCE_SIZE]), EdgeConnectionError> {
+    let mut hello = [0u8; 5];
+    stream.read_exact(&mut hello).await?;
+
+    if &hello != HELLO {
+        return Err(EdgeConnectionError::HandshakeMismatch(hello));
+    }
+
+    let mut device_id = [0u8; 16];
+    stream.read_exact(&mut device_id).await?;
+
+    let mut nonce = [0u8; NONCE_SIZE];
+    stream.read_exact(&mut nonce).await?;
+
+    Ok((DeviceId(Ulid(u128::from_be_bytes(device_id))), nonce))
+}
+
+/// Agree on a session with a device and check it holds its pre-shared key.
+///
+/// The session key mixes the device's nonce with a fresh one of ours. The
+/// device must answer our nonce with a sealed `Hello` carrying its
+/// location and boot id. Our clock goes back sealed in a `Time` frame, so
+/// the node knows it reached a dispatcher holding its key.
+async fn authenticate(
+    stream: &mut TcpStream,
+    state: &DispatcherState,
+    device_id: DeviceId,
+    device_nonce: &[u8; NONCE_SIZE],
+    buf: &mut Vec<u8>,
+) -> Result<(u64, u32, Session), EdgeConnectionError> {
+    let Some(key) = state.device_key(device_id).await else {
+        stream.write_all(&[HANDSHAKE_UNKNOWN_DEVICE]).await?;
+        return Err(EdgeConnectionError::UnknownDevice(device_id.0));
+    };
+
+    let nonce: [u8; NONCE_SIZE] = rand::random();
+    stream.write_all(&[HANDSHAKE_ACCEPTED]).await?;
+    stream.write_all(&nonce).await?;
+
+    let mut session = Session::new(&key.0, device_nonce, &nonce, device_id, Role::Dispatcher);
+
+    let mut tmp = [0u8; 128];
+    let hello = loop {
+        if let Some(frame) = Frame::take(buf)? {
+            break frame.open(&mut session)?;
+        }
+
+        let n = stream.read(&mut tmp).await?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        buf.extend_from_slice(&tmp[..n]);
+    };
+
+    if hello.msg_type != MsgType::Hello {
+        return Err(EdgeConnectionError::UnexpectedMessage(hello.msg_type));
+    }
+    let hello = <[u8; 12]>::try_from(hello.payload.as_slice())
+        .map_err(|_| EdgeConnectionError::AuthenticationFailed)?;
+    let (location, boot_id) = hello.split_at(8);
+
+    // the node keeps its clock in step with ours from here on
+    let mut now_ms = (Timestamp::now().as_millisecond() as u64).to_be_bytes();
+    send_sealed(stream, &mut session, MsgType::Time, &mut now_ms).await?;
+
+    Ok((
+        u64::from_be_bytes(location.try_into().unwrap()),
+        u32::from_be_bytes(boot_id.try_into().unwrap()),
+        session,
+    ))
+}
+
+/// Send the configuration changes queued for a device, putting back any
+/// that could not be sent.
+async fn deliver_downlinks(
+    stream: &mut TcpStream,
+    session: &mut Session,
+    state: &DispatcherState,
+    device_id: DeviceId,
+) -> Result<(), EdgeConnectionError> {
+    let downlinks = state.take_downlinks(device_id).await;
+
+    for (i, downlink) in downlinks.iter().enumerate() {
+        let update = SensorConfigUpdate {
+            sensor_id: downlink.sensor_id,
+            sampling_rate_ms: downlink.sampling_rate_ms,
+            offset: downlink.offset,
+        };
+        let mut payload = postcard::to_allocvec(&update)?;
+        if let Err(e) = send_sealed(stream, session, MsgType::Config, &mut payload).await {
+            state
+                .requeue_downlinks(device_id, downlinks[i..].to_vec())
+                .await;
+            return Err(e);
+        }
+        info!(sensor_id = ?downlink.sensor_id, "Sent sensor config update");
+    }
 
     Ok(())
 }
 
-fn convert_metric(source: ersha_edge::SensorMetric) -> ersha_core::SensorMetric {
-    match source {
-        ersha_edge::SensorMetric::SoilMoisture(v) => ersha_core::SensorMetric::SoilMoisture {
-            value: ersha_core::Percentage(v),
+/// Send the actuator commands queued for a device, returning the ids of
+/// those sent. Commands that could not be sent are put back.
+async fn deliver_commands(
+    stream: &mut TcpStream,
+    session: &mut Session,
+    state: &DispatcherState,
+    device_id: DeviceId,
+) -> Result<Vec<CommandId>, EdgeConnectionError> {
+    let commands = state.take_commands(device_id, Timestamp::now()).await;
+
+    let mut sent = Vec::with_capacity(commands.len());
+    for (i, command) in commands.iter().enumerate() {
+        let mut payload = postcard::to_allocvec(&convert_command(command))?;
+        if let Err(e) = send_sealed(stream, session, MsgType::Command, &mut payload).await {
+            state.requeue_commands(commands[i..].to_vec()).await;
+            return Err(e);
+        }
+        info!(command_id = ?command.id, action = ?command.action, "Sent actuator command");
+        sent.push(command.id);
+    }
+
+    Ok(sent)
+}
+
+/// The cached image to offer a device after its firmware report, if it is
+/// newer than what the device runs and the device is not mid-update.
+async fn firmware_offer(
+    state: &DispatcherState,
+    report: &FirmwareReport<'_>,
+) -> Option<FirmwareOffer> {
+    let cached = state.firmware().get(report.model).await?;
+    let image = &cached.image;
+
+    let ready = match report.state {
+        UpdateState::Idle | UpdateState::Confirmed { .. } => true,
+        // offering the version that just failed would only fail again
+        UpdateState::Failed { version, .. } => version != image.version,
+        UpdateState::Downloading { .. } | UpdateState::Staged { .. } => false,
+    };
+    if !ready || image.version <= report.version {
+        return None;
+    }
+
+    Some(FirmwareOffer {
+        version: image.version,
+        size: image.size,
+        sha256: image.sha256,
+    })
+}
+
+/// The part of a transfer a device asked for. Empty if the image is no
+/// longer cached or the offset is past its end.
+async fn firmware_chunk(
+    state: &DispatcherState,
+    model: Option<&str>,
+    request: &ChunkRequest,
+) -> Vec<u8> {
+    let Some(model) = model else {
+        return Vec::new();
+    };
+    let cached = state.firmware().get(model).await;
+    let Some(cached) = cached.filter(|c| c.image.version == request.version) else {
+        return Vec::new();
+    };
+
+    let start = (request.offset as usize).min(cached.transfer.len());
+    let end = (start + CHUNK_SIZE).min(cached.transfer.len());
+    cached.transfer[start..end].to_vec()
+}
+
+/// Seal a payload with the device's session and write it as one frame.
+async fn send_sealed(
+    stream: &mut TcpStream,
+    session: &mut Session,
+    msg_type: MsgType,
+    payload: &mut [u8],
+) -> Result<(), EdgeConnectionError> {
+    let (counter, tag) = session
+        .seal(&msg_type, payload)
+        .map_err(|_| EdgeConnectionError::AuthenticationFailed)?;
+    let msg = Msg {
+        preamble: PACKET_PREAMBLE,
+        version: PROTOCOL_VERSION,
+        msg_type,
+        counter,
+        payload,
+        tag,
+    };
+    stream.write_all(&postcard::to_allocvec(&msg)?).await?;
+    Ok(())
+}
+
+/// How far ahead of its arrival a reading may be stamped before the device
+/// clock is considered wrong.
+const MAX_CLOCK_AHEAD: SignedDuration = SignedDuration::from_secs(5);
+
+/// Age past which the device clock may have drifted noticeably since the
+/// reading was taken.
+const MAX_READING_AGE: SignedDuration = SignedDuration::from_hours(24);
+
+/// Confidence given to readings whose timestamp could not be trusted.
+const DRIFTED_CONFIDENCE: Percentage = Percentage(50);
+
+/// Turn a device timestamp into the reading's absolute time and confidence.
+///
+/// Timestamps in the future mean the device clock has drifted, so the
+/// arrival time is used instead and the confidence is lowered. Readings older
+/// than [`MAX_READING_AGE`] keep their timestamp, since a device that was
+/// offline for long still flushes its backlog, but at the lower confidence.
+fn reading_timestamp(timestamp_ms: u64, arrival: Timestamp) -> (Timestamp, Percentage) {
+    let timestamp = i64::try_from(timestamp_ms)
+        .ok()
+        .and_then(|ms| Timestamp::from_millisecond(ms).ok());
+
+    match timestamp {
+        Some(ts) if ts <= arrival.saturating_add(MAX_CLOCK_AHEAD).unwrap_or(arrival) => {
+            if ts >= arrival.saturating_sub(MAX_READING_AGE).unwrap_or(arrival) {
+                (ts, Percentage(100))
+            } else {
+                (ts, DRIFTED_CONFIDENCE)
+            }
+        }
+        _ => (arrival, DRIFTED_CONFIDENCE),
+    }
+}
+
+/// ID of a reading, the same every time its device sends it.
+///
+/// Devices send a reading again until it is acknowledged, possibly through
+/// another dispatcher or after a restart, so the ID is derived from where the
+/// reading falls in what the device sent instead of being minted on arrival.
+/// The capture time keeps readings apart once `seq` wraps around.
+fn reading_id(
+    device_id: DeviceId,
+    sensor_id: SensorId,
+    seq: ReadingSeq,
+    timestamp_ms: u64,
+) -> ReadingId {
+    let digest = Sha256::new()
+        .chain_update(device_id.0.0.to_be_bytes())
+        .chain_update(seq.boot_id.to_be_bytes())
+        .chain_update(sensor_id.0.0.to_be_bytes())
+        .chain_update(seq.seq.to_be_bytes())
+        .finalize();
+    let mut random = [0; 16];
+    random.copy_from_slice(&digest[..16]);
+
+    ReadingId(Ulid::from_parts(timestamp_ms, u128::from_be_bytes(random)))
+}
+
+fn convert_firmware(report: &FirmwareReport) -> FirmwareStatus {
+    let convert_failure = |reason| match reason {
+        UpdateFailure::HashMismatch => FirmwareFailure::HashMismatch,
+        UpdateFailure::BadSignature => FirmwareFailure::BadSignature,
+        UpdateFailure::Storage => FirmwareFailure::Storage,
+        UpdateFailure::Transfer => FirmwareFailure::Transfer,
+    };
+
+    let update = match report.state {
+        UpdateState::Idle => FirmwareUpdate::Idle,
+        UpdateState::Downloading { version, progress } => FirmwareUpdate::Downloading {
+            version,
+            progress: Percentage(progress),
         },
-        ersha_edge::SensorMetric::SoilTemp(v) => ersha_core::SensorMetric::SoilTemp {
-            value: NotNan::new(v as f64 / 100.0).unwrap(),
+        UpdateState::Staged { version } => FirmwareUpdate::Staged { version },
+        UpdateState::Confirmed { version } => FirmwareUpdate::Confirmed { version },
+        UpdateState::Failed { version, reason } => FirmwareUpdate::Failed {
+            version,
+            reason: convert_failure(reason),
+        },
+    };
+
+    FirmwareStatus {
+        model: report.model.into(),
+        version: report.version,
+        update,
+    }
+}
+
+fn convert_command(command: &ActuatorCommand) -> actuator::ActuatorCommand {
+    actuator::ActuatorCommand {
+        command_id: command.id,
+        actuator_id: command.actuator_id,
+        action: match command.action {
+            ActuatorAction::Open { duration_secs } => actuator::ActuatorAction::Open {
+                duration_s: duration_secs,
+            },
+            ActuatorAction::Close => actuator::ActuatorAction::Close,
         },
-        ersha_edge::SensorMetric::AirTemp(v) => ersha_core::SensorMetric::AirTemp {
-            value: NotNan::new(v as f64 / 100.0).unwrap(),
+    }
+}
+
+/// The result a device acknowledged, with its remaining run time made
+/// absolute from when the acknowledgement arrived.
+fn convert_command_result(result: actuator::CommandResult, arrival: Timestamp) -> CommandResult {
+    match result {
+        actuator::CommandResult::Open { remaining_s } => CommandResult::Applied {
+            state: ActuatorState::Open {
+                until: arrival + SignedDuration::from_secs(remaining_s as i64),
+            },
         },
-        ersha_edge::SensorMetric::Humidity(v) => ersha_core::SensorMetric::Humidity {
-            value: ersha_core::Percentage(v),
+        actuator::CommandResult::Closed => CommandResult::Applied {
+            state: ActuatorState::Closed,
         },
-        ersha_edge::SensorMetric::Rainfall(v) => ersha_core::SensorMetric::Rainfall {
-            value: NotNan::new(v as f64 / 100.0).unwrap(),
+        actuator::CommandResult::Fault => CommandResult::Failed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DRIFTED_CONFIDENCE, TcpEdgeReceiver, reading_id, reading_timestamp};
+    use crate::edge::{EdgeData, EdgeReceiver, ReadingSeq};
+    use crate::state::{DispatcherState, SensorDownlink};
+    use ersha_core::{
+        ActuatorAction, ActuatorCommand, ActuatorId, ActuatorState, CommandId, CommandResult,
+        DeviceCredential, DeviceId, DeviceKey, DisconnectionReason, DispatcherId, FirmwareId,
+        FirmwareImage, FirmwareUpdate, Percentage, SensorId,
+    };
+    use ersha_edge::{
+        ReadingPacket, SensorConfigUpdate, SensorMetric, actuator,
+        ota::{ChunkRequest, FirmwareChunk, FirmwareOffer, FirmwareReport, UpdateState},
+        transport::{
+            HANDSHAKE_ACCEPTED, HANDSHAKE_UNKNOWN_DEVICE, HELLO, Msg, MsgType, PACKET_PREAMBLE,
+            PROTOCOL_VERSION, Role, Session,
         },
+    };
+    use jiff::{SignedDuration, Timestamp};
+    use sha2::{Digest, Sha256};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::sync::CancellationToken;
+    use ulid::Ulid;
+
+    fn millis(ts: Timestamp) -> u64 {
+        ts.as_millisecond() as u64
+    }
+
+    #[test]
+    fn trusts_plausible_device_time() {
+        // device timestamps only carry milliseconds
+        let arrival = Timestamp::from_millisecond(Timestamp::now().as_millisecond()).unwrap();
+        let taken = arrival - SignedDuration::from_mins(30);
+
+        assert_eq!(
+            reading_timestamp(millis(taken), arrival),
+            (taken, Percentage(100))
+        );
+    }
+
+    #[test]
+    fn flags_drifted_device_time() {
+        let arrival = Timestamp::now();
+        let ahead = arrival + SignedDuration::from_mins(10);
+
+        assert_eq!(
+            reading_timestamp(millis(ahead), arrival),
+            (arrival, DRIFTED_CONFIDENCE)
+        );
+        assert_eq!(
+            reading_timestamp(u64::MAX, arrival),
+            (arrival, DRIFTED_CONFIDENCE)
+        );
+    }
+
+    #[test]
+    fn reading_ids_survive_resends() {
+        let device_id = DeviceId(Ulid::new());
+        let sensor_id = SensorId(Ulid::new());
+        let seq = ReadingSeq {
+            boot_id: BOOT_ID,
+            seq: 7,
+        };
+        let taken = millis(Timestamp::now());
+
+        let id = reading_id(device_id, sensor_id, seq, taken);
+        assert_eq!(id, reading_id(device_id, sensor_id, seq, taken));
+        assert_eq!(id.0.timestamp_ms(), taken);
+
+        let next = ReadingSeq { seq: 8, ..seq };
+        assert_ne!(id, reading_id(device_id, sensor_id, next, taken));
+        let rebooted = ReadingSeq { boot_id: 1, ..seq };
+        assert_ne!(id, reading_id(device_id, sensor_id, rebooted, taken));
+        // the same seq once it has wrapped around
+        assert_ne!(id, reading_id(device_id, sensor_id, seq, taken + 60_000));
+    }
+
+    #[test]
+    fn keeps_time_of_old_backlog_readings() {
+        let arrival = Timestamp::from_millisecond(Timestamp::now().as_millisecond()).unwrap();
+        let stale = arrival - SignedDuration::from_hours(48);
+
+        // a device offline for two days still knows when it took the reading
+        assert_eq!(
+            reading_timestamp(millis(stale), arrival),
+            (stale, DRIFTED_CONFIDENCE)
+        );
+    }
+
+    const KEY: [u8; 32] = [9; 32];
+    const BOOT_ID: u32 = 0x5eed;
+
+    async fn start_receiver(
+        state: DispatcherState,
+        cancel: &CancellationToken,
+    ) -> (std::net::SocketAddr, tokio::sync::mpsc::Receiver<EdgeData>) {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let receiver = TcpEdgeReceiver::new(addr, DispatcherId(Ulid::new()), state);
+        let rx = receiver.start(cancel.clone()).await.unwrap();
+        (addr, rx)
+    }
+
+    async fn provisioned(device_id: DeviceId) -> DispatcherState {
+        let state = DispatcherState::new();
+        state
+            .set_device_keys([DeviceCredential {
+                device_id,
+                key: DeviceKey(KEY),
+            }])
+            .await;
+        state
+    }
+
+    fn seal(session: &mut Session, msg_type: MsgType, payload: &[u8]) -> Vec<u8> {
+        let mut payload = payload.to_vec();
+        let (counter, tag) = session.seal(&msg_type, &mut payload).unwrap();
+        let msg = Msg {
+            preamble: PACKET_PREAMBLE,
+            version: PROTOCOL_VERSION,
+            msg_type,
+            counter,
+            payload: &payload,
+            tag,
+        };
+        postcard::to_allocvec(&msg).unwrap()
+    }
+
+    async fn send(
+        stream: &mut TcpStream,
+        session: &mut Session,
+        msg_type: MsgType,
+        payload: &[u8],
+    ) {
+        let frame = seal(session, msg_type, payload);
+        stream.write_all(&frame).await.unwrap();
+    }
+
+    /// Run the device side of the handshake, returning the session and the
+    /// dispatcher's clock.
+    async fn handshake(stream: &mut TcpStream, device_id: DeviceId) -> (Session, u64) {
+        stream.write_all(HELLO).await.unwrap();
+        stream
+            .write_all(&device_id.0.0.to_be_bytes())
+            .await
+            .unwrap();
+        let device_nonce: [u8; 16] = rand::random();
+        stream.write_all(&device_nonce).await.unwrap();
+
+        let mut status = [0u8; 1];
+        stream.read_exact(&mut status).await.unwrap();
+        assert_eq!(status[0], HANDSHAKE_ACCEPTED);
+        let mut nonce = [0u8; 16];
+        stream.read_exact(&mut nonce).await.unwrap();
+
+        let mut session = Session::new(&KEY, &device_nonce, &nonce, device_id, Role::Device);
+        let mut hello = [0u8; 12];
+        hello[..8].copy_from_slice(&1u64.to_be_bytes());
+        hello[8..].copy_from_slice(&BOOT_ID.to_be_bytes());
+        send(stream, &mut session, MsgType::Hello, &hello).await;
+
+        let (msg_type, payload) = recv(stream, &mut session).await;
+        assert_eq!(msg_type, MsgType::Time);
+
+        (session, u64::from_be_bytes(payload.try_into().unwrap()))
+    }
+
+    /// Read and open the next frame from the dispatcher.
+    async fn recv(stream: &mut TcpStream, session: &mut Session) -> (MsgType, Vec<u8>) {
+        let mut buf = vec![0u8; 256];
+        let mut len = 0;
+        let (msg_type, counter, mut payload, tag) = loop {
+            len += stream.read(&mut buf[len..]).await.unwrap();
+            if let Ok((msg, _)) = postcard::take_from_bytes::<Msg>(&buf[..len]) {
+                break (msg.msg_type, msg.counter, msg.payload.to_vec(), msg.tag);
+            }
+        };
+        session
+            .open(&msg_type, counter, &mut payload, &tag)
+            .unwrap();
+        (msg_type, payload)
+    }
+
+    #[tokio::test]
+    async fn backlog_reading_keeps_device_time() {
+        let device_id = DeviceId(Ulid::new());
+        let cancel = CancellationToken::new();
+        let (addr, mut rx) = start_receiver(provisioned(device_id).await, &cancel).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let (mut session, server_time) = handshake(&mut stream, device_id).await;
+        assert!(server_time.abs_diff(millis(Timestamp::now())) < 5_000);
+
+        // a reading taken ten minutes before the sync, flushed from the backlog
+        let taken_ms = server_time - 10 * 60 * 1000;
+        let packet = ReadingPacket {
+            device_id,
+            sensor_id: SensorId(Ulid::new()),
+            reading_id: 3,
+            metric: SensorMetric::SoilMoisture(40),
+            timestamp_ms: taken_ms,
+        };
+        let payload = postcard::to_allocvec(&packet).unwrap();
+        send(&mut stream, &mut session, MsgType::Reading, &payload).await;
+
+        let data = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let EdgeData::Reading { reading, seq, ack } = data else {
+            panic!("expected a reading");
+        };
+        assert_eq!(reading.device_id, device_id);
+        assert_eq!(
+            seq,
+            Some(ReadingSeq {
+                boot_id: BOOT_ID,
+                seq: 3
+            })
+        );
+        assert_eq!(reading.timestamp.as_millisecond() as u64, taken_ms);
+        assert_eq!(reading.confidence, Percentage(100));
+
+        // the collector acknowledges once stored, and the device hears of it
+        ack.unwrap().send();
+        let (msg_type, payload) = recv(&mut stream, &mut session).await;
+        assert_eq!(msg_type, MsgType::Ack);
+        assert_eq!(payload, 3u16.to_be_bytes());
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn queued_config_is_sent_while_connected() {
+        let device_id = DeviceId(Ulid::new());
+        let sensor_id = SensorId(Ulid::new());
+        let state = provisioned(device_id).await;
+        state
+            .queue_downlink(
+                device_id,
+                SensorDownlink {
+                    sensor_id,
+                    sampling_rate_ms: Some(15_000),
+                    offset: Some(-2),
+                },
+            )
+            .await;
+        let cancel = CancellationToken::new();
+        let (addr, _rx) = start_receiver(state.clone(), &cancel).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let (mut session, _) = handshake(&mut stream, device_id).await;
+
+        let (msg_type, payload) = recv(&mut stream, &mut session).await;
+        assert_eq!(msg_type, MsgType::Config);
+        let update: SensorConfigUpdate = postcard::from_bytes(&payload).unwrap();
+        assert_eq!(
+            update,
+            SensorConfigUpdate {
+                sensor_id,
+                sampling_rate_ms: Some(15_000),
+                offset: Some(-2),
+            }
+        );
+        assert!(state.take_downlinks(device_id).await.is_empty());
+
+        // changes queued while connected go out on the next poll
+        state
+            .queue_downlink(
+                device_id,
+                SensorDownlink {
+                    sensor_id,
+                    sampling_rate_ms: None,
+                    offset: Some(1),
+                },
+            )
+            .await;
+        let (msg_type, payload) = recv(&mut stream, &mut session).await;
+        assert_eq!(msg_type, MsgType::Config);
+        let update: SensorConfigUpdate = postcard::from_bytes(&payload).unwrap();
+        assert_eq!(update.offset, Some(1));
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn delivers_commands_and_reports_acks() {
+        let device_id = DeviceId(Ulid::new());
+        let actuator_id = ActuatorId(Ulid::new());
+        let state = provisioned(device_id).await;
+        let issued_at = Timestamp::now();
+        let command = ActuatorCommand {
+            id: CommandId(Ulid::new()),
+            device_id,
+            actuator_id,
+            action: ActuatorAction::Open { duration_secs: 600 },
+            issued_at,
+            expires_at: issued_at + SignedDuration::from_mins(5),
+        };
+        assert_eq!(state.set_pending_commands([command.clone()]).await, 1);
+        let cancel = CancellationToken::new();
+        let (addr, mut rx) = start_receiver(state.clone(), &cancel).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let (mut session, _) = handshake(&mut stream, device_id).await;
+
+        let (msg_type, payload) = recv(&mut stream, &mut session).await;
+        assert_eq!(msg_type, MsgType::Command);
+        let sent: actuator::ActuatorCommand = postcard::from_bytes(&payload).unwrap();
+        assert_eq!(
+            sent,
+            actuator::ActuatorCommand {
+                command_id: command.id,
+                actuator_id,
+                action: actuator::ActuatorAction::Open { duration_s: 600 },
+            }
+        );
+        assert!(
+            state
+                .take_commands(device_id, Timestamp::now())
+                .await
+                .is_empty()
+        );
+
+        let ack = actuator::CommandAck {
+            command_id: command.id,
+            result: actuator::CommandResult::Open { remaining_s: 598 },
+        };
+        let payload = postcard::to_allocvec(&ack).unwrap();
+        send(&mut stream, &mut session, MsgType::CommandAck, &payload).await;
+
+        let data = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let EdgeData::CommandOutcome {
+            command_id,
+            result,
+            timestamp,
+            ..
+        } = data
+        else {
+            panic!("expected a command outcome");
+        };
+        assert_eq!(command_id, command.id);
+        assert_eq!(
+            result,
+            CommandResult::Applied {
+                state: ActuatorState::Open {
+                    until: timestamp + SignedDuration::from_secs(598),
+                },
+            }
+        );
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn offers_and_serves_newer_firmware() {
+        let device_id = DeviceId(Ulid::new());
+        let state = provisioned(device_id).await;
+        let data: Vec<u8> = (0..100).collect();
+        let image = FirmwareImage {
+            id: FirmwareId(Ulid::new()),
+            model: "soil-v1".into(),
+            version: 3,
+            size: data.len() as u32,
+            sha256: Sha256::digest(&data).into(),
+            signature: vec![7; 64].into_boxed_slice(),
+            created_at: Timestamp::now(),
+        };
+        assert!(state.firmware().insert(image.clone(), &data).await);
+        let cancel = CancellationToken::new();
+        let (addr, mut rx) = start_receiver(state, &cancel).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let (mut session, _) = handshake(&mut stream, device_id).await;
+
+        let report = FirmwareReport {
+            model: "soil-v1",
+            version: 2,
+            state: UpdateState::Idle,
+        };
+        let payload = postcard::to_allocvec(&report).unwrap();
+        send(&mut stream, &mut session, MsgType::FirmwareStatus, &payload).await;
+
+        let data_event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let EdgeData::Firmware { firmware, .. } = data_event else {
+            panic!("expected a firmware status");
+        };
+        assert_eq!(firmware.version, 2);
+        assert_eq!(firmware.update, FirmwareUpdate::Idle);
+
+        let (msg_type, payload) = recv(&mut stream, &mut session).await;
+        assert_eq!(msg_type, MsgType::FirmwareOffer);
+        let offer: FirmwareOffer = postcard::from_bytes(&payload).unwrap();
+        assert_eq!(
+            offer,
+            FirmwareOffer {
+                version: 3,
+                size: 100,
+                sha256: image.sha256,
+            }
+        );
+
+        // the last chunk runs from the end of the image into the signature
+        let request = ChunkRequest {
+            version: 3,
+            offset: 64,
+        };
+        let payload = postcard::to_allocvec(&request).unwrap();
+        send(
+            &mut stream,
+            &mut session,
+            MsgType::FirmwareRequest,
+            &payload,
+        )
+        .await;
+
+        let (msg_type, payload) = recv(&mut stream, &mut session).await;
+        assert_eq!(msg_type, MsgType::FirmwareChunk);
+        let chunk: FirmwareChunk = postcard::from_bytes(&payload).unwrap();
+        assert_eq!(chunk.offset, 64);
+        assert_eq!(chunk.data.len(), 64);
+        assert_eq!(&chunk.data[..36], &data[64..]);
+        assert_eq!(&chunk.data[36..], &[7; 28]);
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_devices() {
+        let cancel = CancellationToken::new();
+        let (addr, _rx) = start_receiver(DispatcherState::new(), &cancel).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(HELLO).await.unwrap();
+        stream
+            .write_all(&Ulid::new().0.to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&[0; 16]).await.unwrap();
+
+        let mut status = [0u8; 1];
+        stream.read_exact(&mut status).await.unwrap();
+        assert_eq!(status[0], HANDSHAKE_UNKNOWN_DEVICE);
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn drops_devices_sending_replayed_frames() {
+        let device_id = DeviceId(Ulid::new());
+        let cancel = CancellationToken::new();
+        let (addr, mut rx) = start_receiver(provisioned(device_id).await, &cancel).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let (mut session, _) = handshake(&mut stream, device_id).await;
+
+        let packet = ReadingPacket {
+            device_id,
+            sensor_id: SensorId(Ulid::new()),
+            reading_id: 1,
+            metric: SensorMetric::SoilMoisture(40),
+            timestamp_ms: millis(Timestamp::now()),
+        };
+        let payload = postcard::to_allocvec(&packet).unwrap();
+
+        // a captured frame sent again verbatim
+        let frame = seal(&mut session, MsgType::Reading, &payload);
+        stream.write_all(&frame).await.unwrap();
+        stream.write_all(&frame).await.unwrap();
+
+        let mut events = Vec::new();
+        while events.len() < 2 {
+            let data = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            events.push(data);
+        }
+        assert!(matches!(events[0], EdgeData::Reading { .. }));
+        let EdgeData::Disconnection { reason, .. } = &events[1] else {
+            panic!("expected a disconnection");
+        };
+        assert_eq!(*reason, DisconnectionReason::ReplayDetected);
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn disconnects_devices_sending_malformed_payloads() {
+        let device_id = DeviceId(Ulid::new());
+        let cancel = CancellationToken::new();
+        let state = provisioned(device_id).await;
+        let (addr, mut rx) = start_receiver(state.clone(), &cancel).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let (mut session, _) = handshake(&mut stream, device_id).await;
+
+        // sealed with the right key, but not a reading packet
+        send(&mut stream, &mut session, MsgType::Reading, &[0xff]).await;
+
+        let data = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let EdgeData::Disconnection {
+            device_id: disconnected,
+            reason,
+            ..
+        } = data
+        else {
+            panic!("expected a disconnection");
+        };
+        assert_eq!(disconnected, device_id);
+        assert!(matches!(reason, DisconnectionReason::Error(_)));
+        assert_eq!(state.connected_count().await, 0);
+
+        cancel.cancel();
     }
 }
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ersha_core::{
+    BoxStr, DispatcherId, FirmwareChunkRequest, FirmwareImage, FirmwareManifestRequest,
+};
+use ersha_rpc::{Client, ClientError};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// Bytes requested from ersha-prime per chunk.
+const DOWNLOAD_CHUNK: u32 = 256 * 1024;
+
+/// A firmware image held by the dispatcher.
+#[derive(Debug, Clone)]
+pub struct CachedFirmware {
+    pub image: FirmwareImage,
+    /// The image followed by its signature, served to devices as one
+    /// transfer.
+    pub transfer: Arc<[u8]>,
+}
+
+/// Newest firmware image of each device model, downloaded from ersha-prime
+/// and served to edge devices.
+#[derive(Clone, Default)]
+pub struct FirmwareCache {
+    inner: Arc<RwLock<HashMap<BoxStr, CachedFirmware>>>,
+}
+
+impl FirmwareCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The image for a device model, if one is cached.
+    pub async fn get(&self, model: &str) -> Option<CachedFirmware> {
+        self.inner.read().await.get(model).cloned()
+    }
+
+    /// Cache a downloaded image, replacing the one of the same model.
+    /// Returns `false` if the data does not match the image's size and digest.
+    pub async fn insert(&self, image: FirmwareImage, data: &[u8]) -> bool {
+        let digest: [u8; 32] = Sha256::digest(data).into();
+        if data.len() != image.size as usize || digest != image.sha256 {
+            return false;
+        }
+
+        let transfer = [data, &image.signature].concat().into();
+        self.inner
+            .write()
+            .await
+            .insert(image.model.clone(), CachedFirmware { image, transfer });
+        true
+    }
+
+    /// Whether `image` is the one cached for its model.
+    async fn contains(&self, image: &FirmwareImage) -> bool {
+        self.inner
+            .read()
+            .await
+            .get(&image.model)
+            .is_some_and(|cached| cached.image.id == image.id)
+    }
+
+    /// Drop images of models that are no longer published.
+    async fn retain(&self, manifest: &[FirmwareImage]) {
+        self.inner
+            .write()
+            .await
+            .retain(|model, _| manifest.iter().any(|image| &image.model == model));
+    }
+
+    /// Bring the cache in line with ersha-prime's manifest, downloading
+    /// images that are not cached yet.
+    pub async fn sync(
+        &self,
+        client: &Client,
+        dispatcher_id: DispatcherId,
+    ) -> Result<(), ClientError> {
+        let manifest = client
+            .firmware_manifest(FirmwareManifestRequest { dispatcher_id })
+            .await?
+            .images;
+        self.retain(&manifest).await;
+
+        for image in manifest.into_vec() {
+            if self.contains(&image).await {
+                continue;
+            }
+
+            let mut data = Vec::with_capacity(image.size as usize);
+            while data.len() < image.size as usize {
+                let response = client
+                    .firmware_chunk(FirmwareChunkRequest {
+                        dispatcher_id,
+                        firmware_id: image.id,
+                        offset: data.len() as u32,
+                        len: DOWNLOAD_CHUNK,
+                    })
+                    .await?;
+                if response.data.is_empty() {
+                    break;
+                }
+                data.extend_from_slice(&response.data);
+            }
+
+            let (model, version) = (image.model.clone(), image.version);
+            if self.insert(image, &data).await {
+                info!(%model, version, "Firmware image cached");
+            } else {
+                warn!(%model, version, "Downloaded firmware does not match its digest");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FirmwareCache;
+    use ersha_core::*;
+    use ersha_rpc::{Client, RpcTcp, WireMessage};
+    use sha2::{Digest, Sha256};
+    use tokio::net::{TcpListener, TcpStream};
+    use ulid::Ulid;
+
+    fn image(model: &str, version: u32, data: &[u8]) -> FirmwareImage {
+        FirmwareImage {
+            id: FirmwareId(Ulid::new()),
+            model: model.into(),
+            version,
+            size: data.len() as u32,
+            sha256: Sha256::digest(data).into(),
+            signature: vec![9; 64].into_boxed_slice(),
+            created_at: jiff::Timestamp::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_images_not_matching_their_digest() {
+        let cache = FirmwareCache::new();
+        let data = [1u8, 2, 3, 4];
+
+        assert!(!cache.insert(image("soil-v1", 2, &[0; 4]), &data).await);
+        assert!(cache.get("soil-v1").await.is_none());
+
+        assert!(cache.insert(image("soil-v1", 2, &data), &data).await);
+        let cached = cache.get("soil-v1").await.unwrap();
+        assert_eq!(cached.transfer.len(), data.len() + 64);
+        assert_eq!(&cached.transfer[..4], &data);
+    }
+
+    #[tokio::test]
+    async fn sync_downloads_published_images() {
+        let data: Vec<u8> = (0..=255).collect();
+        let published = image("soil-v1", 2, &data);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let manifest = published.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut rpc = RpcTcp::new(stream, 16);
+            while let Some(env) = rpc.recv().await {
+                let reply = match env.payload {
+                    WireMessage::FirmwareManifestRequest(_) => {
+                        WireMessage::FirmwareManifestResponse(FirmwareManifestResponse {
+                            images: vec![manifest.clone()].into_boxed_slice(),
+                        })
+                    }
+                    // serve small chunks to exercise reassembly
+                    WireMessage::FirmwareChunkRequest(request) => {
+                        let start = (request.offset as usize).min(data.len());
+                        let end = (start + 100).min(data.len());
+                        WireMessage::FirmwareChunkResponse(FirmwareChunkResponse {
+                            firmware_id: request.firmware_id,
+                            offset: request.offset,
+                            data: data[start..end].into(),
+                        })
+                    }
+                    _ => continue,
+                };
+                let _ = rpc.reply(env.msg_id, reply).await;
+            }
+        });
+
+        let client = Client::new(TcpStream::connect(addr).await.unwrap());
+        let cache = FirmwareCache::new();
+        cache
+            .sync(&client, DispatcherId(Ulid::new()))
+            .await
+            .unwrap();
+
+        let cached = cache.get("soil-v1").await.unwrap();
+        assert_eq!(cached.image, published);
+        assert_eq!(cached.transfer.len(), 256 + 64);
+    }
+}
@@ -1,12 +1,29 @@
+pub mod calibration;
 pub mod config;
+pub mod dedup;
 pub mod edge;
+pub mod firmware;
+pub mod prime;
 pub mod state;
 pub mod storage;
+pub mod validation;
 
-pub use config::{Config, DispatcherConfig, EdgeConfig, PrimeConfig, ServerConfig, StorageConfig};
+pub use calibration::Calibrations;
+pub use config::{
+    Config, DedupConfig, DispatcherConfig, EdgeConfig, PrimeConfig, ServerConfig, StorageConfig,
+    ValidationConfig,
+};
+pub use dedup::Deduplicator;
 pub use edge::mock::{MockDeviceInfo, MockEdgeReceiver};
-pub use edge::{EdgeData, EdgeReceiver};
-pub use state::{DispatcherState, PrimeEvent};
+pub use edge::mqtt::{MqttEdgeReceiver, PayloadFormat};
+pub use edge::{EdgeData, EdgeReceiver, EdgeSource, ReadingSeq, SourcedEdgeData, merge_receivers};
+pub use firmware::{CachedFirmware, FirmwareCache};
+pub use prime::{DeliveryScope, PrimeConnection, run_alert_sender};
+pub use state::{DispatcherState, PrimeEvent, SensorDownlink};
 pub use storage::memory::MemoryStorage;
 pub use storage::sqlite::SqliteStorage;
-pub use storage::{DeviceStatusStorage, SensorReadingsStorage, StorageMaintenance};
+pub use storage::{
+    DeviceStatusStorage, DownlinkStorage, EventId, PrimeEventStorage, QueuedEvent, SensorReadingsStorage,
+    StorageMaintenance,
+};
+pub use validation::{Assessment, Rejection, Validator};
@@ -1,28 +1,39 @@
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
-use axum::{Router, routing::get};
+use axum::{
+    Json, Router,
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
 use clap::Parser;
 use ersha_core::{
-    AlertId, AlertRequest, AlertSeverity, AlertType, BatchId, BatchUploadRequest,
-    DeviceDisconnectionRequest, DispatcherId, DispatcherStatusRequest, H3Cell, HelloRequest,
-    HelloResponse, SensorState,
+    ActuatorCommandsRequest, AlertId, AlertRequest, AlertSeverity, AlertType, BatchId,
+    BatchUploadRequest, CalibrationRequest, DeviceId, DeviceKeysRequest, DispatcherId,
+    DispatcherStatusRequest, H3Cell, HelloRequest, HelloResponse, ReadingId, SensorId,
+    SensorMetricType, SensorState, StatusId,
 };
 use ersha_dispatch::edge::tcp::TcpEdgeReceiver;
 use ersha_dispatch::{
-    Config, DeviceStatusStorage, DispatcherState, EdgeConfig, EdgeData, EdgeReceiver,
-    MemoryStorage, MockDeviceInfo, MockEdgeReceiver, PrimeEvent, SensorReadingsStorage,
-    SqliteStorage, StorageConfig,
+    Calibrations, Config, Deduplicator, DeliveryScope, DeviceStatusStorage, DispatcherState,
+    DownlinkStorage, EdgeConfig, EdgeData, EdgeReceiver, EdgeSource, MemoryStorage, MockDeviceInfo,
+    MockEdgeReceiver, MqttEdgeReceiver, PrimeConnection, PrimeEvent, PrimeEventStorage, ReadingSeq,
+    SensorDownlink, SensorReadingsStorage, SourcedEdgeData, SqliteStorage, StorageConfig,
+    Validator, merge_receivers, run_alert_sender,
 };
 use ersha_rpc::Client;
 use ersha_tls::TlsConfig;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc;
+use tokio::sync::{Notify, mpsc};
 use tokio_rustls::TlsConnector;
 use tokio_rustls::rustls::pki_types::ServerName;
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use ulid::Ulid;
 
 #[derive(Parser)]
@@ -95,21 +106,83 @@ async fn run_dispatcher<S>(
     location: H3Cell,
 ) -> color_eyre::Result<()>
 where
-    S: SensorReadingsStorage + DeviceStatusStorage + Clone + Send + Sync + 'static,
+    S: SensorReadingsStorage
+        + DeviceStatusStorage
+        + PrimeEventStorage
+        + DownlinkStorage
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     <S as SensorReadingsStorage>::Error: std::error::Error + Send + Sync + 'static,
     <S as DeviceStatusStorage>::Error: std::error::Error + Send + Sync + 'static,
+    <S as PrimeEventStorage>::Error: std::error::Error + Send + Sync + 'static,
+    <S as DownlinkStorage>::Error: std::error::Error + Send + Sync + 'static,
 {
     let cancel = CancellationToken::new();
     let state = DispatcherState::new();
 
-    // Create edge receiver based on config
-    match &config.edge {
+    let downlinks = storage.fetch_downlinks().await?;
+    if !downlinks.is_empty() {
+        info!(
+            devices = downlinks.len(),
+            "Restored queued sensor config updates"
+        );
+    }
+    state.restore_downlinks(downlinks).await;
+
+    if config.edge.is_empty() {
+        return Err(color_eyre::eyre::eyre!("no edge receivers configured"));
+    }
+
+    // Start every configured edge receiver and merge their data
+    let mut receivers = Vec::with_capacity(config.edge.len());
+    for (index, edge) in config.edge.iter().enumerate() {
+        let source = EdgeSource::new(format!("{}-{}", edge.kind(), index));
+        let edge_rx = start_edge_receiver(
+            edge,
+            &source,
+            &config,
+            &cancel,
+            dispatcher_id,
+            location,
+            &state,
+        )
+        .await?;
+        receivers.push((source, edge_rx));
+    }
+    let edge_rx = merge_receivers(receivers);
+
+    run_edge_receivers(
+        edge_rx,
+        cancel,
+        storage,
+        dispatcher_id,
+        location,
+        config,
+        state,
+    )
+    .await
+}
+
+/// Start the edge receiver described by `edge`.
+async fn start_edge_receiver(
+    edge: &EdgeConfig,
+    source: &EdgeSource,
+    config: &Config,
+    cancel: &CancellationToken,
+    dispatcher_id: DispatcherId,
+    location: H3Cell,
+    state: &DispatcherState,
+) -> color_eyre::Result<mpsc::Receiver<EdgeData>> {
+    let edge_rx = match edge {
         EdgeConfig::Mock {
             reading_interval_secs,
             status_interval_secs,
             device_count,
         } => {
             info!(
+                %source,
                 reading_interval_secs,
                 status_interval_secs, device_count, "Using mock edge receiver"
             );
@@ -127,45 +200,42 @@ where
                 format!("http://{}", config.prime.rpc_addr).replace(":9000", ":8080");
             register_mock_entities(
                 &prime_http_url,
+                config.prime.api_key.as_deref(),
                 dispatcher_id,
                 location,
                 &receiver.device_info(),
             )
             .await;
 
-            run_edge_receiver(
-                receiver,
-                cancel,
-                storage,
-                dispatcher_id,
-                location,
-                config,
-                state,
-            )
-            .await?;
+            receiver.start(cancel.clone()).await?
         }
         EdgeConfig::Tcp { addr } => {
-            info!(?addr, "Started TCP edge receiver");
+            info!(%source, ?addr, "Started TCP edge receiver");
 
             let receiver = TcpEdgeReceiver::new(*addr, dispatcher_id, state.clone());
-            run_edge_receiver(
-                receiver,
-                cancel,
-                storage,
-                dispatcher_id,
-                location,
-                config,
-                state,
-            )
-            .await?;
+            receiver.start(cancel.clone()).await?
+        }
+        EdgeConfig::Mqtt {
+            host,
+            port,
+            topic_prefix,
+            payload_format,
+        } => {
+            info!(%source, %host, port, %topic_prefix, "Using MQTT edge receiver");
+
+            let receiver =
+                MqttEdgeReceiver::new(host.clone(), *port, dispatcher_id, location, state.clone())
+                    .with_topic_prefix(topic_prefix.clone())
+                    .with_payload_format(*payload_format);
+            receiver.start(cancel.clone()).await?
         }
     };
 
-    Ok(())
+    Ok(edge_rx)
 }
 
-async fn run_edge_receiver<E: EdgeReceiver, S>(
-    edge_receiver: E,
+async fn run_edge_receivers<S>(
+    edge_rx: mpsc::Receiver<SourcedEdgeData>,
     cancel: CancellationToken,
     storage: S,
     dispatcher_id: DispatcherId,
@@ -174,51 +244,113 @@ async fn run_edge_receiver<E: EdgeReceiver, S>(
     state: DispatcherState,
 ) -> color_eyre::Result<()>
 where
-    S: SensorReadingsStorage + DeviceStatusStorage + Clone + Send + Sync + 'static,
+    S: SensorReadingsStorage
+        + DeviceStatusStorage
+        + PrimeEventStorage
+        + DownlinkStorage
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     <S as SensorReadingsStorage>::Error: std::error::Error + Send + Sync + 'static,
     <S as DeviceStatusStorage>::Error: std::error::Error + Send + Sync + 'static,
+    <S as PrimeEventStorage>::Error: std::error::Error + Send + Sync + 'static,
+    <S as DownlinkStorage>::Error: std::error::Error + Send + Sync + 'static,
 {
-    // Start edge receiver
-    let edge_rx = edge_receiver.start(cancel.clone()).await?;
+    let prime_addr = config.prime.rpc_addr;
+    let tls_config = Arc::new(config.tls);
+    let connection = PrimeConnection::new(move || {
+        let tls_config = Arc::clone(&tls_config);
+        async move { connect_and_register(prime_addr, dispatcher_id, location, &tls_config).await }
+    });
+    let urgent = Arc::new(Notify::new());
 
     // Spawn data collector task
     let storage_for_collector = storage.clone();
     let cancel_for_collector = cancel.clone();
-    let state_for_collector = state.clone();
+    let urgent_for_collector = urgent.clone();
+    let dedup = Deduplicator::new(
+        Duration::from_secs(config.dedup.window_secs),
+        config.dedup.capacity,
+    );
+    let duplicates_dropped = dedup.dropped_counter();
+    let validator = Validator::new(config.validation);
+    let checks = ReadingChecks {
+        dedup,
+        validator,
+        calibrations: state.calibrations(),
+    };
     let collector_handle = tokio::spawn(async move {
         run_data_collector(
             edge_rx,
             storage_for_collector,
             cancel_for_collector,
-            state_for_collector,
             dispatcher_id,
+            urgent_for_collector,
+            checks,
         )
         .await;
     });
 
+    // Spawn alert sender task for critical alerts
+    let alert_sender_handle = tokio::spawn(run_alert_sender(
+        storage.clone(),
+        connection.clone(),
+        dispatcher_id,
+        urgent,
+        cancel.clone(),
+    ));
+
+    // Spawn actuator command poller, faster than the upload interval
+    let command_poll = Duration::from_secs(config.prime.command_poll_secs);
+    let commands_handle = tokio::spawn(run_command_poller(
+        connection.clone(),
+        dispatcher_id,
+        command_poll,
+        cancel.clone(),
+        state.clone(),
+    ));
+
     // Spawn uploader task
     let storage_for_uploader = storage.clone();
     let cancel_for_uploader = cancel.clone();
     let state_for_uploader = state.clone();
-    let prime_addr = config.prime.rpc_addr;
     let upload_interval = Duration::from_secs(config.prime.upload_interval_secs);
     let uploader_handle = tokio::spawn(async move {
         run_uploader(
             storage_for_uploader,
+            connection,
             prime_addr,
             dispatcher_id,
-            location,
             upload_interval,
             cancel_for_uploader,
             state_for_uploader,
-            config.tls,
+            duplicates_dropped,
         )
         .await;
     });
 
+    // Keep queued sensor config updates across restarts
+    let downlinks_handle = tokio::spawn(run_downlink_saver(
+        storage.clone(),
+        state.clone(),
+        cancel.clone(),
+    ));
+
     // HTTP server
     let http_addr = config.server.http_addr;
-    let axum_app = Router::new().route("/health", get(health_handler));
+    let http_state = HttpState {
+        dispatcher: state.clone(),
+        api_token: config.server.api_token.map(Arc::from),
+    };
+    let axum_app = Router::new()
+        .route("/health", get(health_handler))
+        .route(
+            "/devices/{device_id}/sensors/{sensor_id}/config",
+            post(queue_sensor_config),
+        )
+        .with_state(http_state)
+        .into_make_service_with_connect_info::<SocketAddr>();
     let axum_listener = TcpListener::bind(http_addr).await?;
     info!(%http_addr, "HTTP server listening");
 
@@ -241,42 +373,133 @@ where
 
     // Wait for background tasks to complete
     let _ = collector_handle.await;
+    let _ = alert_sender_handle.await;
     let _ = uploader_handle.await;
+    let _ = downlinks_handle.await;
+    let _ = commands_handle.await;
 
     info!("ersha-dispatch shut down complete");
     Ok(())
 }
 
+/// Per-reading processing applied by the data collector, in order: duplicate
+/// removal, calibration, then validation.
+struct ReadingChecks {
+    dedup: Deduplicator,
+    validator: Validator,
+    calibrations: Calibrations,
+}
+
 async fn run_data_collector<S>(
-    mut edge_rx: mpsc::Receiver<EdgeData>,
+    mut edge_rx: mpsc::Receiver<SourcedEdgeData>,
     storage: S,
     cancel: CancellationToken,
-    state: DispatcherState,
     dispatcher_id: DispatcherId,
+    urgent: Arc<Notify>,
+    checks: ReadingChecks,
 ) where
-    S: SensorReadingsStorage + DeviceStatusStorage,
+    S: SensorReadingsStorage + DeviceStatusStorage + PrimeEventStorage,
     <S as SensorReadingsStorage>::Error: std::error::Error,
     <S as DeviceStatusStorage>::Error: std::error::Error,
+    <S as PrimeEventStorage>::Error: std::error::Error,
 {
+    let ReadingChecks {
+        mut dedup,
+        mut validator,
+        calibrations,
+    } = checks;
+
     info!("Data collector started");
 
     loop {
         tokio::select! {
             _ = cancel.cancelled() => {
-                info!("Data collector shutting down");
+                info!(duplicates_dropped = dedup.dropped(), "Data collector shutting down");
                 break;
             }
-            Some(data) = edge_rx.recv() => {
+            Some(SourcedEdgeData { source, data }) = edge_rx.recv() => {
                 match data {
-                    EdgeData::Reading(reading) => {
+                    EdgeData::Reading { mut reading, seq, ack } => {
+                        if let Some(ReadingSeq { boot_id, seq }) = seq
+                            && !dedup.check(reading.device_id, boot_id, reading.sensor_id, seq)
+                        {
+                            // already stored, the device missed our ack
+                            if let Some(ack) = ack {
+                                ack.send();
+                            }
+                            debug!(
+                                %source,
+                                device_id = ?reading.device_id,
+                                sensor_id = ?reading.sensor_id,
+                                seq,
+                                duplicates_dropped = dedup.dropped(),
+                                "Dropped duplicate reading"
+                            );
+                            continue;
+                        }
+
+                        // bounds and history checks see the calibrated value
+                        calibrations.apply(&mut reading).await;
+
+                        let assessment = match validator.assess(&reading) {
+                            Ok(assessment) => assessment,
+                            Err(e) => {
+                                warn!(
+                                    %source,
+                                    device_id = ?reading.device_id,
+                                    sensor_id = ?reading.sensor_id,
+                                    reason = %e,
+                                    "Rejected reading"
+                                );
+                                // sending it again would not change the verdict
+                                if let Some(ack) = ack {
+                                    ack.send();
+                                }
+                                continue;
+                            }
+                        };
+                        reading.confidence = assessment.confidence;
+
+                        if assessment.newly_stuck {
+                            let alert = AlertRequest {
+                                id: AlertId(Ulid::new()),
+                                dispatcher_id,
+                                device_id: Some(reading.device_id),
+                                severity: AlertSeverity::Warning,
+                                alert_type: AlertType::SensorFailure,
+                                message: format!(
+                                    "Sensor {:?} is stuck at {}",
+                                    reading.sensor_id,
+                                    reading.metric.value()
+                                )
+                                .into(),
+                                timestamp: jiff::Timestamp::now(),
+                            };
+                            enqueue_event(&storage, &urgent, PrimeEvent::Alert(alert)).await;
+                            warn!(
+                                device_id = ?reading.device_id,
+                                sensor_id = ?reading.sensor_id,
+                                "Stuck sensor alert queued"
+                            );
+                        }
+
                         let reading_id = reading.id;
+                        let (device_id, sensor_id) = (reading.device_id, reading.sensor_id);
                         if let Err(e) = SensorReadingsStorage::store(&storage, reading).await {
-                            error!(error = ?e, reading_id = ?reading_id, "Failed to store reading");
+                            error!(error = ?e, %source, reading_id = ?reading_id, "Failed to store reading");
+                            // unacknowledged, so let the device's retry through
+                            if let Some(ReadingSeq { boot_id, seq }) = seq {
+                                dedup.forget(device_id, boot_id, sensor_id, seq);
+                            }
                         } else {
-                            info!(reading_id = ?reading_id, "Stored sensor reading");
+                            info!(%source, reading_id = ?reading_id, "Stored sensor reading");
+                            if let Some(ack) = ack {
+                                ack.send();
+                            }
                         }
                     }
                     EdgeData::Status(status) => {
+                        validator.record_status(&status);
                         let status_id = status.id;
                         let device_id = status.device_id;
 
@@ -295,7 +518,7 @@ async fn run_data_collector<S>(
                                 .into(),
                                 timestamp: jiff::Timestamp::now(),
                             };
-                            state.queue_alert(alert).await;
+                            enqueue_event(&storage, &urgent, PrimeEvent::Alert(alert)).await;
                             warn!(
                                 device_id = ?device_id,
                                 battery_percent = status.battery_percent.0,
@@ -319,7 +542,7 @@ async fn run_data_collector<S>(
                                     .into(),
                                     timestamp: jiff::Timestamp::now(),
                                 };
-                                state.queue_alert(alert).await;
+                                enqueue_event(&storage, &urgent, PrimeEvent::Alert(alert)).await;
                                 warn!(
                                     device_id = ?device_id,
                                     sensor_id = ?sensor_status.sensor_id,
@@ -329,31 +552,127 @@ async fn run_data_collector<S>(
                         }
 
                         if let Err(e) = DeviceStatusStorage::store(&storage, status).await {
-                            error!(error = ?e, status_id = ?status_id, "Failed to store status");
+                            error!(error = ?e, %source, status_id = ?status_id, "Failed to store status");
                         } else {
-                            info!(status_id = ?status_id, "Stored device status");
+                            info!(%source, status_id = ?status_id, "Stored device status");
                         }
                     }
+                    EdgeData::Firmware { device_id, firmware, timestamp } => {
+                        // not a health report, so it goes to prime on its own
+                        // rather than as a status
+                        let event = PrimeEvent::Firmware {
+                            device_id,
+                            firmware,
+                            timestamp,
+                        };
+                        enqueue_event(&storage, &urgent, event).await;
+                        debug!(%source, device_id = ?device_id, "Firmware report queued");
+                    }
+                    EdgeData::CommandOutcome { device_id, command_id, result, timestamp } => {
+                        let event = PrimeEvent::CommandOutcome {
+                            command_id,
+                            device_id,
+                            result,
+                            timestamp,
+                        };
+                        enqueue_event(&storage, &urgent, event).await;
+                        info!(%source, device_id = ?device_id, command_id = ?command_id, ?result, "Command outcome queued");
+                    }
+                    EdgeData::Disconnection { device_id, reason, timestamp } => {
+                        validator.forget_device(device_id);
+                        let event = PrimeEvent::DeviceDisconnection {
+                            device_id,
+                            reason,
+                            timestamp,
+                        };
+                        enqueue_event(&storage, &urgent, event).await;
+                        info!(%source, device_id = ?device_id, "Device disconnection queued");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Persist an event for delivery to ersha-prime, waking the alert sender for
+/// critical alerts.
+async fn enqueue_event<S>(storage: &S, urgent: &Notify, event: PrimeEvent)
+where
+    S: PrimeEventStorage,
+    S::Error: std::error::Error,
+{
+    let critical = event.is_critical();
+
+    if let Err(e) = PrimeEventStorage::enqueue(storage, event).await {
+        error!(error = ?e, "Failed to queue event for ersha-prime");
+        return;
+    }
+
+    if critical {
+        urgent.notify_one();
+    }
+}
+
+/// Pick up actuator commands for connected devices every `poll_interval`.
+async fn run_command_poller(
+    connection: PrimeConnection,
+    dispatcher_id: DispatcherId,
+    poll_interval: Duration,
+    cancel: CancellationToken,
+    state: DispatcherState,
+) {
+    info!(
+        poll_interval_secs = poll_interval.as_secs(),
+        "Command poller started"
+    );
+
+    let mut interval = tokio::time::interval(poll_interval);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                info!("Command poller shutting down");
+                break;
+            }
+            _ = interval.tick() => {
+                let c = match connection.client().await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        debug!(error = %e, "Failed to connect to ersha-prime, will retry");
+                        continue;
+                    }
+                };
+
+                match c.actuator_commands(ActuatorCommandsRequest { dispatcher_id }).await {
+                    Ok(resp) => {
+                        let queued = state.set_pending_commands(resp.commands.into_vec()).await;
+                        debug!(queued, "Actuator commands refreshed");
+                    }
+                    Err(e) => {
+                        // queued commands still go out until they expire
+                        warn!(error = ?e, "Failed to fetch actuator commands, will reconnect");
+                        connection.reset(&c).await;
+                    }
                 }
             }
         }
     }
 }
 
-#[allow(clippy::too_many_arguments)]
 async fn run_uploader<S>(
     storage: S,
+    connection: PrimeConnection,
     prime_addr: std::net::SocketAddr,
     dispatcher_id: DispatcherId,
-    location: H3Cell,
     upload_interval: Duration,
     cancel: CancellationToken,
     state: DispatcherState,
-    tls_config: TlsConfig,
+    duplicates_dropped: Arc<AtomicU64>,
 ) where
-    S: SensorReadingsStorage + DeviceStatusStorage,
+    S: SensorReadingsStorage + DeviceStatusStorage + PrimeEventStorage,
     <S as SensorReadingsStorage>::Error: std::error::Error,
     <S as DeviceStatusStorage>::Error: std::error::Error,
+    <S as PrimeEventStorage>::Error: std::error::Error,
 {
     info!(
         prime_addr = %prime_addr,
@@ -362,9 +681,11 @@ async fn run_uploader<S>(
     );
 
     let mut interval = tokio::time::interval(upload_interval);
-    let mut client: Option<Client> = None;
     let mut backoff = Duration::from_secs(1);
     const MAX_BACKOFF: Duration = Duration::from_secs(60);
+    // Batch that ersha-prime did not confirm storing, so a retry of the same
+    // data keeps its ID and ersha-prime can recognise it
+    let mut unacknowledged: Option<(BatchId, Vec<ReadingId>, Vec<StatusId>)> = None;
 
     loop {
         tokio::select! {
@@ -374,30 +695,57 @@ async fn run_uploader<S>(
             }
             _ = interval.tick() => {
                 // Ensure we have a connected and registered client
-                if client.is_none() {
-                    match connect_and_register(prime_addr, dispatcher_id, location, &tls_config).await {
-                        Ok(c) => {
-                            client = Some(c);
-                            backoff = Duration::from_secs(1);
-                        }
-                        Err(e) => {
-                            warn!(error = %e, backoff_secs = backoff.as_secs(), "Failed to connect to ersha-prime, will retry");
-                            tokio::time::sleep(backoff).await;
-                            backoff = (backoff * 2).min(MAX_BACKOFF);
-                            continue;
-                        }
+                let c = match connection.client().await {
+                    Ok(c) => {
+                        backoff = Duration::from_secs(1);
+                        c
+                    }
+                    Err(e) => {
+                        warn!(error = %e, backoff_secs = backoff.as_secs(), "Failed to connect to ersha-prime, will retry");
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                        continue;
+                    }
+                };
+
+                // Alerts and other events go out ahead of the bulk upload,
+                // alerts in a pass of their own so that no other event can
+                // hold them up. Undelivered events stay queued without
+                // holding up the upload, which reconnects if the connection
+                // is gone.
+                for scope in [DeliveryScope::Alerts, DeliveryScope::Other] {
+                    if !connection
+                        .deliver_pending_events(&c, &storage, dispatcher_id, scope)
+                        .await
+                    {
+                        warn!(?scope, "Event delivery failed, uploading data anyway");
                     }
                 }
 
-                let c = client.as_ref().unwrap();
+                // Fetch pending data
+                let readings = match SensorReadingsStorage::fetch_pending(&storage).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        error!(error = ?e, "Failed to fetch pending readings");
+                        continue;
+                    }
+                };
+
+                let statuses = match DeviceStatusStorage::fetch_pending(&storage).await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        error!(error = ?e, "Failed to fetch pending statuses");
+                        continue;
+                    }
+                };
 
                 // Send dispatcher status
-                let pending_events = state.take_pending_events().await;
                 let status_request = DispatcherStatusRequest {
                     dispatcher_id,
                     connected_devices: state.connected_count().await,
                     uptime_seconds: state.uptime_secs().await,
-                    pending_uploads: pending_events.len() as u32,
+                    pending_uploads: (readings.len() + statuses.len()) as u32,
+                    duplicates_dropped: duplicates_dropped.load(Ordering::Relaxed),
                     timestamp: jiff::Timestamp::now(),
                 };
 
@@ -407,60 +755,42 @@ async fn run_uploader<S>(
                     }
                     Err(e) => {
                         error!(error = ?e, "Failed to send dispatcher status, will reconnect");
-                        client = None;
+                        connection.reset(&c).await;
                         continue;
                     }
                 }
 
-                // Process pending events (disconnections and alerts)
-                for event in pending_events {
-                    match event {
-                        PrimeEvent::DeviceDisconnection { device_id, reason } => {
-                            let request = DeviceDisconnectionRequest {
-                                device_id,
-                                dispatcher_id,
-                                timestamp: jiff::Timestamp::now(),
-                                reason: Some(reason),
-                            };
-                            match c.device_disconnection(request).await {
-                                Ok(_) => {
-                                    info!(device_id = ?device_id, "Device disconnection sent to ersha-prime");
-                                }
-                                Err(e) => {
-                                    error!(error = ?e, device_id = ?device_id, "Failed to send device disconnection");
-                                }
-                            }
-                        }
-                        PrimeEvent::Alert(alert) => {
-                            let alert_id = alert.id;
-                            match c.alert(alert).await {
-                                Ok(_) => {
-                                    info!(alert_id = ?alert_id, "Alert sent to ersha-prime");
-                                }
-                                Err(e) => {
-                                    error!(error = ?e, alert_id = ?alert_id, "Failed to send alert");
-                                }
-                            }
-                        }
+                // Refresh calibration profiles for the readings collected next
+                match c.calibration(CalibrationRequest { dispatcher_id }).await {
+                    Ok(resp) => {
+                        let calibrated_sensors = resp.profiles.len();
+                        state.calibrations().replace(resp.profiles.into_vec()).await;
+                        tracing::debug!(calibrated_sensors, "Calibration profiles refreshed");
                     }
-                }
-
-                // Fetch pending data
-                let readings = match SensorReadingsStorage::fetch_pending(&storage).await {
-                    Ok(r) => r,
                     Err(e) => {
-                        error!(error = ?e, "Failed to fetch pending readings");
-                        continue;
+                        // keep applying the last known profiles
+                        warn!(error = ?e, "Failed to fetch calibration profiles");
                     }
-                };
+                }
 
-                let statuses = match DeviceStatusStorage::fetch_pending(&storage).await {
-                    Ok(s) => s,
+                // Refresh the keys edge devices authenticate with
+                match c.device_keys(DeviceKeysRequest { dispatcher_id }).await {
+                    Ok(resp) => {
+                        let device_keys = resp.credentials.len();
+                        state.set_device_keys(resp.credentials.into_vec()).await;
+                        tracing::debug!(device_keys, "Device keys refreshed");
+                    }
                     Err(e) => {
-                        error!(error = ?e, "Failed to fetch pending statuses");
-                        continue;
+                        // keep accepting the last known devices
+                        warn!(error = ?e, "Failed to fetch device keys");
                     }
-                };
+                }
+
+                // Pick up newly published firmware for edge devices
+                if let Err(e) = state.firmware().sync(&c, dispatcher_id).await {
+                    // keep serving the last known images
+                    warn!(error = ?e, "Failed to sync firmware images");
+                }
 
                 if readings.is_empty() && statuses.is_empty() {
                     tracing::debug!("No pending data to upload");
@@ -477,8 +807,14 @@ async fn run_uploader<S>(
                 let reading_ids: Vec<_> = readings.iter().map(|r| r.id).collect();
                 let status_ids: Vec<_> = statuses.iter().map(|s| s.id).collect();
 
+                let batch_id = match unacknowledged.take() {
+                    Some((id, readings, statuses))
+                        if readings == reading_ids && statuses == status_ids => id,
+                    _ => BatchId(Ulid::new()),
+                };
+
                 let batch = BatchUploadRequest {
-                    id: BatchId(Ulid::new()),
+                    id: batch_id,
                     dispatcher_id,
                     readings: readings.into_boxed_slice(),
                     statuses: statuses.into_boxed_slice(),
@@ -486,8 +822,24 @@ async fn run_uploader<S>(
                 };
 
                 match c.batch_upload(batch).await {
+                    Ok(resp) if resp.failed => {
+                        // keep the data pending and upload it again next tick
+                        warn!(
+                            batch_id = ?resp.id,
+                            rejections = ?resp.rejections,
+                            "ersha-prime did not take the batch, will retry"
+                        );
+                        unacknowledged = Some((batch_id, reading_ids, status_ids));
+                    }
                     Ok(resp) => {
                         info!(batch_id = ?resp.id, "Batch uploaded successfully");
+                        if !resp.rejections.is_empty() {
+                            warn!(
+                                batch_id = ?resp.id,
+                                rejections = ?resp.rejections,
+                                "ersha-prime rejected part of the batch"
+                            );
+                        }
 
                         // Mark data as uploaded
                         if let Err(e) = SensorReadingsStorage::mark_uploaded(&storage, &reading_ids).await {
@@ -499,7 +851,8 @@ async fn run_uploader<S>(
                     }
                     Err(e) => {
                         error!(error = ?e, "Failed to upload batch, will reconnect");
-                        client = None;
+                        unacknowledged = Some((batch_id, reading_ids, status_ids));
+                        connection.reset(&c).await;
                     }
                 }
             }
@@ -546,13 +899,108 @@ async fn health_handler() -> &'static str {
     "OK"
 }
 
+/// Store the queued sensor config updates whenever they change, and once
+/// more on shutdown.
+async fn run_downlink_saver<S>(storage: S, state: DispatcherState, cancel: CancellationToken)
+where
+    S: DownlinkStorage,
+{
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = state.downlinks_changed() => {
+                let pending = state.pending_downlinks().await;
+                if let Err(e) = storage.replace_downlinks(&pending).await {
+                    error!(error = ?e, "Failed to store queued sensor config updates");
+                }
+            }
+        }
+    }
+
+    let pending = state.pending_downlinks().await;
+    if let Err(e) = storage.replace_downlinks(&pending).await {
+        error!(error = ?e, "Failed to store queued sensor config updates");
+    }
+}
+
+/// State shared by the HTTP handlers.
+#[derive(Clone)]
+struct HttpState {
+    dispatcher: DispatcherState,
+    api_token: Option<Arc<str>>,
+}
+
+impl HttpState {
+    /// Whether a request may change device configuration: it must carry the
+    /// configured bearer token, or come from the local host if there is none.
+    fn authorized(&self, peer: SocketAddr, headers: &HeaderMap) -> bool {
+        let Some(token) = self.api_token.as_deref() else {
+            return peer.ip().is_loopback();
+        };
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|presented| presented == token)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SensorConfigBody {
+    sampling_rate_ms: Option<u32>,
+    offset: Option<i16>,
+}
+
+/// Queue a sensor configuration change for the device's next connection.
+async fn queue_sensor_config(
+    State(http): State<HttpState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path((device_id, sensor_id)): Path<(String, String)>,
+    Json(body): Json<SensorConfigBody>,
+) -> Response {
+    if !http.authorized(peer, &headers) {
+        warn!(%peer, "Rejected unauthorized sensor config update");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+
+    let (Ok(device_id), Ok(sensor_id)) = (device_id.parse::<Ulid>(), sensor_id.parse::<Ulid>())
+    else {
+        return (StatusCode::BAD_REQUEST, "Invalid device or sensor ID").into_response();
+    };
+
+    if body.sampling_rate_ms.is_none() && body.offset.is_none() {
+        return (StatusCode::BAD_REQUEST, "Nothing to change").into_response();
+    }
+    if body.sampling_rate_ms == Some(0) {
+        return (StatusCode::BAD_REQUEST, "Sampling rate must be positive").into_response();
+    }
+
+    let downlink = SensorDownlink {
+        sensor_id: SensorId(sensor_id),
+        sampling_rate_ms: body.sampling_rate_ms,
+        offset: body.offset,
+    };
+    let pending = http
+        .dispatcher
+        .queue_downlink(DeviceId(device_id), downlink)
+        .await;
+    info!(%device_id, %sensor_id, pending, "Queued sensor config update");
+
+    (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({ "pending_sensors": pending })),
+    )
+        .into_response()
+}
+
 /// Sensor kinds in the same order as MockDevice creates them.
-const SENSOR_KINDS: [&str; 5] = [
-    "soil_moisture",
-    "soil_temp",
-    "air_temp",
-    "humidity",
-    "rainfall",
+const SENSOR_KINDS: [SensorMetricType; 5] = [
+    SensorMetricType::SoilMoisture,
+    SensorMetricType::SoilTemp,
+    SensorMetricType::AirTemp,
+    SensorMetricType::Humidity,
+    SensorMetricType::Rainfall,
 ];
 
 /// Register the dispatcher and all mock devices with ersha-prime's HTTP API.
@@ -561,23 +1009,26 @@ const SENSOR_KINDS: [&str; 5] = [
 /// registered, we log and continue.
 async fn register_mock_entities(
     prime_http_url: &str,
+    api_key: Option<&str>,
     dispatcher_id: DispatcherId,
     location: H3Cell,
     devices: &[MockDeviceInfo],
 ) {
     let http = reqwest::Client::new();
+    let post = |path: &str| {
+        let request = http.post(format!("{prime_http_url}{path}"));
+        match api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    };
 
     // Register dispatcher
     let dispatcher_body = serde_json::json!({
         "id": dispatcher_id.0.to_string(),
         "location": location.0,
     });
-    match http
-        .post(format!("{prime_http_url}/api/dispatchers"))
-        .json(&dispatcher_body)
-        .send()
-        .await
-    {
+    match post("/api/dispatchers").json(&dispatcher_body).send().await {
         Ok(resp) if resp.status().is_success() => {
             info!("Registered dispatcher with ersha-prime");
         }
@@ -602,7 +1053,7 @@ async fn register_mock_entities(
             .map(|(sid, kind)| {
                 serde_json::json!({
                     "id": sid.0.to_string(),
-                    "kind": kind,
+                    "kind": kind.as_str(),
                 })
             })
             .collect();
@@ -615,12 +1066,7 @@ async fn register_mock_entities(
             "sensors": sensors,
         });
 
-        match http
-            .post(format!("{prime_http_url}/api/devices"))
-            .json(&body)
-            .send()
-            .await
-        {
+        match post("/api/devices").json(&body).send().await {
             Ok(resp) if resp.status().is_success() => {
                 registered += 1;
             }
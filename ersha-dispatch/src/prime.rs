@@ -0,0 +1,564 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use ersha_core::{
+    CommandOutcomeRequest, DeviceDisconnectionRequest, DispatcherId, FirmwareReportRequest,
+};
+use ersha_rpc::{Client, ClientError, WireErrorCode};
+use tokio::sync::{Mutex, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::state::PrimeEvent;
+use crate::storage::PrimeEventStorage;
+
+/// Failed deliveries after which an event is given up on.
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 10;
+
+pub type ConnectFn =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = color_eyre::Result<Client>> + Send>> + Send + Sync>;
+
+/// Which queued events a delivery pass should send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryScope {
+    /// Every pending event, in priority order.
+    All,
+    /// Only critical alerts.
+    Critical,
+    /// Every alert, critical ones first.
+    Alerts,
+    /// Every event except alerts.
+    Other,
+}
+
+impl DeliveryScope {
+    fn includes(&self, event: &PrimeEvent) -> bool {
+        match self {
+            DeliveryScope::All => true,
+            DeliveryScope::Critical => event.is_critical(),
+            DeliveryScope::Alerts => event.is_alert(),
+            DeliveryScope::Other => !event.is_alert(),
+        }
+    }
+}
+
+/// Connection to ersha-prime shared by the uploader and the alert sender.
+///
+/// The connection is opened on demand and dropped on failure so that the
+/// next caller reconnects.
+#[derive(Clone)]
+pub struct PrimeConnection {
+    connect: ConnectFn,
+    client: Arc<Mutex<Option<Arc<Client>>>>,
+    delivery: Arc<Mutex<()>>,
+}
+
+impl PrimeConnection {
+    pub fn new<F, Fut>(connect: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = color_eyre::Result<Client>> + Send + 'static,
+    {
+        Self {
+            connect: Arc::new(move || Box::pin(connect())),
+            client: Arc::new(Mutex::new(None)),
+            delivery: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Get the open client, connecting first if there is none.
+    pub async fn client(&self) -> color_eyre::Result<Arc<Client>> {
+        let mut slot = self.client.lock().await;
+
+        if let Some(client) = slot.as_ref() {
+            return Ok(Arc::clone(client));
+        }
+
+        let client = Arc::new((self.connect)().await?);
+        *slot = Some(Arc::clone(&client));
+        Ok(client)
+    }
+
+    /// Drop `client` so the next call to [`PrimeConnection::client`] reconnects.
+    ///
+    /// Does nothing if a newer client has already replaced it.
+    pub async fn reset(&self, client: &Arc<Client>) {
+        let mut slot = self.client.lock().await;
+
+        if slot.as_ref().is_some_and(|c| Arc::ptr_eq(c, client)) {
+            *slot = None;
+        }
+    }
+
+    /// Deliver queued events to ersha-prime in priority order.
+    ///
+    /// An event that ersha-prime rejects is marked dead and the pass goes on
+    /// with the next one. Any other failure stops the pass, since the
+    /// connection is likely gone, and `false` is returned. The event stays
+    /// queued until it has failed [`MAX_DELIVERY_ATTEMPTS`] times. Only one
+    /// delivery pass runs at a time.
+    pub async fn deliver_pending_events<S>(
+        &self,
+        client: &Client,
+        storage: &S,
+        dispatcher_id: DispatcherId,
+        scope: DeliveryScope,
+    ) -> bool
+    where
+        S: PrimeEventStorage,
+        S::Error: std::error::Error,
+    {
+        let _guard = self.delivery.lock().await;
+
+        let pending = match PrimeEventStorage::fetch_pending(storage).await {
+            Ok(events) => events,
+            Err(e) => {
+                error!(error = ?e, "Failed to fetch pending events");
+                return true;
+            }
+        };
+
+        for queued in pending.into_iter().filter(|q| scope.includes(&q.event)) {
+            let result = match queued.event {
+                PrimeEvent::DeviceDisconnection {
+                    device_id,
+                    reason,
+                    timestamp,
+                } => {
+                    let request = DeviceDisconnectionRequest {
+                        device_id,
+                        dispatcher_id,
+                        timestamp,
+                        reason: Some(reason),
+                    };
+                    client.device_disconnection(request).await.map(|_| ())
+                }
+                PrimeEvent::Alert(alert) => client.alert(alert).await.map(|_| ()),
+                PrimeEvent::CommandOutcome {
+                    command_id,
+                    device_id,
+                    result,
+                    timestamp,
+                } => {
+                    let request = CommandOutcomeRequest {
+                        command_id,
+                        dispatcher_id,
+                        device_id,
+                        result,
+                        timestamp,
+                    };
+                    client.command_outcome(request).await.map(|_| ())
+                }
+                PrimeEvent::Firmware {
+                    device_id,
+                    firmware,
+                    timestamp,
+                } => {
+                    let request = FirmwareReportRequest {
+                        dispatcher_id,
+                        device_id,
+                        firmware,
+                        timestamp,
+                    };
+                    client.firmware_report(request).await.map(|_| ())
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    info!(event_id = ?queued.id, "Event delivered to ersha-prime");
+                    if let Err(e) =
+                        PrimeEventStorage::mark_delivered(storage, std::slice::from_ref(&queued.id))
+                            .await
+                    {
+                        error!(error = ?e, event_id = ?queued.id, "Failed to mark event as delivered");
+                    }
+                }
+                Err(e) => {
+                    let attempts = queued.attempts + 1;
+                    let rejected = is_rejection(&e);
+                    error!(
+                        error = ?e,
+                        event_id = ?queued.id,
+                        attempts,
+                        rejected,
+                        "Failed to deliver event"
+                    );
+
+                    let recorded = if rejected || attempts >= MAX_DELIVERY_ATTEMPTS {
+                        warn!(event_id = ?queued.id, attempts, "Giving up on event");
+                        PrimeEventStorage::mark_dead(storage, queued.id).await
+                    } else {
+                        PrimeEventStorage::record_failure(storage, queued.id).await
+                    };
+                    if let Err(e) = recorded {
+                        error!(error = ?e, event_id = ?queued.id, "Failed to record delivery failure");
+                    }
+
+                    if !rejected {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+}
+
+/// Whether ersha-prime answered with a rejection that retrying the same
+/// request cannot change.
+fn is_rejection(error: &ClientError) -> bool {
+    match error {
+        ClientError::ErrorResponse(e) => {
+            matches!(
+                e.code,
+                WireErrorCode::BadRequest | WireErrorCode::Unsupported
+            )
+        }
+        ClientError::UnexpectedResponse => true,
+        ClientError::Rpc(_) => false,
+    }
+}
+
+/// Send critical alerts to ersha-prime as soon as they are queued.
+///
+/// Each notification on `urgent` triggers a delivery pass limited to critical
+/// alerts, opening a connection if none is available. Anything this pass
+/// cannot deliver stays queued for the regular uploader.
+pub async fn run_alert_sender<S>(
+    storage: S,
+    connection: PrimeConnection,
+    dispatcher_id: DispatcherId,
+    urgent: Arc<Notify>,
+    cancel: CancellationToken,
+) where
+    S: PrimeEventStorage,
+    S::Error: std::error::Error,
+{
+    info!("Alert sender started");
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                info!("Alert sender shutting down");
+                break;
+            }
+            _ = urgent.notified() => {
+                let client = match connection.client().await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        warn!(error = %e, "Failed to connect to ersha-prime, critical alerts wait for next upload");
+                        continue;
+                    }
+                };
+
+                if !connection
+                    .deliver_pending_events(&client, &storage, dispatcher_id, DeliveryScope::Critical)
+                    .await
+                {
+                    warn!("Critical alert delivery failed, will reconnect");
+                    connection.reset(&client).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeliveryScope, PrimeConnection, run_alert_sender};
+    use crate::state::PrimeEvent;
+    use crate::storage::memory::MemoryStorage;
+    use crate::storage::{PrimeEventStorage, StorageMaintenance};
+    use ersha_core::*;
+    use ersha_rpc::{Client, RpcTcp, WireError, WireErrorCode, WireMessage};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::{Mutex, Notify};
+    use tokio_util::sync::CancellationToken;
+    use ulid::Ulid;
+
+    /// Minimal ersha-prime stand-in that records the alerts it receives,
+    /// accepts command outcomes and rejects disconnection notices.
+    #[derive(Clone, Default)]
+    struct PrimeStandIn {
+        alerts: Arc<Mutex<Vec<AlertRequest>>>,
+        connections: Arc<AtomicUsize>,
+    }
+
+    impl PrimeStandIn {
+        async fn start(&self) -> SocketAddr {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let this = self.clone();
+
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    this.connections.fetch_add(1, Ordering::SeqCst);
+                    let alerts = this.alerts.clone();
+
+                    tokio::spawn(async move {
+                        let mut rpc = RpcTcp::new(stream, 16);
+                        while let Some(env) = rpc.recv().await {
+                            let reply = match env.payload {
+                                WireMessage::AlertRequest(alert) => {
+                                    let response = AlertResponse {
+                                        alert_id: alert.id,
+                                        acknowledged: true,
+                                    };
+                                    alerts.lock().await.push(alert);
+                                    WireMessage::AlertResponse(response)
+                                }
+                                WireMessage::CommandOutcomeRequest(outcome) => {
+                                    WireMessage::CommandOutcomeResponse(CommandOutcomeResponse {
+                                        command_id: outcome.command_id,
+                                    })
+                                }
+                                WireMessage::DeviceDisconnectionRequest(_) => {
+                                    WireMessage::Error(WireError {
+                                        code: WireErrorCode::BadRequest,
+                                        message: "unknown device".to_string(),
+                                    })
+                                }
+                                _ => continue,
+                            };
+                            let _ = rpc.reply(env.msg_id, reply).await;
+                        }
+                    });
+                }
+            });
+
+            addr
+        }
+
+        async fn wait_for_alerts(&self, count: usize) -> Vec<AlertRequest> {
+            for _ in 0..100 {
+                let alerts = self.alerts.lock().await;
+                if alerts.len() >= count {
+                    return alerts.clone();
+                }
+                drop(alerts);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            self.alerts.lock().await.clone()
+        }
+    }
+
+    fn connection_to(addr: SocketAddr) -> PrimeConnection {
+        PrimeConnection::new(move || async move {
+            let stream = TcpStream::connect(addr).await?;
+            Ok(Client::new(stream))
+        })
+    }
+
+    fn dummy_alert(severity: AlertSeverity) -> AlertRequest {
+        AlertRequest {
+            id: AlertId(Ulid::new()),
+            dispatcher_id: DispatcherId(Ulid::new()),
+            device_id: Some(DeviceId(Ulid::new())),
+            severity,
+            alert_type: AlertType::CriticalBattery,
+            message: "battery low".into(),
+            timestamp: jiff::Timestamp::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn critical_alert_sent_on_demand() {
+        let prime = PrimeStandIn::default();
+        let addr = prime.start().await;
+
+        let storage = MemoryStorage::default();
+        let urgent = Arc::new(Notify::new());
+        let cancel = CancellationToken::new();
+
+        tokio::spawn(run_alert_sender(
+            storage.clone(),
+            connection_to(addr),
+            DispatcherId(Ulid::new()),
+            urgent.clone(),
+            cancel.clone(),
+        ));
+
+        // nothing is connected until there is something urgent to send
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(prime.connections.load(Ordering::SeqCst), 0);
+
+        let critical = dummy_alert(AlertSeverity::Critical);
+        PrimeEventStorage::enqueue(&storage, PrimeEvent::Alert(critical.clone()))
+            .await
+            .unwrap();
+        urgent.notify_one();
+
+        let received = prime.wait_for_alerts(1).await;
+        assert_eq!(received, vec![critical]);
+        assert_eq!(prime.connections.load(Ordering::SeqCst), 1);
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn urgent_path_leaves_other_events_queued() {
+        let prime = PrimeStandIn::default();
+        let addr = prime.start().await;
+
+        let storage = MemoryStorage::default();
+        let connection = connection_to(addr);
+
+        let warning = dummy_alert(AlertSeverity::Warning);
+        let critical = dummy_alert(AlertSeverity::Critical);
+        PrimeEventStorage::enqueue(&storage, PrimeEvent::Alert(warning.clone()))
+            .await
+            .unwrap();
+        PrimeEventStorage::enqueue(&storage, PrimeEvent::Alert(critical.clone()))
+            .await
+            .unwrap();
+
+        let client = connection.client().await.unwrap();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        assert!(
+            connection
+                .deliver_pending_events(&client, &storage, dispatcher_id, DeliveryScope::Critical)
+                .await
+        );
+
+        assert_eq!(prime.wait_for_alerts(1).await, vec![critical.clone()]);
+        let pending = PrimeEventStorage::fetch_pending(&storage).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].event, PrimeEvent::Alert(warning.clone()));
+
+        // the regular pass picks up the rest
+        assert!(
+            connection
+                .deliver_pending_events(&client, &storage, dispatcher_id, DeliveryScope::All)
+                .await
+        );
+        assert_eq!(prime.wait_for_alerts(2).await, vec![critical, warning]);
+    }
+
+    #[tokio::test]
+    async fn rejected_event_is_given_up_without_blocking_the_rest() {
+        let prime = PrimeStandIn::default();
+        let addr = prime.start().await;
+
+        let storage = MemoryStorage::default();
+        let connection = connection_to(addr);
+
+        PrimeEventStorage::enqueue(
+            &storage,
+            PrimeEvent::DeviceDisconnection {
+                device_id: DeviceId(Ulid::new()),
+                reason: DisconnectionReason::Timeout,
+                timestamp: jiff::Timestamp::now(),
+            },
+        )
+        .await
+        .unwrap();
+        PrimeEventStorage::enqueue(
+            &storage,
+            PrimeEvent::CommandOutcome {
+                command_id: CommandId(Ulid::new()),
+                device_id: DeviceId(Ulid::new()),
+                result: CommandResult::Failed,
+                timestamp: jiff::Timestamp::now(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let client = connection.client().await.unwrap();
+        assert!(
+            connection
+                .deliver_pending_events(
+                    &client,
+                    &storage,
+                    DispatcherId(Ulid::new()),
+                    DeliveryScope::All
+                )
+                .await
+        );
+
+        let pending = PrimeEventStorage::fetch_pending(&storage).await.unwrap();
+        assert!(pending.is_empty());
+
+        let stats = storage.get_stats().await.unwrap();
+        assert_eq!(stats.prime_events_dead, 1);
+        assert_eq!(stats.prime_events_delivered, 1);
+    }
+
+    #[tokio::test]
+    async fn alerts_are_delivered_in_their_own_pass() {
+        let prime = PrimeStandIn::default();
+        let addr = prime.start().await;
+
+        let storage = MemoryStorage::default();
+        let connection = connection_to(addr);
+
+        let disconnection = PrimeEvent::DeviceDisconnection {
+            device_id: DeviceId(Ulid::new()),
+            reason: DisconnectionReason::Timeout,
+            timestamp: jiff::Timestamp::now(),
+        };
+        let warning = dummy_alert(AlertSeverity::Warning);
+        PrimeEventStorage::enqueue(&storage, disconnection.clone())
+            .await
+            .unwrap();
+        PrimeEventStorage::enqueue(&storage, PrimeEvent::Alert(warning.clone()))
+            .await
+            .unwrap();
+
+        let client = connection.client().await.unwrap();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        assert!(
+            connection
+                .deliver_pending_events(&client, &storage, dispatcher_id, DeliveryScope::Alerts)
+                .await
+        );
+
+        assert_eq!(prime.wait_for_alerts(1).await, vec![warning]);
+        let pending = PrimeEventStorage::fetch_pending(&storage).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].event, disconnection);
+    }
+
+    #[tokio::test]
+    async fn unreachable_prime_keeps_alert_queued() {
+        // bind and drop a listener so the port is known to be closed
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let storage = MemoryStorage::default();
+        let urgent = Arc::new(Notify::new());
+        let cancel = CancellationToken::new();
+
+        tokio::spawn(run_alert_sender(
+            storage.clone(),
+            connection_to(addr),
+            DispatcherId(Ulid::new()),
+            urgent.clone(),
+            cancel.clone(),
+        ));
+
+        PrimeEventStorage::enqueue(
+            &storage,
+            PrimeEvent::Alert(dummy_alert(AlertSeverity::Critical)),
+        )
+        .await
+        .unwrap();
+        urgent.notify_one();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let pending = PrimeEventStorage::fetch_pending(&storage).await.unwrap();
+        assert_eq!(pending.len(), 1);
+
+        cancel.cancel();
+    }
+}
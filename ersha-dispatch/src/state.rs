@@ -1,28 +1,105 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 
-use ersha_core::{AlertRequest, DeviceId, DisconnectionReason};
+use ersha_core::{
+    ActuatorCommand, AlertRequest, AlertSeverity, CommandId, CommandResult, DeviceCredential,
+    DeviceId, DeviceKey, DisconnectionReason, FirmwareStatus, SensorId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::calibration::Calibrations;
+use crate::firmware::FirmwareCache;
 
 /// Events to be sent to ersha-prime.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PrimeEvent {
     DeviceDisconnection {
         device_id: DeviceId,
         reason: DisconnectionReason,
+        timestamp: jiff::Timestamp,
     },
     Alert(AlertRequest),
+    /// How an actuator command ended on a device.
+    CommandOutcome {
+        command_id: CommandId,
+        device_id: DeviceId,
+        result: CommandResult,
+        timestamp: jiff::Timestamp,
+    },
+    /// Firmware a device reported, with the progress of any update.
+    Firmware {
+        device_id: DeviceId,
+        firmware: FirmwareStatus,
+        timestamp: jiff::Timestamp,
+    },
+}
+
+impl PrimeEvent {
+    /// Delivery priority of this event, lower values are delivered first.
+    ///
+    /// Critical alerts go out before any other alert, and all alerts go out
+    /// before device disconnection notices, command outcomes and firmware
+    /// reports.
+    pub fn priority(&self) -> u8 {
+        match self {
+            PrimeEvent::Alert(alert) if alert.severity == AlertSeverity::Critical => 0,
+            PrimeEvent::Alert(_) => 1,
+            PrimeEvent::DeviceDisconnection { .. }
+            | PrimeEvent::CommandOutcome { .. }
+            | PrimeEvent::Firmware { .. } => 2,
+        }
+    }
+
+    /// Whether this event should reach ersha-prime without waiting for the
+    /// next upload interval.
+    pub fn is_critical(&self) -> bool {
+        self.priority() == 0
+    }
+
+    /// Whether this event is an alert, delivered in a pass of its own.
+    pub fn is_alert(&self) -> bool {
+        matches!(self, PrimeEvent::Alert(_))
+    }
+}
+
+/// Change to a sensor's configuration, held until it is sent to its device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SensorDownlink {
+    pub sensor_id: SensorId,
+    /// New sampling period in milliseconds.
+    pub sampling_rate_ms: Option<u32>,
+    /// Offset the device adds to every reading, in the units it reports.
+    pub offset: Option<i16>,
 }
 
-/// Shared state for tracking devices and pending events.
+impl SensorDownlink {
+    /// Fold a later change for the same sensor into this one.
+    fn merge(&mut self, newer: &SensorDownlink) {
+        self.sampling_rate_ms = newer.sampling_rate_ms.or(self.sampling_rate_ms);
+        self.offset = newer.offset.or(self.offset);
+    }
+}
+
+/// Shared state for tracking connected devices.
 pub struct DispatcherState {
     inner: Arc<Mutex<Inner>>,
+    /// Woken whenever the queued downlinks change, so they can be stored.
+    downlinks_changed: Arc<Notify>,
+    calibrations: Calibrations,
+    firmware: FirmwareCache,
 }
 
 struct Inner {
     connected_devices: HashSet<DeviceId>,
-    pending_events: Vec<PrimeEvent>,
+    device_keys: HashMap<DeviceId, DeviceKey>,
+    downlinks: HashMap<DeviceId, Vec<SensorDownlink>>,
+    /// Actuator commands from ersha-prime not yet sent to their device.
+    commands: HashMap<CommandId, ActuatorCommand>,
+    /// Commands sent to their device, kept until ersha-prime stops listing
+    /// them so they are not sent again.
+    sent_commands: HashSet<CommandId>,
     startup_time: Instant,
 }
 
@@ -31,9 +108,15 @@ impl DispatcherState {
         Self {
             inner: Arc::new(Mutex::new(Inner {
                 connected_devices: HashSet::new(),
-                pending_events: Vec::new(),
+                device_keys: HashMap::new(),
+                downlinks: HashMap::new(),
+                commands: HashMap::new(),
+                sent_commands: HashSet::new(),
                 startup_time: Instant::now(),
             })),
+            downlinks_changed: Arc::new(Notify::new()),
+            calibrations: Calibrations::new(),
+            firmware: FirmwareCache::new(),
         }
     }
 
@@ -43,31 +126,168 @@ impl DispatcherState {
         inner.connected_devices.insert(device_id);
     }
 
-    /// Record that a device has disconnected and queue a disconnection event.
-    pub async fn device_disconnected(&self, device_id: DeviceId, reason: DisconnectionReason) {
+    /// Record that a device has disconnected.
+    pub async fn device_disconnected(&self, device_id: DeviceId) {
         let mut inner = self.inner.lock().await;
         inner.connected_devices.remove(&device_id);
-        inner
-            .pending_events
-            .push(PrimeEvent::DeviceDisconnection { device_id, reason });
     }
 
-    /// Queue an alert to be sent to prime.
-    pub async fn queue_alert(&self, alert: AlertRequest) {
+    /// Get the number of currently connected devices.
+    pub async fn connected_count(&self) -> u32 {
+        let inner = self.inner.lock().await;
+        inner.connected_devices.len() as u32
+    }
+
+    /// Replace the keys of devices allowed to connect.
+    pub async fn set_device_keys(&self, credentials: impl IntoIterator<Item = DeviceCredential>) {
         let mut inner = self.inner.lock().await;
-        inner.pending_events.push(PrimeEvent::Alert(alert));
+        inner.device_keys = credentials
+            .into_iter()
+            .map(|c| (c.device_id, c.key))
+            .collect();
+    }
+
+    /// Get the pre-shared key of a device, if it may connect.
+    pub async fn device_key(&self, device_id: DeviceId) -> Option<DeviceKey> {
+        let inner = self.inner.lock().await;
+        inner.device_keys.get(&device_id).cloned()
     }
 
-    /// Take all pending events, leaving the queue empty.
-    pub async fn take_pending_events(&self) -> Vec<PrimeEvent> {
+    /// Queue a configuration change for a device's next connection, merging
+    /// it with any change still pending for the same sensor. Returns the
+    /// number of sensors with pending changes on that device.
+    pub async fn queue_downlink(&self, device_id: DeviceId, downlink: SensorDownlink) -> usize {
         let mut inner = self.inner.lock().await;
-        std::mem::take(&mut inner.pending_events)
+        let pending = inner.downlinks.entry(device_id).or_default();
+        match pending
+            .iter_mut()
+            .find(|d| d.sensor_id == downlink.sensor_id)
+        {
+            Some(queued) => queued.merge(&downlink),
+            None => pending.push(downlink),
+        }
+        self.downlinks_changed.notify_one();
+        pending.len()
     }
 
-    /// Get the number of currently connected devices.
-    pub async fn connected_count(&self) -> u32 {
+    /// Take all changes pending for a device.
+    pub async fn take_downlinks(&self, device_id: DeviceId) -> Vec<SensorDownlink> {
+        let mut inner = self.inner.lock().await;
+        let taken = inner.downlinks.remove(&device_id).unwrap_or_default();
+        if !taken.is_empty() {
+            self.downlinks_changed.notify_one();
+        }
+        taken
+    }
+
+    /// Put back changes that could not be delivered. Anything queued since
+    /// they were taken takes precedence.
+    pub async fn requeue_downlinks(&self, device_id: DeviceId, unsent: Vec<SensorDownlink>) {
+        let mut inner = self.inner.lock().await;
+        let pending = inner.downlinks.entry(device_id).or_default();
+        for mut downlink in unsent {
+            match pending
+                .iter_mut()
+                .find(|d| d.sensor_id == downlink.sensor_id)
+            {
+                Some(queued) => {
+                    downlink.merge(queued);
+                    *queued = downlink;
+                }
+                None => pending.push(downlink),
+            }
+        }
+        self.downlinks_changed.notify_one();
+    }
+
+    /// All configuration changes still waiting for their device.
+    pub async fn pending_downlinks(&self) -> HashMap<DeviceId, Vec<SensorDownlink>> {
         let inner = self.inner.lock().await;
-        inner.connected_devices.len() as u32
+        inner.downlinks.clone()
+    }
+
+    /// Queue the changes that were pending when the dispatcher last stopped.
+    pub async fn restore_downlinks(&self, pending: HashMap<DeviceId, Vec<SensorDownlink>>) {
+        for (device_id, downlinks) in pending {
+            self.requeue_downlinks(device_id, downlinks).await;
+        }
+    }
+
+    /// Wait until the queued downlinks change.
+    pub async fn downlinks_changed(&self) {
+        self.downlinks_changed.notified().await;
+    }
+
+    /// Replace the queued actuator commands with those pending at
+    /// ersha-prime, leaving out commands already sent. Returns the number of
+    /// commands waiting to be sent.
+    pub async fn set_pending_commands(
+        &self,
+        pending: impl IntoIterator<Item = ActuatorCommand>,
+    ) -> usize {
+        let mut inner = self.inner.lock().await;
+        let Inner {
+            commands,
+            sent_commands,
+            ..
+        } = &mut *inner;
+        let pending: HashMap<CommandId, ActuatorCommand> =
+            pending.into_iter().map(|c| (c.id, c)).collect();
+
+        // commands ersha-prime no longer lists have a result or expired
+        sent_commands.retain(|id| pending.contains_key(id));
+        *commands = pending
+            .into_iter()
+            .filter(|(id, _)| !sent_commands.contains(id))
+            .collect();
+        commands.len()
+    }
+
+    /// Take the unexpired commands for a device, oldest first, and record
+    /// them as sent.
+    pub async fn take_commands(
+        &self,
+        device_id: DeviceId,
+        now: jiff::Timestamp,
+    ) -> Vec<ActuatorCommand> {
+        let mut inner = self.inner.lock().await;
+        let ids: Vec<CommandId> = inner
+            .commands
+            .values()
+            .filter(|c| c.device_id == device_id)
+            .map(|c| c.id)
+            .collect();
+
+        let mut taken = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(command) = inner.commands.remove(&id)
+                && command.expires_at > now
+            {
+                inner.sent_commands.insert(id);
+                taken.push(command);
+            }
+        }
+        taken.sort_by_key(|c| c.issued_at);
+        taken
+    }
+
+    /// Put back commands that could not be sent.
+    pub async fn requeue_commands(&self, unsent: Vec<ActuatorCommand>) {
+        let mut inner = self.inner.lock().await;
+        for command in unsent {
+            inner.sent_commands.remove(&command.id);
+            inner.commands.insert(command.id, command);
+        }
+    }
+
+    /// Calibration profiles applied to incoming readings.
+    pub fn calibrations(&self) -> Calibrations {
+        self.calibrations.clone()
+    }
+
+    /// Firmware images served to edge devices.
+    pub fn firmware(&self) -> FirmwareCache {
+        self.firmware.clone()
     }
 
     /// Get the dispatcher uptime in seconds.
@@ -87,6 +307,116 @@ impl Clone for DispatcherState {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
+            downlinks_changed: Arc::clone(&self.downlinks_changed),
+            calibrations: self.calibrations.clone(),
+            firmware: self.firmware.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DispatcherState, SensorDownlink};
+    use ersha_core::{ActuatorAction, ActuatorCommand, ActuatorId, CommandId, DeviceId, SensorId};
+    use ulid::Ulid;
+
+    fn downlink(sensor_id: SensorId, rate: Option<u32>, offset: Option<i16>) -> SensorDownlink {
+        SensorDownlink {
+            sensor_id,
+            sampling_rate_ms: rate,
+            offset,
         }
     }
+
+    #[tokio::test]
+    async fn downlinks_merge_per_sensor_until_taken() {
+        let state = DispatcherState::new();
+        let device = DeviceId(Ulid::new());
+        let (soil, rain) = (SensorId(Ulid::new()), SensorId(Ulid::new()));
+
+        state
+            .queue_downlink(device, downlink(soil, Some(60_000), None))
+            .await;
+        state
+            .queue_downlink(device, downlink(soil, Some(10_000), Some(2)))
+            .await;
+        let pending = state
+            .queue_downlink(device, downlink(rain, None, Some(-1)))
+            .await;
+        assert_eq!(pending, 2);
+
+        let taken = state.take_downlinks(device).await;
+        assert_eq!(
+            taken,
+            vec![
+                downlink(soil, Some(10_000), Some(2)),
+                downlink(rain, None, Some(-1)),
+            ]
+        );
+        assert!(state.take_downlinks(device).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn requeued_downlinks_yield_to_newer_changes() {
+        let state = DispatcherState::new();
+        let device = DeviceId(Ulid::new());
+        let soil = SensorId(Ulid::new());
+
+        state
+            .queue_downlink(device, downlink(soil, Some(60_000), Some(2)))
+            .await;
+        let unsent = state.take_downlinks(device).await;
+        state
+            .queue_downlink(device, downlink(soil, Some(5_000), None))
+            .await;
+        state.requeue_downlinks(device, unsent).await;
+
+        assert_eq!(
+            state.take_downlinks(device).await,
+            vec![downlink(soil, Some(5_000), Some(2))]
+        );
+    }
+
+    fn command(device_id: DeviceId, expires_at: jiff::Timestamp) -> ActuatorCommand {
+        ActuatorCommand {
+            id: CommandId(Ulid::new()),
+            device_id,
+            actuator_id: ActuatorId(Ulid::new()),
+            action: ActuatorAction::Open { duration_secs: 600 },
+            issued_at: expires_at - jiff::SignedDuration::from_secs(300),
+            expires_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn commands_are_sent_once_until_prime_drops_them() {
+        let state = DispatcherState::new();
+        let (valve, pump) = (DeviceId(Ulid::new()), DeviceId(Ulid::new()));
+        let now = jiff::Timestamp::now();
+        let later = now + jiff::SignedDuration::from_secs(60);
+
+        let open = command(valve, later);
+        let stale = command(valve, now);
+        let other = command(pump, later);
+        let pending = vec![open.clone(), stale.clone(), other.clone()];
+        assert_eq!(state.set_pending_commands(pending.clone()).await, 3);
+
+        // expired commands are dropped rather than sent
+        assert_eq!(state.take_commands(valve, now).await, vec![open.clone()]);
+        assert!(state.take_commands(valve, now).await.is_empty());
+
+        // still pending at prime, but already sent
+        assert_eq!(state.set_pending_commands(pending).await, 2);
+        assert!(state.take_commands(valve, now).await.is_empty());
+
+        let unsent = state.take_commands(pump, now).await;
+        assert_eq!(unsent, vec![other.clone()]);
+        state.requeue_commands(unsent).await;
+        assert_eq!(state.take_commands(pump, now).await, vec![other]);
+
+        // resolved at prime, so forgotten here
+        assert_eq!(state.set_pending_commands(Vec::new()).await, 0);
+        assert_eq!(state.set_pending_commands(vec![open.clone()]).await, 1);
+        assert_eq!(state.take_commands(valve, now).await, vec![open]);
+    }
 }
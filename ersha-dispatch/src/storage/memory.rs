@@ -1,20 +1,26 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use async_trait::async_trait;
-use ersha_core::{DeviceStatus, ReadingId, SensorReading, StatusId};
+use ersha_core::{DeviceId, DeviceStatus, ReadingId, SensorReading, StatusId};
 use thiserror::Error;
 use tokio::sync::RwLock;
+use ulid::Ulid;
 
+use crate::state::{PrimeEvent, SensorDownlink};
 use crate::storage::{
-    CleanupStats, DeviceStatusStorage, SensorReadingsStorage, StorageMaintenance, StorageStats,
+    CleanupStats, DeviceStatusStorage, DownlinkStorage, EventId, PrimeEventStorage, QueuedEvent,
+    SensorReadingsStorage, StorageMaintenance, StorageStats,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StorageState {
     Pending,
     Uploaded,
+    /// Given up on, only used for prime events.
+    Dead,
 }
 
 #[derive(Debug, Clone)]
@@ -31,10 +37,23 @@ pub struct StoredDeviceStatus {
     pub state: StorageState,
 }
 
+#[derive(Debug, Clone)]
+pub struct StoredPrimeEvent {
+    pub id: EventId,
+    /// Position in the queue, used to keep delivery order stable.
+    pub seq: u64,
+    pub event: PrimeEvent,
+    pub attempts: u32,
+    pub state: StorageState,
+}
+
 #[derive(Clone, Default)]
 pub struct MemoryStorage {
     sensor_readings: Arc<RwLock<HashMap<ReadingId, StoredSensorReading>>>,
     device_statuses: Arc<RwLock<HashMap<StatusId, StoredDeviceStatus>>>,
+    prime_events: Arc<RwLock<HashMap<EventId, StoredPrimeEvent>>>,
+    event_seq: Arc<AtomicU64>,
+    downlinks: Arc<RwLock<HashMap<DeviceId, Vec<SensorDownlink>>>>,
 }
 
 #[derive(Debug, Error)]
@@ -48,14 +67,11 @@ impl SensorReadingsStorage for MemoryStorage {
         let mut map = self.sensor_readings.write().await;
 
         let id = reading.id;
-        map.insert(
+        map.entry(id).or_insert(StoredSensorReading {
             id,
-            StoredSensorReading {
-                id,
-                reading,
-                state: StorageState::Pending,
-            },
-        );
+            reading,
+            state: StorageState::Pending,
+        });
 
         Ok(())
     }
@@ -65,14 +81,11 @@ impl SensorReadingsStorage for MemoryStorage {
 
         for reading in readings {
             let id = reading.id;
-            map.insert(
+            map.entry(id).or_insert(StoredSensorReading {
                 id,
-                StoredSensorReading {
-                    id,
-                    reading,
-                    state: StorageState::Pending,
-                },
-            );
+                reading,
+                state: StorageState::Pending,
+            });
         }
 
         Ok(())
@@ -162,6 +175,100 @@ impl DeviceStatusStorage for MemoryStorage {
     }
 }
 
+#[async_trait]
+impl PrimeEventStorage for MemoryStorage {
+    type Error = MemoryStorageError;
+
+    async fn enqueue(&self, event: PrimeEvent) -> Result<EventId, Self::Error> {
+        let mut map = self.prime_events.write().await;
+
+        let id = EventId(Ulid::new());
+        let seq = self.event_seq.fetch_add(1, Ordering::Relaxed);
+        map.insert(
+            id,
+            StoredPrimeEvent {
+                id,
+                seq,
+                event,
+                attempts: 0,
+                state: StorageState::Pending,
+            },
+        );
+
+        Ok(id)
+    }
+
+    async fn fetch_pending(&self) -> Result<Vec<QueuedEvent>, Self::Error> {
+        let map = self.prime_events.read().await;
+
+        let mut pending: Vec<&StoredPrimeEvent> = map
+            .values()
+            .filter(|e| e.state == StorageState::Pending)
+            .collect();
+        pending.sort_by_key(|e| (e.event.priority(), e.seq));
+
+        Ok(pending
+            .into_iter()
+            .map(|e| QueuedEvent {
+                id: e.id,
+                event: e.event.clone(),
+                attempts: e.attempts,
+            })
+            .collect())
+    }
+
+    async fn mark_delivered(&self, ids: &[EventId]) -> Result<(), Self::Error> {
+        let mut map = self.prime_events.write().await;
+
+        for id in ids {
+            if let Some(entry) = map.get_mut(id) {
+                entry.state = StorageState::Uploaded;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn record_failure(&self, id: EventId) -> Result<(), Self::Error> {
+        let mut map = self.prime_events.write().await;
+
+        if let Some(entry) = map.get_mut(&id) {
+            entry.attempts += 1;
+        }
+
+        Ok(())
+    }
+
+    async fn mark_dead(&self, id: EventId) -> Result<(), Self::Error> {
+        let mut map = self.prime_events.write().await;
+
+        if let Some(entry) = map.get_mut(&id) {
+            entry.state = StorageState::Dead;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DownlinkStorage for MemoryStorage {
+    type Error = MemoryStorageError;
+
+    async fn replace_downlinks(
+        &self,
+        pending: &HashMap<DeviceId, Vec<SensorDownlink>>,
+    ) -> Result<(), Self::Error> {
+        let mut downlinks = self.downlinks.write().await;
+        *downlinks = pending.clone();
+        Ok(())
+    }
+
+    async fn fetch_downlinks(&self) -> Result<HashMap<DeviceId, Vec<SensorDownlink>>, Self::Error> {
+        let downlinks = self.downlinks.read().await;
+        Ok(downlinks.clone())
+    }
+}
+
 #[async_trait]
 impl StorageMaintenance for MemoryStorage {
     type Error = MemoryStorageError;
@@ -169,6 +276,7 @@ impl StorageMaintenance for MemoryStorage {
     async fn get_stats(&self) -> Result<StorageStats, Self::Error> {
         let sensor_map = self.sensor_readings.read().await;
         let device_map = self.device_statuses.read().await;
+        let event_map = self.prime_events.read().await;
 
         let sensor_readings_total = sensor_map.len();
         let sensor_readings_pending = sensor_map
@@ -184,6 +292,16 @@ impl StorageMaintenance for MemoryStorage {
             .count();
         let device_statuses_uploaded = device_statuses_total - device_statuses_pending;
 
+        let prime_events_pending = event_map
+            .values()
+            .filter(|e| e.state == StorageState::Pending)
+            .count();
+        let prime_events_dead = event_map
+            .values()
+            .filter(|e| e.state == StorageState::Dead)
+            .count();
+        let prime_events_delivered = event_map.len() - prime_events_pending - prime_events_dead;
+
         Ok(StorageStats {
             sensor_readings_pending,
             sensor_readings_uploaded,
@@ -191,12 +309,16 @@ impl StorageMaintenance for MemoryStorage {
             device_statuses_pending,
             device_statuses_uploaded,
             device_statuses_total,
+            prime_events_pending,
+            prime_events_delivered,
+            prime_events_dead,
         })
     }
 
     async fn cleanup_uploaded(&self, _older_than: Duration) -> Result<CleanupStats, Self::Error> {
         let mut sensor_map = self.sensor_readings.write().await;
         let mut device_map = self.device_statuses.write().await;
+        let mut event_map = self.prime_events.write().await;
 
         // Memory storage: just remove uploaded entries (ignore timestamp)
         let sensor_keys_to_remove: Vec<_> = sensor_map
@@ -221,9 +343,14 @@ impl StorageMaintenance for MemoryStorage {
             device_map.remove(&key);
         }
 
+        let event_count_before = event_map.len();
+        event_map.retain(|_, v| v.state != StorageState::Uploaded);
+        let prime_events_deleted = event_count_before - event_map.len();
+
         Ok(CleanupStats {
             sensor_readings_deleted,
             device_statuses_deleted,
+            prime_events_deleted,
         })
     }
 }
@@ -231,7 +358,10 @@ impl StorageMaintenance for MemoryStorage {
 #[cfg(test)]
 mod tests {
     use super::{MemoryStorage, MemoryStorageError};
-    use crate::storage::{DeviceStatusStorage, SensorReadingsStorage, StorageMaintenance};
+    use crate::state::PrimeEvent;
+    use crate::storage::{
+        DeviceStatusStorage, PrimeEventStorage, SensorReadingsStorage, StorageMaintenance,
+    };
     use ersha_core::*;
     use std::time::Duration;
     use ulid::Ulid;
@@ -248,6 +378,7 @@ mod tests {
             confidence: Percentage(95),
             timestamp: jiff::Timestamp::now(),
             sensor_id: SensorId(Ulid::new()),
+            raw_value: None,
         }
     }
 
@@ -262,6 +393,7 @@ mod tests {
             errors: Box::new([]),
             timestamp: jiff::Timestamp::now(),
             sensor_statuses: Box::new([]),
+            firmware: None,
         }
     }
 
@@ -445,4 +577,73 @@ mod tests {
 
         Ok(())
     }
+
+    fn dummy_alert(severity: AlertSeverity) -> PrimeEvent {
+        PrimeEvent::Alert(AlertRequest {
+            id: AlertId(Ulid::new()),
+            dispatcher_id: DispatcherId(Ulid::new()),
+            device_id: Some(DeviceId(Ulid::new())),
+            severity,
+            alert_type: AlertType::CriticalBattery,
+            message: "battery low".into(),
+            timestamp: jiff::Timestamp::now(),
+        })
+    }
+
+    fn dummy_disconnection() -> PrimeEvent {
+        PrimeEvent::DeviceDisconnection {
+            device_id: DeviceId(Ulid::new()),
+            reason: DisconnectionReason::Timeout,
+            timestamp: jiff::Timestamp::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_prime_event_priority_order() -> Result<(), MemoryStorageError> {
+        let storage: MemoryStorage = MemoryStorage::default();
+
+        let disconnection = dummy_disconnection();
+        let warning = dummy_alert(AlertSeverity::Warning);
+        let critical = dummy_alert(AlertSeverity::Critical);
+        let second_disconnection = dummy_disconnection();
+
+        PrimeEventStorage::enqueue(&storage, disconnection.clone()).await?;
+        PrimeEventStorage::enqueue(&storage, warning.clone()).await?;
+        PrimeEventStorage::enqueue(&storage, critical.clone()).await?;
+        PrimeEventStorage::enqueue(&storage, second_disconnection.clone()).await?;
+
+        let pending = PrimeEventStorage::fetch_pending(&storage).await?;
+        let events: Vec<PrimeEvent> = pending.into_iter().map(|e| e.event).collect();
+        assert_eq!(
+            events,
+            vec![critical, warning, disconnection, second_disconnection]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_prime_event_retry_and_cleanup() -> Result<(), MemoryStorageError> {
+        let storage: MemoryStorage = MemoryStorage::default();
+
+        let id = PrimeEventStorage::enqueue(&storage, dummy_disconnection()).await?;
+
+        // a failed delivery keeps the event queued
+        PrimeEventStorage::record_failure(&storage, id).await?;
+
+        let pending = PrimeEventStorage::fetch_pending(&storage).await?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].attempts, 1);
+
+        PrimeEventStorage::mark_delivered(&storage, std::slice::from_ref(&id)).await?;
+
+        let stats = storage.get_stats().await?;
+        assert_eq!(stats.prime_events_pending, 0);
+        assert_eq!(stats.prime_events_delivered, 1);
+
+        let cleanup = storage.cleanup_uploaded(Duration::ZERO).await?;
+        assert_eq!(cleanup.prime_events_deleted, 1);
+
+        Ok(())
+    }
 }
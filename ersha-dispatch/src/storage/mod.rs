@@ -3,8 +3,13 @@ pub mod memory;
 pub mod sqlite;
 
 use async_trait::async_trait;
-use ersha_core::{DeviceStatus, ReadingId, SensorReading, StatusId};
+use ersha_core::{DeviceId, DeviceStatus, ReadingId, SensorReading, StatusId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::Duration;
+use ulid::Ulid;
+
+use crate::state::{PrimeEvent, SensorDownlink};
 
 /// Storage abstraction for sensor readings.
 #[async_trait]
@@ -13,9 +18,13 @@ pub trait SensorReadingsStorage: Clone + Send + Sync + 'static {
     type Error: std::error::Error + Send + Sync + 'static;
 
     /// Store a sensor reading event as pending.
+    ///
+    /// A reading whose ID is already stored is left as it is, as that is the
+    /// same reading sent again.
     async fn store(&self, reading: SensorReading) -> Result<(), Self::Error>;
 
-    /// Store multiple sensor readings in a batch (more efficient).
+    /// Store multiple sensor readings in a batch (more efficient), skipping
+    /// IDs that are already stored like [`SensorReadingsStorage::store`].
     async fn store_batch(&self, readings: Vec<SensorReading>) -> Result<(), Self::Error>;
 
     /// Fetch all pending sensor readings.
@@ -44,6 +53,64 @@ pub trait DeviceStatusStorage: Clone + Send + Sync + 'static {
     async fn mark_uploaded(&self, ids: &[StatusId]) -> Result<(), Self::Error>;
 }
 
+/// Unique identifier for a queued prime event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EventId(pub Ulid);
+
+/// A prime event waiting in the outbound queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedEvent {
+    /// Queue identity of this event.
+    pub id: EventId,
+    /// The event to deliver.
+    pub event: PrimeEvent,
+    /// Number of failed delivery attempts so far.
+    pub attempts: u32,
+}
+
+/// Storage abstraction for the outbound queue of events sent to ersha-prime.
+#[async_trait]
+pub trait PrimeEventStorage: Clone + Send + Sync + 'static {
+    /// Error type specific to this storage implementation
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Queue an event for delivery.
+    async fn enqueue(&self, event: PrimeEvent) -> Result<EventId, Self::Error>;
+
+    /// Fetch all pending events in delivery order.
+    ///
+    /// Events are ordered by [`PrimeEvent::priority`] first and by the order
+    /// in which they were queued second.
+    async fn fetch_pending(&self) -> Result<Vec<QueuedEvent>, Self::Error>;
+
+    /// Mark events as successfully delivered.
+    async fn mark_delivered(&self, ids: &[EventId]) -> Result<(), Self::Error>;
+
+    /// Record a failed delivery attempt, keeping the event pending.
+    async fn record_failure(&self, id: EventId) -> Result<(), Self::Error>;
+
+    /// Give up on delivering an event. It is kept, but never sent again and
+    /// never removed by cleanup.
+    async fn mark_dead(&self, id: EventId) -> Result<(), Self::Error>;
+}
+
+/// Storage abstraction for sensor configuration changes still waiting for
+/// their device, so they survive a restart.
+#[async_trait]
+pub trait DownlinkStorage: Clone + Send + Sync + 'static {
+    /// Error type specific to this storage implementation
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Replace the stored changes with those pending now.
+    async fn replace_downlinks(
+        &self,
+        pending: &HashMap<DeviceId, Vec<SensorDownlink>>,
+    ) -> Result<(), Self::Error>;
+
+    /// Fetch the changes stored last, in the order they were queued.
+    async fn fetch_downlinks(&self) -> Result<HashMap<DeviceId, Vec<SensorDownlink>>, Self::Error>;
+}
+
 /// Storage abstraction for maintenance operations.
 #[async_trait]
 pub trait StorageMaintenance: Clone + Send + Sync + 'static {
@@ -53,7 +120,7 @@ pub trait StorageMaintenance: Clone + Send + Sync + 'static {
     /// Get statistics about stored data.
     async fn get_stats(&self) -> Result<StorageStats, Self::Error>;
 
-    /// Clean up uploaded data older than the specified duration.
+    /// Clean up uploaded data and delivered events older than the specified duration.
     async fn cleanup_uploaded(&self, older_than: Duration) -> Result<CleanupStats, Self::Error>;
 }
 
@@ -72,6 +139,12 @@ pub struct StorageStats {
     pub device_statuses_uploaded: usize,
     /// Total number of device statuses.
     pub device_statuses_total: usize,
+    /// Number of prime events waiting for delivery.
+    pub prime_events_pending: usize,
+    /// Number of delivered prime events.
+    pub prime_events_delivered: usize,
+    /// Number of prime events given up on.
+    pub prime_events_dead: usize,
 }
 
 /// Statistics about cleanup operation.
@@ -81,4 +154,6 @@ pub struct CleanupStats {
     pub sensor_readings_deleted: usize,
     /// Number of device statuses deleted.
     pub device_statuses_deleted: usize,
+    /// Number of delivered prime events deleted.
+    pub prime_events_deleted: usize,
 }
@@ -3,10 +3,15 @@ use sqlx::{Error as SqlxError, Row, SqlitePool};
 use std::path::Path;
 use std::time::Duration;
 
+use crate::state::{PrimeEvent, SensorDownlink};
 use crate::storage::{
-    CleanupStats, DeviceStatusStorage, SensorReadingsStorage, StorageMaintenance, StorageStats,
+    CleanupStats, DeviceStatusStorage, DownlinkStorage, EventId, PrimeEventStorage, QueuedEvent,
+    SensorReadingsStorage, StorageMaintenance, StorageStats,
 };
-use ersha_core::{DeviceStatus, ReadingId, SensorReading, StatusId};
+use ersha_core::{DeviceId, DeviceStatus, ReadingId, SensorReading, StatusId};
+use std::collections::HashMap;
+use std::str::FromStr;
+use ulid::Ulid;
 
 #[derive(Clone)]
 pub struct SqliteStorage {
@@ -23,6 +28,10 @@ pub enum SqliteStorageError {
     SerdeJson(#[from] serde_json::Error),
     #[error("migration error: {0}")]
     Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("invalid event id: {0}")]
+    InvalidEventId(String),
+    #[error("invalid device id: {0}")]
+    InvalidDeviceId(String),
 }
 
 impl SqliteStorage {
@@ -68,6 +77,14 @@ impl SqliteStorage {
     fn deserialize_status(json: &str) -> Result<DeviceStatus, SqliteStorageError> {
         Ok(serde_json::from_str(json)?)
     }
+
+    fn serialize_event(event: &PrimeEvent) -> Result<String, SqliteStorageError> {
+        Ok(serde_json::to_string(event)?)
+    }
+
+    fn deserialize_event(json: &str) -> Result<PrimeEvent, SqliteStorageError> {
+        Ok(serde_json::from_str(json)?)
+    }
 }
 
 #[async_trait]
@@ -79,7 +96,7 @@ impl SensorReadingsStorage for SqliteStorage {
         let id_str = reading.id.0.to_string();
 
         sqlx::query(
-            "INSERT INTO sensor_readings (id, reading_json, state) VALUES (?, ?, 'pending')",
+            "INSERT OR IGNORE INTO sensor_readings (id, reading_json, state) VALUES (?, ?, 'pending')",
         )
         .bind(&id_str)
         .bind(&json)
@@ -101,7 +118,7 @@ impl SensorReadingsStorage for SqliteStorage {
             let id_str = reading.id.0.to_string();
 
             sqlx::query(
-                "INSERT INTO sensor_readings (id, reading_json, state) VALUES (?, ?, 'pending')",
+                "INSERT OR IGNORE INTO sensor_readings (id, reading_json, state) VALUES (?, ?, 'pending')",
             )
             .bind(&id_str)
             .bind(&json)
@@ -236,6 +253,152 @@ impl DeviceStatusStorage for SqliteStorage {
     }
 }
 
+#[async_trait]
+impl PrimeEventStorage for SqliteStorage {
+    type Error = SqliteStorageError;
+
+    async fn enqueue(&self, event: PrimeEvent) -> Result<EventId, Self::Error> {
+        let json = Self::serialize_event(&event)?;
+        let id = EventId(Ulid::new());
+
+        sqlx::query(
+            "INSERT INTO prime_events (id, event_json, priority, state) VALUES (?, ?, ?, 'pending')",
+        )
+        .bind(id.0.to_string())
+        .bind(&json)
+        .bind(event.priority() as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    async fn fetch_pending(&self) -> Result<Vec<QueuedEvent>, Self::Error> {
+        let rows = sqlx::query(
+            "SELECT id, event_json, attempts FROM prime_events WHERE state = 'pending' ORDER BY priority, seq",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            let id_str: String = row.try_get("id")?;
+            let json: String = row.try_get("event_json")?;
+            let attempts: i64 = row.try_get("attempts")?;
+
+            let id = Ulid::from_str(&id_str)
+                .map_err(|_| SqliteStorageError::InvalidEventId(id_str.clone()))?;
+
+            events.push(QueuedEvent {
+                id: EventId(id),
+                event: Self::deserialize_event(&json)?,
+                attempts: attempts as u32,
+            });
+        }
+
+        Ok(events)
+    }
+
+    async fn mark_delivered(&self, ids: &[EventId]) -> Result<(), Self::Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        for id in ids {
+            let id_str = id.0.to_string();
+
+            sqlx::query(
+                "UPDATE prime_events SET state = 'delivered', delivered_at = CURRENT_TIMESTAMP WHERE id = ?",
+            )
+                .bind(&id_str)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn record_failure(&self, id: EventId) -> Result<(), Self::Error> {
+        sqlx::query("UPDATE prime_events SET attempts = attempts + 1 WHERE id = ?")
+            .bind(id.0.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_dead(&self, id: EventId) -> Result<(), Self::Error> {
+        sqlx::query("UPDATE prime_events SET state = 'dead' WHERE id = ?")
+            .bind(id.0.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DownlinkStorage for SqliteStorage {
+    type Error = SqliteStorageError;
+
+    async fn replace_downlinks(
+        &self,
+        pending: &HashMap<DeviceId, Vec<SensorDownlink>>,
+    ) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM sensor_downlinks")
+            .execute(&mut *tx)
+            .await?;
+
+        for (device_id, downlinks) in pending {
+            for (position, downlink) in downlinks.iter().enumerate() {
+                sqlx::query(
+                    "INSERT INTO sensor_downlinks (device_id, sensor_id, downlink_json, position) VALUES (?, ?, ?, ?)",
+                )
+                .bind(device_id.0.to_string())
+                .bind(downlink.sensor_id.0.to_string())
+                .bind(serde_json::to_string(downlink)?)
+                .bind(position as i64)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn fetch_downlinks(&self) -> Result<HashMap<DeviceId, Vec<SensorDownlink>>, Self::Error> {
+        let rows = sqlx::query(
+            "SELECT device_id, downlink_json FROM sensor_downlinks ORDER BY device_id, position",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut downlinks: HashMap<DeviceId, Vec<SensorDownlink>> = HashMap::new();
+        for row in rows {
+            let id_str: String = row.try_get("device_id")?;
+            let json: String = row.try_get("downlink_json")?;
+
+            let device_id = Ulid::from_str(&id_str)
+                .map_err(|_| SqliteStorageError::InvalidDeviceId(id_str.clone()))?;
+
+            downlinks
+                .entry(DeviceId(device_id))
+                .or_default()
+                .push(serde_json::from_str(&json)?);
+        }
+
+        Ok(downlinks)
+    }
+}
+
 #[async_trait]
 impl StorageMaintenance for SqliteStorage {
     type Error = SqliteStorageError;
@@ -265,6 +428,18 @@ impl StorageMaintenance for SqliteStorage {
         .fetch_one(&self.pool)
         .await?;
 
+        let event_stats: (i64, i64, i64) = sqlx::query_as(
+            r#"
+            SELECT 
+                COALESCE(SUM(CASE WHEN state = 'pending' THEN 1 ELSE 0 END), 0) as pending,
+                COALESCE(SUM(CASE WHEN state = 'delivered' THEN 1 ELSE 0 END), 0) as delivered,
+                COALESCE(SUM(CASE WHEN state = 'dead' THEN 1 ELSE 0 END), 0) as dead
+             FROM prime_events
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
         Ok(StorageStats {
             sensor_readings_total: sensor_stats.0 as usize,
             sensor_readings_pending: sensor_stats.1 as usize,
@@ -272,6 +447,9 @@ impl StorageMaintenance for SqliteStorage {
             device_statuses_total: device_stats.0 as usize,
             device_statuses_pending: device_stats.1 as usize,
             device_statuses_uploaded: device_stats.2 as usize,
+            prime_events_pending: event_stats.0 as usize,
+            prime_events_delivered: event_stats.1 as usize,
+            prime_events_dead: event_stats.2 as usize,
         })
     }
 
@@ -291,11 +469,17 @@ impl StorageMaintenance for SqliteStorage {
                     .await?
                     .rows_affected();
 
+            let events_deleted = sqlx::query("DELETE FROM prime_events WHERE state = 'delivered'")
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+
             tx.commit().await?;
 
             return Ok(CleanupStats {
                 sensor_readings_deleted: sensor_deleted as usize,
                 device_statuses_deleted: device_deleted as usize,
+                prime_events_deleted: events_deleted as usize,
             });
         }
 
@@ -319,11 +503,20 @@ impl StorageMaintenance for SqliteStorage {
             .await?
             .rows_affected();
 
+        let events_deleted = sqlx::query(
+            "DELETE FROM prime_events WHERE state = 'delivered' AND delivered_at IS NOT NULL AND julianday('now') - julianday(delivered_at) >= ?",
+        )
+            .bind(cutoff_days)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
         tx.commit().await?;
 
         Ok(CleanupStats {
             sensor_readings_deleted: sensor_deleted as usize,
             device_statuses_deleted: device_deleted as usize,
+            prime_events_deleted: events_deleted as usize,
         })
     }
 }
@@ -331,8 +524,13 @@ impl StorageMaintenance for SqliteStorage {
 #[cfg(test)]
 mod tests {
     use super::{SqliteStorage, SqliteStorageError};
-    use crate::storage::{DeviceStatusStorage, SensorReadingsStorage, StorageMaintenance};
+    use crate::state::{PrimeEvent, SensorDownlink};
+    use crate::storage::{
+        DeviceStatusStorage, DownlinkStorage, PrimeEventStorage, SensorReadingsStorage,
+        StorageMaintenance,
+    };
     use ersha_core::*;
+    use std::collections::HashMap;
     use std::time::Duration;
     use ulid::Ulid;
 
@@ -348,6 +546,7 @@ mod tests {
             confidence: Percentage(95),
             timestamp: jiff::Timestamp::now(),
             sensor_id: SensorId(Ulid::new()),
+            raw_value: None,
         }
     }
 
@@ -362,6 +561,7 @@ mod tests {
             errors: Box::new([]),
             timestamp: jiff::Timestamp::now(),
             sensor_statuses: Box::new([]),
+            firmware: None,
         }
     }
 
@@ -385,6 +585,26 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn sqlite_resent_reading_is_stored_once() -> Result<(), SqliteStorageError> {
+        let storage = SqliteStorage::new_in_memory().await?;
+
+        let reading = dummy_reading();
+        let reading_id = reading.id;
+
+        SensorReadingsStorage::store(&storage, reading.clone()).await?;
+        SensorReadingsStorage::mark_uploaded(&storage, std::slice::from_ref(&reading_id)).await?;
+
+        // a resend of an uploaded reading does not queue it again
+        SensorReadingsStorage::store(&storage, reading.clone()).await?;
+        SensorReadingsStorage::store_batch(&storage, vec![reading]).await?;
+
+        let pending: Vec<SensorReading> = SensorReadingsStorage::fetch_pending(&storage).await?;
+        assert!(pending.is_empty());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn sqlite_device_status_lifecycle() -> Result<(), SqliteStorageError> {
         let storage = SqliteStorage::new_in_memory().await?;
@@ -682,4 +902,126 @@ mod tests {
 
         Ok(())
     }
+
+    fn dummy_alert(severity: AlertSeverity) -> PrimeEvent {
+        PrimeEvent::Alert(AlertRequest {
+            id: AlertId(Ulid::new()),
+            dispatcher_id: DispatcherId(Ulid::new()),
+            device_id: Some(DeviceId(Ulid::new())),
+            severity,
+            alert_type: AlertType::CriticalBattery,
+            message: "battery low".into(),
+            timestamp: jiff::Timestamp::now(),
+        })
+    }
+
+    fn dummy_disconnection() -> PrimeEvent {
+        PrimeEvent::DeviceDisconnection {
+            device_id: DeviceId(Ulid::new()),
+            reason: DisconnectionReason::Timeout,
+            timestamp: jiff::Timestamp::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_prime_event_priority_order() -> Result<(), SqliteStorageError> {
+        let storage = SqliteStorage::new_in_memory().await?;
+
+        let disconnection = dummy_disconnection();
+        let warning = dummy_alert(AlertSeverity::Warning);
+        let critical = dummy_alert(AlertSeverity::Critical);
+        let second_disconnection = dummy_disconnection();
+
+        PrimeEventStorage::enqueue(&storage, disconnection.clone()).await?;
+        PrimeEventStorage::enqueue(&storage, warning.clone()).await?;
+        PrimeEventStorage::enqueue(&storage, critical.clone()).await?;
+        PrimeEventStorage::enqueue(&storage, second_disconnection.clone()).await?;
+
+        let pending = PrimeEventStorage::fetch_pending(&storage).await?;
+        let events: Vec<PrimeEvent> = pending.into_iter().map(|e| e.event).collect();
+        assert_eq!(
+            events,
+            vec![critical, warning, disconnection, second_disconnection]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sqlite_prime_event_retry_and_cleanup() -> Result<(), SqliteStorageError> {
+        let storage = SqliteStorage::new_in_memory().await?;
+
+        let id = PrimeEventStorage::enqueue(&storage, dummy_disconnection()).await?;
+
+        // a failed delivery keeps the event queued
+        PrimeEventStorage::record_failure(&storage, id).await?;
+        PrimeEventStorage::record_failure(&storage, id).await?;
+
+        let pending = PrimeEventStorage::fetch_pending(&storage).await?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, id);
+        assert_eq!(pending[0].attempts, 2);
+
+        PrimeEventStorage::mark_delivered(&storage, std::slice::from_ref(&id)).await?;
+
+        let pending = PrimeEventStorage::fetch_pending(&storage).await?;
+        assert!(pending.is_empty());
+
+        let stats = storage.get_stats().await?;
+        assert_eq!(stats.prime_events_pending, 0);
+        assert_eq!(stats.prime_events_delivered, 1);
+
+        let cleanup = storage.cleanup_uploaded(Duration::ZERO).await?;
+        assert_eq!(cleanup.prime_events_deleted, 1);
+
+        let stats = storage.get_stats().await?;
+        assert_eq!(stats.prime_events_delivered, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sqlite_dead_prime_events_are_kept_but_not_retried() -> Result<(), SqliteStorageError> {
+        let storage = SqliteStorage::new_in_memory().await?;
+
+        let dead = PrimeEventStorage::enqueue(&storage, dummy_disconnection()).await?;
+        let pending = PrimeEventStorage::enqueue(&storage, dummy_disconnection()).await?;
+
+        PrimeEventStorage::mark_dead(&storage, dead).await?;
+
+        let queued = PrimeEventStorage::fetch_pending(&storage).await?;
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].id, pending);
+
+        let cleanup = storage.cleanup_uploaded(Duration::ZERO).await?;
+        assert_eq!(cleanup.prime_events_deleted, 0);
+
+        let stats = storage.get_stats().await?;
+        assert_eq!(stats.prime_events_pending, 1);
+        assert_eq!(stats.prime_events_delivered, 0);
+        assert_eq!(stats.prime_events_dead, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sqlite_downlinks_are_replaced_and_kept_in_order() -> Result<(), SqliteStorageError> {
+        let storage = SqliteStorage::new_in_memory().await?;
+        let device = DeviceId(Ulid::new());
+        let downlink = |sampling_rate_ms| SensorDownlink {
+            sensor_id: SensorId(Ulid::new()),
+            sampling_rate_ms: Some(sampling_rate_ms),
+            offset: None,
+        };
+
+        let pending = HashMap::from([(device, vec![downlink(60_000), downlink(5_000)])]);
+        storage.replace_downlinks(&pending).await?;
+        assert_eq!(storage.fetch_downlinks().await?, pending);
+
+        // delivered changes are gone once the queue is stored again
+        storage.replace_downlinks(&HashMap::new()).await?;
+        assert!(storage.fetch_downlinks().await?.is_empty());
+
+        Ok(())
+    }
 }
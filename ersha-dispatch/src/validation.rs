@@ -0,0 +1,318 @@
+use std::collections::{HashMap, VecDeque};
+
+use ersha_core::{DeviceId, DeviceStatus, Percentage, SensorId, SensorMetric, SensorReading};
+
+use crate::config::ValidationConfig;
+
+/// Confidence taken off a reading that jumps away from its recent average.
+const SPIKE_PENALTY: u8 = 40;
+
+/// Confidence taken off readings from a sensor reporting the same value over
+/// and over.
+const STUCK_PENALTY: u8 = 50;
+
+/// Confidence taken off per dBm the device signal is below the weak threshold.
+const RSSI_PENALTY_PER_DB: u8 = 2;
+
+/// Largest confidence penalty for a weak signal.
+const MAX_RSSI_PENALTY: u8 = 40;
+
+/// Why a reading was rejected.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Rejection {
+    #[error("Value {value} outside physical bounds {min}..={max}")]
+    OutOfBounds { value: f64, min: f64, max: f64 },
+}
+
+/// Outcome for a reading that passed validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Assessment {
+    /// Confidence the reading should be stored with.
+    pub confidence: Percentage,
+    /// Set when this reading is the one that showed the sensor to be stuck.
+    pub newly_stuck: bool,
+}
+
+/// Per-metric checks beyond the physical bounds.
+struct MetricRules {
+    /// Largest plausible change from the recent average, if spikes are checked.
+    max_step: Option<f64>,
+    /// Whether repeating the exact same value marks the sensor as stuck.
+    detect_stuck: bool,
+}
+
+fn rules(metric: &SensorMetric) -> MetricRules {
+    match metric {
+        // integer percentages that legitimately hold steady for hours
+        SensorMetric::SoilMoisture { .. } => MetricRules {
+            max_step: Some(25.0),
+            detect_stuck: false,
+        },
+        SensorMetric::SoilTemp { .. } => MetricRules {
+            max_step: Some(8.0),
+            detect_stuck: true,
+        },
+        SensorMetric::AirTemp { .. } => MetricRules {
+            max_step: Some(12.0),
+            detect_stuck: true,
+        },
+        SensorMetric::Humidity { .. } => MetricRules {
+            max_step: Some(35.0),
+            detect_stuck: true,
+        },
+        SensorMetric::SoilEc { .. } => MetricRules {
+            max_step: Some(3.0),
+            detect_stuck: true,
+        },
+        SensorMetric::SoilPh { .. } => MetricRules {
+            max_step: Some(1.5),
+            detect_stuck: true,
+        },
+        SensorMetric::BarometricPressure { .. } => MetricRules {
+            max_step: Some(10.0),
+            detect_stuck: true,
+        },
+        // levels hold steady for days but move quickly when gates open
+        SensorMetric::WaterLevel { .. } => MetricRules {
+            max_step: Some(2.0),
+            detect_stuck: false,
+        },
+        // rain starts and stops abruptly and reads zero while dry, and the
+        // same goes for dew, gusts, passing clouds and pumps switching
+        SensorMetric::Rainfall { .. }
+        | SensorMetric::LeafWetness { .. }
+        | SensorMetric::WindSpeed { .. }
+        | SensorMetric::SolarRadiation { .. }
+        | SensorMetric::FlowRate { .. } => MetricRules {
+            max_step: None,
+            detect_stuck: false,
+        },
+        // wraps from 359 to 0 and holds still while calm
+        SensorMetric::WindDirection { .. } => MetricRules {
+            max_step: None,
+            detect_stuck: false,
+        },
+    }
+}
+
+#[derive(Default)]
+struct SensorHistory {
+    values: VecDeque<f64>,
+    stuck: bool,
+}
+
+#[derive(Default)]
+struct DeviceHistory {
+    rssi: Option<i16>,
+    sensors: HashMap<SensorId, SensorHistory>,
+}
+
+/// Checks readings for plausibility and scores their confidence.
+///
+/// Readings outside the physical bounds of their metric are rejected. The
+/// rest keep their incoming confidence minus penalties for spikes against a
+/// sliding window of recent values, for stuck sensors, and for devices whose
+/// last reported signal was weak.
+pub struct Validator {
+    config: ValidationConfig,
+    devices: HashMap<DeviceId, DeviceHistory>,
+}
+
+impl Validator {
+    pub fn new(config: ValidationConfig) -> Self {
+        Self {
+            config,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// Validate a reading and record it in its sensor's history.
+    pub fn assess(&mut self, reading: &SensorReading) -> Result<Assessment, Rejection> {
+        let value = reading.metric.value();
+        if !reading.metric.is_plausible() {
+            let (min, max) = reading.metric.bounds();
+            return Err(Rejection::OutOfBounds { value, min, max });
+        }
+
+        let rules = rules(&reading.metric);
+        let device = self.devices.entry(reading.device_id).or_default();
+        let history = device.sensors.entry(reading.sensor_id).or_default();
+        let mut confidence = reading.confidence.0.min(100);
+
+        if let Some(max_step) = rules.max_step
+            && !history.values.is_empty()
+        {
+            let mean = history.values.iter().sum::<f64>() / history.values.len() as f64;
+            if (value - mean).abs() > max_step {
+                confidence = confidence.saturating_sub(SPIKE_PENALTY);
+            }
+        }
+
+        history.values.push_back(value);
+        let keep = self.config.window_size.max(self.config.stuck_after).max(1);
+        while history.values.len() > keep {
+            history.values.pop_front();
+        }
+
+        let mut newly_stuck = false;
+        if rules.detect_stuck && self.config.stuck_after > 1 {
+            let stuck = history.values.len() >= self.config.stuck_after
+                && history
+                    .values
+                    .iter()
+                    .rev()
+                    .take(self.config.stuck_after)
+                    .all(|v| *v == value);
+
+            if stuck {
+                confidence = confidence.saturating_sub(STUCK_PENALTY);
+            }
+            newly_stuck = stuck && !history.stuck;
+            history.stuck = stuck;
+        }
+
+        if let Some(rssi) = device.rssi
+            && rssi < self.config.weak_rssi_dbm
+        {
+            let below = (self.config.weak_rssi_dbm - rssi).unsigned_abs();
+            let penalty = below
+                .saturating_mul(RSSI_PENALTY_PER_DB as u16)
+                .min(MAX_RSSI_PENALTY as u16) as u8;
+            confidence = confidence.saturating_sub(penalty);
+        }
+
+        Ok(Assessment {
+            confidence: Percentage(confidence),
+            newly_stuck,
+        })
+    }
+
+    /// Remember the signal strength a device last reported.
+    pub fn record_status(&mut self, status: &DeviceStatus) {
+        self.devices.entry(status.device_id).or_default().rssi = Some(status.signal_rssi);
+    }
+
+    /// Drop the history of a device that has disconnected.
+    pub fn forget_device(&mut self, device_id: DeviceId) {
+        self.devices.remove(&device_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rejection, Validator};
+    use crate::config::ValidationConfig;
+    use ersha_core::*;
+    use ordered_float::NotNan;
+    use ulid::Ulid;
+
+    fn air_temp(device_id: DeviceId, sensor_id: SensorId, value: f64) -> SensorReading {
+        SensorReading {
+            id: ReadingId(Ulid::new()),
+            device_id,
+            dispatcher_id: DispatcherId(Ulid::new()),
+            metric: SensorMetric::AirTemp {
+                value: NotNan::new(value).unwrap(),
+            },
+            location: H3Cell(0x8a2a1072b59ffff),
+            confidence: Percentage(100),
+            timestamp: jiff::Timestamp::now(),
+            sensor_id,
+            raw_value: None,
+        }
+    }
+
+    fn validator() -> Validator {
+        Validator::new(ValidationConfig {
+            window_size: 5,
+            stuck_after: 4,
+            weak_rssi_dbm: -100,
+        })
+    }
+
+    #[test]
+    fn rejects_values_outside_physical_bounds() {
+        let mut validator = validator();
+        let reading = air_temp(DeviceId(Ulid::new()), SensorId(Ulid::new()), 300.0);
+
+        assert_eq!(
+            validator.assess(&reading),
+            Err(Rejection::OutOfBounds {
+                value: 300.0,
+                min: -90.0,
+                max: 60.0
+            })
+        );
+    }
+
+    #[test]
+    fn penalises_spikes_and_clamps_confidence() {
+        let mut validator = validator();
+        let device = DeviceId(Ulid::new());
+        let sensor = SensorId(Ulid::new());
+
+        for value in [20.0, 21.0, 20.5] {
+            let assessment = validator.assess(&air_temp(device, sensor, value)).unwrap();
+            assert_eq!(assessment.confidence, Percentage(100));
+        }
+
+        let mut spike = air_temp(device, sensor, 45.0);
+        spike.confidence = Percentage(255);
+        let assessment = validator.assess(&spike).unwrap();
+        assert_eq!(assessment.confidence, Percentage(60));
+    }
+
+    #[test]
+    fn reports_stuck_sensor_once() {
+        let mut validator = validator();
+        let device = DeviceId(Ulid::new());
+        let sensor = SensorId(Ulid::new());
+
+        let flags: Vec<bool> = (0..6)
+            .map(|_| {
+                validator
+                    .assess(&air_temp(device, sensor, 22.5))
+                    .unwrap()
+                    .newly_stuck
+            })
+            .collect();
+        assert_eq!(flags, vec![false, false, false, true, false, false]);
+
+        let stuck = validator.assess(&air_temp(device, sensor, 22.5)).unwrap();
+        assert_eq!(stuck.confidence, Percentage(50));
+
+        let moving = validator.assess(&air_temp(device, sensor, 23.0)).unwrap();
+        assert_eq!(moving.confidence, Percentage(100));
+        assert!(!moving.newly_stuck);
+    }
+
+    #[test]
+    fn penalises_weak_signal() {
+        let mut validator = validator();
+        let device = DeviceId(Ulid::new());
+
+        validator.record_status(&DeviceStatus {
+            id: StatusId(Ulid::new()),
+            device_id: device,
+            dispatcher_id: DispatcherId(Ulid::new()),
+            battery_percent: Percentage(80),
+            uptime_seconds: 60,
+            signal_rssi: -110,
+            errors: Box::new([]),
+            timestamp: jiff::Timestamp::now(),
+            sensor_statuses: Box::new([]),
+            firmware: None,
+        });
+
+        let assessment = validator
+            .assess(&air_temp(device, SensorId(Ulid::new()), 20.0))
+            .unwrap();
+        assert_eq!(assessment.confidence, Percentage(80));
+
+        validator.forget_device(device);
+        let assessment = validator
+            .assess(&air_temp(device, SensorId(Ulid::new()), 20.0))
+            .unwrap();
+        assert_eq!(assessment.confidence, Percentage(100));
+    }
+}
@@ -19,16 +19,20 @@ use {defmt_rtt as _, panic_probe as _};
 use ulid::Ulid;
 
 use ersha_edge::{
-    Engine, Sensor, SensorMetric,
+    DeviceId, Engine, Sensor, SensorMetric,
     sensor::{SensorConfig, SensorError},
     sensor_task,
-    transport::Wifi,
+    transport::{Credentials, Wifi},
 
 };
 
 const WIFI_NETWORK: &str = "A";
 const WIFI_PASSWORD: &str = "123r5678i879";
 
+// Registered with ersha-prime; the key comes from POST /api/devices/{id}/key.
+const DEVICE_ID: &str = "01KFYZSR0DB1WQKGNZ09WDD29N";
+const DEVICE_KEY: [u8; 32] = [0; 32];
+
 static RX_BUFFER: StaticCell<[u8; 4096]> = StaticCell::new();
 static TX_BUFFER: StaticCell<[u8; 4096]> = StaticCell::new();
 
@@ -149,8 +153,14 @@ async fn main(spawner: Spawner) {
     let rx_buffer = RX_BUFFER.init([0; 4096]);
     let tx_buffer = TX_BUFFER.init([0; 4096]);
 
-    let wifi = Wifi::new(stack, rx_buffer, tx_buffer);
-    let engine = Engine::new(wifi, 0x887ade7255fffff).await.unwrap();
+    let credentials = Credentials {
+        device_id: DeviceId(Ulid::from_string(DEVICE_ID).expect("invalid ulid")),
+        key: DEVICE_KEY,
+    };
+    let wifi = Wifi::new(stack, rx_buffer, tx_buffer, credentials, RoscRng);
+    let engine = Engine::new(wifi, 0x887ade7255fffff, rng.next_u32())
+        .await
+        .unwrap();
 
     spawner.spawn(unwrap!(soil_moisture(&MockSoilMoistureSensor)));
     spawner.spawn(unwrap!(air_temperature(&MockTempSensor)));
@@ -169,6 +179,6 @@ async fn main(spawner: Spawner) {
 }
 
 #[embassy_executor::task]
-async fn ersha_wifi(runner: Engine<Wifi<'static>>) {
+async fn ersha_wifi(runner: Engine<Wifi<'static, RoscRng>>) {
     runner.run().await
 }
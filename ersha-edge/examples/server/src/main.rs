@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use tokio::{
     io,
     io::AsyncReadExt,
@@ -6,11 +8,19 @@ use tokio::{
 };
 
 use ersha_edge::{
-    H3Cell, ReadingPacket,
-    transport::{Msg, MsgType, PACKET_PREAMBLE},
+    DeviceId, H3Cell, ReadingPacket,
+    actuator::CommandAck,
+    ota::FirmwareReport,
+    transport::{
+        DeviceKey, HANDSHAKE_ACCEPTED, HELLO, MAX_PACKET_SIZE, Msg, MsgType, PACKET_PREAMBLE,
+        PROTOCOL_VERSION, Role, Session,
+    },
 };
 use ulid::Ulid;
 
+/// Key every device is expected to use, matching the rp235x example.
+const DEMO_KEY: DeviceKey = [0; 32];
+
 #[tokio::main]
 async fn main() -> io::Result<()> {
     let addr = "0.0.0.0:9001";
@@ -35,21 +45,32 @@ async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
     let mut hello = [0u8; 5];
     stream.read_exact(&mut hello).await?;
 
-    if &hello != b"HELLO" {
+    if &hello != HELLO {
         println!("Invalid handshake");
         return Ok(());
     }
 
-    let mut location = [0u8; 8];
-    stream.read_exact(&mut location).await?;
-
-    let location: H3Cell = u64::from_be_bytes(location);
-
-    let device_id = Ulid::new();
-    stream.write_all(&device_id.0.to_be_bytes()).await?;
-    println!("Assigned device_id={}", device_id);
+    let mut device_id = [0u8; 16];
+    stream.read_exact(&mut device_id).await?;
+    let device_id = Ulid(u128::from_be_bytes(device_id));
+
+    let mut device_nonce = [0u8; 16];
+    stream.read_exact(&mut device_nonce).await?;
+
+    // a fresh ULID is unique enough for a demo session nonce
+    let nonce = Ulid::new().0.to_be_bytes();
+    stream.write_all(&[HANDSHAKE_ACCEPTED]).await?;
+    stream.write_all(&nonce).await?;
+    let mut session = Session::new(
+        &DEMO_KEY,
+        &device_nonce,
+        &nonce,
+        DeviceId(device_id),
+        Role::Dispatcher,
+    );
 
     let mut buf: Vec<u8> = Vec::with_capacity(1024);
+    let mut location: Option<H3Cell> = None;
     let mut tmp = [0u8; 256];
 
     loop {
@@ -93,9 +114,58 @@ async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
                     msg.payload.len()
                 );
 
+                let mut payload = msg.payload.to_vec();
+                if let Err(e) = session.open(&msg.msg_type, msg.counter, &mut payload, &msg.tag) {
+                    println!("Rejected frame from device {}: {:?}", device_id, e);
+                    return Ok(());
+                }
+
                 match msg.msg_type {
+                    MsgType::Hello => {
+                        let Ok(bytes) = <[u8; 8]>::try_from(payload.as_slice()) else {
+                            println!("Invalid hello from device {}", device_id);
+                            return Ok(());
+                        };
+                        location = Some(u64::from_be_bytes(bytes));
+                        println!("Device {} authenticated", device_id);
+
+                        let mut now_ms = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_millis() as u64)
+                            .unwrap_or_default()
+                            .to_be_bytes();
+                        send_sealed(&mut stream, &mut session, MsgType::Time, &mut now_ms).await?;
+                    }
+                    MsgType::FirmwareStatus => {
+                        match postcard::from_bytes::<FirmwareReport>(&payload) {
+                            Ok(report) => println!(
+                                "[device {}] firmware {} v{}: {:?}",
+                                device_id, report.model, report.version, report.state
+                            ),
+                            Err(_) => println!("Invalid firmware report from device {}", device_id),
+                        }
+                    }
+                    MsgType::CommandAck => match postcard::from_bytes::<CommandAck>(&payload) {
+                        Ok(ack) => println!(
+                            "[device {}] command {} acknowledged: {:?}",
+                            device_id, ack.command_id.0, ack.result
+                        ),
+                        Err(_) => println!("Invalid command ack from device {}", device_id),
+                    },
+                    MsgType::Time
+                    | MsgType::Ack
+                    | MsgType::Config
+                    | MsgType::FirmwareOffer
+                    | MsgType::FirmwareRequest
+                    | MsgType::FirmwareChunk
+                    | MsgType::Command => {
+                        println!(
+                            "Unexpected {:?} frame from device {}",
+                            msg.msg_type, device_id
+                        );
+                    }
                     MsgType::Reading => {
-                        let packet: ReadingPacket = match postcard::from_bytes(msg.payload) {
+                        let packet: ReadingPacket = match postcard::from_bytes(&payload) {
                             Ok(p) => p,
                             Err(_) => {
                                 println!("Invalid reading payload from device {}", device_id);
@@ -104,13 +174,18 @@ async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
                         };
 
                         println!(
-                            "[device {} location {}] sensor {} reading {} => {:?}",
-                            packet.device_id,
+                            "[device {} location {:?}] sensor {} reading {} at {} => {:?}",
+                            packet.device_id.0,
                             location,
-                            packet.sensor_id,
+                            packet.sensor_id.0,
                             packet.reading_id,
+                            packet.timestamp_ms,
                             packet.metric
                         );
+
+                        // nothing is stored here, so acknowledge on receipt
+                        let mut ack = packet.reading_id.to_be_bytes();
+                        send_sealed(&mut stream, &mut session, MsgType::Ack, &mut ack).await?;
                     }
                 }
             }
@@ -121,3 +196,25 @@ async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
 
     Ok(())
 }
+
+async fn send_sealed(
+    stream: &mut TcpStream,
+    session: &mut Session,
+    msg_type: MsgType,
+    payload: &mut [u8],
+) -> io::Result<()> {
+    let (counter, tag) = session
+        .seal(&msg_type, payload)
+        .map_err(|e| io::Error::other(format!("{e:?}")))?;
+    let msg = Msg {
+        preamble: PACKET_PREAMBLE,
+        version: PROTOCOL_VERSION,
+        msg_type,
+        counter,
+        payload,
+        tag,
+    };
+    let mut frame = [0u8; MAX_PACKET_SIZE];
+    let frame = postcard::to_slice(&msg, &mut frame).expect("frame fits");
+    stream.write_all(frame).await
+}
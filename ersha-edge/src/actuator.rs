@@ -0,0 +1,325 @@
+use defmt::{Format, error, info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::pubsub::PubSubChannel;
+use embassy_time::{Duration, Instant, Timer};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// Actuator tasks that can listen for commands at the same time.
+pub const MAX_ACTUATORS: usize = 4;
+/// Longest any actuator may run on one command, whatever its configuration.
+pub const MAX_RUN: Duration = Duration::from_secs(4 * 60 * 60);
+/// Commands remembered per actuator, so one delivered twice only runs once.
+const RECENT_COMMANDS: usize = 4;
+/// How long to wait before trying again to close an actuator.
+const CLOSE_RETRY_MS: u64 = 1_000;
+
+pub use ersha_core::{ActuatorId, CommandId};
+
+/// Commands from the dispatcher, picked up by the actuator they address.
+pub static ACTUATOR_COMMANDS: PubSubChannel<
+    CriticalSectionRawMutex,
+    ActuatorCommand,
+    MAX_ACTUATORS,
+    MAX_ACTUATORS,
+    1,
+> = PubSubChannel::new();
+
+/// Acknowledgements from actuator tasks, sent on to the dispatcher.
+///
+/// Actuator tasks never wait on this channel. While the engine is not
+/// draining it, for example during a reconnect, acks that do not fit are
+/// dropped so the run time limit is always enforced.
+pub static ACTUATOR_ACKS: Channel<CriticalSectionRawMutex, CommandAck, MAX_ACTUATORS> =
+    Channel::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum ActuatorAction {
+    /// Open for this many seconds, limited by the actuator's maximum run time.
+    Open {
+        duration_s: u32,
+    },
+    Close,
+}
+
+/// Command for one actuator, sent by the dispatcher.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct ActuatorCommand {
+    pub command_id: CommandId,
+    pub actuator_id: ActuatorId,
+    pub action: ActuatorAction,
+}
+
+/// State of the actuator after a command.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum CommandResult {
+    /// Open, closing on its own after this many seconds.
+    Open {
+        remaining_s: u32,
+    },
+    Closed,
+    /// Driving the actuator failed, so it was closed instead.
+    Fault,
+}
+
+/// Acknowledgement of a command, sent to the dispatcher.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct CommandAck {
+    pub command_id: CommandId,
+    pub result: CommandResult,
+}
+
+#[derive(Debug, Format)]
+pub enum ActuatorError {
+    Fault,
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum ActuatorKind {
+    Valve,
+    Pump,
+}
+
+pub struct ActuatorConfig {
+    pub actuator_id: Ulid,
+    pub kind: ActuatorKind,
+    /// Longest the actuator may stay open on one command, capped at [`MAX_RUN`].
+    pub max_run: Duration,
+}
+
+pub trait Actuator {
+    fn config(&self) -> ActuatorConfig;
+    /// Drive the actuator open or closed.
+    fn set(&self, open: bool) -> impl Future<Output = Result<(), ActuatorError>>;
+}
+
+/// Run-time limits of one actuator, kept apart from the hardware.
+///
+/// The actuator is closed unless a command opened it, and every opening
+/// ends on its own at a deadline no later than the maximum run time.
+pub struct ActuatorControl {
+    actuator_id: ActuatorId,
+    max_run_ms: u64,
+    open_until_ms: Option<u64>,
+    recent: [Option<CommandId>; RECENT_COMMANDS],
+    next_recent: usize,
+}
+
+impl ActuatorControl {
+    pub fn new(config: &ActuatorConfig) -> Self {
+        Self {
+            actuator_id: ActuatorId(config.actuator_id),
+            max_run_ms: config.max_run.min(MAX_RUN).as_millis(),
+            open_until_ms: None,
+            recent: [None; RECENT_COMMANDS],
+            next_recent: 0,
+        }
+    }
+
+    /// Apply a command, returning whether the actuator should now be open.
+    /// `None` if the command is for another actuator. A command seen before
+    /// leaves the state as it is.
+    pub fn command(&mut self, command: &ActuatorCommand, now_ms: u64) -> Option<bool> {
+        if command.actuator_id != self.actuator_id {
+            return None;
+        }
+        if self.recent.contains(&Some(command.command_id)) {
+            return Some(self.is_open());
+        }
+        self.recent[self.next_recent] = Some(command.command_id);
+        self.next_recent = (self.next_recent + 1) % RECENT_COMMANDS;
+
+        self.open_until_ms = match command.action {
+            ActuatorAction::Open { duration_s } if duration_s > 0 => {
+                let run_ms = (duration_s as u64 * 1000).min(self.max_run_ms);
+                Some(now_ms + run_ms)
+            }
+            ActuatorAction::Open { .. } | ActuatorAction::Close => None,
+        };
+        Some(self.is_open())
+    }
+
+    /// Acknowledgement reporting the current state.
+    pub fn ack(&self, command_id: CommandId, now_ms: u64) -> CommandAck {
+        let result = match self.open_until_ms {
+            Some(until) => CommandResult::Open {
+                remaining_s: until.saturating_sub(now_ms).div_ceil(1000) as u32,
+            },
+            None => CommandResult::Closed,
+        };
+        CommandAck { command_id, result }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open_until_ms.is_some()
+    }
+
+    /// When the actuator has to close, if it is open.
+    pub fn deadline_ms(&self) -> Option<u64> {
+        self.open_until_ms
+    }
+
+    /// Close once the deadline has passed. Returns whether it just closed.
+    pub fn expire(&mut self, now_ms: u64) -> bool {
+        match self.open_until_ms {
+            Some(until) if now_ms >= until => {
+                self.open_until_ms = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record that the actuator could not be driven and was closed.
+    pub fn fail(&mut self) {
+        self.open_until_ms = None;
+    }
+}
+
+/// Carry out commands for one actuator until the device restarts.
+///
+/// The actuator is closed first, whatever state it powered up in, and is
+/// closed again at the end of every run even without a dispatcher.
+pub async fn run<A: Actuator>(actuator: &A) -> ! {
+    let config = actuator.config();
+    let mut control = ActuatorControl::new(&config);
+    close(actuator).await;
+
+    let mut commands = match ACTUATOR_COMMANDS.subscriber() {
+        Ok(commands) => commands,
+        Err(_) => {
+            error!("Too many actuators, {} stays closed", config.actuator_id.0);
+            core::future::pending().await
+        }
+    };
+
+    loop {
+        let deadline = control.deadline_ms();
+        let expiry = async {
+            match deadline {
+                Some(ms) => Timer::at(Instant::from_millis(ms)).await,
+                None => core::future::pending().await,
+            }
+        };
+
+        match select(commands.next_message_pure(), expiry).await {
+            Either::First(command) => {
+                let now = Instant::now().as_millis();
+                let Some(open) = control.command(&command, now) else {
+                    continue;
+                };
+
+                let mut ack = control.ack(command.command_id, now);
+                if let Err(e) = actuator.set(open).await {
+                    error!("Driving actuator failed: {:?}", e);
+                    control.fail();
+                    close(actuator).await;
+                    ack.result = CommandResult::Fault;
+                }
+                if ACTUATOR_ACKS.try_send(ack).is_err() {
+                    warn!("Ack queue full, dropping ack for {}", config.actuator_id.0);
+                }
+            }
+            Either::Second(()) => {
+                if control.expire(Instant::now().as_millis()) {
+                    info!("Run time over, closing {}", config.actuator_id.0);
+                    close(actuator).await;
+                }
+            }
+        }
+    }
+}
+
+/// Drive the actuator closed, trying until it succeeds.
+async fn close<A: Actuator>(actuator: &A) {
+    while let Err(e) = actuator.set(false).await {
+        error!("Closing actuator failed: {:?}", e);
+        Timer::after_millis(CLOSE_RETRY_MS).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ActuatorAction, ActuatorCommand, ActuatorConfig, ActuatorControl, ActuatorId, ActuatorKind,
+        CommandId, CommandResult, MAX_RUN,
+    };
+    use embassy_time::Duration;
+    use ulid::Ulid;
+
+    fn control(max_run: Duration) -> ActuatorControl {
+        ActuatorControl::new(&ActuatorConfig {
+            actuator_id: Ulid(7),
+            kind: ActuatorKind::Valve,
+            max_run,
+        })
+    }
+
+    fn open(command_id: u128, duration_s: u32) -> ActuatorCommand {
+        ActuatorCommand {
+            command_id: CommandId(Ulid(command_id)),
+            actuator_id: ActuatorId(Ulid(7)),
+            action: ActuatorAction::Open { duration_s },
+        }
+    }
+
+    #[test]
+    fn runs_are_limited_to_the_maximum_run_time() {
+        let mut valve = control(Duration::from_secs(600));
+
+        assert_eq!(valve.command(&open(1, 3600), 0), Some(true));
+        assert_eq!(valve.deadline_ms(), Some(600_000));
+        assert_eq!(
+            valve.ack(CommandId(Ulid(1)), 1_000).result,
+            CommandResult::Open { remaining_s: 599 }
+        );
+
+        assert!(!valve.expire(599_999));
+        assert!(valve.expire(600_000));
+        assert!(!valve.is_open());
+
+        // a configured limit above the global one does not lift it
+        let mut pump = control(MAX_RUN * 2);
+        pump.command(&open(2, u32::MAX), 0);
+        assert_eq!(pump.deadline_ms(), Some(MAX_RUN.as_millis()));
+    }
+
+    #[test]
+    fn repeated_commands_do_not_extend_a_run() {
+        let mut valve = control(Duration::from_secs(600));
+
+        valve.command(&open(1, 60), 0);
+        assert_eq!(valve.command(&open(1, 60), 30_000), Some(true));
+        assert_eq!(valve.deadline_ms(), Some(60_000));
+
+        // other actuators' commands are not ours to acknowledge
+        let other = ActuatorCommand {
+            actuator_id: ActuatorId(Ulid(8)),
+            ..open(2, 60)
+        };
+        assert_eq!(valve.command(&other, 0), None);
+    }
+
+    #[test]
+    fn close_and_zero_duration_close() {
+        let mut valve = control(Duration::from_secs(600));
+
+        valve.command(&open(1, 60), 0);
+        let close = ActuatorCommand {
+            action: ActuatorAction::Close,
+            ..open(2, 0)
+        };
+        assert_eq!(valve.command(&close, 10), Some(false));
+        assert_eq!(
+            valve.ack(CommandId(Ulid(2)), 10).result,
+            CommandResult::Closed
+        );
+
+        valve.command(&open(3, 60), 20);
+        assert_eq!(valve.command(&open(4, 0), 30), Some(false));
+        assert_eq!(valve.deadline_ms(), None);
+    }
+}
@@ -0,0 +1,45 @@
+use defmt::Format;
+
+/// Relation between the node's monotonic clock and the dispatcher's clock,
+/// captured during the handshake.
+#[derive(Clone, Copy, Debug, Format)]
+pub struct TimeSync {
+    /// Dispatcher time at the handshake, in milliseconds since the unix epoch.
+    pub server_time_ms: u64,
+    /// Node uptime at the handshake, in milliseconds.
+    pub local_time_ms: u64,
+}
+
+impl TimeSync {
+    pub fn new(server_time_ms: u64, local_time_ms: u64) -> Self {
+        Self {
+            server_time_ms,
+            local_time_ms,
+        }
+    }
+
+    /// Convert a node uptime in milliseconds to dispatcher time.
+    pub fn server_time(&self, local_ms: u64) -> u64 {
+        if local_ms >= self.local_time_ms {
+            self.server_time_ms
+                .saturating_add(local_ms - self.local_time_ms)
+        } else {
+            self.server_time_ms
+                .saturating_sub(self.local_time_ms - local_ms)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeSync;
+
+    #[test]
+    fn converts_local_time_around_sync_point() {
+        let sync = TimeSync::new(1_700_000_000_000, 5_000);
+
+        assert_eq!(sync.server_time(5_000), 1_700_000_000_000);
+        assert_eq!(sync.server_time(7_500), 1_700_000_002_500);
+        assert_eq!(sync.server_time(1_000), 1_699_999_996_000);
+    }
+}
@@ -0,0 +1,269 @@
+use defmt::Format;
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::watch::{Receiver, Watch};
+use embassy_time::{Duration, Instant, Timer};
+use serde::{Deserialize, Serialize};
+
+use crate::sensor::SensorConfig;
+use crate::{Error, SensorId, SensorMetric};
+
+/// Sensors whose configuration can be changed at runtime, and sensor tasks
+/// that can watch for changes.
+pub const MAX_CONFIGURED_SENSORS: usize = 8;
+
+/// Runtime configuration received from the dispatcher.
+pub static SENSOR_CONFIG: ConfigRegistry = ConfigRegistry::new();
+
+/// Configuration change for one sensor, sent by the dispatcher.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct SensorConfigUpdate {
+    pub sensor_id: SensorId,
+    /// New sampling period in milliseconds.
+    pub sampling_rate_ms: Option<u32>,
+    /// Offset added to every reading, in the units of the metric on the wire.
+    pub offset: Option<i16>,
+}
+
+/// Effective configuration of a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SensorSettings {
+    pub sampling_rate: Duration,
+    pub offset: i16,
+}
+
+impl SensorSettings {
+    /// Apply the offset to a reading.
+    pub fn calibrate(&self, metric: SensorMetric) -> SensorMetric {
+        if self.offset == 0 {
+            metric
+        } else {
+            metric.calibrate(self.offset)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Override {
+    sensor_id: SensorId,
+    sampling_rate: Option<Duration>,
+    offset: Option<i16>,
+}
+
+type Overrides = [Option<Override>; MAX_CONFIGURED_SENSORS];
+
+/// Runtime overrides of the compiled-in [`SensorConfig`] of each sensor.
+pub struct ConfigRegistry {
+    overrides: Watch<CriticalSectionRawMutex, Overrides, MAX_CONFIGURED_SENSORS>,
+}
+
+impl ConfigRegistry {
+    pub const fn new() -> Self {
+        Self {
+            overrides: Watch::new_with([None; MAX_CONFIGURED_SENSORS]),
+        }
+    }
+
+    /// Apply an update, waking the tasks of affected sensors.
+    pub fn apply(&self, update: &SensorConfigUpdate) -> Result<(), Error> {
+        let sampling_rate = update
+            .sampling_rate_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| Duration::from_millis(ms as u64));
+
+        let mut overrides = self
+            .overrides
+            .try_get()
+            .unwrap_or([None; MAX_CONFIGURED_SENSORS]);
+
+        let slot = match overrides
+            .iter()
+            .position(|o| o.is_some_and(|o| o.sensor_id == update.sensor_id))
+        {
+            Some(i) => &mut overrides[i],
+            None => overrides
+                .iter_mut()
+                .find(|o| o.is_none())
+                .ok_or(Error::TooManySensors)?,
+        };
+
+        let current = slot.get_or_insert(Override {
+            sensor_id: update.sensor_id,
+            sampling_rate: None,
+            offset: None,
+        });
+        current.sampling_rate = sampling_rate.or(current.sampling_rate);
+        current.offset = update.offset.or(current.offset);
+
+        self.overrides.sender().send(overrides);
+        Ok(())
+    }
+
+    /// Effective settings of a sensor, falling back to its compiled-in
+    /// configuration for anything not overridden.
+    pub fn settings(&self, config: &SensorConfig) -> SensorSettings {
+        let sensor_id = SensorId(config.sensor_id);
+        let found = self.overrides.try_get().and_then(|overrides| {
+            overrides
+                .into_iter()
+                .flatten()
+                .find(|o| o.sensor_id == sensor_id)
+        });
+
+        SensorSettings {
+            sampling_rate: found
+                .and_then(|o| o.sampling_rate)
+                .unwrap_or(config.sampling_rate),
+            offset: found.and_then(|o| o.offset).unwrap_or(0),
+        }
+    }
+
+    /// Watch the settings of one sensor. Without a free watch slot, changes
+    /// still apply but only after the current sampling period.
+    pub fn watch(&'static self, config: SensorConfig) -> SettingsWatch {
+        SettingsWatch {
+            registry: self,
+            receiver: self.overrides.receiver(),
+            config,
+        }
+    }
+}
+
+impl Default for ConfigRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A sensor task's view of its own runtime configuration.
+pub struct SettingsWatch {
+    registry: &'static ConfigRegistry,
+    receiver: Option<Receiver<'static, CriticalSectionRawMutex, Overrides, MAX_CONFIGURED_SENSORS>>,
+    config: SensorConfig,
+}
+
+impl SettingsWatch {
+    pub fn sensor_id(&self) -> SensorId {
+        SensorId(self.config.sensor_id)
+    }
+
+    pub fn current(&self) -> SensorSettings {
+        self.registry.settings(&self.config)
+    }
+
+    /// Sleep until the next sample is due. A change to this sensor's
+    /// settings ends the wait early, so the new rate applies at once.
+    pub async fn wait_next_sample(&mut self) {
+        let settings = self.current();
+        let deadline = Instant::now() + settings.sampling_rate;
+
+        let Some(receiver) = self.receiver.as_mut() else {
+            Timer::at(deadline).await;
+            return;
+        };
+
+        loop {
+            match select(Timer::at(deadline), receiver.changed()).await {
+                Either::First(()) => return,
+                Either::Second(_) => {
+                    if self.registry.settings(&self.config) != settings {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigRegistry, MAX_CONFIGURED_SENSORS, SensorConfigUpdate, SensorSettings};
+    use crate::{Error, SensorId, SensorMetric, sensor::SensorConfig};
+    use embassy_time::Duration;
+    use ulid::Ulid;
+
+    fn config(sensor_id: u128) -> SensorConfig {
+        SensorConfig {
+            sampling_rate: Duration::from_secs(60),
+            sensor_id: Ulid(sensor_id),
+        }
+    }
+
+    #[test]
+    fn updates_override_compiled_in_config() {
+        let registry = ConfigRegistry::new();
+        assert_eq!(
+            registry.settings(&config(1)),
+            SensorSettings {
+                sampling_rate: Duration::from_secs(60),
+                offset: 0,
+            }
+        );
+
+        registry
+            .apply(&SensorConfigUpdate {
+                sensor_id: SensorId(Ulid(1)),
+                sampling_rate_ms: Some(5_000),
+                offset: None,
+            })
+            .unwrap();
+        registry
+            .apply(&SensorConfigUpdate {
+                sensor_id: SensorId(Ulid(1)),
+                sampling_rate_ms: None,
+                offset: Some(-3),
+            })
+            .unwrap();
+
+        let settings = registry.settings(&config(1));
+        assert_eq!(settings.sampling_rate, Duration::from_secs(5));
+        assert_eq!(settings.offset, -3);
+        assert!(matches!(
+            settings.calibrate(SensorMetric::SoilMoisture(40)),
+            SensorMetric::SoilMoisture(37)
+        ));
+
+        // other sensors keep their own configuration
+        assert_eq!(
+            registry.settings(&config(2)).sampling_rate,
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn zero_sampling_rate_is_ignored() {
+        let registry = ConfigRegistry::new();
+        registry
+            .apply(&SensorConfigUpdate {
+                sensor_id: SensorId(Ulid(1)),
+                sampling_rate_ms: Some(0),
+                offset: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            registry.settings(&config(1)).sampling_rate,
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn rejects_updates_beyond_capacity() {
+        let registry = ConfigRegistry::new();
+        let update = |sensor_id| SensorConfigUpdate {
+            sensor_id: SensorId(Ulid(sensor_id)),
+            sampling_rate_ms: Some(1_000),
+            offset: None,
+        };
+
+        for id in 0..MAX_CONFIGURED_SENSORS as u128 {
+            registry.apply(&update(id)).unwrap();
+        }
+        assert!(matches!(
+            registry.apply(&update(100)),
+            Err(Error::TooManySensors)
+        ));
+        // known sensors can still be updated
+        registry.apply(&update(0)).unwrap();
+    }
+}
@@ -1,57 +1,410 @@
-use crate::{DeviceId, Error, H3Cell, ReadingId, ReadingPacket, TaggedReading, Transport};
+use crate::{
+    BootId, DeviceId, Error, H3Cell, ReadingId, ReadingPacket, SENSOR_CONFIG, TaggedReading,
+    TimeSync, Transport,
+    actuator::{ACTUATOR_ACKS, ACTUATOR_COMMANDS},
+    ota::{FirmwareInfo, FirmwareUpdater, NoFirmwareUpdates, Ota, UpdateState},
+    transport::Downlink,
+};
 
+use embassy_futures::select::{Either4, select4};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::channel::Sender;
-use embassy_time::Timer;
+use embassy_time::{Instant, Timer};
 
-use defmt::error;
+use defmt::{error, info, warn};
 
 const READING_QUEUE_DEPTH: usize = 16;
+/// Readings sent but not yet acknowledged by the dispatcher. New readings
+/// wait in the queue while the window is full.
+const RETRY_WINDOW: usize = 8;
+/// How long to wait for an acknowledgement before sending a reading again.
+const ACK_TIMEOUT_MS: u64 = 5_000;
+/// Pause before each attempt to open a new session after a transport error.
+const RECONNECT_DELAY_MS: u64 = 5_000;
 
 pub static READING_CHANNEL: Channel<CriticalSectionRawMutex, TaggedReading, READING_QUEUE_DEPTH> =
     Channel::new();
 
-pub struct Engine<T: Transport> {
+pub struct Engine<T: Transport, F: FirmwareUpdater = NoFirmwareUpdates> {
     transport: T,
     device_id: DeviceId,
+    location: H3Cell,
+    boot_id: BootId,
+    /// Cleared when a transport call fails, until a new session is open.
+    connected: bool,
+    time_sync: TimeSync,
     reading_seq: ReadingId,
+    unacked: RetryWindow,
+    ota: Option<Ota<F>>,
 }
 
 impl<T: Transport> Engine<T> {
-    pub async fn new(mut transport: T, location: H3Cell) -> Result<Self, Error> {
-        let device_id = transport.provision(location).await?;
+    /// Connect to the dispatcher. `boot_id` must be chosen at random on
+    /// every boot, as reading ids start over from zero.
+    pub async fn new(mut transport: T, location: H3Cell, boot_id: BootId) -> Result<Self, Error> {
+        let handshake = transport.provision(location, boot_id).await?;
 
         Ok(Self {
             transport,
-            device_id,
+            device_id: handshake.device_id,
+            location,
+            boot_id,
+            connected: true,
+            time_sync: handshake.time_sync,
             reading_seq: 0,
+            unacked: RetryWindow::new(),
+            ota: None,
         })
     }
 
+    /// Accept firmware updates offered by the dispatcher, staging them
+    /// with `updater`.
+    pub fn with_firmware<F: FirmwareUpdater>(self, updater: F, info: FirmwareInfo) -> Engine<T, F> {
+        Engine {
+            transport: self.transport,
+            device_id: self.device_id,
+            location: self.location,
+            boot_id: self.boot_id,
+            connected: self.connected,
+            time_sync: self.time_sync,
+            reading_seq: self.reading_seq,
+            unacked: self.unacked,
+            ota: Some(Ota::new(updater, info)),
+        }
+    }
+}
+
+impl<T: Transport, F: FirmwareUpdater> Engine<T, F> {
     pub async fn run(mut self) -> ! {
         let receiver = READING_CHANNEL.receiver();
 
+        if let Some(ota) = self.ota.as_mut()
+            && let Err(e) = ota.confirm_boot().await
+        {
+            error!("Confirming firmware failed: {:?}", e);
+        }
+        self.update_firmware(Instant::now().as_millis()).await;
+
         loop {
-            let reading = receiver.receive().await;
+            if !self.connected {
+                self.reconnect().await;
+            }
 
-            let packet = ReadingPacket {
-                device_id: self.device_id,
-                sensor_id: reading.sensor_id,
-                reading_id: self.reading_seq,
-                metric: reading.metric,
+            // once an image is staged, pending readings drain before restarting
+            let staged = self
+                .ota
+                .as_ref()
+                .is_some_and(|ota| matches!(ota.state(), UpdateState::Staged { .. }));
+            let room = !self.unacked.is_full() && !staged;
+            let next_reading = async {
+                if room {
+                    receiver.receive().await
+                } else {
+                    core::future::pending().await
+                }
             };
 
-            if let Err(e) = self.transport.send_reading(&packet).await {
+            let event = select4(
+                next_reading,
+                self.transport.recv(),
+                ACTUATOR_ACKS.receive(),
+                Timer::after_millis(ACK_TIMEOUT_MS),
+            )
+            .await;
+
+            match event {
+                Either4::First(reading) => {
+                    let packet = ReadingPacket {
+                        device_id: self.device_id,
+                        sensor_id: reading.sensor_id,
+                        reading_id: self.reading_seq,
+                        metric: reading.metric,
+                        timestamp_ms: self.time_sync.server_time(reading.captured_at_ms),
+                    };
+                    self.reading_seq = self.reading_seq.wrapping_add(1);
+
+                    // kept even if the send fails, the retry picks it up
+                    if let Err(e) = self.transport.send_reading(&packet).await {
+                        error!("Uplink failed: {:?}", e);
+                        self.connected = false;
+                    }
+                    self.unacked.push(packet, Instant::now().as_millis());
+
+                    Timer::after_millis(100).await;
+                }
+                Either4::Second(Ok(Downlink::Ack(reading_id))) => {
+                    self.unacked.ack(reading_id);
+                }
+                Either4::Second(Ok(Downlink::Config(update))) => {
+                    info!("Config update for sensor {}", update.sensor_id);
+                    if let Err(e) = SENSOR_CONFIG.apply(&update) {
+                        error!("Config update rejected: {:?}", e);
+                    }
+                }
+                Either4::Second(Ok(Downlink::FirmwareOffer(offer))) => {
+                    if let Some(ota) = self.ota.as_mut()
+                        && let Some(request) = ota.offer(offer, Instant::now().as_millis()).await
+                    {
+                        info!("Downloading firmware {}", offer.version);
+                        if let Err(e) = self.transport.request_firmware_chunk(&request).await {
+                            error!("Firmware request failed: {:?}", e);
+                            self.connected = false;
+                        }
+                    }
+                }
+                Either4::Second(Ok(Downlink::FirmwareChunk(chunk))) => {
+                    if let Some(ota) = self.ota.as_mut()
+                        && let Some(request) = ota.chunk(&chunk, Instant::now().as_millis()).await
+                        && let Err(e) = self.transport.request_firmware_chunk(&request).await
+                    {
+                        error!("Firmware request failed: {:?}", e);
+                        self.connected = false;
+                    }
+                }
+                Either4::Second(Ok(Downlink::Command(command))) => {
+                    info!(
+                        "Command {} for actuator {}",
+                        command.command_id, command.actuator_id
+                    );
+                    // actuator tasks pick out their own commands
+                    ACTUATOR_COMMANDS
+                        .immediate_publisher()
+                        .publish_immediate(command);
+                }
+                Either4::Second(Err(e)) => {
+                    error!("Receiving from dispatcher failed: {:?}", e);
+                    self.connected = false;
+                }
+                Either4::Third(ack) => {
+                    if let Err(e) = self.transport.send_command_ack(&ack).await {
+                        error!("Command acknowledgement failed: {:?}", e);
+                        self.connected = false;
+                    }
+                }
+                Either4::Fourth(()) => {}
+            }
+
+            if !self.connected {
+                continue;
+            }
+
+            let now = Instant::now().as_millis();
+            while let Some(packet) = self.unacked.next_due(now) {
+                warn!("Reading {} not acknowledged, resending", packet.reading_id);
+                if let Err(e) = self.transport.send_reading(packet).await {
+                    error!("Uplink failed: {:?}", e);
+                    self.connected = false;
+                    break;
+                }
+            }
+
+            self.update_firmware(now).await;
+        }
+    }
+
+    /// Open a new session after a transport error, with a fresh time sync,
+    /// then send every unacknowledged reading again.
+    async fn reconnect(&mut self) {
+        loop {
+            Timer::after_millis(RECONNECT_DELAY_MS).await;
+            match self.transport.provision(self.location, self.boot_id).await {
+                Ok(handshake) => {
+                    self.time_sync = handshake.time_sync;
+                    break;
+                }
+                Err(e) => error!("Reconnecting to dispatcher failed: {:?}", e),
+            }
+        }
+
+        info!("Reconnected to dispatcher");
+        self.connected = true;
+
+        // same boot id, so the dispatcher drops any it already stored
+        for packet in self.unacked.resend_all(Instant::now().as_millis()) {
+            if let Err(e) = self.transport.send_reading(packet).await {
                 error!("Uplink failed: {:?}", e);
+                self.connected = false;
+                return;
             }
+        }
+    }
+
+    /// Report firmware state changes, ask again for overdue chunks and
+    /// restart into a staged image once sent readings are acknowledged.
+    async fn update_firmware(&mut self, now_ms: u64) {
+        let Some(ota) = self.ota.as_mut() else {
+            return;
+        };
+
+        if let Some(report) = ota.take_report()
+            && let Err(e) = self.transport.send_firmware_report(&report).await
+        {
+            error!("Firmware report failed: {:?}", e);
+            self.connected = false;
+        }
 
-            self.reading_seq = self.reading_seq.wrapping_add(1);
-            Timer::after_millis(100).await;
+        if let Some(request) = ota.next_due(now_ms) {
+            warn!(
+                "Firmware chunk {} not received, requesting again",
+                request.offset
+            );
+            if let Err(e) = self.transport.request_firmware_chunk(&request).await {
+                error!("Firmware request failed: {:?}", e);
+                self.connected = false;
+            }
         }
+
+        ota.reset_if_staged(self.unacked.is_empty(), now_ms);
+    }
+}
+
+struct Unacked {
+    packet: ReadingPacket,
+    sent_at_ms: u64,
+}
+
+/// Readings awaiting an acknowledgement from the dispatcher.
+struct RetryWindow {
+    slots: [Option<Unacked>; RETRY_WINDOW],
+}
+
+impl RetryWindow {
+    fn new() -> Self {
+        Self {
+            slots: [const { None }; RETRY_WINDOW],
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.slots.iter().all(Option::is_some)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    /// Track a reading that was just sent. Returns `false` if the window is
+    /// full and the reading was dropped.
+    fn push(&mut self, packet: ReadingPacket, now_ms: u64) -> bool {
+        let Some(slot) = self.slots.iter_mut().find(|slot| slot.is_none()) else {
+            return false;
+        };
+        *slot = Some(Unacked {
+            packet,
+            sent_at_ms: now_ms,
+        });
+        true
+    }
+
+    /// Stop tracking an acknowledged reading. Returns whether it was pending.
+    fn ack(&mut self, reading_id: ReadingId) -> bool {
+        let acked = self.slots.iter_mut().find(|slot| {
+            slot.as_ref()
+                .is_some_and(|u| u.packet.reading_id == reading_id)
+        });
+        match acked {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Next reading whose acknowledgement is overdue, marking it as sent
+    /// again at `now_ms`.
+    fn next_due(&mut self, now_ms: u64) -> Option<&ReadingPacket> {
+        let unacked = self
+            .slots
+            .iter_mut()
+            .flatten()
+            .filter(|u| now_ms.saturating_sub(u.sent_at_ms) >= ACK_TIMEOUT_MS)
+            .min_by_key(|u| u.sent_at_ms)?;
+        unacked.sent_at_ms = now_ms;
+        Some(&unacked.packet)
+    }
+
+    /// Every pending reading, marking each as sent again at `now_ms`.
+    fn resend_all(&mut self, now_ms: u64) -> impl Iterator<Item = &ReadingPacket> {
+        self.slots.iter_mut().flatten().map(move |unacked| {
+            unacked.sent_at_ms = now_ms;
+            &unacked.packet
+        })
     }
 }
 
 pub fn sender() -> Sender<'static, CriticalSectionRawMutex, TaggedReading, READING_QUEUE_DEPTH> {
     READING_CHANNEL.sender()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ACK_TIMEOUT_MS, RETRY_WINDOW, RetryWindow};
+    use crate::{DeviceId, ReadingId, ReadingPacket, SensorId, SensorMetric};
+    use ulid::Ulid;
+
+    fn packet(reading_id: ReadingId) -> ReadingPacket {
+        ReadingPacket {
+            device_id: DeviceId(Ulid(1)),
+            sensor_id: SensorId(Ulid(2)),
+            reading_id,
+            metric: SensorMetric::SoilMoisture(40),
+            timestamp_ms: 0,
+        }
+    }
+
+    #[test]
+    fn acked_readings_are_not_resent() {
+        let mut window = RetryWindow::new();
+        window.push(packet(0), 0);
+        window.push(packet(1), 10);
+
+        assert!(window.ack(0));
+        assert!(!window.ack(0));
+        assert!(window.next_due(ACK_TIMEOUT_MS).is_none());
+
+        let due = window.next_due(ACK_TIMEOUT_MS + 10).unwrap();
+        assert_eq!(due.reading_id, 1);
+        // resent, so not due again until another timeout passes
+        assert!(window.next_due(ACK_TIMEOUT_MS + 20).is_none());
+        assert!(window.next_due(2 * ACK_TIMEOUT_MS + 10).is_some());
+
+        assert!(!window.is_empty());
+        assert!(window.ack(1));
+        assert!(window.is_empty());
+    }
+
+    #[test]
+    fn reconnect_resends_every_pending_reading() {
+        let mut window = RetryWindow::new();
+        window.push(packet(0), 0);
+        window.push(packet(1), 10);
+        window.push(packet(2), 20);
+        window.ack(1);
+
+        let mut resent = [None; 3];
+        for (slot, packet) in resent.iter_mut().zip(window.resend_all(30)) {
+            *slot = Some(packet.reading_id);
+        }
+        assert_eq!(resent, [Some(0), Some(2), None]);
+        // not due again until a full timeout after the replay
+        assert!(window.next_due(ACK_TIMEOUT_MS + 20).is_none());
+        assert!(window.next_due(ACK_TIMEOUT_MS + 30).is_some());
+    }
+
+    #[test]
+    fn full_window_holds_back_new_readings() {
+        let mut window = RetryWindow::new();
+        for id in 0..RETRY_WINDOW as ReadingId {
+            assert!(window.push(packet(id), 0));
+        }
+
+        assert!(window.is_full());
+        assert!(!window.push(packet(100), 0));
+
+        window.ack(3);
+        assert!(!window.is_full());
+        assert!(window.push(packet(100), 0));
+    }
+}
@@ -1,19 +1,28 @@
 #![no_std]
 
+pub mod actuator;
+pub mod clock;
+pub mod config;
 pub mod engine;
+pub mod ota;
 pub mod sensor;
 pub mod transport;
 
+pub use actuator::Actuator;
+pub use clock::TimeSync;
+pub use config::{SENSOR_CONFIG, SensorConfigUpdate};
 pub use engine::Engine;
+pub use ersha_core::{DeviceId, SensorId};
 pub use sensor::{Sensor, SensorMetric};
 pub use transport::Transport;
 
 use defmt::Format;
 use serde::{Deserialize, Serialize};
 
-pub type DeviceId = u128;
-pub type SensorId = u128;
 pub type ReadingId = u16;
+/// Picked at random on every boot. Reading ids start over when the node
+/// restarts, so the dispatcher keys them on this to tell them apart.
+pub type BootId = u32;
 pub type H3Cell = u64;
 
 #[derive(Serialize, Deserialize, Format)]
@@ -22,12 +31,17 @@ pub struct ReadingPacket {
     pub sensor_id: SensorId,
     pub reading_id: ReadingId,
     pub metric: SensorMetric,
+    /// Capture time on the dispatcher's clock as tracked by the node since
+    /// the last time sync, in milliseconds since the unix epoch.
+    pub timestamp_ms: u64,
 }
 
 #[derive(Clone, Format)]
 pub struct TaggedReading {
     pub sensor_id: SensorId,
     pub metric: SensorMetric,
+    /// Node uptime when the reading was taken, in milliseconds.
+    pub captured_at_ms: u64,
 }
 
 #[derive(Debug, Format)]
@@ -36,6 +50,12 @@ pub enum Error {
     SerializationFailed,
     ServerNotFound,
     TooManySensors,
+    /// The dispatcher does not know this device.
+    Rejected,
+    /// A frame failed authentication or was replayed.
+    AuthenticationFailed,
+    /// The firmware staging partition could not be used.
+    Storage,
 }
 
 #[macro_export]
@@ -44,14 +64,15 @@ macro_rules! sensor_task {
         #[embassy_executor::task]
         async fn $task_name(sensor: &'static $sensor_ty) -> ! {
             let sender = $crate::engine::sender();
-            let config = sensor.config();
+            let mut settings = $crate::SENSOR_CONFIG.watch(sensor.config());
 
             loop {
                 match sensor.read().await {
                     Ok(reading) => {
                         let reading = $crate::TaggedReading {
-                            sensor_id: config.sensor_id.into(),
-                            metric: reading,
+                            sensor_id: settings.sensor_id(),
+                            metric: settings.current().calibrate(reading),
+                            captured_at_ms: embassy_time::Instant::now().as_millis(),
                         };
 
                         if sender.try_send(reading).is_err() {
@@ -63,16 +84,27 @@ macro_rules! sensor_task {
                     }
                 }
 
-                embassy_time::Timer::after(config.sampling_rate).await;
+                settings.wait_next_sample().await;
             }
         }
     };
 }
 
+#[macro_export]
+macro_rules! actuator_task {
+    ($task_name:ident, $actuator_ty:ty) => {
+        #[embassy_executor::task]
+        async fn $task_name(actuator: &'static $actuator_ty) -> ! {
+            $crate::actuator::run(actuator).await
+        }
+    };
+}
+
 #[cfg(test)]
 #[allow(dead_code)]
 mod tests {
     use super::*;
+    use crate::actuator::{Actuator, ActuatorConfig, ActuatorError, ActuatorKind};
     use crate::sensor::{Sensor, SensorConfig, SensorError};
 
     use embassy_time::Duration;
@@ -128,4 +160,22 @@ mod tests {
     }
 
     sensor_task!(rain_task, MockRainSensor);
+
+    struct MockValve;
+
+    impl Actuator for MockValve {
+        fn config(&self) -> ActuatorConfig {
+            ActuatorConfig {
+                actuator_id: Ulid::from_string("01KFYZSR0DB1WQKGNZ09WDD29P").expect("invalid ulid"),
+                kind: ActuatorKind::Valve,
+                max_run: Duration::from_secs(30 * 60),
+            }
+        }
+
+        async fn set(&self, _open: bool) -> Result<(), ActuatorError> {
+            Ok(())
+        }
+    }
+
+    actuator_task!(valve_task, MockValve);
 }
@@ -0,0 +1,676 @@
+use defmt::Format;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::Error;
+
+/// Longest device model name, in bytes.
+pub const MAX_MODEL_LEN: usize = 32;
+/// Bytes of image carried by one chunk, sized to fit a frame.
+pub const CHUNK_SIZE: usize = 64;
+/// The Ed25519 signature is transferred after the image, as if it were
+/// part of it.
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// How long to wait for a chunk before asking for it again.
+const CHUNK_TIMEOUT_MS: u64 = 5_000;
+/// Download progress is reported every this many percent.
+const PROGRESS_STEP: u8 = 10;
+/// Longest a staged image waits for unacknowledged readings before the
+/// device restarts into it anyway.
+const RESET_GRACE_MS: u64 = 30_000;
+
+/// A newer image the dispatcher holds for this device's model.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct FirmwareOffer {
+    pub version: u32,
+    /// Image size in bytes, without the signature.
+    pub size: u32,
+    pub sha256: [u8; 32],
+}
+
+/// Request for the transfer bytes starting at `offset`. Offsets past the
+/// image size address the signature.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct ChunkRequest {
+    pub version: u32,
+    pub offset: u32,
+}
+
+/// Part of a transfer, as sent on the wire. Empty if the dispatcher no
+/// longer holds the image.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FirmwareChunk<'a> {
+    pub version: u32,
+    pub offset: u32,
+    pub data: &'a [u8],
+}
+
+/// A [`FirmwareChunk`] copied out of the receive buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct ReceivedChunk {
+    pub version: u32,
+    pub offset: u32,
+    len: usize,
+    buf: [u8; CHUNK_SIZE],
+}
+
+impl ReceivedChunk {
+    pub fn new(chunk: &FirmwareChunk) -> Result<Self, Error> {
+        let mut buf = [0u8; CHUNK_SIZE];
+        buf.get_mut(..chunk.data.len())
+            .ok_or(Error::SerializationFailed)?
+            .copy_from_slice(chunk.data);
+
+        Ok(Self {
+            version: chunk.version,
+            offset: chunk.offset,
+            len: chunk.data.len(),
+            buf,
+        })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Why an update was abandoned.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum UpdateFailure {
+    HashMismatch,
+    BadSignature,
+    Storage,
+    Transfer,
+}
+
+/// Progress of a firmware update.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum UpdateState {
+    Idle,
+    Downloading {
+        version: u32,
+        progress: u8,
+    },
+    /// Verified and staged, the device restarts into it.
+    Staged {
+        version: u32,
+    },
+    /// Running the update after confirming it booted.
+    Confirmed {
+        version: u32,
+    },
+    Failed {
+        version: u32,
+        reason: UpdateFailure,
+    },
+}
+
+/// Firmware status reported to the dispatcher.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FirmwareReport<'a> {
+    pub model: &'a str,
+    pub version: u32,
+    pub state: UpdateState,
+}
+
+/// Identity of the running firmware, compiled into the image.
+#[derive(Debug, Clone, Copy)]
+pub struct FirmwareInfo {
+    /// At most [`MAX_MODEL_LEN`] bytes.
+    pub model: &'static str,
+    pub version: u32,
+    /// Ed25519 public key releases are signed with.
+    pub release_key: [u8; 32],
+}
+
+/// Staging partition and bootloader hooks, e.g. on top of embassy-boot's
+/// `FirmwareUpdater`.
+pub trait FirmwareUpdater {
+    /// Erase the staging partition for an image of `size` bytes.
+    fn prepare(&mut self, size: u32) -> impl Future<Output = Result<(), Error>>;
+
+    /// Write part of the image to the staging partition.
+    fn write(&mut self, offset: u32, data: &[u8]) -> impl Future<Output = Result<(), Error>>;
+
+    /// Have the bootloader swap in the staged image on the next reset. It
+    /// rolls back unless the new image calls [`mark_booted`](Self::mark_booted).
+    fn mark_updated(&mut self) -> impl Future<Output = Result<(), Error>>;
+
+    /// Whether the running image was just swapped in and is not confirmed yet.
+    fn is_trial_boot(&mut self) -> impl Future<Output = Result<bool, Error>>;
+
+    /// Keep the running image.
+    fn mark_booted(&mut self) -> impl Future<Output = Result<(), Error>>;
+
+    /// Restart the device.
+    fn reset(&mut self);
+}
+
+/// For devices without a staging partition.
+pub struct NoFirmwareUpdates;
+
+impl FirmwareUpdater for NoFirmwareUpdates {
+    async fn prepare(&mut self, _size: u32) -> Result<(), Error> {
+        Err(Error::Storage)
+    }
+
+    async fn write(&mut self, _offset: u32, _data: &[u8]) -> Result<(), Error> {
+        Err(Error::Storage)
+    }
+
+    async fn mark_updated(&mut self) -> Result<(), Error> {
+        Err(Error::Storage)
+    }
+
+    async fn is_trial_boot(&mut self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    async fn mark_booted(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn reset(&mut self) {}
+}
+
+struct Download {
+    offer: FirmwareOffer,
+    hasher: Sha256,
+    received: u32,
+    signature: [u8; SIGNATURE_SIZE],
+    requested_at_ms: u64,
+}
+
+impl Download {
+    fn total(&self) -> u32 {
+        self.offer.size + SIGNATURE_SIZE as u32
+    }
+
+    fn request(&self) -> ChunkRequest {
+        ChunkRequest {
+            version: self.offer.version,
+            offset: self.received,
+        }
+    }
+
+    fn progress(&self) -> u8 {
+        (self.received as u64 * 100 / self.total() as u64) as u8
+    }
+}
+
+/// Downloads offered images into the staging partition and tracks what to
+/// report to the dispatcher.
+pub struct Ota<F: FirmwareUpdater> {
+    updater: F,
+    info: FirmwareInfo,
+    state: UpdateState,
+    download: Option<Download>,
+    /// A version that failed verification, not downloaded again.
+    rejected: Option<u32>,
+    /// When the current image was staged.
+    staged_at_ms: Option<u64>,
+    report_due: bool,
+}
+
+impl<F: FirmwareUpdater> Ota<F> {
+    pub fn new(updater: F, info: FirmwareInfo) -> Self {
+        Self {
+            updater,
+            info,
+            state: UpdateState::Idle,
+            download: None,
+            rejected: None,
+            staged_at_ms: None,
+            report_due: true,
+        }
+    }
+
+    /// Confirm the running image if the bootloader just swapped it in, so
+    /// it is not rolled back.
+    pub async fn confirm_boot(&mut self) -> Result<(), Error> {
+        if self.updater.is_trial_boot().await? {
+            self.updater.mark_booted().await?;
+            self.set_state(UpdateState::Confirmed {
+                version: self.info.version,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn state(&self) -> UpdateState {
+        self.state
+    }
+
+    /// The current status, if it changed since it was last taken.
+    pub fn take_report(&mut self) -> Option<FirmwareReport<'static>> {
+        if !core::mem::take(&mut self.report_due) {
+            return None;
+        }
+
+        Some(FirmwareReport {
+            model: self.info.model,
+            version: self.info.version,
+            state: self.state,
+        })
+    }
+
+    /// Start downloading an offered image if it is newer than the running
+    /// one. Returns the first chunk to request.
+    pub async fn offer(&mut self, offer: FirmwareOffer, now_ms: u64) -> Option<ChunkRequest> {
+        if offer.version <= self.info.version
+            || self.rejected == Some(offer.version)
+            || self.download.is_some()
+            || matches!(self.state, UpdateState::Staged { .. })
+        {
+            return None;
+        }
+
+        if self.updater.prepare(offer.size).await.is_err() {
+            self.fail(offer.version, UpdateFailure::Storage);
+            return None;
+        }
+
+        let download = Download {
+            offer,
+            hasher: Sha256::new(),
+            received: 0,
+            signature: [0u8; SIGNATURE_SIZE],
+            requested_at_ms: now_ms,
+        };
+        let request = download.request();
+        self.download = Some(download);
+        self.set_state(UpdateState::Downloading {
+            version: offer.version,
+            progress: 0,
+        });
+
+        Some(request)
+    }
+
+    /// Store a received chunk. Returns the next chunk to request, if any.
+    ///
+    /// Chunks other than the one requested are ignored; a lost chunk is
+    /// requested again by [`next_due`](Self::next_due).
+    pub async fn chunk(&mut self, chunk: &ReceivedChunk, now_ms: u64) -> Option<ChunkRequest> {
+        let download = self.download.as_mut()?;
+        if chunk.version != download.offer.version || chunk.offset != download.received {
+            return None;
+        }
+
+        let version = download.offer.version;
+        let data = chunk.data();
+        if data.is_empty() || download.received + data.len() as u32 > download.total() {
+            self.fail(version, UpdateFailure::Transfer);
+            return None;
+        }
+
+        let image_len = download.offer.size.saturating_sub(chunk.offset) as usize;
+        let (image, signature) = data.split_at(image_len.min(data.len()));
+
+        if !image.is_empty() {
+            if self.updater.write(chunk.offset, image).await.is_err() {
+                self.fail(version, UpdateFailure::Storage);
+                return None;
+            }
+            download.hasher.update(image);
+        }
+        if !signature.is_empty() {
+            let start = (chunk.offset as usize + image.len()) - download.offer.size as usize;
+            download.signature[start..start + signature.len()].copy_from_slice(signature);
+        }
+
+        let before = download.progress();
+        download.received += data.len() as u32;
+        download.requested_at_ms = now_ms;
+        let progress = download.progress();
+
+        if download.received < download.total() {
+            let request = download.request();
+            if progress / PROGRESS_STEP != before / PROGRESS_STEP {
+                self.set_state(UpdateState::Downloading { version, progress });
+            }
+            return Some(request);
+        }
+
+        self.finish(now_ms).await;
+        None
+    }
+
+    /// The chunk to request again if its reply is overdue.
+    pub fn next_due(&mut self, now_ms: u64) -> Option<ChunkRequest> {
+        let download = self.download.as_mut()?;
+        if now_ms.saturating_sub(download.requested_at_ms) < CHUNK_TIMEOUT_MS {
+            return None;
+        }
+
+        download.requested_at_ms = now_ms;
+        Some(download.request())
+    }
+
+    /// Restart into a staged image once `drained` says nothing is left to
+    /// send, or once [`RESET_GRACE_MS`] has passed since it was staged.
+    pub fn reset_if_staged(&mut self, drained: bool, now_ms: u64) {
+        let Some(staged_at_ms) = self.staged_at_ms else {
+            return;
+        };
+        if drained || now_ms.saturating_sub(staged_at_ms) >= RESET_GRACE_MS {
+            self.updater.reset();
+        }
+    }
+
+    async fn finish(&mut self, now_ms: u64) {
+        let Some(download) = self.download.take() else {
+            return;
+        };
+        let version = download.offer.version;
+
+        let digest: [u8; 32] = download.hasher.finalize().into();
+        if digest != download.offer.sha256 {
+            self.rejected = Some(version);
+            self.fail(version, UpdateFailure::HashMismatch);
+            return;
+        }
+
+        if !verify_signature(&self.info, &download.offer, &download.signature) {
+            self.rejected = Some(version);
+            self.fail(version, UpdateFailure::BadSignature);
+            return;
+        }
+
+        if self.updater.mark_updated().await.is_err() {
+            self.fail(version, UpdateFailure::Storage);
+            return;
+        }
+
+        self.staged_at_ms = Some(now_ms);
+        self.set_state(UpdateState::Staged { version });
+    }
+
+    fn fail(&mut self, version: u32, reason: UpdateFailure) {
+        self.download = None;
+        self.set_state(UpdateState::Failed { version, reason });
+    }
+
+    fn set_state(&mut self, state: UpdateState) {
+        self.state = state;
+        self.report_due = true;
+    }
+}
+
+/// Release signatures cover the big endian version, the image digest and
+/// the model name, so an image cannot be replayed as another version or
+/// installed on another model.
+pub fn signed_message<'a>(
+    version: u32,
+    sha256: &[u8; 32],
+    model: &str,
+    buf: &'a mut [u8; 4 + 32 + MAX_MODEL_LEN],
+) -> Option<&'a [u8]> {
+    let model = model.as_bytes();
+    if model.len() > MAX_MODEL_LEN {
+        return None;
+    }
+
+    buf[..4].copy_from_slice(&version.to_be_bytes());
+    buf[4..36].copy_from_slice(sha256);
+    buf[36..36 + model.len()].copy_from_slice(model);
+    Some(&buf[..36 + model.len()])
+}
+
+fn verify_signature(
+    info: &FirmwareInfo,
+    offer: &FirmwareOffer,
+    signature: &[u8; SIGNATURE_SIZE],
+) -> bool {
+    let Ok(key) = VerifyingKey::from_bytes(&info.release_key) else {
+        return false;
+    };
+
+    let mut buf = [0u8; 4 + 32 + MAX_MODEL_LEN];
+    let Some(message) = signed_message(offer.version, &offer.sha256, info.model, &mut buf) else {
+        return false;
+    };
+
+    key.verify_strict(message, &Signature::from_bytes(signature))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CHUNK_SIZE, FirmwareChunk, FirmwareInfo, FirmwareOffer, FirmwareUpdater, MAX_MODEL_LEN,
+        Ota, RESET_GRACE_MS, ReceivedChunk, SIGNATURE_SIZE, UpdateFailure, UpdateState,
+        signed_message,
+    };
+    use crate::Error;
+    use ed25519_dalek::{Signer, SigningKey};
+    use embassy_futures::block_on;
+    use sha2::{Digest, Sha256};
+
+    const IMAGE_SIZE: usize = 200;
+
+    struct MockUpdater {
+        staging: [u8; IMAGE_SIZE],
+        updated: bool,
+        reset: bool,
+    }
+
+    impl FirmwareUpdater for &mut MockUpdater {
+        async fn prepare(&mut self, size: u32) -> Result<(), Error> {
+            if size as usize > IMAGE_SIZE {
+                return Err(Error::Storage);
+            }
+            self.staging = [0; IMAGE_SIZE];
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+            let offset = offset as usize;
+            self.staging[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        async fn mark_updated(&mut self) -> Result<(), Error> {
+            self.updated = true;
+            Ok(())
+        }
+
+        async fn is_trial_boot(&mut self) -> Result<bool, Error> {
+            Ok(false)
+        }
+
+        async fn mark_booted(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn reset(&mut self) {
+            self.reset = true;
+        }
+    }
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn info() -> FirmwareInfo {
+        FirmwareInfo {
+            model: "soil-v1",
+            version: 1,
+            release_key: signing_key().verifying_key().to_bytes(),
+        }
+    }
+
+    /// An image and its transfer bytes: the image followed by its signature.
+    fn release(version: u32) -> (FirmwareOffer, [u8; IMAGE_SIZE + SIGNATURE_SIZE]) {
+        let mut transfer = [0u8; IMAGE_SIZE + SIGNATURE_SIZE];
+        for (i, byte) in transfer[..IMAGE_SIZE].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let sha256: [u8; 32] = Sha256::digest(&transfer[..IMAGE_SIZE]).into();
+        let mut buf = [0u8; 4 + 32 + MAX_MODEL_LEN];
+        let message = signed_message(version, &sha256, "soil-v1", &mut buf).unwrap();
+        transfer[IMAGE_SIZE..].copy_from_slice(&signing_key().sign(message).to_bytes());
+
+        let offer = FirmwareOffer {
+            version,
+            size: IMAGE_SIZE as u32,
+            sha256,
+        };
+        (offer, transfer)
+    }
+
+    fn chunk(version: u32, offset: u32, transfer: &[u8]) -> ReceivedChunk {
+        let start = offset as usize;
+        let end = (start + CHUNK_SIZE).min(transfer.len());
+        ReceivedChunk::new(&FirmwareChunk {
+            version,
+            offset,
+            data: &transfer[start..end],
+        })
+        .unwrap()
+    }
+
+    /// Offer an image and feed its whole transfer, returning the final state.
+    fn download(
+        ota: &mut Ota<&mut MockUpdater>,
+        offer: FirmwareOffer,
+        transfer: &[u8],
+    ) -> UpdateState {
+        let mut request = block_on(ota.offer(offer, 0));
+        while let Some(next) = request {
+            request = block_on(ota.chunk(&chunk(next.version, next.offset, transfer), 0));
+        }
+        ota.state()
+    }
+
+    #[test]
+    fn verified_image_is_staged() {
+        let mut updater = MockUpdater {
+            staging: [0; IMAGE_SIZE],
+            updated: false,
+            reset: false,
+        };
+        let mut ota = Ota::new(&mut updater, info());
+        assert!(ota.take_report().is_some());
+
+        let (offer, transfer) = release(2);
+        let first = block_on(ota.offer(offer, 0)).unwrap();
+        assert_eq!(first.offset, 0);
+        assert_eq!(
+            ota.take_report().unwrap().state,
+            UpdateState::Downloading {
+                version: 2,
+                progress: 0
+            }
+        );
+
+        // a chunk that was not asked for is ignored
+        assert!(block_on(ota.chunk(&chunk(2, 64, &transfer), 0)).is_none());
+
+        let mut request = Some(first);
+        let mut reports = 0;
+        while let Some(next) = request {
+            request = block_on(ota.chunk(&chunk(2, next.offset, &transfer), 0));
+            reports += ota.take_report().is_some() as usize;
+        }
+
+        assert!(reports > 1);
+        assert_eq!(ota.state(), UpdateState::Staged { version: 2 });
+        drop(ota);
+        assert!(updater.updated);
+        assert_eq!(updater.staging, transfer[..IMAGE_SIZE]);
+    }
+
+    #[test]
+    fn tampered_images_are_rejected() {
+        let mut updater = MockUpdater {
+            staging: [0; IMAGE_SIZE],
+            updated: false,
+            reset: false,
+        };
+        let mut ota = Ota::new(&mut updater, info());
+
+        let (offer, mut transfer) = release(2);
+        transfer[10] ^= 0xFF;
+        assert_eq!(
+            download(&mut ota, offer, &transfer),
+            UpdateState::Failed {
+                version: 2,
+                reason: UpdateFailure::HashMismatch
+            }
+        );
+        // not downloaded again when offered again
+        assert!(block_on(ota.offer(offer, 0)).is_none());
+
+        let (offer, mut transfer) = release(3);
+        transfer[IMAGE_SIZE] ^= 0xFF;
+        assert_eq!(
+            download(&mut ota, offer, &transfer),
+            UpdateState::Failed {
+                version: 3,
+                reason: UpdateFailure::BadSignature
+            }
+        );
+        drop(ota);
+        assert!(!updater.updated);
+    }
+
+    #[test]
+    fn older_images_are_ignored_and_lost_chunks_requested_again() {
+        let mut updater = MockUpdater {
+            staging: [0; IMAGE_SIZE],
+            updated: false,
+            reset: false,
+        };
+        let mut ota = Ota::new(&mut updater, info());
+
+        let (offer, _) = release(1);
+        assert!(block_on(ota.offer(offer, 0)).is_none());
+
+        let (offer, transfer) = release(2);
+        block_on(ota.offer(offer, 0)).unwrap();
+        let next = block_on(ota.chunk(&chunk(2, 0, &transfer), 100)).unwrap();
+
+        assert!(ota.next_due(1_000).is_none());
+        assert_eq!(ota.next_due(5_100), Some(next));
+        assert!(ota.next_due(5_200).is_none());
+    }
+
+    #[test]
+    fn staged_image_waits_for_pending_readings() {
+        let mut updater = MockUpdater {
+            staging: [0; IMAGE_SIZE],
+            updated: false,
+            reset: false,
+        };
+        let mut ota = Ota::new(&mut updater, info());
+        ota.reset_if_staged(true, 0);
+
+        let (offer, transfer) = release(2);
+        assert_eq!(
+            download(&mut ota, offer, &transfer),
+            UpdateState::Staged { version: 2 }
+        );
+
+        // readings still unacknowledged hold the restart off for a while
+        ota.reset_if_staged(false, RESET_GRACE_MS - 1);
+        drop(ota);
+        assert!(!updater.reset);
+
+        let mut ota = Ota::new(&mut updater, info());
+        download(&mut ota, offer, &transfer);
+        ota.reset_if_staged(true, 0);
+        drop(ota);
+        assert!(updater.reset);
+
+        updater.reset = false;
+        let mut ota = Ota::new(&mut updater, info());
+        download(&mut ota, offer, &transfer);
+        ota.reset_if_staged(false, RESET_GRACE_MS);
+        drop(ota);
+        assert!(updater.reset);
+    }
+}
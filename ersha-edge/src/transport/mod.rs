@@ -1,21 +1,76 @@
-use crate::{DeviceId, Error, H3Cell, ReadingPacket};
+use crate::actuator::{ActuatorCommand, CommandAck};
+use crate::ota::{ChunkRequest, FirmwareOffer, FirmwareReport, ReceivedChunk};
+use crate::{
+    BootId, DeviceId, Error, H3Cell, ReadingId, ReadingPacket, SensorConfigUpdate, TimeSync,
+};
 
+pub mod secure;
 pub mod wifi;
+pub use secure::{DeviceKey, Role, SecureError, Session};
 pub use wifi::*;
 
 use serde::Deserialize;
 use serde::Serialize;
 
 pub const PACKET_PREAMBLE: u16 = 0xE45A;
-pub const PROTOCOL_VERSION: u8 = 0x01;
+pub const PROTOCOL_VERSION: u8 = 0x08;
 pub const MAX_PACKET_SIZE: usize = 128;
 pub const PREAMBLE_SIZE: usize = 2;
-pub const PACKET_HEADER_SIZE: usize = 6;
+/// Version, type, counter, payload length and tag.
+pub const PACKET_HEADER_SIZE: usize = 30;
 pub const MAX_PAYLOAD_SIZE: usize = MAX_PACKET_SIZE - PREAMBLE_SIZE - PACKET_HEADER_SIZE;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Opens the handshake, followed by the device id and the device's session
+/// nonce.
+pub const HELLO: &[u8; 5] = b"HELLO";
+/// Dispatcher reply to `HELLO` when it holds the device's key, followed by
+/// the dispatcher's session nonce.
+pub const HANDSHAKE_ACCEPTED: u8 = 0x00;
+/// Dispatcher reply to `HELLO` for a device it has no key for.
+pub const HANDSHAKE_UNKNOWN_DEVICE: u8 = 0x01;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MsgType {
     Reading,
+    /// Device location and boot id, proving the device holds the session
+    /// key.
+    Hello,
+    /// Dispatcher time in unix milliseconds, completing the handshake.
+    Time,
+    /// Id of a reading the dispatcher has stored, big endian.
+    Ack,
+    /// A [`SensorConfigUpdate`] queued for the device.
+    Config,
+    /// A [`FirmwareReport`] from the device.
+    FirmwareStatus,
+    /// A [`FirmwareOffer`] for a newer image.
+    FirmwareOffer,
+    /// A [`ChunkRequest`] from the device.
+    FirmwareRequest,
+    /// A [`FirmwareChunk`](crate::ota::FirmwareChunk) answering a request.
+    FirmwareChunk,
+    /// An [`ActuatorCommand`] for the device.
+    Command,
+    /// A [`CommandAck`] from the device.
+    CommandAck,
+}
+
+impl MsgType {
+    pub fn code(&self) -> u8 {
+        match self {
+            MsgType::Reading => 0,
+            MsgType::Hello => 1,
+            MsgType::Time => 2,
+            MsgType::Ack => 3,
+            MsgType::Config => 4,
+            MsgType::FirmwareStatus => 5,
+            MsgType::FirmwareOffer => 6,
+            MsgType::FirmwareRequest => 7,
+            MsgType::FirmwareChunk => 8,
+            MsgType::Command => 9,
+            MsgType::CommandAck => 10,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,13 +78,68 @@ pub struct Msg<'a> {
     pub preamble: u16,
     pub version: u8,
     pub msg_type: MsgType,
+    /// Frame counter of the sender, strictly increasing within a session.
+    pub counter: u64,
+    /// Payload encrypted with the session key.
     pub payload: &'a [u8],
+    pub tag: [u8; secure::TAG_SIZE],
+}
+
+/// Identity and pre-shared key a device is provisioned with.
+#[derive(Clone)]
+pub struct Credentials {
+    pub device_id: DeviceId,
+    pub key: DeviceKey,
+}
+
+/// Message from the dispatcher after the handshake.
+#[derive(Debug)]
+pub enum Downlink {
+    /// The dispatcher stored the reading with this id.
+    Ack(ReadingId),
+    Config(SensorConfigUpdate),
+    FirmwareOffer(FirmwareOffer),
+    FirmwareChunk(ReceivedChunk),
+    Command(ActuatorCommand),
+}
+
+/// Result of the handshake with the dispatcher.
+pub struct Handshake {
+    pub device_id: DeviceId,
+    pub time_sync: TimeSync,
 }
 
 pub trait Transport {
-    /// Called once after network join / connect
-    fn provision(&mut self, location: H3Cell) -> impl Future<Output = Result<DeviceId, Error>>;
+    /// Called after network join / connect, and again to open a new
+    /// session after any other call failed
+    fn provision(
+        &mut self,
+        location: H3Cell,
+        boot_id: BootId,
+    ) -> impl Future<Output = Result<Handshake, Error>>;
 
     /// Send a single sensor reading
     fn send_reading(&mut self, packet: &ReadingPacket) -> impl Future<Output = Result<(), Error>>;
+
+    /// Report the running firmware and the state of any update
+    fn send_firmware_report(
+        &mut self,
+        report: &FirmwareReport,
+    ) -> impl Future<Output = Result<(), Error>>;
+
+    /// Ask for part of an offered firmware image
+    fn request_firmware_chunk(
+        &mut self,
+        request: &ChunkRequest,
+    ) -> impl Future<Output = Result<(), Error>>;
+
+    /// Acknowledge an actuator command
+    fn send_command_ack(&mut self, ack: &CommandAck) -> impl Future<Output = Result<(), Error>>;
+
+    /// Wait for the next acknowledgement, configuration update, firmware
+    /// message or actuator command from the dispatcher.
+    ///
+    /// Must be cancel safe: the engine drops this future whenever a new
+    /// reading arrives or a retry is due.
+    fn recv(&mut self) -> impl Future<Output = Result<Downlink, Error>>;
 }
@@ -0,0 +1,267 @@
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce, Tag,
+    aead::{AeadInPlace, KeyInit},
+};
+use defmt::Format;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::DeviceId;
+
+use super::{MsgType, PROTOCOL_VERSION};
+
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 16;
+pub const TAG_SIZE: usize = 16;
+
+/// Pre-shared key provisioned for a device through ersha-prime.
+pub type DeviceKey = [u8; KEY_SIZE];
+
+/// HKDF info prefix, followed by the device id.
+const SESSION_INFO: &[u8] = b"ersha-edge session v1";
+
+/// Which end of the link a session belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format)]
+pub enum Role {
+    Device,
+    Dispatcher,
+}
+
+impl Role {
+    fn peer(self) -> Self {
+        match self {
+            Role::Device => Role::Dispatcher,
+            Role::Dispatcher => Role::Device,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format)]
+pub enum SecureError {
+    /// The frame was not sealed with the session key or was altered.
+    Authentication,
+    /// The frame counter did not advance, so the frame is a replay.
+    Replay,
+    /// Every counter value has been used; a new session is needed.
+    CounterExhausted,
+}
+
+/// Encrypted, authenticated session between a device and its dispatcher.
+///
+/// The session key is derived from the device's pre-shared key and a random
+/// nonce from each end of the connection. Neither side alone picks the key,
+/// so frame counters can restart at zero without ever reusing a nonce, and
+/// frames recorded from an earlier session cannot be replayed into a new
+/// one. Each direction keeps its own counter, and received counters must
+/// strictly increase.
+pub struct Session {
+    cipher: ChaCha20Poly1305,
+    role: Role,
+    send_counter: u64,
+    last_received: Option<u64>,
+}
+
+impl Session {
+    pub fn new(
+        key: &DeviceKey,
+        device_nonce: &[u8; NONCE_SIZE],
+        dispatcher_nonce: &[u8; NONCE_SIZE],
+        device_id: DeviceId,
+        role: Role,
+    ) -> Self {
+        let mut salt = [0u8; 2 * NONCE_SIZE];
+        salt[..NONCE_SIZE].copy_from_slice(device_nonce);
+        salt[NONCE_SIZE..].copy_from_slice(dispatcher_nonce);
+
+        let mut info = [0u8; SESSION_INFO.len() + 16];
+        info[..SESSION_INFO.len()].copy_from_slice(SESSION_INFO);
+        info[SESSION_INFO.len()..].copy_from_slice(&device_id.0.0.to_be_bytes());
+
+        let mut session_key = [0u8; KEY_SIZE];
+        Hkdf::<Sha256>::new(Some(&salt), key)
+            .expand(&info, &mut session_key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&session_key)),
+            role,
+            send_counter: 0,
+            last_received: None,
+        }
+    }
+
+    /// Encrypt `buf` in place, returning the counter and tag to send with it.
+    pub fn seal(
+        &mut self,
+        msg_type: &MsgType,
+        buf: &mut [u8],
+    ) -> Result<(u64, [u8; TAG_SIZE]), SecureError> {
+        let counter = self.send_counter;
+        self.send_counter = counter
+            .checked_add(1)
+            .ok_or(SecureError::CounterExhausted)?;
+
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&nonce(self.role, counter), &aad(msg_type), buf)
+            .map_err(|_| SecureError::Authentication)?;
+
+        let mut out = [0u8; TAG_SIZE];
+        out.copy_from_slice(tag.as_slice());
+        Ok((counter, out))
+    }
+
+    /// Authenticate and decrypt a frame from the peer in place.
+    pub fn open(
+        &mut self,
+        msg_type: &MsgType,
+        counter: u64,
+        buf: &mut [u8],
+        tag: &[u8; TAG_SIZE],
+    ) -> Result<(), SecureError> {
+        if self.last_received.is_some_and(|last| counter <= last) {
+            return Err(SecureError::Replay);
+        }
+
+        self.cipher
+            .decrypt_in_place_detached(
+                &nonce(self.role.peer(), counter),
+                &aad(msg_type),
+                buf,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| SecureError::Authentication)?;
+
+        self.last_received = Some(counter);
+        Ok(())
+    }
+}
+
+/// Nonce for a frame: the sender's role followed by its frame counter.
+fn nonce(sender: Role, counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[0] = match sender {
+        Role::Device => 0,
+        Role::Dispatcher => 1,
+    };
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+/// Frame header fields covered by the tag besides the counter.
+fn aad(msg_type: &MsgType) -> [u8; 2] {
+    [PROTOCOL_VERSION, msg_type.code()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Role, SecureError, Session};
+    use crate::{DeviceId, transport::MsgType};
+    use ulid::Ulid;
+
+    const KEY: [u8; 32] = [7; 32];
+    const DEVICE_ID: DeviceId = DeviceId(Ulid(42));
+    const DEVICE_NONCE: [u8; 16] = [3; 16];
+    const DISPATCHER_NONCE: [u8; 16] = [5; 16];
+
+    fn pair() -> (Session, Session) {
+        (
+            Session::new(
+                &KEY,
+                &DEVICE_NONCE,
+                &DISPATCHER_NONCE,
+                DEVICE_ID,
+                Role::Device,
+            ),
+            Session::new(
+                &KEY,
+                &DEVICE_NONCE,
+                &DISPATCHER_NONCE,
+                DEVICE_ID,
+                Role::Dispatcher,
+            ),
+        )
+    }
+
+    #[test]
+    fn round_trips_in_both_directions() {
+        let (mut device, mut dispatcher) = pair();
+
+        let mut up = *b"reading";
+        let (counter, tag) = device.seal(&MsgType::Reading, &mut up).unwrap();
+        assert_ne!(&up, b"reading");
+        dispatcher
+            .open(&MsgType::Reading, counter, &mut up, &tag)
+            .unwrap();
+        assert_eq!(&up, b"reading");
+
+        let mut down = *b"time";
+        let (counter, tag) = dispatcher.seal(&MsgType::Time, &mut down).unwrap();
+        device
+            .open(&MsgType::Time, counter, &mut down, &tag)
+            .unwrap();
+        assert_eq!(&down, b"time");
+    }
+
+    #[test]
+    fn rejects_tampering_and_wrong_keys() {
+        let (mut device, mut dispatcher) = pair();
+
+        let mut buf = *b"reading";
+        let (counter, tag) = device.seal(&MsgType::Reading, &mut buf).unwrap();
+
+        let mut flipped = buf;
+        flipped[0] ^= 1;
+        assert_eq!(
+            dispatcher.open(&MsgType::Reading, counter, &mut flipped, &tag),
+            Err(SecureError::Authentication)
+        );
+
+        let mut retyped = buf;
+        assert_eq!(
+            dispatcher.open(&MsgType::Hello, counter, &mut retyped, &tag),
+            Err(SecureError::Authentication)
+        );
+
+        let mut other_nonce =
+            Session::new(&KEY, &DEVICE_NONCE, &[4; 16], DEVICE_ID, Role::Dispatcher);
+        let mut copy = buf;
+        assert_eq!(
+            other_nonce.open(&MsgType::Reading, counter, &mut copy, &tag),
+            Err(SecureError::Authentication)
+        );
+    }
+
+    #[test]
+    fn rejects_replayed_frames() {
+        let (mut device, mut dispatcher) = pair();
+
+        let mut first = *b"one";
+        let (first_counter, first_tag) = device.seal(&MsgType::Reading, &mut first).unwrap();
+        let mut replay = first;
+        dispatcher
+            .open(&MsgType::Reading, first_counter, &mut first, &first_tag)
+            .unwrap();
+
+        assert_eq!(
+            dispatcher.open(&MsgType::Reading, first_counter, &mut replay, &first_tag),
+            Err(SecureError::Replay)
+        );
+    }
+
+    #[test]
+    fn replayed_dispatcher_nonce_gives_a_new_key() {
+        let (_, mut dispatcher) = pair();
+
+        let mut recorded = *b"open valve";
+        let (counter, tag) = dispatcher.seal(&MsgType::Command, &mut recorded).unwrap();
+
+        // someone posing as the dispatcher sends the old nonce again, but
+        // the device picked a fresh one of its own
+        let mut device = Session::new(&KEY, &[8; 16], &DISPATCHER_NONCE, DEVICE_ID, Role::Device);
+        assert_eq!(
+            device.open(&MsgType::Command, counter, &mut recorded, &tag),
+            Err(SecureError::Authentication)
+        );
+    }
+}
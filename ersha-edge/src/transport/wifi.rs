@@ -2,9 +2,18 @@ use embassy_net::{
     IpAddress, IpEndpoint, Stack,
     tcp::{State, TcpSocket},
 };
+use embassy_time::Instant;
+use rand_core::RngCore;
 
-use crate::{DeviceId, Error, H3Cell, ReadingPacket};
+use crate::actuator::CommandAck;
+use crate::ota::{ChunkRequest, FirmwareChunk, FirmwareReport, ReceivedChunk};
+use crate::{BootId, Error, H3Cell, ReadingId, ReadingPacket, TimeSync};
 
+use super::Credentials;
+use super::Downlink;
+use super::HANDSHAKE_ACCEPTED;
+use super::HELLO;
+use super::Handshake;
 use super::MAX_PACKET_SIZE;
 use super::Transport;
 
@@ -13,28 +22,113 @@ use super::Msg;
 use super::MsgType;
 use super::PACKET_PREAMBLE;
 use super::PROTOCOL_VERSION;
+use super::secure::{NONCE_SIZE, Role, Session};
 
 const SERVER_ADDR: IpEndpoint = IpEndpoint {
     addr: IpAddress::v4(10, 46, 238, 14),
     port: 9001,
 };
 
-pub struct Wifi<'a> {
+pub struct Wifi<'a, R> {
     socket: TcpSocket<'a>,
-    device_id: Option<DeviceId>,
+    credentials: Credentials,
+    /// Source of the nonce sent with every `HELLO`.
+    rng: R,
+    session: Option<Session>,
+    /// Bytes received after the handshake that do not form a frame yet.
+    rx_buf: [u8; MAX_PACKET_SIZE],
+    rx_len: usize,
 }
 
-impl<'a> Wifi<'a> {
-    pub fn new(stack: Stack<'a>, rx: &'a mut [u8], tx: &'a mut [u8]) -> Self {
+impl<'a, R: RngCore> Wifi<'a, R> {
+    pub fn new(
+        stack: Stack<'a>,
+        rx: &'a mut [u8],
+        tx: &'a mut [u8],
+        credentials: Credentials,
+        rng: R,
+    ) -> Self {
         Self {
             socket: TcpSocket::new(stack, rx, tx),
-            device_id: None,
+            credentials,
+            rng,
+            session: None,
+            rx_buf: [0u8; MAX_PACKET_SIZE],
+            rx_len: 0,
         }
     }
+
+    async fn send_msg(&mut self, msg_type: MsgType, payload: &mut [u8]) -> Result<(), Error> {
+        let session = self.session.as_mut().ok_or(Error::UnableToSend)?;
+        let (counter, tag) = session
+            .seal(&msg_type, payload)
+            .map_err(|_| Error::AuthenticationFailed)?;
+
+        let msg = Msg {
+            preamble: PACKET_PREAMBLE,
+            version: PROTOCOL_VERSION,
+            msg_type,
+            counter,
+            payload,
+            tag,
+        };
+
+        let mut msg_buf = [0u8; MAX_PACKET_SIZE];
+        let used =
+            postcard::to_slice(&msg, &mut msg_buf).map_err(|_| Error::SerializationFailed)?;
+
+        write_all(&mut self.socket, used).await
+    }
+
+    /// Take the next complete frame from the receive buffer and open it,
+    /// returning its type and decrypted payload.
+    fn take_frame(
+        &mut self,
+        payload: &mut [u8; MAX_PAYLOAD_SIZE],
+    ) -> Result<Option<(MsgType, usize)>, Error> {
+        let (msg_type, counter, len, tag, used) =
+            match postcard::take_from_bytes::<Msg>(&self.rx_buf[..self.rx_len]) {
+                Ok((msg, rest)) => {
+                    if msg.payload.len() > MAX_PAYLOAD_SIZE {
+                        return Err(Error::SerializationFailed);
+                    }
+                    payload[..msg.payload.len()].copy_from_slice(msg.payload);
+                    (
+                        msg.msg_type,
+                        msg.counter,
+                        msg.payload.len(),
+                        msg.tag,
+                        self.rx_len - rest.len(),
+                    )
+                }
+                Err(postcard::Error::DeserializeUnexpectedEnd) if self.rx_len < MAX_PACKET_SIZE => {
+                    return Ok(None);
+                }
+                Err(_) => return Err(Error::SerializationFailed),
+            };
+
+        self.rx_buf.copy_within(used..self.rx_len, 0);
+        self.rx_len -= used;
+
+        self.session
+            .as_mut()
+            .ok_or(Error::UnableToSend)?
+            .open(&msg_type, counter, &mut payload[..len], &tag)
+            .map_err(|_| Error::AuthenticationFailed)?;
+
+        Ok(Some((msg_type, len)))
+    }
 }
 
-impl<'a> Transport for Wifi<'a> {
-    async fn provision(&mut self, location: H3Cell) -> Result<DeviceId, Error> {
+impl<'a, R: RngCore> Transport for Wifi<'a, R> {
+    async fn provision(&mut self, location: H3Cell, boot_id: BootId) -> Result<Handshake, Error> {
+        // an earlier session broke, start over on a fresh connection
+        if self.session.take().is_some() {
+            self.socket.abort();
+            let _ = self.socket.flush().await;
+        }
+        self.rx_len = 0;
+
         if self.socket.state() != State::Established {
             self.socket
                 .connect(SERVER_ADDR)
@@ -42,25 +136,59 @@ impl<'a> Transport for Wifi<'a> {
                 .map_err(|_| Error::ServerNotFound)?;
         }
 
-        self.socket
-            .write(b"HELLO")
-            .await
-            .map_err(|_| Error::UnableToSend)?;
-
-        // TODO: use a proper frame
-        self.socket
-            .write(&location.to_be_bytes())
-            .await
-            .map_err(|_| Error::UnableToSend)?;
-
-        let mut buf = [0u8; 16];
-        read_exact(&mut self.socket, &mut buf)
-            .await
-            .map_err(|_| Error::UnableToSend)?;
-
-        let id = u128::from_be_bytes(buf);
-        self.device_id = Some(id);
-        Ok(id)
+        // fresh for every session, so an old dispatcher nonce sent again
+        // cannot bring back an earlier session key
+        let mut device_nonce = [0u8; NONCE_SIZE];
+        self.rng.fill_bytes(&mut device_nonce);
+
+        let device_id = self.credentials.device_id;
+        write_all(&mut self.socket, HELLO).await?;
+        write_all(&mut self.socket, &device_id.0.0.to_be_bytes()).await?;
+        write_all(&mut self.socket, &device_nonce).await?;
+
+        let mut status = [0u8; 1];
+        read_exact(&mut self.socket, &mut status).await?;
+        if status[0] != HANDSHAKE_ACCEPTED {
+            return Err(Error::Rejected);
+        }
+
+        let mut dispatcher_nonce = [0u8; NONCE_SIZE];
+        read_exact(&mut self.socket, &mut dispatcher_nonce).await?;
+        self.session = Some(Session::new(
+            &self.credentials.key,
+            &device_nonce,
+            &dispatcher_nonce,
+            device_id,
+            Role::Device,
+        ));
+
+        // sealing the location proves we hold the key
+        let mut hello = [0u8; 12];
+        hello[..8].copy_from_slice(&location.to_be_bytes());
+        hello[8..].copy_from_slice(&boot_id.to_be_bytes());
+        self.send_msg(MsgType::Hello, &mut hello).await?;
+
+        // dispatcher time in unix milliseconds, sealed with the same session
+        let mut frame = [0u8; MAX_PACKET_SIZE];
+        let msg = read_msg(&mut self.socket, &mut frame).await?;
+        if msg.msg_type != MsgType::Time || msg.payload.len() != 8 {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        let mut time = [0u8; 8];
+        time.copy_from_slice(msg.payload);
+        self.session
+            .as_mut()
+            .ok_or(Error::UnableToSend)?
+            .open(&msg.msg_type, msg.counter, &mut time, &msg.tag)
+            .map_err(|_| Error::AuthenticationFailed)?;
+
+        let time_sync = TimeSync::new(u64::from_be_bytes(time), Instant::now().as_millis());
+
+        Ok(Handshake {
+            device_id,
+            time_sync,
+        })
     }
 
     async fn send_reading(&mut self, packet: &ReadingPacket) -> Result<(), Error> {
@@ -68,19 +196,98 @@ impl<'a> Transport for Wifi<'a> {
         let payload =
             postcard::to_slice(packet, &mut payload_buf).map_err(|_| Error::SerializationFailed)?;
 
-        let msg = Msg {
-            preamble: PACKET_PREAMBLE,
-            version: PROTOCOL_VERSION,
-            msg_type: MsgType::Reading,
-            payload,
-        };
+        self.send_msg(MsgType::Reading, payload).await
+    }
 
-        let mut msg_buf = [0u8; MAX_PACKET_SIZE];
-        let used =
-            postcard::to_slice(&msg, &mut msg_buf).map_err(|_| Error::SerializationFailed)?;
+    async fn send_firmware_report(&mut self, report: &FirmwareReport<'_>) -> Result<(), Error> {
+        let mut payload_buf = [0u8; MAX_PAYLOAD_SIZE];
+        let payload =
+            postcard::to_slice(report, &mut payload_buf).map_err(|_| Error::SerializationFailed)?;
 
-        write_all(&mut self.socket, used).await
+        self.send_msg(MsgType::FirmwareStatus, payload).await
+    }
+
+    async fn request_firmware_chunk(&mut self, request: &ChunkRequest) -> Result<(), Error> {
+        let mut payload_buf = [0u8; MAX_PAYLOAD_SIZE];
+        let payload = postcard::to_slice(request, &mut payload_buf)
+            .map_err(|_| Error::SerializationFailed)?;
+
+        self.send_msg(MsgType::FirmwareRequest, payload).await
+    }
+
+    async fn send_command_ack(&mut self, ack: &CommandAck) -> Result<(), Error> {
+        let mut payload_buf = [0u8; MAX_PAYLOAD_SIZE];
+        let payload =
+            postcard::to_slice(ack, &mut payload_buf).map_err(|_| Error::SerializationFailed)?;
+
+        self.send_msg(MsgType::CommandAck, payload).await
+    }
+
+    async fn recv(&mut self) -> Result<Downlink, Error> {
+        let mut payload = [0u8; MAX_PAYLOAD_SIZE];
+        loop {
+            match self.take_frame(&mut payload)? {
+                Some((MsgType::Ack, 2)) => {
+                    let reading_id = ReadingId::from_be_bytes([payload[0], payload[1]]);
+                    return Ok(Downlink::Ack(reading_id));
+                }
+                Some((MsgType::Config, len)) => {
+                    let update = postcard::from_bytes(&payload[..len])
+                        .map_err(|_| Error::SerializationFailed)?;
+                    return Ok(Downlink::Config(update));
+                }
+                Some((MsgType::FirmwareOffer, len)) => {
+                    let offer = postcard::from_bytes(&payload[..len])
+                        .map_err(|_| Error::SerializationFailed)?;
+                    return Ok(Downlink::FirmwareOffer(offer));
+                }
+                Some((MsgType::FirmwareChunk, len)) => {
+                    let chunk: FirmwareChunk = postcard::from_bytes(&payload[..len])
+                        .map_err(|_| Error::SerializationFailed)?;
+                    return Ok(Downlink::FirmwareChunk(ReceivedChunk::new(&chunk)?));
+                }
+                Some((MsgType::Command, len)) => {
+                    let command = postcard::from_bytes(&payload[..len])
+                        .map_err(|_| Error::SerializationFailed)?;
+                    return Ok(Downlink::Command(command));
+                }
+                Some(_) => continue,
+                None => {}
+            }
+
+            // a single read, so dropping this future never loses bytes
+            let n = self
+                .socket
+                .read(&mut self.rx_buf[self.rx_len..])
+                .await
+                .map_err(|_| Error::UnableToSend)?;
+            if n == 0 {
+                return Err(Error::ServerNotFound);
+            }
+            self.rx_len += n;
+        }
+    }
+}
+
+/// Read a single frame during the handshake, one byte at a time since its
+/// length is only known once it decodes.
+async fn read_msg<'b>(socket: &mut TcpSocket<'_>, buf: &'b mut [u8]) -> Result<Msg<'b>, Error> {
+    let mut len = 0;
+    loop {
+        if len == buf.len() {
+            return Err(Error::SerializationFailed);
+        }
+        read_exact(socket, &mut buf[len..len + 1]).await?;
+        len += 1;
+
+        match postcard::from_bytes::<Msg>(&buf[..len]) {
+            Ok(_) => break,
+            Err(postcard::Error::DeserializeUnexpectedEnd) => continue,
+            Err(_) => return Err(Error::SerializationFailed),
+        }
     }
+
+    postcard::from_bytes(&buf[..len]).map_err(|_| Error::SerializationFailed)
 }
 
 async fn read_exact(socket: &mut TcpSocket<'_>, mut buf: &mut [u8]) -> Result<(), Error> {
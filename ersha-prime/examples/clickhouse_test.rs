@@ -71,6 +71,7 @@ async fn test_dispatcher_registry() -> Result<()> {
         state: DispatcherState::Active,
         location: H3Cell(0x8a2a1072b59ffff),
         provisioned_at: Timestamp::now(),
+        organization: None,
     };
 
     // Register
@@ -116,6 +117,8 @@ async fn test_device_registry() -> Result<()> {
             },
         }]
         .into_boxed_slice(),
+        actuators: vec![].into_boxed_slice(),
+        organization: None,
     };
 
     // Register
@@ -222,6 +225,7 @@ async fn test_device_status_registry() -> Result<()> {
         errors: vec![].into_boxed_slice(),
         timestamp: Timestamp::now(),
         sensor_statuses: vec![].into_boxed_slice(),
+        firmware: None,
     };
 
     // Store
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS sensor_calibrations (
+    sensor_id TEXT PRIMARY KEY NOT NULL,
+    kind INTEGER NOT NULL,
+    gain REAL,
+    offset_value REAL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sensor_calibration_points (
+    sensor_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    raw REAL NOT NULL,
+    calibrated REAL NOT NULL,
+    PRIMARY KEY (sensor_id, position),
+    FOREIGN KEY(sensor_id) REFERENCES sensor_calibrations(sensor_id)
+);
+
+ALTER TABLE readings ADD COLUMN raw_value REAL;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS device_keys (
+    device_id TEXT PRIMARY KEY NOT NULL,
+    key BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(device_id) REFERENCES devices(id)
+);
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS firmware_images (
+    id TEXT PRIMARY KEY NOT NULL,
+    model TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    sha256 BLOB NOT NULL,
+    signature BLOB NOT NULL,
+    data BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    UNIQUE (model, version)
+);
+
+CREATE TABLE IF NOT EXISTS device_status_firmware (
+    status_id TEXT PRIMARY KEY NOT NULL,
+    model TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    update_state INTEGER NOT NULL,
+    target_version INTEGER NOT NULL,
+    progress INTEGER NOT NULL,
+    failure INTEGER NOT NULL,
+    FOREIGN KEY(status_id) REFERENCES device_statuses(id)
+);
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS actuators (
+    id TEXT PRIMARY KEY NOT NULL,
+    kind INTEGER NOT NULL,
+    max_run_secs INTEGER NOT NULL,
+    device_id TEXT NOT NULL,
+    FOREIGN KEY(device_id) REFERENCES devices(id)
+);
+
+CREATE TABLE IF NOT EXISTS actuator_commands (
+    id TEXT PRIMARY KEY NOT NULL,
+    device_id TEXT NOT NULL,
+    actuator_id TEXT NOT NULL,
+    action INTEGER NOT NULL,
+    duration_secs INTEGER NOT NULL,
+    issued_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    result INTEGER,
+    open_until INTEGER,
+    completed_at INTEGER,
+    FOREIGN KEY(device_id) REFERENCES devices(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_actuator_commands_device ON actuator_commands(device_id);
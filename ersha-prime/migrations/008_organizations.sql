@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS organizations (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL
+);
+
+-- NULL means the entity belongs to no organization and only keys spanning
+-- all organizations can see it.
+ALTER TABLE dispatchers ADD COLUMN organization_id TEXT;
+ALTER TABLE devices ADD COLUMN organization_id TEXT;
+ALTER TABLE api_keys ADD COLUMN organization_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_dispatchers_organization_id ON dispatchers(organization_id);
+CREATE INDEX IF NOT EXISTS idx_devices_organization_id ON devices(organization_id);
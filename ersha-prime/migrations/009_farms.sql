@@ -0,0 +1,53 @@
+CREATE TABLE IF NOT EXISTS farms (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    organization_id TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS fields (
+    id TEXT PRIMARY KEY NOT NULL,
+    farm_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    crop TEXT,
+    planted_on TEXT,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(farm_id) REFERENCES farms(id)
+);
+
+CREATE TABLE IF NOT EXISTS plots (
+    id TEXT PRIMARY KEY NOT NULL,
+    field_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    crop TEXT,
+    planted_on TEXT,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(field_id) REFERENCES fields(id)
+);
+
+-- Boundaries of farms, fields and plots, keyed by the id of their owner.
+CREATE TABLE IF NOT EXISTS boundary_points (
+    owner_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    lat REAL NOT NULL,
+    lng REAL NOT NULL,
+    PRIMARY KEY (owner_id, position)
+);
+
+CREATE TABLE IF NOT EXISTS boundary_cells (
+    owner_id TEXT NOT NULL,
+    cell INTEGER NOT NULL,
+    PRIMARY KEY (owner_id, cell)
+);
+
+-- A device is assigned to at most one plot.
+CREATE TABLE IF NOT EXISTS plot_devices (
+    device_id TEXT PRIMARY KEY NOT NULL,
+    plot_id TEXT NOT NULL,
+    FOREIGN KEY(plot_id) REFERENCES plots(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_farms_organization_id ON farms(organization_id);
+CREATE INDEX IF NOT EXISTS idx_fields_farm_id ON fields(farm_id);
+CREATE INDEX IF NOT EXISTS idx_plots_field_id ON plots(field_id);
+CREATE INDEX IF NOT EXISTS idx_plot_devices_plot_id ON plot_devices(plot_id);
@@ -0,0 +1,42 @@
+-- Center and ancestors of every cell that devices and readings are located
+-- in, so spatial filters need no H3 functions in SQL. Column rN holds the
+-- ancestor at resolution N, or NULL for resolutions finer than the cell.
+CREATE TABLE IF NOT EXISTS h3_cells (
+    cell INTEGER PRIMARY KEY NOT NULL,
+    lat REAL NOT NULL,
+    lng REAL NOT NULL,
+    r0 INTEGER,
+    r1 INTEGER,
+    r2 INTEGER,
+    r3 INTEGER,
+    r4 INTEGER,
+    r5 INTEGER,
+    r6 INTEGER,
+    r7 INTEGER,
+    r8 INTEGER,
+    r9 INTEGER,
+    r10 INTEGER,
+    r11 INTEGER,
+    r12 INTEGER,
+    r13 INTEGER,
+    r14 INTEGER,
+    r15 INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_h3_cells_lat_lng ON h3_cells(lat, lng);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r0 ON h3_cells(r0);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r1 ON h3_cells(r1);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r2 ON h3_cells(r2);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r3 ON h3_cells(r3);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r4 ON h3_cells(r4);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r5 ON h3_cells(r5);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r6 ON h3_cells(r6);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r7 ON h3_cells(r7);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r8 ON h3_cells(r8);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r9 ON h3_cells(r9);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r10 ON h3_cells(r10);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r11 ON h3_cells(r11);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r12 ON h3_cells(r12);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r13 ON h3_cells(r13);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r14 ON h3_cells(r14);
+CREATE INDEX IF NOT EXISTS idx_h3_cells_r15 ON h3_cells(r15);
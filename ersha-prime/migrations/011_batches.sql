@@ -0,0 +1,20 @@
+-- Response sent for each processed batch upload, returned again when a
+-- dispatcher uploads the same batch after losing the first response.
+CREATE TABLE IF NOT EXISTS batches (
+    id TEXT PRIMARY KEY NOT NULL,
+    readings_stored INTEGER NOT NULL,
+    readings_rejected INTEGER NOT NULL,
+    statuses_stored INTEGER NOT NULL,
+    statuses_rejected INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS batch_rejections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    batch_id TEXT NOT NULL,
+    kind INTEGER NOT NULL,
+    record_id TEXT NOT NULL,
+    reason INTEGER NOT NULL,
+    FOREIGN KEY(batch_id) REFERENCES batches(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_batch_rejections_batch_id ON batch_rejections(batch_id);
@@ -0,0 +1,48 @@
+-- Status reports dispatchers send with every upload.
+CREATE TABLE IF NOT EXISTS dispatcher_status_reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    dispatcher_id TEXT NOT NULL,
+    connected_devices INTEGER NOT NULL,
+    uptime_seconds INTEGER NOT NULL,
+    pending_uploads INTEGER NOT NULL,
+    reported_at INTEGER NOT NULL,
+    received_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_dispatcher_status_reports_dispatcher_id ON dispatcher_status_reports(dispatcher_id);
+
+CREATE TABLE IF NOT EXISTS dispatcher_health (
+    dispatcher_id TEXT PRIMARY KEY NOT NULL,
+    last_seen INTEGER NOT NULL,
+    online INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS device_health (
+    device_id TEXT PRIMARY KEY NOT NULL,
+    dispatcher_id TEXT NOT NULL,
+    last_seen INTEGER NOT NULL,
+    online INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS device_connection_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id TEXT NOT NULL,
+    dispatcher_id TEXT NOT NULL,
+    kind INTEGER NOT NULL,
+    reason INTEGER,
+    message TEXT,
+    timestamp INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_device_connection_events_device_id ON device_connection_events(device_id);
+
+CREATE TABLE IF NOT EXISTS offline_alerts (
+    id TEXT PRIMARY KEY NOT NULL,
+    dispatcher_id TEXT NOT NULL,
+    device_id TEXT,
+    last_seen INTEGER NOT NULL,
+    raised_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_offline_alerts_dispatcher_id ON offline_alerts(dispatcher_id);
+CREATE INDEX IF NOT EXISTS idx_offline_alerts_device_id ON offline_alerts(device_id);
@@ -0,0 +1,4 @@
+-- Dispatcher a batch response was sent to. Only that dispatcher gets the
+-- response replayed; batches recorded before have none and are never
+-- replayed.
+ALTER TABLE batches ADD COLUMN dispatcher_id TEXT;
@@ -0,0 +1,17 @@
+-- Alerts dispatchers report, kept with the organization owning the
+-- dispatcher when the alert arrived. NULL means no organization.
+CREATE TABLE IF NOT EXISTS reported_alerts (
+    id TEXT PRIMARY KEY NOT NULL,
+    dispatcher_id TEXT NOT NULL,
+    device_id TEXT,
+    organization_id TEXT,
+    severity INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    custom TEXT,
+    message TEXT NOT NULL,
+    raised_at INTEGER NOT NULL,
+    received_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_reported_alerts_dispatcher_id ON reported_alerts(dispatcher_id);
+CREATE INDEX IF NOT EXISTS idx_reported_alerts_device_id ON reported_alerts(device_id);
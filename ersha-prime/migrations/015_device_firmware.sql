@@ -0,0 +1,12 @@
+-- The firmware each device reported last, kept apart from its status
+-- reports. Update state columns match device_status_firmware.
+CREATE TABLE IF NOT EXISTS device_firmware (
+    device_id TEXT PRIMARY KEY NOT NULL,
+    model TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    update_state INTEGER NOT NULL,
+    target_version INTEGER NOT NULL,
+    progress INTEGER NOT NULL,
+    failure INTEGER NOT NULL,
+    reported_at INTEGER NOT NULL
+);
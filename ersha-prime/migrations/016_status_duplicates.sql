@@ -0,0 +1,3 @@
+-- Duplicate readings a dispatcher dropped since it started. Reports recorded
+-- before carry none.
+ALTER TABLE dispatcher_status_reports ADD COLUMN duplicates_dropped INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,152 @@
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ersha_core::OrganizationId;
+use ulid::Ulid;
+
+use crate::{
+    auth::{Role, hash_key},
+    registry::ApiKeyRegistry,
+};
+
+/// Who made a request. [`authorize`] adds it to the request extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Principal {
+    pub role: Role,
+    /// Organization the caller is limited to, or `None` for a caller
+    /// spanning all organizations.
+    pub organization: Option<OrganizationId>,
+}
+
+impl Principal {
+    /// Whether the caller may see an entity owned by `owner`.
+    pub fn can_access(&self, owner: Option<OrganizationId>) -> bool {
+        self.organization.is_none() || self.organization == owner
+    }
+
+    /// The organization a new entity is created in.
+    ///
+    /// Callers limited to an organization always create in their own, and
+    /// may only name it explicitly; others may pick any organization or none.
+    pub fn owner_for(
+        &self,
+        requested: Option<Ulid>,
+    ) -> Result<Option<OrganizationId>, (StatusCode, &'static str)> {
+        let requested = requested.map(OrganizationId);
+        match self.organization {
+            None => Ok(requested),
+            Some(own) if requested.is_none() || requested == Some(own) => Ok(Some(own)),
+            Some(_) => Err((
+                StatusCode::FORBIDDEN,
+                "Cannot create entities in another organization",
+            )),
+        }
+    }
+
+    /// Reject callers limited to an organization.
+    pub fn require_all_organizations(&self) -> Result<(), (StatusCode, &'static str)> {
+        match self.organization {
+            None => Ok(()),
+            Some(_) => Err((
+                StatusCode::FORBIDDEN,
+                "Requires a key spanning all organizations",
+            )),
+        }
+    }
+}
+
+/// State of the [`authorize`] layer guarding one route.
+#[derive(Clone)]
+pub struct Authorizer<K: ApiKeyRegistry> {
+    keys: K,
+    enabled: bool,
+    role: Role,
+}
+
+impl<K: ApiKeyRegistry> Authorizer<K> {
+    /// Authorize requests against `keys`. When `enabled` is false every
+    /// request is let through.
+    pub fn new(keys: K, enabled: bool) -> Self {
+        Self {
+            keys,
+            enabled,
+            role: Role::Viewer,
+        }
+    }
+
+    /// The same authorizer, requiring at least `role`.
+    pub fn require(&self, role: Role) -> Self {
+        Self {
+            role,
+            ..self.clone()
+        }
+    }
+}
+
+/// Check the bearer API key of a request against the role the route
+/// requires.
+///
+/// Missing, unknown and revoked keys get 401; keys with too low a role get
+/// 403. With authentication disabled every request is made by an admin
+/// spanning all organizations.
+pub async fn authorize<K>(
+    State(auth): State<Authorizer<K>>,
+    mut request: Request,
+    next: Next,
+) -> Response
+where
+    K: ApiKeyRegistry,
+{
+    if !auth.enabled {
+        request.extensions_mut().insert(Principal {
+            role: Role::Admin,
+            organization: None,
+        });
+        return next.run(request).await;
+    }
+
+    let Some(secret) = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return unauthorized();
+    };
+
+    match auth.keys.get_by_hash(hash_key(secret.trim())).await {
+        Ok(Some(key)) if key.is_active() && key.role >= auth.role => {
+            request.extensions_mut().insert(Principal {
+                role: key.role,
+                organization: key.organization,
+            });
+            next.run(request).await
+        }
+        Ok(Some(key)) if key.is_active() => (
+            StatusCode::FORBIDDEN,
+            format!("Requires the {} role", auth.role.as_str()),
+        )
+            .into_response(),
+        Ok(_) => unauthorized(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to look up API key");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to look up API key",
+            )
+                .into_response()
+        }
+    }
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        "Missing or invalid API key",
+    )
+        .into_response()
+}
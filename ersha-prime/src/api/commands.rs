@@ -0,0 +1,282 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use ersha_core::{
+    ActuatorAction, ActuatorCommand, ActuatorId, ActuatorState, CommandId, CommandRecord,
+    CommandResult, DeviceId, DeviceState, MAX_ACTUATOR_RUN_SECS,
+};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::registry::{DeviceRegistry, DispatcherRegistry};
+
+use super::{ApiState, auth::Principal};
+
+/// How long a command waits for its device when no TTL is given.
+pub const DEFAULT_COMMAND_TTL_SECS: u32 = 5 * 60;
+/// Longest a command may wait for its device.
+pub const MAX_COMMAND_TTL_SECS: u32 = 60 * 60;
+
+/// Request body for commanding an actuator.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssueCommandRequest {
+    pub actuator_id: Ulid,
+    /// "open" or "close".
+    pub action: String,
+    /// How long to stay open, in seconds. Required to open.
+    pub duration_secs: Option<u32>,
+    /// Seconds the command may wait for the device before it times out.
+    pub ttl_secs: Option<u32>,
+}
+
+/// Response body for a command.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandResponse {
+    pub id: String,
+    pub device_id: String,
+    pub actuator_id: String,
+    pub action: String,
+    pub duration_secs: Option<u32>,
+    pub issued_at: String,
+    pub expires_at: String,
+    /// "pending", "applied", "failed" or "timed_out".
+    pub status: String,
+    /// State the device reported, for applied commands: "open" or "closed".
+    pub state: Option<String>,
+    /// When an actuator left open closes on its own.
+    pub open_until: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+impl From<CommandRecord> for CommandResponse {
+    fn from(r: CommandRecord) -> Self {
+        let (action, duration_secs) = match r.command.action {
+            ActuatorAction::Open { duration_secs } => ("open", Some(duration_secs)),
+            ActuatorAction::Close => ("close", None),
+        };
+
+        let (status, state, open_until) = match r.result {
+            None if r.command.expires_at <= jiff::Timestamp::now() => ("timed_out", None, None),
+            None => ("pending", None, None),
+            Some(CommandResult::Applied {
+                state: ActuatorState::Closed,
+            }) => ("applied", Some("closed"), None),
+            Some(CommandResult::Applied {
+                state: ActuatorState::Open { until },
+            }) => ("applied", Some("open"), Some(until.to_string())),
+            Some(CommandResult::Failed) => ("failed", None, None),
+            Some(CommandResult::TimedOut) => ("timed_out", None, None),
+        };
+
+        Self {
+            id: r.command.id.0.to_string(),
+            device_id: r.command.device_id.0.to_string(),
+            actuator_id: r.command.actuator_id.0.to_string(),
+            action: action.to_string(),
+            duration_secs,
+            issued_at: r.command.issued_at.to_string(),
+            expires_at: r.command.expires_at.to_string(),
+            status: status.to_string(),
+            state: state.map(str::to_string),
+            open_until,
+            completed_at: r.completed_at.map(|t| t.to_string()),
+        }
+    }
+}
+
+/// Command an actuator of a device.
+///
+/// The command is queued for the dispatchers and delivered when the device
+/// is connected. Opening is refused beyond the actuator's maximum run time.
+///
+/// POST /api/devices/:id/commands
+pub async fn issue_command<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<IssueCommandRequest>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid device ID").into_response(),
+    };
+
+    let device = match state.device_registry.get(DeviceId(ulid)).await {
+        Ok(Some(device)) if principal.can_access(device.organization) => device,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Device not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get device");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get device").into_response();
+        }
+    };
+
+    if device.state != DeviceState::Active {
+        return (StatusCode::CONFLICT, "Device is not active").into_response();
+    }
+
+    let actuator_id = ActuatorId(request.actuator_id);
+    let Some(actuator) = device.actuators.iter().find(|a| a.id == actuator_id) else {
+        return (StatusCode::NOT_FOUND, "Actuator not found on device").into_response();
+    };
+
+    let action = match (request.action.as_str(), request.duration_secs) {
+        ("open", Some(duration_secs)) => {
+            let max_run_secs = actuator.max_run_secs.min(MAX_ACTUATOR_RUN_SECS);
+            if !(1..=max_run_secs).contains(&duration_secs) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("duration_secs must be between 1 and {max_run_secs}"),
+                )
+                    .into_response();
+            }
+            ActuatorAction::Open { duration_secs }
+        }
+        ("open", None) => {
+            return (StatusCode::BAD_REQUEST, "duration_secs is required to open").into_response();
+        }
+        ("close", _) => ActuatorAction::Close,
+        (other, _) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid action: {other}")).into_response();
+        }
+    };
+
+    let ttl_secs = request.ttl_secs.unwrap_or(DEFAULT_COMMAND_TTL_SECS);
+    if !(1..=MAX_COMMAND_TTL_SECS).contains(&ttl_secs) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("ttl_secs must be between 1 and {MAX_COMMAND_TTL_SECS}"),
+        )
+            .into_response();
+    }
+
+    let issued_at = jiff::Timestamp::now();
+    let command = ActuatorCommand {
+        id: CommandId(Ulid::new()),
+        device_id: device.id,
+        actuator_id,
+        action,
+        issued_at,
+        expires_at: issued_at + jiff::SignedDuration::from_secs(ttl_secs as i64),
+    };
+
+    match state.device_registry.add_command(command.clone()).await {
+        Ok(()) => {
+            let record = CommandRecord {
+                command,
+                result: None,
+                completed_at: None,
+            };
+            (StatusCode::ACCEPTED, Json(CommandResponse::from(record))).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to queue command");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to queue command").into_response()
+        }
+    }
+}
+
+/// List the commands issued to a device, oldest first.
+///
+/// GET /api/devices/:id/commands
+pub async fn list_commands<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid device ID").into_response(),
+    };
+
+    if let Err(response) =
+        check_device_access(&state.device_registry, &principal, DeviceId(ulid)).await
+    {
+        return response;
+    }
+
+    match state.device_registry.list_commands(DeviceId(ulid)).await {
+        Ok(records) => {
+            let commands: Vec<CommandResponse> =
+                records.into_iter().map(CommandResponse::from).collect();
+            (StatusCode::OK, Json(commands)).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to list commands");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list commands").into_response()
+        }
+    }
+}
+
+/// Get a command and its result.
+///
+/// GET /api/commands/:id
+pub async fn get_command<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid command ID").into_response(),
+    };
+
+    match state.device_registry.get_command(CommandId(ulid)).await {
+        Ok(Some(record)) => {
+            if let Err(response) =
+                check_device_access(&state.device_registry, &principal, record.command.device_id)
+                    .await
+            {
+                return match response.status() {
+                    StatusCode::NOT_FOUND => {
+                        (StatusCode::NOT_FOUND, "Command not found").into_response()
+                    }
+                    _ => response,
+                };
+            }
+            (StatusCode::OK, Json(CommandResponse::from(record))).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Command not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get command");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get command").into_response()
+        }
+    }
+}
+
+/// Respond 404 for devices outside the caller's organization.
+async fn check_device_access<Dev>(
+    registry: &Dev,
+    principal: &Principal,
+    device_id: DeviceId,
+) -> Result<(), Response>
+where
+    Dev: DeviceRegistry,
+{
+    if principal.organization.is_none() {
+        return Ok(());
+    }
+
+    match registry.get(device_id).await {
+        Ok(Some(device)) if principal.can_access(device.organization) => Ok(()),
+        Ok(_) => Err((StatusCode::NOT_FOUND, "Device not found").into_response()),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get device");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to get device").into_response())
+        }
+    }
+}
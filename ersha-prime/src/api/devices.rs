@@ -1,22 +1,29 @@
 use axum::{
-    Json,
+    Extension, Json,
     extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
 };
 use ersha_core::{
-    Device, DeviceId, DeviceKind, DeviceState, H3Cell, Sensor, SensorId, SensorKind, SensorMetric,
+    Actuator, ActuatorId, ActuatorKind, Calibration, CalibrationProfile, Device, DeviceId,
+    DeviceKey, DeviceKind, DeviceState, FieldId, H3Cell, MAX_ACTUATOR_RUN_SECS, Sensor, SensorId,
+    SensorKind, SensorMetric, SensorMetricType,
 };
 use ordered_float::NotNan;
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
-use crate::registry::{
-    DeviceRegistry, DispatcherRegistry,
-    filter::{DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder},
+use crate::{
+    geo,
+    registry::{
+        DeviceRegistry, DispatcherRegistry,
+        filter::{
+            BoundingBox, CellRing, DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder,
+        },
+    },
 };
 
-use super::ApiState;
+use super::{ApiState, auth::Principal};
 
 /// Request body for registering a new device.
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,13 +32,19 @@ pub struct RegisterDeviceRequest {
     pub id: Option<Ulid>,
     /// H3 cell location of the device.
     pub location: u64,
-    /// Device kind (currently only "sensor" is supported).
+    /// Device kind: "sensor" (the default) or "actuator".
     pub kind: Option<String>,
     /// Manufacturer name.
     pub manufacturer: Option<String>,
     /// Sensors attached to this device.
     #[serde(default)]
     pub sensors: Vec<SensorRequest>,
+    /// Actuators driven by this device. Only actuator devices have them.
+    #[serde(default)]
+    pub actuators: Vec<ActuatorRequest>,
+    /// Owning organization. Defaults to the caller's own.
+    #[serde(default)]
+    pub organization: Option<Ulid>,
 }
 
 /// Request body for a sensor.
@@ -39,8 +52,19 @@ pub struct RegisterDeviceRequest {
 pub struct SensorRequest {
     /// Optional ID. If not provided, a new ULID will be generated.
     pub id: Option<Ulid>,
-    /// Sensor kind: "soil_moisture", "soil_temp", "air_temp", "humidity", "rainfall".
+    /// Sensor kind, named like its metric, e.g. "soil_moisture" or "rainfall".
+    pub kind: String,
+}
+
+/// Request body for an actuator.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActuatorRequest {
+    /// Optional ID. If not provided, a new ULID will be generated.
+    pub id: Option<Ulid>,
+    /// Actuator kind: "valve" or "pump".
     pub kind: String,
+    /// Longest the actuator may stay open on one command, in seconds.
+    pub max_run_secs: u32,
 }
 
 /// Response body for a sensor.
@@ -54,13 +78,28 @@ impl From<&Sensor> for SensorResponse {
     fn from(s: &Sensor) -> Self {
         Self {
             id: s.id.0.to_string(),
-            kind: match s.kind {
-                SensorKind::SoilMoisture => "soil_moisture".to_string(),
-                SensorKind::SoilTemp => "soil_temp".to_string(),
-                SensorKind::AirTemp => "air_temp".to_string(),
-                SensorKind::Humidity => "humidity".to_string(),
-                SensorKind::Rainfall => "rainfall".to_string(),
+            kind: SensorMetricType::from(&s.kind).as_str().to_string(),
+        }
+    }
+}
+
+/// Response body for an actuator.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActuatorResponse {
+    pub id: String,
+    pub kind: String,
+    pub max_run_secs: u32,
+}
+
+impl From<&Actuator> for ActuatorResponse {
+    fn from(a: &Actuator) -> Self {
+        Self {
+            id: a.id.0.to_string(),
+            kind: match a.kind {
+                ActuatorKind::Valve => "valve".to_string(),
+                ActuatorKind::Pump => "pump".to_string(),
             },
+            max_run_secs: a.max_run_secs,
         }
     }
 }
@@ -75,6 +114,10 @@ pub struct DeviceResponse {
     pub manufacturer: Option<String>,
     pub provisioned_at: String,
     pub sensors: Vec<SensorResponse>,
+    #[serde(default)]
+    pub actuators: Vec<ActuatorResponse>,
+    #[serde(default)]
+    pub organization: Option<String>,
 }
 
 impl From<Device> for DeviceResponse {
@@ -83,15 +126,19 @@ impl From<Device> for DeviceResponse {
             id: d.id.0.to_string(),
             kind: match d.kind {
                 DeviceKind::Sensor => "sensor".to_string(),
+                DeviceKind::Actuator => "actuator".to_string(),
             },
             state: match d.state {
                 DeviceState::Active => "active".to_string(),
                 DeviceState::Suspended => "suspended".to_string(),
+                DeviceState::Retired => "retired".to_string(),
             },
             location: d.location.0,
             manufacturer: d.manufacturer.map(|s| s.to_string()),
             provisioned_at: d.provisioned_at.to_string(),
             sensors: d.sensors.iter().map(SensorResponse::from).collect(),
+            actuators: d.actuators.iter().map(ActuatorResponse::from).collect(),
+            organization: d.organization.map(|o| o.0.to_string()),
         }
     }
 }
@@ -129,6 +176,16 @@ pub struct ListDevicesQuery {
     pub provisioned_after: Option<String>,
     /// Filter by provisioned before (ISO 8601 timestamp)
     pub provisioned_before: Option<String>,
+    /// Filter by field (ULID), matching devices assigned to its plots
+    pub field: Option<String>,
+    /// Filter by ancestor H3 cell, matching locations within it
+    pub within: Option<u64>,
+    /// Filter by H3 cell, matching locations within `radius` cells of it
+    pub near: Option<u64>,
+    /// Grid distance for `near` (default 1, max 50)
+    pub radius: Option<u32>,
+    /// Filter by bounding box: "south,west,north,east" in degrees
+    pub bbox: Option<String>,
     /// Sort by field
     pub sort_by: Option<DeviceQuerySortBy>,
     /// Sort order
@@ -141,13 +198,18 @@ pub struct ListDevicesQuery {
     pub after: Option<String>,
 }
 
-fn parse_sensor_kind(kind: &str) -> Option<SensorKind> {
+fn parse_device_kind(kind: &str) -> Option<DeviceKind> {
+    match kind {
+        "sensor" => Some(DeviceKind::Sensor),
+        "actuator" => Some(DeviceKind::Actuator),
+        _ => None,
+    }
+}
+
+fn parse_actuator_kind(kind: &str) -> Option<ActuatorKind> {
     match kind {
-        "soil_moisture" => Some(SensorKind::SoilMoisture),
-        "soil_temp" => Some(SensorKind::SoilTemp),
-        "air_temp" => Some(SensorKind::AirTemp),
-        "humidity" => Some(SensorKind::Humidity),
-        "rainfall" => Some(SensorKind::Rainfall),
+        "valve" => Some(ActuatorKind::Valve),
+        "pump" => Some(ActuatorKind::Pump),
         _ => None,
     }
 }
@@ -169,50 +231,158 @@ fn default_metric_for_kind(kind: SensorKind) -> SensorMetric {
         SensorKind::Rainfall => SensorMetric::Rainfall {
             value: NotNan::new(0.0).unwrap(),
         },
+        SensorKind::SoilEc => SensorMetric::SoilEc {
+            value: NotNan::new(0.0).unwrap(),
+        },
+        SensorKind::SoilPh => SensorMetric::SoilPh {
+            value: NotNan::new(0.0).unwrap(),
+        },
+        SensorKind::LeafWetness => SensorMetric::LeafWetness {
+            value: ersha_core::Percentage(0),
+        },
+        SensorKind::WindSpeed => SensorMetric::WindSpeed {
+            value: NotNan::new(0.0).unwrap(),
+        },
+        SensorKind::WindDirection => SensorMetric::WindDirection {
+            value: NotNan::new(0.0).unwrap(),
+        },
+        SensorKind::SolarRadiation => SensorMetric::SolarRadiation {
+            value: NotNan::new(0.0).unwrap(),
+        },
+        SensorKind::BarometricPressure => SensorMetric::BarometricPressure {
+            value: NotNan::new(0.0).unwrap(),
+        },
+        SensorKind::WaterLevel => SensorMetric::WaterLevel {
+            value: NotNan::new(0.0).unwrap(),
+        },
+        SensorKind::FlowRate => SensorMetric::FlowRate {
+            value: NotNan::new(0.0).unwrap(),
+        },
     }
 }
 
+fn parse_sensors(requests: Vec<SensorRequest>) -> Result<Vec<Sensor>, Response> {
+    let mut sensors = Vec::with_capacity(requests.len());
+    for sensor_req in requests {
+        let sensor_kind = match sensor_req.kind.parse::<SensorMetricType>() {
+            Ok(metric_type) => SensorKind::from(metric_type),
+            Err(_) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid sensor kind: {}", sensor_req.kind),
+                )
+                    .into_response());
+            }
+        };
+        sensors.push(Sensor {
+            id: SensorId(sensor_req.id.unwrap_or_else(Ulid::new)),
+            kind: sensor_kind.clone(),
+            metric: default_metric_for_kind(sensor_kind),
+        });
+    }
+    Ok(sensors)
+}
+
+/// Parse a "south,west,north,east" bounding box.
+pub(super) fn parse_bbox(bbox: &str) -> Option<BoundingBox> {
+    let values: Vec<f64> = bbox
+        .split(',')
+        .map(|v| v.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    let [south, west, north, east] = values[..] else {
+        return None;
+    };
+    let valid_lat = |lat: f64| (-90.0..=90.0).contains(&lat);
+    let valid_lng = |lng: f64| (-180.0..=180.0).contains(&lng);
+    if !(valid_lat(south) && valid_lat(north) && valid_lng(west) && valid_lng(east))
+        || south > north
+    {
+        return None;
+    }
+    Some(BoundingBox {
+        south,
+        west,
+        north,
+        east,
+    })
+}
+
 /// Register a new device.
 ///
 /// POST /api/devices
 pub async fn register_device<D, Dev>(
     State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
     Json(request): Json<RegisterDeviceRequest>,
 ) -> impl IntoResponse
 where
     D: DispatcherRegistry,
     Dev: DeviceRegistry,
 {
+    let organization = match principal.owner_for(request.organization) {
+        Ok(organization) => organization,
+        Err(rejection) => return rejection.into_response(),
+    };
     let id = request.id.unwrap_or_else(Ulid::new);
 
-    // Parse sensors
-    let mut sensors = Vec::with_capacity(request.sensors.len());
-    for sensor_req in request.sensors {
-        let sensor_kind = match parse_sensor_kind(&sensor_req.kind) {
-            Some(kind) => kind,
-            None => {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    format!("Invalid sensor kind: {}", sensor_req.kind),
-                )
-                    .into_response();
-            }
+    let kind = match request.kind.as_deref().map(parse_device_kind) {
+        None => DeviceKind::Sensor,
+        Some(Some(kind)) => kind,
+        Some(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid device kind: {}", request.kind.unwrap_or_default()),
+            )
+                .into_response();
+        }
+    };
+
+    if kind != DeviceKind::Actuator && !request.actuators.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Only actuator devices can have actuators",
+        )
+            .into_response();
+    }
+
+    let sensors = match parse_sensors(request.sensors) {
+        Ok(sensors) => sensors,
+        Err(rejection) => return rejection,
+    };
+
+    let mut actuators = Vec::with_capacity(request.actuators.len());
+    for actuator_req in request.actuators {
+        let Some(actuator_kind) = parse_actuator_kind(&actuator_req.kind) else {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid actuator kind: {}", actuator_req.kind),
+            )
+                .into_response();
         };
-        sensors.push(Sensor {
-            id: SensorId(sensor_req.id.unwrap_or_else(Ulid::new)),
-            kind: sensor_kind.clone(),
-            metric: default_metric_for_kind(sensor_kind),
+        if !(1..=MAX_ACTUATOR_RUN_SECS).contains(&actuator_req.max_run_secs) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Actuator max_run_secs must be between 1 and {MAX_ACTUATOR_RUN_SECS}"),
+            )
+                .into_response();
+        }
+        actuators.push(Actuator {
+            id: ActuatorId(actuator_req.id.unwrap_or_else(Ulid::new)),
+            kind: actuator_kind,
+            max_run_secs: actuator_req.max_run_secs,
         });
     }
 
     let device = Device {
         id: DeviceId(id),
-        kind: DeviceKind::Sensor,
+        kind,
         state: DeviceState::Active,
         location: H3Cell(request.location),
         manufacturer: request.manufacturer.map(|s| s.into_boxed_str()),
         provisioned_at: jiff::Timestamp::now(),
         sensors: sensors.into_boxed_slice(),
+        actuators: actuators.into_boxed_slice(),
+        organization,
     };
 
     match state.device_registry.register(device.clone()).await {
@@ -233,6 +403,7 @@ where
 /// GET /api/devices/:id
 pub async fn get_device<D, Dev>(
     State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
     Path(id): Path<String>,
 ) -> impl IntoResponse
 where
@@ -245,8 +416,10 @@ where
     };
 
     match state.device_registry.get(DeviceId(ulid)).await {
-        Ok(Some(device)) => (StatusCode::OK, Json(DeviceResponse::from(device))).into_response(),
-        Ok(None) => (StatusCode::NOT_FOUND, "Device not found").into_response(),
+        Ok(Some(device)) if principal.can_access(device.organization) => {
+            (StatusCode::OK, Json(DeviceResponse::from(device))).into_response()
+        }
+        Ok(_) => (StatusCode::NOT_FOUND, "Device not found").into_response(),
         Err(e) => {
             tracing::error!(error = ?e, "Failed to get device");
             (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get device").into_response()
@@ -259,6 +432,7 @@ where
 /// GET /api/devices
 pub async fn list_devices<D, Dev>(
     State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
     Query(query): Query<ListDevicesQuery>,
 ) -> impl IntoResponse
 where
@@ -266,12 +440,16 @@ where
     Dev: DeviceRegistry,
 {
     // Build filter
-    let mut filter = DeviceFilter::default();
+    let mut filter = DeviceFilter {
+        organization: principal.organization,
+        ..Default::default()
+    };
 
     if let Some(state_filter) = query.state {
         let device_state = match state_filter {
             StateFilter::Active => DeviceState::Active,
             StateFilter::Suspended => DeviceState::Suspended,
+            StateFilter::Retired => DeviceState::Retired,
         };
         filter.states = Some(vec![device_state]);
     }
@@ -284,6 +462,33 @@ where
         filter.manufacturer_pattern = Some(manufacturer.clone());
     }
 
+    if let Some(ref field) = query.field {
+        match field.parse::<Ulid>() {
+            Ok(ulid) => filter.field = Some(FieldId(ulid)),
+            Err(_) => return (StatusCode::BAD_REQUEST, "Invalid field ID").into_response(),
+        }
+    }
+
+    filter.ancestor = query.within.map(H3Cell);
+
+    if let Some(center) = query.near {
+        let radius = query.radius.unwrap_or(1);
+        if radius > geo::MAX_RING_RADIUS {
+            return (StatusCode::BAD_REQUEST, "Radius is too large").into_response();
+        }
+        filter.ring = Some(CellRing {
+            center: H3Cell(center),
+            radius,
+        });
+    }
+
+    if let Some(ref bbox) = query.bbox {
+        match parse_bbox(bbox) {
+            Some(bbox) => filter.bbox = Some(bbox),
+            None => return (StatusCode::BAD_REQUEST, "Invalid bbox").into_response(),
+        }
+    }
+
     if let Some(ref ts_str) = query.provisioned_after {
         match ts_str.parse::<jiff::Timestamp>() {
             Ok(ts) => filter.provisioned_after = Some(ts),
@@ -361,3 +566,553 @@ where
         }
     }
 }
+
+/// Request body for updating a device. Omitted fields are left as they are.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateDeviceRequest {
+    /// New H3 cell location of the device.
+    pub location: Option<u64>,
+    /// New manufacturer name.
+    pub manufacturer: Option<String>,
+}
+
+/// Request body for attaching sensors to a device.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddSensorsRequest {
+    pub sensors: Vec<SensorRequest>,
+}
+
+/// Update a device's location or manufacturer.
+///
+/// PATCH /api/devices/:id
+pub async fn update_device<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<UpdateDeviceRequest>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let device = match managed_device(&state.device_registry, &principal, &id).await {
+        Ok(device) => device,
+        Err(rejection) => return rejection,
+    };
+    let id = device.id;
+    let new = Device {
+        location: request.location.map(H3Cell).unwrap_or(device.location),
+        manufacturer: request
+            .manufacturer
+            .map(String::into_boxed_str)
+            .or(device.manufacturer),
+        ..device
+    };
+
+    let result = state.device_registry.update(id, new).await;
+    respond_with_device(&state.device_registry, id, result, "update").await
+}
+
+/// Suspend a device.
+///
+/// POST /api/devices/:id/suspend
+pub async fn suspend_device<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match managed_device(&state.device_registry, &principal, &id).await {
+        Ok(device) => device.id,
+        Err(rejection) => return rejection,
+    };
+
+    let result = state.device_registry.suspend(id).await;
+    respond_with_device(&state.device_registry, id, result, "suspend").await
+}
+
+/// Return a suspended device to active.
+///
+/// POST /api/devices/:id/reactivate
+pub async fn reactivate_device<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match managed_device(&state.device_registry, &principal, &id).await {
+        Ok(device) => device.id,
+        Err(rejection) => return rejection,
+    };
+
+    let result = state.device_registry.reactivate(id).await;
+    respond_with_device(&state.device_registry, id, result, "reactivate").await
+}
+
+/// Decommission a device. It is kept as retired along with its readings and
+/// statuses, but it can no longer upload or be changed.
+///
+/// POST /api/devices/:id/decommission
+pub async fn decommission_device<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match managed_device(&state.device_registry, &principal, &id).await {
+        Ok(device) => device.id,
+        Err(rejection) => return rejection,
+    };
+
+    let result = state.device_registry.retire(id).await;
+    respond_with_device(&state.device_registry, id, result, "decommission").await
+}
+
+/// Attach sensors to a device.
+///
+/// POST /api/devices/:id/sensors
+pub async fn add_sensors<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<AddSensorsRequest>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let sensors = match parse_sensors(request.sensors) {
+        Ok(sensors) => sensors,
+        Err(rejection) => return rejection,
+    };
+
+    let id = match managed_device(&state.device_registry, &principal, &id).await {
+        Ok(device) => device.id,
+        Err(rejection) => return rejection,
+    };
+
+    let result = state
+        .device_registry
+        .add_sensors(id, sensors.into_iter())
+        .await;
+    respond_with_device(&state.device_registry, id, result, "update").await
+}
+
+/// Detach a sensor from a device. Its readings are kept.
+///
+/// DELETE /api/devices/:id/sensors/:sensor_id
+pub async fn remove_sensor<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path((id, sensor_id)): Path<(String, String)>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let sensor_id = match sensor_id.parse::<Ulid>() {
+        Ok(ulid) => SensorId(ulid),
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid sensor ID").into_response(),
+    };
+
+    let id = match managed_device(&state.device_registry, &principal, &id).await {
+        Ok(device) => device.id,
+        Err(rejection) => return rejection,
+    };
+
+    match state.device_registry.remove_sensor(id, sensor_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Sensor not found").into_response(),
+        Err(e) => {
+            let err_str = e.to_string();
+            if err_str.contains("not found") || err_str.contains("NotFound") {
+                (StatusCode::NOT_FOUND, "Device not found").into_response()
+            } else {
+                tracing::error!(error = ?e, "Failed to remove sensor");
+                (StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove sensor").into_response()
+            }
+        }
+    }
+}
+
+/// Look up a device the caller may change. Responds 404 for devices outside
+/// the caller's organization and 409 for retired ones.
+async fn managed_device<Dev>(
+    registry: &Dev,
+    principal: &Principal,
+    id: &str,
+) -> Result<Device, Response>
+where
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid device ID").into_response()),
+    };
+
+    match registry.get(DeviceId(ulid)).await {
+        Ok(Some(device)) if principal.can_access(device.organization) => {
+            if device.state == DeviceState::Retired {
+                Err((StatusCode::CONFLICT, "Device is retired").into_response())
+            } else {
+                Ok(device)
+            }
+        }
+        Ok(_) => Err((StatusCode::NOT_FOUND, "Device not found").into_response()),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get device");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to get device").into_response())
+        }
+    }
+}
+
+/// Respond with the device as stored after a `action` on it.
+async fn respond_with_device<Dev>(
+    registry: &Dev,
+    id: DeviceId,
+    result: Result<(), Dev::Error>,
+    action: &str,
+) -> Response
+where
+    Dev: DeviceRegistry,
+{
+    if let Err(e) = result {
+        let err_str = e.to_string();
+        if err_str.contains("not found") || err_str.contains("NotFound") {
+            return (StatusCode::NOT_FOUND, "Device not found").into_response();
+        }
+        tracing::error!(error = ?e, "Failed to {action} device");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {action} device"),
+        )
+            .into_response();
+    }
+
+    match registry.get(id).await {
+        Ok(Some(device)) => (StatusCode::OK, Json(DeviceResponse::from(device))).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Device not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get device after {action}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Device {action} succeeded but failed to fetch"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Request and response body for a sensor calibration.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CalibrationBody {
+    /// `calibrated = raw * gain + offset`.
+    Linear { gain: f64, offset: f64 },
+    /// `[raw, calibrated]` pairs sorted by raw value.
+    Lookup { points: Vec<(f64, f64)> },
+}
+
+impl CalibrationBody {
+    fn into_calibration(self) -> Option<Calibration> {
+        let calibration = match self {
+            CalibrationBody::Linear { gain, offset } => Calibration::Linear {
+                gain: NotNan::new(gain).ok()?,
+                offset: NotNan::new(offset).ok()?,
+            },
+            CalibrationBody::Lookup { points } => Calibration::Lookup {
+                points: points
+                    .into_iter()
+                    .map(|(raw, calibrated)| {
+                        Some((NotNan::new(raw).ok()?, NotNan::new(calibrated).ok()?))
+                    })
+                    .collect::<Option<_>>()?,
+            },
+        };
+
+        calibration.is_valid().then_some(calibration)
+    }
+}
+
+impl From<Calibration> for CalibrationBody {
+    fn from(c: Calibration) -> Self {
+        match c {
+            Calibration::Linear { gain, offset } => CalibrationBody::Linear {
+                gain: gain.into_inner(),
+                offset: offset.into_inner(),
+            },
+            Calibration::Lookup { points } => CalibrationBody::Lookup {
+                points: points
+                    .iter()
+                    .map(|(raw, calibrated)| (raw.into_inner(), calibrated.into_inner()))
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Response body for a sensor calibration.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CalibrationResponse {
+    pub sensor_id: String,
+    pub calibration: CalibrationBody,
+    pub updated_at: String,
+}
+
+impl From<CalibrationProfile> for CalibrationResponse {
+    fn from(p: CalibrationProfile) -> Self {
+        Self {
+            sensor_id: p.sensor_id.0.to_string(),
+            calibration: CalibrationBody::from(p.calibration),
+            updated_at: p.updated_at.to_string(),
+        }
+    }
+}
+
+/// Get the calibration of a sensor.
+///
+/// GET /api/sensors/:id/calibration
+pub async fn get_calibration<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid sensor ID").into_response(),
+    };
+
+    if let Err(response) =
+        check_sensor_access(&state.device_registry, &principal, SensorId(ulid)).await
+    {
+        return response;
+    }
+
+    match state.device_registry.get_calibration(SensorId(ulid)).await {
+        Ok(Some(profile)) => {
+            (StatusCode::OK, Json(CalibrationResponse::from(profile))).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Calibration not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get calibration");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get calibration",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Set the calibration of a sensor.
+///
+/// PUT /api/sensors/:id/calibration
+pub async fn set_calibration<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<CalibrationBody>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid sensor ID").into_response(),
+    };
+
+    let Some(calibration) = request.into_calibration() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Invalid calibration: values must be numbers and lookup tables need at least two points with increasing raw values",
+        )
+            .into_response();
+    };
+
+    let sensor_id = SensorId(ulid);
+    if let Err(response) = check_sensor_access(&state.device_registry, &principal, sensor_id).await
+    {
+        return response;
+    }
+
+    if let Err(e) = state
+        .device_registry
+        .set_calibration(sensor_id, Some(calibration))
+        .await
+    {
+        return calibration_error(e, "Failed to set calibration");
+    }
+
+    match state.device_registry.get_calibration(sensor_id).await {
+        Ok(Some(profile)) => {
+            (StatusCode::OK, Json(CalibrationResponse::from(profile))).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Calibration not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get calibration");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Calibration set but failed to fetch",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Remove the calibration of a sensor.
+///
+/// DELETE /api/sensors/:id/calibration
+pub async fn delete_calibration<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid sensor ID").into_response(),
+    };
+
+    if let Err(response) =
+        check_sensor_access(&state.device_registry, &principal, SensorId(ulid)).await
+    {
+        return response;
+    }
+
+    match state
+        .device_registry
+        .set_calibration(SensorId(ulid), None)
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => calibration_error(e, "Failed to remove calibration"),
+    }
+}
+
+/// Respond 404 for sensors on devices outside the caller's organization.
+async fn check_sensor_access<Dev>(
+    registry: &Dev,
+    principal: &Principal,
+    sensor_id: SensorId,
+) -> Result<(), axum::response::Response>
+where
+    Dev: DeviceRegistry,
+{
+    let Some(organization) = principal.organization else {
+        return Ok(());
+    };
+
+    let filter = DeviceFilter {
+        sensor_ids: Some(vec![sensor_id]),
+        organization: Some(organization),
+        ..Default::default()
+    };
+    match registry.count(Some(filter)).await {
+        Ok(0) => Err((StatusCode::NOT_FOUND, "Sensor not found").into_response()),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to look up sensor owner");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to look up sensor",
+            )
+                .into_response())
+        }
+    }
+}
+
+fn calibration_error(
+    e: impl std::fmt::Debug + std::fmt::Display,
+    message: &'static str,
+) -> axum::response::Response {
+    let err_str = e.to_string();
+    if err_str.contains("not found") || err_str.contains("NotFound") {
+        (StatusCode::NOT_FOUND, "Sensor not found").into_response()
+    } else {
+        tracing::error!(error = ?e, "{message}");
+        (StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
+    }
+}
+
+/// Response body for a newly issued device key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceKeyResponse {
+    pub device_id: String,
+    /// Hex encoded 32-byte key to flash onto the device. It is not shown again.
+    pub key: String,
+}
+
+/// Issue a new pre-shared key for a device, replacing any previous key.
+///
+/// POST /api/devices/:id/key
+pub async fn issue_device_key<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid device ID").into_response(),
+    };
+
+    match state.device_registry.get(DeviceId(ulid)).await {
+        Ok(Some(device)) if principal.can_access(device.organization) => {}
+        Ok(_) => return (StatusCode::NOT_FOUND, "Device not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get device");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to issue device key",
+            )
+                .into_response();
+        }
+    }
+
+    let key = DeviceKey(rand::random());
+    let hex = key.0.iter().map(|b| format!("{b:02x}")).collect();
+
+    match state.device_registry.set_key(DeviceId(ulid), key).await {
+        Ok(()) => (
+            StatusCode::CREATED,
+            Json(DeviceKeyResponse {
+                device_id: ulid.to_string(),
+                key: hex,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            let err_str = e.to_string();
+            if err_str.contains("not found") || err_str.contains("NotFound") {
+                (StatusCode::NOT_FOUND, "Device not found").into_response()
+            } else {
+                tracing::error!(error = ?e, "Failed to issue device key");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to issue device key",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
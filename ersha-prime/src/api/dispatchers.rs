@@ -1,8 +1,8 @@
 use axum::{
-    Json,
+    Extension, Json,
     extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
 };
 use ersha_core::{Dispatcher, DispatcherId, DispatcherState, H3Cell};
 use serde::{Deserialize, Serialize};
@@ -13,7 +13,7 @@ use crate::registry::{
     filter::{DispatcherFilter, DispatcherSortBy, Pagination, QueryOptions, SortOrder},
 };
 
-use super::ApiState;
+use super::{ApiState, auth::Principal};
 
 /// Request body for registering a new dispatcher.
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,6 +22,9 @@ pub struct RegisterDispatcherRequest {
     pub id: Option<Ulid>,
     /// H3 cell location of the dispatcher.
     pub location: u64,
+    /// Owning organization. Defaults to the caller's own.
+    #[serde(default)]
+    pub organization: Option<Ulid>,
 }
 
 /// Response body for a dispatcher.
@@ -31,6 +34,7 @@ pub struct DispatcherResponse {
     pub location: u64,
     pub state: String,
     pub provisioned_at: String,
+    pub organization: Option<String>,
 }
 
 impl From<Dispatcher> for DispatcherResponse {
@@ -41,8 +45,10 @@ impl From<Dispatcher> for DispatcherResponse {
             state: match d.state {
                 DispatcherState::Active => "active".to_string(),
                 DispatcherState::Suspended => "suspended".to_string(),
+                DispatcherState::Retired => "retired".to_string(),
             },
             provisioned_at: d.provisioned_at.to_string(),
+            organization: d.organization.map(|o| o.0.to_string()),
         }
     }
 }
@@ -60,6 +66,7 @@ pub struct ListDispatchersResponse {
 pub enum StateFilter {
     Active,
     Suspended,
+    Retired,
 }
 
 /// Sort order for queries.
@@ -92,18 +99,24 @@ pub struct ListDispatchersQuery {
 /// POST /api/dispatchers
 pub async fn register_dispatcher<D, Dev>(
     State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
     Json(request): Json<RegisterDispatcherRequest>,
 ) -> impl IntoResponse
 where
     D: DispatcherRegistry,
     Dev: DeviceRegistry,
 {
+    let organization = match principal.owner_for(request.organization) {
+        Ok(organization) => organization,
+        Err(rejection) => return rejection.into_response(),
+    };
     let id = request.id.unwrap_or_else(Ulid::new);
     let dispatcher = Dispatcher {
         id: DispatcherId(id),
         location: H3Cell(request.location),
         state: DispatcherState::Active,
         provisioned_at: jiff::Timestamp::now(),
+        organization,
     };
 
     match state.dispatcher_registry.register(dispatcher.clone()).await {
@@ -128,6 +141,7 @@ where
 /// GET /api/dispatchers/:id
 pub async fn get_dispatcher<D, Dev>(
     State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
     Path(id): Path<String>,
 ) -> impl IntoResponse
 where
@@ -140,10 +154,10 @@ where
     };
 
     match state.dispatcher_registry.get(DispatcherId(ulid)).await {
-        Ok(Some(dispatcher)) => {
+        Ok(Some(dispatcher)) if principal.can_access(dispatcher.organization) => {
             (StatusCode::OK, Json(DispatcherResponse::from(dispatcher))).into_response()
         }
-        Ok(None) => (StatusCode::NOT_FOUND, "Dispatcher not found").into_response(),
+        Ok(_) => (StatusCode::NOT_FOUND, "Dispatcher not found").into_response(),
         Err(e) => {
             tracing::error!(error = ?e, "Failed to get dispatcher");
             (
@@ -160,6 +174,7 @@ where
 /// GET /api/dispatchers
 pub async fn list_dispatchers<D, Dev>(
     State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
     Query(query): Query<ListDispatchersQuery>,
 ) -> impl IntoResponse
 where
@@ -167,12 +182,16 @@ where
     Dev: DeviceRegistry,
 {
     // Build filter
-    let mut filter = DispatcherFilter::default();
+    let mut filter = DispatcherFilter {
+        organization: principal.organization,
+        ..Default::default()
+    };
 
     if let Some(state_filter) = query.state {
         let dispatcher_state = match state_filter {
             StateFilter::Active => DispatcherState::Active,
             StateFilter::Suspended => DispatcherState::Suspended,
+            StateFilter::Retired => DispatcherState::Retired,
         };
         filter.states = Some(vec![dispatcher_state]);
     }
@@ -233,52 +252,174 @@ where
     }
 }
 
+/// Request body for updating a dispatcher. Omitted fields are left as they are.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateDispatcherRequest {
+    /// New H3 cell location of the dispatcher.
+    pub location: Option<u64>,
+}
+
+/// Update a dispatcher's location.
+///
+/// PATCH /api/dispatchers/:id
+pub async fn update_dispatcher<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<UpdateDispatcherRequest>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let dispatcher = match managed_dispatcher(&state.dispatcher_registry, &principal, &id).await {
+        Ok(dispatcher) => dispatcher,
+        Err(rejection) => return rejection,
+    };
+    let id = dispatcher.id;
+    let new = Dispatcher {
+        location: request.location.map(H3Cell).unwrap_or(dispatcher.location),
+        ..dispatcher
+    };
+
+    let result = state.dispatcher_registry.update(id, new).await;
+    respond_with_dispatcher(&state.dispatcher_registry, id, result, "update").await
+}
+
 /// Suspend a dispatcher.
 ///
 /// POST /api/dispatchers/:id/suspend
 pub async fn suspend_dispatcher<D, Dev>(
     State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
     Path(id): Path<String>,
 ) -> impl IntoResponse
 where
     D: DispatcherRegistry,
     Dev: DeviceRegistry,
+{
+    let id = match managed_dispatcher(&state.dispatcher_registry, &principal, &id).await {
+        Ok(dispatcher) => dispatcher.id,
+        Err(rejection) => return rejection,
+    };
+
+    let result = state.dispatcher_registry.suspend(id).await;
+    respond_with_dispatcher(&state.dispatcher_registry, id, result, "suspend").await
+}
+
+/// Return a suspended dispatcher to active.
+///
+/// POST /api/dispatchers/:id/reactivate
+pub async fn reactivate_dispatcher<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match managed_dispatcher(&state.dispatcher_registry, &principal, &id).await {
+        Ok(dispatcher) => dispatcher.id,
+        Err(rejection) => return rejection,
+    };
+
+    let result = state.dispatcher_registry.reactivate(id).await;
+    respond_with_dispatcher(&state.dispatcher_registry, id, result, "reactivate").await
+}
+
+/// Decommission a dispatcher. It is kept as retired so the data it uploaded
+/// still resolves, but it can no longer upload or be changed.
+///
+/// POST /api/dispatchers/:id/decommission
+pub async fn decommission_dispatcher<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match managed_dispatcher(&state.dispatcher_registry, &principal, &id).await {
+        Ok(dispatcher) => dispatcher.id,
+        Err(rejection) => return rejection,
+    };
+
+    let result = state.dispatcher_registry.retire(id).await;
+    respond_with_dispatcher(&state.dispatcher_registry, id, result, "decommission").await
+}
+
+/// Look up a dispatcher the caller may change. Responds 404 for dispatchers
+/// outside the caller's organization and 409 for retired ones.
+async fn managed_dispatcher<D>(
+    registry: &D,
+    principal: &Principal,
+    id: &str,
+) -> Result<Dispatcher, Response>
+where
+    D: DispatcherRegistry,
 {
     let ulid = match id.parse::<Ulid>() {
         Ok(ulid) => ulid,
-        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid dispatcher ID").into_response(),
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid dispatcher ID").into_response()),
     };
 
-    match state.dispatcher_registry.suspend(DispatcherId(ulid)).await {
-        Ok(()) => {
-            // Fetch the updated dispatcher to return
-            match state.dispatcher_registry.get(DispatcherId(ulid)).await {
-                Ok(Some(dispatcher)) => {
-                    (StatusCode::OK, Json(DispatcherResponse::from(dispatcher))).into_response()
-                }
-                Ok(None) => (StatusCode::NOT_FOUND, "Dispatcher not found").into_response(),
-                Err(e) => {
-                    tracing::error!(error = ?e, "Failed to get suspended dispatcher");
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Dispatcher suspended but failed to fetch",
-                    )
-                        .into_response()
-                }
+    match registry.get(DispatcherId(ulid)).await {
+        Ok(Some(dispatcher)) if principal.can_access(dispatcher.organization) => {
+            if dispatcher.state == DispatcherState::Retired {
+                Err((StatusCode::CONFLICT, "Dispatcher is retired").into_response())
+            } else {
+                Ok(dispatcher)
             }
         }
+        Ok(_) => Err((StatusCode::NOT_FOUND, "Dispatcher not found").into_response()),
         Err(e) => {
-            let err_str = e.to_string();
-            if err_str.contains("not found") || err_str.contains("NotFound") {
-                (StatusCode::NOT_FOUND, "Dispatcher not found").into_response()
-            } else {
-                tracing::error!(error = ?e, "Failed to suspend dispatcher");
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to suspend dispatcher",
-                )
-                    .into_response()
-            }
+            tracing::error!(error = ?e, "Failed to get dispatcher");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get dispatcher",
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Respond with the dispatcher as stored after a `action` on it.
+async fn respond_with_dispatcher<D>(
+    registry: &D,
+    id: DispatcherId,
+    result: Result<(), D::Error>,
+    action: &str,
+) -> Response
+where
+    D: DispatcherRegistry,
+{
+    if let Err(e) = result {
+        let err_str = e.to_string();
+        if err_str.contains("not found") || err_str.contains("NotFound") {
+            return (StatusCode::NOT_FOUND, "Dispatcher not found").into_response();
+        }
+        tracing::error!(error = ?e, "Failed to {action} dispatcher");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {action} dispatcher"),
+        )
+            .into_response();
+    }
+
+    match registry.get(id).await {
+        Ok(Some(dispatcher)) => {
+            (StatusCode::OK, Json(DispatcherResponse::from(dispatcher))).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Dispatcher not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get dispatcher after {action}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Dispatcher {action} succeeded but failed to fetch"),
+            )
+                .into_response()
         }
     }
 }
@@ -0,0 +1,941 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use ersha_core::{Boundary, DeviceId, Farm, FarmId, Field, FieldId, Plot, PlotId};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    geo::{self, Feature, FeatureCollection, Geometry},
+    registry::{DeviceRegistry, FarmRegistry},
+};
+
+use super::auth::Principal;
+
+/// Shared state for farm handlers. Devices are looked up when they are
+/// assigned to plots.
+#[derive(Clone)]
+pub struct FarmState<F, Dev>
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    pub farm_registry: F,
+    pub device_registry: Dev,
+}
+
+type Rejection = (StatusCode, &'static str);
+
+/// Request body for creating a farm.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateFarmRequest {
+    /// Optional ID. If not provided, a new ULID will be generated.
+    pub id: Option<Ulid>,
+    pub name: String,
+    /// GeoJSON polygon without holes.
+    pub boundary: Geometry,
+    /// Owning organization. Defaults to the caller's own.
+    #[serde(default)]
+    pub organization: Option<Ulid>,
+}
+
+/// Request body for replacing a farm's name and boundary.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateFarmRequest {
+    pub name: String,
+    /// GeoJSON polygon without holes.
+    pub boundary: Geometry,
+}
+
+/// Request body for creating or replacing a field or plot.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AreaRequest {
+    /// Optional ID for new areas. If not provided, a new ULID will be
+    /// generated. Ignored on updates.
+    pub id: Option<Ulid>,
+    pub name: String,
+    /// GeoJSON polygon without holes.
+    pub boundary: Geometry,
+    /// Crop grown, e.g. "maize".
+    pub crop: Option<String>,
+    /// Planting date (ISO 8601 date, e.g. "2025-03-15").
+    pub planted_on: Option<String>,
+}
+
+/// Response body for a farm.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FarmResponse {
+    pub id: String,
+    pub name: String,
+    pub organization: Option<String>,
+    pub boundary: Geometry,
+    /// Number of H3 cells covering the boundary.
+    pub cell_count: usize,
+    pub created_at: String,
+}
+
+impl From<Farm> for FarmResponse {
+    fn from(farm: Farm) -> Self {
+        Self {
+            id: farm.id.0.to_string(),
+            name: farm.name.into_string(),
+            organization: farm.organization.map(|o| o.0.to_string()),
+            boundary: Geometry::from(&farm.boundary),
+            cell_count: farm.boundary.cells.len(),
+            created_at: farm.created_at.to_string(),
+        }
+    }
+}
+
+/// Response body for a field.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldResponse {
+    pub id: String,
+    pub farm_id: String,
+    pub name: String,
+    pub crop: Option<String>,
+    pub planted_on: Option<String>,
+    pub boundary: Geometry,
+    /// Number of H3 cells covering the boundary.
+    pub cell_count: usize,
+    pub created_at: String,
+}
+
+impl From<Field> for FieldResponse {
+    fn from(field: Field) -> Self {
+        Self {
+            id: field.id.0.to_string(),
+            farm_id: field.farm_id.0.to_string(),
+            name: field.name.into_string(),
+            crop: field.crop.map(String::from),
+            planted_on: field.planted_on.map(|d| d.to_string()),
+            boundary: Geometry::from(&field.boundary),
+            cell_count: field.boundary.cells.len(),
+            created_at: field.created_at.to_string(),
+        }
+    }
+}
+
+/// Response body for a plot.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlotResponse {
+    pub id: String,
+    pub field_id: String,
+    pub name: String,
+    pub crop: Option<String>,
+    pub planted_on: Option<String>,
+    pub boundary: Geometry,
+    /// Number of H3 cells covering the boundary.
+    pub cell_count: usize,
+    pub created_at: String,
+}
+
+impl From<Plot> for PlotResponse {
+    fn from(plot: Plot) -> Self {
+        Self {
+            id: plot.id.0.to_string(),
+            field_id: plot.field_id.0.to_string(),
+            name: plot.name.into_string(),
+            crop: plot.crop.map(String::from),
+            planted_on: plot.planted_on.map(|d| d.to_string()),
+            boundary: Geometry::from(&plot.boundary),
+            cell_count: plot.boundary.cells.len(),
+            created_at: plot.created_at.to_string(),
+        }
+    }
+}
+
+/// Response body for list of farms.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListFarmsResponse {
+    pub farms: Vec<FarmResponse>,
+}
+
+/// Response body for list of fields.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListFieldsResponse {
+    pub fields: Vec<FieldResponse>,
+}
+
+/// Response body for list of plots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListPlotsResponse {
+    pub plots: Vec<PlotResponse>,
+}
+
+/// Response body for the devices assigned to a plot.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlotDevicesResponse {
+    pub devices: Vec<String>,
+}
+
+/// Properties of a field in the GeoJSON export.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldProperties {
+    pub name: String,
+    pub farm_id: String,
+    pub crop: Option<String>,
+    pub planted_on: Option<String>,
+}
+
+fn parse_id(id: &str, message: &'static str) -> Result<Ulid, Rejection> {
+    id.parse::<Ulid>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, message))
+}
+
+fn parse_name(name: &str) -> Result<Box<str>, Rejection> {
+    match name.trim() {
+        "" => Err((StatusCode::BAD_REQUEST, "Name is required")),
+        name => Ok(name.into()),
+    }
+}
+
+fn parse_boundary(geometry: Geometry) -> Result<Boundary, (StatusCode, String)> {
+    geometry
+        .into_ring()
+        .and_then(geo::boundary)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+fn parse_planted_on(date: Option<&str>) -> Result<Option<jiff::civil::Date>, Rejection> {
+    date.map(|date| {
+        date.parse()
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid planted_on date"))
+    })
+    .transpose()
+}
+
+fn internal_error(e: impl std::fmt::Debug, message: &'static str) -> Rejection {
+    tracing::error!(error = ?e, "{message}");
+    (StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn write_error(
+    e: impl std::error::Error,
+    not_found: &'static str,
+    message: &'static str,
+) -> Rejection {
+    let err_str = e.to_string();
+    if err_str.contains("not found") || err_str.contains("NotFound") {
+        (StatusCode::NOT_FOUND, not_found)
+    } else if err_str.contains("already exists") || err_str.contains("AlreadyExists") {
+        (StatusCode::CONFLICT, "Already exists")
+    } else {
+        internal_error(e, message)
+    }
+}
+
+/// The farm with `id`, if the caller may see it.
+async fn accessible_farm<F: FarmRegistry>(
+    farms: &F,
+    principal: &Principal,
+    id: FarmId,
+) -> Result<Farm, Rejection> {
+    match farms.get_farm(id).await {
+        Ok(Some(farm)) if principal.can_access(farm.organization) => Ok(farm),
+        Ok(_) => Err((StatusCode::NOT_FOUND, "Farm not found")),
+        Err(e) => Err(internal_error(e, "Failed to get farm")),
+    }
+}
+
+/// The field with `id` and its farm, if the caller may see them.
+async fn accessible_field<F: FarmRegistry>(
+    farms: &F,
+    principal: &Principal,
+    id: FieldId,
+) -> Result<(Field, Farm), Rejection> {
+    match farms.get_field(id).await {
+        Ok(Some(field)) => match accessible_farm(farms, principal, field.farm_id).await {
+            Ok(farm) => Ok((field, farm)),
+            Err((StatusCode::NOT_FOUND, _)) => Err((StatusCode::NOT_FOUND, "Field not found")),
+            Err(rejection) => Err(rejection),
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Field not found")),
+        Err(e) => Err(internal_error(e, "Failed to get field")),
+    }
+}
+
+/// The plot with `id` and its farm, if the caller may see them.
+async fn accessible_plot<F: FarmRegistry>(
+    farms: &F,
+    principal: &Principal,
+    id: PlotId,
+) -> Result<(Plot, Farm), Rejection> {
+    match farms.get_plot(id).await {
+        Ok(Some(plot)) => match accessible_field(farms, principal, plot.field_id).await {
+            Ok((_, farm)) => Ok((plot, farm)),
+            Err((StatusCode::NOT_FOUND, _)) => Err((StatusCode::NOT_FOUND, "Plot not found")),
+            Err(rejection) => Err(rejection),
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Plot not found")),
+        Err(e) => Err(internal_error(e, "Failed to get plot")),
+    }
+}
+
+/// Create a farm.
+///
+/// POST /api/farms
+pub async fn create_farm<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<CreateFarmRequest>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let organization = match principal.owner_for(request.organization) {
+        Ok(organization) => organization,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let name = match parse_name(&request.name) {
+        Ok(name) => name,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let boundary = match parse_boundary(request.boundary) {
+        Ok(boundary) => boundary,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let farm = Farm {
+        id: FarmId(request.id.unwrap_or_else(Ulid::new)),
+        name,
+        boundary,
+        organization,
+        created_at: jiff::Timestamp::now(),
+    };
+
+    match state.farm_registry.add_farm(farm.clone()).await {
+        Ok(()) => (StatusCode::CREATED, Json(FarmResponse::from(farm))).into_response(),
+        Err(e) => write_error(e, "Farm not found", "Failed to create farm").into_response(),
+    }
+}
+
+/// List the farms visible to the caller.
+///
+/// GET /api/farms
+pub async fn list_farms<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    match state.farm_registry.list_farms(principal.organization).await {
+        Ok(farms) => Json(ListFarmsResponse {
+            farms: farms.into_iter().map(FarmResponse::from).collect(),
+        })
+        .into_response(),
+        Err(e) => internal_error(e, "Failed to list farms").into_response(),
+    }
+}
+
+/// Get a farm by ID.
+///
+/// GET /api/farms/:id
+pub async fn get_farm<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid farm ID") {
+        Ok(id) => FarmId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match accessible_farm(&state.farm_registry, &principal, id).await {
+        Ok(farm) => Json(FarmResponse::from(farm)).into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Replace a farm's name and boundary.
+///
+/// PUT /api/farms/:id
+pub async fn update_farm<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<UpdateFarmRequest>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid farm ID") {
+        Ok(id) => FarmId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    let name = match parse_name(&request.name) {
+        Ok(name) => name,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let boundary = match parse_boundary(request.boundary) {
+        Ok(boundary) => boundary,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let farm = match accessible_farm(&state.farm_registry, &principal, id).await {
+        Ok(farm) => Farm {
+            name,
+            boundary,
+            ..farm
+        },
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match state.farm_registry.update_farm(farm.clone()).await {
+        Ok(()) => Json(FarmResponse::from(farm)).into_response(),
+        Err(e) => write_error(e, "Farm not found", "Failed to update farm").into_response(),
+    }
+}
+
+/// Delete a farm with its fields, plots and device assignments.
+///
+/// DELETE /api/farms/:id
+pub async fn delete_farm<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid farm ID") {
+        Ok(id) => FarmId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_farm(&state.farm_registry, &principal, id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.remove_farm(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => write_error(e, "Farm not found", "Failed to delete farm").into_response(),
+    }
+}
+
+/// Create a field on a farm.
+///
+/// POST /api/farms/:id/fields
+pub async fn create_field<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(farm_id): Path<String>,
+    Json(request): Json<AreaRequest>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let farm_id = match parse_id(&farm_id, "Invalid farm ID") {
+        Ok(id) => FarmId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_farm(&state.farm_registry, &principal, farm_id).await {
+        return rejection.into_response();
+    }
+    let name = match parse_name(&request.name) {
+        Ok(name) => name,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let planted_on = match parse_planted_on(request.planted_on.as_deref()) {
+        Ok(planted_on) => planted_on,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let boundary = match parse_boundary(request.boundary) {
+        Ok(boundary) => boundary,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let field = Field {
+        id: FieldId(request.id.unwrap_or_else(Ulid::new)),
+        farm_id,
+        name,
+        boundary,
+        crop: request.crop.map(String::into_boxed_str),
+        planted_on,
+        created_at: jiff::Timestamp::now(),
+    };
+
+    match state.farm_registry.add_field(field.clone()).await {
+        Ok(()) => (StatusCode::CREATED, Json(FieldResponse::from(field))).into_response(),
+        Err(e) => write_error(e, "Farm not found", "Failed to create field").into_response(),
+    }
+}
+
+/// List the fields of a farm.
+///
+/// GET /api/farms/:id/fields
+pub async fn list_fields<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(farm_id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let farm_id = match parse_id(&farm_id, "Invalid farm ID") {
+        Ok(id) => FarmId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_farm(&state.farm_registry, &principal, farm_id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.list_fields(farm_id).await {
+        Ok(fields) => Json(ListFieldsResponse {
+            fields: fields.into_iter().map(FieldResponse::from).collect(),
+        })
+        .into_response(),
+        Err(e) => internal_error(e, "Failed to list fields").into_response(),
+    }
+}
+
+/// Export the boundaries of a farm's fields as a GeoJSON feature collection.
+///
+/// GET /api/farms/:id/fields/geojson
+pub async fn export_fields<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(farm_id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let farm_id = match parse_id(&farm_id, "Invalid farm ID") {
+        Ok(id) => FarmId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_farm(&state.farm_registry, &principal, farm_id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.list_fields(farm_id).await {
+        Ok(fields) => {
+            let collection = FeatureCollection {
+                features: fields
+                    .into_iter()
+                    .map(|field| Feature {
+                        id: field.id.0.to_string(),
+                        geometry: Geometry::from(&field.boundary),
+                        properties: FieldProperties {
+                            name: field.name.into_string(),
+                            farm_id: field.farm_id.0.to_string(),
+                            crop: field.crop.map(String::from),
+                            planted_on: field.planted_on.map(|d| d.to_string()),
+                        },
+                    })
+                    .collect(),
+            };
+            (
+                [(header::CONTENT_TYPE, "application/geo+json")],
+                Json(collection),
+            )
+                .into_response()
+        }
+        Err(e) => internal_error(e, "Failed to list fields").into_response(),
+    }
+}
+
+/// Get a field by ID.
+///
+/// GET /api/fields/:id
+pub async fn get_field<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid field ID") {
+        Ok(id) => FieldId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match accessible_field(&state.farm_registry, &principal, id).await {
+        Ok((field, _)) => Json(FieldResponse::from(field)).into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Replace a field's name, boundary, crop and planting date.
+///
+/// PUT /api/fields/:id
+pub async fn update_field<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<AreaRequest>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid field ID") {
+        Ok(id) => FieldId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    let name = match parse_name(&request.name) {
+        Ok(name) => name,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let planted_on = match parse_planted_on(request.planted_on.as_deref()) {
+        Ok(planted_on) => planted_on,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let boundary = match parse_boundary(request.boundary) {
+        Ok(boundary) => boundary,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let field = match accessible_field(&state.farm_registry, &principal, id).await {
+        Ok((field, _)) => Field {
+            name,
+            boundary,
+            crop: request.crop.map(String::into_boxed_str),
+            planted_on,
+            ..field
+        },
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match state.farm_registry.update_field(field.clone()).await {
+        Ok(()) => Json(FieldResponse::from(field)).into_response(),
+        Err(e) => write_error(e, "Field not found", "Failed to update field").into_response(),
+    }
+}
+
+/// Delete a field with its plots and device assignments.
+///
+/// DELETE /api/fields/:id
+pub async fn delete_field<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid field ID") {
+        Ok(id) => FieldId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_field(&state.farm_registry, &principal, id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.remove_field(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => write_error(e, "Field not found", "Failed to delete field").into_response(),
+    }
+}
+
+/// Create a plot in a field.
+///
+/// POST /api/fields/:id/plots
+pub async fn create_plot<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(field_id): Path<String>,
+    Json(request): Json<AreaRequest>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let field_id = match parse_id(&field_id, "Invalid field ID") {
+        Ok(id) => FieldId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_field(&state.farm_registry, &principal, field_id).await {
+        return rejection.into_response();
+    }
+    let name = match parse_name(&request.name) {
+        Ok(name) => name,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let planted_on = match parse_planted_on(request.planted_on.as_deref()) {
+        Ok(planted_on) => planted_on,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let boundary = match parse_boundary(request.boundary) {
+        Ok(boundary) => boundary,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let plot = Plot {
+        id: PlotId(request.id.unwrap_or_else(Ulid::new)),
+        field_id,
+        name,
+        boundary,
+        crop: request.crop.map(String::into_boxed_str),
+        planted_on,
+        created_at: jiff::Timestamp::now(),
+    };
+
+    match state.farm_registry.add_plot(plot.clone()).await {
+        Ok(()) => (StatusCode::CREATED, Json(PlotResponse::from(plot))).into_response(),
+        Err(e) => write_error(e, "Field not found", "Failed to create plot").into_response(),
+    }
+}
+
+/// List the plots of a field.
+///
+/// GET /api/fields/:id/plots
+pub async fn list_plots<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(field_id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let field_id = match parse_id(&field_id, "Invalid field ID") {
+        Ok(id) => FieldId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_field(&state.farm_registry, &principal, field_id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.list_plots(field_id).await {
+        Ok(plots) => Json(ListPlotsResponse {
+            plots: plots.into_iter().map(PlotResponse::from).collect(),
+        })
+        .into_response(),
+        Err(e) => internal_error(e, "Failed to list plots").into_response(),
+    }
+}
+
+/// Get a plot by ID.
+///
+/// GET /api/plots/:id
+pub async fn get_plot<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid plot ID") {
+        Ok(id) => PlotId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match accessible_plot(&state.farm_registry, &principal, id).await {
+        Ok((plot, _)) => Json(PlotResponse::from(plot)).into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Replace a plot's name, boundary, crop and planting date.
+///
+/// PUT /api/plots/:id
+pub async fn update_plot<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<AreaRequest>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid plot ID") {
+        Ok(id) => PlotId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    let name = match parse_name(&request.name) {
+        Ok(name) => name,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let planted_on = match parse_planted_on(request.planted_on.as_deref()) {
+        Ok(planted_on) => planted_on,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let boundary = match parse_boundary(request.boundary) {
+        Ok(boundary) => boundary,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let plot = match accessible_plot(&state.farm_registry, &principal, id).await {
+        Ok((plot, _)) => Plot {
+            name,
+            boundary,
+            crop: request.crop.map(String::into_boxed_str),
+            planted_on,
+            ..plot
+        },
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match state.farm_registry.update_plot(plot.clone()).await {
+        Ok(()) => Json(PlotResponse::from(plot)).into_response(),
+        Err(e) => write_error(e, "Plot not found", "Failed to update plot").into_response(),
+    }
+}
+
+/// Delete a plot with its device assignments.
+///
+/// DELETE /api/plots/:id
+pub async fn delete_plot<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid plot ID") {
+        Ok(id) => PlotId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_plot(&state.farm_registry, &principal, id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.remove_plot(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => write_error(e, "Plot not found", "Failed to delete plot").into_response(),
+    }
+}
+
+/// List the devices assigned to a plot.
+///
+/// GET /api/plots/:id/devices
+pub async fn list_plot_devices<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid plot ID") {
+        Ok(id) => PlotId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_plot(&state.farm_registry, &principal, id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.plot_devices(id).await {
+        Ok(devices) => Json(PlotDevicesResponse {
+            devices: devices.into_iter().map(|d| d.0.to_string()).collect(),
+        })
+        .into_response(),
+        Err(e) => internal_error(e, "Failed to list plot devices").into_response(),
+    }
+}
+
+/// Assign a device to a plot, moving it from any plot it was assigned to.
+///
+/// The device must belong to the farm's organization and be located within
+/// the plot's boundary.
+///
+/// PUT /api/plots/:id/devices/:device_id
+pub async fn assign_device<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path((id, device_id)): Path<(String, String)>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid plot ID") {
+        Ok(id) => PlotId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    let device_id = match parse_id(&device_id, "Invalid device ID") {
+        Ok(id) => DeviceId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    let (plot, farm) = match accessible_plot(&state.farm_registry, &principal, id).await {
+        Ok(found) => found,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let device = match state.device_registry.get(device_id).await {
+        Ok(Some(device)) if principal.can_access(device.organization) => device,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Device not found").into_response(),
+        Err(e) => return internal_error(e, "Failed to get device").into_response(),
+    };
+    if device.organization != farm.organization {
+        return (
+            StatusCode::CONFLICT,
+            "Device belongs to another organization than the farm",
+        )
+            .into_response();
+    }
+    if !geo::covers(&plot.boundary, device.location) {
+        return (
+            StatusCode::CONFLICT,
+            "Device location is outside the plot boundary",
+        )
+            .into_response();
+    }
+
+    match state.farm_registry.assign_device(device_id, id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => write_error(e, "Plot not found", "Failed to assign device").into_response(),
+    }
+}
+
+/// Remove a device from a plot.
+///
+/// DELETE /api/plots/:id/devices/:device_id
+pub async fn unassign_device<F, Dev>(
+    State(state): State<FarmState<F, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Path((id, device_id)): Path<(String, String)>,
+) -> impl IntoResponse
+where
+    F: FarmRegistry,
+    Dev: DeviceRegistry,
+{
+    let id = match parse_id(&id, "Invalid plot ID") {
+        Ok(id) => PlotId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    let device_id = match parse_id(&device_id, "Invalid device ID") {
+        Ok(id) => DeviceId(id),
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(rejection) = accessible_plot(&state.farm_registry, &principal, id).await {
+        return rejection.into_response();
+    }
+
+    match state.farm_registry.plot_devices(id).await {
+        Ok(devices) if devices.contains(&device_id) => {}
+        Ok(_) => {
+            return (StatusCode::NOT_FOUND, "Device is not assigned to the plot").into_response();
+        }
+        Err(e) => return internal_error(e, "Failed to list plot devices").into_response(),
+    }
+
+    match state.farm_registry.unassign_device(device_id).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => internal_error(e, "Failed to unassign device").into_response(),
+    }
+}
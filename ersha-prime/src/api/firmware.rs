@@ -0,0 +1,161 @@
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use ersha_core::{FirmwareId, FirmwareImage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ulid::Ulid;
+
+use crate::registry::{DeviceRegistry, DispatcherRegistry};
+
+use super::{ApiState, auth::Principal};
+
+/// Largest firmware image accepted for upload.
+pub const MAX_FIRMWARE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Length of an Ed25519 signature.
+const SIGNATURE_LEN: usize = 64;
+
+/// Query parameters of a firmware upload. The image itself is the request body.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadFirmwareQuery {
+    /// Device model the image is built for.
+    pub model: String,
+    pub version: u32,
+    /// Hex encoded Ed25519 signature of the release key.
+    pub signature: String,
+}
+
+/// Response body for a firmware image.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FirmwareResponse {
+    pub id: String,
+    pub model: String,
+    pub version: u32,
+    pub size: u32,
+    /// Hex encoded SHA-256 digest of the image.
+    pub sha256: String,
+    pub created_at: String,
+}
+
+impl From<FirmwareImage> for FirmwareResponse {
+    fn from(image: FirmwareImage) -> Self {
+        Self {
+            id: image.id.0.to_string(),
+            model: image.model.into_string(),
+            version: image.version,
+            size: image.size,
+            sha256: encode_hex(&image.sha256),
+            created_at: image.created_at.to_string(),
+        }
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Publish a firmware image for a device model.
+///
+/// Firmware is shared by all organizations, so only keys spanning all of
+/// them may publish it.
+///
+/// POST /api/firmware?model=&version=&signature=
+pub async fn upload_firmware<D, Dev>(
+    State(state): State<ApiState<D, Dev>>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<UploadFirmwareQuery>,
+    body: Bytes,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    if let Err(rejection) = principal.require_all_organizations() {
+        return rejection.into_response();
+    }
+
+    if query.model.is_empty() || body.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Model and image are required").into_response();
+    }
+
+    let Some(signature) = decode_hex(&query.signature).filter(|s| s.len() == SIGNATURE_LEN) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Signature must be 64 hex encoded bytes",
+        )
+            .into_response();
+    };
+
+    let image = FirmwareImage {
+        id: FirmwareId(Ulid::new()),
+        model: query.model.into_boxed_str(),
+        version: query.version,
+        size: body.len() as u32,
+        sha256: Sha256::digest(&body).into(),
+        signature: signature.into_boxed_slice(),
+        created_at: jiff::Timestamp::now(),
+    };
+
+    match state
+        .device_registry
+        .add_firmware(image.clone(), body.to_vec())
+        .await
+    {
+        Ok(()) => (StatusCode::CREATED, Json(FirmwareResponse::from(image))).into_response(),
+        Err(e) => {
+            let err_str = e.to_string();
+            if err_str.contains("already exists") || err_str.contains("AlreadyExists") {
+                (
+                    StatusCode::CONFLICT,
+                    "Firmware version already published for this model",
+                )
+                    .into_response()
+            } else {
+                tracing::error!(error = ?e, "Failed to publish firmware");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to publish firmware",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
+
+/// List published firmware images.
+///
+/// GET /api/firmware
+pub async fn list_firmware<D, Dev>(State(state): State<ApiState<D, Dev>>) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+{
+    match state.device_registry.list_firmware().await {
+        Ok(mut images) => {
+            images.sort_by(|a, b| a.model.cmp(&b.model).then(b.version.cmp(&a.version)));
+            let images: Vec<FirmwareResponse> =
+                images.into_iter().map(FirmwareResponse::from).collect();
+            (StatusCode::OK, Json(images)).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to list firmware");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list firmware").into_response()
+        }
+    }
+}
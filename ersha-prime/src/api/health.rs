@@ -0,0 +1,486 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use ersha_core::{AlertSeverity, AlertType, DeviceId, DisconnectionReason, DispatcherId};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    health::{
+        AlertSubject, ConnectionChange, ConnectionEvent, DeviceHealth, DispatcherHealth,
+        DispatcherStatusRecord, OfflineAlert, ReportedAlert,
+    },
+    registry::{DeviceRegistry, DispatcherRegistry, HealthRegistry},
+};
+
+use super::auth::Principal;
+
+/// Shared state for health handlers. Dispatchers and devices are looked up
+/// to check the caller may see them.
+#[derive(Clone)]
+pub struct HealthState<D, Dev, H>
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+    H: HealthRegistry,
+{
+    pub dispatcher_registry: D,
+    pub device_registry: Dev,
+    pub health_registry: H,
+}
+
+type Rejection = (StatusCode, &'static str);
+
+/// Most history entries returned when no limit is given.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+/// Most history entries returned at once.
+pub const MAX_HISTORY_LIMIT: usize = 1000;
+
+/// Query parameters for status, connection and alert history.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct HistoryQuery {
+    /// Number of entries to return, newest first (default 100, max 1000)
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_HISTORY_LIMIT)
+            .min(MAX_HISTORY_LIMIT)
+    }
+}
+
+/// Response body for a status report a dispatcher sent.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusReportResponse {
+    pub connected_devices: u32,
+    pub uptime_seconds: u64,
+    pub pending_uploads: u32,
+    /// Duplicate readings the dispatcher dropped since it started.
+    pub duplicates_dropped: u64,
+    /// When the dispatcher sent the report, by its own clock.
+    pub reported_at: String,
+    pub received_at: String,
+}
+
+impl From<DispatcherStatusRecord> for StatusReportResponse {
+    fn from(record: DispatcherStatusRecord) -> Self {
+        Self {
+            connected_devices: record.connected_devices,
+            uptime_seconds: record.uptime_seconds,
+            pending_uploads: record.pending_uploads,
+            duplicates_dropped: record.duplicates_dropped,
+            reported_at: record.reported_at.to_string(),
+            received_at: record.received_at.to_string(),
+        }
+    }
+}
+
+/// Response body for a dispatcher's health. A dispatcher that never
+/// reported its status is offline with no `last_seen`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DispatcherHealthResponse {
+    pub dispatcher_id: String,
+    pub online: bool,
+    pub last_seen: Option<String>,
+    pub latest_status: Option<StatusReportResponse>,
+}
+
+/// Response body for a device's health. A device no data arrived from yet
+/// is offline with no `last_seen`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceHealthResponse {
+    pub device_id: String,
+    pub online: bool,
+    pub last_seen: Option<String>,
+    /// Dispatcher the device's data last arrived through.
+    pub dispatcher_id: Option<String>,
+}
+
+impl DeviceHealthResponse {
+    fn new(device_id: DeviceId, health: Option<DeviceHealth>) -> Self {
+        Self {
+            device_id: device_id.0.to_string(),
+            online: health.is_some_and(|h| h.online),
+            last_seen: health.map(|h| h.last_seen.to_string()),
+            dispatcher_id: health.map(|h| h.dispatcher_id.0.to_string()),
+        }
+    }
+}
+
+/// Response body for a device coming online or going offline.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionEventResponse {
+    pub dispatcher_id: String,
+    /// "connected" or "disconnected".
+    pub event: String,
+    /// Why the device disconnected: "timeout", "graceful_close", "error",
+    /// "unknown", "unknown_device", "authentication_failed" or
+    /// "replay_detected".
+    pub reason: Option<String>,
+    /// Details of an "error" disconnection.
+    pub message: Option<String>,
+    pub timestamp: String,
+}
+
+impl From<ConnectionEvent> for ConnectionEventResponse {
+    fn from(event: ConnectionEvent) -> Self {
+        let (name, reason) = match event.change {
+            ConnectionChange::Connected => ("connected", None),
+            ConnectionChange::Disconnected(reason) => ("disconnected", reason),
+        };
+        let (reason, message) = match reason {
+            None => (None, None),
+            Some(DisconnectionReason::Timeout) => (Some("timeout"), None),
+            Some(DisconnectionReason::GracefulClose) => (Some("graceful_close"), None),
+            Some(DisconnectionReason::Error(message)) => (Some("error"), Some(message.into())),
+            Some(DisconnectionReason::Unknown) => (Some("unknown"), None),
+            Some(DisconnectionReason::UnknownDevice) => (Some("unknown_device"), None),
+            Some(DisconnectionReason::AuthenticationFailed) => {
+                (Some("authentication_failed"), None)
+            }
+            Some(DisconnectionReason::ReplayDetected) => (Some("replay_detected"), None),
+        };
+
+        Self {
+            dispatcher_id: event.dispatcher_id.0.to_string(),
+            event: name.to_string(),
+            reason: reason.map(String::from),
+            message,
+            timestamp: event.timestamp.to_string(),
+        }
+    }
+}
+
+/// Response body for an alert prime raised when a dispatcher or device went
+/// silent, or one a dispatcher reported.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthAlertResponse {
+    pub id: String,
+    /// "critical_battery", "sensor_failure", "device_offline",
+    /// "communication_error", "security_event" or a custom type.
+    pub alert_type: String,
+    /// "critical", "warning" or "info".
+    pub severity: String,
+    pub dispatcher_id: String,
+    /// Set when the alert is for a device rather than the dispatcher.
+    pub device_id: Option<String>,
+    /// Set for alerts a dispatcher reported.
+    pub message: Option<String>,
+    /// Set for alerts prime raised for a silent dispatcher or device.
+    pub last_seen: Option<String>,
+    pub raised_at: String,
+}
+
+impl From<OfflineAlert> for HealthAlertResponse {
+    fn from(alert: OfflineAlert) -> Self {
+        Self {
+            id: alert.id.0.to_string(),
+            alert_type: alert_type_name(&alert.alert_type()),
+            severity: severity_name(alert.severity()).to_string(),
+            dispatcher_id: alert.dispatcher_id.0.to_string(),
+            device_id: alert.device_id.map(|id| id.0.to_string()),
+            message: None,
+            last_seen: Some(alert.last_seen.to_string()),
+            raised_at: alert.raised_at.to_string(),
+        }
+    }
+}
+
+impl From<ReportedAlert> for HealthAlertResponse {
+    fn from(alert: ReportedAlert) -> Self {
+        Self {
+            id: alert.id.0.to_string(),
+            alert_type: alert_type_name(&alert.alert_type),
+            severity: severity_name(alert.severity).to_string(),
+            dispatcher_id: alert.dispatcher_id.0.to_string(),
+            device_id: alert.device_id.map(|id| id.0.to_string()),
+            message: Some(alert.message.into()),
+            last_seen: None,
+            raised_at: alert.raised_at.to_string(),
+        }
+    }
+}
+
+fn alert_type_name(alert_type: &AlertType) -> String {
+    match alert_type {
+        AlertType::CriticalBattery => "critical_battery".to_string(),
+        AlertType::SensorFailure => "sensor_failure".to_string(),
+        AlertType::DeviceOffline => "device_offline".to_string(),
+        AlertType::CommunicationError => "communication_error".to_string(),
+        AlertType::SecurityEvent => "security_event".to_string(),
+        AlertType::Custom(name) => name.to_string(),
+    }
+}
+
+fn severity_name(severity: AlertSeverity) -> &'static str {
+    match severity {
+        AlertSeverity::Critical => "critical",
+        AlertSeverity::Warning => "warning",
+        AlertSeverity::Info => "info",
+    }
+}
+
+fn parse_id(id: &str, message: &'static str) -> Result<Ulid, Rejection> {
+    id.parse::<Ulid>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, message))
+}
+
+fn internal_error(e: impl std::fmt::Debug, message: &'static str) -> Rejection {
+    tracing::error!(error = ?e, "{message}");
+    (StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+/// The dispatcher with `id`, if the caller may see it.
+async fn check_dispatcher_access<D: DispatcherRegistry>(
+    dispatchers: &D,
+    principal: &Principal,
+    id: &str,
+) -> Result<DispatcherId, Rejection> {
+    let id = DispatcherId(parse_id(id, "Invalid dispatcher ID")?);
+    match dispatchers.get(id).await {
+        Ok(Some(dispatcher)) if principal.can_access(dispatcher.organization) => Ok(id),
+        Ok(_) => Err((StatusCode::NOT_FOUND, "Dispatcher not found")),
+        Err(e) => Err(internal_error(e, "Failed to get dispatcher")),
+    }
+}
+
+/// The device with `id`, if the caller may see it.
+async fn check_device_access<Dev: DeviceRegistry>(
+    devices: &Dev,
+    principal: &Principal,
+    id: &str,
+) -> Result<DeviceId, Rejection> {
+    let id = DeviceId(parse_id(id, "Invalid device ID")?);
+    match devices.get(id).await {
+        Ok(Some(device)) if principal.can_access(device.organization) => Ok(id),
+        Ok(_) => Err((StatusCode::NOT_FOUND, "Device not found")),
+        Err(e) => Err(internal_error(e, "Failed to get device")),
+    }
+}
+
+/// Get whether a dispatcher is online, when it was last heard from and its
+/// latest status report.
+///
+/// GET /api/dispatchers/:id/health
+pub async fn dispatcher_health<D, Dev, H>(
+    State(state): State<HealthState<D, Dev, H>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+    H: HealthRegistry,
+{
+    let id = match check_dispatcher_access(&state.dispatcher_registry, &principal, &id).await {
+        Ok(id) => id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let health: Option<DispatcherHealth> = match state.health_registry.dispatcher_health(id).await {
+        Ok(health) => health,
+        Err(e) => return internal_error(e, "Failed to get dispatcher health").into_response(),
+    };
+    let latest_status = match state.health_registry.list_statuses(id, 1).await {
+        Ok(statuses) => statuses.into_iter().next(),
+        Err(e) => return internal_error(e, "Failed to get dispatcher health").into_response(),
+    };
+
+    let response = DispatcherHealthResponse {
+        dispatcher_id: id.0.to_string(),
+        online: health.is_some_and(|h| h.online),
+        last_seen: health.map(|h| h.last_seen.to_string()),
+        latest_status: latest_status.map(StatusReportResponse::from),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// List the status reports a dispatcher sent, newest first.
+///
+/// GET /api/dispatchers/:id/statuses
+pub async fn dispatcher_statuses<D, Dev, H>(
+    State(state): State<HealthState<D, Dev, H>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+    H: HealthRegistry,
+{
+    let id = match check_dispatcher_access(&state.dispatcher_registry, &principal, &id).await {
+        Ok(id) => id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match state.health_registry.list_statuses(id, query.limit()).await {
+        Ok(statuses) => {
+            let statuses: Vec<StatusReportResponse> = statuses
+                .into_iter()
+                .map(StatusReportResponse::from)
+                .collect();
+            (StatusCode::OK, Json(statuses)).into_response()
+        }
+        Err(e) => internal_error(e, "Failed to list dispatcher statuses").into_response(),
+    }
+}
+
+/// List alerts for a dispatcher and the devices last seen through it,
+/// newest first. Alerts dispatchers reported are only listed for the
+/// organization they were reported in.
+///
+/// GET /api/dispatchers/:id/alerts
+pub async fn dispatcher_alerts<D, Dev, H>(
+    State(state): State<HealthState<D, Dev, H>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+    H: HealthRegistry,
+{
+    let id = match check_dispatcher_access(&state.dispatcher_registry, &principal, &id).await {
+        Ok(id) => id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    list_alerts(
+        &state.health_registry,
+        &principal,
+        AlertSubject::Dispatcher(id),
+        &query,
+    )
+    .await
+}
+
+/// Get whether a device is online and when its data last arrived.
+///
+/// GET /api/devices/:id/health
+pub async fn device_health<D, Dev, H>(
+    State(state): State<HealthState<D, Dev, H>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+    H: HealthRegistry,
+{
+    let id = match check_device_access(&state.device_registry, &principal, &id).await {
+        Ok(id) => id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match state.health_registry.device_health(id).await {
+        Ok(health) => (StatusCode::OK, Json(DeviceHealthResponse::new(id, health))).into_response(),
+        Err(e) => internal_error(e, "Failed to get device health").into_response(),
+    }
+}
+
+/// List the times a device connected and disconnected, newest first.
+///
+/// GET /api/devices/:id/connections
+pub async fn device_connections<D, Dev, H>(
+    State(state): State<HealthState<D, Dev, H>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+    H: HealthRegistry,
+{
+    let id = match check_device_access(&state.device_registry, &principal, &id).await {
+        Ok(id) => id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match state
+        .health_registry
+        .list_connections(id, query.limit())
+        .await
+    {
+        Ok(events) => {
+            let events: Vec<ConnectionEventResponse> = events
+                .into_iter()
+                .map(ConnectionEventResponse::from)
+                .collect();
+            (StatusCode::OK, Json(events)).into_response()
+        }
+        Err(e) => internal_error(e, "Failed to list device connections").into_response(),
+    }
+}
+
+/// List alerts for a device, newest first. Alerts dispatchers reported are
+/// only listed for the organization they were reported in.
+///
+/// GET /api/devices/:id/alerts
+pub async fn device_alerts<D, Dev, H>(
+    State(state): State<HealthState<D, Dev, H>>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse
+where
+    D: DispatcherRegistry,
+    Dev: DeviceRegistry,
+    H: HealthRegistry,
+{
+    let id = match check_device_access(&state.device_registry, &principal, &id).await {
+        Ok(id) => id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    list_alerts(
+        &state.health_registry,
+        &principal,
+        AlertSubject::Device(id),
+        &query,
+    )
+    .await
+}
+
+/// Offline alerts and alerts reported by dispatchers, merged newest first.
+async fn list_alerts<H: HealthRegistry>(
+    registry: &H,
+    principal: &Principal,
+    subject: AlertSubject,
+    query: &HistoryQuery,
+) -> Response {
+    let limit = query.limit();
+    let offline = match registry.list_alerts(subject, limit).await {
+        Ok(alerts) => alerts,
+        Err(e) => return internal_error(e, "Failed to list alerts").into_response(),
+    };
+    let reported = match registry
+        .list_reported_alerts(subject, principal.organization, limit)
+        .await
+    {
+        Ok(alerts) => alerts,
+        Err(e) => return internal_error(e, "Failed to list alerts").into_response(),
+    };
+
+    let mut alerts: Vec<(jiff::Timestamp, HealthAlertResponse)> = offline
+        .into_iter()
+        .map(|alert| (alert.raised_at, alert.into()))
+        .chain(
+            reported
+                .into_iter()
+                .map(|alert| (alert.raised_at, alert.into())),
+        )
+        .collect();
+    alerts.sort_by(|a, b| b.0.cmp(&a.0));
+    alerts.truncate(limit);
+
+    let alerts: Vec<HealthAlertResponse> = alerts.into_iter().map(|(_, alert)| alert).collect();
+    (StatusCode::OK, Json(alerts)).into_response()
+}
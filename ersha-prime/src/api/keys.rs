@@ -0,0 +1,192 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    auth::{ApiKey, ApiKeyId, Role, generate_key},
+    registry::ApiKeyRegistry,
+};
+
+use super::auth::Principal;
+
+/// Request body for creating an API key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    /// Who or what the key is for.
+    pub name: String,
+    /// One of "viewer", "operator" or "admin".
+    pub role: String,
+    /// Organization the key is limited to. Defaults to the caller's own.
+    #[serde(default)]
+    pub organization: Option<Ulid>,
+}
+
+/// Response body for an API key. The secret is never returned.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyResponse {
+    pub id: String,
+    pub name: String,
+    pub role: String,
+    pub organization: Option<String>,
+    pub created_at: String,
+    pub revoked_at: Option<String>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id.0.to_string(),
+            name: key.name,
+            role: key.role.as_str().to_string(),
+            organization: key.organization.map(|o| o.0.to_string()),
+            created_at: key.created_at.to_string(),
+            revoked_at: key.revoked_at.map(|t| t.to_string()),
+        }
+    }
+}
+
+/// Response body for a newly created API key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub key: ApiKeyResponse,
+    /// The secret to send as a bearer token. Only returned once.
+    pub secret: String,
+}
+
+/// Response body for list of API keys.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListApiKeysResponse {
+    pub keys: Vec<ApiKeyResponse>,
+}
+
+/// Create an API key.
+///
+/// Keys limited to an organization can only create keys for it.
+///
+/// POST /api/keys
+pub async fn create_key<K>(
+    State(keys): State<K>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<CreateApiKeyRequest>,
+) -> impl IntoResponse
+where
+    K: ApiKeyRegistry,
+{
+    let role = match request.role.parse::<Role>() {
+        Ok(role) => role,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let organization = match principal.owner_for(request.organization) {
+        Ok(organization) => organization,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let secret = generate_key();
+    let mut key = ApiKey::new(request.name, role, &secret);
+    if let Some(organization) = organization {
+        key = key.with_organization(organization);
+    }
+
+    match keys.add(key.clone()).await {
+        Ok(()) => (
+            StatusCode::CREATED,
+            Json(CreatedApiKeyResponse {
+                key: key.into(),
+                secret,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to create API key");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create API key",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// List API keys, including revoked ones.
+///
+/// GET /api/keys
+pub async fn list_keys<K>(
+    State(keys): State<K>,
+    Extension(principal): Extension<Principal>,
+) -> impl IntoResponse
+where
+    K: ApiKeyRegistry,
+{
+    match keys.list().await {
+        Ok(keys) => Json(ListApiKeysResponse {
+            keys: keys
+                .into_iter()
+                .filter(|key| principal.can_access(key.organization))
+                .map(ApiKeyResponse::from)
+                .collect(),
+        })
+        .into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to list API keys");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list API keys").into_response()
+        }
+    }
+}
+
+/// Revoke an API key.
+///
+/// POST /api/keys/:id/revoke
+pub async fn revoke_key<K>(
+    State(keys): State<K>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    K: ApiKeyRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid API key ID").into_response(),
+    };
+
+    if principal.organization.is_some() {
+        match keys.list().await {
+            Ok(list) => {
+                let visible = list
+                    .iter()
+                    .any(|key| key.id.0 == ulid && principal.can_access(key.organization));
+                if !visible {
+                    return (StatusCode::NOT_FOUND, "Active API key not found").into_response();
+                }
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "Failed to list API keys");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to revoke API key",
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    match keys.revoke(ApiKeyId(ulid), jiff::Timestamp::now()).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Active API key not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to revoke API key");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to revoke API key",
+            )
+                .into_response()
+        }
+    }
+}
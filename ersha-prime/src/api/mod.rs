@@ -1,12 +1,31 @@
+pub mod auth;
+pub mod commands;
 pub mod devices;
 pub mod dispatchers;
+pub mod farms;
+pub mod firmware;
+pub mod health;
+pub mod keys;
+pub mod organizations;
+pub mod readings;
 
 use axum::{
     Router,
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    middleware::from_fn_with_state,
+    routing::{delete, get, patch, post, put},
 };
 
-use crate::registry::{DeviceRegistry, DispatcherRegistry};
+use crate::{
+    auth::Role,
+    registry::{
+        ApiKeyRegistry, DeviceRegistry, DispatcherRegistry, FarmRegistry, HealthRegistry,
+        OrganizationRegistry, ReadingRegistry,
+    },
+};
+use auth::Authorizer;
+use farms::FarmState;
+use health::HealthState;
 
 /// Shared state for API handlers.
 #[derive(Clone)]
@@ -20,35 +39,293 @@ where
 }
 
 /// Create the full API router with all endpoints.
-pub fn api_router<D, Dev>(dispatcher_registry: D, device_registry: Dev) -> Router
+///
+/// Reads need a viewer key, changes to dispatchers, devices, commands,
+/// firmware and farms an operator key, and device credentials and API key management
+/// an admin key. With `auth_enabled` false no key is checked.
+///
+/// Keys limited to an organization only see and change what it owns.
+pub fn api_router<D, Dev, R, H, K, O, F>(
+    dispatcher_registry: D,
+    device_registry: Dev,
+    reading_registry: R,
+    health_registry: H,
+    key_registry: K,
+    organization_registry: O,
+    farm_registry: F,
+    auth_enabled: bool,
+) -> Router
 where
     D: DispatcherRegistry,
     Dev: DeviceRegistry,
+    R: ReadingRegistry,
+    H: HealthRegistry,
+    K: ApiKeyRegistry,
+    O: OrganizationRegistry,
+    F: FarmRegistry,
 {
+    let farm_state = FarmState {
+        farm_registry,
+        device_registry: device_registry.clone(),
+    };
+    let health_state = HealthState {
+        dispatcher_registry: dispatcher_registry.clone(),
+        device_registry: device_registry.clone(),
+        health_registry,
+    };
     let state = ApiState {
         dispatcher_registry,
         device_registry,
     };
 
+    let authorizer = Authorizer::new(key_registry.clone(), auth_enabled);
+    let require = |role| from_fn_with_state(authorizer.require(role), auth::authorize::<K>);
+
+    let keys = Router::new()
+        .route(
+            "/api/keys",
+            post(keys::create_key::<K>).route_layer(require(Role::Admin)),
+        )
+        .route(
+            "/api/keys",
+            get(keys::list_keys::<K>).route_layer(require(Role::Admin)),
+        )
+        .route(
+            "/api/keys/{id}/revoke",
+            post(keys::revoke_key::<K>).route_layer(require(Role::Admin)),
+        )
+        .with_state(key_registry);
+
+    let organizations = Router::new()
+        .route(
+            "/api/organizations",
+            post(organizations::create_organization::<O>).route_layer(require(Role::Admin)),
+        )
+        .route(
+            "/api/organizations",
+            get(organizations::list_organizations::<O>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/organizations/{id}",
+            get(organizations::get_organization::<O>).route_layer(require(Role::Viewer)),
+        )
+        .with_state(organization_registry);
+
+    let readings = Router::new()
+        .route(
+            "/api/readings/rollup",
+            get(readings::rollup::<R>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/readings/rollup/geojson",
+            get(readings::export_rollup::<R>).route_layer(require(Role::Viewer)),
+        )
+        .with_state(reading_registry);
+
+    let farms = Router::new()
+        .route(
+            "/api/farms",
+            get(farms::list_farms::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/farms",
+            post(farms::create_farm::<F, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/farms/{id}",
+            get(farms::get_farm::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/farms/{id}",
+            put(farms::update_farm::<F, Dev>)
+                .delete(farms::delete_farm::<F, Dev>)
+                .route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/farms/{id}/fields",
+            get(farms::list_fields::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/farms/{id}/fields",
+            post(farms::create_field::<F, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/farms/{id}/fields/geojson",
+            get(farms::export_fields::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/fields/{id}",
+            get(farms::get_field::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/fields/{id}",
+            put(farms::update_field::<F, Dev>)
+                .delete(farms::delete_field::<F, Dev>)
+                .route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/fields/{id}/plots",
+            get(farms::list_plots::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/fields/{id}/plots",
+            post(farms::create_plot::<F, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/plots/{id}",
+            get(farms::get_plot::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/plots/{id}",
+            put(farms::update_plot::<F, Dev>)
+                .delete(farms::delete_plot::<F, Dev>)
+                .route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/plots/{id}/devices",
+            get(farms::list_plot_devices::<F, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/plots/{id}/devices/{device_id}",
+            put(farms::assign_device::<F, Dev>)
+                .delete(farms::unassign_device::<F, Dev>)
+                .route_layer(require(Role::Operator)),
+        )
+        .with_state(farm_state);
+
+    let health = Router::new()
+        .route(
+            "/api/dispatchers/{id}/health",
+            get(health::dispatcher_health::<D, Dev, H>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/dispatchers/{id}/statuses",
+            get(health::dispatcher_statuses::<D, Dev, H>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/dispatchers/{id}/alerts",
+            get(health::dispatcher_alerts::<D, Dev, H>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/devices/{id}/health",
+            get(health::device_health::<D, Dev, H>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/devices/{id}/connections",
+            get(health::device_connections::<D, Dev, H>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/devices/{id}/alerts",
+            get(health::device_alerts::<D, Dev, H>).route_layer(require(Role::Viewer)),
+        )
+        .with_state(health_state);
+
     Router::new()
         .route(
             "/api/dispatchers",
-            post(dispatchers::register_dispatcher::<D, Dev>),
+            post(dispatchers::register_dispatcher::<D, Dev>).route_layer(require(Role::Operator)),
         )
         .route(
             "/api/dispatchers",
-            get(dispatchers::list_dispatchers::<D, Dev>),
+            get(dispatchers::list_dispatchers::<D, Dev>).route_layer(require(Role::Viewer)),
         )
         .route(
             "/api/dispatchers/{id}",
-            get(dispatchers::get_dispatcher::<D, Dev>),
+            get(dispatchers::get_dispatcher::<D, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/dispatchers/{id}",
+            patch(dispatchers::update_dispatcher::<D, Dev>).route_layer(require(Role::Operator)),
         )
         .route(
             "/api/dispatchers/{id}/suspend",
-            post(dispatchers::suspend_dispatcher::<D, Dev>),
+            post(dispatchers::suspend_dispatcher::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/dispatchers/{id}/reactivate",
+            post(dispatchers::reactivate_dispatcher::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/dispatchers/{id}/decommission",
+            post(dispatchers::decommission_dispatcher::<D, Dev>)
+                .route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices",
+            post(devices::register_device::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices",
+            get(devices::list_devices::<D, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/devices/{id}",
+            get(devices::get_device::<D, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/devices/{id}",
+            patch(devices::update_device::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices/{id}/suspend",
+            post(devices::suspend_device::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices/{id}/reactivate",
+            post(devices::reactivate_device::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices/{id}/decommission",
+            post(devices::decommission_device::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices/{id}/sensors",
+            post(devices::add_sensors::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices/{id}/sensors/{sensor_id}",
+            delete(devices::remove_sensor::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/devices/{id}/key",
+            post(devices::issue_device_key::<D, Dev>).route_layer(require(Role::Admin)),
+        )
+        .route(
+            "/api/devices/{id}/commands",
+            get(commands::list_commands::<D, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/devices/{id}/commands",
+            post(commands::issue_command::<D, Dev>).route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/commands/{id}",
+            get(commands::get_command::<D, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/sensors/{id}/calibration",
+            get(devices::get_calibration::<D, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/sensors/{id}/calibration",
+            put(devices::set_calibration::<D, Dev>)
+                .delete(devices::delete_calibration::<D, Dev>)
+                .route_layer(require(Role::Operator)),
+        )
+        .route(
+            "/api/firmware",
+            get(firmware::list_firmware::<D, Dev>).route_layer(require(Role::Viewer)),
+        )
+        .route(
+            "/api/firmware",
+            post(firmware::upload_firmware::<D, Dev>)
+                .layer(DefaultBodyLimit::max(firmware::MAX_FIRMWARE_SIZE))
+                .route_layer(require(Role::Operator)),
         )
-        .route("/api/devices", post(devices::register_device::<D, Dev>))
-        .route("/api/devices", get(devices::list_devices::<D, Dev>))
-        .route("/api/devices/{id}", get(devices::get_device::<D, Dev>))
         .with_state(state)
+        .merge(keys)
+        .merge(organizations)
+        .merge(farms)
+        .merge(readings)
+        .merge(health)
 }
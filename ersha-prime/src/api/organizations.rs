@@ -0,0 +1,158 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use ersha_core::{Organization, OrganizationId};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::registry::OrganizationRegistry;
+
+use super::auth::Principal;
+
+/// Request body for creating an organization.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateOrganizationRequest {
+    /// Optional ID. If not provided, a new ULID will be generated.
+    pub id: Option<Ulid>,
+    /// Unique name of the organization.
+    pub name: String,
+}
+
+/// Response body for an organization.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationResponse {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+impl From<Organization> for OrganizationResponse {
+    fn from(o: Organization) -> Self {
+        Self {
+            id: o.id.0.to_string(),
+            name: o.name.into_string(),
+            created_at: o.created_at.to_string(),
+        }
+    }
+}
+
+/// Response body for list of organizations.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListOrganizationsResponse {
+    pub organizations: Vec<OrganizationResponse>,
+}
+
+/// Create an organization. Only keys spanning all organizations may do so.
+///
+/// POST /api/organizations
+pub async fn create_organization<O>(
+    State(organizations): State<O>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<CreateOrganizationRequest>,
+) -> impl IntoResponse
+where
+    O: OrganizationRegistry,
+{
+    if let Err(rejection) = principal.require_all_organizations() {
+        return rejection.into_response();
+    }
+
+    let name = request.name.trim();
+    if name.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Name is required").into_response();
+    }
+
+    let organization = Organization {
+        id: OrganizationId(request.id.unwrap_or_else(Ulid::new)),
+        name: name.into(),
+        created_at: jiff::Timestamp::now(),
+    };
+
+    match organizations.add(organization.clone()).await {
+        Ok(()) => (
+            StatusCode::CREATED,
+            Json(OrganizationResponse::from(organization)),
+        )
+            .into_response(),
+        Err(e) => {
+            let err_str = e.to_string();
+            if err_str.contains("already exists") || err_str.contains("AlreadyExists") {
+                (StatusCode::CONFLICT, "Organization already exists").into_response()
+            } else {
+                tracing::error!(error = ?e, "Failed to create organization");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to create organization",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
+
+/// List the organizations visible to the caller.
+///
+/// GET /api/organizations
+pub async fn list_organizations<O>(
+    State(organizations): State<O>,
+    Extension(principal): Extension<Principal>,
+) -> impl IntoResponse
+where
+    O: OrganizationRegistry,
+{
+    match organizations.list().await {
+        Ok(list) => Json(ListOrganizationsResponse {
+            organizations: list
+                .into_iter()
+                .filter(|o| principal.can_access(Some(o.id)))
+                .map(OrganizationResponse::from)
+                .collect(),
+        })
+        .into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to list organizations");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list organizations",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Get an organization by ID.
+///
+/// GET /api/organizations/:id
+pub async fn get_organization<O>(
+    State(organizations): State<O>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> impl IntoResponse
+where
+    O: OrganizationRegistry,
+{
+    let ulid = match id.parse::<Ulid>() {
+        Ok(ulid) => ulid,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid organization ID").into_response(),
+    };
+
+    match organizations.get(OrganizationId(ulid)).await {
+        Ok(Some(organization)) if principal.can_access(Some(organization.id)) => (
+            StatusCode::OK,
+            Json(OrganizationResponse::from(organization)),
+        )
+            .into_response(),
+        Ok(_) => (StatusCode::NOT_FOUND, "Organization not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to get organization");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get organization",
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use ersha_core::{FieldId, H3Cell};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    geo::{self, Feature, FeatureCollection},
+    registry::{ReadingRegistry, filter::ReadingFilter, rollup::CellRollup},
+};
+
+use super::{auth::Principal, devices::parse_bbox};
+
+type Rejection = (StatusCode, &'static str);
+
+/// Window rolled up when a query gives no start.
+const DEFAULT_ROLLUP_WINDOW: jiff::SignedDuration = jiff::SignedDuration::from_hours(7 * 24);
+
+/// Longest window a rollup may cover.
+const MAX_ROLLUP_WINDOW: jiff::SignedDuration = jiff::SignedDuration::from_hours(366 * 24);
+
+/// Query parameters for rolling readings up to coarser cells.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RollupQuery {
+    /// H3 resolution of the cells readings are aggregated to (0-15)
+    pub resolution: u8,
+    /// Start of the window (ISO 8601 timestamp, default 7 days before its end)
+    pub after: Option<String>,
+    /// End of the window (ISO 8601 timestamp, default now)
+    pub before: Option<String>,
+    /// Metrics to aggregate, comma separated, e.g. "soil_moisture,rainfall"
+    pub metrics: Option<String>,
+    /// Filter by ancestor H3 cell, matching locations within it
+    pub within: Option<u64>,
+    /// Filter by bounding box: "south,west,north,east" in degrees
+    pub bbox: Option<String>,
+    /// Filter by field (ULID), matching devices assigned to its plots
+    pub field: Option<String>,
+}
+
+/// Statistics of one metric within one cell.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CellRollupResponse {
+    /// H3 cell at the requested resolution.
+    pub cell: u64,
+    pub metric: String,
+    pub readings: u64,
+    /// Number of distinct devices the readings came from.
+    pub devices: u64,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl From<CellRollup> for CellRollupResponse {
+    fn from(rollup: CellRollup) -> Self {
+        Self {
+            cell: rollup.cell.0,
+            metric: rollup.metric_type.as_str().to_string(),
+            readings: rollup.readings,
+            devices: rollup.devices,
+            mean: rollup.mean,
+            min: rollup.min,
+            max: rollup.max,
+        }
+    }
+}
+
+/// Response body for a rollup, ordered by cell then metric.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RollupResponse {
+    pub resolution: u8,
+    pub rollups: Vec<CellRollupResponse>,
+}
+
+/// Statistics of one metric in the GeoJSON export.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricStatistics {
+    pub readings: u64,
+    pub devices: u64,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Properties of a cell in the GeoJSON export, keyed by metric name.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CellProperties {
+    pub cell: u64,
+    pub metrics: BTreeMap<String, MetricStatistics>,
+}
+
+/// Build the filter of a rollup, limited to what the caller may see.
+fn rollup_filter(query: &RollupQuery, principal: &Principal) -> Result<ReadingFilter, Rejection> {
+    if query.resolution > geo::MAX_RESOLUTION {
+        return Err((StatusCode::BAD_REQUEST, "Resolution must be at most 15"));
+    }
+
+    let mut filter = ReadingFilter {
+        organization: principal.organization,
+        ..Default::default()
+    };
+
+    if let Some(within) = query.within {
+        match geo::resolution(H3Cell(within)) {
+            Some(res) if res <= query.resolution => filter.ancestor = Some(H3Cell(within)),
+            Some(_) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Within cell must not be finer than the resolution",
+                ));
+            }
+            None => return Err((StatusCode::BAD_REQUEST, "Invalid within cell")),
+        }
+    }
+
+    let after = match query.after {
+        Some(ref ts_str) => match ts_str.parse::<jiff::Timestamp>() {
+            Ok(ts) => Some(ts),
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid after timestamp")),
+        },
+        None => None,
+    };
+
+    let before = match query.before {
+        Some(ref ts_str) => match ts_str.parse::<jiff::Timestamp>() {
+            Ok(ts) => ts,
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid before timestamp")),
+        },
+        None => jiff::Timestamp::now(),
+    };
+
+    let after = after.unwrap_or_else(|| {
+        before
+            .checked_sub(DEFAULT_ROLLUP_WINDOW)
+            .unwrap_or(jiff::Timestamp::MIN)
+    });
+    if after >= before {
+        return Err((StatusCode::BAD_REQUEST, "Window must end after it starts"));
+    }
+    if before.duration_since(after) > MAX_ROLLUP_WINDOW {
+        return Err((StatusCode::BAD_REQUEST, "Window must be at most 366 days"));
+    }
+    filter.timestamp_after = Some(after);
+    filter.timestamp_before = Some(before);
+
+    if let Some(ref metrics) = query.metrics {
+        match metrics.split(',').map(|m| m.trim().parse()).collect() {
+            Ok(metric_types) => filter.metric_types = Some(metric_types),
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid metric")),
+        }
+    }
+
+    if let Some(ref bbox) = query.bbox {
+        match parse_bbox(bbox) {
+            Some(bbox) => filter.bbox = Some(bbox),
+            None => return Err((StatusCode::BAD_REQUEST, "Invalid bbox")),
+        }
+    }
+
+    if let Some(ref field) = query.field {
+        match field.parse::<Ulid>() {
+            Ok(ulid) => filter.field = Some(FieldId(ulid)),
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid field ID")),
+        }
+    }
+
+    Ok(filter)
+}
+
+/// Aggregate readings to cells at a coarser resolution.
+///
+/// Readings located at a coarser resolution than requested are left out.
+/// The window defaults to the last 7 days and may span at most 366.
+///
+/// GET /api/readings/rollup
+pub async fn rollup<R>(
+    State(readings): State<R>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<RollupQuery>,
+) -> impl IntoResponse
+where
+    R: ReadingRegistry,
+{
+    let filter = match rollup_filter(&query, &principal) {
+        Ok(filter) => filter,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match readings.rollup(query.resolution, filter).await {
+        Ok(rollups) => Json(RollupResponse {
+            resolution: query.resolution,
+            rollups: rollups.into_iter().map(CellRollupResponse::from).collect(),
+        })
+        .into_response(),
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to roll up readings");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to roll up readings",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Export a rollup as a GeoJSON feature collection of cell outlines, with
+/// the statistics of every metric in a cell's properties.
+///
+/// GET /api/readings/rollup/geojson
+pub async fn export_rollup<R>(
+    State(readings): State<R>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<RollupQuery>,
+) -> impl IntoResponse
+where
+    R: ReadingRegistry,
+{
+    let filter = match rollup_filter(&query, &principal) {
+        Ok(filter) => filter,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let rollups = match readings.rollup(query.resolution, filter).await {
+        Ok(rollups) => rollups,
+        Err(e) => {
+            tracing::error!(error = ?e, "Failed to roll up readings");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to roll up readings",
+            )
+                .into_response();
+        }
+    };
+
+    // Rollups are ordered by cell, so each cell's metrics are adjacent.
+    let mut features: Vec<Feature<CellProperties>> = Vec::new();
+    for rollup in rollups {
+        let statistics = MetricStatistics {
+            readings: rollup.readings,
+            devices: rollup.devices,
+            mean: rollup.mean,
+            min: rollup.min,
+            max: rollup.max,
+        };
+        let metric = rollup.metric_type.as_str().to_string();
+
+        if let Some(feature) = features.last_mut()
+            && feature.properties.cell == rollup.cell.0
+        {
+            feature.properties.metrics.insert(metric, statistics);
+            continue;
+        }
+        let Some(geometry) = geo::outline(rollup.cell) else {
+            continue;
+        };
+        features.push(Feature {
+            id: format!("{:x}", rollup.cell.0),
+            geometry,
+            properties: CellProperties {
+                cell: rollup.cell.0,
+                metrics: BTreeMap::from([(metric, statistics)]),
+            },
+        });
+    }
+
+    (
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        Json(FeatureCollection { features }),
+    )
+        .into_response()
+}
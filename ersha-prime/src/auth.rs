@@ -0,0 +1,192 @@
+use std::str::FromStr;
+
+use ersha_core::OrganizationId;
+use sha2::{Digest, Sha256};
+use ulid::Ulid;
+
+use crate::registry::ApiKeyRegistry;
+
+/// Prefix of every API key, so leaked keys are easy to recognise.
+pub const KEY_PREFIX: &str = "ersha_";
+
+/// Shortest key accepted as a bootstrap key.
+pub const MIN_KEY_LEN: usize = 32;
+
+/// Unique identifier for an API key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ApiKeyId(pub Ulid);
+
+/// What an API key may do. Each role may do everything the ones before it
+/// may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    /// Read dispatchers, devices, commands and firmware.
+    Viewer,
+    /// Register, suspend and command dispatchers and devices.
+    Operator,
+    /// Manage API keys and device credentials.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Invalid role: {other}")),
+        }
+    }
+}
+
+/// An API key as stored: only the SHA-256 hash of the secret is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub id: ApiKeyId,
+    /// Who or what the key was issued to.
+    pub name: String,
+    pub role: Role,
+    pub hash: [u8; 32],
+    /// Organization the key is limited to. Keys without one see every
+    /// organization.
+    pub organization: Option<OrganizationId>,
+    pub created_at: jiff::Timestamp,
+    pub revoked_at: Option<jiff::Timestamp>,
+}
+
+impl ApiKey {
+    /// A new key for `secret`.
+    pub fn new(name: impl Into<String>, role: Role, secret: &str) -> Self {
+        Self {
+            id: ApiKeyId(Ulid::new()),
+            name: name.into(),
+            role,
+            hash: hash_key(secret),
+            organization: None,
+            created_at: jiff::Timestamp::now(),
+            revoked_at: None,
+        }
+    }
+
+    /// Limit the key to one organization.
+    pub fn with_organization(mut self, organization: OrganizationId) -> Self {
+        self.organization = Some(organization);
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none()
+    }
+}
+
+/// Generate a new random secret.
+pub fn generate_key() -> String {
+    let bytes: [u8; 32] = rand::random();
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{KEY_PREFIX}{hex}")
+}
+
+pub fn hash_key(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BootstrapError<E> {
+    #[error("bootstrap key must be at least {MIN_KEY_LEN} characters")]
+    KeyTooShort,
+    #[error("registry error: {0}")]
+    Registry(#[from] E),
+}
+
+/// Make sure the registry has an admin key spanning all organizations to
+/// manage the others with.
+///
+/// When there is no such key, one is added: `configured` if given,
+/// otherwise a newly generated key, which is returned as it cannot be
+/// recovered from the registry later.
+pub async fn bootstrap<K>(
+    keys: &K,
+    configured: Option<&str>,
+) -> Result<Option<String>, BootstrapError<K::Error>>
+where
+    K: ApiKeyRegistry,
+{
+    let has_admin = keys
+        .list()
+        .await?
+        .iter()
+        .any(|key| key.role == Role::Admin && key.organization.is_none() && key.is_active());
+    if has_admin {
+        return Ok(None);
+    }
+
+    let (secret, generated) = match configured {
+        Some(secret) if secret.len() < MIN_KEY_LEN => return Err(BootstrapError::KeyTooShort),
+        Some(secret) => (secret.to_string(), false),
+        None => (generate_key(), true),
+    };
+
+    keys.add(ApiKey::new("bootstrap", Role::Admin, &secret))
+        .await?;
+
+    Ok(generated.then_some(secret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KEY_PREFIX, Role, bootstrap, generate_key, hash_key};
+    use crate::registry::{ApiKeyRegistry, memory::InMemoryApiKeyRegistry};
+
+    #[test]
+    fn roles_include_the_ones_below() {
+        assert!(Role::Admin > Role::Operator);
+        assert!(Role::Operator > Role::Viewer);
+        assert_eq!("operator".parse(), Ok(Role::Operator));
+        assert!("root".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn generated_keys_are_prefixed_and_distinct() {
+        let a = generate_key();
+        let b = generate_key();
+        assert!(a.starts_with(KEY_PREFIX));
+        assert_eq!(a.len(), KEY_PREFIX.len() + 64);
+        assert_ne!(hash_key(&a), hash_key(&b));
+    }
+
+    #[tokio::test]
+    async fn bootstraps_only_without_an_admin() {
+        let keys = InMemoryApiKeyRegistry::new();
+
+        let secret = bootstrap(&keys, None).await.unwrap().unwrap();
+        let stored = keys.get_by_hash(hash_key(&secret)).await.unwrap().unwrap();
+        assert_eq!(stored.role, Role::Admin);
+
+        // an admin exists now, so nothing more is added
+        assert_eq!(bootstrap(&keys, None).await.unwrap(), None);
+        assert_eq!(keys.list().await.unwrap().len(), 1);
+
+        let keys = InMemoryApiKeyRegistry::new();
+        assert!(bootstrap(&keys, Some("short")).await.is_err());
+        let configured = "a-long-enough-configured-admin-key";
+        assert_eq!(bootstrap(&keys, Some(configured)).await.unwrap(), None);
+        assert!(
+            keys.get_by_hash(hash_key(configured))
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -5,13 +5,20 @@ use ulid::Ulid;
 
 use crate::api::{
     devices::{
-        DeviceResponse, ListDevicesQuery, ListDevicesResponse, RegisterDeviceRequest, SensorRequest,
+        ActuatorRequest, AddSensorsRequest, DeviceResponse, ListDevicesQuery, ListDevicesResponse,
+        RegisterDeviceRequest, SensorRequest, UpdateDeviceRequest,
     },
     dispatchers::{
         DispatcherResponse, ListDispatchersQuery, ListDispatchersResponse,
-        RegisterDispatcherRequest,
+        RegisterDispatcherRequest, UpdateDispatcherRequest,
     },
+    health::{
+        ConnectionEventResponse, DeviceHealthResponse, DispatcherHealthResponse,
+        HealthAlertResponse, HistoryQuery, StatusReportResponse,
+    },
+    readings::{CellProperties, RollupQuery, RollupResponse},
 };
+use crate::geo::FeatureCollection;
 
 // Re-export query enums for convenience
 pub use crate::api::devices::DeviceQuerySortBy;
@@ -31,6 +38,12 @@ pub enum ClientError {
 
     #[error("Invalid request: {0}")]
     BadRequest(String),
+
+    #[error("Missing, unknown or revoked API key")]
+    Unauthorized,
+
+    #[error("API key role does not allow this operation")]
+    Forbidden,
 }
 
 /// HTTP API client for ersha-prime.
@@ -42,6 +55,7 @@ pub enum ClientError {
 pub struct Client {
     http: HttpClient,
     base_url: String,
+    api_key: Option<String>,
 }
 
 impl Client {
@@ -57,6 +71,7 @@ impl Client {
         Self {
             http: HttpClient::new(),
             base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
         }
     }
 
@@ -67,6 +82,28 @@ impl Client {
         Self {
             http,
             base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Authenticate every request with the given API key.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ersha_prime::client::Client;
+    ///
+    /// let client = Client::new("http://localhost:3000").with_api_key("ersha_...");
+    /// ```
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, url);
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
         }
     }
 
@@ -101,10 +138,18 @@ impl Client {
         id: Option<Ulid>,
         location: u64,
     ) -> Result<DispatcherResponse, ClientError> {
-        let request = RegisterDispatcherRequest { id, location };
+        let request = RegisterDispatcherRequest {
+            id,
+            location,
+            organization: None,
+        };
         let url = format!("{}/api/dispatchers", self.base_url);
 
-        let response = self.http.post(&url).json(&request).send().await?;
+        let response = self
+            .request(reqwest::Method::POST, &url)
+            .json(&request)
+            .send()
+            .await?;
 
         handle_response(response).await
     }
@@ -119,7 +164,7 @@ impl Client {
     pub async fn get_dispatcher(&self, id: Ulid) -> Result<DispatcherResponse, ClientError> {
         let url = format!("{}/api/dispatchers/{}", self.base_url, id);
 
-        let response = self.http.get(&url).send().await?;
+        let response = self.request(reqwest::Method::GET, &url).send().await?;
 
         handle_response(response).await
     }
@@ -146,7 +191,11 @@ impl Client {
     ) -> Result<ListDispatchersResponse, ClientError> {
         let url = format!("{}/api/dispatchers", self.base_url);
 
-        let response = self.http.get(&url).query(&query).send().await?;
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&query)
+            .send()
+            .await?;
 
         handle_response(response).await
     }
@@ -163,7 +212,70 @@ impl Client {
     pub async fn suspend_dispatcher(&self, id: Ulid) -> Result<DispatcherResponse, ClientError> {
         let url = format!("{}/api/dispatchers/{}/suspend", self.base_url, id);
 
-        let response = self.http.post(&url).send().await?;
+        let response = self.request(reqwest::Method::POST, &url).send().await?;
+
+        handle_response(response).await
+    }
+
+    /// Move a dispatcher to a new location.
+    ///
+    /// # Arguments
+    /// * `id` - The dispatcher's ULID
+    /// * `location` - New H3 cell location of the dispatcher
+    ///
+    /// # Returns
+    /// The updated dispatcher.
+    pub async fn update_dispatcher(
+        &self,
+        id: Ulid,
+        location: u64,
+    ) -> Result<DispatcherResponse, ClientError> {
+        let request = UpdateDispatcherRequest {
+            location: Some(location),
+        };
+        let url = format!("{}/api/dispatchers/{}", self.base_url, id);
+
+        let response = self
+            .request(reqwest::Method::PATCH, &url)
+            .json(&request)
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// Return a suspended dispatcher to active.
+    ///
+    /// # Arguments
+    /// * `id` - The dispatcher's ULID
+    ///
+    /// # Returns
+    /// The updated dispatcher with active state.
+    pub async fn reactivate_dispatcher(&self, id: Ulid) -> Result<DispatcherResponse, ClientError> {
+        let url = format!("{}/api/dispatchers/{}/reactivate", self.base_url, id);
+
+        let response = self.request(reqwest::Method::POST, &url).send().await?;
+
+        handle_response(response).await
+    }
+
+    /// Decommission a dispatcher.
+    ///
+    /// Retired dispatchers cannot upload data or be changed, but the data
+    /// they uploaded is kept.
+    ///
+    /// # Arguments
+    /// * `id` - The dispatcher's ULID
+    ///
+    /// # Returns
+    /// The updated dispatcher with retired state.
+    pub async fn decommission_dispatcher(
+        &self,
+        id: Ulid,
+    ) -> Result<DispatcherResponse, ClientError> {
+        let url = format!("{}/api/dispatchers/{}/decommission", self.base_url, id);
+
+        let response = self.request(reqwest::Method::POST, &url).send().await?;
 
         handle_response(response).await
     }
@@ -185,7 +297,11 @@ impl Client {
     ) -> Result<DeviceResponse, ClientError> {
         let url = format!("{}/api/devices", self.base_url);
 
-        let response = self.http.post(&url).json(&request).send().await?;
+        let response = self
+            .request(reqwest::Method::POST, &url)
+            .json(&request)
+            .send()
+            .await?;
 
         handle_response(response).await
     }
@@ -207,6 +323,8 @@ impl Client {
             kind: None,
             manufacturer: None,
             sensors: vec![],
+            actuators: vec![],
+            organization: None,
         })
         .await
     }
@@ -221,7 +339,7 @@ impl Client {
     pub async fn get_device(&self, id: Ulid) -> Result<DeviceResponse, ClientError> {
         let url = format!("{}/api/devices/{}", self.base_url, id);
 
-        let response = self.http.get(&url).send().await?;
+        let response = self.request(reqwest::Method::GET, &url).send().await?;
 
         handle_response(response).await
     }
@@ -248,7 +366,301 @@ impl Client {
     ) -> Result<ListDevicesResponse, ClientError> {
         let url = format!("{}/api/devices", self.base_url);
 
-        let response = self.http.get(&url).query(&query).send().await?;
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&query)
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// Update a device's location or manufacturer.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    /// * `request` - Fields to change; omitted ones are left as they are
+    ///
+    /// # Returns
+    /// The updated device.
+    pub async fn update_device(
+        &self,
+        id: Ulid,
+        request: UpdateDeviceRequest,
+    ) -> Result<DeviceResponse, ClientError> {
+        let url = format!("{}/api/devices/{}", self.base_url, id);
+
+        let response = self
+            .request(reqwest::Method::PATCH, &url)
+            .json(&request)
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// Suspend a device.
+    ///
+    /// Suspended devices cannot upload telemetry until they are reactivated.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    ///
+    /// # Returns
+    /// The updated device with suspended state.
+    pub async fn suspend_device(&self, id: Ulid) -> Result<DeviceResponse, ClientError> {
+        let url = format!("{}/api/devices/{}/suspend", self.base_url, id);
+
+        let response = self.request(reqwest::Method::POST, &url).send().await?;
+
+        handle_response(response).await
+    }
+
+    /// Return a suspended device to active.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    ///
+    /// # Returns
+    /// The updated device with active state.
+    pub async fn reactivate_device(&self, id: Ulid) -> Result<DeviceResponse, ClientError> {
+        let url = format!("{}/api/devices/{}/reactivate", self.base_url, id);
+
+        let response = self.request(reqwest::Method::POST, &url).send().await?;
+
+        handle_response(response).await
+    }
+
+    /// Decommission a device.
+    ///
+    /// Retired devices cannot upload telemetry or be changed, but their
+    /// readings and statuses are kept.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    ///
+    /// # Returns
+    /// The updated device with retired state.
+    pub async fn decommission_device(&self, id: Ulid) -> Result<DeviceResponse, ClientError> {
+        let url = format!("{}/api/devices/{}/decommission", self.base_url, id);
+
+        let response = self.request(reqwest::Method::POST, &url).send().await?;
+
+        handle_response(response).await
+    }
+
+    /// Attach sensors to a device.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    /// * `sensors` - Sensors to attach
+    ///
+    /// # Returns
+    /// The updated device.
+    pub async fn add_sensors(
+        &self,
+        id: Ulid,
+        sensors: Vec<SensorRequest>,
+    ) -> Result<DeviceResponse, ClientError> {
+        let request = AddSensorsRequest { sensors };
+        let url = format!("{}/api/devices/{}/sensors", self.base_url, id);
+
+        let response = self
+            .request(reqwest::Method::POST, &url)
+            .json(&request)
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// Detach a sensor from a device. Its readings are kept.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    /// * `sensor_id` - The sensor's ULID
+    pub async fn remove_sensor(&self, id: Ulid, sensor_id: Ulid) -> Result<(), ClientError> {
+        let url = format!("{}/api/devices/{}/sensors/{}", self.base_url, id, sensor_id);
+
+        let response = self.request(reqwest::Method::DELETE, &url).send().await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(error_from_response(response).await)
+        }
+    }
+
+    // -------------------------------------------------------------------------
+    // Health operations
+    // -------------------------------------------------------------------------
+
+    /// Get whether a dispatcher is online and its latest status report.
+    ///
+    /// # Arguments
+    /// * `id` - The dispatcher's ULID
+    ///
+    /// # Returns
+    /// The dispatcher's health, or `ClientError::NotFound`.
+    pub async fn dispatcher_health(
+        &self,
+        id: Ulid,
+    ) -> Result<DispatcherHealthResponse, ClientError> {
+        let url = format!("{}/api/dispatchers/{}/health", self.base_url, id);
+
+        let response = self.request(reqwest::Method::GET, &url).send().await?;
+
+        handle_response(response).await
+    }
+
+    /// List the status reports a dispatcher sent.
+    ///
+    /// # Arguments
+    /// * `id` - The dispatcher's ULID
+    /// * `limit` - Most reports to return, 100 if not given
+    ///
+    /// # Returns
+    /// The reports, newest first.
+    pub async fn dispatcher_statuses(
+        &self,
+        id: Ulid,
+        limit: Option<usize>,
+    ) -> Result<Vec<StatusReportResponse>, ClientError> {
+        let url = format!("{}/api/dispatchers/{}/statuses", self.base_url, id);
+
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&HistoryQuery { limit })
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// List alerts for a dispatcher and the devices last seen
+    /// through it.
+    ///
+    /// # Arguments
+    /// * `id` - The dispatcher's ULID
+    /// * `limit` - Most alerts to return, 100 if not given
+    ///
+    /// # Returns
+    /// The alerts, newest first.
+    pub async fn dispatcher_alerts(
+        &self,
+        id: Ulid,
+        limit: Option<usize>,
+    ) -> Result<Vec<HealthAlertResponse>, ClientError> {
+        let url = format!("{}/api/dispatchers/{}/alerts", self.base_url, id);
+
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&HistoryQuery { limit })
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// Get whether a device is online and when its data last arrived.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    ///
+    /// # Returns
+    /// The device's health, or `ClientError::NotFound`.
+    pub async fn device_health(&self, id: Ulid) -> Result<DeviceHealthResponse, ClientError> {
+        let url = format!("{}/api/devices/{}/health", self.base_url, id);
+
+        let response = self.request(reqwest::Method::GET, &url).send().await?;
+
+        handle_response(response).await
+    }
+
+    /// List the times a device connected and disconnected.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    /// * `limit` - Most events to return, 100 if not given
+    ///
+    /// # Returns
+    /// The events, newest first.
+    pub async fn device_connections(
+        &self,
+        id: Ulid,
+        limit: Option<usize>,
+    ) -> Result<Vec<ConnectionEventResponse>, ClientError> {
+        let url = format!("{}/api/devices/{}/connections", self.base_url, id);
+
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&HistoryQuery { limit })
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// List alerts for a device.
+    ///
+    /// # Arguments
+    /// * `id` - The device's ULID
+    /// * `limit` - Most alerts to return, 100 if not given
+    ///
+    /// # Returns
+    /// The alerts, newest first.
+    pub async fn device_alerts(
+        &self,
+        id: Ulid,
+        limit: Option<usize>,
+    ) -> Result<Vec<HealthAlertResponse>, ClientError> {
+        let url = format!("{}/api/devices/{}/alerts", self.base_url, id);
+
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&HistoryQuery { limit })
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    // -------------------------------------------------------------------------
+    // Reading operations
+    // -------------------------------------------------------------------------
+
+    /// Aggregate readings to cells at a coarser H3 resolution.
+    ///
+    /// # Arguments
+    /// * `query` - Resolution, time window and filters, see [`RollupQueryBuilder`]
+    ///
+    /// # Returns
+    /// Per-cell statistics of each metric.
+    pub async fn rollup_readings(&self, query: RollupQuery) -> Result<RollupResponse, ClientError> {
+        let url = format!("{}/api/readings/rollup", self.base_url);
+
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&query)
+            .send()
+            .await?;
+
+        handle_response(response).await
+    }
+
+    /// Aggregate readings like [`Client::rollup_readings`], as GeoJSON cell
+    /// outlines.
+    pub async fn export_rollup(
+        &self,
+        query: RollupQuery,
+    ) -> Result<FeatureCollection<CellProperties>, ClientError> {
+        let url = format!("{}/api/readings/rollup/geojson", self.base_url);
+
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .query(&query)
+            .send()
+            .await?;
 
         handle_response(response).await
     }
@@ -259,21 +671,32 @@ async fn handle_response<T>(response: reqwest::Response) -> Result<T, ClientErro
 where
     T: for<'de> Deserialize<'de>,
 {
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        Err(error_from_response(response).await)
+    }
+}
+
+/// Convert an unsuccessful HTTP response to a [`ClientError`].
+async fn error_from_response(response: reqwest::Response) -> ClientError {
     let status = response.status();
 
-    if status.is_success() {
-        Ok(response.json().await?)
-    } else if status == reqwest::StatusCode::NOT_FOUND {
-        Err(ClientError::NotFound)
+    if status == reqwest::StatusCode::NOT_FOUND {
+        ClientError::NotFound
+    } else if status == reqwest::StatusCode::UNAUTHORIZED {
+        ClientError::Unauthorized
+    } else if status == reqwest::StatusCode::FORBIDDEN {
+        ClientError::Forbidden
     } else if status == reqwest::StatusCode::BAD_REQUEST {
         let message = response.text().await.unwrap_or_default();
-        Err(ClientError::BadRequest(message))
+        ClientError::BadRequest(message)
     } else {
         let message = response.text().await.unwrap_or_default();
-        Err(ClientError::ServerError {
+        ClientError::ServerError {
             status: status.as_u16(),
             message,
-        })
+        }
     }
 }
 
@@ -355,6 +778,31 @@ impl ListDevicesQueryBuilder {
         self
     }
 
+    /// Filter by ancestor H3 cell, at any coarser resolution than the devices.
+    pub fn within(mut self, cell: u64) -> Self {
+        self.query.within = Some(cell);
+        self
+    }
+
+    /// Filter by grid distance from an H3 cell.
+    pub fn near(mut self, cell: u64, radius: u32) -> Self {
+        self.query.near = Some(cell);
+        self.query.radius = Some(radius);
+        self
+    }
+
+    /// Filter by bounding box, in degrees.
+    pub fn bbox(mut self, south: f64, west: f64, north: f64, east: f64) -> Self {
+        self.query.bbox = Some(format!("{south},{west},{north},{east}"));
+        self
+    }
+
+    /// Filter by field, matching devices assigned to its plots.
+    pub fn field(mut self, field: Ulid) -> Self {
+        self.query.field = Some(field.to_string());
+        self
+    }
+
     /// Filter by manufacturer (pattern match).
     pub fn manufacturer(mut self, manufacturer: impl Into<String>) -> Self {
         self.query.manufacturer = Some(manufacturer.into());
@@ -409,6 +857,72 @@ impl ListDevicesQueryBuilder {
     }
 }
 
+/// Builder for creating reading rollup queries.
+pub struct RollupQueryBuilder {
+    query: RollupQuery,
+}
+
+impl RollupQueryBuilder {
+    /// Create a query aggregating to cells at `resolution`.
+    pub fn new(resolution: u8) -> Self {
+        Self {
+            query: RollupQuery {
+                resolution,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Only readings at or after this time (ISO 8601 timestamp).
+    pub fn after(mut self, ts: impl Into<String>) -> Self {
+        self.query.after = Some(ts.into());
+        self
+    }
+
+    /// Only readings at or before this time (ISO 8601 timestamp).
+    pub fn before(mut self, ts: impl Into<String>) -> Self {
+        self.query.before = Some(ts.into());
+        self
+    }
+
+    /// Only these metrics, e.g. "soil_moisture".
+    pub fn metrics<I, S>(mut self, metrics: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let metrics: Vec<String> = metrics
+            .into_iter()
+            .map(|m| m.as_ref().to_string())
+            .collect();
+        self.query.metrics = Some(metrics.join(","));
+        self
+    }
+
+    /// Only readings located within an ancestor H3 cell.
+    pub fn within(mut self, cell: u64) -> Self {
+        self.query.within = Some(cell);
+        self
+    }
+
+    /// Only readings located in a bounding box, in degrees.
+    pub fn bbox(mut self, south: f64, west: f64, north: f64, east: f64) -> Self {
+        self.query.bbox = Some(format!("{south},{west},{north},{east}"));
+        self
+    }
+
+    /// Only readings from devices assigned to plots of a field.
+    pub fn field(mut self, field: Ulid) -> Self {
+        self.query.field = Some(field.to_string());
+        self
+    }
+
+    /// Build the query.
+    pub fn build(self) -> RollupQuery {
+        self.query
+    }
+}
+
 /// Builder for creating device registration requests.
 ///
 /// This provides a fluent API for constructing `RegisterDeviceRequest`.
@@ -419,6 +933,8 @@ pub struct RegisterDeviceBuilder {
     kind: Option<String>,
     manufacturer: Option<String>,
     sensors: Vec<SensorRequest>,
+    actuators: Vec<ActuatorRequest>,
+    organization: Option<Ulid>,
 }
 
 impl RegisterDeviceBuilder {
@@ -448,6 +964,13 @@ impl RegisterDeviceBuilder {
         self
     }
 
+    /// Set the owning organization. Only keys spanning all organizations
+    /// may pick another than their own.
+    pub fn organization(mut self, organization: Ulid) -> Self {
+        self.organization = Some(organization);
+        self
+    }
+
     /// Add a sensor to the device.
     pub fn sensor(mut self, kind: impl Into<String>) -> Self {
         self.sensors.push(SensorRequest {
@@ -466,6 +989,16 @@ impl RegisterDeviceBuilder {
         self
     }
 
+    /// Add an actuator to the device. Actuators require the "actuator" kind.
+    pub fn actuator(mut self, kind: impl Into<String>, max_run_secs: u32) -> Self {
+        self.actuators.push(ActuatorRequest {
+            id: None,
+            kind: kind.into(),
+            max_run_secs,
+        });
+        self
+    }
+
     /// Build the registration request.
     pub fn build(self) -> RegisterDeviceRequest {
         RegisterDeviceRequest {
@@ -474,6 +1007,8 @@ impl RegisterDeviceBuilder {
             kind: self.kind,
             manufacturer: self.manufacturer,
             sensors: self.sensors,
+            actuators: self.actuators,
+            organization: self.organization,
         }
     }
 }
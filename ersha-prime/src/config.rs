@@ -10,6 +10,10 @@ pub struct Config {
     pub server: ServerConfig,
     pub registry: RegistryConfig,
     pub tls: TlsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +24,53 @@ pub struct ServerConfig {
     pub http_addr: SocketAddr,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Require an API key on the HTTP API
+    pub enabled: bool,
+    /// Admin key to add when the registry has none. If unset, one is
+    /// generated and logged once.
+    pub bootstrap_key: Option<String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bootstrap_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Seconds without a status report before a dispatcher is offline
+    pub dispatcher_offline_after_secs: u64,
+    /// Seconds without readings or statuses before a device is offline
+    pub device_offline_after_secs: u64,
+}
+
+impl HealthConfig {
+    pub fn dispatcher_offline_after(&self) -> jiff::SignedDuration {
+        jiff::SignedDuration::from_secs(self.dispatcher_offline_after_secs as i64)
+    }
+
+    pub fn device_offline_after(&self) -> jiff::SignedDuration {
+        jiff::SignedDuration::from_secs(self.device_offline_after_secs as i64)
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            dispatcher_offline_after_secs: 180,
+            device_offline_after_secs: 900,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum RegistryConfig {
@@ -45,6 +96,8 @@ impl Default for Config {
             },
             registry: RegistryConfig::Memory,
             tls: TlsConfig::server_default(),
+            auth: AuthConfig::default(),
+            health: HealthConfig::default(),
         }
     }
 }
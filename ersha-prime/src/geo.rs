@@ -0,0 +1,481 @@
+use std::collections::HashSet;
+
+use ersha_core::{Boundary, GeoPoint, H3Cell};
+use geo_types::{LineString, Polygon};
+use h3o::{
+    CellIndex, LatLng, Resolution,
+    geom::{ContainmentMode, TilerBuilder},
+};
+use ordered_float::NotNan;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::registry::filter::{BoundingBox, CellRing};
+
+/// Resolution of the cells covering farm, field and plot boundaries. Cells
+/// are about 2,150 m².
+pub const COVERAGE_RESOLUTION: Resolution = Resolution::Eleven;
+
+/// Most cells a boundary may cover, about 21,000 ha.
+pub const MAX_COVERAGE_CELLS: usize = 100_000;
+
+/// Most points a boundary ring may have.
+pub const MAX_RING_POINTS: usize = 1_000;
+
+/// Largest radius of a cell ring filter, 7,651 cells.
+pub const MAX_RING_RADIUS: u32 = 50;
+
+/// Finest H3 resolution.
+pub const MAX_RESOLUTION: u8 = 15;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BoundaryError {
+    #[error("boundary needs at least three distinct points")]
+    TooFewPoints,
+    #[error("boundary may have at most {MAX_RING_POINTS} points")]
+    TooManyPoints,
+    #[error("boundary points must be valid latitudes and longitudes")]
+    InvalidPoint,
+    #[error("boundary polygons with holes are not supported")]
+    Holes,
+    #[error("boundary is too large, it may cover at most {MAX_COVERAGE_CELLS} cells")]
+    TooLarge,
+}
+
+/// Build a boundary from the outer ring of a polygon.
+///
+/// The ring is covered with the cells at [`COVERAGE_RESOLUTION`] whose
+/// centers fall inside it. A polygon too small to contain any center is
+/// covered by the cell at its centroid.
+pub fn boundary(mut ring: Vec<GeoPoint>) -> Result<Boundary, BoundaryError> {
+    // GeoJSON rings repeat the first point at the end.
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    ring.dedup();
+    if ring.len() < 3 {
+        return Err(BoundaryError::TooFewPoints);
+    }
+    if ring.len() > MAX_RING_POINTS {
+        return Err(BoundaryError::TooManyPoints);
+    }
+
+    let mut min = (f64::MAX, f64::MAX);
+    let mut max = (f64::MIN, f64::MIN);
+    for point in &ring {
+        let (lat, lng) = (point.lat.into_inner(), point.lng.into_inner());
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) {
+            return Err(BoundaryError::InvalidPoint);
+        }
+        min = (min.0.min(lat), min.1.min(lng));
+        max = (max.0.max(lat), max.1.max(lng));
+    }
+    // Rings crossing the antimeridian would need splitting first.
+    if max.1 - min.1 > 180.0 {
+        return Err(BoundaryError::TooLarge);
+    }
+
+    let count = ring.len() as f64;
+    let centroid = latlng(
+        ring.iter().map(|p| p.lat.into_inner()).sum::<f64>() / count,
+        ring.iter().map(|p| p.lng.into_inner()).sum::<f64>() / count,
+    )?;
+    let center = centroid.to_cell(COVERAGE_RESOLUTION);
+
+    let exterior: Vec<(f64, f64)> = ring
+        .iter()
+        .map(|p| (p.lng.into_inner(), p.lat.into_inner()))
+        .collect();
+    let mut tiler = TilerBuilder::new(COVERAGE_RESOLUTION)
+        .containment_mode(ContainmentMode::ContainsCentroid)
+        .build();
+    tiler
+        .add(Polygon::new(LineString::from(exterior), Vec::new()))
+        .map_err(|_| BoundaryError::InvalidPoint)?;
+    // An upper bound from the bounding box, checked before tiling.
+    if tiler.coverage_size_hint() > 4 * MAX_COVERAGE_CELLS {
+        return Err(BoundaryError::TooLarge);
+    }
+
+    let mut cells: Vec<H3Cell> = tiler
+        .into_coverage()
+        .map(|cell| H3Cell(u64::from(cell)))
+        .collect();
+    if cells.len() > MAX_COVERAGE_CELLS {
+        return Err(BoundaryError::TooLarge);
+    }
+    if cells.is_empty() {
+        cells.push(H3Cell(u64::from(center)));
+    }
+    cells.sort_unstable_by_key(|cell| cell.0);
+    cells.dedup();
+
+    Ok(Boundary {
+        ring: ring.into_boxed_slice(),
+        cells: cells.into_boxed_slice(),
+    })
+}
+
+fn latlng(lat: f64, lng: f64) -> Result<LatLng, BoundaryError> {
+    LatLng::new(lat, lng).map_err(|_| BoundaryError::InvalidPoint)
+}
+
+/// Whether a location falls in a boundary's coverage. Locations at other
+/// resolutions are compared by their center.
+pub fn covers(boundary: &Boundary, location: H3Cell) -> bool {
+    let Ok(cell) = CellIndex::try_from(location.0) else {
+        return false;
+    };
+    let cell = LatLng::from(cell).to_cell(COVERAGE_RESOLUTION);
+    boundary
+        .cells
+        .binary_search_by_key(&u64::from(cell), |c| c.0)
+        .is_ok()
+}
+
+/// The resolution of a cell, or `None` for an invalid cell.
+pub fn resolution(cell: H3Cell) -> Option<u8> {
+    CellIndex::try_from(cell.0)
+        .ok()
+        .map(|cell| u8::from(cell.resolution()))
+}
+
+/// The ancestor of a cell at a coarser resolution, or the cell itself at its
+/// own resolution.
+pub fn parent(cell: H3Cell, resolution: u8) -> Option<H3Cell> {
+    let cell = CellIndex::try_from(cell.0).ok()?;
+    let resolution = Resolution::try_from(resolution).ok()?;
+    cell.parent(resolution).map(|p| H3Cell(u64::from(p)))
+}
+
+/// Latitude and longitude of a cell's center.
+pub fn center(cell: H3Cell) -> Option<(f64, f64)> {
+    let center = LatLng::from(CellIndex::try_from(cell.0).ok()?);
+    Some((center.lat(), center.lng()))
+}
+
+/// Cells of a ring filter, with the radius capped at [`MAX_RING_RADIUS`].
+pub fn disk(ring: CellRing) -> Option<Vec<H3Cell>> {
+    let center = CellIndex::try_from(ring.center.0).ok()?;
+    Some(
+        center
+            .grid_disk::<Vec<_>>(ring.radius.min(MAX_RING_RADIUS))
+            .into_iter()
+            .map(|cell| H3Cell(u64::from(cell)))
+            .collect(),
+    )
+}
+
+/// The hexagon, or pentagon, outlining a cell.
+pub fn outline(cell: H3Cell) -> Option<Geometry> {
+    let cell = CellIndex::try_from(cell.0).ok()?;
+    let mut ring: Vec<[f64; 2]> = cell
+        .boundary()
+        .iter()
+        .map(|vertex| [vertex.lng(), vertex.lat()])
+        .collect();
+    ring.push(*ring.first()?);
+    Some(Geometry::Polygon {
+        coordinates: vec![ring],
+    })
+}
+
+/// Spatial conditions of a filter, resolved once to match many locations.
+pub struct SpatialMatcher {
+    ancestor: Option<Option<(u8, H3Cell)>>,
+    ring: Option<Option<(u8, HashSet<H3Cell>)>>,
+    bbox: Option<BoundingBox>,
+}
+
+impl SpatialMatcher {
+    pub fn new(
+        ancestor: Option<H3Cell>,
+        ring: Option<CellRing>,
+        bbox: Option<BoundingBox>,
+    ) -> Self {
+        Self {
+            ancestor: ancestor.map(|cell| resolution(cell).map(|r| (r, cell))),
+            ring: ring.map(|ring| {
+                let cells = disk(ring)?;
+                Some((resolution(ring.center)?, cells.into_iter().collect()))
+            }),
+            bbox,
+        }
+    }
+
+    /// Whether a location meets every condition. Invalid cells only match
+    /// when there are none.
+    pub fn matches(&self, location: H3Cell) -> bool {
+        if let Some(ancestor) = &self.ancestor {
+            match ancestor {
+                Some((resolution, cell)) if parent(location, *resolution) == Some(*cell) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(ring) = &self.ring {
+            match ring {
+                Some((resolution, cells))
+                    if parent(location, *resolution).is_some_and(|p| cells.contains(&p)) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(bbox) = &self.bbox {
+            match center(location) {
+                Some((lat, lng)) if bbox.contains(lat, lng) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// A GeoJSON geometry. Positions are `[longitude, latitude]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Geometry {
+    Polygon { coordinates: Vec<Vec<[f64; 2]>> },
+}
+
+impl Geometry {
+    /// Points of the polygon's outer ring.
+    pub fn into_ring(self) -> Result<Vec<GeoPoint>, BoundaryError> {
+        let Geometry::Polygon { mut coordinates } = self;
+        if coordinates.len() > 1 {
+            return Err(BoundaryError::Holes);
+        }
+        coordinates
+            .pop()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|[lng, lat]| {
+                Ok(GeoPoint {
+                    lat: NotNan::new(lat).map_err(|_| BoundaryError::InvalidPoint)?,
+                    lng: NotNan::new(lng).map_err(|_| BoundaryError::InvalidPoint)?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl From<&Boundary> for Geometry {
+    fn from(boundary: &Boundary) -> Self {
+        let mut ring: Vec<[f64; 2]> = boundary
+            .ring
+            .iter()
+            .map(|p| [p.lng.into_inner(), p.lat.into_inner()])
+            .collect();
+        if let Some(first) = ring.first().copied() {
+            ring.push(first);
+        }
+        Geometry::Polygon {
+            coordinates: vec![ring],
+        }
+    }
+}
+
+/// A GeoJSON feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "Feature")]
+pub struct Feature<P> {
+    pub id: String,
+    pub geometry: Geometry,
+    pub properties: P,
+}
+
+/// A GeoJSON feature collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "FeatureCollection")]
+pub struct FeatureCollection<P> {
+    pub features: Vec<Feature<P>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, lng: f64) -> GeoPoint {
+        GeoPoint {
+            lat: NotNan::new(lat).unwrap(),
+            lng: NotNan::new(lng).unwrap(),
+        }
+    }
+
+    // About 550 m by 550 m near Nakuru.
+    fn square() -> Vec<GeoPoint> {
+        vec![
+            point(-0.300, 36.080),
+            point(-0.300, 36.085),
+            point(-0.305, 36.085),
+            point(-0.305, 36.080),
+        ]
+    }
+
+    #[test]
+    fn test_boundary_covers_the_polygon() {
+        let boundary = boundary(square()).unwrap();
+
+        // 30 ha in cells of about 0.2 ha.
+        assert!((100..=180).contains(&boundary.cells.len()));
+        assert!(boundary.cells.windows(2).all(|w| w[0].0 < w[1].0));
+        for cell in boundary.cells.iter() {
+            let center = LatLng::from(CellIndex::try_from(cell.0).unwrap());
+            assert!((-0.305..=-0.300).contains(&center.lat()));
+            assert!((36.080..=36.085).contains(&center.lng()));
+        }
+
+        let inside = LatLng::new(-0.3025, 36.0825).unwrap();
+        let outside = LatLng::new(-0.31, 36.0825).unwrap();
+        assert!(covers(
+            &boundary,
+            H3Cell(u64::from(inside.to_cell(Resolution::Ten)))
+        ));
+        assert!(!covers(
+            &boundary,
+            H3Cell(u64::from(outside.to_cell(Resolution::Twelve)))
+        ));
+        assert!(!covers(&boundary, H3Cell(0)));
+    }
+
+    #[test]
+    fn test_tiny_polygon_gets_its_centroid_cell() {
+        let ring = vec![
+            point(-0.30000, 36.08000),
+            point(-0.30000, 36.08001),
+            point(-0.30001, 36.08001),
+        ];
+
+        let boundary = boundary(ring).unwrap();
+
+        assert_eq!(boundary.cells.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_boundaries_are_rejected() {
+        let closed = vec![point(0.0, 0.0), point(0.0, 1.0), point(0.0, 0.0)];
+        assert_eq!(boundary(closed), Err(BoundaryError::TooFewPoints));
+
+        let mut out_of_range = square();
+        out_of_range[0] = point(91.0, 36.08);
+        assert_eq!(boundary(out_of_range), Err(BoundaryError::InvalidPoint));
+
+        let continent = vec![point(-30.0, 10.0), point(-30.0, 40.0), point(10.0, 25.0)];
+        assert_eq!(boundary(continent), Err(BoundaryError::TooLarge));
+
+        let detailed: Vec<GeoPoint> = (0..=MAX_RING_POINTS)
+            .map(|i| {
+                let angle = i as f64 * std::f64::consts::TAU / (MAX_RING_POINTS + 1) as f64;
+                point(-0.3 + 0.01 * angle.sin(), 36.08 + 0.01 * angle.cos())
+            })
+            .collect();
+        assert_eq!(boundary(detailed), Err(BoundaryError::TooManyPoints));
+    }
+
+    #[test]
+    fn test_boundary_of_concave_ring() {
+        // An L shape of about 1.1 km sides missing its north east quarter.
+        let ring = vec![
+            point(0.00, 36.00),
+            point(0.00, 36.01),
+            point(0.005, 36.01),
+            point(0.005, 36.005),
+            point(0.01, 36.005),
+            point(0.01, 36.00),
+        ];
+        let boundary = boundary(ring).unwrap();
+
+        let covered = |lat, lng| {
+            let cell = LatLng::new(lat, lng).unwrap().to_cell(COVERAGE_RESOLUTION);
+            covers(&boundary, H3Cell(u64::from(cell)))
+        };
+        assert!(covered(0.0025, 36.0025));
+        assert!(covered(0.0025, 36.0075));
+        assert!(covered(0.0075, 36.0025));
+        assert!(!covered(0.0075, 36.0075));
+        assert!(!covered(-0.0025, 36.0025));
+    }
+
+    #[test]
+    fn test_spatial_matcher() {
+        let location = LatLng::new(-0.3025, 36.0825).unwrap();
+        let device = H3Cell(u64::from(location.to_cell(Resolution::Ten)));
+        let district = H3Cell(u64::from(location.to_cell(Resolution::Five)));
+        let elsewhere = H3Cell(u64::from(
+            LatLng::new(-1.29, 36.82).unwrap().to_cell(Resolution::Ten),
+        ));
+
+        let within = SpatialMatcher::new(Some(district), None, None);
+        assert!(within.matches(device));
+        assert!(within.matches(district));
+        assert!(!within.matches(elsewhere));
+        // Coarser than the ancestor
+        assert!(!within.matches(parent(device, 4).unwrap()));
+
+        let neighbour = CellIndex::try_from(district.0)
+            .unwrap()
+            .grid_ring_fast(1)
+            .flatten()
+            .next()
+            .unwrap();
+        let ring = SpatialMatcher::new(
+            None,
+            Some(CellRing {
+                center: H3Cell(u64::from(neighbour)),
+                radius: 1,
+            }),
+            None,
+        );
+        assert!(ring.matches(device));
+        assert!(!ring.matches(elsewhere));
+
+        let nakuru = BoundingBox {
+            south: -0.5,
+            west: 35.9,
+            north: 0.0,
+            east: 36.2,
+        };
+        let bbox = SpatialMatcher::new(None, None, Some(nakuru));
+        assert!(bbox.matches(device));
+        assert!(!bbox.matches(elsewhere));
+
+        let invalid = SpatialMatcher::new(Some(H3Cell(0)), None, None);
+        assert!(!invalid.matches(device));
+        assert!(SpatialMatcher::new(None, None, None).matches(H3Cell(0)));
+    }
+
+    #[test]
+    fn test_geometry_round_trip() {
+        let boundary = boundary(square()).unwrap();
+
+        let geometry = Geometry::from(&boundary);
+        let Geometry::Polygon { coordinates } = &geometry;
+        assert_eq!(coordinates.len(), 1);
+        assert_eq!(coordinates[0].len(), 5);
+        assert_eq!(coordinates[0][0], [36.080, -0.300]);
+        assert_eq!(coordinates[0][0], coordinates[0][4]);
+
+        let ring = geometry.into_ring().unwrap();
+        assert_eq!(super::boundary(ring).unwrap(), boundary);
+
+        let with_hole = Geometry::Polygon {
+            coordinates: vec![vec![[0.0, 0.0]; 4], vec![[0.0, 0.0]; 4]],
+        };
+        assert_eq!(with_hole.into_ring(), Err(BoundaryError::Holes));
+    }
+
+    #[test]
+    fn test_outline() {
+        let cell = LatLng::new(-0.3, 36.08).unwrap().to_cell(Resolution::Five);
+        let Some(Geometry::Polygon { coordinates }) = outline(H3Cell(u64::from(cell))) else {
+            panic!("valid cells have an outline");
+        };
+        assert_eq!(coordinates[0].len(), 7);
+        assert_eq!(coordinates[0][0], coordinates[0][6]);
+        let [lng, lat] = coordinates[0][0];
+        assert!((lat + 0.3).abs() < 0.5 && (lng - 36.08).abs() < 0.5);
+
+        assert_eq!(outline(H3Cell(0)), None);
+    }
+}
@@ -0,0 +1,324 @@
+//! Liveness of dispatchers and devices, tracked from what they send prime.
+
+use std::time::Duration;
+
+use ersha_core::{
+    AlertId, AlertRequest, AlertSeverity, AlertType, DeviceId, DisconnectionReason, DispatcherId,
+    DispatcherStatusRequest, OrganizationId,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
+use ulid::Ulid;
+
+use crate::{config::HealthConfig, registry::HealthRegistry};
+
+/// How often the watchdog looks for silent dispatchers and devices.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A status report a dispatcher sent, as prime received it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DispatcherStatusRecord {
+    pub dispatcher_id: DispatcherId,
+    pub connected_devices: u32,
+    pub uptime_seconds: u64,
+    pub pending_uploads: u32,
+    /// Duplicate readings the dispatcher dropped since it started.
+    pub duplicates_dropped: u64,
+    /// When the dispatcher sent the report, by its own clock.
+    pub reported_at: jiff::Timestamp,
+    pub received_at: jiff::Timestamp,
+}
+
+impl DispatcherStatusRecord {
+    pub fn new(request: DispatcherStatusRequest, received_at: jiff::Timestamp) -> Self {
+        Self {
+            dispatcher_id: request.dispatcher_id,
+            connected_devices: request.connected_devices,
+            uptime_seconds: request.uptime_seconds,
+            pending_uploads: request.pending_uploads,
+            duplicates_dropped: request.duplicates_dropped,
+            reported_at: request.timestamp,
+            received_at,
+        }
+    }
+}
+
+/// When a dispatcher last reported its status, and whether it is online.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DispatcherHealth {
+    pub dispatcher_id: DispatcherId,
+    pub last_seen: jiff::Timestamp,
+    pub online: bool,
+}
+
+/// When data from a device last arrived, through which dispatcher, and
+/// whether the device is online.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceHealth {
+    pub device_id: DeviceId,
+    pub dispatcher_id: DispatcherId,
+    pub last_seen: jiff::Timestamp,
+    pub online: bool,
+}
+
+/// A device coming online or going offline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionChange {
+    /// Data from the device arrived while it was offline or unknown.
+    Connected,
+    /// The dispatcher reported the device gone, or it went silent, which is
+    /// recorded as [`DisconnectionReason::Timeout`].
+    Disconnected(Option<DisconnectionReason>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionEvent {
+    pub device_id: DeviceId,
+    pub dispatcher_id: DispatcherId,
+    pub change: ConnectionChange,
+    pub timestamp: jiff::Timestamp,
+}
+
+/// A [`AlertType::DeviceOffline`] alert prime raised for a silent
+/// dispatcher, or for a silent device when `device_id` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OfflineAlert {
+    pub id: AlertId,
+    pub dispatcher_id: DispatcherId,
+    pub device_id: Option<DeviceId>,
+    pub last_seen: jiff::Timestamp,
+    pub raised_at: jiff::Timestamp,
+}
+
+impl OfflineAlert {
+    pub fn alert_type(&self) -> AlertType {
+        AlertType::DeviceOffline
+    }
+
+    pub fn severity(&self) -> AlertSeverity {
+        AlertSeverity::Warning
+    }
+}
+
+/// An alert a dispatcher reported, such as a critical battery or a failed
+/// sensor, kept with the organization owning the dispatcher when it arrived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportedAlert {
+    pub id: AlertId,
+    pub dispatcher_id: DispatcherId,
+    pub device_id: Option<DeviceId>,
+    pub organization: Option<OrganizationId>,
+    pub severity: AlertSeverity,
+    pub alert_type: AlertType,
+    pub message: Box<str>,
+    /// When the dispatcher raised the alert, by its own clock.
+    pub raised_at: jiff::Timestamp,
+    pub received_at: jiff::Timestamp,
+}
+
+impl ReportedAlert {
+    pub fn new(
+        request: AlertRequest,
+        organization: Option<OrganizationId>,
+        received_at: jiff::Timestamp,
+    ) -> Self {
+        Self {
+            id: request.id,
+            dispatcher_id: request.dispatcher_id,
+            device_id: request.device_id,
+            organization,
+            severity: request.severity,
+            alert_type: request.alert_type,
+            message: request.message,
+            raised_at: request.timestamp,
+            received_at,
+        }
+    }
+}
+
+/// What alerts are listed for. A dispatcher's alerts include the ones for
+/// devices last seen through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertSubject {
+    Dispatcher(DispatcherId),
+    Device(DeviceId),
+}
+
+/// Take dispatchers and devices silent for longer than configured offline,
+/// raising an alert for each. Returns the alerts raised.
+pub async fn check<H>(
+    registry: &H,
+    config: &HealthConfig,
+    now: jiff::Timestamp,
+) -> Result<Vec<OfflineAlert>, H::Error>
+where
+    H: HealthRegistry,
+{
+    let mut alerts = Vec::new();
+
+    let cutoff = now - config.dispatcher_offline_after();
+    for dispatcher in registry.silent_dispatchers(cutoff).await? {
+        let alert = OfflineAlert {
+            id: AlertId(Ulid::new()),
+            dispatcher_id: dispatcher.dispatcher_id,
+            device_id: None,
+            last_seen: dispatcher.last_seen,
+            raised_at: now,
+        };
+        registry.raise_alert(alert).await?;
+        alerts.push(alert);
+    }
+
+    let cutoff = now - config.device_offline_after();
+    for device in registry.silent_devices(cutoff).await? {
+        registry
+            .record_disconnection(ConnectionEvent {
+                device_id: device.device_id,
+                dispatcher_id: device.dispatcher_id,
+                change: ConnectionChange::Disconnected(Some(DisconnectionReason::Timeout)),
+                timestamp: now,
+            })
+            .await?;
+
+        let alert = OfflineAlert {
+            id: AlertId(Ulid::new()),
+            dispatcher_id: device.dispatcher_id,
+            device_id: Some(device.device_id),
+            last_seen: device.last_seen,
+            raised_at: now,
+        };
+        registry.raise_alert(alert).await?;
+        alerts.push(alert);
+    }
+
+    Ok(alerts)
+}
+
+/// Run [`check`] every [`CHECK_INTERVAL`] until cancelled.
+pub async fn watch<H>(registry: H, config: HealthConfig, cancel: CancellationToken)
+where
+    H: HealthRegistry,
+{
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = interval.tick() => {
+                match check(&registry, &config, jiff::Timestamp::now()).await {
+                    Ok(alerts) => {
+                        for alert in alerts {
+                            warn!(
+                                alert_id = ?alert.id,
+                                alert_type = ?alert.alert_type(),
+                                dispatcher_id = ?alert.dispatcher_id,
+                                device_id = ?alert.device_id,
+                                last_seen = %alert.last_seen,
+                                "went offline"
+                            );
+                        }
+                    }
+                    Err(e) => error!(error = ?e, "failed to check dispatcher and device health"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::memory::InMemoryHealthRegistry;
+
+    use super::*;
+
+    fn status(dispatcher_id: DispatcherId, received_at: jiff::Timestamp) -> DispatcherStatusRecord {
+        DispatcherStatusRecord {
+            dispatcher_id,
+            connected_devices: 1,
+            uptime_seconds: 60,
+            pending_uploads: 0,
+            duplicates_dropped: 0,
+            reported_at: received_at,
+            received_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_raises_alert_once() {
+        let registry = InMemoryHealthRegistry::new();
+        let config = HealthConfig::default();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        let device_id = DeviceId(Ulid::new());
+        let seen = jiff::Timestamp::from_second(1_700_000_000).unwrap();
+
+        registry
+            .record_status(status(dispatcher_id, seen))
+            .await
+            .unwrap();
+        registry
+            .record_seen(dispatcher_id, vec![device_id], seen)
+            .await
+            .unwrap();
+
+        let alerts = check(&registry, &config, seen).await.unwrap();
+        assert!(alerts.is_empty());
+
+        let later = seen + jiff::SignedDuration::from_hours(1);
+        let alerts = check(&registry, &config, later).await.unwrap();
+        assert_eq!(alerts.len(), 2);
+        assert!(
+            !registry
+                .dispatcher_health(dispatcher_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .online
+        );
+        assert!(
+            !registry
+                .device_health(device_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .online
+        );
+
+        let events = registry.list_connections(device_id, 10).await.unwrap();
+        assert_eq!(
+            events[0].change,
+            ConnectionChange::Disconnected(Some(DisconnectionReason::Timeout))
+        );
+
+        // Already offline, nothing new to raise
+        let alerts = check(&registry, &config, later).await.unwrap();
+        assert!(alerts.is_empty());
+
+        // Back online once heard from again
+        registry
+            .record_status(status(dispatcher_id, later))
+            .await
+            .unwrap();
+        let events = registry
+            .record_seen(dispatcher_id, vec![device_id], later)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].change, ConnectionChange::Connected);
+        assert!(
+            registry
+                .dispatcher_health(dispatcher_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .online
+        );
+        assert_eq!(
+            registry
+                .list_alerts(AlertSubject::Dispatcher(dispatcher_id), 10)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Checks applied to readings and statuses that dispatchers upload.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ersha_core::{
+    DeviceId, DeviceState, DeviceStatus, DispatcherId, IngestRejection, IngestRejectionReason,
+    SensorReading,
+};
+use tokio::sync::RwLock;
+
+use crate::registry::{
+    DeviceRegistry,
+    filter::{DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder},
+};
+
+/// How long a looked up device state is trusted. This bounds how long a
+/// suspension takes to reach ingest.
+pub const DEVICE_STATE_TTL: Duration = Duration::from_secs(30);
+
+/// States of devices looked up during ingest, shared across batches.
+#[derive(Clone)]
+pub struct DeviceStateCache {
+    ttl: Duration,
+    inner: Arc<RwLock<HashMap<DeviceId, (DeviceState, Instant)>>>,
+}
+
+impl Default for DeviceStateCache {
+    fn default() -> Self {
+        Self::new(DEVICE_STATE_TTL)
+    }
+}
+
+impl DeviceStateCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            inner: Arc::default(),
+        }
+    }
+
+    /// States of the given devices, fetching the ones not cached in a single
+    /// query. Devices that are not registered are left out, and are looked
+    /// up again next time.
+    pub async fn resolve<Dev>(
+        &self,
+        registry: &Dev,
+        ids: impl IntoIterator<Item = DeviceId>,
+    ) -> Result<HashMap<DeviceId, DeviceState>, Dev::Error>
+    where
+        Dev: DeviceRegistry,
+    {
+        let mut states = HashMap::new();
+        let mut missing = HashSet::new();
+        {
+            let cache = self.inner.read().await;
+            let now = Instant::now();
+            for id in ids {
+                match cache.get(&id) {
+                    Some((state, fetched_at)) if now.duration_since(*fetched_at) < self.ttl => {
+                        states.insert(id, state.clone());
+                    }
+                    _ => {
+                        missing.insert(id);
+                    }
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(states);
+        }
+
+        let limit = missing.len();
+        let options = QueryOptions {
+            filter: DeviceFilter {
+                ids: Some(missing.into_iter().collect()),
+                ..Default::default()
+            },
+            sort_by: DeviceSortBy::ProvisionAt,
+            sort_order: SortOrder::Asc,
+            pagination: Pagination::Offset { offset: 0, limit },
+        };
+        let devices = registry.list(options).await?;
+
+        let fetched_at = Instant::now();
+        let mut cache = self.inner.write().await;
+        cache.retain(|_, (_, at)| fetched_at.duration_since(*at) < self.ttl);
+        for device in devices {
+            cache.insert(device.id, (device.state.clone(), fetched_at));
+            states.insert(device.id, device.state);
+        }
+
+        Ok(states)
+    }
+}
+
+/// Records of a batch split into the ones to store and the rejected ones.
+#[derive(Debug, Default)]
+pub struct Screened {
+    pub readings: Vec<SensorReading>,
+    pub statuses: Vec<DeviceStatus>,
+    pub rejections: Vec<IngestRejection>,
+}
+
+/// Split a batch uploaded by `uploader`. `devices` holds the states of the
+/// devices in the batch, or the reason to reject the whole batch.
+pub fn screen(
+    readings: Vec<SensorReading>,
+    statuses: Vec<DeviceStatus>,
+    uploader: DispatcherId,
+    devices: Result<&HashMap<DeviceId, DeviceState>, IngestRejectionReason>,
+) -> Screened {
+    let mut screened = Screened::default();
+
+    for reading in readings {
+        let verdict = if !reading.metric.is_plausible() || !reading.confidence.is_valid() {
+            Err(IngestRejectionReason::Implausible)
+        } else {
+            devices.and_then(|devices| {
+                check_source(reading.device_id, reading.dispatcher_id, uploader, devices)
+            })
+        };
+
+        match verdict {
+            Ok(()) => screened.readings.push(reading),
+            Err(reason) => screened.rejections.push(IngestRejection::Reading {
+                id: reading.id,
+                reason,
+            }),
+        }
+    }
+
+    for status in statuses {
+        let verdict = devices.and_then(|devices| {
+            check_source(status.device_id, status.dispatcher_id, uploader, devices)
+        });
+
+        match verdict {
+            Ok(()) => screened.statuses.push(status),
+            Err(reason) => screened.rejections.push(IngestRejection::Status {
+                id: status.id,
+                reason,
+            }),
+        }
+    }
+
+    screened
+}
+
+/// Whether a record from `device_id`, forwarded by `dispatcher_id`, may be
+/// stored from a batch uploaded by `uploader`.
+fn check_source(
+    device_id: DeviceId,
+    dispatcher_id: DispatcherId,
+    uploader: DispatcherId,
+    devices: &HashMap<DeviceId, DeviceState>,
+) -> Result<(), IngestRejectionReason> {
+    if dispatcher_id != uploader {
+        return Err(IngestRejectionReason::DispatcherMismatch);
+    }
+
+    match devices.get(&device_id) {
+        Some(DeviceState::Active) => Ok(()),
+        Some(DeviceState::Suspended) => Err(IngestRejectionReason::DeviceSuspended),
+        Some(DeviceState::Retired) => Err(IngestRejectionReason::DeviceRetired),
+        None => Err(IngestRejectionReason::UnknownDevice),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{
+        Device, DeviceKind, H3Cell, Percentage, ReadingId, SensorId, SensorMetric, StatusId,
+    };
+    use ulid::Ulid;
+
+    use crate::registry::memory::InMemoryDeviceRegistry;
+
+    use super::*;
+
+    fn device(state: DeviceState) -> Device {
+        Device {
+            id: DeviceId(Ulid::new()),
+            kind: DeviceKind::Sensor,
+            state,
+            location: H3Cell(0x8a2a1072b59ffff),
+            manufacturer: None,
+            provisioned_at: jiff::Timestamp::now(),
+            sensors: vec![].into_boxed_slice(),
+            actuators: vec![].into_boxed_slice(),
+            organization: None,
+        }
+    }
+
+    fn reading(device_id: DeviceId, dispatcher_id: DispatcherId) -> SensorReading {
+        SensorReading {
+            id: ReadingId(Ulid::new()),
+            device_id,
+            dispatcher_id,
+            metric: SensorMetric::SoilMoisture {
+                value: Percentage(40),
+            },
+            location: H3Cell(0x8a2a1072b59ffff),
+            confidence: Percentage(100),
+            timestamp: jiff::Timestamp::now(),
+            sensor_id: SensorId(Ulid::new()),
+            raw_value: None,
+        }
+    }
+
+    fn status(device_id: DeviceId, dispatcher_id: DispatcherId) -> DeviceStatus {
+        DeviceStatus {
+            id: StatusId(Ulid::new()),
+            device_id,
+            dispatcher_id,
+            battery_percent: Percentage(80),
+            uptime_seconds: 60,
+            signal_rssi: -70,
+            errors: vec![].into_boxed_slice(),
+            timestamp: jiff::Timestamp::now(),
+            sensor_statuses: vec![].into_boxed_slice(),
+            firmware: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_devices_in_bulk() {
+        let registry = InMemoryDeviceRegistry::new();
+        let active = device(DeviceState::Active);
+        let suspended = device(DeviceState::Suspended);
+        registry.register(active.clone()).await.unwrap();
+        registry.register(suspended.clone()).await.unwrap();
+
+        let cache = DeviceStateCache::default();
+        let unknown = DeviceId(Ulid::new());
+        let states = cache
+            .resolve(&registry, [active.id, suspended.id, active.id, unknown])
+            .await
+            .unwrap();
+
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[&active.id], DeviceState::Active);
+        assert_eq!(states[&suspended.id], DeviceState::Suspended);
+
+        // Cached states are served until they expire.
+        registry.suspend(active.id).await.unwrap();
+        let states = cache.resolve(&registry, [active.id]).await.unwrap();
+        assert_eq!(states[&active.id], DeviceState::Active);
+
+        let cache = DeviceStateCache::new(Duration::ZERO);
+        let states = cache.resolve(&registry, [active.id]).await.unwrap();
+        assert_eq!(states[&active.id], DeviceState::Suspended);
+    }
+
+    #[test]
+    fn test_screen_rejects_with_reasons() {
+        let uploader = DispatcherId(Ulid::new());
+        let active = DeviceId(Ulid::new());
+        let suspended = DeviceId(Ulid::new());
+        let retired = DeviceId(Ulid::new());
+        let devices = HashMap::from([
+            (active, DeviceState::Active),
+            (suspended, DeviceState::Suspended),
+            (retired, DeviceState::Retired),
+        ]);
+
+        let mut implausible = reading(active, uploader);
+        implausible.metric = SensorMetric::SoilMoisture {
+            value: Percentage(150),
+        };
+        let readings = vec![
+            reading(active, uploader),
+            reading(suspended, uploader),
+            reading(retired, uploader),
+            reading(DeviceId(Ulid::new()), uploader),
+            reading(active, DispatcherId(Ulid::new())),
+            implausible,
+        ];
+        let statuses = vec![status(active, uploader), status(suspended, uploader)];
+
+        let screened = screen(readings, statuses, uploader, Ok(&devices));
+
+        assert_eq!(screened.readings.len(), 1);
+        assert_eq!(screened.statuses.len(), 1);
+        let reasons: Vec<_> = screened
+            .rejections
+            .iter()
+            .map(|rejection| match rejection {
+                IngestRejection::Reading { reason, .. }
+                | IngestRejection::Status { reason, .. } => *reason,
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                IngestRejectionReason::DeviceSuspended,
+                IngestRejectionReason::DeviceRetired,
+                IngestRejectionReason::UnknownDevice,
+                IngestRejectionReason::DispatcherMismatch,
+                IngestRejectionReason::Implausible,
+                IngestRejectionReason::DeviceSuspended,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_screen_rejects_whole_batch() {
+        let uploader = DispatcherId(Ulid::new());
+        let device_id = DeviceId(Ulid::new());
+
+        let screened = screen(
+            vec![reading(device_id, uploader)],
+            vec![status(device_id, uploader)],
+            uploader,
+            Err(IngestRejectionReason::DispatcherInactive),
+        );
+
+        assert!(screened.readings.is_empty());
+        assert!(screened.statuses.is_empty());
+        assert_eq!(screened.rejections.len(), 2);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod api;
+pub mod auth;
 pub mod client;
 pub mod config;
+pub mod geo;
+pub mod health;
+pub mod ingest;
 pub mod registry;
@@ -1,32 +1,45 @@
+use std::collections::HashSet;
 use std::path::PathBuf;
-use std::{net::SocketAddr, sync::Arc};
+use std::sync::Arc;
 
 use axum::http::{StatusCode, header};
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use clap::Parser;
 use ersha_core::{
-    AlertRequest, AlertResponse, BatchUploadRequest, BatchUploadResponse,
-    DeviceDisconnectionRequest, DeviceDisconnectionResponse, DispatcherState,
-    DispatcherStatusRequest, DispatcherStatusResponse, HelloRejectionReason, HelloRequest,
-    HelloResponse,
+    ActuatorCommandsRequest, ActuatorCommandsResponse, AlertRequest, AlertResponse,
+    BatchUploadRequest, BatchUploadResponse, CalibrationRequest, CalibrationResponse,
+    CommandOutcomeRequest, CommandOutcomeResponse, DeviceDisconnectionRequest,
+    DeviceDisconnectionResponse, DeviceFirmware, DeviceId, DeviceKeysRequest, DeviceKeysResponse,
+    Dispatcher, DispatcherId, DispatcherState, DispatcherStatusRequest, DispatcherStatusResponse,
+    FirmwareChunkRequest, FirmwareChunkResponse, FirmwareImage, FirmwareManifestRequest,
+    FirmwareManifestResponse, FirmwareReportRequest, FirmwareReportResponse, HelloRejectionReason,
+    HelloRequest, HelloResponse, IngestRejection, IngestRejectionReason,
 };
 use ersha_prime::{
-    api,
-    config::{Config, RegistryConfig},
+    api, auth,
+    config::{AuthConfig, Config, HealthConfig, RegistryConfig, ServerConfig},
+    health::{self, ConnectionChange, ConnectionEvent, DispatcherStatusRecord, ReportedAlert},
+    ingest::{self, DeviceStateCache},
     registry::{
-        DeviceRegistry, DeviceStatusRegistry, DispatcherRegistry, ReadingRegistry,
+        ApiKeyRegistry, BatchRegistry, DeviceRegistry, DispatcherRegistry, FarmRegistry,
+        HealthRegistry, IngestRegistry, OrganizationRegistry, ReadingRegistry,
         clickhouse::{
-            ClickHouseDeviceRegistry, ClickHouseDeviceStatusRegistry, ClickHouseDispatcherRegistry,
+            ClickHouseApiKeyRegistry, ClickHouseBatchRegistry, ClickHouseDeviceRegistry,
+            ClickHouseDeviceStatusRegistry, ClickHouseDispatcherRegistry, ClickHouseFarmRegistry,
+            ClickHouseHealthRegistry, ClickHouseIngestRegistry, ClickHouseOrganizationRegistry,
             ClickHouseReadingRegistry,
         },
         memory::{
-            InMemoryDeviceRegistry, InMemoryDeviceStatusRegistry, InMemoryDispatcherRegistry,
+            InMemoryApiKeyRegistry, InMemoryBatchRegistry, InMemoryDeviceRegistry,
+            InMemoryDeviceStatusRegistry, InMemoryDispatcherRegistry, InMemoryFarmRegistry,
+            InMemoryHealthRegistry, InMemoryIngestRegistry, InMemoryOrganizationRegistry,
             InMemoryReadingRegistry,
         },
         sqlite::{
-            SqliteDeviceRegistry, SqliteDeviceStatusRegistry, SqliteDispatcherRegistry,
-            SqliteReadingRegistry,
+            SqliteApiKeyRegistry, SqliteBatchRegistry, SqliteDeviceRegistry,
+            SqliteDispatcherRegistry, SqliteFarmRegistry, SqliteHealthRegistry,
+            SqliteIngestRegistry, SqliteOrganizationRegistry, SqliteReadingRegistry,
         },
     },
 };
@@ -50,17 +63,22 @@ struct Cli {
     config: PathBuf,
 }
 
-struct AppState<D, Dev, R, S>
+struct AppState<D, Dev, R, I, B, H>
 where
     D: DispatcherRegistry,
     Dev: DeviceRegistry,
     R: ReadingRegistry,
-    S: DeviceStatusRegistry,
+    I: IngestRegistry,
+    B: BatchRegistry,
+    H: HealthRegistry,
 {
     dispatcher_registry: D,
     device_registry: Dev,
     reading_registry: R,
-    device_status_registry: S,
+    ingest_registry: I,
+    batch_registry: B,
+    health_registry: H,
+    device_cache: DeviceStateCache,
 }
 
 #[tokio::main]
@@ -94,17 +112,36 @@ async fn main() -> color_eyre::Result<()> {
         RegistryConfig::Memory => {
             info!("Using in-memory registries");
             let dispatcher_registry = InMemoryDispatcherRegistry::new();
-            let device_registry = InMemoryDeviceRegistry::new();
-            let reading_registry = InMemoryReadingRegistry::new();
-            let device_status_registry = InMemoryDeviceStatusRegistry::new();
-            run_server(
+            let farm_registry = InMemoryFarmRegistry::new();
+            let device_registry = InMemoryDeviceRegistry::new().with_farms(farm_registry.clone());
+            let reading_registry =
+                InMemoryReadingRegistry::new().with_devices(device_registry.clone());
+            let device_status_registry =
+                InMemoryDeviceStatusRegistry::new().with_devices(device_registry.clone());
+            let ingest_registry =
+                InMemoryIngestRegistry::new(reading_registry.clone(), device_status_registry);
+            let batch_registry = InMemoryBatchRegistry::new();
+            let health_registry = InMemoryHealthRegistry::new();
+            let key_registry = InMemoryApiKeyRegistry::new();
+            let organization_registry = InMemoryOrganizationRegistry::new();
+            let state = AppState {
                 dispatcher_registry,
                 device_registry,
                 reading_registry,
-                device_status_registry,
-                config.server.rpc_addr,
-                config.server.http_addr,
+                ingest_registry,
+                batch_registry,
+                health_registry,
+                device_cache: DeviceStateCache::default(),
+            };
+            run_server(
+                state,
+                key_registry,
+                organization_registry,
+                farm_registry,
+                config.server,
                 config.tls,
+                config.auth,
+                config.health,
             )
             .await?;
         }
@@ -114,15 +151,30 @@ async fn main() -> color_eyre::Result<()> {
             let dispatcher_registry = SqliteDispatcherRegistry::new(&path_str).await?;
             let device_registry = SqliteDeviceRegistry::new(&path_str).await?;
             let reading_registry = SqliteReadingRegistry::new(&path_str).await?;
-            let device_status_registry = SqliteDeviceStatusRegistry::new(&path_str).await?;
-            run_server(
+            let ingest_registry = SqliteIngestRegistry::new(&path_str).await?;
+            let batch_registry = SqliteBatchRegistry::new(&path_str).await?;
+            let health_registry = SqliteHealthRegistry::new(&path_str).await?;
+            let key_registry = SqliteApiKeyRegistry::new(&path_str).await?;
+            let organization_registry = SqliteOrganizationRegistry::new(&path_str).await?;
+            let farm_registry = SqliteFarmRegistry::new(&path_str).await?;
+            let state = AppState {
                 dispatcher_registry,
                 device_registry,
                 reading_registry,
-                device_status_registry,
-                config.server.rpc_addr,
-                config.server.http_addr,
+                ingest_registry,
+                batch_registry,
+                health_registry,
+                device_cache: DeviceStateCache::default(),
+            };
+            run_server(
+                state,
+                key_registry,
+                organization_registry,
+                farm_registry,
+                config.server,
                 config.tls,
+                config.auth,
+                config.health,
             )
             .await?;
         }
@@ -133,14 +185,32 @@ async fn main() -> color_eyre::Result<()> {
             let reading_registry = ClickHouseReadingRegistry::new(&url, &database).await?;
             let device_status_registry =
                 ClickHouseDeviceStatusRegistry::new(&url, &database).await?;
-            run_server(
+            let ingest_registry =
+                ClickHouseIngestRegistry::new(reading_registry.clone(), device_status_registry);
+            let batch_registry = ClickHouseBatchRegistry::new(&url, &database).await?;
+            let health_registry = ClickHouseHealthRegistry::new(&url, &database).await?;
+            let key_registry = ClickHouseApiKeyRegistry::new(&url, &database).await?;
+            let organization_registry =
+                ClickHouseOrganizationRegistry::new(&url, &database).await?;
+            let farm_registry = ClickHouseFarmRegistry::new(&url, &database).await?;
+            let state = AppState {
                 dispatcher_registry,
                 device_registry,
                 reading_registry,
-                device_status_registry,
-                config.server.rpc_addr,
-                config.server.http_addr,
+                ingest_registry,
+                batch_registry,
+                health_registry,
+                device_cache: DeviceStateCache::default(),
+            };
+            run_server(
+                state,
+                key_registry,
+                organization_registry,
+                farm_registry,
+                config.server,
                 config.tls,
+                config.auth,
+                config.health,
             )
             .await?;
         }
@@ -149,34 +219,56 @@ async fn main() -> color_eyre::Result<()> {
     Ok(())
 }
 
-async fn run_server<D, Dev, R, S>(
-    dispatcher_registry: D,
-    device_registry: Dev,
-    reading_registry: R,
-    device_status_registry: S,
-    rpc_addr: SocketAddr,
-    http_addr: SocketAddr,
+async fn run_server<D, Dev, R, I, B, H, K, O, F>(
+    state: AppState<D, Dev, R, I, B, H>,
+    key_registry: K,
+    organization_registry: O,
+    farm_registry: F,
+    server_config: ServerConfig,
     tls_config: TlsConfig,
+    auth_config: AuthConfig,
+    health_config: HealthConfig,
 ) -> color_eyre::Result<()>
 where
     D: DispatcherRegistry,
     Dev: DeviceRegistry,
     R: ReadingRegistry,
-    S: DeviceStatusRegistry,
+    I: IngestRegistry,
+    B: BatchRegistry,
+    H: HealthRegistry,
+    K: ApiKeyRegistry,
+    O: OrganizationRegistry,
+    F: FarmRegistry,
 {
-    // Clone registries for HTTP API before moving them into AppState
-    let api_dispatcher_registry = dispatcher_registry.clone();
-    let api_device_registry = device_registry.clone();
-
-    let state = AppState {
-        dispatcher_registry,
-        device_registry,
-        reading_registry,
-        device_status_registry,
-    };
+    if auth_config.enabled {
+        if let Some(secret) =
+            auth::bootstrap(&key_registry, auth_config.bootstrap_key.as_deref()).await?
+        {
+            warn!(key = %secret, "Generated admin API key; it will not be shown again");
+        }
+    } else {
+        warn!("API authentication is disabled");
+    }
+
+    let ServerConfig {
+        rpc_addr,
+        http_addr,
+    } = server_config;
+
+    // Clone registries for HTTP API before moving AppState into the RPC server
+    let api_dispatcher_registry = state.dispatcher_registry.clone();
+    let api_device_registry = state.device_registry.clone();
+    let api_reading_registry = state.reading_registry.clone();
+    let api_health_registry = state.health_registry.clone();
 
     let cancel = CancellationToken::new();
 
+    tokio::spawn(health::watch(
+        state.health_registry.clone(),
+        health_config,
+        cancel.clone(),
+    ));
+
     let rpc_listener = TcpListener::bind(rpc_addr).await?;
     info!(%rpc_addr, "RPC server listening");
 
@@ -185,7 +277,7 @@ where
 
     let rpc_server = Server::new(rpc_listener, state, rpc_acceptor)
         .on_hello(
-            |hello: HelloRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, S>| {
+            |hello: HelloRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
                 let dispatcher_registry = state.dispatcher_registry.clone();
                 async move {
                     info!(
@@ -201,6 +293,12 @@ where
                                 dispatcher_id: hello.dispatcher_id,
                             }
                         }
+                        Ok(Some(dispatcher)) if dispatcher.state == DispatcherState::Retired => {
+                            warn!(dispatcher_id = ?hello.dispatcher_id, "dispatcher is retired");
+                            HelloResponse::Rejected {
+                                reason: HelloRejectionReason::DispatcherRetired,
+                            }
+                        }
                         Ok(Some(_)) => {
                             warn!(dispatcher_id = ?hello.dispatcher_id, "dispatcher is suspended");
                             HelloResponse::Rejected {
@@ -224,10 +322,13 @@ where
             },
         )
         .on_batch_upload(
-            |request: BatchUploadRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, S>| {
+            |request: BatchUploadRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
                 let device_registry = state.device_registry.clone();
-                let reading_registry = state.reading_registry.clone();
-                let device_status_registry = state.device_status_registry.clone();
+                let ingest_registry = state.ingest_registry.clone();
+                let batch_registry = state.batch_registry.clone();
+                let health_registry = state.health_registry.clone();
+                let device_cache = state.device_cache.clone();
                 async move {
                     info!(
                         batch_id = ?request.id,
@@ -236,51 +337,102 @@ where
                         "batch upload received"
                     );
 
-                    // Filter readings to only include known devices
-                    let mut valid_readings = Vec::new();
-                    let mut rejected_readings = 0u32;
-                    for reading in request.readings.into_vec() {
-                        match device_registry.get(reading.device_id).await {
-                            Ok(Some(_)) => {
-                                valid_readings.push(reading);
+                    // A dispatcher suspended after its hello may not keep uploading
+                    let devices = match dispatcher_registry.get(request.dispatcher_id).await {
+                        Ok(Some(dispatcher)) if dispatcher.state == DispatcherState::Active => {
+                            // A dispatcher that lost our response uploads the batch again
+                            match batch_registry.get(request.dispatcher_id, request.id).await {
+                                Ok(Some(response)) => {
+                                    info!(batch_id = ?request.id, "replaying response for processed batch");
+                                    return response;
+                                }
+                                Ok(None) => {}
+                                Err(e) => error!(error = ?e, "failed to look up batch"),
                             }
-                            _ => {
-                                rejected_readings += 1;
-                                warn!(device_id = ?reading.device_id, "rejected reading from unknown device");
+
+                            let device_ids = request
+                                .readings
+                                .iter()
+                                .map(|r| r.device_id)
+                                .chain(request.statuses.iter().map(|s| s.device_id));
+                            match device_cache.resolve(&device_registry, device_ids).await {
+                                Ok(devices) => Ok(devices),
+                                Err(e) => {
+                                    error!(error = ?e, "failed to look up devices");
+                                    return BatchUploadResponse::failed(request.id);
+                                }
                             }
                         }
-                    }
+                        Ok(_) => {
+                            warn!(dispatcher_id = ?request.dispatcher_id, "rejected batch from inactive dispatcher");
+                            Err(IngestRejectionReason::DispatcherInactive)
+                        }
+                        Err(e) => {
+                            error!(error = ?e, "failed to check dispatcher");
+                            return BatchUploadResponse::failed(request.id);
+                        }
+                    };
+
+                    let screened = ingest::screen(
+                        request.readings.into_vec(),
+                        request.statuses.into_vec(),
+                        request.dispatcher_id,
+                        devices.as_ref().map_err(|reason| *reason),
+                    );
 
-                    // Filter statuses to only include known devices
-                    let mut valid_statuses = Vec::new();
+                    let mut rejected_readings = 0u32;
                     let mut rejected_statuses = 0u32;
-                    for status in request.statuses.into_vec() {
-                        match device_registry.get(status.device_id).await {
-                            Ok(Some(_)) => {
-                                valid_statuses.push(status);
+                    for rejection in &screened.rejections {
+                        match rejection {
+                            IngestRejection::Reading { id, reason } => {
+                                rejected_readings += 1;
+                                warn!(reading_id = ?id, ?reason, "rejected reading");
                             }
-                            _ => {
+                            IngestRejection::Status { id, reason } => {
                                 rejected_statuses += 1;
-                                warn!(device_id = ?status.device_id, "rejected status from unknown device");
+                                warn!(status_id = ?id, ?reason, "rejected status");
                             }
                         }
                     }
 
-                    let readings_stored = valid_readings.len() as u32;
-                    let statuses_stored = valid_statuses.len() as u32;
+                    let readings_stored = screened.readings.len() as u32;
+                    let statuses_stored = screened.statuses.len() as u32;
+
+                    let seen: HashSet<DeviceId> = screened
+                        .readings
+                        .iter()
+                        .map(|r| r.device_id)
+                        .chain(screened.statuses.iter().map(|s| s.device_id))
+                        .collect();
 
-                    // Store valid readings
-                    if !valid_readings.is_empty()
-                        && let Err(e) = reading_registry.batch_store(valid_readings).await
+                    // Readings and statuses are stored together or not at all
+                    if let Err(e) = ingest_registry
+                        .store_batch(screened.readings, screened.statuses)
+                        .await
                     {
-                        error!(error = ?e, "failed to store readings");
+                        error!(error = ?e, batch_id = ?request.id, "failed to store batch");
+                        return BatchUploadResponse::failed(request.id);
                     }
 
-                    // Store valid statuses
-                    if !valid_statuses.is_empty()
-                        && let Err(e) = device_status_registry.batch_store(valid_statuses).await
+                    // Devices are live as long as their data keeps arriving
+                    match health_registry
+                        .record_seen(
+                            request.dispatcher_id,
+                            seen.into_iter().collect(),
+                            jiff::Timestamp::now(),
+                        )
+                        .await
                     {
-                        error!(error = ?e, "failed to store statuses");
+                        Ok(events) => {
+                            for event in events {
+                                info!(
+                                    device_id = ?event.device_id,
+                                    dispatcher_id = ?event.dispatcher_id,
+                                    "device connected"
+                                );
+                            }
+                        }
+                        Err(e) => error!(error = ?e, "failed to record devices seen"),
                     }
 
                     info!(
@@ -292,69 +444,391 @@ where
                         "batch upload processed"
                     );
 
-                    BatchUploadResponse {
+                    let response = BatchUploadResponse {
                         id: request.id,
                         readings_stored,
                         readings_rejected: rejected_readings,
                         statuses_stored,
                         statuses_rejected: rejected_statuses,
+                        rejections: screened.rejections.into_boxed_slice(),
+                        // An inactive dispatcher uploads the batch again once reactivated
+                        failed: devices.is_err(),
+                    };
+
+                    // Batches rejected as a whole are not replayed, so a retry is screened again
+                    if devices.is_ok()
+                        && let Err(e) = batch_registry
+                            .record(request.dispatcher_id, response.clone())
+                            .await
+                    {
+                        error!(error = ?e, batch_id = ?request.id, "failed to record batch");
                     }
+
+                    response
                 }
             },
         )
         .on_alert(
-            |request: AlertRequest, _msg_id, _rpc, _state: &AppState<D, Dev, R, S>| async move {
-
-                info!(
-                    alert_id = ?request.id,
-                    dispatcher_id = ?request.dispatcher_id,
-                    device_id = ?request.device_id,
-                    severity = ?request.severity,
-                    alert_type = ?request.alert_type,
-                    message = %request.message,
-                    "alert received"
-                );
-
-                // For now, just acknowledge the alert
-                // In the future, this could trigger notifications, store alerts, etc.
-                AlertResponse {
-                    alert_id: request.id,
-                    acknowledged: true,
+            |request: AlertRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let health_registry = state.health_registry.clone();
+                async move {
+                    let organization = match dispatcher_registry.get(request.dispatcher_id).await {
+                        Ok(dispatcher) => dispatcher.and_then(|d| d.organization),
+                        Err(e) => {
+                            warn!(error = ?e, "failed to look up alerting dispatcher");
+                            None
+                        }
+                    };
+
+                    info!(
+                        alert_id = ?request.id,
+                        dispatcher_id = ?request.dispatcher_id,
+                        organization = ?organization,
+                        device_id = ?request.device_id,
+                        severity = ?request.severity,
+                        alert_type = ?request.alert_type,
+                        message = %request.message,
+                        "alert received"
+                    );
+
+                    // Listed to the dispatcher's organization through the alert endpoints
+                    let alert_id = request.id;
+                    let alert = ReportedAlert::new(request, organization, jiff::Timestamp::now());
+                    let acknowledged = match health_registry.record_reported_alert(alert).await {
+                        Ok(()) => true,
+                        Err(e) => {
+                            error!(error = ?e, ?alert_id, "failed to record alert");
+                            false
+                        }
+                    };
+
+                    AlertResponse {
+                        alert_id,
+                        acknowledged,
+                    }
                 }
             },
         )
         .on_dispatcher_status(
-            |request: DispatcherStatusRequest, _msg_id, _rpc, _state: &AppState<D, Dev, R, S>| async move {
-                info!(
-                    dispatcher_id = ?request.dispatcher_id,
-                    connected_devices = request.connected_devices,
-                    uptime_seconds = request.uptime_seconds,
-                    pending_uploads = request.pending_uploads,
-                    "dispatcher status received"
-                );
-
-                DispatcherStatusResponse {
-                    dispatcher_id: request.dispatcher_id,
+            |request: DispatcherStatusRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let health_registry = state.health_registry.clone();
+                async move {
+                    info!(
+                        dispatcher_id = ?request.dispatcher_id,
+                        connected_devices = request.connected_devices,
+                        uptime_seconds = request.uptime_seconds,
+                        pending_uploads = request.pending_uploads,
+                        duplicates_dropped = request.duplicates_dropped,
+                        "dispatcher status received"
+                    );
+
+                    let dispatcher_id = request.dispatcher_id;
+                    let record = DispatcherStatusRecord::new(request, jiff::Timestamp::now());
+                    if let Err(e) = health_registry.record_status(record).await {
+                        error!(error = ?e, ?dispatcher_id, "failed to record dispatcher status");
+                    }
+
+                    DispatcherStatusResponse { dispatcher_id }
                 }
             },
         )
         .on_device_disconnection(
-            |request: DeviceDisconnectionRequest, _msg_id, _rpc, _state: &AppState<D, Dev, R, S>| async move {
-                info!(
-                    device_id = ?request.device_id,
-                    dispatcher_id = ?request.dispatcher_id,
-                    reason = ?request.reason,
-                    "device disconnection notification received"
-                );
-
-                DeviceDisconnectionResponse {
-                    device_id: request.device_id,
+            |request: DeviceDisconnectionRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let health_registry = state.health_registry.clone();
+                async move {
+                    info!(
+                        device_id = ?request.device_id,
+                        dispatcher_id = ?request.dispatcher_id,
+                        reason = ?request.reason,
+                        "device disconnection notification received"
+                    );
+
+                    let device_id = request.device_id;
+                    let event = ConnectionEvent {
+                        device_id,
+                        dispatcher_id: request.dispatcher_id,
+                        change: ConnectionChange::Disconnected(request.reason),
+                        timestamp: request.timestamp,
+                    };
+                    if let Err(e) = health_registry.record_disconnection(event).await {
+                        error!(error = ?e, ?device_id, "failed to record device disconnection");
+                    }
+
+                    DeviceDisconnectionResponse { device_id }
+                }
+            },
+        )
+        .on_calibration(
+            |request: CalibrationRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let device_registry = state.device_registry.clone();
+                async move {
+                    let Some(dispatcher) =
+                        active_dispatcher(&dispatcher_registry, request.dispatcher_id).await
+                    else {
+                        warn!(
+                            dispatcher_id = ?request.dispatcher_id,
+                            "calibrations requested by unknown or suspended dispatcher"
+                        );
+                        return CalibrationResponse {
+                            profiles: Box::new([]),
+                        };
+                    };
+
+                    // Dispatchers only see their own organization's sensors
+                    let profiles = match device_registry
+                        .list_calibrations(dispatcher.organization)
+                        .await
+                    {
+                        Ok(profiles) => profiles,
+                        Err(e) => {
+                            error!(
+                                error = ?e,
+                                dispatcher_id = ?request.dispatcher_id,
+                                "failed to list calibrations"
+                            );
+                            Vec::new()
+                        }
+                    };
+
+                    CalibrationResponse {
+                        profiles: profiles.into_boxed_slice(),
+                    }
+                }
+            },
+        )
+        .on_device_keys(
+            |request: DeviceKeysRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let device_registry = state.device_registry.clone();
+                async move {
+                    // keys only go to dispatchers allowed to ingest data
+                    let Some(dispatcher) =
+                        active_dispatcher(&dispatcher_registry, request.dispatcher_id).await
+                    else {
+                        warn!(
+                            dispatcher_id = ?request.dispatcher_id,
+                            "device keys requested by unknown or suspended dispatcher"
+                        );
+                        return DeviceKeysResponse {
+                            credentials: Box::new([]),
+                        };
+                    };
+
+                    // and only for devices of the dispatcher's own organization
+                    let credentials = match device_registry.list_keys(dispatcher.organization).await
+                    {
+                        Ok(credentials) => credentials,
+                        Err(e) => {
+                            error!(
+                                error = ?e,
+                                dispatcher_id = ?request.dispatcher_id,
+                                "failed to list device keys"
+                            );
+                            Vec::new()
+                        }
+                    };
+
+                    DeviceKeysResponse {
+                        credentials: credentials.into_boxed_slice(),
+                    }
+                }
+            },
+        )
+        .on_firmware_manifest(
+            |request: FirmwareManifestRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let device_registry = state.device_registry.clone();
+                async move {
+                    if !is_active_dispatcher(&dispatcher_registry, request.dispatcher_id).await {
+                        warn!(
+                            dispatcher_id = ?request.dispatcher_id,
+                            "firmware requested by unknown or suspended dispatcher"
+                        );
+                        return FirmwareManifestResponse {
+                            images: Box::new([]),
+                        };
+                    }
+
+                    let images = match device_registry.list_firmware().await {
+                        Ok(images) => images,
+                        Err(e) => {
+                            error!(error = ?e, "failed to list firmware");
+                            Vec::new()
+                        }
+                    };
+
+                    FirmwareManifestResponse {
+                        images: newest_per_model(images).into_boxed_slice(),
+                    }
+                }
+            },
+        )
+        .on_firmware_chunk(
+            |request: FirmwareChunkRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let device_registry = state.device_registry.clone();
+                async move {
+                    let mut response = FirmwareChunkResponse {
+                        firmware_id: request.firmware_id,
+                        offset: request.offset,
+                        data: Box::new([]),
+                    };
+
+                    if !is_active_dispatcher(&dispatcher_registry, request.dispatcher_id).await {
+                        return response;
+                    }
+
+                    let len = request.len.min(MAX_FIRMWARE_CHUNK);
+                    match device_registry
+                        .firmware_chunk(request.firmware_id, request.offset, len)
+                        .await
+                    {
+                        Ok(Some(data)) => response.data = data.into_boxed_slice(),
+                        Ok(None) => {}
+                        Err(e) => {
+                            error!(
+                                error = ?e,
+                                firmware_id = ?request.firmware_id,
+                                "failed to read firmware chunk"
+                            );
+                        }
+                    }
+
+                    response
+                }
+            },
+        )
+        .on_actuator_commands(
+            |request: ActuatorCommandsRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let device_registry = state.device_registry.clone();
+                async move {
+                    let Some(dispatcher) =
+                        active_dispatcher(&dispatcher_registry, request.dispatcher_id).await
+                    else {
+                        warn!(
+                            dispatcher_id = ?request.dispatcher_id,
+                            "commands requested by unknown or suspended dispatcher"
+                        );
+                        return ActuatorCommandsResponse {
+                            commands: Box::new([]),
+                        };
+                    };
+
+                    let now = jiff::Timestamp::now();
+                    match device_registry.expire_commands(now).await {
+                        Ok(0) => {}
+                        Ok(expired) => info!(expired, "actuator commands timed out"),
+                        Err(e) => error!(error = ?e, "failed to expire commands"),
+                    }
+
+                    // only for devices of the dispatcher's own organization
+                    let commands = match device_registry
+                        .pending_commands(now, dispatcher.organization)
+                        .await
+                    {
+                        Ok(commands) => commands,
+                        Err(e) => {
+                            error!(error = ?e, "failed to list pending commands");
+                            Vec::new()
+                        }
+                    };
+
+                    ActuatorCommandsResponse {
+                        commands: commands.into_boxed_slice(),
+                    }
+                }
+            },
+        )
+        .on_command_outcome(
+            |request: CommandOutcomeRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let device_registry = state.device_registry.clone();
+                async move {
+                    let response = CommandOutcomeResponse {
+                        command_id: request.command_id,
+                    };
+
+                    if !is_active_dispatcher(&dispatcher_registry, request.dispatcher_id).await {
+                        warn!(
+                            dispatcher_id = ?request.dispatcher_id,
+                            "command outcome from unknown or suspended dispatcher"
+                        );
+                        return response;
+                    }
+
+                    // another dispatcher may have reported it first
+                    match device_registry
+                        .complete_command(request.command_id, request.result, request.timestamp)
+                        .await
+                    {
+                        Ok(true) => info!(
+                            command_id = ?request.command_id,
+                            device_id = ?request.device_id,
+                            result = ?request.result,
+                            "actuator command completed"
+                        ),
+                        Ok(false) => {}
+                        Err(e) => error!(
+                            error = ?e,
+                            command_id = ?request.command_id,
+                            "failed to record command outcome"
+                        ),
+                    }
+
+                    response
+                }
+            },
+        )
+        .on_firmware_report(
+            |request: FirmwareReportRequest, _msg_id, _rpc, state: &AppState<D, Dev, R, I, B, H>| {
+                let dispatcher_registry = state.dispatcher_registry.clone();
+                let device_registry = state.device_registry.clone();
+                async move {
+                    let response = FirmwareReportResponse {
+                        device_id: request.device_id,
+                    };
+
+                    if !is_active_dispatcher(&dispatcher_registry, request.dispatcher_id).await {
+                        warn!(
+                            dispatcher_id = ?request.dispatcher_id,
+                            "firmware report from unknown or suspended dispatcher"
+                        );
+                        return response;
+                    }
+
+                    let report = DeviceFirmware {
+                        device_id: request.device_id,
+                        firmware: request.firmware,
+                        reported_at: request.timestamp,
+                    };
+
+                    if let Err(e) = device_registry.record_firmware(report).await {
+                        error!(
+                            error = ?e,
+                            device_id = ?request.device_id,
+                            "failed to record firmware report"
+                        );
+                    }
+
+                    response
                 }
             },
         );
 
     // Create the API router with dispatcher and device routes
-    let api_router = api::api_router(api_dispatcher_registry, api_device_registry);
+    let api_router = api::api_router(
+        api_dispatcher_registry,
+        api_device_registry,
+        api_reading_registry,
+        api_health_registry,
+        key_registry,
+        organization_registry,
+        farm_registry,
+        auth_config.enabled,
+    );
 
     // Merge with health endpoint
     let axum_app = api_router
@@ -386,6 +860,41 @@ where
     Ok(())
 }
 
+/// Largest firmware chunk served to a dispatcher in one response.
+const MAX_FIRMWARE_CHUNK: u32 = 256 * 1024;
+
+async fn is_active_dispatcher<D: DispatcherRegistry>(registry: &D, id: DispatcherId) -> bool {
+    active_dispatcher(registry, id).await.is_some()
+}
+
+/// The dispatcher with `id`, if it is registered and active.
+async fn active_dispatcher<D: DispatcherRegistry>(
+    registry: &D,
+    id: DispatcherId,
+) -> Option<Dispatcher> {
+    match registry.get(id).await {
+        Ok(Some(dispatcher)) if dispatcher.state == DispatcherState::Active => Some(dispatcher),
+        Ok(_) => None,
+        Err(e) => {
+            error!(error = ?e, "failed to check dispatcher");
+            None
+        }
+    }
+}
+
+/// Keep only the newest image of each device model.
+fn newest_per_model(images: Vec<FirmwareImage>) -> Vec<FirmwareImage> {
+    let mut newest: Vec<FirmwareImage> = Vec::new();
+    for image in images {
+        match newest.iter_mut().find(|n| n.model == image.model) {
+            Some(n) if n.version < image.version => *n = image,
+            Some(_) => {}
+            None => newest.push(image),
+        }
+    }
+    newest
+}
+
 async fn health_handler() -> &'static str {
     "OK"
 }
@@ -0,0 +1,175 @@
+use ersha_core::{ActuatorAction, ActuatorKind, ActuatorState, CommandResult};
+
+pub(crate) fn actuator_kind_code(kind: ActuatorKind) -> i32 {
+    match kind {
+        ActuatorKind::Valve => 0,
+        ActuatorKind::Pump => 1,
+    }
+}
+
+/// Decode an actuator kind, or return the unknown code.
+pub(crate) fn decode_actuator_kind(code: i32) -> Result<ActuatorKind, i32> {
+    match code {
+        0 => Ok(ActuatorKind::Valve),
+        1 => Ok(ActuatorKind::Pump),
+        other => Err(other),
+    }
+}
+
+/// Column form of an [`ActuatorAction`], shared by the SQL backends. The
+/// duration is zero for a close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ActionColumns {
+    pub action: i32,
+    pub duration_secs: u32,
+}
+
+impl From<&ActuatorAction> for ActionColumns {
+    fn from(action: &ActuatorAction) -> Self {
+        let (action, duration_secs) = match action {
+            ActuatorAction::Open { duration_secs } => (0, *duration_secs),
+            ActuatorAction::Close => (1, 0),
+        };
+
+        Self {
+            action,
+            duration_secs,
+        }
+    }
+}
+
+impl ActionColumns {
+    /// Decode the columns, or return the unknown action code.
+    pub fn decode(self) -> Result<ActuatorAction, i32> {
+        match self.action {
+            0 => Ok(ActuatorAction::Open {
+                duration_secs: self.duration_secs,
+            }),
+            1 => Ok(ActuatorAction::Close),
+            other => Err(other),
+        }
+    }
+}
+
+/// Whether `result` may be recorded for a command whose result so far is
+/// `current`. The first result is kept, except that one from the device
+/// replaces a timeout declared before its late acknowledgement arrived.
+pub(crate) fn replaces_result(current: Option<&CommandResult>, result: &CommandResult) -> bool {
+    match current {
+        None => true,
+        Some(CommandResult::TimedOut) => *result != CommandResult::TimedOut,
+        Some(_) => false,
+    }
+}
+
+/// Column form of a [`CommandResult`], shared by the SQL backends.
+/// `open_until` is in seconds and only set for an actuator left open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResultColumns {
+    pub result: i32,
+    pub open_until: Option<i64>,
+}
+
+impl From<&CommandResult> for ResultColumns {
+    fn from(result: &CommandResult) -> Self {
+        let (result, open_until) = match result {
+            CommandResult::Applied {
+                state: ActuatorState::Closed,
+            } => (0, None),
+            CommandResult::Applied {
+                state: ActuatorState::Open { until },
+            } => (1, Some(until.as_second())),
+            CommandResult::Failed => (2, None),
+            CommandResult::TimedOut => (3, None),
+        };
+
+        Self { result, open_until }
+    }
+}
+
+impl ResultColumns {
+    /// Decode the columns, or return the unknown result code.
+    pub fn decode(self) -> Result<CommandResult, i32> {
+        Ok(match self.result {
+            0 => CommandResult::Applied {
+                state: ActuatorState::Closed,
+            },
+            1 => {
+                let until = self
+                    .open_until
+                    .and_then(|secs| jiff::Timestamp::from_second(secs).ok())
+                    .ok_or(1)?;
+                CommandResult::Applied {
+                    state: ActuatorState::Open { until },
+                }
+            }
+            2 => CommandResult::Failed,
+            3 => CommandResult::TimedOut,
+            other => return Err(other),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{ActuatorAction, ActuatorState, CommandResult};
+
+    use super::{ActionColumns, ResultColumns, replaces_result};
+
+    #[test]
+    fn round_trips_actions_and_results() {
+        for action in [
+            ActuatorAction::Open { duration_secs: 900 },
+            ActuatorAction::Close,
+        ] {
+            assert_eq!(ActionColumns::from(&action).decode(), Ok(action));
+        }
+
+        let until = jiff::Timestamp::from_second(1_700_000_000).unwrap();
+        let results = [
+            CommandResult::Applied {
+                state: ActuatorState::Closed,
+            },
+            CommandResult::Applied {
+                state: ActuatorState::Open { until },
+            },
+            CommandResult::Failed,
+            CommandResult::TimedOut,
+        ];
+        for result in results {
+            assert_eq!(ResultColumns::from(&result).decode(), Ok(result));
+        }
+    }
+
+    #[test]
+    fn device_results_replace_timeouts_only() {
+        let applied = CommandResult::Applied {
+            state: ActuatorState::Closed,
+        };
+
+        assert!(replaces_result(None, &CommandResult::TimedOut));
+        assert!(replaces_result(Some(&CommandResult::TimedOut), &applied));
+        assert!(replaces_result(
+            Some(&CommandResult::TimedOut),
+            &CommandResult::Failed
+        ));
+        assert!(!replaces_result(
+            Some(&CommandResult::TimedOut),
+            &CommandResult::TimedOut
+        ));
+        assert!(!replaces_result(Some(&applied), &CommandResult::Failed));
+        assert!(!replaces_result(
+            Some(&CommandResult::Failed),
+            &CommandResult::TimedOut
+        ));
+    }
+
+    #[test]
+    fn rejects_open_results_without_a_deadline() {
+        let columns = ResultColumns {
+            result: 1,
+            open_until: None,
+        };
+        assert_eq!(columns.decode(), Err(1));
+    }
+}
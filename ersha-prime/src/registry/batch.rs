@@ -0,0 +1,106 @@
+use ersha_core::{IngestRejection, IngestRejectionReason, ReadingId, StatusId};
+use ulid::Ulid;
+
+fn reason_code(reason: IngestRejectionReason) -> i32 {
+    match reason {
+        IngestRejectionReason::UnknownDevice => 0,
+        IngestRejectionReason::DeviceSuspended => 1,
+        IngestRejectionReason::DeviceRetired => 2,
+        IngestRejectionReason::DispatcherMismatch => 3,
+        IngestRejectionReason::DispatcherInactive => 4,
+        IngestRejectionReason::Implausible => 5,
+        IngestRejectionReason::InternalError => 6,
+    }
+}
+
+fn decode_reason(code: i32) -> Result<IngestRejectionReason, i32> {
+    match code {
+        0 => Ok(IngestRejectionReason::UnknownDevice),
+        1 => Ok(IngestRejectionReason::DeviceSuspended),
+        2 => Ok(IngestRejectionReason::DeviceRetired),
+        3 => Ok(IngestRejectionReason::DispatcherMismatch),
+        4 => Ok(IngestRejectionReason::DispatcherInactive),
+        5 => Ok(IngestRejectionReason::Implausible),
+        6 => Ok(IngestRejectionReason::InternalError),
+        other => Err(other),
+    }
+}
+
+/// Column form of an [`IngestRejection`], shared by the SQL backends. `kind`
+/// is 0 for a reading and 1 for a status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RejectionColumns {
+    pub kind: i32,
+    pub record_id: Ulid,
+    pub reason: i32,
+}
+
+impl From<&IngestRejection> for RejectionColumns {
+    fn from(rejection: &IngestRejection) -> Self {
+        let (kind, record_id, reason) = match *rejection {
+            IngestRejection::Reading { id, reason } => (0, id.0, reason),
+            IngestRejection::Status { id, reason } => (1, id.0, reason),
+        };
+
+        Self {
+            kind,
+            record_id,
+            reason: reason_code(reason),
+        }
+    }
+}
+
+impl RejectionColumns {
+    /// Decode the columns, or return the unknown kind or reason code.
+    pub fn decode(self) -> Result<IngestRejection, i32> {
+        let reason = decode_reason(self.reason)?;
+        match self.kind {
+            0 => Ok(IngestRejection::Reading {
+                id: ReadingId(self.record_id),
+                reason,
+            }),
+            1 => Ok(IngestRejection::Status {
+                id: StatusId(self.record_id),
+                reason,
+            }),
+            other => Err(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{IngestRejection, IngestRejectionReason, ReadingId, StatusId};
+    use ulid::Ulid;
+
+    use super::RejectionColumns;
+
+    #[test]
+    fn round_trips_every_rejection() {
+        let reasons = [
+            IngestRejectionReason::UnknownDevice,
+            IngestRejectionReason::DeviceSuspended,
+            IngestRejectionReason::DeviceRetired,
+            IngestRejectionReason::DispatcherMismatch,
+            IngestRejectionReason::DispatcherInactive,
+            IngestRejectionReason::Implausible,
+            IngestRejectionReason::InternalError,
+        ];
+
+        for reason in reasons {
+            for rejection in [
+                IngestRejection::Reading {
+                    id: ReadingId(Ulid::new()),
+                    reason,
+                },
+                IngestRejection::Status {
+                    id: StatusId(Ulid::new()),
+                    reason,
+                },
+            ] {
+                let columns = RejectionColumns::from(&rejection);
+                assert_eq!(columns.decode(), Ok(rejection));
+            }
+        }
+    }
+}
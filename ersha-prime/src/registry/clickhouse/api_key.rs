@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use clickhouse::{Client, Row};
+use ersha_core::OrganizationId;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use super::{
+    ClickHouseError,
+    device::{decode_hex, encode_hex},
+};
+use crate::{
+    auth::{ApiKey, ApiKeyId, Role},
+    registry::ApiKeyRegistry,
+};
+
+const CREATE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS api_keys (
+    id String,
+    name String,
+    role Int32,
+    hash String,
+    organization_id Nullable(String),
+    created_at Int64,
+    revoked_at Nullable(Int64),
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY id
+"#;
+
+const ADD_ORGANIZATION: &str =
+    "ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS organization_id Nullable(String)";
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct ApiKeyRow {
+    id: String,
+    name: String,
+    role: i32,
+    hash: String,
+    organization_id: Option<String>,
+    created_at: i64,
+    revoked_at: Option<i64>,
+    version: u64,
+}
+
+impl TryFrom<ApiKeyRow> for ApiKey {
+    type Error = ClickHouseError;
+
+    fn try_from(row: ApiKeyRow) -> Result<Self, Self::Error> {
+        let id =
+            Ulid::from_str(&row.id).map_err(|_| ClickHouseError::InvalidUlid(row.id.clone()))?;
+
+        let role = match row.role {
+            0 => Role::Viewer,
+            1 => Role::Operator,
+            2 => Role::Admin,
+            other => return Err(ClickHouseError::InvalidRole(other)),
+        };
+
+        let hash = decode_hex(&row.hash)
+            .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+            .ok_or(ClickHouseError::InvalidKeyHash)?;
+
+        let organization = row
+            .organization_id
+            .map(|id| {
+                Ulid::from_str(&id)
+                    .map(OrganizationId)
+                    .map_err(|_| ClickHouseError::InvalidUlid(id))
+            })
+            .transpose()?;
+
+        let timestamp = |secs: i64| {
+            jiff::Timestamp::from_second(secs).map_err(|_| ClickHouseError::InvalidTimestamp(secs))
+        };
+
+        Ok(ApiKey {
+            id: ApiKeyId(id),
+            name: row.name,
+            role,
+            hash,
+            organization,
+            created_at: timestamp(row.created_at)?,
+            revoked_at: row.revoked_at.map(timestamp).transpose()?,
+        })
+    }
+}
+
+impl From<&ApiKey> for ApiKeyRow {
+    fn from(key: &ApiKey) -> Self {
+        ApiKeyRow {
+            id: key.id.0.to_string(),
+            name: key.name.clone(),
+            role: key.role as i32,
+            hash: encode_hex(&key.hash),
+            organization_id: key.organization.map(|o| o.0.to_string()),
+            created_at: key.created_at.as_second(),
+            revoked_at: key.revoked_at.map(|t| t.as_second()),
+            version: jiff::Timestamp::now().as_millisecond() as u64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClickHouseApiKeyRegistry {
+    client: Client,
+}
+
+impl ClickHouseApiKeyRegistry {
+    pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
+        let client = super::create_client(url, database);
+        client.query(CREATE_TABLE).execute().await?;
+        client.query(ADD_ORGANIZATION).execute().await?;
+        Ok(Self { client })
+    }
+
+    async fn write(&self, key: &ApiKey) -> Result<(), ClickHouseError> {
+        let mut insert = self.client.insert("api_keys")?;
+        insert.write(&ApiKeyRow::from(key)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn get(&self, id: ApiKeyId) -> Result<Option<ApiKey>, ClickHouseError> {
+        let row: Option<ApiKeyRow> = self
+            .client
+            .query("SELECT ?fields FROM api_keys FINAL WHERE id = ?")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(ApiKey::try_from).transpose()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRegistry for ClickHouseApiKeyRegistry {
+    type Error = ClickHouseError;
+
+    async fn add(&self, key: ApiKey) -> Result<(), Self::Error> {
+        if self.get_by_hash(key.hash).await?.is_some() {
+            return Err(ClickHouseError::AlreadyExists);
+        }
+        self.write(&key).await
+    }
+
+    async fn get_by_hash(&self, hash: [u8; 32]) -> Result<Option<ApiKey>, Self::Error> {
+        let row: Option<ApiKeyRow> = self
+            .client
+            .query("SELECT ?fields FROM api_keys FINAL WHERE hash = ?")
+            .bind(encode_hex(&hash))
+            .fetch_optional()
+            .await?;
+
+        row.map(ApiKey::try_from).transpose()
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKey>, Self::Error> {
+        let rows: Vec<ApiKeyRow> = self
+            .client
+            .query("SELECT ?fields FROM api_keys FINAL ORDER BY id")
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(ApiKey::try_from).collect()
+    }
+
+    async fn revoke(&self, id: ApiKeyId, at: jiff::Timestamp) -> Result<bool, Self::Error> {
+        match self.get(id).await? {
+            Some(key) if key.is_active() => {
+                let key = ApiKey {
+                    revoked_at: Some(at),
+                    ..key
+                };
+                self.write(&key).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use clickhouse::{Client, Row};
+use ersha_core::{BatchId, BatchUploadResponse, DispatcherId};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use super::ClickHouseError;
+use crate::registry::{BatchRegistry, batch::RejectionColumns};
+
+const CREATE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS batches (
+    id String,
+    dispatcher_id String DEFAULT '',
+    readings_stored UInt32,
+    readings_rejected UInt32,
+    statuses_stored UInt32,
+    statuses_rejected UInt32,
+    rejections Array(Tuple(Int32, String, Int32)),
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY id
+"#;
+
+const ADD_DISPATCHER: &str =
+    "ALTER TABLE batches ADD COLUMN IF NOT EXISTS dispatcher_id String DEFAULT ''";
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct BatchRow {
+    id: String,
+    dispatcher_id: String,
+    readings_stored: u32,
+    readings_rejected: u32,
+    statuses_stored: u32,
+    statuses_rejected: u32,
+    rejections: Vec<(i32, String, i32)>,
+    version: u64,
+}
+
+impl TryFrom<BatchRow> for BatchUploadResponse {
+    type Error = ClickHouseError;
+
+    fn try_from(row: BatchRow) -> Result<Self, Self::Error> {
+        let id =
+            Ulid::from_str(&row.id).map_err(|_| ClickHouseError::InvalidUlid(row.id.clone()))?;
+
+        let rejections = row
+            .rejections
+            .into_iter()
+            .map(|(kind, record_id, reason)| {
+                let record_id = Ulid::from_str(&record_id)
+                    .map_err(|_| ClickHouseError::InvalidUlid(record_id))?;
+                RejectionColumns {
+                    kind,
+                    record_id,
+                    reason,
+                }
+                .decode()
+                .map_err(ClickHouseError::InvalidRejection)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(BatchUploadResponse {
+            id: BatchId(id),
+            readings_stored: row.readings_stored,
+            readings_rejected: row.readings_rejected,
+            statuses_stored: row.statuses_stored,
+            statuses_rejected: row.statuses_rejected,
+            rejections: rejections.into_boxed_slice(),
+            failed: false,
+        })
+    }
+}
+
+impl BatchRow {
+    fn new(dispatcher_id: DispatcherId, response: &BatchUploadResponse) -> Self {
+        BatchRow {
+            id: response.id.0.to_string(),
+            dispatcher_id: dispatcher_id.0.to_string(),
+            readings_stored: response.readings_stored,
+            readings_rejected: response.readings_rejected,
+            statuses_stored: response.statuses_stored,
+            statuses_rejected: response.statuses_rejected,
+            rejections: response
+                .rejections
+                .iter()
+                .map(|rejection| {
+                    let columns = RejectionColumns::from(rejection);
+                    (columns.kind, columns.record_id.to_string(), columns.reason)
+                })
+                .collect(),
+            version: jiff::Timestamp::now().as_millisecond() as u64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClickHouseBatchRegistry {
+    client: Client,
+}
+
+impl ClickHouseBatchRegistry {
+    pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
+        let client = super::create_client(url, database);
+        client.query(CREATE_TABLE).execute().await?;
+        client.query(ADD_DISPATCHER).execute().await?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl BatchRegistry for ClickHouseBatchRegistry {
+    type Error = ClickHouseError;
+
+    async fn get(
+        &self,
+        dispatcher_id: DispatcherId,
+        id: BatchId,
+    ) -> Result<Option<BatchUploadResponse>, Self::Error> {
+        // The oldest row is the response first sent, replayed only to the
+        // dispatcher it was sent to.
+        let row: Option<BatchRow> = self
+            .client
+            .query("SELECT ?fields FROM batches WHERE id = ? ORDER BY version LIMIT 1")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.filter(|row| row.dispatcher_id == dispatcher_id.0.to_string())
+            .map(BatchUploadResponse::try_from)
+            .transpose()
+    }
+
+    async fn record(
+        &self,
+        dispatcher_id: DispatcherId,
+        response: BatchUploadResponse,
+    ) -> Result<(), Self::Error> {
+        let recorded: u64 = self
+            .client
+            .query("SELECT count() FROM batches WHERE id = ?")
+            .bind(response.id.0.to_string())
+            .fetch_one()
+            .await?;
+        if recorded > 0 {
+            return Ok(());
+        }
+
+        let mut insert = self.client.insert("batches")?;
+        insert
+            .write(&BatchRow::new(dispatcher_id, &response))
+            .await?;
+        insert.end().await?;
+        Ok(())
+    }
+}
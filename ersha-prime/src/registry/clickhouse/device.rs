@@ -3,8 +3,10 @@ use std::str::FromStr;
 use async_trait::async_trait;
 use clickhouse::{Client, Row};
 use ersha_core::{
-    Device, DeviceId, DeviceKind, DeviceState, H3Cell, Percentage, Sensor, SensorId, SensorKind,
-    SensorMetric,
+    Actuator, ActuatorCommand, ActuatorId, Calibration, CalibrationProfile, CommandId,
+    CommandRecord, CommandResult, Device, DeviceCredential, DeviceFirmware, DeviceId, DeviceKey,
+    DeviceKind, DeviceState, FirmwareId, FirmwareImage, FirmwareStatus, H3Cell, OrganizationId,
+    Sensor, SensorId,
 };
 use ordered_float::NotNan;
 use serde::{Deserialize, Serialize};
@@ -13,7 +15,12 @@ use ulid::Ulid;
 use super::ClickHouseError;
 use crate::registry::{
     DeviceRegistry,
+    actuator::{
+        ActionColumns, ResultColumns, actuator_kind_code, decode_actuator_kind, replaces_result,
+    },
     filter::{DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder},
+    firmware::FirmwareUpdateColumns,
+    metric::{decode_metric, decode_sensor_kind, disect_metric},
 };
 
 const CREATE_DEVICE_TABLE: &str = r#"
@@ -25,11 +32,18 @@ CREATE TABLE IF NOT EXISTS devices (
     manufacturer Nullable(String),
     provisioned_at Int64,
     sensor_count Int64,
+    organization_id Nullable(String),
     version UInt64
 ) ENGINE = ReplacingMergeTree(version)
 ORDER BY id
 "#;
 
+const ADD_DEVICE_ORGANIZATION: &str =
+    "ALTER TABLE devices ADD COLUMN IF NOT EXISTS organization_id Nullable(String)";
+
+const ADD_SENSOR_DELETED: &str =
+    "ALTER TABLE sensors ADD COLUMN IF NOT EXISTS deleted UInt8 DEFAULT 0";
+
 const CREATE_SENSOR_TABLE: &str = r#"
 CREATE TABLE IF NOT EXISTS sensors (
     id String,
@@ -42,6 +56,88 @@ CREATE TABLE IF NOT EXISTS sensors (
 ORDER BY (device_id, id)
 "#;
 
+const CREATE_CALIBRATION_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS sensor_calibrations (
+    sensor_id String,
+    kind Int32,
+    gain Float64,
+    offset Float64,
+    points Array(Tuple(Float64, Float64)),
+    cleared UInt8,
+    updated_at Int64,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY sensor_id
+"#;
+
+const CREATE_KEY_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS device_keys (
+    device_id String,
+    key String,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY device_id
+"#;
+
+const CREATE_FIRMWARE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS firmware_images (
+    id String,
+    model String,
+    version UInt32,
+    size UInt32,
+    sha256 String,
+    signature String,
+    data String,
+    created_at Int64
+) ENGINE = ReplacingMergeTree
+ORDER BY id
+"#;
+
+/// The firmware each device reported last. Rows are versioned by when the
+/// device reported, so a late report never replaces a newer one.
+const CREATE_DEVICE_FIRMWARE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS device_firmware (
+    device_id String,
+    model String,
+    firmware_version UInt32,
+    update_state Int32,
+    target_version UInt32,
+    progress UInt8,
+    failure Int32,
+    reported_at Int64,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY device_id
+"#;
+
+const CREATE_ACTUATOR_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS actuators (
+    id String,
+    kind Int32,
+    max_run_secs UInt32,
+    device_id String,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY (device_id, id)
+"#;
+
+const CREATE_COMMAND_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS actuator_commands (
+    id String,
+    device_id String,
+    actuator_id String,
+    action Int32,
+    duration_secs UInt32,
+    issued_at Int64,
+    expires_at Int64,
+    result Nullable(Int32),
+    open_until Nullable(Int64),
+    completed_at Nullable(Int64),
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY id
+"#;
+
 #[derive(Debug, Clone, Row, Serialize, Deserialize)]
 struct DeviceRow {
     id: String,
@@ -51,6 +147,7 @@ struct DeviceRow {
     manufacturer: Option<String>,
     provisioned_at: i64,
     sensor_count: i64,
+    organization_id: Option<String>,
     version: u64,
 }
 
@@ -61,50 +158,300 @@ struct SensorRow {
     metric_type: i32,
     metric_value: f64,
     device_id: String,
+    deleted: u8,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct CalibrationRow {
+    sensor_id: String,
+    kind: i32,
+    gain: f64,
+    offset: f64,
+    points: Vec<(f64, f64)>,
+    cleared: u8,
+    updated_at: i64,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct DeviceKeyRow {
+    device_id: String,
+    /// Hex encoded key.
+    key: String,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct ActuatorRow {
+    id: String,
+    kind: i32,
+    max_run_secs: u32,
+    device_id: String,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct CommandRow {
+    id: String,
+    device_id: String,
+    actuator_id: String,
+    action: i32,
+    duration_secs: u32,
+    issued_at: i64,
+    expires_at: i64,
+    result: Option<i32>,
+    open_until: Option<i64>,
+    completed_at: Option<i64>,
     version: u64,
 }
 
-fn disect_metric(metric: &SensorMetric) -> (i32, f64) {
-    match metric {
-        SensorMetric::SoilMoisture { value } => (0, value.0 as f64),
-        SensorMetric::SoilTemp { value } => (1, value.into_inner()),
-        SensorMetric::AirTemp { value } => (2, value.into_inner()),
-        SensorMetric::Humidity { value } => (3, value.0 as f64),
-        SensorMetric::Rainfall { value } => (4, value.into_inner()),
+impl CommandRow {
+    fn new(record: &CommandRecord) -> Self {
+        let command = &record.command;
+        let action = ActionColumns::from(&command.action);
+        let result = record.result.as_ref().map(ResultColumns::from);
+
+        Self {
+            id: command.id.0.to_string(),
+            device_id: command.device_id.0.to_string(),
+            actuator_id: command.actuator_id.0.to_string(),
+            action: action.action,
+            duration_secs: action.duration_secs,
+            issued_at: command.issued_at.as_second(),
+            expires_at: command.expires_at.as_second(),
+            result: result.map(|r| r.result),
+            open_until: result.and_then(|r| r.open_until),
+            completed_at: record.completed_at.map(|at| at.as_second()),
+            version: jiff::Timestamp::now().as_millisecond() as u64,
+        }
     }
 }
 
-fn map_sensor_row(row: SensorRow) -> Result<Sensor, ClickHouseError> {
+/// Firmware image metadata, without the image bytes.
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct FirmwareRow {
+    id: String,
+    model: String,
+    version: u32,
+    size: u32,
+    /// Hex encoded digest.
+    sha256: String,
+    /// Hex encoded signature.
+    signature: String,
+    created_at: i64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct FirmwareDataRow {
+    id: String,
+    model: String,
+    version: u32,
+    size: u32,
+    sha256: String,
+    signature: String,
+    /// Hex encoded image.
+    data: String,
+    created_at: i64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct DeviceFirmwareRow {
+    device_id: String,
+    model: String,
+    firmware_version: u32,
+    update_state: i32,
+    target_version: u32,
+    progress: u8,
+    failure: i32,
+    reported_at: i64,
+    version: u64,
+}
+
+impl DeviceFirmwareRow {
+    fn new(report: &DeviceFirmware) -> Self {
+        let update = FirmwareUpdateColumns::from(&report.firmware.update);
+
+        Self {
+            device_id: report.device_id.0.to_string(),
+            model: report.firmware.model.to_string(),
+            firmware_version: report.firmware.version,
+            update_state: update.state,
+            target_version: update.target_version,
+            progress: update.progress,
+            failure: update.failure,
+            reported_at: report.reported_at.as_second(),
+            version: report.reported_at.as_millisecond() as u64,
+        }
+    }
+}
+
+fn map_device_firmware_row(row: DeviceFirmwareRow) -> Result<DeviceFirmware, ClickHouseError> {
+    let id = Ulid::from_str(&row.device_id)
+        .map_err(|_| ClickHouseError::InvalidUlid(row.device_id.clone()))?;
+
+    let update = FirmwareUpdateColumns {
+        state: row.update_state,
+        target_version: row.target_version,
+        progress: row.progress,
+        failure: row.failure,
+    }
+    .decode()
+    .map_err(ClickHouseError::InvalidFirmwareUpdate)?;
+
+    Ok(DeviceFirmware {
+        device_id: DeviceId(id),
+        firmware: FirmwareStatus {
+            model: row.model.into_boxed_str(),
+            version: row.firmware_version,
+            update,
+        },
+        reported_at: jiff::Timestamp::from_second(row.reported_at)
+            .map_err(|_| ClickHouseError::InvalidTimestamp(row.reported_at))?,
+    })
+}
+
+pub(super) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(super) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn map_key_row(row: DeviceKeyRow) -> Result<DeviceCredential, ClickHouseError> {
+    let id = Ulid::from_str(&row.device_id)
+        .map_err(|_| ClickHouseError::InvalidUlid(row.device_id.clone()))?;
+
+    let key = decode_hex(&row.key)
+        .and_then(|key| <[u8; 32]>::try_from(key).ok())
+        .ok_or(ClickHouseError::InvalidDeviceKey)?;
+
+    Ok(DeviceCredential {
+        device_id: DeviceId(id),
+        key: DeviceKey(key),
+    })
+}
+
+fn map_firmware_row(row: FirmwareRow) -> Result<FirmwareImage, ClickHouseError> {
     let id = Ulid::from_str(&row.id).map_err(|_| ClickHouseError::InvalidUlid(row.id.clone()))?;
 
-    let kind = match row.kind {
-        0 => SensorKind::SoilMoisture,
-        1 => SensorKind::SoilTemp,
-        2 => SensorKind::AirTemp,
-        3 => SensorKind::Humidity,
-        4 => SensorKind::Rainfall,
-        other => return Err(ClickHouseError::InvalidSensorKind(other)),
+    let sha256 = decode_hex(&row.sha256)
+        .and_then(|digest| <[u8; 32]>::try_from(digest).ok())
+        .ok_or(ClickHouseError::InvalidFirmware)?;
+    let signature = decode_hex(&row.signature).ok_or(ClickHouseError::InvalidFirmware)?;
+
+    let created_at = jiff::Timestamp::from_second(row.created_at)
+        .map_err(|_| ClickHouseError::InvalidTimestamp(row.created_at))?;
+
+    Ok(FirmwareImage {
+        id: FirmwareId(id),
+        model: row.model.into_boxed_str(),
+        version: row.version,
+        size: row.size,
+        sha256,
+        signature: signature.into_boxed_slice(),
+        created_at,
+    })
+}
+
+fn map_actuator_row(row: ActuatorRow) -> Result<Actuator, ClickHouseError> {
+    let id = Ulid::from_str(&row.id).map_err(|_| ClickHouseError::InvalidUlid(row.id.clone()))?;
+
+    Ok(Actuator {
+        id: ActuatorId(id),
+        kind: decode_actuator_kind(row.kind).map_err(ClickHouseError::InvalidActuatorKind)?,
+        max_run_secs: row.max_run_secs,
+    })
+}
+
+fn map_command_row(row: CommandRow) -> Result<CommandRecord, ClickHouseError> {
+    let ulid = |id: &str| Ulid::from_str(id).map_err(|_| ClickHouseError::InvalidUlid(id.into()));
+    let timestamp = |secs: i64| {
+        jiff::Timestamp::from_second(secs).map_err(|_| ClickHouseError::InvalidTimestamp(secs))
     };
 
-    let metric = match row.metric_type {
-        0 => SensorMetric::SoilMoisture {
-            value: Percentage(row.metric_value as u8),
-        },
-        1 => SensorMetric::SoilTemp {
-            value: NotNan::new(row.metric_value).expect("database should not contain NaN"),
-        },
-        2 => SensorMetric::AirTemp {
-            value: NotNan::new(row.metric_value).expect("database should not contain NaN"),
+    let action = ActionColumns {
+        action: row.action,
+        duration_secs: row.duration_secs,
+    }
+    .decode()
+    .map_err(ClickHouseError::InvalidCommandAction)?;
+
+    let result = row
+        .result
+        .map(|result| {
+            ResultColumns {
+                result,
+                open_until: row.open_until,
+            }
+            .decode()
+            .map_err(ClickHouseError::InvalidCommandResult)
+        })
+        .transpose()?;
+
+    Ok(CommandRecord {
+        command: ActuatorCommand {
+            id: CommandId(ulid(&row.id)?),
+            device_id: DeviceId(ulid(&row.device_id)?),
+            actuator_id: ActuatorId(ulid(&row.actuator_id)?),
+            action,
+            issued_at: timestamp(row.issued_at)?,
+            expires_at: timestamp(row.expires_at)?,
         },
-        3 => SensorMetric::Humidity {
-            value: Percentage(row.metric_value as u8),
+        result,
+        completed_at: row.completed_at.map(timestamp).transpose()?,
+    })
+}
+
+fn map_calibration_row(row: CalibrationRow) -> Result<CalibrationProfile, ClickHouseError> {
+    let id = Ulid::from_str(&row.sensor_id)
+        .map_err(|_| ClickHouseError::InvalidUlid(row.sensor_id.clone()))?;
+
+    let calibration = match row.kind {
+        0 => Calibration::Linear {
+            gain: NotNan::new(row.gain).expect("database should not contain NaN"),
+            offset: NotNan::new(row.offset).expect("database should not contain NaN"),
         },
-        4 => SensorMetric::Rainfall {
-            value: NotNan::new(row.metric_value).expect("database should not contain NaN"),
+        1 => Calibration::Lookup {
+            points: row
+                .points
+                .into_iter()
+                .map(|(raw, calibrated)| {
+                    (
+                        NotNan::new(raw).expect("database should not contain NaN"),
+                        NotNan::new(calibrated).expect("database should not contain NaN"),
+                    )
+                })
+                .collect(),
         },
-        other => return Err(ClickHouseError::InvalidMetricType(other)),
+        other => return Err(ClickHouseError::InvalidCalibrationKind(other)),
     };
 
+    let updated_at = jiff::Timestamp::from_second(row.updated_at)
+        .map_err(|_| ClickHouseError::InvalidTimestamp(row.updated_at))?;
+
+    Ok(CalibrationProfile {
+        sensor_id: SensorId(id),
+        calibration,
+        updated_at,
+    })
+}
+
+fn map_sensor_row(row: SensorRow) -> Result<Sensor, ClickHouseError> {
+    let id = Ulid::from_str(&row.id).map_err(|_| ClickHouseError::InvalidUlid(row.id.clone()))?;
+
+    let kind = decode_sensor_kind(row.kind).map_err(ClickHouseError::InvalidSensorKind)?;
+    let metric = decode_metric(row.metric_type, row.metric_value)
+        .map_err(ClickHouseError::InvalidMetricType)?;
+
     Ok(Sensor {
         id: SensorId(id),
         kind,
@@ -121,10 +468,69 @@ impl ClickHouseDeviceRegistry {
     pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
         let client = super::create_client(url, database);
         client.query(CREATE_DEVICE_TABLE).execute().await?;
+        client.query(ADD_DEVICE_ORGANIZATION).execute().await?;
+        for column in super::location_columns("devices") {
+            client.query(&column).execute().await?;
+        }
         client.query(CREATE_SENSOR_TABLE).execute().await?;
+        client.query(ADD_SENSOR_DELETED).execute().await?;
+        client.query(CREATE_CALIBRATION_TABLE).execute().await?;
+        client.query(CREATE_KEY_TABLE).execute().await?;
+        client.query(CREATE_FIRMWARE_TABLE).execute().await?;
+        client.query(CREATE_DEVICE_FIRMWARE_TABLE).execute().await?;
+        client.query(CREATE_ACTUATOR_TABLE).execute().await?;
+        client.query(CREATE_COMMAND_TABLE).execute().await?;
         Ok(Self { client })
     }
 
+    async fn store_actuators(
+        &self,
+        device_id: DeviceId,
+        actuators: &[Actuator],
+    ) -> Result<(), ClickHouseError> {
+        if actuators.is_empty() {
+            return Ok(());
+        }
+
+        let version = jiff::Timestamp::now().as_millisecond() as u64;
+        let mut insert = self.client.insert("actuators")?;
+        for actuator in actuators {
+            let row = ActuatorRow {
+                id: actuator.id.0.to_string(),
+                kind: actuator_kind_code(actuator.kind),
+                max_run_secs: actuator.max_run_secs,
+                device_id: device_id.0.to_string(),
+                version,
+            };
+            insert.write(&row).await?;
+        }
+        insert.end().await?;
+
+        Ok(())
+    }
+
+    async fn fetch_actuators(
+        &self,
+        device_id: DeviceId,
+    ) -> Result<Box<[Actuator]>, ClickHouseError> {
+        let rows: Vec<ActuatorRow> = self
+            .client
+            .query("SELECT ?fields FROM actuators FINAL WHERE device_id = ?")
+            .bind(device_id.0.to_string())
+            .fetch_all()
+            .await?;
+
+        let actuators: Result<Vec<_>, _> = rows.into_iter().map(map_actuator_row).collect();
+        Ok(actuators?.into_boxed_slice())
+    }
+
+    async fn store_command(&self, record: &CommandRecord) -> Result<(), ClickHouseError> {
+        let mut insert = self.client.insert("actuator_commands")?;
+        insert.write(&CommandRow::new(record)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
     async fn store_sensors(
         &self,
         device_id: DeviceId,
@@ -143,6 +549,7 @@ impl ClickHouseDeviceRegistry {
                 metric_type,
                 metric_value,
                 device_id: device_id.0.to_string(),
+                deleted: 0,
                 version,
             };
             insert.write(&row).await?;
@@ -157,7 +564,7 @@ impl ClickHouseDeviceRegistry {
     async fn fetch_sensors(&self, device_id: DeviceId) -> Result<Box<[Sensor]>, ClickHouseError> {
         let rows: Vec<SensorRow> = self
             .client
-            .query("SELECT ?fields FROM sensors FINAL WHERE device_id = ?")
+            .query("SELECT ?fields FROM sensors FINAL WHERE device_id = ? AND deleted = 0")
             .bind(device_id.0.to_string())
             .fetch_all()
             .await?;
@@ -172,18 +579,29 @@ impl ClickHouseDeviceRegistry {
 
         let kind = match row.kind {
             0 => DeviceKind::Sensor,
+            1 => DeviceKind::Actuator,
             other => return Err(ClickHouseError::InvalidDeviceKind(other)),
         };
 
         let state = match row.state {
             0 => DeviceState::Active,
             1 => DeviceState::Suspended,
+            2 => DeviceState::Retired,
             other => return Err(ClickHouseError::InvalidDeviceState(other)),
         };
 
         let provisioned_at = jiff::Timestamp::from_second(row.provisioned_at)
             .map_err(|_| ClickHouseError::InvalidTimestamp(row.provisioned_at))?;
 
+        let organization = row
+            .organization_id
+            .map(|id| {
+                Ulid::from_str(&id)
+                    .map(OrganizationId)
+                    .map_err(|_| ClickHouseError::InvalidUlid(id))
+            })
+            .transpose()?;
+
         Ok(Device {
             id: DeviceId(id),
             kind,
@@ -192,6 +610,8 @@ impl ClickHouseDeviceRegistry {
             manufacturer: row.manufacturer.map(|s| s.into_boxed_str()),
             provisioned_at,
             sensors: vec![].into_boxed_slice(),
+            actuators: vec![].into_boxed_slice(),
+            organization,
         })
     }
 }
@@ -210,6 +630,7 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
             manufacturer: device.manufacturer.as_ref().map(|s| s.to_string()),
             provisioned_at: device.provisioned_at.as_second(),
             sensor_count: device.sensors.len() as i64,
+            organization_id: device.organization.map(|o| o.0.to_string()),
             version,
         };
 
@@ -217,6 +638,7 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
         insert.write(&row).await?;
         insert.end().await?;
 
+        self.store_actuators(device.id, &device.actuators).await?;
         self.store_sensors(device.id, device.sensors.into_vec().into_iter())
             .await?;
 
@@ -235,6 +657,7 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
             Some(r) => {
                 let mut device = self.map_device_row(r)?;
                 device.sensors = self.fetch_sensors(id).await?;
+                device.actuators = self.fetch_actuators(id).await?;
                 Ok(Some(device))
             }
             None => Ok(None),
@@ -256,6 +679,24 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
         self.register(new).await
     }
 
+    async fn reactivate(&self, id: DeviceId) -> Result<(), Self::Error> {
+        let device = self.get(id).await?.ok_or(ClickHouseError::NotFound)?;
+        let new = Device {
+            state: DeviceState::Active,
+            ..device
+        };
+        self.register(new).await
+    }
+
+    async fn retire(&self, id: DeviceId) -> Result<(), Self::Error> {
+        let device = self.get(id).await?.ok_or(ClickHouseError::NotFound)?;
+        let new = Device {
+            state: DeviceState::Retired,
+            ..device
+        };
+        self.register(new).await
+    }
+
     async fn add_sensor(&self, id: DeviceId, sensor: Sensor) -> Result<(), Self::Error> {
         self.store_sensors(id, std::iter::once(sensor)).await
     }
@@ -268,6 +709,38 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
         self.store_sensors(id, sensors).await
     }
 
+    async fn remove_sensor(&self, id: DeviceId, sensor_id: SensorId) -> Result<bool, Self::Error> {
+        let device = self.get(id).await?.ok_or(ClickHouseError::NotFound)?;
+        let Some(sensor) = device.sensors.iter().find(|s| s.id == sensor_id) else {
+            return Ok(false);
+        };
+
+        let (metric_type, metric_value) = disect_metric(&sensor.metric);
+        let row = SensorRow {
+            id: sensor.id.0.to_string(),
+            kind: sensor.kind.clone() as i32,
+            metric_type,
+            metric_value,
+            device_id: id.0.to_string(),
+            deleted: 1,
+            version: jiff::Timestamp::now().as_millisecond() as u64,
+        };
+        let mut insert = self.client.insert("sensors")?;
+        insert.write(&row).await?;
+        insert.end().await?;
+
+        // Store the device again so its sensor count drops.
+        let sensors = device
+            .sensors
+            .iter()
+            .filter(|s| s.id != sensor_id)
+            .cloned()
+            .collect();
+        self.register(Device { sensors, ..device }).await?;
+
+        Ok(true)
+    }
+
     async fn batch_register(&self, devices: Vec<Device>) -> Result<(), Self::Error> {
         if devices.is_empty() {
             return Ok(());
@@ -285,6 +758,7 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
                 manufacturer: device.manufacturer.as_ref().map(|s| s.to_string()),
                 provisioned_at: device.provisioned_at.as_second(),
                 sensor_count: device.sensors.len() as i64,
+                organization_id: device.organization.map(|o| o.0.to_string()),
                 version,
             };
             insert.write(&row).await?;
@@ -292,6 +766,7 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
         insert.end().await?;
 
         for device in devices {
+            self.store_actuators(device.id, &device.actuators).await?;
             self.store_sensors(device.id, device.sensors.into_vec().into_iter())
                 .await?;
         }
@@ -332,11 +807,319 @@ impl DeviceRegistry for ClickHouseDeviceRegistry {
         for row in rows {
             let mut device = self.map_device_row(row)?;
             device.sensors = self.fetch_sensors(device.id).await?;
+            device.actuators = self.fetch_actuators(device.id).await?;
             devices.push(device);
         }
 
         Ok(devices)
     }
+
+    async fn set_calibration(
+        &self,
+        sensor_id: SensorId,
+        calibration: Option<Calibration>,
+    ) -> Result<(), Self::Error> {
+        let id = sensor_id.0.to_string();
+
+        let known: u64 = self
+            .client
+            .query("SELECT count() FROM sensors FINAL WHERE id = ? AND deleted = 0")
+            .bind(&id)
+            .fetch_one()
+            .await?;
+        if known == 0 {
+            return Err(ClickHouseError::NotFound);
+        }
+
+        let now = jiff::Timestamp::now();
+        let mut row = CalibrationRow {
+            sensor_id: id,
+            kind: 0,
+            gain: 1.0,
+            offset: 0.0,
+            points: Vec::new(),
+            cleared: 0,
+            updated_at: now.as_second(),
+            version: now.as_millisecond() as u64,
+        };
+
+        match calibration {
+            Some(Calibration::Linear { gain, offset }) => {
+                row.gain = gain.into_inner();
+                row.offset = offset.into_inner();
+            }
+            Some(Calibration::Lookup { points }) => {
+                row.kind = 1;
+                row.points = points
+                    .iter()
+                    .map(|(raw, calibrated)| (raw.into_inner(), calibrated.into_inner()))
+                    .collect();
+            }
+            // ReplacingMergeTree keeps the latest version, so clearing is a
+            // newer row that is filtered out on read
+            None => row.cleared = 1,
+        }
+
+        let mut insert = self.client.insert("sensor_calibrations")?;
+        insert.write(&row).await?;
+        insert.end().await?;
+
+        Ok(())
+    }
+
+    async fn get_calibration(
+        &self,
+        sensor_id: SensorId,
+    ) -> Result<Option<CalibrationProfile>, Self::Error> {
+        let row: Option<CalibrationRow> = self
+            .client
+            .query(
+                "SELECT ?fields FROM sensor_calibrations FINAL WHERE sensor_id = ? AND cleared = 0",
+            )
+            .bind(sensor_id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(map_calibration_row).transpose()
+    }
+
+    async fn list_calibrations(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<CalibrationProfile>, Self::Error> {
+        let sql = format!(
+            "SELECT ?fields FROM sensor_calibrations FINAL WHERE cleared = 0 AND sensor_id IN \
+             (SELECT id FROM sensors FINAL WHERE deleted = 0 AND device_id IN ({}))",
+            owned_devices(organization)
+        );
+        let mut query = self.client.query(&sql);
+        if let Some(organization) = organization {
+            query = query.bind(organization.0.to_string());
+        }
+        let rows: Vec<CalibrationRow> = query.fetch_all().await?;
+
+        rows.into_iter().map(map_calibration_row).collect()
+    }
+
+    async fn set_key(&self, id: DeviceId, key: DeviceKey) -> Result<(), Self::Error> {
+        if self.get(id).await?.is_none() {
+            return Err(ClickHouseError::NotFound);
+        }
+
+        let row = DeviceKeyRow {
+            device_id: id.0.to_string(),
+            key: encode_hex(&key.0),
+            version: jiff::Timestamp::now().as_millisecond() as u64,
+        };
+
+        let mut insert = self.client.insert("device_keys")?;
+        insert.write(&row).await?;
+        insert.end().await?;
+
+        Ok(())
+    }
+
+    async fn list_keys(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<DeviceCredential>, Self::Error> {
+        let sql = format!(
+            "SELECT ?fields FROM device_keys FINAL WHERE device_id IN ({})",
+            owned_devices(organization)
+        );
+        let mut query = self.client.query(&sql);
+        if let Some(organization) = organization {
+            query = query.bind(organization.0.to_string());
+        }
+        let rows: Vec<DeviceKeyRow> = query.fetch_all().await?;
+
+        rows.into_iter().map(map_key_row).collect()
+    }
+
+    async fn add_firmware(&self, image: FirmwareImage, data: Vec<u8>) -> Result<(), Self::Error> {
+        let existing: u64 = self
+            .client
+            .query("SELECT count() FROM firmware_images FINAL WHERE id = ? OR (model = ? AND version = ?)")
+            .bind(image.id.0.to_string())
+            .bind(image.model.as_ref())
+            .bind(image.version)
+            .fetch_one()
+            .await?;
+        if existing > 0 {
+            return Err(ClickHouseError::AlreadyExists);
+        }
+
+        let row = FirmwareDataRow {
+            id: image.id.0.to_string(),
+            model: image.model.into_string(),
+            version: image.version,
+            size: image.size,
+            sha256: encode_hex(&image.sha256),
+            signature: encode_hex(&image.signature),
+            data: encode_hex(&data),
+            created_at: image.created_at.as_second(),
+        };
+
+        let mut insert = self.client.insert("firmware_images")?;
+        insert.write(&row).await?;
+        insert.end().await?;
+
+        Ok(())
+    }
+
+    async fn list_firmware(&self) -> Result<Vec<FirmwareImage>, Self::Error> {
+        let rows: Vec<FirmwareRow> = self
+            .client
+            .query("SELECT ?fields FROM firmware_images FINAL")
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(map_firmware_row).collect()
+    }
+
+    async fn firmware_chunk(
+        &self,
+        id: FirmwareId,
+        offset: u32,
+        len: u32,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        // two hex digits per byte, substring() starts at 1
+        let chunk: Option<String> = self
+            .client
+            .query("SELECT substring(data, ?, ?) FROM firmware_images FINAL WHERE id = ?")
+            .bind(offset as u64 * 2 + 1)
+            .bind(len as u64 * 2)
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        chunk
+            .map(|hex| decode_hex(&hex).ok_or(ClickHouseError::InvalidFirmware))
+            .transpose()
+    }
+
+    async fn record_firmware(&self, report: DeviceFirmware) -> Result<(), Self::Error> {
+        let mut insert = self.client.insert("device_firmware")?;
+        insert.write(&DeviceFirmwareRow::new(&report)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn get_firmware(&self, id: DeviceId) -> Result<Option<DeviceFirmware>, Self::Error> {
+        let row: Option<DeviceFirmwareRow> = self
+            .client
+            .query("SELECT ?fields FROM device_firmware FINAL WHERE device_id = ?")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(map_device_firmware_row).transpose()
+    }
+
+    async fn add_command(&self, command: ActuatorCommand) -> Result<(), Self::Error> {
+        if self.get_command(command.id).await?.is_some() {
+            return Err(ClickHouseError::AlreadyExists);
+        }
+
+        self.store_command(&CommandRecord {
+            command,
+            result: None,
+            completed_at: None,
+        })
+        .await
+    }
+
+    async fn get_command(&self, id: CommandId) -> Result<Option<CommandRecord>, Self::Error> {
+        let row: Option<CommandRow> = self
+            .client
+            .query("SELECT ?fields FROM actuator_commands FINAL WHERE id = ?")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(map_command_row).transpose()
+    }
+
+    async fn list_commands(&self, device_id: DeviceId) -> Result<Vec<CommandRecord>, Self::Error> {
+        let rows: Vec<CommandRow> = self
+            .client
+            .query(
+                "SELECT ?fields FROM actuator_commands FINAL WHERE device_id = ? ORDER BY issued_at",
+            )
+            .bind(device_id.0.to_string())
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(map_command_row).collect()
+    }
+
+    async fn pending_commands(
+        &self,
+        now: jiff::Timestamp,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<ActuatorCommand>, Self::Error> {
+        let sql = format!(
+            "SELECT ?fields FROM actuator_commands FINAL WHERE result IS NULL AND expires_at > ? AND device_id IN ({}) ORDER BY issued_at",
+            owned_devices(organization)
+        );
+        let mut query = self.client.query(&sql).bind(now.as_second());
+        if let Some(organization) = organization {
+            query = query.bind(organization.0.to_string());
+        }
+        let rows: Vec<CommandRow> = query.fetch_all().await?;
+
+        rows.into_iter()
+            .map(|row| map_command_row(row).map(|record| record.command))
+            .collect()
+    }
+
+    async fn complete_command(
+        &self,
+        id: CommandId,
+        result: CommandResult,
+        at: jiff::Timestamp,
+    ) -> Result<bool, Self::Error> {
+        let Some(mut record) = self.get_command(id).await? else {
+            return Ok(false);
+        };
+        if !replaces_result(record.result.as_ref(), &result) {
+            return Ok(false);
+        }
+
+        record.result = Some(result);
+        record.completed_at = Some(at);
+        self.store_command(&record).await?;
+        Ok(true)
+    }
+
+    async fn expire_commands(&self, now: jiff::Timestamp) -> Result<usize, Self::Error> {
+        let rows: Vec<CommandRow> = self
+            .client
+            .query(
+                "SELECT ?fields FROM actuator_commands FINAL WHERE result IS NULL AND expires_at <= ?",
+            )
+            .bind(now.as_second())
+            .fetch_all()
+            .await?;
+
+        for row in &rows {
+            let mut record = map_command_row(row.clone())?;
+            record.result = Some(CommandResult::TimedOut);
+            record.completed_at = Some(now);
+            self.store_command(&record).await?;
+        }
+
+        Ok(rows.len())
+    }
+}
+
+/// Subquery of the ids of devices owned by `organization`, taking the
+/// owner as a binding, or of devices without an owner for `None`.
+fn owned_devices(organization: Option<OrganizationId>) -> &'static str {
+    match organization {
+        Some(_) => "SELECT id FROM devices FINAL WHERE organization_id = ?",
+        None => "SELECT id FROM devices FINAL WHERE organization_id IS NULL",
+    }
 }
 
 fn build_count_query(filter: Option<DeviceFilter>) -> (String, Vec<String>) {
@@ -446,6 +1229,36 @@ fn build_where_clause(filter: &DeviceFilter) -> (String, Vec<String>) {
         conditions.push(format!("manufacturer LIKE '%{}%'", pattern));
     }
 
+    if let Some(sensor_ids) = &filter.sensor_ids
+        && !sensor_ids.is_empty()
+    {
+        let placeholders: Vec<_> = sensor_ids.iter().map(|_| "?").collect();
+        conditions.push(format!(
+            "id IN (SELECT device_id FROM sensors FINAL WHERE deleted = 0 AND id IN ({}))",
+            placeholders.join(", ")
+        ));
+        bindings.extend(sensor_ids.iter().map(|id| id.0.to_string()));
+    }
+
+    if let Some(organization) = &filter.organization {
+        conditions.push("organization_id = ?".to_string());
+        bindings.push(organization.0.to_string());
+    }
+
+    conditions.extend(super::spatial_conditions(
+        filter.ancestor,
+        filter.ring,
+        filter.bbox,
+    ));
+
+    if let Some(field) = &filter.field {
+        conditions.push(
+            "id IN (SELECT device_id FROM plot_devices FINAL WHERE deleted = 0 AND plot_id IN (SELECT id FROM plots FINAL WHERE deleted = 0 AND field_id = ?))"
+                .to_string(),
+        );
+        bindings.push(field.0.to_string());
+    }
+
     let where_clause = if conditions.is_empty() {
         String::new()
     } else {
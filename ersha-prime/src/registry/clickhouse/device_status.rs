@@ -1,10 +1,11 @@
+use std::collections::HashSet;
 use std::str::FromStr;
 
 use async_trait::async_trait;
 use clickhouse::{Client, Row};
 use ersha_core::{
-    DeviceError, DeviceErrorCode, DeviceId, DeviceStatus, DispatcherId, Percentage, SensorId,
-    SensorState, SensorStatus, StatusId,
+    DeviceError, DeviceErrorCode, DeviceId, DeviceStatus, DispatcherId, FirmwareStatus, Percentage,
+    SensorId, SensorState, SensorStatus, StatusId,
 };
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
@@ -13,6 +14,7 @@ use super::ClickHouseError;
 use crate::registry::{
     DeviceStatusRegistry,
     filter::{DeviceStatusFilter, DeviceStatusSortBy, Pagination, QueryOptions, SortOrder},
+    firmware::FirmwareUpdateColumns,
 };
 
 const CREATE_STATUS_TABLE: &str = r#"
@@ -48,6 +50,19 @@ CREATE TABLE IF NOT EXISTS device_status_sensor_statuses (
 ORDER BY status_id
 "#;
 
+const CREATE_FIRMWARE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS device_status_firmware (
+    status_id String,
+    model String,
+    version UInt32,
+    update_state Int32,
+    target_version UInt32,
+    progress UInt8,
+    failure Int32
+) ENGINE = MergeTree()
+ORDER BY status_id
+"#;
+
 #[derive(Debug, Clone, Row, Serialize, Deserialize)]
 struct StatusRow {
     id: String,
@@ -74,6 +89,17 @@ struct SensorStatusRow {
     last_reading: Option<i64>,
 }
 
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct FirmwareRow {
+    status_id: String,
+    model: String,
+    version: u32,
+    update_state: i32,
+    target_version: u32,
+    progress: u8,
+    failure: i32,
+}
+
 #[derive(Clone)]
 pub struct ClickHouseDeviceStatusRegistry {
     client: Client,
@@ -85,9 +111,32 @@ impl ClickHouseDeviceStatusRegistry {
         client.query(CREATE_STATUS_TABLE).execute().await?;
         client.query(CREATE_ERRORS_TABLE).execute().await?;
         client.query(CREATE_SENSOR_STATUSES_TABLE).execute().await?;
+        client.query(CREATE_FIRMWARE_TABLE).execute().await?;
         Ok(Self { client })
     }
 
+    async fn store_firmware(
+        &self,
+        status_id: &StatusId,
+        firmware: &FirmwareStatus,
+    ) -> Result<(), ClickHouseError> {
+        let update = FirmwareUpdateColumns::from(&firmware.update);
+        let row = FirmwareRow {
+            status_id: status_id.0.to_string(),
+            model: firmware.model.to_string(),
+            version: firmware.version,
+            update_state: update.state,
+            target_version: update.target_version,
+            progress: update.progress,
+            failure: update.failure,
+        };
+
+        let mut insert = self.client.insert("device_status_firmware")?;
+        insert.write(&row).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
     async fn store_errors(
         &self,
         status_id: &StatusId,
@@ -217,6 +266,37 @@ impl ClickHouseDeviceStatusRegistry {
         Ok(sensor_statuses.into_boxed_slice())
     }
 
+    async fn fetch_firmware(
+        &self,
+        status_id: &StatusId,
+    ) -> Result<Option<FirmwareStatus>, ClickHouseError> {
+        let row: Option<FirmwareRow> = self
+            .client
+            .query("SELECT ?fields FROM device_status_firmware WHERE status_id = ? LIMIT 1")
+            .bind(status_id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let update = FirmwareUpdateColumns {
+            state: row.update_state,
+            target_version: row.target_version,
+            progress: row.progress,
+            failure: row.failure,
+        }
+        .decode()
+        .map_err(ClickHouseError::InvalidFirmwareUpdate)?;
+
+        Ok(Some(FirmwareStatus {
+            model: row.model.into_boxed_str(),
+            version: row.version,
+            update,
+        }))
+    }
+
     async fn map_row_to_status(&self, row: StatusRow) -> Result<DeviceStatus, ClickHouseError> {
         let id =
             Ulid::from_str(&row.id).map_err(|_| ClickHouseError::InvalidUlid(row.id.clone()))?;
@@ -231,6 +311,7 @@ impl ClickHouseDeviceStatusRegistry {
         let status_id = StatusId(id);
         let errors = self.fetch_errors(&status_id).await?;
         let sensor_statuses = self.fetch_sensor_statuses(&status_id).await?;
+        let firmware = self.fetch_firmware(&status_id).await?;
 
         Ok(DeviceStatus {
             id: status_id,
@@ -242,6 +323,7 @@ impl ClickHouseDeviceStatusRegistry {
             errors,
             timestamp,
             sensor_statuses,
+            firmware,
         })
     }
 }
@@ -268,6 +350,9 @@ impl DeviceStatusRegistry for ClickHouseDeviceStatusRegistry {
         self.store_errors(&status.id, &status.errors).await?;
         self.store_sensor_statuses(&status.id, &status.sensor_statuses)
             .await?;
+        if let Some(firmware) = &status.firmware {
+            self.store_firmware(&status.id, firmware).await?;
+        }
 
         Ok(())
     }
@@ -301,8 +386,26 @@ impl DeviceStatusRegistry for ClickHouseDeviceStatusRegistry {
     }
 
     async fn batch_store(&self, statuses: Vec<DeviceStatus>) -> Result<(), Self::Error> {
+        if statuses.is_empty() {
+            return Ok(());
+        }
+
+        // The tables are plain MergeTrees, so skip ids that are already
+        // stored rather than relying on merges to collapse them.
+        let ids: Vec<String> = statuses.iter().map(|s| s.id.0.to_string()).collect();
+        let mut seen: HashSet<String> = self
+            .client
+            .query("SELECT id FROM device_statuses WHERE id IN ?")
+            .bind(&ids)
+            .fetch_all::<String>()
+            .await?
+            .into_iter()
+            .collect();
+
         for status in statuses {
-            self.store(status).await?;
+            if seen.insert(status.id.0.to_string()) {
+                self.store(status).await?;
+            }
         }
         Ok(())
     }
@@ -480,6 +583,13 @@ fn build_clauses(filter: &DeviceStatusFilter) -> (String, String, Vec<String>) {
         ));
     }
 
+    if let Some(organization) = &filter.organization {
+        conditions.push(
+            "device_id IN (SELECT id FROM devices FINAL WHERE organization_id = ?)".to_string(),
+        );
+        bindings.push(organization.0.to_string());
+    }
+
     let where_clause = if conditions.is_empty() {
         String::new()
     } else {
@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use async_trait::async_trait;
 use clickhouse::{Client, Row};
-use ersha_core::{Dispatcher, DispatcherId, DispatcherState, H3Cell};
+use ersha_core::{Dispatcher, DispatcherId, DispatcherState, H3Cell, OrganizationId};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
@@ -18,17 +18,22 @@ CREATE TABLE IF NOT EXISTS dispatchers (
     state Int32,
     location Int64,
     provisioned_at Int64,
+    organization_id Nullable(String),
     version UInt64
 ) ENGINE = ReplacingMergeTree(version)
 ORDER BY id
 "#;
 
+const ADD_ORGANIZATION: &str =
+    "ALTER TABLE dispatchers ADD COLUMN IF NOT EXISTS organization_id Nullable(String)";
+
 #[derive(Debug, Clone, Row, Serialize, Deserialize)]
 struct DispatcherRow {
     id: String,
     state: i32,
     location: i64,
     provisioned_at: i64,
+    organization_id: Option<String>,
     version: u64,
 }
 
@@ -42,17 +47,28 @@ impl TryFrom<DispatcherRow> for Dispatcher {
         let state = match row.state {
             0 => DispatcherState::Active,
             1 => DispatcherState::Suspended,
+            2 => DispatcherState::Retired,
             other => return Err(ClickHouseError::InvalidDispatcherState(other)),
         };
 
         let provisioned_at = jiff::Timestamp::from_second(row.provisioned_at)
             .map_err(|_| ClickHouseError::InvalidTimestamp(row.provisioned_at))?;
 
+        let organization = row
+            .organization_id
+            .map(|id| {
+                Ulid::from_str(&id)
+                    .map(OrganizationId)
+                    .map_err(|_| ClickHouseError::InvalidUlid(id))
+            })
+            .transpose()?;
+
         Ok(Dispatcher {
             id: DispatcherId(id),
             state,
             location: H3Cell(row.location as u64),
             provisioned_at,
+            organization,
         })
     }
 }
@@ -64,6 +80,7 @@ impl From<&Dispatcher> for DispatcherRow {
             state: dispatcher.state.clone() as i32,
             location: dispatcher.location.0 as i64,
             provisioned_at: dispatcher.provisioned_at.as_second(),
+            organization_id: dispatcher.organization.map(|o| o.0.to_string()),
             version: jiff::Timestamp::now().as_millisecond() as u64,
         }
     }
@@ -78,6 +95,7 @@ impl ClickHouseDispatcherRegistry {
     pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
         let client = super::create_client(url, database);
         client.query(CREATE_TABLE).execute().await?;
+        client.query(ADD_ORGANIZATION).execute().await?;
         Ok(Self { client })
     }
 }
@@ -120,6 +138,24 @@ impl DispatcherRegistry for ClickHouseDispatcherRegistry {
         self.register(new).await
     }
 
+    async fn reactivate(&self, id: DispatcherId) -> Result<(), Self::Error> {
+        let dispatcher = self.get(id).await?.ok_or(ClickHouseError::NotFound)?;
+        let new = Dispatcher {
+            state: DispatcherState::Active,
+            ..dispatcher
+        };
+        self.register(new).await
+    }
+
+    async fn retire(&self, id: DispatcherId) -> Result<(), Self::Error> {
+        let dispatcher = self.get(id).await?.ok_or(ClickHouseError::NotFound)?;
+        let new = Dispatcher {
+            state: DispatcherState::Retired,
+            ..dispatcher
+        };
+        self.register(new).await
+    }
+
     async fn batch_register(&self, dispatchers: Vec<Dispatcher>) -> Result<(), Self::Error> {
         if dispatchers.is_empty() {
             return Ok(());
@@ -211,7 +247,7 @@ fn build_list_query(
 
 fn build_where_clause(filter: &DispatcherFilter) -> (String, Vec<String>) {
     let mut conditions = Vec::new();
-    let bindings = Vec::new();
+    let mut bindings = Vec::new();
 
     if let Some(states) = &filter.states
         && !states.is_empty()
@@ -230,6 +266,11 @@ fn build_where_clause(filter: &DispatcherFilter) -> (String, Vec<String>) {
         conditions.push(format!("location IN ({})", values.join(", ")));
     }
 
+    if let Some(organization) = &filter.organization {
+        conditions.push("organization_id = ?".to_string());
+        bindings.push(organization.0.to_string());
+    }
+
     let where_clause = if conditions.is_empty() {
         String::new()
     } else {
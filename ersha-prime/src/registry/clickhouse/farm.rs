@@ -0,0 +1,589 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use clickhouse::{Client, Row};
+use ersha_core::{
+    Boundary, DeviceId, Farm, FarmId, Field, FieldId, GeoPoint, H3Cell, OrganizationId, Plot,
+    PlotId,
+};
+use ordered_float::NotNan;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use super::ClickHouseError;
+use crate::registry::FarmRegistry;
+
+const CREATE_FARM_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS farms (
+    id String,
+    name String,
+    organization_id Nullable(String),
+    ring Array(Tuple(Float64, Float64)),
+    cells Array(UInt64),
+    created_at Int64,
+    deleted UInt8,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY id
+"#;
+
+const CREATE_FIELD_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS fields (
+    id String,
+    farm_id String,
+    name String,
+    crop Nullable(String),
+    planted_on Nullable(String),
+    ring Array(Tuple(Float64, Float64)),
+    cells Array(UInt64),
+    created_at Int64,
+    deleted UInt8,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY id
+"#;
+
+const CREATE_PLOT_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS plots (
+    id String,
+    field_id String,
+    name String,
+    crop Nullable(String),
+    planted_on Nullable(String),
+    ring Array(Tuple(Float64, Float64)),
+    cells Array(UInt64),
+    created_at Int64,
+    deleted UInt8,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY id
+"#;
+
+/// A device is assigned to at most one plot, so assignments are keyed by
+/// device.
+const CREATE_PLOT_DEVICE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS plot_devices (
+    device_id String,
+    plot_id String,
+    deleted UInt8,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY device_id
+"#;
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct FarmRow {
+    id: String,
+    name: String,
+    organization_id: Option<String>,
+    ring: Vec<(f64, f64)>,
+    cells: Vec<u64>,
+    created_at: i64,
+    deleted: u8,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct FieldRow {
+    id: String,
+    farm_id: String,
+    name: String,
+    crop: Option<String>,
+    planted_on: Option<String>,
+    ring: Vec<(f64, f64)>,
+    cells: Vec<u64>,
+    created_at: i64,
+    deleted: u8,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct PlotRow {
+    id: String,
+    field_id: String,
+    name: String,
+    crop: Option<String>,
+    planted_on: Option<String>,
+    ring: Vec<(f64, f64)>,
+    cells: Vec<u64>,
+    created_at: i64,
+    deleted: u8,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct PlotDeviceRow {
+    device_id: String,
+    plot_id: String,
+    deleted: u8,
+    version: u64,
+}
+
+impl TryFrom<FarmRow> for Farm {
+    type Error = ClickHouseError;
+
+    fn try_from(row: FarmRow) -> Result<Self, Self::Error> {
+        Ok(Farm {
+            id: FarmId(parse_ulid(&row.id)?),
+            name: row.name.into_boxed_str(),
+            boundary: map_boundary(row.ring, row.cells)?,
+            organization: row
+                .organization_id
+                .map(|id| parse_ulid(&id).map(OrganizationId))
+                .transpose()?,
+            created_at: parse_timestamp(row.created_at)?,
+        })
+    }
+}
+
+impl From<&Farm> for FarmRow {
+    fn from(farm: &Farm) -> Self {
+        FarmRow {
+            id: farm.id.0.to_string(),
+            name: farm.name.to_string(),
+            organization_id: farm.organization.map(|o| o.0.to_string()),
+            ring: ring_row(&farm.boundary),
+            cells: farm.boundary.cells.iter().map(|c| c.0).collect(),
+            created_at: farm.created_at.as_second(),
+            deleted: 0,
+            version: version(),
+        }
+    }
+}
+
+impl TryFrom<FieldRow> for Field {
+    type Error = ClickHouseError;
+
+    fn try_from(row: FieldRow) -> Result<Self, Self::Error> {
+        Ok(Field {
+            id: FieldId(parse_ulid(&row.id)?),
+            farm_id: FarmId(parse_ulid(&row.farm_id)?),
+            name: row.name.into_boxed_str(),
+            boundary: map_boundary(row.ring, row.cells)?,
+            crop: row.crop.map(String::into_boxed_str),
+            planted_on: parse_date(row.planted_on)?,
+            created_at: parse_timestamp(row.created_at)?,
+        })
+    }
+}
+
+impl From<&Field> for FieldRow {
+    fn from(field: &Field) -> Self {
+        FieldRow {
+            id: field.id.0.to_string(),
+            farm_id: field.farm_id.0.to_string(),
+            name: field.name.to_string(),
+            crop: field.crop.as_deref().map(str::to_string),
+            planted_on: field.planted_on.map(|d| d.to_string()),
+            ring: ring_row(&field.boundary),
+            cells: field.boundary.cells.iter().map(|c| c.0).collect(),
+            created_at: field.created_at.as_second(),
+            deleted: 0,
+            version: version(),
+        }
+    }
+}
+
+impl TryFrom<PlotRow> for Plot {
+    type Error = ClickHouseError;
+
+    fn try_from(row: PlotRow) -> Result<Self, Self::Error> {
+        Ok(Plot {
+            id: PlotId(parse_ulid(&row.id)?),
+            field_id: FieldId(parse_ulid(&row.field_id)?),
+            name: row.name.into_boxed_str(),
+            boundary: map_boundary(row.ring, row.cells)?,
+            crop: row.crop.map(String::into_boxed_str),
+            planted_on: parse_date(row.planted_on)?,
+            created_at: parse_timestamp(row.created_at)?,
+        })
+    }
+}
+
+impl From<&Plot> for PlotRow {
+    fn from(plot: &Plot) -> Self {
+        PlotRow {
+            id: plot.id.0.to_string(),
+            field_id: plot.field_id.0.to_string(),
+            name: plot.name.to_string(),
+            crop: plot.crop.as_deref().map(str::to_string),
+            planted_on: plot.planted_on.map(|d| d.to_string()),
+            ring: ring_row(&plot.boundary),
+            cells: plot.boundary.cells.iter().map(|c| c.0).collect(),
+            created_at: plot.created_at.as_second(),
+            deleted: 0,
+            version: version(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClickHouseFarmRegistry {
+    client: Client,
+}
+
+impl ClickHouseFarmRegistry {
+    pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
+        let client = super::create_client(url, database);
+        client.query(CREATE_FARM_TABLE).execute().await?;
+        client.query(CREATE_FIELD_TABLE).execute().await?;
+        client.query(CREATE_PLOT_TABLE).execute().await?;
+        client.query(CREATE_PLOT_DEVICE_TABLE).execute().await?;
+        Ok(Self { client })
+    }
+
+    async fn farm_row(&self, id: &str) -> Result<Option<FarmRow>, ClickHouseError> {
+        Ok(self
+            .client
+            .query("SELECT ?fields FROM farms FINAL WHERE id = ? AND deleted = 0")
+            .bind(id)
+            .fetch_optional()
+            .await?)
+    }
+
+    async fn field_rows(&self, farm_id: &str) -> Result<Vec<FieldRow>, ClickHouseError> {
+        Ok(self
+            .client
+            .query(
+                "SELECT ?fields FROM fields FINAL WHERE farm_id = ? AND deleted = 0 ORDER BY name",
+            )
+            .bind(farm_id)
+            .fetch_all()
+            .await?)
+    }
+
+    async fn plot_rows(&self, field_id: &str) -> Result<Vec<PlotRow>, ClickHouseError> {
+        Ok(self
+            .client
+            .query(
+                "SELECT ?fields FROM plots FINAL WHERE field_id = ? AND deleted = 0 ORDER BY name",
+            )
+            .bind(field_id)
+            .fetch_all()
+            .await?)
+    }
+
+    /// Mark a field and its plots deleted.
+    async fn delete_field(&self, mut field: FieldRow) -> Result<(), ClickHouseError> {
+        for plot in self.plot_rows(&field.id).await? {
+            self.delete_plot(plot).await?;
+        }
+
+        field.deleted = 1;
+        field.version = version();
+        let mut insert = self.client.insert("fields")?;
+        insert.write(&field).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    /// Mark a plot and its device assignments deleted.
+    async fn delete_plot(&self, mut plot: PlotRow) -> Result<(), ClickHouseError> {
+        let assignments: Vec<PlotDeviceRow> = self
+            .client
+            .query("SELECT ?fields FROM plot_devices FINAL WHERE plot_id = ? AND deleted = 0")
+            .bind(&plot.id)
+            .fetch_all()
+            .await?;
+        if !assignments.is_empty() {
+            let mut insert = self.client.insert("plot_devices")?;
+            for mut assignment in assignments {
+                assignment.deleted = 1;
+                assignment.version = version();
+                insert.write(&assignment).await?;
+            }
+            insert.end().await?;
+        }
+
+        plot.deleted = 1;
+        plot.version = version();
+        let mut insert = self.client.insert("plots")?;
+        insert.write(&plot).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn exists(&self, table: &str, id: &str) -> Result<bool, ClickHouseError> {
+        let count: u64 = self
+            .client
+            .query(&format!(
+                "SELECT count() FROM {table} FINAL WHERE id = ? AND deleted = 0"
+            ))
+            .bind(id)
+            .fetch_one()
+            .await?;
+        Ok(count > 0)
+    }
+}
+
+#[async_trait]
+impl FarmRegistry for ClickHouseFarmRegistry {
+    type Error = ClickHouseError;
+
+    async fn add_farm(&self, farm: Farm) -> Result<(), Self::Error> {
+        if self.exists("farms", &farm.id.0.to_string()).await? {
+            return Err(ClickHouseError::AlreadyExists);
+        }
+
+        let mut insert = self.client.insert("farms")?;
+        insert.write(&FarmRow::from(&farm)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn get_farm(&self, id: FarmId) -> Result<Option<Farm>, Self::Error> {
+        self.farm_row(&id.0.to_string())
+            .await?
+            .map(Farm::try_from)
+            .transpose()
+    }
+
+    async fn list_farms(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<Farm>, Self::Error> {
+        let rows: Vec<FarmRow> = match organization {
+            Some(organization) => {
+                self.client
+                    .query("SELECT ?fields FROM farms FINAL WHERE deleted = 0 AND organization_id = ? ORDER BY name")
+                    .bind(organization.0.to_string())
+                    .fetch_all()
+                    .await?
+            }
+            None => {
+                self.client
+                    .query("SELECT ?fields FROM farms FINAL WHERE deleted = 0 ORDER BY name")
+                    .fetch_all()
+                    .await?
+            }
+        };
+
+        rows.into_iter().map(Farm::try_from).collect()
+    }
+
+    async fn update_farm(&self, farm: Farm) -> Result<(), Self::Error> {
+        if !self.exists("farms", &farm.id.0.to_string()).await? {
+            return Err(ClickHouseError::NotFound);
+        }
+
+        let mut insert = self.client.insert("farms")?;
+        insert.write(&FarmRow::from(&farm)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn remove_farm(&self, id: FarmId) -> Result<(), Self::Error> {
+        let id = id.0.to_string();
+        let mut farm = self.farm_row(&id).await?.ok_or(ClickHouseError::NotFound)?;
+
+        for field in self.field_rows(&id).await? {
+            self.delete_field(field).await?;
+        }
+
+        farm.deleted = 1;
+        farm.version = version();
+        let mut insert = self.client.insert("farms")?;
+        insert.write(&farm).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn add_field(&self, field: Field) -> Result<(), Self::Error> {
+        if self.exists("fields", &field.id.0.to_string()).await? {
+            return Err(ClickHouseError::AlreadyExists);
+        }
+
+        let mut insert = self.client.insert("fields")?;
+        insert.write(&FieldRow::from(&field)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn get_field(&self, id: FieldId) -> Result<Option<Field>, Self::Error> {
+        let row: Option<FieldRow> = self
+            .client
+            .query("SELECT ?fields FROM fields FINAL WHERE id = ? AND deleted = 0")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(Field::try_from).transpose()
+    }
+
+    async fn list_fields(&self, farm_id: FarmId) -> Result<Vec<Field>, Self::Error> {
+        self.field_rows(&farm_id.0.to_string())
+            .await?
+            .into_iter()
+            .map(Field::try_from)
+            .collect()
+    }
+
+    async fn update_field(&self, field: Field) -> Result<(), Self::Error> {
+        if !self.exists("fields", &field.id.0.to_string()).await? {
+            return Err(ClickHouseError::NotFound);
+        }
+
+        let mut insert = self.client.insert("fields")?;
+        insert.write(&FieldRow::from(&field)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn remove_field(&self, id: FieldId) -> Result<(), Self::Error> {
+        let row: Option<FieldRow> = self
+            .client
+            .query("SELECT ?fields FROM fields FINAL WHERE id = ? AND deleted = 0")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        self.delete_field(row.ok_or(ClickHouseError::NotFound)?)
+            .await
+    }
+
+    async fn add_plot(&self, plot: Plot) -> Result<(), Self::Error> {
+        if self.exists("plots", &plot.id.0.to_string()).await? {
+            return Err(ClickHouseError::AlreadyExists);
+        }
+
+        let mut insert = self.client.insert("plots")?;
+        insert.write(&PlotRow::from(&plot)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn get_plot(&self, id: PlotId) -> Result<Option<Plot>, Self::Error> {
+        let row: Option<PlotRow> = self
+            .client
+            .query("SELECT ?fields FROM plots FINAL WHERE id = ? AND deleted = 0")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(Plot::try_from).transpose()
+    }
+
+    async fn list_plots(&self, field_id: FieldId) -> Result<Vec<Plot>, Self::Error> {
+        self.plot_rows(&field_id.0.to_string())
+            .await?
+            .into_iter()
+            .map(Plot::try_from)
+            .collect()
+    }
+
+    async fn update_plot(&self, plot: Plot) -> Result<(), Self::Error> {
+        if !self.exists("plots", &plot.id.0.to_string()).await? {
+            return Err(ClickHouseError::NotFound);
+        }
+
+        let mut insert = self.client.insert("plots")?;
+        insert.write(&PlotRow::from(&plot)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn remove_plot(&self, id: PlotId) -> Result<(), Self::Error> {
+        let row: Option<PlotRow> = self
+            .client
+            .query("SELECT ?fields FROM plots FINAL WHERE id = ? AND deleted = 0")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        self.delete_plot(row.ok_or(ClickHouseError::NotFound)?)
+            .await
+    }
+
+    async fn assign_device(&self, device_id: DeviceId, plot_id: PlotId) -> Result<(), Self::Error> {
+        let plot_id = plot_id.0.to_string();
+        if !self.exists("plots", &plot_id).await? {
+            return Err(ClickHouseError::NotFound);
+        }
+
+        let mut insert = self.client.insert("plot_devices")?;
+        insert
+            .write(&PlotDeviceRow {
+                device_id: device_id.0.to_string(),
+                plot_id,
+                deleted: 0,
+                version: version(),
+            })
+            .await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn unassign_device(&self, device_id: DeviceId) -> Result<bool, Self::Error> {
+        let row: Option<PlotDeviceRow> = self
+            .client
+            .query("SELECT ?fields FROM plot_devices FINAL WHERE device_id = ? AND deleted = 0")
+            .bind(device_id.0.to_string())
+            .fetch_optional()
+            .await?;
+        let Some(mut row) = row else {
+            return Ok(false);
+        };
+
+        row.deleted = 1;
+        row.version = version();
+        let mut insert = self.client.insert("plot_devices")?;
+        insert.write(&row).await?;
+        insert.end().await?;
+        Ok(true)
+    }
+
+    async fn plot_devices(&self, plot_id: PlotId) -> Result<Vec<DeviceId>, Self::Error> {
+        let ids: Vec<String> = self
+            .client
+            .query(
+                "SELECT device_id FROM plot_devices FINAL WHERE plot_id = ? AND deleted = 0 ORDER BY device_id",
+            )
+            .bind(plot_id.0.to_string())
+            .fetch_all()
+            .await?;
+
+        ids.iter().map(|id| parse_ulid(id).map(DeviceId)).collect()
+    }
+}
+
+fn map_boundary(ring: Vec<(f64, f64)>, cells: Vec<u64>) -> Result<Boundary, ClickHouseError> {
+    let ring = ring
+        .into_iter()
+        .map(|(lat, lng)| {
+            Ok(GeoPoint {
+                lat: NotNan::new(lat).map_err(|_| ClickHouseError::InvalidBoundary)?,
+                lng: NotNan::new(lng).map_err(|_| ClickHouseError::InvalidBoundary)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ClickHouseError>>()?;
+
+    Ok(Boundary {
+        ring: ring.into_boxed_slice(),
+        cells: cells.into_iter().map(H3Cell).collect(),
+    })
+}
+
+fn ring_row(boundary: &Boundary) -> Vec<(f64, f64)> {
+    boundary
+        .ring
+        .iter()
+        .map(|p| (p.lat.into_inner(), p.lng.into_inner()))
+        .collect()
+}
+
+fn parse_ulid(id: &str) -> Result<Ulid, ClickHouseError> {
+    Ulid::from_str(id).map_err(|_| ClickHouseError::InvalidUlid(id.to_string()))
+}
+
+fn parse_timestamp(seconds: i64) -> Result<jiff::Timestamp, ClickHouseError> {
+    jiff::Timestamp::from_second(seconds).map_err(|_| ClickHouseError::InvalidTimestamp(seconds))
+}
+
+fn parse_date(date: Option<String>) -> Result<Option<jiff::civil::Date>, ClickHouseError> {
+    date.map(|date| date.parse().map_err(|_| ClickHouseError::InvalidDate(date)))
+        .transpose()
+}
+
+fn version() -> u64 {
+    jiff::Timestamp::now().as_millisecond() as u64
+}
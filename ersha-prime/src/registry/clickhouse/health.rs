@@ -0,0 +1,663 @@
+use std::{collections::HashMap, str::FromStr};
+
+use async_trait::async_trait;
+use clickhouse::{Client, Row};
+use ersha_core::{AlertId, DeviceId, DispatcherId, OrganizationId};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use super::ClickHouseError;
+use crate::{
+    health::{
+        AlertSubject, ConnectionChange, ConnectionEvent, DeviceHealth, DispatcherHealth,
+        DispatcherStatusRecord, OfflineAlert, ReportedAlert,
+    },
+    registry::{
+        HealthRegistry,
+        health::{AlertColumns, ConnectionColumns},
+    },
+};
+
+const CREATE_STATUS_REPORTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS dispatcher_status_reports (
+    dispatcher_id String,
+    connected_devices UInt32,
+    uptime_seconds UInt64,
+    pending_uploads UInt32,
+    duplicates_dropped UInt64 DEFAULT 0,
+    reported_at Int64,
+    received_at Int64
+) ENGINE = MergeTree()
+ORDER BY (dispatcher_id, received_at)
+"#;
+
+const ADD_STATUS_DUPLICATES: &str = "ALTER TABLE dispatcher_status_reports \
+     ADD COLUMN IF NOT EXISTS duplicates_dropped UInt64 DEFAULT 0 AFTER pending_uploads";
+
+const CREATE_DISPATCHER_HEALTH_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS dispatcher_health (
+    dispatcher_id String,
+    last_seen Int64,
+    online UInt8,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY dispatcher_id
+"#;
+
+const CREATE_DEVICE_HEALTH_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS device_health (
+    device_id String,
+    dispatcher_id String,
+    last_seen Int64,
+    online UInt8,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY device_id
+"#;
+
+const CREATE_CONNECTION_EVENTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS device_connection_events (
+    device_id String,
+    dispatcher_id String,
+    kind Int32,
+    reason Nullable(Int32),
+    message Nullable(String),
+    timestamp Int64,
+    recorded_at UInt64
+) ENGINE = MergeTree()
+ORDER BY (device_id, recorded_at)
+"#;
+
+const CREATE_ALERTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS offline_alerts (
+    id String,
+    dispatcher_id String,
+    device_id Nullable(String),
+    last_seen Int64,
+    raised_at Int64
+) ENGINE = MergeTree()
+ORDER BY (dispatcher_id, raised_at)
+"#;
+
+const CREATE_REPORTED_ALERTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS reported_alerts (
+    id String,
+    dispatcher_id String,
+    device_id Nullable(String),
+    organization_id Nullable(String),
+    severity Int32,
+    kind Int32,
+    custom Nullable(String),
+    message String,
+    raised_at Int64,
+    received_at Int64
+) ENGINE = ReplacingMergeTree()
+ORDER BY id
+"#;
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct StatusReportRow {
+    dispatcher_id: String,
+    connected_devices: u32,
+    uptime_seconds: u64,
+    pending_uploads: u32,
+    duplicates_dropped: u64,
+    reported_at: i64,
+    received_at: i64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct DispatcherHealthRow {
+    dispatcher_id: String,
+    last_seen: i64,
+    online: u8,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct DeviceHealthRow {
+    device_id: String,
+    dispatcher_id: String,
+    last_seen: i64,
+    online: u8,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct ConnectionEventRow {
+    device_id: String,
+    dispatcher_id: String,
+    kind: i32,
+    reason: Option<i32>,
+    message: Option<String>,
+    timestamp: i64,
+    recorded_at: u64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct AlertRow {
+    id: String,
+    dispatcher_id: String,
+    device_id: Option<String>,
+    last_seen: i64,
+    raised_at: i64,
+}
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct ReportedAlertRow {
+    id: String,
+    dispatcher_id: String,
+    device_id: Option<String>,
+    organization_id: Option<String>,
+    severity: i32,
+    kind: i32,
+    custom: Option<String>,
+    message: String,
+    raised_at: i64,
+    received_at: i64,
+}
+
+fn parse_ulid(id: String) -> Result<Ulid, ClickHouseError> {
+    Ulid::from_str(&id).map_err(|_| ClickHouseError::InvalidUlid(id))
+}
+
+fn parse_timestamp(secs: i64) -> Result<jiff::Timestamp, ClickHouseError> {
+    jiff::Timestamp::from_second(secs).map_err(|_| ClickHouseError::InvalidTimestamp(secs))
+}
+
+fn version() -> u64 {
+    jiff::Timestamp::now().as_millisecond() as u64
+}
+
+impl TryFrom<StatusReportRow> for DispatcherStatusRecord {
+    type Error = ClickHouseError;
+
+    fn try_from(row: StatusReportRow) -> Result<Self, Self::Error> {
+        Ok(DispatcherStatusRecord {
+            dispatcher_id: DispatcherId(parse_ulid(row.dispatcher_id)?),
+            connected_devices: row.connected_devices,
+            uptime_seconds: row.uptime_seconds,
+            pending_uploads: row.pending_uploads,
+            duplicates_dropped: row.duplicates_dropped,
+            reported_at: parse_timestamp(row.reported_at)?,
+            received_at: parse_timestamp(row.received_at)?,
+        })
+    }
+}
+
+impl From<&DispatcherStatusRecord> for StatusReportRow {
+    fn from(record: &DispatcherStatusRecord) -> Self {
+        StatusReportRow {
+            dispatcher_id: record.dispatcher_id.0.to_string(),
+            connected_devices: record.connected_devices,
+            uptime_seconds: record.uptime_seconds,
+            pending_uploads: record.pending_uploads,
+            duplicates_dropped: record.duplicates_dropped,
+            reported_at: record.reported_at.as_second(),
+            received_at: record.received_at.as_second(),
+        }
+    }
+}
+
+impl TryFrom<DispatcherHealthRow> for DispatcherHealth {
+    type Error = ClickHouseError;
+
+    fn try_from(row: DispatcherHealthRow) -> Result<Self, Self::Error> {
+        Ok(DispatcherHealth {
+            dispatcher_id: DispatcherId(parse_ulid(row.dispatcher_id)?),
+            last_seen: parse_timestamp(row.last_seen)?,
+            online: row.online != 0,
+        })
+    }
+}
+
+impl From<&DispatcherHealth> for DispatcherHealthRow {
+    fn from(health: &DispatcherHealth) -> Self {
+        DispatcherHealthRow {
+            dispatcher_id: health.dispatcher_id.0.to_string(),
+            last_seen: health.last_seen.as_second(),
+            online: health.online as u8,
+            version: version(),
+        }
+    }
+}
+
+impl TryFrom<DeviceHealthRow> for DeviceHealth {
+    type Error = ClickHouseError;
+
+    fn try_from(row: DeviceHealthRow) -> Result<Self, Self::Error> {
+        Ok(DeviceHealth {
+            device_id: DeviceId(parse_ulid(row.device_id)?),
+            dispatcher_id: DispatcherId(parse_ulid(row.dispatcher_id)?),
+            last_seen: parse_timestamp(row.last_seen)?,
+            online: row.online != 0,
+        })
+    }
+}
+
+impl From<&DeviceHealth> for DeviceHealthRow {
+    fn from(health: &DeviceHealth) -> Self {
+        DeviceHealthRow {
+            device_id: health.device_id.0.to_string(),
+            dispatcher_id: health.dispatcher_id.0.to_string(),
+            last_seen: health.last_seen.as_second(),
+            online: health.online as u8,
+            version: version(),
+        }
+    }
+}
+
+impl TryFrom<ConnectionEventRow> for ConnectionEvent {
+    type Error = ClickHouseError;
+
+    fn try_from(row: ConnectionEventRow) -> Result<Self, Self::Error> {
+        let columns = ConnectionColumns {
+            kind: row.kind,
+            reason: row.reason,
+            message: row.message,
+        };
+
+        Ok(ConnectionEvent {
+            device_id: DeviceId(parse_ulid(row.device_id)?),
+            dispatcher_id: DispatcherId(parse_ulid(row.dispatcher_id)?),
+            change: columns
+                .decode()
+                .map_err(ClickHouseError::InvalidConnectionChange)?,
+            timestamp: parse_timestamp(row.timestamp)?,
+        })
+    }
+}
+
+impl From<&ConnectionEvent> for ConnectionEventRow {
+    fn from(event: &ConnectionEvent) -> Self {
+        let columns = ConnectionColumns::from(&event.change);
+
+        ConnectionEventRow {
+            device_id: event.device_id.0.to_string(),
+            dispatcher_id: event.dispatcher_id.0.to_string(),
+            kind: columns.kind,
+            reason: columns.reason,
+            message: columns.message,
+            timestamp: event.timestamp.as_second(),
+            recorded_at: version(),
+        }
+    }
+}
+
+impl TryFrom<AlertRow> for OfflineAlert {
+    type Error = ClickHouseError;
+
+    fn try_from(row: AlertRow) -> Result<Self, Self::Error> {
+        Ok(OfflineAlert {
+            id: AlertId(parse_ulid(row.id)?),
+            dispatcher_id: DispatcherId(parse_ulid(row.dispatcher_id)?),
+            device_id: row
+                .device_id
+                .map(|id| parse_ulid(id).map(DeviceId))
+                .transpose()?,
+            last_seen: parse_timestamp(row.last_seen)?,
+            raised_at: parse_timestamp(row.raised_at)?,
+        })
+    }
+}
+
+impl From<&OfflineAlert> for AlertRow {
+    fn from(alert: &OfflineAlert) -> Self {
+        AlertRow {
+            id: alert.id.0.to_string(),
+            dispatcher_id: alert.dispatcher_id.0.to_string(),
+            device_id: alert.device_id.map(|id| id.0.to_string()),
+            last_seen: alert.last_seen.as_second(),
+            raised_at: alert.raised_at.as_second(),
+        }
+    }
+}
+
+impl TryFrom<ReportedAlertRow> for ReportedAlert {
+    type Error = ClickHouseError;
+
+    fn try_from(row: ReportedAlertRow) -> Result<Self, Self::Error> {
+        let columns = AlertColumns {
+            severity: row.severity,
+            kind: row.kind,
+            custom: row.custom,
+        };
+        let (severity, alert_type) = columns.decode().map_err(ClickHouseError::InvalidAlert)?;
+
+        Ok(ReportedAlert {
+            id: AlertId(parse_ulid(row.id)?),
+            dispatcher_id: DispatcherId(parse_ulid(row.dispatcher_id)?),
+            device_id: row
+                .device_id
+                .map(|id| parse_ulid(id).map(DeviceId))
+                .transpose()?,
+            organization: row
+                .organization_id
+                .map(|id| parse_ulid(id).map(OrganizationId))
+                .transpose()?,
+            severity,
+            alert_type,
+            message: row.message.into_boxed_str(),
+            raised_at: parse_timestamp(row.raised_at)?,
+            received_at: parse_timestamp(row.received_at)?,
+        })
+    }
+}
+
+impl From<&ReportedAlert> for ReportedAlertRow {
+    fn from(alert: &ReportedAlert) -> Self {
+        let columns = AlertColumns::new(alert.severity, &alert.alert_type);
+        ReportedAlertRow {
+            id: alert.id.0.to_string(),
+            dispatcher_id: alert.dispatcher_id.0.to_string(),
+            device_id: alert.device_id.map(|id| id.0.to_string()),
+            organization_id: alert.organization.map(|id| id.0.to_string()),
+            severity: columns.severity,
+            kind: columns.kind,
+            custom: columns.custom,
+            message: alert.message.to_string(),
+            raised_at: alert.raised_at.as_second(),
+            received_at: alert.received_at.as_second(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClickHouseHealthRegistry {
+    client: Client,
+}
+
+impl ClickHouseHealthRegistry {
+    pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
+        let client = super::create_client(url, database);
+        client.query(CREATE_STATUS_REPORTS_TABLE).execute().await?;
+        client.query(ADD_STATUS_DUPLICATES).execute().await?;
+        client
+            .query(CREATE_DISPATCHER_HEALTH_TABLE)
+            .execute()
+            .await?;
+        client.query(CREATE_DEVICE_HEALTH_TABLE).execute().await?;
+        client
+            .query(CREATE_CONNECTION_EVENTS_TABLE)
+            .execute()
+            .await?;
+        client.query(CREATE_ALERTS_TABLE).execute().await?;
+        client.query(CREATE_REPORTED_ALERTS_TABLE).execute().await?;
+        Ok(Self { client })
+    }
+
+    async fn write_dispatcher_health(
+        &self,
+        health: &DispatcherHealth,
+    ) -> Result<(), ClickHouseError> {
+        let mut insert = self.client.insert("dispatcher_health")?;
+        insert.write(&DispatcherHealthRow::from(health)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn write_connections(&self, events: &[ConnectionEvent]) -> Result<(), ClickHouseError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut insert = self.client.insert("device_connection_events")?;
+        for event in events {
+            insert.write(&ConnectionEventRow::from(event)).await?;
+        }
+        insert.end().await?;
+        Ok(())
+    }
+
+    /// Mark a device offline, keeping when and where it was last seen.
+    async fn set_device_offline(&self, id: DeviceId) -> Result<(), ClickHouseError> {
+        let Some(mut health) = self.device_health(id).await? else {
+            return Ok(());
+        };
+        health.online = false;
+
+        let mut insert = self.client.insert("device_health")?;
+        insert.write(&DeviceHealthRow::from(&health)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HealthRegistry for ClickHouseHealthRegistry {
+    type Error = ClickHouseError;
+
+    async fn record_status(&self, record: DispatcherStatusRecord) -> Result<(), Self::Error> {
+        let mut insert = self.client.insert("dispatcher_status_reports")?;
+        insert.write(&StatusReportRow::from(&record)).await?;
+        insert.end().await?;
+
+        self.write_dispatcher_health(&DispatcherHealth {
+            dispatcher_id: record.dispatcher_id,
+            last_seen: record.received_at,
+            online: true,
+        })
+        .await
+    }
+
+    async fn list_statuses(
+        &self,
+        dispatcher_id: DispatcherId,
+        limit: usize,
+    ) -> Result<Vec<DispatcherStatusRecord>, Self::Error> {
+        let rows: Vec<StatusReportRow> = self
+            .client
+            .query(
+                "SELECT ?fields FROM dispatcher_status_reports WHERE dispatcher_id = ? ORDER BY received_at DESC LIMIT ?",
+            )
+            .bind(dispatcher_id.0.to_string())
+            .bind(limit as u64)
+            .fetch_all()
+            .await?;
+
+        rows.into_iter()
+            .map(DispatcherStatusRecord::try_from)
+            .collect()
+    }
+
+    async fn dispatcher_health(
+        &self,
+        id: DispatcherId,
+    ) -> Result<Option<DispatcherHealth>, Self::Error> {
+        let row: Option<DispatcherHealthRow> = self
+            .client
+            .query("SELECT ?fields FROM dispatcher_health FINAL WHERE dispatcher_id = ?")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(DispatcherHealth::try_from).transpose()
+    }
+
+    async fn record_seen(
+        &self,
+        dispatcher_id: DispatcherId,
+        devices: Vec<DeviceId>,
+        at: jiff::Timestamp,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error> {
+        if devices.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<String> = devices.iter().map(|id| id.0.to_string()).collect();
+        let online: HashMap<String, bool> = self
+            .client
+            .query("SELECT ?fields FROM device_health FINAL WHERE device_id IN ?")
+            .bind(&ids)
+            .fetch_all::<DeviceHealthRow>()
+            .await?
+            .into_iter()
+            .map(|row| (row.device_id, row.online != 0))
+            .collect();
+
+        let mut insert = self.client.insert("device_health")?;
+        let mut events = Vec::new();
+        for (device_id, id) in devices.into_iter().zip(ids) {
+            let health = DeviceHealth {
+                device_id,
+                dispatcher_id,
+                last_seen: at,
+                online: true,
+            };
+            insert.write(&DeviceHealthRow::from(&health)).await?;
+
+            if online.get(&id) != Some(&true) {
+                events.push(ConnectionEvent {
+                    device_id,
+                    dispatcher_id,
+                    change: ConnectionChange::Connected,
+                    timestamp: at,
+                });
+            }
+        }
+        insert.end().await?;
+
+        self.write_connections(&events).await?;
+        Ok(events)
+    }
+
+    async fn record_disconnection(&self, event: ConnectionEvent) -> Result<(), Self::Error> {
+        self.set_device_offline(event.device_id).await?;
+        self.write_connections(std::slice::from_ref(&event)).await
+    }
+
+    async fn list_connections(
+        &self,
+        device_id: DeviceId,
+        limit: usize,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error> {
+        let rows: Vec<ConnectionEventRow> = self
+            .client
+            .query(
+                "SELECT ?fields FROM device_connection_events WHERE device_id = ? ORDER BY recorded_at DESC LIMIT ?",
+            )
+            .bind(device_id.0.to_string())
+            .bind(limit as u64)
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(ConnectionEvent::try_from).collect()
+    }
+
+    async fn device_health(&self, id: DeviceId) -> Result<Option<DeviceHealth>, Self::Error> {
+        let row: Option<DeviceHealthRow> = self
+            .client
+            .query("SELECT ?fields FROM device_health FINAL WHERE device_id = ?")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(DeviceHealth::try_from).transpose()
+    }
+
+    async fn silent_dispatchers(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DispatcherHealth>, Self::Error> {
+        let rows: Vec<DispatcherHealthRow> = self
+            .client
+            .query("SELECT ?fields FROM dispatcher_health FINAL WHERE online = 1 AND last_seen < ?")
+            .bind(cutoff.as_second())
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(DispatcherHealth::try_from).collect()
+    }
+
+    async fn silent_devices(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DeviceHealth>, Self::Error> {
+        let rows: Vec<DeviceHealthRow> = self
+            .client
+            .query("SELECT ?fields FROM device_health FINAL WHERE online = 1 AND last_seen < ?")
+            .bind(cutoff.as_second())
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(DeviceHealth::try_from).collect()
+    }
+
+    async fn raise_alert(&self, alert: OfflineAlert) -> Result<(), Self::Error> {
+        let mut insert = self.client.insert("offline_alerts")?;
+        insert.write(&AlertRow::from(&alert)).await?;
+        insert.end().await?;
+
+        match alert.device_id {
+            Some(device_id) => self.set_device_offline(device_id).await,
+            None => {
+                let Some(mut health) = self.dispatcher_health(alert.dispatcher_id).await? else {
+                    return Ok(());
+                };
+                health.online = false;
+                self.write_dispatcher_health(&health).await
+            }
+        }
+    }
+
+    async fn list_alerts(
+        &self,
+        subject: AlertSubject,
+        limit: usize,
+    ) -> Result<Vec<OfflineAlert>, Self::Error> {
+        let (column, id) = match subject {
+            AlertSubject::Dispatcher(id) => ("dispatcher_id", id.0),
+            AlertSubject::Device(id) => ("device_id", id.0),
+        };
+
+        let rows: Vec<AlertRow> = self
+            .client
+            .query(&format!(
+                "SELECT ?fields FROM offline_alerts WHERE {column} = ? ORDER BY raised_at DESC, id DESC LIMIT ?"
+            ))
+            .bind(id.to_string())
+            .bind(limit as u64)
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(OfflineAlert::try_from).collect()
+    }
+
+    async fn record_reported_alert(&self, alert: ReportedAlert) -> Result<(), Self::Error> {
+        // a retried alert replaces the stored copy once merged
+        let mut insert = self.client.insert("reported_alerts")?;
+        insert.write(&ReportedAlertRow::from(&alert)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn list_reported_alerts(
+        &self,
+        subject: AlertSubject,
+        organization: Option<OrganizationId>,
+        limit: usize,
+    ) -> Result<Vec<ReportedAlert>, Self::Error> {
+        let (column, id) = match subject {
+            AlertSubject::Dispatcher(id) => ("dispatcher_id", id.0),
+            AlertSubject::Device(id) => ("device_id", id.0),
+        };
+        let owner = if organization.is_some() {
+            " AND organization_id = ?"
+        } else {
+            ""
+        };
+
+        let mut query = self
+            .client
+            .query(&format!(
+                "SELECT ?fields FROM reported_alerts FINAL WHERE {column} = ?{owner} ORDER BY raised_at DESC, id DESC LIMIT ?"
+            ))
+            .bind(id.to_string());
+        if let Some(organization) = organization {
+            query = query.bind(organization.0.to_string());
+        }
+        let rows: Vec<ReportedAlertRow> = query.bind(limit as u64).fetch_all().await?;
+
+        rows.into_iter().map(ReportedAlert::try_from).collect()
+    }
+}
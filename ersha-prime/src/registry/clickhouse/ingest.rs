@@ -0,0 +1,76 @@
+use std::{future::Future, time::Duration};
+
+use async_trait::async_trait;
+use ersha_core::{DeviceStatus, SensorReading};
+use tracing::warn;
+
+use super::{ClickHouseDeviceStatusRegistry, ClickHouseError, ClickHouseReadingRegistry};
+use crate::registry::{DeviceStatusRegistry, IngestRegistry, ReadingRegistry};
+
+/// How many times each half of a batch is written before giving up.
+const ATTEMPTS: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Stores batches into the reading and status tables. ClickHouse has no
+/// transactions across tables, so each write is retried instead; both skip
+/// ids that are already stored, which makes retrying safe.
+#[derive(Clone)]
+pub struct ClickHouseIngestRegistry {
+    readings: ClickHouseReadingRegistry,
+    statuses: ClickHouseDeviceStatusRegistry,
+}
+
+impl ClickHouseIngestRegistry {
+    pub fn new(
+        readings: ClickHouseReadingRegistry,
+        statuses: ClickHouseDeviceStatusRegistry,
+    ) -> Self {
+        Self { readings, statuses }
+    }
+}
+
+#[async_trait]
+impl IngestRegistry for ClickHouseIngestRegistry {
+    type Error = ClickHouseError;
+
+    async fn store_batch(
+        &self,
+        readings: Vec<SensorReading>,
+        statuses: Vec<DeviceStatus>,
+    ) -> Result<(), Self::Error> {
+        retry("readings", readings, |readings| {
+            self.readings.batch_store(readings)
+        })
+        .await?;
+        retry("statuses", statuses, |statuses| {
+            self.statuses.batch_store(statuses)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Run `store` on `records` until it succeeds or runs out of attempts.
+async fn retry<T, F, Fut>(what: &str, records: Vec<T>, store: F) -> Result<(), ClickHouseError>
+where
+    T: Clone,
+    F: Fn(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), ClickHouseError>>,
+{
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..ATTEMPTS {
+        match store(records.clone()).await {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                warn!(error = ?e, attempt, "failed to store {what}, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    }
+    store(records).await
+}
@@ -1,14 +1,32 @@
+mod api_key;
+mod batch;
 mod device;
 mod device_status;
 mod dispatcher;
+mod farm;
+mod health;
+mod ingest;
+mod organization;
 mod reading;
 
+pub use api_key::ClickHouseApiKeyRegistry;
+pub use batch::ClickHouseBatchRegistry;
 pub use device::ClickHouseDeviceRegistry;
 pub use device_status::ClickHouseDeviceStatusRegistry;
 pub use dispatcher::ClickHouseDispatcherRegistry;
+pub use farm::ClickHouseFarmRegistry;
+pub use health::ClickHouseHealthRegistry;
+pub use ingest::ClickHouseIngestRegistry;
+pub use organization::ClickHouseOrganizationRegistry;
 pub use reading::ClickHouseReadingRegistry;
 
 use clickhouse::Client;
+use ersha_core::H3Cell;
+
+use crate::{
+    geo,
+    registry::filter::{BoundingBox, CellRing},
+};
 
 /// Shared error type for all ClickHouse registry implementations.
 #[derive(Debug, thiserror::Error)]
@@ -33,11 +51,108 @@ pub enum ClickHouseError {
     InvalidErrorCode(i32),
     #[error("invalid sensor state: {0}")]
     InvalidSensorState(i32),
+    #[error("invalid firmware update state: {0}")]
+    InvalidFirmwareUpdate(i32),
+    #[error("invalid calibration kind: {0}")]
+    InvalidCalibrationKind(i32),
+    #[error("invalid device key")]
+    InvalidDeviceKey,
+    #[error("invalid firmware image")]
+    InvalidFirmware,
+    #[error("invalid actuator kind: {0}")]
+    InvalidActuatorKind(i32),
+    #[error("invalid command action: {0}")]
+    InvalidCommandAction(i32),
+    #[error("invalid command result: {0}")]
+    InvalidCommandResult(i32),
+    #[error("invalid role: {0}")]
+    InvalidRole(i32),
+    #[error("invalid API key hash")]
+    InvalidKeyHash,
+    #[error("invalid boundary")]
+    InvalidBoundary,
+    #[error("invalid rejection code: {0}")]
+    InvalidRejection(i32),
+    #[error("invalid connection change code: {0}")]
+    InvalidConnectionChange(i32),
+    #[error("invalid alert code: {0}")]
+    InvalidAlert(i32),
+    #[error("invalid date: {0}")]
+    InvalidDate(String),
     #[error("entity not found")]
     NotFound,
+    #[error("entity already exists")]
+    AlreadyExists,
 }
 
 /// Creates a ClickHouse client configured for the given URL and database.
 pub fn create_client(url: &str, database: &str) -> Client {
     Client::default().with_url(url).with_database(database)
 }
+
+/// Columns backing spatial filters on a table's `location`, computed by
+/// ClickHouse on insert. `h3ToGeo` returns `(lon, lat)`. Column
+/// `location_rN` holds the ancestor at resolution N, or 0 for resolutions
+/// finer than the location, like `rN` of SQLite's `h3_cells`.
+fn location_columns(table: &str) -> Vec<String> {
+    let mut columns = vec![
+        format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS location_resolution UInt8 MATERIALIZED h3GetResolution(toUInt64(location))"
+        ),
+        format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS location_lat Float64 MATERIALIZED h3ToGeo(toUInt64(location)).2"
+        ),
+        format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS location_lng Float64 MATERIALIZED h3ToGeo(toUInt64(location)).1"
+        ),
+    ];
+    columns.extend((0..=geo::MAX_RESOLUTION).map(|r| {
+        format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS location_r{r} UInt64 MATERIALIZED if(h3GetResolution(toUInt64(location)) >= {r}, h3ToParent(toUInt64(location), {r}), 0)"
+        )
+    }));
+    columns
+}
+
+/// Conditions for the ancestor, ring and bounding box filters on `location`.
+fn spatial_conditions(
+    ancestor: Option<H3Cell>,
+    ring: Option<CellRing>,
+    bbox: Option<BoundingBox>,
+) -> Vec<String> {
+    let mut conditions = Vec::new();
+
+    if let Some(ancestor) = ancestor {
+        conditions.push(match geo::resolution(ancestor) {
+            Some(r) => format!("location_r{r} = {}", ancestor.0),
+            None => "0".to_string(),
+        });
+    }
+
+    if let Some(ring) = ring {
+        conditions.push(match (geo::resolution(ring.center), geo::disk(ring)) {
+            (Some(r), Some(cells)) => {
+                let values: Vec<_> = cells.iter().map(|cell| cell.0.to_string()).collect();
+                format!("location_r{r} IN ({})", values.join(", "))
+            }
+            _ => "0".to_string(),
+        });
+    }
+
+    if let Some(bbox) = bbox {
+        let lng = if bbox.west <= bbox.east {
+            format!("location_lng BETWEEN {} AND {}", bbox.west, bbox.east)
+        } else {
+            format!(
+                "(location_lng >= {} OR location_lng <= {})",
+                bbox.west, bbox.east
+            )
+        };
+        conditions.push(format!(
+            "location_lat BETWEEN {} AND {} AND {lng}",
+            bbox.south, bbox.north
+        ));
+    }
+
+    conditions
+}
@@ -0,0 +1,114 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use clickhouse::{Client, Row};
+use ersha_core::{Organization, OrganizationId};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use super::ClickHouseError;
+use crate::registry::OrganizationRegistry;
+
+const CREATE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS organizations (
+    id String,
+    name String,
+    created_at Int64,
+    version UInt64
+) ENGINE = ReplacingMergeTree(version)
+ORDER BY id
+"#;
+
+#[derive(Debug, Clone, Row, Serialize, Deserialize)]
+struct OrganizationRow {
+    id: String,
+    name: String,
+    created_at: i64,
+    version: u64,
+}
+
+impl TryFrom<OrganizationRow> for Organization {
+    type Error = ClickHouseError;
+
+    fn try_from(row: OrganizationRow) -> Result<Self, Self::Error> {
+        let id =
+            Ulid::from_str(&row.id).map_err(|_| ClickHouseError::InvalidUlid(row.id.clone()))?;
+
+        let created_at = jiff::Timestamp::from_second(row.created_at)
+            .map_err(|_| ClickHouseError::InvalidTimestamp(row.created_at))?;
+
+        Ok(Organization {
+            id: OrganizationId(id),
+            name: row.name.into_boxed_str(),
+            created_at,
+        })
+    }
+}
+
+impl From<&Organization> for OrganizationRow {
+    fn from(organization: &Organization) -> Self {
+        OrganizationRow {
+            id: organization.id.0.to_string(),
+            name: organization.name.to_string(),
+            created_at: organization.created_at.as_second(),
+            version: jiff::Timestamp::now().as_millisecond() as u64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClickHouseOrganizationRegistry {
+    client: Client,
+}
+
+impl ClickHouseOrganizationRegistry {
+    pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
+        let client = super::create_client(url, database);
+        client.query(CREATE_TABLE).execute().await?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl OrganizationRegistry for ClickHouseOrganizationRegistry {
+    type Error = ClickHouseError;
+
+    async fn add(&self, organization: Organization) -> Result<(), Self::Error> {
+        let taken: u64 = self
+            .client
+            .query("SELECT count() FROM organizations FINAL WHERE id = ? OR name = ?")
+            .bind(organization.id.0.to_string())
+            .bind(organization.name.as_ref())
+            .fetch_one()
+            .await?;
+        if taken > 0 {
+            return Err(ClickHouseError::AlreadyExists);
+        }
+
+        let mut insert = self.client.insert("organizations")?;
+        insert.write(&OrganizationRow::from(&organization)).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn get(&self, id: OrganizationId) -> Result<Option<Organization>, Self::Error> {
+        let row: Option<OrganizationRow> = self
+            .client
+            .query("SELECT ?fields FROM organizations FINAL WHERE id = ?")
+            .bind(id.0.to_string())
+            .fetch_optional()
+            .await?;
+
+        row.map(Organization::try_from).transpose()
+    }
+
+    async fn list(&self) -> Result<Vec<Organization>, Self::Error> {
+        let rows: Vec<OrganizationRow> = self
+            .client
+            .query("SELECT ?fields FROM organizations FINAL ORDER BY name")
+            .fetch_all()
+            .await?;
+
+        rows.into_iter().map(Organization::try_from).collect()
+    }
+}
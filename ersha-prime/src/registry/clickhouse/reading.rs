@@ -1,18 +1,23 @@
+use std::collections::HashSet;
 use std::str::FromStr;
 
 use async_trait::async_trait;
 use clickhouse::{Client, Row};
-use ersha_core::{
-    DeviceId, DispatcherId, H3Cell, Percentage, ReadingId, SensorId, SensorMetric, SensorReading,
-};
+use ersha_core::{DeviceId, DispatcherId, H3Cell, Percentage, ReadingId, SensorId, SensorReading};
 use ordered_float::NotNan;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use ulid::Ulid;
 
 use super::ClickHouseError;
-use crate::registry::{
-    ReadingRegistry,
-    filter::{Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SensorMetricType, SortOrder},
+use crate::{
+    geo,
+    registry::{
+        ReadingRegistry,
+        filter::{Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SortOrder},
+        metric::{decode_metric, decode_metric_type, disect_metric, metric_type_code},
+        rollup::CellRollup,
+    },
 };
 
 const CREATE_TABLE: &str = r#"
@@ -25,12 +30,60 @@ CREATE TABLE IF NOT EXISTS sensor_readings (
     metric_value Float64,
     location Int64,
     confidence Int32,
-    timestamp Int64
+    timestamp Int64,
+    raw_value Nullable(Float64)
 ) ENGINE = MergeTree()
 PARTITION BY toYYYYMM(toDateTime(timestamp))
 ORDER BY (device_id, sensor_id, timestamp)
+SETTINGS non_replicated_deduplication_window = 1000
 "#;
 
+const ADD_RAW_VALUE: &str =
+    "ALTER TABLE sensor_readings ADD COLUMN IF NOT EXISTS raw_value Nullable(Float64)";
+
+/// Lets [`ClickHouseReadingRegistry::batch_store`] drop a repeated insert by
+/// its deduplication token on tables created before the setting was added.
+const SET_DEDUPLICATION_WINDOW: &str =
+    "ALTER TABLE sensor_readings MODIFY SETTING non_replicated_deduplication_window = 1000";
+
+/// Hourly statistics per device, metric and location, kept up to date by
+/// [`CREATE_ROLLUP_VIEW`].
+const CREATE_ROLLUP_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS reading_rollups (
+    hour Int64,
+    device_id String,
+    metric_type Int32,
+    location Int64,
+    readings SimpleAggregateFunction(sum, UInt64),
+    total SimpleAggregateFunction(sum, Float64),
+    minimum SimpleAggregateFunction(min, Float64),
+    maximum SimpleAggregateFunction(max, Float64)
+) ENGINE = AggregatingMergeTree()
+PARTITION BY toYYYYMM(toDateTime(hour))
+ORDER BY (metric_type, location, device_id, hour)
+"#;
+
+const ROLLUP_SELECT: &str = r#"
+SELECT
+    intDiv(timestamp, 3600) * 3600 AS hour,
+    device_id,
+    metric_type,
+    location,
+    count() AS readings,
+    sum(metric_value) AS total,
+    min(metric_value) AS minimum,
+    max(metric_value) AS maximum
+FROM sensor_readings
+GROUP BY hour, device_id, metric_type, location
+"#;
+
+const CREATE_ROLLUP_VIEW: &str =
+    "CREATE MATERIALIZED VIEW IF NOT EXISTS reading_rollups_mv TO reading_rollups AS";
+
+const INSERT_ROLLUPS: &str = "INSERT INTO reading_rollups (hour, device_id, metric_type, location, readings, total, minimum, maximum)";
+
+const HOUR: i64 = 3600;
+
 #[derive(Debug, Clone, Row, Serialize, Deserialize)]
 struct ReadingRow {
     id: String,
@@ -42,6 +95,7 @@ struct ReadingRow {
     location: i64,
     confidence: i32,
     timestamp: i64,
+    raw_value: Option<f64>,
 }
 
 impl TryFrom<ReadingRow> for SensorReading {
@@ -57,24 +111,8 @@ impl TryFrom<ReadingRow> for SensorReading {
         let sensor_id = Ulid::from_str(&row.sensor_id)
             .map_err(|_| ClickHouseError::InvalidUlid(row.sensor_id.clone()))?;
 
-        let metric = match row.metric_type {
-            0 => SensorMetric::SoilMoisture {
-                value: Percentage(row.metric_value as u8),
-            },
-            1 => SensorMetric::SoilTemp {
-                value: NotNan::new(row.metric_value).expect("database should not contain NaN"),
-            },
-            2 => SensorMetric::AirTemp {
-                value: NotNan::new(row.metric_value).expect("database should not contain NaN"),
-            },
-            3 => SensorMetric::Humidity {
-                value: Percentage(row.metric_value as u8),
-            },
-            4 => SensorMetric::Rainfall {
-                value: NotNan::new(row.metric_value).expect("database should not contain NaN"),
-            },
-            other => return Err(ClickHouseError::InvalidMetricType(other)),
-        };
+        let metric = decode_metric(row.metric_type, row.metric_value)
+            .map_err(ClickHouseError::InvalidMetricType)?;
 
         let timestamp = jiff::Timestamp::from_second(row.timestamp)
             .map_err(|_| ClickHouseError::InvalidTimestamp(row.timestamp))?;
@@ -88,6 +126,7 @@ impl TryFrom<ReadingRow> for SensorReading {
             location: H3Cell(row.location as u64),
             confidence: Percentage(row.confidence as u8),
             timestamp,
+            raw_value: row.raw_value.and_then(|v| NotNan::new(v).ok()),
         })
     }
 }
@@ -105,17 +144,36 @@ impl From<&SensorReading> for ReadingRow {
             location: reading.location.0 as i64,
             confidence: reading.confidence.0 as i32,
             timestamp: reading.timestamp.as_second(),
+            raw_value: reading.raw_value.map(|v| v.into_inner()),
         }
     }
 }
 
-fn disect_metric(metric: &SensorMetric) -> (i32, f64) {
-    match metric {
-        SensorMetric::SoilMoisture { value } => (0, value.0 as f64),
-        SensorMetric::SoilTemp { value } => (1, value.into_inner()),
-        SensorMetric::AirTemp { value } => (2, value.into_inner()),
-        SensorMetric::Humidity { value } => (3, value.0 as f64),
-        SensorMetric::Rainfall { value } => (4, value.into_inner()),
+#[derive(Debug, Clone, Row, Deserialize)]
+struct RollupRow {
+    cell: u64,
+    metric_type: i32,
+    readings: u64,
+    devices: u64,
+    mean: f64,
+    minimum: f64,
+    maximum: f64,
+}
+
+impl TryFrom<RollupRow> for CellRollup {
+    type Error = ClickHouseError;
+
+    fn try_from(row: RollupRow) -> Result<Self, Self::Error> {
+        Ok(CellRollup {
+            cell: H3Cell(row.cell),
+            metric_type: decode_metric_type(row.metric_type)
+                .map_err(ClickHouseError::InvalidMetricType)?,
+            readings: row.readings,
+            devices: row.devices,
+            mean: row.mean,
+            min: row.minimum,
+            max: row.maximum,
+        })
     }
 }
 
@@ -128,6 +186,33 @@ impl ClickHouseReadingRegistry {
     pub async fn new(url: &str, database: &str) -> Result<Self, ClickHouseError> {
         let client = super::create_client(url, database);
         client.query(CREATE_TABLE).execute().await?;
+        client.query(ADD_RAW_VALUE).execute().await?;
+        client.query(SET_DEDUPLICATION_WINDOW).execute().await?;
+        for column in super::location_columns("sensor_readings") {
+            client.query(&column).execute().await?;
+        }
+
+        client.query(CREATE_ROLLUP_TABLE).execute().await?;
+        for column in super::location_columns("reading_rollups") {
+            client.query(&column).execute().await?;
+        }
+        // Readings stored before the view existed are rolled up once. This
+        // runs before prime accepts uploads, so none are counted twice.
+        let rolled_up: u64 = client
+            .query("SELECT count() FROM reading_rollups")
+            .fetch_one()
+            .await?;
+        client
+            .query(&format!("{CREATE_ROLLUP_VIEW} {ROLLUP_SELECT}"))
+            .execute()
+            .await?;
+        if rolled_up == 0 {
+            client
+                .query(&format!("{INSERT_ROLLUPS} {ROLLUP_SELECT}"))
+                .execute()
+                .await?;
+        }
+
         Ok(Self { client })
     }
 }
@@ -159,10 +244,59 @@ impl ReadingRegistry for ClickHouseReadingRegistry {
             return Ok(());
         }
 
-        let mut insert = self.client.insert("sensor_readings")?;
-        for reading in &readings {
-            let row = ReadingRow::from(reading);
-            insert.write(&row).await?;
+        // The table is a plain MergeTree, so skip ids that are already stored
+        // rather than relying on merges to collapse them. Bounding the devices
+        // and timestamps keeps the lookup on the primary key.
+        let ids: Vec<String> = readings.iter().map(|r| r.id.0.to_string()).collect();
+        let devices: Vec<String> = readings
+            .iter()
+            .map(|r| r.device_id.0.to_string())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let timestamps = readings.iter().map(|r| r.timestamp.as_second());
+        let earliest = timestamps.clone().min().unwrap_or_default();
+        let latest = timestamps.max().unwrap_or_default();
+        let mut seen: HashSet<String> = self
+            .client
+            .query(
+                "SELECT id FROM sensor_readings \
+                 WHERE device_id IN ? AND timestamp BETWEEN ? AND ? AND id IN ?",
+            )
+            .bind(&devices)
+            .bind(earliest)
+            .bind(latest)
+            .bind(&ids)
+            .fetch_all::<String>()
+            .await?
+            .into_iter()
+            .collect();
+
+        let rows: Vec<ReadingRow> = readings
+            .iter()
+            .map(ReadingRow::from)
+            .filter(|row| seen.insert(row.id.clone()))
+            .collect();
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        // Two uploads of the same readings racing past the lookup above
+        // carry the same token, so ClickHouse keeps only the first insert.
+        let mut inserted: Vec<&str> = rows.iter().map(|row| row.id.as_str()).collect();
+        inserted.sort_unstable();
+        let token: String = Sha256::digest(inserted.join(",").as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        let mut insert = self
+            .client
+            .clone()
+            .with_option("insert_deduplication_token", token)
+            .insert("sensor_readings")?;
+        for row in &rows {
+            insert.write(row).await?;
         }
         insert.end().await?;
         Ok(())
@@ -194,6 +328,26 @@ impl ReadingRegistry for ClickHouseReadingRegistry {
         let rows: Vec<ReadingRow> = query.fetch_all().await?;
         rows.into_iter().map(SensorReading::try_from).collect()
     }
+
+    async fn rollup(
+        &self,
+        resolution: u8,
+        filter: ReadingFilter,
+    ) -> Result<Vec<CellRollup>, Self::Error> {
+        if resolution > geo::MAX_RESOLUTION {
+            return Ok(Vec::new());
+        }
+
+        let (query_str, bindings) = build_rollup_query(resolution, &filter);
+        let mut query = self.client.query(&query_str);
+
+        for binding in bindings {
+            query = query.bind(binding);
+        }
+
+        let rows: Vec<RollupRow> = query.fetch_all().await?;
+        rows.into_iter().map(CellRollup::try_from).collect()
+    }
 }
 
 fn build_count_query(filter: Option<ReadingFilter>) -> (String, Vec<String>) {
@@ -243,7 +397,90 @@ fn build_list_query(options: &QueryOptions<ReadingFilter, ReadingSortBy>) -> (St
     (query, bindings)
 }
 
+/// Rollups at `resolution`, per device first so devices are counted once
+/// across both sources.
+///
+/// Whole hours of the window are read from `reading_rollups`, the partial
+/// hours at its edges from `sensor_readings`. Filters on columns the
+/// rollups do not keep read every reading.
+fn build_rollup_query(resolution: u8, filter: &ReadingFilter) -> (String, Vec<String>) {
+    let cell = format!("location_r{resolution}");
+    let fine_enough = format!("location_resolution >= {resolution}");
+    let mut sources = Vec::new();
+    let mut bindings = Vec::new();
+
+    let readings_source = |edges: Option<String>, bindings: &mut Vec<String>| {
+        let (mut conditions, filter_bindings) = filter_conditions(filter);
+        conditions.push(fine_enough.clone());
+        conditions.extend(edges);
+        bindings.extend(filter_bindings);
+        format!(
+            "SELECT {cell} AS cell, metric_type, device_id, count() AS reading_count, sum(metric_value) AS value_total, min(metric_value) AS value_min, max(metric_value) AS value_max FROM sensor_readings WHERE {} GROUP BY cell, metric_type, device_id",
+            conditions.join(" AND ")
+        )
+    };
+
+    let rollups_apply = filter.ids.is_none()
+        && filter.sensor_ids.is_none()
+        && filter.dispatcher_ids.is_none()
+        && filter.confidence_range.is_none();
+    if rollups_apply {
+        let first_hour = filter
+            .timestamp_after
+            .map(|after| (after.as_second() + HOUR - 1).div_euclid(HOUR) * HOUR);
+        let end_hour = filter
+            .timestamp_before
+            .map(|before| (before.as_second() + 1).div_euclid(HOUR) * HOUR);
+
+        let whole_hours = ReadingFilter {
+            timestamp_after: None,
+            timestamp_before: None,
+            ..filter.clone()
+        };
+        let (mut conditions, filter_bindings) = filter_conditions(&whole_hours);
+        conditions.push(fine_enough.clone());
+        conditions.extend(first_hour.map(|first| format!("hour >= {first}")));
+        conditions.extend(end_hour.map(|end| format!("hour < {end}")));
+        bindings.extend(filter_bindings);
+        sources.push(format!(
+            "SELECT {cell} AS cell, metric_type, device_id, sum(readings) AS reading_count, sum(total) AS value_total, min(minimum) AS value_min, max(maximum) AS value_max FROM reading_rollups WHERE {} GROUP BY cell, metric_type, device_id",
+            conditions.join(" AND ")
+        ));
+
+        let edges: Vec<String> = first_hour
+            .map(|first| format!("timestamp < {first}"))
+            .into_iter()
+            .chain(end_hour.map(|end| format!("timestamp >= {end}")))
+            .collect();
+        if !edges.is_empty() {
+            let edges = format!("({})", edges.join(" OR "));
+            sources.push(readings_source(Some(edges), &mut bindings));
+        }
+    } else {
+        sources.push(readings_source(None, &mut bindings));
+    }
+
+    let query = format!(
+        "SELECT cell, metric_type, sum(reading_count) AS readings, uniqExact(device_id) AS devices, sum(value_total) / sum(reading_count) AS mean, min(value_min) AS minimum, max(value_max) AS maximum FROM ({}) GROUP BY cell, metric_type ORDER BY cell, metric_type",
+        sources.join(" UNION ALL ")
+    );
+
+    (query, bindings)
+}
+
 fn build_where_clause(filter: &ReadingFilter) -> (String, Vec<String>) {
+    let (conditions, bindings) = filter_conditions(filter);
+
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    };
+
+    (where_clause, bindings)
+}
+
+fn filter_conditions(filter: &ReadingFilter) -> (Vec<String>, Vec<String>) {
     let mut conditions = Vec::new();
     let mut bindings = Vec::new();
 
@@ -284,16 +521,7 @@ fn build_where_clause(filter: &ReadingFilter) -> (String, Vec<String>) {
     {
         let values: Vec<_> = metric_types
             .iter()
-            .map(|mt| {
-                match mt {
-                    SensorMetricType::SoilMoisture => 0,
-                    SensorMetricType::SoilTemp => 1,
-                    SensorMetricType::AirTemp => 2,
-                    SensorMetricType::Humidity => 3,
-                    SensorMetricType::Rainfall => 4,
-                }
-                .to_string()
-            })
+            .map(|mt| metric_type_code(*mt).to_string())
             .collect();
         conditions.push(format!("metric_type IN ({})", values.join(", ")));
     }
@@ -324,11 +552,26 @@ fn build_where_clause(filter: &ReadingFilter) -> (String, Vec<String>) {
         ));
     }
 
-    let where_clause = if conditions.is_empty() {
-        String::new()
-    } else {
-        format!(" WHERE {}", conditions.join(" AND "))
-    };
+    if let Some(organization) = &filter.organization {
+        conditions.push(
+            "device_id IN (SELECT id FROM devices FINAL WHERE organization_id = ?)".to_string(),
+        );
+        bindings.push(organization.0.to_string());
+    }
 
-    (where_clause, bindings)
+    conditions.extend(super::spatial_conditions(
+        filter.ancestor,
+        filter.ring,
+        filter.bbox,
+    ));
+
+    if let Some(field) = &filter.field {
+        conditions.push(
+            "device_id IN (SELECT device_id FROM plot_devices FINAL WHERE deleted = 0 AND plot_id IN (SELECT id FROM plots FINAL WHERE deleted = 0 AND field_id = ?))"
+                .to_string(),
+        );
+        bindings.push(field.0.to_string());
+    }
+
+    (conditions, bindings)
 }
@@ -1,20 +1,41 @@
 use ersha_core::{
-    DeviceErrorCode, DeviceId, DeviceKind, DeviceState, DispatcherId, DispatcherState, H3Cell,
-    ReadingId, SensorId, StatusId,
+    DeviceErrorCode, DeviceId, DeviceKind, DeviceState, DispatcherId, DispatcherState, FieldId,
+    H3Cell, OrganizationId, ReadingId, SensorId, StatusId,
 };
 
+pub use ersha_core::SensorMetricType;
+
 use jiff;
 use std::ops::RangeInclusive;
 use ulid::Ulid;
 
-/// Enum variant discriminator for SensorMetric, used for filtering by metric type without values.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum SensorMetricType {
-    SoilMoisture,
-    SoilTemp,
-    AirTemp,
-    Humidity,
-    Rainfall,
+/// Cells within `radius` grid steps of `center`. Locations are compared at
+/// the center's resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRing {
+    pub center: H3Cell,
+    pub radius: u32,
+}
+
+/// An area between two latitudes and two longitudes, in degrees. Boxes with
+/// `west` greater than `east` cross the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, lat: f64, lng: f64) -> bool {
+        let in_lng = if self.west <= self.east {
+            (self.west..=self.east).contains(&lng)
+        } else {
+            lng >= self.west || lng <= self.east
+        };
+        (self.south..=self.north).contains(&lat) && in_lng
+    }
 }
 
 pub enum DeviceSortBy {
@@ -55,6 +76,18 @@ pub struct DeviceFilter {
     pub provisioned_before: Option<jiff::Timestamp>,
     pub sensor_count: Option<RangeInclusive<usize>>,
     pub manufacturer_pattern: Option<String>,
+    /// Devices with any of these sensors.
+    pub sensor_ids: Option<Vec<SensorId>>,
+    /// Only devices owned by this organization.
+    pub organization: Option<OrganizationId>,
+    /// Only devices assigned to plots of this field.
+    pub field: Option<FieldId>,
+    /// Only locations within this cell, at its resolution or finer.
+    pub ancestor: Option<H3Cell>,
+    /// Only locations within a ring of cells.
+    pub ring: Option<CellRing>,
+    /// Only locations whose cell center lies in this box.
+    pub bbox: Option<BoundingBox>,
 }
 
 impl DeviceFilter {
@@ -128,6 +161,39 @@ impl DeviceFilterBuilder {
         self
     }
 
+    pub fn sensor_ids<I>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = SensorId>,
+    {
+        self.filter.sensor_ids = Some(ids.into_iter().collect());
+        self
+    }
+
+    pub fn organization(mut self, organization: OrganizationId) -> Self {
+        self.filter.organization = Some(organization);
+        self
+    }
+
+    pub fn field(mut self, field: FieldId) -> Self {
+        self.filter.field = Some(field);
+        self
+    }
+
+    pub fn ancestor(mut self, cell: H3Cell) -> Self {
+        self.filter.ancestor = Some(cell);
+        self
+    }
+
+    pub fn ring(mut self, center: H3Cell, radius: u32) -> Self {
+        self.filter.ring = Some(CellRing { center, radius });
+        self
+    }
+
+    pub fn bbox(mut self, bbox: BoundingBox) -> Self {
+        self.filter.bbox = Some(bbox);
+        self
+    }
+
     pub fn build(self) -> DeviceFilter {
         self.filter
     }
@@ -137,6 +203,8 @@ impl DeviceFilterBuilder {
 pub struct DispatcherFilter {
     pub states: Option<Vec<DispatcherState>>,
     pub locations: Option<Vec<H3Cell>>,
+    /// Only dispatchers owned by this organization.
+    pub organization: Option<OrganizationId>,
 }
 
 impl DispatcherFilter {
@@ -171,6 +239,11 @@ impl DispatcherFilterBuilder {
         self
     }
 
+    pub fn organization(mut self, organization: OrganizationId) -> Self {
+        self.filter.organization = Some(organization);
+        self
+    }
+
     pub fn build(self) -> DispatcherFilter {
         self.filter
     }
@@ -182,7 +255,7 @@ pub enum ReadingSortBy {
     DeviceId,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ReadingFilter {
     pub ids: Option<Vec<ReadingId>>,
     pub device_ids: Option<Vec<DeviceId>>,
@@ -193,6 +266,16 @@ pub struct ReadingFilter {
     pub timestamp_after: Option<jiff::Timestamp>,
     pub timestamp_before: Option<jiff::Timestamp>,
     pub confidence_range: Option<RangeInclusive<u8>>,
+    /// Only readings from devices owned by this organization.
+    pub organization: Option<OrganizationId>,
+    /// Only readings from devices assigned to plots of this field.
+    pub field: Option<FieldId>,
+    /// Only locations within this cell, at its resolution or finer.
+    pub ancestor: Option<H3Cell>,
+    /// Only locations within a ring of cells.
+    pub ring: Option<CellRing>,
+    /// Only locations whose cell center lies in this box.
+    pub bbox: Option<BoundingBox>,
 }
 
 impl ReadingFilter {
@@ -274,6 +357,31 @@ impl ReadingFilterBuilder {
         self
     }
 
+    pub fn organization(mut self, organization: OrganizationId) -> Self {
+        self.filter.organization = Some(organization);
+        self
+    }
+
+    pub fn field(mut self, field: FieldId) -> Self {
+        self.filter.field = Some(field);
+        self
+    }
+
+    pub fn ancestor(mut self, cell: H3Cell) -> Self {
+        self.filter.ancestor = Some(cell);
+        self
+    }
+
+    pub fn ring(mut self, center: H3Cell, radius: u32) -> Self {
+        self.filter.ring = Some(CellRing { center, radius });
+        self
+    }
+
+    pub fn bbox(mut self, bbox: BoundingBox) -> Self {
+        self.filter.bbox = Some(bbox);
+        self
+    }
+
     pub fn build(self) -> ReadingFilter {
         self.filter
     }
@@ -295,6 +403,8 @@ pub struct DeviceStatusFilter {
     pub battery_range: Option<RangeInclusive<u8>>,
     pub has_errors: Option<bool>,
     pub error_codes: Option<Vec<DeviceErrorCode>>,
+    /// Only statuses of devices owned by this organization.
+    pub organization: Option<OrganizationId>,
 }
 
 impl DeviceStatusFilter {
@@ -365,6 +475,11 @@ impl DeviceStatusFilterBuilder {
         self
     }
 
+    pub fn organization(mut self, organization: OrganizationId) -> Self {
+        self.filter.organization = Some(organization);
+        self
+    }
+
     pub fn build(self) -> DeviceStatusFilter {
         self.filter
     }
@@ -0,0 +1,104 @@
+use ersha_core::{FirmwareFailure, FirmwareUpdate, Percentage};
+
+/// Column form of a [`FirmwareUpdate`], shared by the SQL backends. Fields
+/// that do not apply to a state are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FirmwareUpdateColumns {
+    pub state: i32,
+    pub target_version: u32,
+    pub progress: u8,
+    pub failure: i32,
+}
+
+impl From<&FirmwareUpdate> for FirmwareUpdateColumns {
+    fn from(update: &FirmwareUpdate) -> Self {
+        let (state, target_version, progress, failure) = match update {
+            FirmwareUpdate::Idle => (0, 0, 0, 0),
+            FirmwareUpdate::Downloading { version, progress } => (1, *version, progress.0, 0),
+            FirmwareUpdate::Staged { version } => (2, *version, 0, 0),
+            FirmwareUpdate::Confirmed { version } => (3, *version, 0, 0),
+            FirmwareUpdate::Failed { version, reason } => {
+                let failure = match reason {
+                    FirmwareFailure::HashMismatch => 0,
+                    FirmwareFailure::BadSignature => 1,
+                    FirmwareFailure::Storage => 2,
+                    FirmwareFailure::Transfer => 3,
+                };
+                (4, *version, 0, failure)
+            }
+        };
+
+        Self {
+            state,
+            target_version,
+            progress,
+            failure,
+        }
+    }
+}
+
+impl FirmwareUpdateColumns {
+    /// Decode the columns, or return the unknown state or failure code.
+    pub fn decode(self) -> Result<FirmwareUpdate, i32> {
+        let version = self.target_version;
+        Ok(match self.state {
+            0 => FirmwareUpdate::Idle,
+            1 => FirmwareUpdate::Downloading {
+                version,
+                progress: Percentage(self.progress),
+            },
+            2 => FirmwareUpdate::Staged { version },
+            3 => FirmwareUpdate::Confirmed { version },
+            4 => FirmwareUpdate::Failed {
+                version,
+                reason: match self.failure {
+                    0 => FirmwareFailure::HashMismatch,
+                    1 => FirmwareFailure::BadSignature,
+                    2 => FirmwareFailure::Storage,
+                    3 => FirmwareFailure::Transfer,
+                    other => return Err(other),
+                },
+            },
+            other => return Err(other),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{FirmwareFailure, FirmwareUpdate, Percentage};
+
+    use super::FirmwareUpdateColumns;
+
+    #[test]
+    fn round_trips_every_state() {
+        let updates = [
+            FirmwareUpdate::Idle,
+            FirmwareUpdate::Downloading {
+                version: 3,
+                progress: Percentage(40),
+            },
+            FirmwareUpdate::Staged { version: 3 },
+            FirmwareUpdate::Confirmed { version: 3 },
+            FirmwareUpdate::Failed {
+                version: 3,
+                reason: FirmwareFailure::BadSignature,
+            },
+        ];
+
+        for update in updates {
+            assert_eq!(FirmwareUpdateColumns::from(&update).decode(), Ok(update));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_codes() {
+        let columns = FirmwareUpdateColumns {
+            state: 9,
+            target_version: 0,
+            progress: 0,
+            failure: 0,
+        };
+        assert_eq!(columns.decode(), Err(9));
+    }
+}
@@ -0,0 +1,180 @@
+use ersha_core::{AlertSeverity, AlertType, DisconnectionReason};
+
+use crate::health::ConnectionChange;
+
+/// Column form of a [`ConnectionChange`], shared by the SQL backends.
+/// `kind` is 0 for connected and 1 for disconnected, and `message` is only
+/// set for [`DisconnectionReason::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConnectionColumns {
+    pub kind: i32,
+    pub reason: Option<i32>,
+    pub message: Option<String>,
+}
+
+impl From<&ConnectionChange> for ConnectionColumns {
+    fn from(change: &ConnectionChange) -> Self {
+        let reason = match change {
+            ConnectionChange::Connected => {
+                return Self {
+                    kind: 0,
+                    reason: None,
+                    message: None,
+                };
+            }
+            ConnectionChange::Disconnected(reason) => reason.as_ref(),
+        };
+
+        let (reason, message) = match reason {
+            None => (None, None),
+            Some(DisconnectionReason::Timeout) => (Some(0), None),
+            Some(DisconnectionReason::GracefulClose) => (Some(1), None),
+            Some(DisconnectionReason::Error(message)) => (Some(2), Some(message.to_string())),
+            Some(DisconnectionReason::Unknown) => (Some(3), None),
+            Some(DisconnectionReason::UnknownDevice) => (Some(4), None),
+            Some(DisconnectionReason::AuthenticationFailed) => (Some(5), None),
+            Some(DisconnectionReason::ReplayDetected) => (Some(6), None),
+        };
+
+        Self {
+            kind: 1,
+            reason,
+            message,
+        }
+    }
+}
+
+impl ConnectionColumns {
+    /// Decode the columns, or return the unknown kind or reason code.
+    pub fn decode(self) -> Result<ConnectionChange, i32> {
+        match self.kind {
+            0 => return Ok(ConnectionChange::Connected),
+            1 => {}
+            other => return Err(other),
+        }
+
+        let reason = match self.reason {
+            None => None,
+            Some(0) => Some(DisconnectionReason::Timeout),
+            Some(1) => Some(DisconnectionReason::GracefulClose),
+            Some(2) => Some(DisconnectionReason::Error(
+                self.message.unwrap_or_default().into_boxed_str(),
+            )),
+            Some(3) => Some(DisconnectionReason::Unknown),
+            Some(4) => Some(DisconnectionReason::UnknownDevice),
+            Some(5) => Some(DisconnectionReason::AuthenticationFailed),
+            Some(6) => Some(DisconnectionReason::ReplayDetected),
+            Some(other) => return Err(other),
+        };
+
+        Ok(ConnectionChange::Disconnected(reason))
+    }
+}
+
+/// Column form of the severity and type of a
+/// [`ReportedAlert`](crate::health::ReportedAlert), shared by the SQL
+/// backends. `custom` is only set for [`AlertType::Custom`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AlertColumns {
+    pub severity: i32,
+    pub kind: i32,
+    pub custom: Option<String>,
+}
+
+impl AlertColumns {
+    pub fn new(severity: AlertSeverity, alert_type: &AlertType) -> Self {
+        let severity = match severity {
+            AlertSeverity::Critical => 0,
+            AlertSeverity::Warning => 1,
+            AlertSeverity::Info => 2,
+        };
+
+        let (kind, custom) = match alert_type {
+            AlertType::CriticalBattery => (0, None),
+            AlertType::SensorFailure => (1, None),
+            AlertType::DeviceOffline => (2, None),
+            AlertType::CommunicationError => (3, None),
+            AlertType::SecurityEvent => (4, None),
+            AlertType::Custom(name) => (5, Some(name.to_string())),
+        };
+
+        Self {
+            severity,
+            kind,
+            custom,
+        }
+    }
+
+    /// Decode the columns, or return the unknown severity or kind code.
+    pub fn decode(self) -> Result<(AlertSeverity, AlertType), i32> {
+        let severity = match self.severity {
+            0 => AlertSeverity::Critical,
+            1 => AlertSeverity::Warning,
+            2 => AlertSeverity::Info,
+            other => return Err(other),
+        };
+
+        let alert_type = match self.kind {
+            0 => AlertType::CriticalBattery,
+            1 => AlertType::SensorFailure,
+            2 => AlertType::DeviceOffline,
+            3 => AlertType::CommunicationError,
+            4 => AlertType::SecurityEvent,
+            5 => AlertType::Custom(self.custom.unwrap_or_default().into_boxed_str()),
+            other => return Err(other),
+        };
+
+        Ok((severity, alert_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{AlertSeverity, AlertType, DisconnectionReason};
+
+    use super::{AlertColumns, ConnectionColumns};
+    use crate::health::ConnectionChange;
+
+    #[test]
+    fn round_trips_every_change() {
+        let changes = [
+            ConnectionChange::Connected,
+            ConnectionChange::Disconnected(None),
+            ConnectionChange::Disconnected(Some(DisconnectionReason::Timeout)),
+            ConnectionChange::Disconnected(Some(DisconnectionReason::GracefulClose)),
+            ConnectionChange::Disconnected(Some(DisconnectionReason::Error("reset".into()))),
+            ConnectionChange::Disconnected(Some(DisconnectionReason::Unknown)),
+            ConnectionChange::Disconnected(Some(DisconnectionReason::UnknownDevice)),
+            ConnectionChange::Disconnected(Some(DisconnectionReason::AuthenticationFailed)),
+            ConnectionChange::Disconnected(Some(DisconnectionReason::ReplayDetected)),
+        ];
+
+        for change in changes {
+            let columns = ConnectionColumns::from(&change);
+            assert_eq!(columns.decode(), Ok(change));
+        }
+    }
+
+    #[test]
+    fn round_trips_every_alert_kind() {
+        let alert_types = [
+            AlertType::CriticalBattery,
+            AlertType::SensorFailure,
+            AlertType::DeviceOffline,
+            AlertType::CommunicationError,
+            AlertType::SecurityEvent,
+            AlertType::Custom("frost".into()),
+        ];
+
+        for severity in [
+            AlertSeverity::Critical,
+            AlertSeverity::Warning,
+            AlertSeverity::Info,
+        ] {
+            for alert_type in &alert_types {
+                let columns = AlertColumns::new(severity, alert_type);
+                assert_eq!(columns.decode(), Ok((severity, alert_type.clone())));
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::{
+    auth::{ApiKey, ApiKeyId},
+    registry::ApiKeyRegistry,
+};
+
+use super::InMemoryError;
+
+#[derive(Clone)]
+pub struct InMemoryApiKeyRegistry {
+    keys: Arc<RwLock<HashMap<ApiKeyId, ApiKey>>>,
+}
+
+impl InMemoryApiKeyRegistry {
+    pub fn new() -> Self {
+        Self {
+            keys: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+impl Default for InMemoryApiKeyRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRegistry for InMemoryApiKeyRegistry {
+    type Error = InMemoryError;
+
+    async fn add(&self, key: ApiKey) -> Result<(), Self::Error> {
+        let mut keys = self.keys.write().await;
+        if keys.values().any(|k| k.hash == key.hash) {
+            return Err(InMemoryError::AlreadyExists);
+        }
+        keys.insert(key.id, key);
+        Ok(())
+    }
+
+    async fn get_by_hash(&self, hash: [u8; 32]) -> Result<Option<ApiKey>, Self::Error> {
+        let keys = self.keys.read().await;
+        Ok(keys.values().find(|k| k.hash == hash).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKey>, Self::Error> {
+        let keys = self.keys.read().await;
+        let mut list: Vec<ApiKey> = keys.values().cloned().collect();
+        list.sort_by_key(|k| k.id.0);
+        Ok(list)
+    }
+
+    async fn revoke(&self, id: ApiKeyId, at: jiff::Timestamp) -> Result<bool, Self::Error> {
+        let mut keys = self.keys.write().await;
+        match keys.get_mut(&id) {
+            Some(key) if key.is_active() => {
+                key.revoked_at = Some(at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemoryApiKeyRegistry;
+    use crate::{
+        auth::{ApiKey, ApiKeyId, Role, hash_key},
+        registry::ApiKeyRegistry,
+    };
+    use ulid::Ulid;
+
+    #[tokio::test]
+    async fn test_add_lookup_and_revoke() {
+        let registry = InMemoryApiKeyRegistry::new();
+        let key = ApiKey::new("ops", Role::Operator, "secret-one");
+        registry.add(key.clone()).await.unwrap();
+
+        // the same secret can't be added twice
+        let duplicate = ApiKey::new("again", Role::Admin, "secret-one");
+        assert!(registry.add(duplicate).await.is_err());
+
+        let found = registry.get_by_hash(hash_key("secret-one")).await.unwrap();
+        assert_eq!(found, Some(key.clone()));
+        assert!(
+            registry
+                .get_by_hash(hash_key("secret-two"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let now = jiff::Timestamp::now();
+        assert!(registry.revoke(key.id, now).await.unwrap());
+        assert!(!registry.revoke(key.id, now).await.unwrap());
+        assert!(!registry.revoke(ApiKeyId(Ulid::new()), now).await.unwrap());
+
+        let revoked = registry.list().await.unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert!(!revoked[0].is_active());
+    }
+}
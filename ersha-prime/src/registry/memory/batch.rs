@@ -0,0 +1,57 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use ersha_core::{BatchId, BatchUploadResponse, DispatcherId};
+use tokio::sync::RwLock;
+
+use crate::registry::BatchRegistry;
+
+use super::InMemoryError;
+
+#[derive(Clone)]
+pub struct InMemoryBatchRegistry {
+    batches: Arc<RwLock<HashMap<BatchId, (DispatcherId, BatchUploadResponse)>>>,
+}
+
+impl InMemoryBatchRegistry {
+    pub fn new() -> Self {
+        Self {
+            batches: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+impl Default for InMemoryBatchRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl BatchRegistry for InMemoryBatchRegistry {
+    type Error = InMemoryError;
+
+    async fn get(
+        &self,
+        dispatcher_id: DispatcherId,
+        id: BatchId,
+    ) -> Result<Option<BatchUploadResponse>, Self::Error> {
+        let batches = self.batches.read().await;
+        Ok(batches
+            .get(&id)
+            .filter(|(recorded_for, _)| *recorded_for == dispatcher_id)
+            .map(|(_, response)| response.clone()))
+    }
+
+    async fn record(
+        &self,
+        dispatcher_id: DispatcherId,
+        response: BatchUploadResponse,
+    ) -> Result<(), Self::Error> {
+        let mut batches = self.batches.write().await;
+        batches
+            .entry(response.id)
+            .or_insert((dispatcher_id, response));
+        Ok(())
+    }
+}
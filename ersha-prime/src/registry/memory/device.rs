@@ -1,27 +1,80 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
-use ersha_core::{Device, DeviceId, DeviceState, Sensor};
+use ersha_core::{
+    ActuatorCommand, Calibration, CalibrationProfile, CommandId, CommandRecord, CommandResult,
+    Device, DeviceCredential, DeviceFirmware, DeviceId, DeviceKey, DeviceState, FieldId,
+    FirmwareId, FirmwareImage, OrganizationId, Sensor, SensorId,
+};
 use tokio::sync::RwLock;
 
-use crate::registry::{
-    DeviceRegistry,
-    filter::{DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder},
+use crate::{
+    geo,
+    registry::{
+        DeviceRegistry,
+        actuator::replaces_result,
+        filter::{DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder},
+    },
 };
 
-use super::InMemoryError;
+use super::{InMemoryError, InMemoryFarmRegistry};
+
+struct StoredFirmware {
+    image: FirmwareImage,
+    data: Vec<u8>,
+}
 
 #[derive(Clone)]
 pub struct InMemoryDeviceRegistry {
     devices: Arc<RwLock<HashMap<DeviceId, Device>>>,
+    calibrations: Arc<RwLock<HashMap<SensorId, CalibrationProfile>>>,
+    keys: Arc<RwLock<HashMap<DeviceId, DeviceKey>>>,
+    firmware: Arc<RwLock<HashMap<FirmwareId, StoredFirmware>>>,
+    reported_firmware: Arc<RwLock<HashMap<DeviceId, DeviceFirmware>>>,
+    commands: Arc<RwLock<HashMap<CommandId, CommandRecord>>>,
+    farms: Option<InMemoryFarmRegistry>,
 }
 
 impl InMemoryDeviceRegistry {
     pub fn new() -> Self {
         Self {
             devices: Arc::new(RwLock::new(HashMap::new())),
+            calibrations: Arc::new(RwLock::new(HashMap::new())),
+            keys: Arc::new(RwLock::new(HashMap::new())),
+            firmware: Arc::new(RwLock::new(HashMap::new())),
+            reported_firmware: Arc::new(RwLock::new(HashMap::new())),
+            commands: Arc::new(RwLock::new(HashMap::new())),
+            farms: None,
+        }
+    }
+
+    /// Resolve field filters against the plot assignments of `farms`.
+    /// Without it, filtering by field matches no devices.
+    pub fn with_farms(mut self, farms: InMemoryFarmRegistry) -> Self {
+        self.farms = Some(farms);
+        self
+    }
+
+    /// Ids of the devices assigned to plots of `field`.
+    pub(super) async fn in_field(&self, field: FieldId) -> HashSet<DeviceId> {
+        match &self.farms {
+            Some(farms) => farms.field_devices(field).await,
+            None => HashSet::new(),
         }
     }
+
+    /// Ids of the devices owned by `organization`.
+    pub(super) async fn owned_by(&self, organization: OrganizationId) -> HashSet<DeviceId> {
+        let devices = self.devices.read().await;
+        devices
+            .values()
+            .filter(|device| device.organization == Some(organization))
+            .map(|device| device.id)
+            .collect()
+    }
 }
 
 impl Default for InMemoryDeviceRegistry {
@@ -70,6 +123,21 @@ impl DeviceRegistry for InMemoryDeviceRegistry {
         Ok(())
     }
 
+    async fn remove_sensor(&self, id: DeviceId, sensor_id: SensorId) -> Result<bool, Self::Error> {
+        let mut devices = self.devices.write().await;
+        let device = devices.get_mut(&id).ok_or(InMemoryError::NotFound)?;
+
+        let count = device.sensors.len();
+        device.sensors = device
+            .sensors
+            .iter()
+            .filter(|sensor| sensor.id != sensor_id)
+            .cloned()
+            .collect();
+
+        Ok(device.sensors.len() != count)
+    }
+
     async fn get(&self, id: DeviceId) -> Result<Option<Device>, Self::Error> {
         let devices = self.devices.read().await;
         Ok(devices.get(&id).cloned())
@@ -96,6 +164,36 @@ impl DeviceRegistry for InMemoryDeviceRegistry {
         Ok(())
     }
 
+    async fn reactivate(&self, id: DeviceId) -> Result<(), Self::Error> {
+        let device = self.get(id).await?.ok_or(InMemoryError::NotFound)?;
+
+        self.update(
+            id,
+            Device {
+                state: DeviceState::Active,
+                ..device
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn retire(&self, id: DeviceId) -> Result<(), Self::Error> {
+        let device = self.get(id).await?.ok_or(InMemoryError::NotFound)?;
+
+        self.update(
+            id,
+            Device {
+                state: DeviceState::Retired,
+                ..device
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
     async fn batch_register(&self, devices: Vec<Device>) -> Result<(), Self::Error> {
         for device in devices {
             self.register(device).await?;
@@ -105,13 +203,18 @@ impl DeviceRegistry for InMemoryDeviceRegistry {
     }
 
     async fn count(&self, filter: Option<DeviceFilter>) -> Result<usize, Self::Error> {
-        let devices = self.devices.read().await;
         if let Some(filter) = filter {
-            let filtered = filter_devices(&devices, &filter);
+            let in_field = match filter.field {
+                Some(field) => Some(self.in_field(field).await),
+                None => None,
+            };
+            let devices = self.devices.read().await;
+            let filtered = filter_devices(&devices, &filter, in_field.as_ref());
 
             return Ok(filtered.count());
         }
 
+        let devices = self.devices.read().await;
         Ok(devices.len())
     }
 
@@ -119,13 +222,237 @@ impl DeviceRegistry for InMemoryDeviceRegistry {
         &self,
         options: QueryOptions<DeviceFilter, DeviceSortBy>,
     ) -> Result<Vec<Device>, Self::Error> {
+        let in_field = match options.filter.field {
+            Some(field) => Some(self.in_field(field).await),
+            None => None,
+        };
         let devices = self.devices.read().await;
-        let filtered: Vec<&Device> = filter_devices(&devices, &options.filter).collect();
+        let filtered: Vec<&Device> =
+            filter_devices(&devices, &options.filter, in_field.as_ref()).collect();
         let sorted = sort_devices(filtered, &options.sort_by, &options.sort_order);
         let paginated = paginate_devices(sorted, &options.pagination);
 
         Ok(paginated)
     }
+
+    async fn set_calibration(
+        &self,
+        sensor_id: SensorId,
+        calibration: Option<Calibration>,
+    ) -> Result<(), Self::Error> {
+        let devices = self.devices.read().await;
+        let known = devices
+            .values()
+            .any(|d| d.sensors.iter().any(|s| s.id == sensor_id));
+        if !known {
+            return Err(InMemoryError::NotFound);
+        }
+
+        let mut calibrations = self.calibrations.write().await;
+        match calibration {
+            Some(calibration) => {
+                calibrations.insert(
+                    sensor_id,
+                    CalibrationProfile {
+                        sensor_id,
+                        calibration,
+                        updated_at: jiff::Timestamp::now(),
+                    },
+                );
+            }
+            None => {
+                calibrations.remove(&sensor_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_calibration(
+        &self,
+        sensor_id: SensorId,
+    ) -> Result<Option<CalibrationProfile>, Self::Error> {
+        let calibrations = self.calibrations.read().await;
+        Ok(calibrations.get(&sensor_id).cloned())
+    }
+
+    async fn list_calibrations(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<CalibrationProfile>, Self::Error> {
+        let devices = self.devices.read().await;
+        let sensors: HashSet<SensorId> = devices
+            .values()
+            .filter(|device| device.organization == organization)
+            .flat_map(|device| device.sensors.iter().map(|sensor| sensor.id))
+            .collect();
+
+        let calibrations = self.calibrations.read().await;
+        Ok(calibrations
+            .values()
+            .filter(|profile| sensors.contains(&profile.sensor_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn set_key(&self, id: DeviceId, key: DeviceKey) -> Result<(), Self::Error> {
+        if !self.devices.read().await.contains_key(&id) {
+            return Err(InMemoryError::NotFound);
+        }
+
+        self.keys.write().await.insert(id, key);
+        Ok(())
+    }
+
+    async fn list_keys(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<DeviceCredential>, Self::Error> {
+        let devices = self.devices.read().await;
+        let keys = self.keys.read().await;
+        Ok(keys
+            .iter()
+            .filter(|(device_id, _)| {
+                devices
+                    .get(device_id)
+                    .is_some_and(|device| device.organization == organization)
+            })
+            .map(|(device_id, key)| DeviceCredential {
+                device_id: *device_id,
+                key: key.clone(),
+            })
+            .collect())
+    }
+
+    async fn add_firmware(&self, image: FirmwareImage, data: Vec<u8>) -> Result<(), Self::Error> {
+        let mut firmware = self.firmware.write().await;
+        if firmware.values().any(|existing| {
+            existing.image.id == image.id
+                || (existing.image.model == image.model && existing.image.version == image.version)
+        }) {
+            return Err(InMemoryError::AlreadyExists);
+        }
+
+        firmware.insert(image.id, StoredFirmware { image, data });
+        Ok(())
+    }
+
+    async fn list_firmware(&self) -> Result<Vec<FirmwareImage>, Self::Error> {
+        let firmware = self.firmware.read().await;
+        Ok(firmware.values().map(|f| f.image.clone()).collect())
+    }
+
+    async fn firmware_chunk(
+        &self,
+        id: FirmwareId,
+        offset: u32,
+        len: u32,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        let firmware = self.firmware.read().await;
+        Ok(firmware.get(&id).map(|StoredFirmware { data, .. }| {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(len as usize).min(data.len());
+            data[start..end].to_vec()
+        }))
+    }
+
+    async fn record_firmware(&self, report: DeviceFirmware) -> Result<(), Self::Error> {
+        let mut reported = self.reported_firmware.write().await;
+        if reported
+            .get(&report.device_id)
+            .is_none_or(|last| last.reported_at <= report.reported_at)
+        {
+            reported.insert(report.device_id, report);
+        }
+        Ok(())
+    }
+
+    async fn get_firmware(&self, id: DeviceId) -> Result<Option<DeviceFirmware>, Self::Error> {
+        let reported = self.reported_firmware.read().await;
+        Ok(reported.get(&id).cloned())
+    }
+
+    async fn add_command(&self, command: ActuatorCommand) -> Result<(), Self::Error> {
+        let mut commands = self.commands.write().await;
+        if commands.contains_key(&command.id) {
+            return Err(InMemoryError::AlreadyExists);
+        }
+
+        commands.insert(
+            command.id,
+            CommandRecord {
+                command,
+                result: None,
+                completed_at: None,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_command(&self, id: CommandId) -> Result<Option<CommandRecord>, Self::Error> {
+        Ok(self.commands.read().await.get(&id).cloned())
+    }
+
+    async fn list_commands(&self, device_id: DeviceId) -> Result<Vec<CommandRecord>, Self::Error> {
+        let commands = self.commands.read().await;
+        let mut records: Vec<CommandRecord> = commands
+            .values()
+            .filter(|r| r.command.device_id == device_id)
+            .cloned()
+            .collect();
+        records.sort_by_key(|r| r.command.issued_at);
+        Ok(records)
+    }
+
+    async fn pending_commands(
+        &self,
+        now: jiff::Timestamp,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<ActuatorCommand>, Self::Error> {
+        let devices = self.devices.read().await;
+        let commands = self.commands.read().await;
+        let mut pending: Vec<ActuatorCommand> = commands
+            .values()
+            .filter(|r| r.result.is_none() && r.command.expires_at > now)
+            .filter(|r| {
+                devices
+                    .get(&r.command.device_id)
+                    .is_some_and(|device| device.organization == organization)
+            })
+            .map(|r| r.command.clone())
+            .collect();
+        pending.sort_by_key(|c| c.issued_at);
+        Ok(pending)
+    }
+
+    async fn complete_command(
+        &self,
+        id: CommandId,
+        result: CommandResult,
+        at: jiff::Timestamp,
+    ) -> Result<bool, Self::Error> {
+        let mut commands = self.commands.write().await;
+        match commands.get_mut(&id) {
+            Some(record) if replaces_result(record.result.as_ref(), &result) => {
+                record.result = Some(result);
+                record.completed_at = Some(at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn expire_commands(&self, now: jiff::Timestamp) -> Result<usize, Self::Error> {
+        let mut commands = self.commands.write().await;
+        let mut expired = 0;
+        for record in commands.values_mut() {
+            if record.result.is_none() && record.command.expires_at <= now {
+                record.result = Some(CommandResult::TimedOut);
+                record.completed_at = Some(now);
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
 }
 
 fn sort_devices<'a>(
@@ -178,14 +505,32 @@ fn paginate_devices(devices: Vec<&Device>, pagination: &Pagination) -> Vec<Devic
 fn filter_devices<'a>(
     devices: &'a HashMap<DeviceId, Device>,
     filter: &DeviceFilter,
+    in_field: Option<&HashSet<DeviceId>>,
 ) -> impl Iterator<Item = &'a Device> {
-    devices.values().filter(|device| {
+    let spatial = geo::SpatialMatcher::new(filter.ancestor, filter.ring, filter.bbox);
+    devices.values().filter(move |device| {
+        if let Some(ids) = &filter.ids
+            && !ids.contains(&device.id)
+        {
+            return false;
+        }
+
+        if let Some(in_field) = in_field
+            && !in_field.contains(&device.id)
+        {
+            return false;
+        }
+
         if let Some(locations) = &filter.locations
             && !locations.contains(&device.location)
         {
             return false;
         }
 
+        if !spatial.matches(device.location) {
+            return false;
+        }
+
         if let Some(states) = &filter.states
             && !states.contains(&device.state)
         {
@@ -218,6 +563,16 @@ fn filter_devices<'a>(
             return false;
         }
 
+        if let Some(sensor_ids) = &filter.sensor_ids
+            && !device.sensors.iter().any(|s| sensor_ids.contains(&s.id))
+        {
+            return false;
+        }
+
+        if filter.organization.is_some() && filter.organization != device.organization {
+            return false;
+        }
+
         match (&filter.provisioned_after, &filter.provisioned_before) {
             (None, None) => (),
             (None, Some(before)) => {
@@ -250,8 +605,10 @@ mod tests {
         DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder,
     };
     use ersha_core::{
-        Device, DeviceId, DeviceKind, DeviceState, H3Cell, Sensor, SensorId, SensorKind,
-        SensorMetric,
+        ActuatorAction, ActuatorCommand, ActuatorId, ActuatorState, Calibration, CommandId,
+        CommandResult, Device, DeviceFirmware, DeviceId, DeviceKey, DeviceKind, DeviceState,
+        FirmwareId, FirmwareImage, FirmwareStatus, FirmwareUpdate, H3Cell, OrganizationId,
+        Percentage, Sensor, SensorId, SensorKind, SensorMetric,
     };
     use ordered_float::NotNan;
 
@@ -266,6 +623,31 @@ mod tests {
             manufacturer: Some(manufacturer.to_string().into_boxed_str()),
             provisioned_at: jiff::Timestamp::now(),
             sensors: vec![].into_boxed_slice(),
+            actuators: vec![].into_boxed_slice(),
+            organization: None,
+        }
+    }
+
+    fn mock_firmware(model: &str, version: u32, size: u32) -> FirmwareImage {
+        FirmwareImage {
+            id: FirmwareId(Ulid::new()),
+            model: model.into(),
+            version,
+            size,
+            sha256: [0; 32],
+            signature: vec![0; 64].into_boxed_slice(),
+            created_at: jiff::Timestamp::now(),
+        }
+    }
+
+    fn mock_command(device_id: DeviceId, issued_at: jiff::Timestamp) -> ActuatorCommand {
+        ActuatorCommand {
+            id: CommandId(Ulid::new()),
+            device_id,
+            actuator_id: ActuatorId(Ulid::new()),
+            action: ActuatorAction::Open { duration_secs: 600 },
+            issued_at,
+            expires_at: issued_at + jiff::SignedDuration::from_secs(60),
         }
     }
 
@@ -312,6 +694,230 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_calibration_profiles() {
+        let registry = device_registry();
+
+        let d_id = Ulid::new();
+        let sensor_id = SensorId(Ulid::new());
+        let mut device = mock_device(d_id, "SensorCo");
+        device.sensors = vec![Sensor {
+            id: sensor_id,
+            kind: SensorKind::SoilMoisture,
+            metric: SensorMetric::SoilMoisture {
+                value: ersha_core::Percentage(0),
+            },
+        }]
+        .into_boxed_slice();
+        registry.register(device).await.unwrap();
+
+        let calibration = Calibration::Linear {
+            gain: NotNan::new(1.1).unwrap(),
+            offset: NotNan::new(-2.0).unwrap(),
+        };
+        registry
+            .set_calibration(sensor_id, Some(calibration.clone()))
+            .await
+            .unwrap();
+
+        let profile = registry.get_calibration(sensor_id).await.unwrap().unwrap();
+        assert_eq!(profile.calibration, calibration);
+        assert_eq!(registry.list_calibrations(None).await.unwrap().len(), 1);
+
+        registry.set_calibration(sensor_id, None).await.unwrap();
+        assert!(registry.get_calibration(sensor_id).await.unwrap().is_none());
+
+        // unknown sensors cannot be calibrated
+        assert!(
+            registry
+                .set_calibration(SensorId(Ulid::new()), Some(calibration))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_device_keys() {
+        let registry = device_registry();
+
+        let d_id = Ulid::new();
+        registry
+            .register(mock_device(d_id, "SensorCo"))
+            .await
+            .unwrap();
+
+        registry
+            .set_key(DeviceId(d_id), DeviceKey([1; 32]))
+            .await
+            .unwrap();
+        registry
+            .set_key(DeviceId(d_id), DeviceKey([2; 32]))
+            .await
+            .unwrap();
+
+        let keys = registry.list_keys(None).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].device_id, DeviceId(d_id));
+        assert_eq!(keys[0].key, DeviceKey([2; 32]));
+
+        assert!(
+            registry
+                .set_key(DeviceId(Ulid::new()), DeviceKey([3; 32]))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_firmware_images() {
+        let registry = device_registry();
+
+        let image = mock_firmware("soil-v1", 2, 10);
+        registry
+            .add_firmware(image.clone(), (0..10).collect())
+            .await
+            .unwrap();
+
+        // one image per model and version
+        assert!(
+            registry
+                .add_firmware(mock_firmware("soil-v1", 2, 1), vec![0])
+                .await
+                .is_err()
+        );
+        registry
+            .add_firmware(mock_firmware("soil-v1", 3, 1), vec![0])
+            .await
+            .unwrap();
+
+        assert_eq!(registry.list_firmware().await.unwrap().len(), 2);
+
+        let chunk = registry.firmware_chunk(image.id, 8, 4).await.unwrap();
+        assert_eq!(chunk, Some(vec![8, 9]));
+        let past_end = registry.firmware_chunk(image.id, 20, 4).await.unwrap();
+        assert_eq!(past_end, Some(vec![]));
+        let unknown = registry
+            .firmware_chunk(FirmwareId(Ulid::new()), 0, 4)
+            .await
+            .unwrap();
+        assert_eq!(unknown, None);
+    }
+
+    #[tokio::test]
+    async fn test_reported_firmware() {
+        let registry = device_registry();
+        let device_id = DeviceId(Ulid::new());
+        let reported_at = jiff::Timestamp::now();
+        let report = |progress, reported_at| DeviceFirmware {
+            device_id,
+            firmware: FirmwareStatus {
+                model: "soil-v1".into(),
+                version: 2,
+                update: FirmwareUpdate::Downloading {
+                    version: 3,
+                    progress: Percentage(progress),
+                },
+            },
+            reported_at,
+        };
+
+        assert_eq!(registry.get_firmware(device_id).await.unwrap(), None);
+
+        let latest = report(60, reported_at);
+        registry.record_firmware(latest.clone()).await.unwrap();
+
+        // a report delivered late does not replace a newer one
+        let earlier = reported_at - jiff::SignedDuration::from_mins(1);
+        registry.record_firmware(report(20, earlier)).await.unwrap();
+        assert_eq!(
+            registry.get_firmware(device_id).await.unwrap(),
+            Some(latest)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_actuator_commands() {
+        let registry = device_registry();
+        let device_id = DeviceId(Ulid::new());
+        registry
+            .register(mock_device(device_id.0, "ValveCo"))
+            .await
+            .unwrap();
+        let now = jiff::Timestamp::from_second(1_700_000_000).unwrap();
+
+        let acked = mock_command(device_id, now);
+        let unacked = mock_command(device_id, now);
+        registry.add_command(acked.clone()).await.unwrap();
+        registry.add_command(unacked.clone()).await.unwrap();
+        assert!(registry.add_command(acked.clone()).await.is_err());
+        assert_eq!(registry.pending_commands(now, None).await.unwrap().len(), 2);
+        // commands only go to dispatchers of the device's organization
+        let other = Some(OrganizationId(Ulid::new()));
+        assert!(
+            registry
+                .pending_commands(now, other)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let result = CommandResult::Applied {
+            state: ActuatorState::Open {
+                until: now + jiff::SignedDuration::from_secs(600),
+            },
+        };
+        assert!(
+            registry
+                .complete_command(acked.id, result, now)
+                .await
+                .unwrap()
+        );
+        // the first result is kept
+        assert!(
+            !registry
+                .complete_command(acked.id, CommandResult::Failed, now)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            registry.pending_commands(now, None).await.unwrap(),
+            vec![unacked.clone()]
+        );
+
+        let later = now + jiff::SignedDuration::from_secs(60);
+        assert!(
+            registry
+                .pending_commands(later, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(registry.expire_commands(later).await.unwrap(), 1);
+        assert_eq!(registry.expire_commands(later).await.unwrap(), 0);
+
+        let record = registry.get_command(unacked.id).await.unwrap().unwrap();
+        assert_eq!(record.result, Some(CommandResult::TimedOut));
+        let record = registry.get_command(acked.id).await.unwrap().unwrap();
+        assert_eq!(record.result, Some(result));
+        assert_eq!(registry.list_commands(device_id).await.unwrap().len(), 2);
+
+        // a late acknowledgement from the device replaces the timeout
+        assert!(
+            registry
+                .complete_command(unacked.id, CommandResult::Failed, later)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !registry
+                .complete_command(unacked.id, CommandResult::TimedOut, later)
+                .await
+                .unwrap()
+        );
+        let record = registry.get_command(unacked.id).await.unwrap().unwrap();
+        assert_eq!(record.result, Some(CommandResult::Failed));
+    }
+
     #[tokio::test]
     async fn test_in_memory_filtering_and_sorting() {
         let registry = device_registry();
@@ -1,7 +1,10 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
-use ersha_core::{DeviceId, DeviceStatus, StatusId};
+use ersha_core::{DeviceId, DeviceStatus, OrganizationId, StatusId};
 use tokio::sync::RwLock;
 
 use crate::registry::{
@@ -9,17 +12,37 @@ use crate::registry::{
     filter::{DeviceStatusFilter, DeviceStatusSortBy, Pagination, QueryOptions, SortOrder},
 };
 
-use super::InMemoryError;
+use super::{InMemoryDeviceRegistry, InMemoryError};
 
 #[derive(Clone)]
 pub struct InMemoryDeviceStatusRegistry {
     statuses: Arc<RwLock<HashMap<StatusId, DeviceStatus>>>,
+    devices: Option<InMemoryDeviceRegistry>,
 }
 
 impl InMemoryDeviceStatusRegistry {
     pub fn new() -> Self {
         Self {
             statuses: Arc::new(RwLock::new(HashMap::new())),
+            devices: None,
+        }
+    }
+
+    /// Resolve organization filters against `devices`. Without it, filtering
+    /// by organization matches no statuses.
+    pub fn with_devices(mut self, devices: InMemoryDeviceRegistry) -> Self {
+        self.devices = Some(devices);
+        self
+    }
+
+    async fn owned_devices(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Option<HashSet<DeviceId>> {
+        let organization = organization?;
+        match &self.devices {
+            Some(devices) => Some(devices.owned_by(organization).await),
+            None => Some(HashSet::new()),
         }
     }
 }
@@ -55,17 +78,20 @@ impl DeviceStatusRegistry for InMemoryDeviceStatusRegistry {
     }
 
     async fn batch_store(&self, statuses: Vec<DeviceStatus>) -> Result<(), Self::Error> {
+        let mut stored = self.statuses.write().await;
         for status in statuses {
-            self.store(status).await?;
+            stored.entry(status.id).or_insert(status);
         }
         Ok(())
     }
 
     async fn count(&self, filter: Option<DeviceStatusFilter>) -> Result<usize, Self::Error> {
-        let statuses = self.statuses.read().await;
         if let Some(filter) = filter {
-            return Ok(filter_statuses(&statuses, &filter).count());
+            let owned = self.owned_devices(filter.organization).await;
+            let statuses = self.statuses.read().await;
+            return Ok(filter_statuses(&statuses, &filter, owned.as_ref()).count());
         }
+        let statuses = self.statuses.read().await;
         Ok(statuses.len())
     }
 
@@ -73,8 +99,10 @@ impl DeviceStatusRegistry for InMemoryDeviceStatusRegistry {
         &self,
         options: QueryOptions<DeviceStatusFilter, DeviceStatusSortBy>,
     ) -> Result<Vec<DeviceStatus>, Self::Error> {
+        let owned = self.owned_devices(options.filter.organization).await;
         let statuses = self.statuses.read().await;
-        let filtered: Vec<&DeviceStatus> = filter_statuses(&statuses, &options.filter).collect();
+        let filtered: Vec<&DeviceStatus> =
+            filter_statuses(&statuses, &options.filter, owned.as_ref()).collect();
         let sorted = sort_statuses(filtered, &options.sort_by, &options.sort_order);
         let paginated = paginate_statuses(sorted, &options.pagination);
         Ok(paginated)
@@ -84,8 +112,15 @@ impl DeviceStatusRegistry for InMemoryDeviceStatusRegistry {
 fn filter_statuses<'a>(
     statuses: &'a HashMap<StatusId, DeviceStatus>,
     filter: &DeviceStatusFilter,
+    owned: Option<&HashSet<DeviceId>>,
 ) -> impl Iterator<Item = &'a DeviceStatus> {
-    statuses.values().filter(|status| {
+    statuses.values().filter(move |status| {
+        if let Some(owned) = owned
+            && !owned.contains(&status.device_id)
+        {
+            return false;
+        }
+
         if let Some(ids) = &filter.ids
             && !ids.contains(&status.id)
         {
@@ -218,6 +253,7 @@ mod tests {
             errors: vec![].into_boxed_slice(),
             timestamp: Timestamp::now(),
             sensor_statuses: vec![].into_boxed_slice(),
+            firmware: None,
         }
     }
 
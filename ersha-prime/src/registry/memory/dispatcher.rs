@@ -66,6 +66,36 @@ impl DispatcherRegistry for InMemoryDispatcherRegistry {
         Ok(())
     }
 
+    async fn reactivate(&self, id: DispatcherId) -> Result<(), Self::Error> {
+        let dispatcher = self.get(id).await?.ok_or(InMemoryError::NotFound)?;
+
+        self.update(
+            id,
+            Dispatcher {
+                state: DispatcherState::Active,
+                ..dispatcher
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn retire(&self, id: DispatcherId) -> Result<(), Self::Error> {
+        let dispatcher = self.get(id).await?.ok_or(InMemoryError::NotFound)?;
+
+        self.update(
+            id,
+            Dispatcher {
+                state: DispatcherState::Retired,
+                ..dispatcher
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
     async fn batch_register(&self, dispatchers: Vec<Dispatcher>) -> Result<(), Self::Error> {
         for dispatcher in dispatchers {
             self.register(dispatcher).await?;
@@ -160,6 +190,10 @@ fn filter_dispatchers<'a>(
             return false;
         }
 
+        if filter.organization.is_some() && filter.organization != dispatcher.organization {
+            return false;
+        }
+
         true
     })
 }
@@ -187,6 +221,7 @@ mod tests {
             state,
             location: H3Cell(0x1337deadbeef),
             provisioned_at,
+            organization: None,
         }
     }
 
@@ -0,0 +1,331 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use ersha_core::{DeviceId, Farm, FarmId, Field, FieldId, OrganizationId, Plot, PlotId};
+use tokio::sync::RwLock;
+
+use crate::registry::FarmRegistry;
+
+use super::InMemoryError;
+
+#[derive(Clone)]
+pub struct InMemoryFarmRegistry {
+    farms: Arc<RwLock<HashMap<FarmId, Farm>>>,
+    fields: Arc<RwLock<HashMap<FieldId, Field>>>,
+    plots: Arc<RwLock<HashMap<PlotId, Plot>>>,
+    assignments: Arc<RwLock<HashMap<DeviceId, PlotId>>>,
+}
+
+impl InMemoryFarmRegistry {
+    pub fn new() -> Self {
+        Self {
+            farms: Arc::new(RwLock::new(HashMap::new())),
+            fields: Arc::new(RwLock::new(HashMap::new())),
+            plots: Arc::new(RwLock::new(HashMap::new())),
+            assignments: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Ids of the devices assigned to plots of `field_id`.
+    pub(super) async fn field_devices(&self, field_id: FieldId) -> HashSet<DeviceId> {
+        let plots = self.plots.read().await;
+        let assignments = self.assignments.read().await;
+        assignments
+            .iter()
+            .filter(|(_, plot_id)| plots.get(plot_id).is_some_and(|p| p.field_id == field_id))
+            .map(|(device_id, _)| *device_id)
+            .collect()
+    }
+
+    async fn remove_plots(&self, plot_ids: &HashSet<PlotId>) {
+        self.plots
+            .write()
+            .await
+            .retain(|id, _| !plot_ids.contains(id));
+        self.assignments
+            .write()
+            .await
+            .retain(|_, plot_id| !plot_ids.contains(plot_id));
+    }
+}
+
+impl Default for InMemoryFarmRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl FarmRegistry for InMemoryFarmRegistry {
+    type Error = InMemoryError;
+
+    async fn add_farm(&self, farm: Farm) -> Result<(), Self::Error> {
+        let mut farms = self.farms.write().await;
+        if farms.contains_key(&farm.id) {
+            return Err(InMemoryError::AlreadyExists);
+        }
+        farms.insert(farm.id, farm);
+        Ok(())
+    }
+
+    async fn get_farm(&self, id: FarmId) -> Result<Option<Farm>, Self::Error> {
+        let farms = self.farms.read().await;
+        Ok(farms.get(&id).cloned())
+    }
+
+    async fn list_farms(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<Farm>, Self::Error> {
+        let farms = self.farms.read().await;
+        let mut list: Vec<Farm> = farms
+            .values()
+            .filter(|farm| organization.is_none() || farm.organization == organization)
+            .cloned()
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(list)
+    }
+
+    async fn update_farm(&self, farm: Farm) -> Result<(), Self::Error> {
+        let mut farms = self.farms.write().await;
+        let existing = farms.get_mut(&farm.id).ok_or(InMemoryError::NotFound)?;
+        *existing = farm;
+        Ok(())
+    }
+
+    async fn remove_farm(&self, id: FarmId) -> Result<(), Self::Error> {
+        if self.farms.write().await.remove(&id).is_none() {
+            return Err(InMemoryError::NotFound);
+        }
+
+        let field_ids: HashSet<FieldId> = {
+            let mut fields = self.fields.write().await;
+            let ids = fields
+                .values()
+                .filter(|field| field.farm_id == id)
+                .map(|field| field.id)
+                .collect();
+            fields.retain(|_, field| field.farm_id != id);
+            ids
+        };
+        let plot_ids: HashSet<PlotId> = {
+            let plots = self.plots.read().await;
+            plots
+                .values()
+                .filter(|plot| field_ids.contains(&plot.field_id))
+                .map(|plot| plot.id)
+                .collect()
+        };
+        self.remove_plots(&plot_ids).await;
+        Ok(())
+    }
+
+    async fn add_field(&self, field: Field) -> Result<(), Self::Error> {
+        let mut fields = self.fields.write().await;
+        if fields.contains_key(&field.id) {
+            return Err(InMemoryError::AlreadyExists);
+        }
+        fields.insert(field.id, field);
+        Ok(())
+    }
+
+    async fn get_field(&self, id: FieldId) -> Result<Option<Field>, Self::Error> {
+        let fields = self.fields.read().await;
+        Ok(fields.get(&id).cloned())
+    }
+
+    async fn list_fields(&self, farm_id: FarmId) -> Result<Vec<Field>, Self::Error> {
+        let fields = self.fields.read().await;
+        let mut list: Vec<Field> = fields
+            .values()
+            .filter(|field| field.farm_id == farm_id)
+            .cloned()
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(list)
+    }
+
+    async fn update_field(&self, field: Field) -> Result<(), Self::Error> {
+        let mut fields = self.fields.write().await;
+        let existing = fields.get_mut(&field.id).ok_or(InMemoryError::NotFound)?;
+        *existing = field;
+        Ok(())
+    }
+
+    async fn remove_field(&self, id: FieldId) -> Result<(), Self::Error> {
+        if self.fields.write().await.remove(&id).is_none() {
+            return Err(InMemoryError::NotFound);
+        }
+
+        let plot_ids: HashSet<PlotId> = {
+            let plots = self.plots.read().await;
+            plots
+                .values()
+                .filter(|plot| plot.field_id == id)
+                .map(|plot| plot.id)
+                .collect()
+        };
+        self.remove_plots(&plot_ids).await;
+        Ok(())
+    }
+
+    async fn add_plot(&self, plot: Plot) -> Result<(), Self::Error> {
+        let mut plots = self.plots.write().await;
+        if plots.contains_key(&plot.id) {
+            return Err(InMemoryError::AlreadyExists);
+        }
+        plots.insert(plot.id, plot);
+        Ok(())
+    }
+
+    async fn get_plot(&self, id: PlotId) -> Result<Option<Plot>, Self::Error> {
+        let plots = self.plots.read().await;
+        Ok(plots.get(&id).cloned())
+    }
+
+    async fn list_plots(&self, field_id: FieldId) -> Result<Vec<Plot>, Self::Error> {
+        let plots = self.plots.read().await;
+        let mut list: Vec<Plot> = plots
+            .values()
+            .filter(|plot| plot.field_id == field_id)
+            .cloned()
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(list)
+    }
+
+    async fn update_plot(&self, plot: Plot) -> Result<(), Self::Error> {
+        let mut plots = self.plots.write().await;
+        let existing = plots.get_mut(&plot.id).ok_or(InMemoryError::NotFound)?;
+        *existing = plot;
+        Ok(())
+    }
+
+    async fn remove_plot(&self, id: PlotId) -> Result<(), Self::Error> {
+        if !self.plots.read().await.contains_key(&id) {
+            return Err(InMemoryError::NotFound);
+        }
+        self.remove_plots(&HashSet::from([id])).await;
+        Ok(())
+    }
+
+    async fn assign_device(&self, device_id: DeviceId, plot_id: PlotId) -> Result<(), Self::Error> {
+        if !self.plots.read().await.contains_key(&plot_id) {
+            return Err(InMemoryError::NotFound);
+        }
+        self.assignments.write().await.insert(device_id, plot_id);
+        Ok(())
+    }
+
+    async fn unassign_device(&self, device_id: DeviceId) -> Result<bool, Self::Error> {
+        let mut assignments = self.assignments.write().await;
+        Ok(assignments.remove(&device_id).is_some())
+    }
+
+    async fn plot_devices(&self, plot_id: PlotId) -> Result<Vec<DeviceId>, Self::Error> {
+        let assignments = self.assignments.read().await;
+        let mut devices: Vec<DeviceId> = assignments
+            .iter()
+            .filter(|(_, id)| **id == plot_id)
+            .map(|(device_id, _)| *device_id)
+            .collect();
+        devices.sort_by_key(|id| id.0);
+        Ok(devices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{Boundary, DeviceId, Farm, FarmId, Field, FieldId, Plot, PlotId};
+    use ulid::Ulid;
+
+    use crate::registry::FarmRegistry;
+
+    use super::InMemoryFarmRegistry;
+
+    fn boundary() -> Boundary {
+        Boundary {
+            ring: Box::new([]),
+            cells: Box::new([]),
+        }
+    }
+
+    fn farm(name: &str) -> Farm {
+        Farm {
+            id: FarmId(Ulid::new()),
+            name: name.into(),
+            boundary: boundary(),
+            organization: None,
+            created_at: jiff::Timestamp::now(),
+        }
+    }
+
+    fn field(farm_id: FarmId, name: &str) -> Field {
+        Field {
+            id: FieldId(Ulid::new()),
+            farm_id,
+            name: name.into(),
+            boundary: boundary(),
+            crop: Some("maize".into()),
+            planted_on: Some(jiff::civil::date(2025, 3, 15)),
+            created_at: jiff::Timestamp::now(),
+        }
+    }
+
+    fn plot(field_id: FieldId, name: &str) -> Plot {
+        Plot {
+            id: PlotId(Ulid::new()),
+            field_id,
+            name: name.into(),
+            boundary: boundary(),
+            crop: None,
+            planted_on: None,
+            created_at: jiff::Timestamp::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_assignments_follow_the_hierarchy() {
+        let registry = InMemoryFarmRegistry::new();
+        let farm = farm("Kilimo");
+        let north = field(farm.id, "North");
+        let south = field(farm.id, "South");
+        let a = plot(north.id, "A");
+        let b = plot(south.id, "B");
+        registry.add_farm(farm.clone()).await.unwrap();
+        for field in [&north, &south] {
+            registry.add_field(field.clone()).await.unwrap();
+        }
+        for plot in [&a, &b] {
+            registry.add_plot(plot.clone()).await.unwrap();
+        }
+
+        let device = DeviceId(Ulid::new());
+        registry.assign_device(device, a.id).await.unwrap();
+        assert_eq!(registry.field_devices(north.id).await.len(), 1);
+
+        // reassigning moves the device
+        registry.assign_device(device, b.id).await.unwrap();
+        assert!(registry.field_devices(north.id).await.is_empty());
+        assert_eq!(registry.plot_devices(b.id).await.unwrap(), vec![device]);
+
+        assert!(
+            registry
+                .assign_device(device, PlotId(Ulid::new()))
+                .await
+                .is_err()
+        );
+
+        registry.remove_field(south.id).await.unwrap();
+        assert!(registry.get_plot(b.id).await.unwrap().is_none());
+        assert!(!registry.unassign_device(device).await.unwrap());
+
+        registry.remove_farm(farm.id).await.unwrap();
+        assert!(registry.list_fields(farm.id).await.unwrap().is_empty());
+        assert!(registry.get_plot(a.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use ersha_core::{DeviceId, DispatcherId, OrganizationId};
+use tokio::sync::RwLock;
+
+use crate::{
+    health::{
+        AlertSubject, ConnectionChange, ConnectionEvent, DeviceHealth, DispatcherHealth,
+        DispatcherStatusRecord, OfflineAlert, ReportedAlert,
+    },
+    registry::HealthRegistry,
+};
+
+use super::InMemoryError;
+
+#[derive(Default)]
+struct Health {
+    statuses: Vec<DispatcherStatusRecord>,
+    dispatchers: HashMap<DispatcherId, DispatcherHealth>,
+    devices: HashMap<DeviceId, DeviceHealth>,
+    connections: Vec<ConnectionEvent>,
+    alerts: Vec<OfflineAlert>,
+    reported_alerts: Vec<ReportedAlert>,
+}
+
+#[derive(Clone)]
+pub struct InMemoryHealthRegistry {
+    health: Arc<RwLock<Health>>,
+}
+
+impl InMemoryHealthRegistry {
+    pub fn new() -> Self {
+        Self {
+            health: Arc::new(RwLock::new(Health::default())),
+        }
+    }
+}
+
+impl Default for InMemoryHealthRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl HealthRegistry for InMemoryHealthRegistry {
+    type Error = InMemoryError;
+
+    async fn record_status(&self, record: DispatcherStatusRecord) -> Result<(), Self::Error> {
+        let mut health = self.health.write().await;
+        health.dispatchers.insert(
+            record.dispatcher_id,
+            DispatcherHealth {
+                dispatcher_id: record.dispatcher_id,
+                last_seen: record.received_at,
+                online: true,
+            },
+        );
+        health.statuses.push(record);
+        Ok(())
+    }
+
+    async fn list_statuses(
+        &self,
+        dispatcher_id: DispatcherId,
+        limit: usize,
+    ) -> Result<Vec<DispatcherStatusRecord>, Self::Error> {
+        let health = self.health.read().await;
+        Ok(health
+            .statuses
+            .iter()
+            .rev()
+            .filter(|status| status.dispatcher_id == dispatcher_id)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn dispatcher_health(
+        &self,
+        id: DispatcherId,
+    ) -> Result<Option<DispatcherHealth>, Self::Error> {
+        let health = self.health.read().await;
+        Ok(health.dispatchers.get(&id).copied())
+    }
+
+    async fn record_seen(
+        &self,
+        dispatcher_id: DispatcherId,
+        devices: Vec<DeviceId>,
+        at: jiff::Timestamp,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error> {
+        let mut health = self.health.write().await;
+        let mut events = Vec::new();
+
+        for device_id in devices {
+            let previous = health.devices.insert(
+                device_id,
+                DeviceHealth {
+                    device_id,
+                    dispatcher_id,
+                    last_seen: at,
+                    online: true,
+                },
+            );
+            if previous.is_none_or(|device| !device.online) {
+                events.push(ConnectionEvent {
+                    device_id,
+                    dispatcher_id,
+                    change: ConnectionChange::Connected,
+                    timestamp: at,
+                });
+            }
+        }
+
+        health.connections.extend(events.iter().cloned());
+        Ok(events)
+    }
+
+    async fn record_disconnection(&self, event: ConnectionEvent) -> Result<(), Self::Error> {
+        let mut health = self.health.write().await;
+        if let Some(device) = health.devices.get_mut(&event.device_id) {
+            device.online = false;
+        }
+        health.connections.push(event);
+        Ok(())
+    }
+
+    async fn list_connections(
+        &self,
+        device_id: DeviceId,
+        limit: usize,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error> {
+        let health = self.health.read().await;
+        Ok(health
+            .connections
+            .iter()
+            .rev()
+            .filter(|event| event.device_id == device_id)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn device_health(&self, id: DeviceId) -> Result<Option<DeviceHealth>, Self::Error> {
+        let health = self.health.read().await;
+        Ok(health.devices.get(&id).copied())
+    }
+
+    async fn silent_dispatchers(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DispatcherHealth>, Self::Error> {
+        let health = self.health.read().await;
+        Ok(health
+            .dispatchers
+            .values()
+            .filter(|dispatcher| dispatcher.online && dispatcher.last_seen < cutoff)
+            .copied()
+            .collect())
+    }
+
+    async fn silent_devices(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DeviceHealth>, Self::Error> {
+        let health = self.health.read().await;
+        Ok(health
+            .devices
+            .values()
+            .filter(|device| device.online && device.last_seen < cutoff)
+            .copied()
+            .collect())
+    }
+
+    async fn raise_alert(&self, alert: OfflineAlert) -> Result<(), Self::Error> {
+        let mut health = self.health.write().await;
+        match alert.device_id {
+            Some(device_id) => {
+                if let Some(device) = health.devices.get_mut(&device_id) {
+                    device.online = false;
+                }
+            }
+            None => {
+                if let Some(dispatcher) = health.dispatchers.get_mut(&alert.dispatcher_id) {
+                    dispatcher.online = false;
+                }
+            }
+        }
+        health.alerts.push(alert);
+        Ok(())
+    }
+
+    async fn list_alerts(
+        &self,
+        subject: AlertSubject,
+        limit: usize,
+    ) -> Result<Vec<OfflineAlert>, Self::Error> {
+        let health = self.health.read().await;
+        Ok(health
+            .alerts
+            .iter()
+            .rev()
+            .filter(|alert| match subject {
+                AlertSubject::Dispatcher(id) => alert.dispatcher_id == id,
+                AlertSubject::Device(id) => alert.device_id == Some(id),
+            })
+            .take(limit)
+            .copied()
+            .collect())
+    }
+
+    async fn record_reported_alert(&self, alert: ReportedAlert) -> Result<(), Self::Error> {
+        let mut health = self.health.write().await;
+        if !health.reported_alerts.iter().any(|a| a.id == alert.id) {
+            health.reported_alerts.push(alert);
+        }
+        Ok(())
+    }
+
+    async fn list_reported_alerts(
+        &self,
+        subject: AlertSubject,
+        organization: Option<OrganizationId>,
+        limit: usize,
+    ) -> Result<Vec<ReportedAlert>, Self::Error> {
+        let health = self.health.read().await;
+        let mut alerts: Vec<ReportedAlert> = health
+            .reported_alerts
+            .iter()
+            .filter(|alert| match subject {
+                AlertSubject::Dispatcher(id) => alert.dispatcher_id == id,
+                AlertSubject::Device(id) => alert.device_id == Some(id),
+            })
+            .filter(|alert| organization.is_none() || alert.organization == organization)
+            .cloned()
+            .collect();
+        alerts.sort_by(|a, b| b.raised_at.cmp(&a.raised_at));
+        alerts.truncate(limit);
+        Ok(alerts)
+    }
+}
@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+use ersha_core::{DeviceStatus, SensorReading};
+
+use crate::registry::{DeviceStatusRegistry, IngestRegistry, ReadingRegistry};
+
+use super::{InMemoryDeviceStatusRegistry, InMemoryError, InMemoryReadingRegistry};
+
+/// Stores batches into the given registries. Storing in memory can't fail
+/// halfway, so the two stores need no transaction.
+#[derive(Clone)]
+pub struct InMemoryIngestRegistry {
+    readings: InMemoryReadingRegistry,
+    statuses: InMemoryDeviceStatusRegistry,
+}
+
+impl InMemoryIngestRegistry {
+    pub fn new(readings: InMemoryReadingRegistry, statuses: InMemoryDeviceStatusRegistry) -> Self {
+        Self { readings, statuses }
+    }
+}
+
+#[async_trait]
+impl IngestRegistry for InMemoryIngestRegistry {
+    type Error = InMemoryError;
+
+    async fn store_batch(
+        &self,
+        readings: Vec<SensorReading>,
+        statuses: Vec<DeviceStatus>,
+    ) -> Result<(), Self::Error> {
+        self.readings.batch_store(readings).await?;
+        self.statuses.batch_store(statuses).await?;
+        Ok(())
+    }
+}
@@ -1,15 +1,29 @@
+mod api_key;
+mod batch;
 mod device;
 mod device_status;
 mod dispatcher;
+mod farm;
+mod health;
+mod ingest;
+mod organization;
 mod reading;
 
+pub use api_key::InMemoryApiKeyRegistry;
+pub use batch::InMemoryBatchRegistry;
 pub use device::InMemoryDeviceRegistry;
 pub use device_status::InMemoryDeviceStatusRegistry;
 pub use dispatcher::InMemoryDispatcherRegistry;
+pub use farm::InMemoryFarmRegistry;
+pub use health::InMemoryHealthRegistry;
+pub use ingest::InMemoryIngestRegistry;
+pub use organization::InMemoryOrganizationRegistry;
 pub use reading::InMemoryReadingRegistry;
 
 #[derive(Debug, thiserror::Error)]
 pub enum InMemoryError {
     #[error("not found")]
     NotFound,
+    #[error("already exists")]
+    AlreadyExists,
 }
@@ -0,0 +1,57 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use ersha_core::{Organization, OrganizationId};
+use tokio::sync::RwLock;
+
+use crate::registry::OrganizationRegistry;
+
+use super::InMemoryError;
+
+#[derive(Clone)]
+pub struct InMemoryOrganizationRegistry {
+    organizations: Arc<RwLock<HashMap<OrganizationId, Organization>>>,
+}
+
+impl InMemoryOrganizationRegistry {
+    pub fn new() -> Self {
+        Self {
+            organizations: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+impl Default for InMemoryOrganizationRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl OrganizationRegistry for InMemoryOrganizationRegistry {
+    type Error = InMemoryError;
+
+    async fn add(&self, organization: Organization) -> Result<(), Self::Error> {
+        let mut organizations = self.organizations.write().await;
+        if organizations
+            .values()
+            .any(|o| o.id == organization.id || o.name == organization.name)
+        {
+            return Err(InMemoryError::AlreadyExists);
+        }
+        organizations.insert(organization.id, organization);
+        Ok(())
+    }
+
+    async fn get(&self, id: OrganizationId) -> Result<Option<Organization>, Self::Error> {
+        let organizations = self.organizations.read().await;
+        Ok(organizations.get(&id).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<Organization>, Self::Error> {
+        let organizations = self.organizations.read().await;
+        let mut list: Vec<Organization> = organizations.values().cloned().collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(list)
+    }
+}
@@ -1,25 +1,66 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
-use ersha_core::{ReadingId, SensorMetric, SensorReading};
+use ersha_core::{DeviceId, ReadingId, SensorReading};
 use tokio::sync::RwLock;
 
-use crate::registry::{
-    ReadingRegistry,
-    filter::{Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SensorMetricType, SortOrder},
+use crate::{
+    geo,
+    registry::{
+        ReadingRegistry,
+        filter::{
+            Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SensorMetricType, SortOrder,
+        },
+        rollup::{CellRollup, RollupAccumulator},
+    },
 };
 
-use super::InMemoryError;
+use super::{InMemoryDeviceRegistry, InMemoryError};
 
 #[derive(Clone)]
 pub struct InMemoryReadingRegistry {
     readings: Arc<RwLock<HashMap<ReadingId, SensorReading>>>,
+    devices: Option<InMemoryDeviceRegistry>,
 }
 
 impl InMemoryReadingRegistry {
     pub fn new() -> Self {
         Self {
             readings: Arc::new(RwLock::new(HashMap::new())),
+            devices: None,
+        }
+    }
+
+    /// Resolve organization and field filters against `devices`. Without
+    /// it, filtering by either matches no readings.
+    pub fn with_devices(mut self, devices: InMemoryDeviceRegistry) -> Self {
+        self.devices = Some(devices);
+        self
+    }
+
+    /// Devices the organization and field filters allow, if any is set.
+    async fn allowed_devices(&self, filter: &ReadingFilter) -> Option<HashSet<DeviceId>> {
+        if filter.organization.is_none() && filter.field.is_none() {
+            return None;
+        }
+        let Some(devices) = &self.devices else {
+            return Some(HashSet::new());
+        };
+
+        let owned = match filter.organization {
+            Some(organization) => Some(devices.owned_by(organization).await),
+            None => None,
+        };
+        let in_field = match filter.field {
+            Some(field) => Some(devices.in_field(field).await),
+            None => None,
+        };
+        match (owned, in_field) {
+            (Some(owned), Some(in_field)) => Some(&owned & &in_field),
+            (owned, in_field) => owned.or(in_field),
         }
     }
 }
@@ -46,17 +87,20 @@ impl ReadingRegistry for InMemoryReadingRegistry {
     }
 
     async fn batch_store(&self, readings: Vec<SensorReading>) -> Result<(), Self::Error> {
+        let mut stored = self.readings.write().await;
         for reading in readings {
-            self.store(reading).await?;
+            stored.entry(reading.id).or_insert(reading);
         }
         Ok(())
     }
 
     async fn count(&self, filter: Option<ReadingFilter>) -> Result<usize, Self::Error> {
-        let readings = self.readings.read().await;
         if let Some(filter) = filter {
-            return Ok(filter_readings(&readings, &filter).count());
+            let allowed = self.allowed_devices(&filter).await;
+            let readings = self.readings.read().await;
+            return Ok(filter_readings(&readings, &filter, allowed.as_ref()).count());
         }
+        let readings = self.readings.read().await;
         Ok(readings.len())
     }
 
@@ -64,29 +108,43 @@ impl ReadingRegistry for InMemoryReadingRegistry {
         &self,
         options: QueryOptions<ReadingFilter, ReadingSortBy>,
     ) -> Result<Vec<SensorReading>, Self::Error> {
+        let allowed = self.allowed_devices(&options.filter).await;
         let readings = self.readings.read().await;
-        let filtered: Vec<&SensorReading> = filter_readings(&readings, &options.filter).collect();
+        let filtered: Vec<&SensorReading> =
+            filter_readings(&readings, &options.filter, allowed.as_ref()).collect();
         let sorted = sort_readings(filtered, &options.sort_by, &options.sort_order);
         let paginated = paginate_readings(sorted, &options.pagination);
         Ok(paginated)
     }
-}
 
-fn metric_type(metric: &SensorMetric) -> SensorMetricType {
-    match metric {
-        SensorMetric::SoilMoisture { .. } => SensorMetricType::SoilMoisture,
-        SensorMetric::SoilTemp { .. } => SensorMetricType::SoilTemp,
-        SensorMetric::AirTemp { .. } => SensorMetricType::AirTemp,
-        SensorMetric::Humidity { .. } => SensorMetricType::Humidity,
-        SensorMetric::Rainfall { .. } => SensorMetricType::Rainfall,
+    async fn rollup(
+        &self,
+        resolution: u8,
+        filter: ReadingFilter,
+    ) -> Result<Vec<CellRollup>, Self::Error> {
+        let allowed = self.allowed_devices(&filter).await;
+        let readings = self.readings.read().await;
+        let mut accumulator = RollupAccumulator::new(resolution);
+        for reading in filter_readings(&readings, &filter, allowed.as_ref()) {
+            accumulator.add(reading);
+        }
+        Ok(accumulator.finish())
     }
 }
 
 fn filter_readings<'a>(
     readings: &'a HashMap<ReadingId, SensorReading>,
     filter: &ReadingFilter,
+    allowed: Option<&HashSet<DeviceId>>,
 ) -> impl Iterator<Item = &'a SensorReading> {
-    readings.values().filter(|reading| {
+    let spatial = geo::SpatialMatcher::new(filter.ancestor, filter.ring, filter.bbox);
+    readings.values().filter(move |reading| {
+        if let Some(allowed) = allowed
+            && !allowed.contains(&reading.device_id)
+        {
+            return false;
+        }
+
         if let Some(ids) = &filter.ids
             && !ids.contains(&reading.id)
         {
@@ -112,7 +170,7 @@ fn filter_readings<'a>(
         }
 
         if let Some(metric_types) = &filter.metric_types
-            && !metric_types.contains(&metric_type(&reading.metric))
+            && !metric_types.contains(&SensorMetricType::from(&reading.metric))
         {
             return false;
         }
@@ -123,6 +181,10 @@ fn filter_readings<'a>(
             return false;
         }
 
+        if !spatial.matches(reading.location) {
+            return false;
+        }
+
         if let Some(confidence_range) = &filter.confidence_range
             && !confidence_range.contains(&reading.confidence.0)
         {
@@ -203,13 +265,16 @@ mod tests {
     use ordered_float::NotNan;
     use ulid::Ulid;
 
-    use crate::registry::ReadingRegistry;
+    use crate::geo;
     use crate::registry::filter::{
         Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SensorMetricType, SortOrder,
     };
+    use crate::registry::{
+        DeviceRegistry, ReadingRegistry, memory::InMemoryDeviceRegistry, rollup::CellRollup,
+    };
     use ersha_core::{
-        DeviceId, DispatcherId, H3Cell, Percentage, ReadingId, SensorId, SensorMetric,
-        SensorReading,
+        Device, DeviceId, DeviceKind, DeviceState, DispatcherId, H3Cell, OrganizationId,
+        Percentage, ReadingId, SensorId, SensorMetric, SensorReading,
     };
 
     use super::InMemoryReadingRegistry;
@@ -224,9 +289,64 @@ mod tests {
             confidence: Percentage(confidence),
             timestamp: Timestamp::now(),
             sensor_id: SensorId(Ulid::new()),
+            raw_value: None,
         }
     }
 
+    #[tokio::test]
+    async fn test_filter_by_organization() {
+        let devices = InMemoryDeviceRegistry::new();
+        let organization = OrganizationId(Ulid::new());
+        let metric = SensorMetric::AirTemp {
+            value: NotNan::new(25.0).unwrap(),
+        };
+
+        let owned = mock_reading(ReadingId(Ulid::new()), metric.clone(), 90);
+        let other = mock_reading(ReadingId(Ulid::new()), metric, 90);
+        devices
+            .register(Device {
+                id: owned.device_id,
+                kind: DeviceKind::Sensor,
+                state: DeviceState::Active,
+                location: owned.location,
+                manufacturer: None,
+                provisioned_at: Timestamp::now(),
+                sensors: vec![].into_boxed_slice(),
+                actuators: vec![].into_boxed_slice(),
+                organization: Some(organization),
+            })
+            .await
+            .unwrap();
+
+        let registry = InMemoryReadingRegistry::new().with_devices(devices);
+        registry
+            .batch_store(vec![owned.clone(), other])
+            .await
+            .unwrap();
+
+        let filter = ReadingFilter::builder().organization(organization).build();
+        let results = registry
+            .list(QueryOptions {
+                filter,
+                sort_by: ReadingSortBy::Timestamp,
+                sort_order: SortOrder::Asc,
+                pagination: Pagination::Offset {
+                    offset: 0,
+                    limit: 10,
+                },
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, owned.id);
+
+        // without devices to resolve ownership nothing matches
+        let unresolved = InMemoryReadingRegistry::new();
+        unresolved.store(owned).await.unwrap();
+        let filter = ReadingFilter::builder().organization(organization).build();
+        assert_eq!(unresolved.count(Some(filter)).await.unwrap(), 0);
+    }
+
     #[tokio::test]
     async fn test_store_and_get() {
         let registry = InMemoryReadingRegistry::new();
@@ -285,6 +405,40 @@ mod tests {
         assert_eq!(registry.count(Some(filter)).await.unwrap(), 2);
     }
 
+    #[tokio::test]
+    async fn test_batch_store_is_idempotent() {
+        let registry = InMemoryReadingRegistry::new();
+        let id = ReadingId(Ulid::new());
+        let original = mock_reading(
+            id,
+            SensorMetric::AirTemp {
+                value: NotNan::new(20.0).unwrap(),
+            },
+            80,
+        );
+        let mut resent = original.clone();
+        resent.confidence = Percentage(10);
+
+        registry.batch_store(vec![original.clone()]).await.unwrap();
+        registry
+            .batch_store(vec![
+                resent,
+                mock_reading(
+                    ReadingId(Ulid::new()),
+                    SensorMetric::Humidity {
+                        value: Percentage(60),
+                    },
+                    90,
+                ),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(registry.count(None).await.unwrap(), 2);
+        let stored = registry.get(id).await.unwrap().unwrap();
+        assert_eq!(stored.confidence.0, 80);
+    }
+
     #[tokio::test]
     async fn test_list_with_sorting() {
         let registry = InMemoryReadingRegistry::new();
@@ -369,4 +523,62 @@ mod tests {
 
         assert_eq!(registry.count(Some(filter)).await.unwrap(), 1);
     }
+
+    #[tokio::test]
+    async fn test_rollup() {
+        let registry = InMemoryReadingRegistry::new();
+        let moisture = |value| SensorMetric::SoilMoisture {
+            value: Percentage(value),
+        };
+
+        let first = mock_reading(ReadingId(Ulid::new()), moisture(20), 90);
+        let mut second = mock_reading(ReadingId(Ulid::new()), moisture(40), 90);
+        second.device_id = first.device_id;
+        let third = mock_reading(ReadingId(Ulid::new()), moisture(60), 90);
+        let rainfall = mock_reading(
+            ReadingId(Ulid::new()),
+            SensorMetric::Rainfall {
+                value: NotNan::new(3.5).unwrap(),
+            },
+            90,
+        );
+        let mut old = mock_reading(ReadingId(Ulid::new()), moisture(100), 90);
+        old.timestamp = Timestamp::from_second(0).unwrap();
+        let district = geo::parent(first.location, 5).unwrap();
+        let mut coarse = mock_reading(ReadingId(Ulid::new()), moisture(0), 90);
+        coarse.location = geo::parent(first.location, 3).unwrap();
+
+        registry
+            .batch_store(vec![first, second, third, rainfall, old, coarse])
+            .await
+            .unwrap();
+
+        let filter = ReadingFilter::builder()
+            .timestamp_after(Timestamp::from_second(1).unwrap())
+            .build();
+        let rollups = registry.rollup(5, filter).await.unwrap();
+        assert_eq!(
+            rollups,
+            vec![
+                CellRollup {
+                    cell: district,
+                    metric_type: SensorMetricType::SoilMoisture,
+                    readings: 3,
+                    devices: 2,
+                    mean: 40.0,
+                    min: 20.0,
+                    max: 60.0,
+                },
+                CellRollup {
+                    cell: district,
+                    metric_type: SensorMetricType::Rainfall,
+                    readings: 1,
+                    devices: 1,
+                    mean: 3.5,
+                    min: 3.5,
+                    max: 3.5,
+                },
+            ]
+        );
+    }
 }
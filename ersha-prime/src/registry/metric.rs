@@ -0,0 +1,168 @@
+use ersha_core::{Percentage, SensorKind, SensorMetric};
+use ordered_float::NotNan;
+
+use super::filter::SensorMetricType;
+
+/// Decode a sensor kind stored as its discriminant, or return the unknown
+/// code.
+pub(crate) fn decode_sensor_kind(code: i32) -> Result<SensorKind, i32> {
+    Ok(match code {
+        0 => SensorKind::SoilMoisture,
+        1 => SensorKind::SoilTemp,
+        2 => SensorKind::AirTemp,
+        3 => SensorKind::Humidity,
+        4 => SensorKind::Rainfall,
+        5 => SensorKind::SoilEc,
+        6 => SensorKind::SoilPh,
+        7 => SensorKind::LeafWetness,
+        8 => SensorKind::WindSpeed,
+        9 => SensorKind::WindDirection,
+        10 => SensorKind::SolarRadiation,
+        11 => SensorKind::BarometricPressure,
+        12 => SensorKind::WaterLevel,
+        13 => SensorKind::FlowRate,
+        other => return Err(other),
+    })
+}
+
+/// `metric_type` column value of a metric type, shared by the SQL backends.
+pub(crate) fn metric_type_code(metric_type: SensorMetricType) -> i32 {
+    match metric_type {
+        SensorMetricType::SoilMoisture => 0,
+        SensorMetricType::SoilTemp => 1,
+        SensorMetricType::AirTemp => 2,
+        SensorMetricType::Humidity => 3,
+        SensorMetricType::Rainfall => 4,
+        SensorMetricType::SoilEc => 5,
+        SensorMetricType::SoilPh => 6,
+        SensorMetricType::LeafWetness => 7,
+        SensorMetricType::WindSpeed => 8,
+        SensorMetricType::WindDirection => 9,
+        SensorMetricType::SolarRadiation => 10,
+        SensorMetricType::BarometricPressure => 11,
+        SensorMetricType::WaterLevel => 12,
+        SensorMetricType::FlowRate => 13,
+    }
+}
+
+/// Decode a `metric_type` column value, or return the unknown code.
+pub(crate) fn decode_metric_type(code: i32) -> Result<SensorMetricType, i32> {
+    Ok(match code {
+        0 => SensorMetricType::SoilMoisture,
+        1 => SensorMetricType::SoilTemp,
+        2 => SensorMetricType::AirTemp,
+        3 => SensorMetricType::Humidity,
+        4 => SensorMetricType::Rainfall,
+        5 => SensorMetricType::SoilEc,
+        6 => SensorMetricType::SoilPh,
+        7 => SensorMetricType::LeafWetness,
+        8 => SensorMetricType::WindSpeed,
+        9 => SensorMetricType::WindDirection,
+        10 => SensorMetricType::SolarRadiation,
+        11 => SensorMetricType::BarometricPressure,
+        12 => SensorMetricType::WaterLevel,
+        13 => SensorMetricType::FlowRate,
+        other => return Err(other),
+    })
+}
+
+/// Split a metric into its `metric_type` and `metric_value` columns.
+pub(crate) fn disect_metric(metric: &SensorMetric) -> (i32, f64) {
+    (
+        metric_type_code(SensorMetricType::from(metric)),
+        metric.value(),
+    )
+}
+
+/// Rebuild a metric from its `metric_type` and `metric_value` columns, or
+/// return the unknown type code.
+pub(crate) fn decode_metric(metric_type: i32, value: f64) -> Result<SensorMetric, i32> {
+    let percentage = || Percentage(value as u8);
+    let number = || NotNan::new(value).expect("database should not contain NaN");
+
+    Ok(match metric_type {
+        0 => SensorMetric::SoilMoisture {
+            value: percentage(),
+        },
+        1 => SensorMetric::SoilTemp { value: number() },
+        2 => SensorMetric::AirTemp { value: number() },
+        3 => SensorMetric::Humidity {
+            value: percentage(),
+        },
+        4 => SensorMetric::Rainfall { value: number() },
+        5 => SensorMetric::SoilEc { value: number() },
+        6 => SensorMetric::SoilPh { value: number() },
+        7 => SensorMetric::LeafWetness {
+            value: percentage(),
+        },
+        8 => SensorMetric::WindSpeed { value: number() },
+        9 => SensorMetric::WindDirection { value: number() },
+        10 => SensorMetric::SolarRadiation { value: number() },
+        11 => SensorMetric::BarometricPressure { value: number() },
+        12 => SensorMetric::WaterLevel { value: number() },
+        13 => SensorMetric::FlowRate { value: number() },
+        other => return Err(other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{Percentage, SensorKind, SensorMetric};
+    use ordered_float::NotNan;
+
+    use super::{decode_metric, decode_metric_type, decode_sensor_kind, disect_metric};
+    use crate::registry::filter::SensorMetricType;
+
+    #[test]
+    fn round_trips_every_metric() {
+        let number = NotNan::new(12.5).unwrap();
+        let metrics = [
+            SensorMetric::SoilMoisture {
+                value: Percentage(40),
+            },
+            SensorMetric::SoilTemp { value: number },
+            SensorMetric::AirTemp { value: number },
+            SensorMetric::Humidity {
+                value: Percentage(65),
+            },
+            SensorMetric::Rainfall { value: number },
+            SensorMetric::SoilEc { value: number },
+            SensorMetric::SoilPh { value: number },
+            SensorMetric::LeafWetness {
+                value: Percentage(20),
+            },
+            SensorMetric::WindSpeed { value: number },
+            SensorMetric::WindDirection { value: number },
+            SensorMetric::SolarRadiation { value: number },
+            SensorMetric::BarometricPressure { value: number },
+            SensorMetric::WaterLevel { value: number },
+            SensorMetric::FlowRate { value: number },
+        ];
+
+        for (code, metric) in metrics.into_iter().enumerate() {
+            let (metric_type, value) = disect_metric(&metric);
+            assert_eq!(metric_type, code as i32);
+            assert_eq!(
+                decode_metric_type(metric_type),
+                Ok(SensorMetricType::from(&metric))
+            );
+            assert_eq!(decode_metric(metric_type, value), Ok(metric));
+        }
+        assert_eq!(decode_metric(14, 0.0), Err(14));
+        assert_eq!(decode_metric_type(14), Err(14));
+    }
+
+    #[test]
+    fn sensor_kinds_decode_from_their_discriminant() {
+        for kind in [
+            SensorKind::SoilMoisture,
+            SensorKind::Rainfall,
+            SensorKind::SoilEc,
+            SensorKind::FlowRate,
+        ] {
+            let code = kind.clone() as i32;
+            assert_eq!(decode_sensor_kind(code).map(|k| k as i32), Ok(code));
+        }
+        assert!(decode_sensor_kind(-1).is_err());
+    }
+}
@@ -1,17 +1,34 @@
+mod actuator;
+mod batch;
 pub mod clickhouse;
 pub mod filter;
+mod firmware;
+mod health;
 pub mod memory;
+mod metric;
+pub mod rollup;
 pub mod sqlite;
 
+use crate::{
+    auth::{ApiKey, ApiKeyId},
+    health::{
+        AlertSubject, ConnectionEvent, DeviceHealth, DispatcherHealth, DispatcherStatusRecord,
+        OfflineAlert, ReportedAlert,
+    },
+};
 use async_trait::async_trait;
 use ersha_core::{
-    Device, DeviceId, DeviceStatus, Dispatcher, DispatcherId, ReadingId, Sensor, SensorReading,
-    StatusId,
+    ActuatorCommand, BatchId, BatchUploadResponse, Calibration, CalibrationProfile, CommandId,
+    CommandRecord, CommandResult, Device, DeviceCredential, DeviceFirmware, DeviceId, DeviceKey,
+    DeviceStatus, Dispatcher, DispatcherId, Farm, FarmId, Field, FieldId, FirmwareId,
+    FirmwareImage, Organization, OrganizationId, Plot, PlotId, ReadingId, Sensor, SensorId,
+    SensorReading, StatusId,
 };
 use filter::{
     DeviceFilter, DeviceSortBy, DeviceStatusFilter, DeviceStatusSortBy, DispatcherFilter,
     DispatcherSortBy, QueryOptions, ReadingFilter, ReadingSortBy,
 };
+use rollup::CellRollup;
 
 #[async_trait]
 pub trait DeviceRegistry: Clone + Send + Sync + 'static {
@@ -21,6 +38,10 @@ pub trait DeviceRegistry: Clone + Send + Sync + 'static {
     async fn get(&self, id: DeviceId) -> Result<Option<Device>, Self::Error>;
     async fn update(&self, id: DeviceId, new: Device) -> Result<(), Self::Error>;
     async fn suspend(&self, id: DeviceId) -> Result<(), Self::Error>;
+    /// Return a suspended device to active.
+    async fn reactivate(&self, id: DeviceId) -> Result<(), Self::Error>;
+    /// Decommission a device for good, keeping its readings and statuses.
+    async fn retire(&self, id: DeviceId) -> Result<(), Self::Error>;
 
     async fn add_sensor(&self, id: DeviceId, sensor: Sensor) -> Result<(), Self::Error>;
     async fn add_sensors(
@@ -28,12 +49,82 @@ pub trait DeviceRegistry: Clone + Send + Sync + 'static {
         id: DeviceId,
         sensors: impl Iterator<Item = Sensor> + Send,
     ) -> Result<(), Self::Error>;
+    /// Detach a sensor from a device, keeping its readings. Returns whether
+    /// the device had it.
+    async fn remove_sensor(&self, id: DeviceId, sensor_id: SensorId) -> Result<bool, Self::Error>;
     async fn batch_register(&self, devices: Vec<Device>) -> Result<(), Self::Error>;
     async fn count(&self, filter: Option<DeviceFilter>) -> Result<usize, Self::Error>;
     async fn list(
         &self,
         options: QueryOptions<DeviceFilter, DeviceSortBy>,
     ) -> Result<Vec<Device>, Self::Error>;
+
+    /// Set the calibration of a registered sensor, or clear it with `None`.
+    async fn set_calibration(
+        &self,
+        sensor_id: SensorId,
+        calibration: Option<Calibration>,
+    ) -> Result<(), Self::Error>;
+    async fn get_calibration(
+        &self,
+        sensor_id: SensorId,
+    ) -> Result<Option<CalibrationProfile>, Self::Error>;
+    /// Calibrations of sensors on devices owned by `organization`, or on
+    /// devices without an owner for `None`.
+    async fn list_calibrations(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<CalibrationProfile>, Self::Error>;
+
+    /// Set the pre-shared key of a registered device, replacing any previous key.
+    async fn set_key(&self, id: DeviceId, key: DeviceKey) -> Result<(), Self::Error>;
+    /// Keys of devices owned by `organization`, or of devices without an
+    /// owner for `None`.
+    async fn list_keys(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<DeviceCredential>, Self::Error>;
+
+    /// Publish a firmware image. A model can only have one image per version.
+    async fn add_firmware(&self, image: FirmwareImage, data: Vec<u8>) -> Result<(), Self::Error>;
+    async fn list_firmware(&self) -> Result<Vec<FirmwareImage>, Self::Error>;
+    /// Read up to `len` bytes of an image from `offset`, or `None` if the
+    /// image is unknown.
+    async fn firmware_chunk(
+        &self,
+        id: FirmwareId,
+        offset: u32,
+        len: u32,
+    ) -> Result<Option<Vec<u8>>, Self::Error>;
+    /// Record the firmware a device reported, unless a later report of the
+    /// same device is already stored.
+    async fn record_firmware(&self, report: DeviceFirmware) -> Result<(), Self::Error>;
+    async fn get_firmware(&self, id: DeviceId) -> Result<Option<DeviceFirmware>, Self::Error>;
+
+    /// Queue a command for delivery to its device.
+    async fn add_command(&self, command: ActuatorCommand) -> Result<(), Self::Error>;
+    async fn get_command(&self, id: CommandId) -> Result<Option<CommandRecord>, Self::Error>;
+    async fn list_commands(&self, device_id: DeviceId) -> Result<Vec<CommandRecord>, Self::Error>;
+    /// Commands without a result that have not expired at `now`, for
+    /// devices owned by `organization`, or without an owner for `None`.
+    async fn pending_commands(
+        &self,
+        now: jiff::Timestamp,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<ActuatorCommand>, Self::Error>;
+    /// Record the result of a command. Returns `false` if the command is
+    /// unknown or already has a result, which is then kept. A result from
+    /// the device still replaces `TimedOut`, as its acknowledgement may
+    /// arrive after the dispatcher gave up waiting.
+    async fn complete_command(
+        &self,
+        id: CommandId,
+        result: CommandResult,
+        at: jiff::Timestamp,
+    ) -> Result<bool, Self::Error>;
+    /// Time out commands without a result that expired before `now`,
+    /// returning how many did.
+    async fn expire_commands(&self, now: jiff::Timestamp) -> Result<usize, Self::Error>;
 }
 
 #[async_trait]
@@ -44,6 +135,10 @@ pub trait DispatcherRegistry: Clone + Send + Sync + 'static {
     async fn get(&self, id: DispatcherId) -> Result<Option<Dispatcher>, Self::Error>;
     async fn update(&self, id: DispatcherId, new: Dispatcher) -> Result<(), Self::Error>;
     async fn suspend(&self, id: DispatcherId) -> Result<(), Self::Error>;
+    /// Return a suspended dispatcher to active.
+    async fn reactivate(&self, id: DispatcherId) -> Result<(), Self::Error>;
+    /// Decommission a dispatcher for good, keeping what it uploaded.
+    async fn retire(&self, id: DispatcherId) -> Result<(), Self::Error>;
 
     async fn batch_register(&self, dispatchers: Vec<Dispatcher>) -> Result<(), Self::Error>;
     async fn count(&self, filter: Option<DispatcherFilter>) -> Result<usize, Self::Error>;
@@ -59,12 +154,24 @@ pub trait ReadingRegistry: Clone + Send + Sync + 'static {
 
     async fn store(&self, reading: SensorReading) -> Result<(), Self::Error>;
     async fn get(&self, id: ReadingId) -> Result<Option<SensorReading>, Self::Error>;
+    /// Store a batch of readings.
+    ///
+    /// Readings whose id is already stored are skipped, so a batch that is
+    /// uploaded again after a lost response does not change what is stored.
     async fn batch_store(&self, readings: Vec<SensorReading>) -> Result<(), Self::Error>;
     async fn count(&self, filter: Option<ReadingFilter>) -> Result<usize, Self::Error>;
     async fn list(
         &self,
         options: QueryOptions<ReadingFilter, ReadingSortBy>,
     ) -> Result<Vec<SensorReading>, Self::Error>;
+    /// Statistics of the readings matching `filter` per metric and per cell
+    /// at `resolution`, ordered by cell then metric. Readings located at a
+    /// coarser resolution are left out.
+    async fn rollup(
+        &self,
+        resolution: u8,
+        filter: ReadingFilter,
+    ) -> Result<Vec<CellRollup>, Self::Error>;
 }
 
 #[async_trait]
@@ -74,6 +181,10 @@ pub trait DeviceStatusRegistry: Clone + Send + Sync + 'static {
     async fn store(&self, status: DeviceStatus) -> Result<(), Self::Error>;
     async fn get(&self, id: StatusId) -> Result<Option<DeviceStatus>, Self::Error>;
     async fn get_latest(&self, device_id: DeviceId) -> Result<Option<DeviceStatus>, Self::Error>;
+    /// Store a batch of statuses.
+    ///
+    /// Statuses whose id is already stored are skipped, like
+    /// [`ReadingRegistry::batch_store`].
     async fn batch_store(&self, statuses: Vec<DeviceStatus>) -> Result<(), Self::Error>;
     async fn count(&self, filter: Option<DeviceStatusFilter>) -> Result<usize, Self::Error>;
     async fn list(
@@ -81,3 +192,178 @@ pub trait DeviceStatusRegistry: Clone + Send + Sync + 'static {
         options: QueryOptions<DeviceStatusFilter, DeviceStatusSortBy>,
     ) -> Result<Vec<DeviceStatus>, Self::Error>;
 }
+
+/// Responses sent for processed batch uploads, so a batch that is uploaded
+/// again gets the same answer without being processed twice.
+#[async_trait]
+pub trait BatchRegistry: Clone + Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// The response sent to `dispatcher_id` for a batch, if it processed
+    /// the batch before. A batch recorded for another dispatcher has none.
+    async fn get(
+        &self,
+        dispatcher_id: DispatcherId,
+        id: BatchId,
+    ) -> Result<Option<BatchUploadResponse>, Self::Error>;
+    /// Record the response sent to `dispatcher_id` for a processed batch. A
+    /// batch that is already recorded keeps its first response.
+    async fn record(
+        &self,
+        dispatcher_id: DispatcherId,
+        response: BatchUploadResponse,
+    ) -> Result<(), Self::Error>;
+}
+
+/// Stores the readings and statuses accepted from a batch upload as one unit
+/// of work.
+#[async_trait]
+pub trait IngestRegistry: Clone + Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Store the readings and statuses of a batch, skipping ids that are
+    /// already stored.
+    ///
+    /// An error means the batch was not stored as a whole and has to be
+    /// uploaded again. Backends that can't store both in one transaction
+    /// may keep part of it, which the retried upload then skips.
+    async fn store_batch(
+        &self,
+        readings: Vec<SensorReading>,
+        statuses: Vec<DeviceStatus>,
+    ) -> Result<(), Self::Error>;
+}
+
+/// Liveness of dispatchers and devices: status report history, last seen
+/// times, device connection events and offline alerts.
+#[async_trait]
+pub trait HealthRegistry: Clone + Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Store a status report and mark its dispatcher online, last seen when
+    /// the report was received.
+    async fn record_status(&self, record: DispatcherStatusRecord) -> Result<(), Self::Error>;
+    /// Status reports of a dispatcher, newest first.
+    async fn list_statuses(
+        &self,
+        dispatcher_id: DispatcherId,
+        limit: usize,
+    ) -> Result<Vec<DispatcherStatusRecord>, Self::Error>;
+    async fn dispatcher_health(
+        &self,
+        id: DispatcherId,
+    ) -> Result<Option<DispatcherHealth>, Self::Error>;
+
+    /// Mark devices online, last seen at `at` through `dispatcher_id`.
+    /// Devices that were offline or never seen get a connected event, which
+    /// is returned.
+    async fn record_seen(
+        &self,
+        dispatcher_id: DispatcherId,
+        devices: Vec<DeviceId>,
+        at: jiff::Timestamp,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error>;
+    /// Store a disconnection event and mark its device offline.
+    async fn record_disconnection(&self, event: ConnectionEvent) -> Result<(), Self::Error>;
+    /// Connection events of a device, newest first.
+    async fn list_connections(
+        &self,
+        device_id: DeviceId,
+        limit: usize,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error>;
+    async fn device_health(&self, id: DeviceId) -> Result<Option<DeviceHealth>, Self::Error>;
+
+    /// Online dispatchers last seen before `cutoff`.
+    async fn silent_dispatchers(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DispatcherHealth>, Self::Error>;
+    /// Online devices last seen before `cutoff`.
+    async fn silent_devices(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DeviceHealth>, Self::Error>;
+    /// Store an alert and mark the dispatcher, or the device if it names
+    /// one, offline.
+    async fn raise_alert(&self, alert: OfflineAlert) -> Result<(), Self::Error>;
+    /// Alerts raised for a dispatcher or device, newest first.
+    async fn list_alerts(
+        &self,
+        subject: AlertSubject,
+        limit: usize,
+    ) -> Result<Vec<OfflineAlert>, Self::Error>;
+    /// Store an alert a dispatcher reported. An alert that is already
+    /// stored is kept as it is.
+    async fn record_reported_alert(&self, alert: ReportedAlert) -> Result<(), Self::Error>;
+    /// Alerts dispatchers reported for a dispatcher or device, newest
+    /// first. Only alerts of `organization` if set.
+    async fn list_reported_alerts(
+        &self,
+        subject: AlertSubject,
+        organization: Option<OrganizationId>,
+        limit: usize,
+    ) -> Result<Vec<ReportedAlert>, Self::Error>;
+}
+
+#[async_trait]
+pub trait ApiKeyRegistry: Clone + Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    async fn add(&self, key: ApiKey) -> Result<(), Self::Error>;
+    /// The key whose secret hashes to `hash`, revoked or not.
+    async fn get_by_hash(&self, hash: [u8; 32]) -> Result<Option<ApiKey>, Self::Error>;
+    async fn list(&self) -> Result<Vec<ApiKey>, Self::Error>;
+    /// Revoke a key. Returns `false` if the key is unknown or already
+    /// revoked.
+    async fn revoke(&self, id: ApiKeyId, at: jiff::Timestamp) -> Result<bool, Self::Error>;
+}
+
+#[async_trait]
+pub trait OrganizationRegistry: Clone + Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Add an organization. Names are unique across organizations.
+    async fn add(&self, organization: Organization) -> Result<(), Self::Error>;
+    async fn get(&self, id: OrganizationId) -> Result<Option<Organization>, Self::Error>;
+    async fn list(&self) -> Result<Vec<Organization>, Self::Error>;
+}
+
+/// Farms, their fields and the fields' plots, and the plot each device is
+/// assigned to.
+#[async_trait]
+pub trait FarmRegistry: Clone + Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    async fn add_farm(&self, farm: Farm) -> Result<(), Self::Error>;
+    async fn get_farm(&self, id: FarmId) -> Result<Option<Farm>, Self::Error>;
+    /// Farms owned by `organization`, or every farm for `None`, by name.
+    async fn list_farms(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<Farm>, Self::Error>;
+    async fn update_farm(&self, farm: Farm) -> Result<(), Self::Error>;
+    /// Remove a farm with its fields, plots and device assignments.
+    async fn remove_farm(&self, id: FarmId) -> Result<(), Self::Error>;
+
+    async fn add_field(&self, field: Field) -> Result<(), Self::Error>;
+    async fn get_field(&self, id: FieldId) -> Result<Option<Field>, Self::Error>;
+    /// Fields of a farm, by name.
+    async fn list_fields(&self, farm_id: FarmId) -> Result<Vec<Field>, Self::Error>;
+    async fn update_field(&self, field: Field) -> Result<(), Self::Error>;
+    /// Remove a field with its plots and device assignments.
+    async fn remove_field(&self, id: FieldId) -> Result<(), Self::Error>;
+
+    async fn add_plot(&self, plot: Plot) -> Result<(), Self::Error>;
+    async fn get_plot(&self, id: PlotId) -> Result<Option<Plot>, Self::Error>;
+    /// Plots of a field, by name.
+    async fn list_plots(&self, field_id: FieldId) -> Result<Vec<Plot>, Self::Error>;
+    async fn update_plot(&self, plot: Plot) -> Result<(), Self::Error>;
+    /// Remove a plot with its device assignments.
+    async fn remove_plot(&self, id: PlotId) -> Result<(), Self::Error>;
+
+    /// Assign a device to a plot, replacing any previous assignment.
+    async fn assign_device(&self, device_id: DeviceId, plot_id: PlotId) -> Result<(), Self::Error>;
+    /// Returns `false` if the device was not assigned to a plot.
+    async fn unassign_device(&self, device_id: DeviceId) -> Result<bool, Self::Error>;
+    async fn plot_devices(&self, plot_id: PlotId) -> Result<Vec<DeviceId>, Self::Error>;
+}
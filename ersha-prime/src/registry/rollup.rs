@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, HashSet};
+
+use ersha_core::{DeviceId, H3Cell, SensorReading};
+
+use super::{
+    filter::SensorMetricType,
+    metric::{decode_metric_type, metric_type_code},
+};
+use crate::geo;
+
+/// Statistics of one metric over the readings located within one cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellRollup {
+    pub cell: H3Cell,
+    pub metric_type: SensorMetricType,
+    pub readings: u64,
+    /// Distinct devices the readings came from.
+    pub devices: u64,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Default)]
+struct Group {
+    readings: u64,
+    devices: HashSet<DeviceId>,
+    total: f64,
+    min: f64,
+    max: f64,
+}
+
+/// Folds readings into rollups at one resolution, for backends that cannot
+/// aggregate in their query language.
+pub(crate) struct RollupAccumulator {
+    resolution: u8,
+    groups: BTreeMap<(u64, i32), Group>,
+}
+
+impl RollupAccumulator {
+    pub fn new(resolution: u8) -> Self {
+        Self {
+            resolution,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// Add a reading to the cell containing it. Readings located at a
+    /// coarser resolution, or at an invalid cell, are left out.
+    pub fn add(&mut self, reading: &SensorReading) {
+        if geo::resolution(reading.location).is_none_or(|r| r < self.resolution) {
+            return;
+        }
+        let Some(cell) = geo::parent(reading.location, self.resolution) else {
+            return;
+        };
+
+        let value = reading.metric.value();
+        let metric_type = metric_type_code(SensorMetricType::from(&reading.metric));
+        let group = self
+            .groups
+            .entry((cell.0, metric_type))
+            .or_insert_with(|| Group {
+                min: value,
+                max: value,
+                ..Group::default()
+            });
+        group.readings += 1;
+        group.devices.insert(reading.device_id);
+        group.total += value;
+        group.min = group.min.min(value);
+        group.max = group.max.max(value);
+    }
+
+    /// Rollups ordered by cell, then metric type.
+    pub fn finish(self) -> Vec<CellRollup> {
+        self.groups
+            .into_iter()
+            .map(|((cell, metric_type), group)| CellRollup {
+                cell: H3Cell(cell),
+                metric_type: decode_metric_type(metric_type)
+                    .expect("codes come from metric_type_code"),
+                readings: group.readings,
+                devices: group.devices.len() as u64,
+                mean: group.total / group.readings as f64,
+                min: group.min,
+                max: group.max,
+            })
+            .collect()
+    }
+}
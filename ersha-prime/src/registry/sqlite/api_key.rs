@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use ersha_core::OrganizationId;
+use sqlx::{Row, SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions, sqlite::SqliteRow};
+use ulid::Ulid;
+
+use async_trait::async_trait;
+
+use crate::{
+    auth::{ApiKey, ApiKeyId, Role},
+    registry::ApiKeyRegistry,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteApiKeyError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    #[error("invalid ULID: {0}")]
+    InvalidUlid(String),
+    #[error("invalid timestamp: {0}")]
+    InvalidTimestamp(i64),
+    #[error("invalid role: {0}")]
+    InvalidRole(i32),
+    #[error("invalid key hash length: {0}")]
+    InvalidHashLength(usize),
+}
+
+#[derive(Clone)]
+pub struct SqliteApiKeyRegistry {
+    pool: SqlitePool,
+}
+
+impl SqliteApiKeyRegistry {
+    pub async fn new(path: impl AsRef<str>) -> Result<Self, SqliteApiKeyError> {
+        let connection_string = format!("sqlite:{}", path.as_ref());
+        let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn new_in_memory() -> Result<Self, SqliteApiKeyError> {
+        let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+const KEY_COLUMNS: &str = "id, name, role, hash, organization_id, created_at, revoked_at";
+
+#[async_trait]
+impl ApiKeyRegistry for SqliteApiKeyRegistry {
+    type Error = SqliteApiKeyError;
+
+    async fn add(&self, key: ApiKey) -> Result<(), Self::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO api_keys (id, name, role, hash, organization_id, created_at, revoked_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(key.id.0.to_string())
+        .bind(&key.name)
+        .bind(key.role as i32)
+        .bind(key.hash.as_slice())
+        .bind(key.organization.map(|o| o.0.to_string()))
+        .bind(key.created_at.as_second())
+        .bind(key.revoked_at.map(|t| t.as_second()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_by_hash(&self, hash: [u8; 32]) -> Result<Option<ApiKey>, Self::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {KEY_COLUMNS} FROM api_keys WHERE hash = ?"
+        ))
+        .bind(hash.as_slice())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(map_row_to_api_key).transpose()
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKey>, Self::Error> {
+        let rows = sqlx::query(&format!("SELECT {KEY_COLUMNS} FROM api_keys ORDER BY id"))
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(map_row_to_api_key).collect()
+    }
+
+    async fn revoke(&self, id: ApiKeyId, at: jiff::Timestamp) -> Result<bool, Self::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE api_keys SET revoked_at = ?
+            WHERE id = ? AND revoked_at IS NULL
+            "#,
+        )
+        .bind(at.as_second())
+        .bind(id.0.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn map_row_to_api_key(row: &SqliteRow) -> Result<ApiKey, SqliteApiKeyError> {
+    let id_str: String = row.try_get("id")?;
+    let ulid = Ulid::from_str(&id_str).map_err(|_| SqliteApiKeyError::InvalidUlid(id_str))?;
+
+    let role = match row.try_get::<i32, _>("role")? {
+        0 => Role::Viewer,
+        1 => Role::Operator,
+        2 => Role::Admin,
+        other => return Err(SqliteApiKeyError::InvalidRole(other)),
+    };
+
+    let hash: Vec<u8> = row.try_get("hash")?;
+    let hash: [u8; 32] = hash
+        .try_into()
+        .map_err(|hash: Vec<u8>| SqliteApiKeyError::InvalidHashLength(hash.len()))?;
+
+    let organization = row
+        .try_get::<Option<String>, _>("organization_id")?
+        .map(|id| {
+            Ulid::from_str(&id)
+                .map(OrganizationId)
+                .map_err(|_| SqliteApiKeyError::InvalidUlid(id))
+        })
+        .transpose()?;
+
+    let timestamp = |secs: i64| {
+        jiff::Timestamp::from_second(secs).map_err(|_| SqliteApiKeyError::InvalidTimestamp(secs))
+    };
+
+    Ok(ApiKey {
+        id: ApiKeyId(ulid),
+        name: row.try_get("name")?,
+        role,
+        hash,
+        organization,
+        created_at: timestamp(row.try_get("created_at")?)?,
+        revoked_at: row
+            .try_get::<Option<i64>, _>("revoked_at")?
+            .map(timestamp)
+            .transpose()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ulid::Ulid;
+
+    use crate::auth::{ApiKey, ApiKeyId, Role, hash_key};
+    use crate::registry::ApiKeyRegistry;
+
+    use super::SqliteApiKeyRegistry;
+
+    #[tokio::test]
+    async fn test_add_lookup_and_revoke() {
+        let registry = SqliteApiKeyRegistry::new_in_memory().await.unwrap();
+        let mut key = ApiKey::new("ops", Role::Operator, "secret-one");
+        // stored with second precision
+        key.created_at = jiff::Timestamp::from_second(key.created_at.as_second()).unwrap();
+        registry.add(key.clone()).await.unwrap();
+
+        // the hash is unique, so a secret can't be stored twice
+        let duplicate = ApiKey::new("again", Role::Admin, "secret-one");
+        assert!(registry.add(duplicate).await.is_err());
+
+        let found = registry.get_by_hash(hash_key("secret-one")).await.unwrap();
+        assert_eq!(found, Some(key.clone()));
+        assert!(
+            registry
+                .get_by_hash(hash_key("secret-two"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let now = jiff::Timestamp::now();
+        assert!(registry.revoke(key.id, now).await.unwrap());
+        assert!(!registry.revoke(key.id, now).await.unwrap());
+        assert!(!registry.revoke(ApiKeyId(Ulid::new()), now).await.unwrap());
+
+        let listed = registry.list().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(
+            listed[0].revoked_at.map(|t| t.as_second()),
+            Some(now.as_second())
+        );
+    }
+}
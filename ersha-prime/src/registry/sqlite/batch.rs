@@ -0,0 +1,220 @@
+use std::str::FromStr;
+
+use ersha_core::{BatchId, BatchUploadResponse, DispatcherId};
+use sqlx::{Row, SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions};
+use ulid::Ulid;
+
+use async_trait::async_trait;
+
+use crate::registry::{BatchRegistry, batch::RejectionColumns};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteBatchError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    #[error("invalid ULID: {0}")]
+    InvalidUlid(String),
+    #[error("invalid rejection code: {0}")]
+    InvalidRejection(i32),
+}
+
+#[derive(Clone)]
+pub struct SqliteBatchRegistry {
+    pool: SqlitePool,
+}
+
+impl SqliteBatchRegistry {
+    pub async fn new(path: impl AsRef<str>) -> Result<Self, SqliteBatchError> {
+        let connection_string = format!("sqlite:{}", path.as_ref());
+        let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn new_in_memory() -> Result<Self, SqliteBatchError> {
+        let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl BatchRegistry for SqliteBatchRegistry {
+    type Error = SqliteBatchError;
+
+    async fn get(
+        &self,
+        dispatcher_id: DispatcherId,
+        id: BatchId,
+    ) -> Result<Option<BatchUploadResponse>, Self::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT readings_stored, readings_rejected, statuses_stored, statuses_rejected
+            FROM batches WHERE id = ? AND dispatcher_id = ?
+            "#,
+        )
+        .bind(id.0.to_string())
+        .bind(dispatcher_id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let rejection_rows = sqlx::query(
+            "SELECT kind, record_id, reason FROM batch_rejections WHERE batch_id = ? ORDER BY id",
+        )
+        .bind(id.0.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut rejections = Vec::with_capacity(rejection_rows.len());
+        for r in &rejection_rows {
+            let record_id: String = r.try_get("record_id")?;
+            let columns = RejectionColumns {
+                kind: r.try_get("kind")?,
+                record_id: Ulid::from_str(&record_id)
+                    .map_err(|_| SqliteBatchError::InvalidUlid(record_id))?,
+                reason: r.try_get("reason")?,
+            };
+            rejections.push(
+                columns
+                    .decode()
+                    .map_err(SqliteBatchError::InvalidRejection)?,
+            );
+        }
+
+        Ok(Some(BatchUploadResponse {
+            id,
+            readings_stored: row.try_get::<i64, _>("readings_stored")? as u32,
+            readings_rejected: row.try_get::<i64, _>("readings_rejected")? as u32,
+            statuses_stored: row.try_get::<i64, _>("statuses_stored")? as u32,
+            statuses_rejected: row.try_get::<i64, _>("statuses_rejected")? as u32,
+            rejections: rejections.into_boxed_slice(),
+            failed: false,
+        }))
+    }
+
+    async fn record(
+        &self,
+        dispatcher_id: DispatcherId,
+        response: BatchUploadResponse,
+    ) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO batches (id, dispatcher_id, readings_stored, readings_rejected, statuses_stored, statuses_rejected)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(response.id.0.to_string())
+        .bind(dispatcher_id.0.to_string())
+        .bind(response.readings_stored as i64)
+        .bind(response.readings_rejected as i64)
+        .bind(response.statuses_stored as i64)
+        .bind(response.statuses_rejected as i64)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if inserted > 0 {
+            for rejection in response.rejections.iter() {
+                let columns = RejectionColumns::from(rejection);
+                sqlx::query(
+                    r#"
+                    INSERT INTO batch_rejections (batch_id, kind, record_id, reason)
+                    VALUES (?, ?, ?, ?)
+                    "#,
+                )
+                .bind(response.id.0.to_string())
+                .bind(columns.kind)
+                .bind(columns.record_id.to_string())
+                .bind(columns.reason)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{
+        BatchId, BatchUploadResponse, DispatcherId, IngestRejection, IngestRejectionReason,
+        ReadingId,
+    };
+    use ulid::Ulid;
+
+    use crate::registry::BatchRegistry;
+
+    use super::SqliteBatchRegistry;
+
+    fn response(readings_stored: u32) -> BatchUploadResponse {
+        BatchUploadResponse {
+            id: BatchId(Ulid::nil()),
+            readings_stored,
+            readings_rejected: 1,
+            statuses_stored: 0,
+            statuses_rejected: 0,
+            rejections: vec![IngestRejection::Reading {
+                id: ReadingId(Ulid::new()),
+                reason: IngestRejectionReason::DeviceSuspended,
+            }]
+            .into_boxed_slice(),
+            failed: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_keeps_first_response() {
+        let registry = SqliteBatchRegistry::new_in_memory().await.unwrap();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        assert!(
+            registry
+                .get(dispatcher_id, BatchId(Ulid::nil()))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let first = response(3);
+        registry.record(dispatcher_id, first.clone()).await.unwrap();
+        registry.record(dispatcher_id, response(0)).await.unwrap();
+
+        assert_eq!(
+            registry.get(dispatcher_id, first.id).await.unwrap(),
+            Some(first)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_is_replayed_only_to_its_dispatcher() {
+        let registry = SqliteBatchRegistry::new_in_memory().await.unwrap();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        let other = DispatcherId(Ulid::new());
+
+        let first = response(3);
+        registry.record(dispatcher_id, first.clone()).await.unwrap();
+        // the same batch id from another dispatcher does not take it over
+        registry.record(other, response(0)).await.unwrap();
+
+        assert!(registry.get(other, first.id).await.unwrap().is_none());
+        assert_eq!(
+            registry.get(dispatcher_id, first.id).await.unwrap(),
+            Some(first)
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeSet;
+
+use ersha_core::H3Cell;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+
+use crate::{
+    geo,
+    registry::filter::{BoundingBox, CellRing},
+};
+
+/// Record the center and ancestors of cells in `h3_cells`. Invalid cells are
+/// skipped, they never match spatial filters.
+pub(super) async fn index_cells(
+    conn: &mut SqliteConnection,
+    cells: impl IntoIterator<Item = H3Cell>,
+) -> Result<(), sqlx::Error> {
+    let cells: BTreeSet<u64> = cells.into_iter().map(|cell| cell.0).collect();
+
+    for cell in cells.into_iter().map(H3Cell) {
+        let (Some(resolution), Some((lat, lng))) = (geo::resolution(cell), geo::center(cell))
+        else {
+            continue;
+        };
+
+        let mut query_builder =
+            QueryBuilder::<Sqlite>::new("INSERT OR IGNORE INTO h3_cells (cell, lat, lng");
+        for r in 0..=resolution {
+            query_builder.push(format!(", r{r}"));
+        }
+        query_builder.push(") VALUES (");
+        let mut separated = query_builder.separated(", ");
+        separated.push_bind(cell.0 as i64);
+        separated.push_bind(lat);
+        separated.push_bind(lng);
+        for r in 0..=resolution {
+            separated.push_bind(geo::parent(cell, r).map(|p| p.0 as i64));
+        }
+        separated.push_unseparated(")");
+
+        query_builder.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Index the locations of devices and readings stored before `h3_cells`
+/// existed.
+pub(super) async fn index_existing(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let cells: Vec<i64> = sqlx::query_scalar(
+        "SELECT location FROM devices UNION SELECT location FROM readings EXCEPT SELECT cell FROM h3_cells",
+    )
+    .fetch_all(pool)
+    .await?;
+    if cells.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = pool.acquire().await?;
+    index_cells(&mut conn, cells.into_iter().map(|cell| H3Cell(cell as u64))).await
+}
+
+/// Push the ancestor, ring and bounding box conditions on the `location`
+/// column, each after a call to `prefix`.
+pub(super) fn push_spatial_filters<'a>(
+    query_builder: &mut QueryBuilder<'a, Sqlite>,
+    prefix: &mut impl FnMut(&mut QueryBuilder<'a, Sqlite>),
+    ancestor: Option<H3Cell>,
+    ring: Option<CellRing>,
+    bbox: Option<BoundingBox>,
+) {
+    if let Some(ancestor) = ancestor {
+        prefix(query_builder);
+        match geo::resolution(ancestor) {
+            Some(r) => {
+                query_builder.push(format!(
+                    "location IN (SELECT cell FROM h3_cells WHERE r{r} = "
+                ));
+                query_builder.push_bind(ancestor.0 as i64);
+                query_builder.push(")");
+            }
+            None => {
+                query_builder.push("0");
+            }
+        }
+    }
+
+    if let Some(ring) = ring {
+        prefix(query_builder);
+        match (geo::resolution(ring.center), geo::disk(ring)) {
+            (Some(r), Some(cells)) => {
+                query_builder.push(format!(
+                    "location IN (SELECT cell FROM h3_cells WHERE r{r} IN ("
+                ));
+                let mut separated = query_builder.separated(", ");
+                for cell in cells {
+                    separated.push_bind(cell.0 as i64);
+                }
+                separated.push_unseparated("))");
+            }
+            _ => {
+                query_builder.push("0");
+            }
+        }
+    }
+
+    if let Some(bbox) = bbox {
+        prefix(query_builder);
+        query_builder.push("location IN (SELECT cell FROM h3_cells WHERE lat BETWEEN ");
+        query_builder.push_bind(bbox.south);
+        query_builder.push(" AND ");
+        query_builder.push_bind(bbox.north);
+        if bbox.west <= bbox.east {
+            query_builder.push(" AND lng BETWEEN ");
+            query_builder.push_bind(bbox.west);
+            query_builder.push(" AND ");
+            query_builder.push_bind(bbox.east);
+            query_builder.push(")");
+        } else {
+            query_builder.push(" AND (lng >= ");
+            query_builder.push_bind(bbox.west);
+            query_builder.push(" OR lng <= ");
+            query_builder.push_bind(bbox.east);
+            query_builder.push("))");
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use std::str::FromStr;
 
 use ersha_core::{
-    Device, DeviceId, DeviceKind, DeviceState, H3Cell, Percentage, Sensor, SensorId, SensorKind,
-    SensorMetric,
+    Actuator, ActuatorCommand, ActuatorId, Calibration, CalibrationProfile, CommandId,
+    CommandRecord, CommandResult, Device, DeviceCredential, DeviceFirmware, DeviceId, DeviceKey,
+    DeviceKind, DeviceState, FirmwareId, FirmwareImage, FirmwareStatus, H3Cell, OrganizationId,
+    Sensor, SensorId,
 };
 use ordered_float::NotNan;
 use sqlx::{
@@ -15,9 +17,16 @@ use async_trait::async_trait;
 
 use crate::registry::{
     DeviceRegistry,
+    actuator::{
+        ActionColumns, ResultColumns, actuator_kind_code, decode_actuator_kind, replaces_result,
+    },
     filter::{DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder},
+    firmware::FirmwareUpdateColumns,
+    metric::{decode_metric, decode_sensor_kind, disect_metric},
 };
 
+use super::cells;
+
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
 #[derive(Debug, thiserror::Error)]
@@ -38,8 +47,26 @@ pub enum SqliteDeviceError {
     InvalidMetricType(i32),
     #[error("invalid sensor kind: {0}")]
     InvalidSensorKind(i32),
+    #[error("invalid calibration kind: {0}")]
+    InvalidCalibrationKind(i32),
+    #[error("invalid calibration value")]
+    InvalidCalibrationValue,
+    #[error("invalid device key length: {0}")]
+    InvalidKeyLength(usize),
+    #[error("invalid firmware digest length: {0}")]
+    InvalidDigestLength(usize),
+    #[error("invalid actuator kind: {0}")]
+    InvalidActuatorKind(i32),
+    #[error("invalid command action: {0}")]
+    InvalidCommandAction(i32),
+    #[error("invalid command result: {0}")]
+    InvalidCommandResult(i32),
+    #[error("invalid firmware update state: {0}")]
+    InvalidFirmwareUpdate(i32),
     #[error("not found")]
     NotFound,
+    #[error("already exists")]
+    AlreadyExists,
 }
 
 #[derive(Clone)]
@@ -53,6 +80,7 @@ impl SqliteDeviceRegistry {
         let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
 
         MIGRATOR.run(&pool).await?;
+        cells::index_existing(&pool).await?;
 
         Ok(Self { pool })
     }
@@ -61,6 +89,7 @@ impl SqliteDeviceRegistry {
         let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
 
         MIGRATOR.run(&pool).await?;
+        cells::index_existing(&pool).await?;
 
         Ok(Self { pool })
     }
@@ -73,8 +102,8 @@ impl DeviceRegistry for SqliteDeviceRegistry {
     async fn register(&self, device: Device) -> Result<(), Self::Error> {
         sqlx::query(
             r#"
-            INSERT OR REPLACE INTO devices (id, kind, state, location, manufacturer, provisioned_at)
-            VALUES (?, ?, ?, ?, ?, ?)
+            INSERT OR REPLACE INTO devices (id, kind, state, location, manufacturer, provisioned_at, organization_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(device.id.0.to_string())
@@ -83,17 +112,20 @@ impl DeviceRegistry for SqliteDeviceRegistry {
         .bind(device.location.0 as i64)
         .bind(device.manufacturer)
         .bind(device.provisioned_at.as_second())
+        .bind(device.organization.map(|o| o.0.to_string()))
         .execute(&self.pool)
         .await?;
+        cells::index_cells(&mut *self.pool.acquire().await?, [device.location]).await?;
 
         self.add_sensors(device.id, device.sensors.into_iter())
             .await?;
+        self.add_actuators(device.id, &device.actuators).await?;
 
         Ok(())
     }
 
     async fn add_sensor(&self, id: DeviceId, sensor: Sensor) -> Result<(), Self::Error> {
-        let (metric_type, metric_value) = disect_metric(sensor.metric);
+        let (metric_type, metric_value) = disect_metric(&sensor.metric);
 
         sqlx::query(
             r#"
@@ -120,7 +152,7 @@ impl DeviceRegistry for SqliteDeviceRegistry {
         let mut tx = self.pool.begin().await?;
 
         for sensor in sensors {
-            let (metric_type, metric_value) = disect_metric(sensor.metric);
+            let (metric_type, metric_value) = disect_metric(&sensor.metric);
 
             sqlx::query(
                 r#"
@@ -141,9 +173,31 @@ impl DeviceRegistry for SqliteDeviceRegistry {
         Ok(())
     }
 
+    async fn remove_sensor(&self, id: DeviceId, sensor_id: SensorId) -> Result<bool, Self::Error> {
+        let removed = sqlx::query("DELETE FROM sensors WHERE id = ? AND device_id = ?")
+            .bind(sensor_id.0.to_string())
+            .bind(id.0.to_string())
+            .execute(&self.pool)
+            .await?
+            .rows_affected()
+            > 0;
+
+        if !removed {
+            let known: Option<i64> = sqlx::query_scalar("SELECT 1 FROM devices WHERE id = ?")
+                .bind(id.0.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+            if known.is_none() {
+                return Err(Self::Error::NotFound);
+            }
+        }
+
+        Ok(removed)
+    }
+
     async fn get(&self, id: DeviceId) -> Result<Option<Device>, Self::Error> {
         let device_row = sqlx::query(
-            r#"SELECT id, kind, state, location, manufacturer, provisioned_at FROM devices WHERE id = ?"#,
+            r#"SELECT id, kind, state, location, manufacturer, provisioned_at, organization_id FROM devices WHERE id = ?"#,
         )
         .bind(id.0.to_string())
         .fetch_optional(&self.pool)
@@ -168,36 +222,13 @@ impl DeviceRegistry for SqliteDeviceRegistry {
 
             sensors.push(Sensor {
                 id: SensorId(s_ulid),
-                kind: match s_row.try_get::<i32, _>("kind")? {
-                    0 => SensorKind::SoilMoisture,
-                    1 => SensorKind::SoilTemp,
-                    2 => SensorKind::AirTemp,
-                    3 => SensorKind::Humidity,
-                    4 => SensorKind::Rainfall,
-                    other => return Err(Self::Error::InvalidSensorKind(other)),
-                },
-
-                metric: match s_row.try_get::<i32, _>("metric_type")? {
-                    0 => SensorMetric::SoilMoisture {
-                        value: Percentage(s_row.try_get::<f64, _>("metric_value")? as u8),
-                    },
-                    1 => SensorMetric::SoilTemp {
-                        value: NotNan::new(s_row.try_get::<f64, _>("metric_value")?)
-                            .expect("database should not contain NaN"),
-                    },
-                    2 => SensorMetric::AirTemp {
-                        value: NotNan::new(s_row.try_get::<f64, _>("metric_value")?)
-                            .expect("database should not contain NaN"),
-                    },
-                    3 => SensorMetric::Humidity {
-                        value: Percentage(s_row.try_get::<f64, _>("metric_value")? as u8),
-                    },
-                    4 => SensorMetric::Rainfall {
-                        value: NotNan::new(s_row.try_get::<f64, _>("metric_value")?)
-                            .expect("database should not contain NaN"),
-                    },
-                    other => return Err(Self::Error::InvalidMetricType(other)),
-                },
+                kind: decode_sensor_kind(s_row.try_get("kind")?)
+                    .map_err(Self::Error::InvalidSensorKind)?,
+                metric: decode_metric(
+                    s_row.try_get("metric_type")?,
+                    s_row.try_get("metric_value")?,
+                )
+                .map_err(Self::Error::InvalidMetricType)?,
             });
         }
 
@@ -208,14 +239,26 @@ impl DeviceRegistry for SqliteDeviceRegistry {
         let state = match r.try_get::<i32, _>("state")? {
             0 => DeviceState::Active,
             1 => DeviceState::Suspended,
+            2 => DeviceState::Retired,
             other => return Err(Self::Error::InvalidState(other)),
         };
 
         let kind = match r.try_get::<i32, _>("kind")? {
             0 => DeviceKind::Sensor,
+            1 => DeviceKind::Actuator,
             other => return Err(Self::Error::InvalidDeviceKind(other)),
         };
 
+        let actuator_rows =
+            sqlx::query(r#"SELECT id, kind, max_run_secs FROM actuators WHERE device_id = ?"#)
+                .bind(id.0.to_string())
+                .fetch_all(&self.pool)
+                .await?;
+        let actuators = actuator_rows
+            .into_iter()
+            .map(map_row_to_actuator)
+            .collect::<Result<Vec<_>, _>>()?;
+
         let manufacturer = r
             .try_get::<Option<String>, _>("manufacturer")?
             .map(|s| s.into_boxed_str());
@@ -228,6 +271,8 @@ impl DeviceRegistry for SqliteDeviceRegistry {
             manufacturer,
             provisioned_at,
             sensors: sensors.into_boxed_slice(),
+            actuators: actuators.into_boxed_slice(),
+            organization: parse_organization(r.try_get("organization_id")?)?,
         }))
     }
 
@@ -249,14 +294,37 @@ impl DeviceRegistry for SqliteDeviceRegistry {
         self.register(new).await
     }
 
+    async fn reactivate(&self, id: DeviceId) -> Result<(), Self::Error> {
+        let device = self.get(id).await?.ok_or(Self::Error::NotFound)?;
+
+        let new = Device {
+            state: DeviceState::Active,
+            ..device
+        };
+
+        self.register(new).await
+    }
+
+    async fn retire(&self, id: DeviceId) -> Result<(), Self::Error> {
+        let device = self.get(id).await?.ok_or(Self::Error::NotFound)?;
+
+        let new = Device {
+            state: DeviceState::Retired,
+            ..device
+        };
+
+        self.register(new).await
+    }
+
     async fn batch_register(&self, devices: Vec<Device>) -> Result<(), Self::Error> {
         let mut tx = self.pool.begin().await?;
+        cells::index_cells(&mut tx, devices.iter().map(|device| device.location)).await?;
 
         for device in devices {
             sqlx::query(
                 r#"
-            INSERT OR REPLACE INTO devices (id, kind, state, location, manufacturer, provisioned_at)
-            VALUES (?, ?, ?, ?, ?, ?)
+            INSERT OR REPLACE INTO devices (id, kind, state, location, manufacturer, provisioned_at, organization_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
             "#,
             )
             .bind(device.id.0.to_string())
@@ -265,11 +333,13 @@ impl DeviceRegistry for SqliteDeviceRegistry {
             .bind(device.location.0 as i64)
             .bind(device.manufacturer)
             .bind(device.provisioned_at.as_second())
+            .bind(device.organization.map(|o| o.0.to_string()))
             .execute(&mut *tx)
             .await?;
 
             self.add_sensors(device.id, device.sensors.into_iter())
                 .await?;
+            self.add_actuators(device.id, &device.actuators).await?;
         }
 
         tx.commit().await?;
@@ -294,7 +364,7 @@ impl DeviceRegistry for SqliteDeviceRegistry {
         options: QueryOptions<DeviceFilter, DeviceSortBy>,
     ) -> Result<Vec<Device>, Self::Error> {
         let mut query_builder = QueryBuilder::new(
-            "SELECT id, kind, state, location, manufacturer, provisioned_at, sensor_count FROM devices ",
+            "SELECT id, kind, state, location, manufacturer, provisioned_at, sensor_count, organization_id FROM devices ",
         );
 
         query_builder = filter_devices(query_builder, options.filter);
@@ -362,8 +432,547 @@ impl DeviceRegistry for SqliteDeviceRegistry {
             }
         }
 
+        let mut actuator_query = QueryBuilder::new(
+            "SELECT id, kind, max_run_secs, device_id FROM actuators WHERE device_id IN (",
+        );
+        let mut separated = actuator_query.separated(", ");
+        for id in &device_ids {
+            separated.push_bind(id);
+        }
+        separated.push_unseparated(")");
+
+        let actuator_rows = actuator_query.build().fetch_all(&self.pool).await?;
+
+        for a_row in actuator_rows {
+            let d_id: String = a_row.try_get("device_id")?;
+            let actuator = map_row_to_actuator(a_row)?;
+
+            if let Some(device) = devices.iter_mut().find(|d| d.id.0.to_string() == d_id) {
+                let mut v = device.actuators.clone().into_vec();
+                v.push(actuator);
+                device.actuators = v.into_boxed_slice();
+            }
+        }
+
         Ok(devices)
     }
+
+    async fn set_calibration(
+        &self,
+        sensor_id: SensorId,
+        calibration: Option<Calibration>,
+    ) -> Result<(), Self::Error> {
+        let id = sensor_id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let known: Option<i64> = sqlx::query_scalar("SELECT 1 FROM sensors WHERE id = ?")
+            .bind(&id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if known.is_none() {
+            return Err(Self::Error::NotFound);
+        }
+
+        sqlx::query("DELETE FROM sensor_calibration_points WHERE sensor_id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM sensor_calibrations WHERE sensor_id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(calibration) = calibration {
+            let (kind, gain, offset) = match &calibration {
+                Calibration::Linear { gain, offset } => {
+                    (0, Some(gain.into_inner()), Some(offset.into_inner()))
+                }
+                Calibration::Lookup { .. } => (1, None, None),
+            };
+
+            sqlx::query(
+                r#"
+                INSERT INTO sensor_calibrations (sensor_id, kind, gain, offset_value, updated_at)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&id)
+            .bind(kind)
+            .bind(gain)
+            .bind(offset)
+            .bind(jiff::Timestamp::now().as_second())
+            .execute(&mut *tx)
+            .await?;
+
+            if let Calibration::Lookup { points } = &calibration {
+                for (position, (raw, calibrated)) in points.iter().enumerate() {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO sensor_calibration_points (sensor_id, position, raw, calibrated)
+                        VALUES (?, ?, ?, ?)
+                        "#,
+                    )
+                    .bind(&id)
+                    .bind(position as i64)
+                    .bind(raw.into_inner())
+                    .bind(calibrated.into_inner())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_calibration(
+        &self,
+        sensor_id: SensorId,
+    ) -> Result<Option<CalibrationProfile>, Self::Error> {
+        let row = sqlx::query(
+            "SELECT sensor_id, kind, gain, offset_value, updated_at FROM sensor_calibrations WHERE sensor_id = ?",
+        )
+        .bind(sensor_id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(self.map_row_to_calibration(row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_calibrations(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<CalibrationProfile>, Self::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT c.sensor_id, c.kind, c.gain, c.offset_value, c.updated_at
+            FROM sensor_calibrations c
+            JOIN sensors s ON s.id = c.sensor_id
+            JOIN devices d ON d.id = s.device_id
+            WHERE d.organization_id IS ?
+            "#,
+        )
+        .bind(organization.map(|o| o.0.to_string()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut profiles = Vec::with_capacity(rows.len());
+        for row in rows {
+            profiles.push(self.map_row_to_calibration(row).await?);
+        }
+
+        Ok(profiles)
+    }
+
+    async fn set_key(&self, id: DeviceId, key: DeviceKey) -> Result<(), Self::Error> {
+        let result = sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO device_keys (device_id, key, created_at)
+            SELECT id, ?, ? FROM devices WHERE id = ?
+            "#,
+        )
+        .bind(key.0.as_slice())
+        .bind(jiff::Timestamp::now().as_second())
+        .bind(id.0.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Self::Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn list_keys(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<DeviceCredential>, Self::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT k.device_id, k.key
+            FROM device_keys k
+            JOIN devices d ON d.id = k.device_id
+            WHERE d.organization_id IS ?
+            "#,
+        )
+        .bind(organization.map(|o| o.0.to_string()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let id_str: String = row.try_get("device_id")?;
+                let ulid =
+                    Ulid::from_str(&id_str).map_err(|_| SqliteDeviceError::InvalidUlid(id_str))?;
+
+                let key: Vec<u8> = row.try_get("key")?;
+                let key = <[u8; 32]>::try_from(key.as_slice())
+                    .map_err(|_| SqliteDeviceError::InvalidKeyLength(key.len()))?;
+
+                Ok(DeviceCredential {
+                    device_id: DeviceId(ulid),
+                    key: DeviceKey(key),
+                })
+            })
+            .collect()
+    }
+
+    async fn add_firmware(&self, image: FirmwareImage, data: Vec<u8>) -> Result<(), Self::Error> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO firmware_images (id, model, version, size, sha256, signature, data, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(image.id.0.to_string())
+        .bind(image.model.as_ref())
+        .bind(image.version as i64)
+        .bind(image.size as i64)
+        .bind(image.sha256.as_slice())
+        .bind(image.signature.as_ref())
+        .bind(data)
+        .bind(image.created_at.as_second())
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                Err(Self::Error::AlreadyExists)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list_firmware(&self) -> Result<Vec<FirmwareImage>, Self::Error> {
+        let rows = sqlx::query(
+            "SELECT id, model, version, size, sha256, signature, created_at FROM firmware_images",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let id_str: String = row.try_get("id")?;
+                let ulid =
+                    Ulid::from_str(&id_str).map_err(|_| SqliteDeviceError::InvalidUlid(id_str))?;
+
+                let sha256: Vec<u8> = row.try_get("sha256")?;
+                let sha256 = <[u8; 32]>::try_from(sha256.as_slice())
+                    .map_err(|_| SqliteDeviceError::InvalidDigestLength(sha256.len()))?;
+
+                let created_at_sec: i64 = row.try_get("created_at")?;
+                let created_at = jiff::Timestamp::from_second(created_at_sec)
+                    .map_err(|_| SqliteDeviceError::InvalidTimestamp(created_at_sec))?;
+
+                Ok(FirmwareImage {
+                    id: FirmwareId(ulid),
+                    model: row.try_get::<String, _>("model")?.into_boxed_str(),
+                    version: row.try_get::<i64, _>("version")? as u32,
+                    size: row.try_get::<i64, _>("size")? as u32,
+                    sha256,
+                    signature: row.try_get::<Vec<u8>, _>("signature")?.into_boxed_slice(),
+                    created_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn firmware_chunk(
+        &self,
+        id: FirmwareId,
+        offset: u32,
+        len: u32,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        // substr() indexes blobs by byte, starting at 1
+        let chunk = sqlx::query_scalar::<_, Vec<u8>>(
+            "SELECT substr(data, ?, ?) FROM firmware_images WHERE id = ?",
+        )
+        .bind(offset as i64 + 1)
+        .bind(len as i64)
+        .bind(id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(chunk)
+    }
+
+    async fn record_firmware(&self, report: DeviceFirmware) -> Result<(), Self::Error> {
+        let update = FirmwareUpdateColumns::from(&report.firmware.update);
+
+        sqlx::query(
+            r#"
+            INSERT INTO device_firmware (device_id, model, version, update_state, target_version, progress, failure, reported_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(device_id) DO UPDATE SET
+                model = excluded.model,
+                version = excluded.version,
+                update_state = excluded.update_state,
+                target_version = excluded.target_version,
+                progress = excluded.progress,
+                failure = excluded.failure,
+                reported_at = excluded.reported_at
+            WHERE excluded.reported_at >= device_firmware.reported_at
+            "#,
+        )
+        .bind(report.device_id.0.to_string())
+        .bind(report.firmware.model.as_ref())
+        .bind(report.firmware.version as i64)
+        .bind(update.state)
+        .bind(update.target_version as i64)
+        .bind(update.progress as i32)
+        .bind(update.failure)
+        .bind(report.reported_at.as_second())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_firmware(&self, id: DeviceId) -> Result<Option<DeviceFirmware>, Self::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT model, version, update_state, target_version, progress, failure, reported_at
+            FROM device_firmware WHERE device_id = ?
+            "#,
+        )
+        .bind(id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let update = FirmwareUpdateColumns {
+            state: row.try_get("update_state")?,
+            target_version: row.try_get::<i64, _>("target_version")? as u32,
+            progress: row.try_get::<i32, _>("progress")? as u8,
+            failure: row.try_get("failure")?,
+        }
+        .decode()
+        .map_err(SqliteDeviceError::InvalidFirmwareUpdate)?;
+
+        let reported_at: i64 = row.try_get("reported_at")?;
+
+        Ok(Some(DeviceFirmware {
+            device_id: id,
+            firmware: FirmwareStatus {
+                model: row.try_get::<String, _>("model")?.into_boxed_str(),
+                version: row.try_get::<i64, _>("version")? as u32,
+                update,
+            },
+            reported_at: jiff::Timestamp::from_second(reported_at)
+                .map_err(|_| SqliteDeviceError::InvalidTimestamp(reported_at))?,
+        }))
+    }
+
+    async fn add_command(&self, command: ActuatorCommand) -> Result<(), Self::Error> {
+        let action = ActionColumns::from(&command.action);
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO actuator_commands (id, device_id, actuator_id, action, duration_secs, issued_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(command.id.0.to_string())
+        .bind(command.device_id.0.to_string())
+        .bind(command.actuator_id.0.to_string())
+        .bind(action.action)
+        .bind(action.duration_secs as i64)
+        .bind(command.issued_at.as_second())
+        .bind(command.expires_at.as_second())
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                Err(Self::Error::AlreadyExists)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_command(&self, id: CommandId) -> Result<Option<CommandRecord>, Self::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {COMMAND_COLUMNS} FROM actuator_commands WHERE id = ?"
+        ))
+        .bind(id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(map_row_to_command).transpose()
+    }
+
+    async fn list_commands(&self, device_id: DeviceId) -> Result<Vec<CommandRecord>, Self::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {COMMAND_COLUMNS} FROM actuator_commands WHERE device_id = ? ORDER BY issued_at"
+        ))
+        .bind(device_id.0.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(map_row_to_command).collect()
+    }
+
+    async fn pending_commands(
+        &self,
+        now: jiff::Timestamp,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<ActuatorCommand>, Self::Error> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {COMMAND_COLUMNS} FROM actuator_commands
+            WHERE result IS NULL AND expires_at > ?
+              AND device_id IN (SELECT id FROM devices WHERE organization_id IS ?)
+            ORDER BY issued_at
+            "#
+        ))
+        .bind(now.as_second())
+        .bind(organization.map(|o| o.0.to_string()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| map_row_to_command(row).map(|record| record.command))
+            .collect()
+    }
+
+    async fn complete_command(
+        &self,
+        id: CommandId,
+        result: CommandResult,
+        at: jiff::Timestamp,
+    ) -> Result<bool, Self::Error> {
+        let columns = ResultColumns::from(&result);
+        let timed_out = ResultColumns::from(&CommandResult::TimedOut);
+        let replaces_timeout = replaces_result(Some(&CommandResult::TimedOut), &result);
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE actuator_commands SET result = ?, open_until = ?, completed_at = ?
+            WHERE id = ? AND (result IS NULL OR (? AND result = ?))
+            "#,
+        )
+        .bind(columns.result)
+        .bind(columns.open_until)
+        .bind(at.as_second())
+        .bind(id.0.to_string())
+        .bind(replaces_timeout)
+        .bind(timed_out.result)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(updated.rows_affected() > 0)
+    }
+
+    async fn expire_commands(&self, now: jiff::Timestamp) -> Result<usize, Self::Error> {
+        let timed_out = ResultColumns::from(&CommandResult::TimedOut);
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE actuator_commands SET result = ?, completed_at = ?
+            WHERE result IS NULL AND expires_at <= ?
+            "#,
+        )
+        .bind(timed_out.result)
+        .bind(now.as_second())
+        .bind(now.as_second())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(updated.rows_affected() as usize)
+    }
+}
+
+const COMMAND_COLUMNS: &str = "id, device_id, actuator_id, action, duration_secs, issued_at, expires_at, result, open_until, completed_at";
+
+impl SqliteDeviceRegistry {
+    async fn add_actuators(
+        &self,
+        id: DeviceId,
+        actuators: &[Actuator],
+    ) -> Result<(), SqliteDeviceError> {
+        let mut tx = self.pool.begin().await?;
+
+        for actuator in actuators {
+            sqlx::query(
+                r#"
+             INSERT OR REPLACE INTO actuators (id, kind, max_run_secs, device_id)
+             VALUES (?, ?, ?, ?)
+            "#,
+            )
+            .bind(actuator.id.0.to_string())
+            .bind(actuator_kind_code(actuator.kind))
+            .bind(actuator.max_run_secs as i64)
+            .bind(id.0.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn map_row_to_calibration(
+        &self,
+        row: SqliteRow,
+    ) -> Result<CalibrationProfile, SqliteDeviceError> {
+        let id_str: String = row.try_get("sensor_id")?;
+        let ulid = Ulid::from_str(&id_str).map_err(|_| SqliteDeviceError::InvalidUlid(id_str))?;
+
+        let updated_at: i64 = row.try_get("updated_at")?;
+        let updated_at = jiff::Timestamp::from_second(updated_at)
+            .map_err(|_| SqliteDeviceError::InvalidTimestamp(updated_at))?;
+
+        let not_nan =
+            |v: f64| NotNan::new(v).map_err(|_| SqliteDeviceError::InvalidCalibrationValue);
+
+        let calibration = match row.try_get::<i32, _>("kind")? {
+            0 => {
+                let gain: Option<f64> = row.try_get("gain")?;
+                let offset: Option<f64> = row.try_get("offset_value")?;
+                Calibration::Linear {
+                    gain: not_nan(gain.ok_or(SqliteDeviceError::InvalidCalibrationValue)?)?,
+                    offset: not_nan(offset.ok_or(SqliteDeviceError::InvalidCalibrationValue)?)?,
+                }
+            }
+            1 => {
+                let point_rows = sqlx::query(
+                    "SELECT raw, calibrated FROM sensor_calibration_points WHERE sensor_id = ? ORDER BY position",
+                )
+                .bind(ulid.to_string())
+                .fetch_all(&self.pool)
+                .await?;
+
+                let mut points = Vec::with_capacity(point_rows.len());
+                for p in point_rows {
+                    points.push((
+                        not_nan(p.try_get("raw")?)?,
+                        not_nan(p.try_get("calibrated")?)?,
+                    ));
+                }
+
+                Calibration::Lookup {
+                    points: points.into_boxed_slice(),
+                }
+            }
+            other => return Err(SqliteDeviceError::InvalidCalibrationKind(other)),
+        };
+
+        Ok(CalibrationProfile {
+            sensor_id: SensorId(ulid),
+            calibration,
+            updated_at,
+        })
+    }
 }
 
 fn map_row_to_device(r: SqliteRow) -> Result<Device, SqliteDeviceError> {
@@ -376,11 +985,13 @@ fn map_row_to_device(r: SqliteRow) -> Result<Device, SqliteDeviceError> {
         id: DeviceId(ulid),
         kind: match r.try_get::<i32, _>("kind")? {
             0 => DeviceKind::Sensor,
+            1 => DeviceKind::Actuator,
             other => return Err(SqliteDeviceError::InvalidDeviceKind(other)),
         },
         state: match r.try_get::<i32, _>("state")? {
             0 => DeviceState::Active,
             1 => DeviceState::Suspended,
+            2 => DeviceState::Retired,
             other => return Err(SqliteDeviceError::InvalidState(other)),
         },
         location: H3Cell(r.try_get::<i64, _>("location")? as u64),
@@ -389,6 +1000,74 @@ fn map_row_to_device(r: SqliteRow) -> Result<Device, SqliteDeviceError> {
             .map(|s| s.into_boxed_str()),
         provisioned_at: jiff::Timestamp::from_second(provisioned_at).unwrap(),
         sensors: vec![].into_boxed_slice(),
+        actuators: vec![].into_boxed_slice(),
+        organization: parse_organization(r.try_get("organization_id")?)?,
+    })
+}
+
+fn parse_organization(id: Option<String>) -> Result<Option<OrganizationId>, SqliteDeviceError> {
+    id.map(|id| {
+        Ulid::from_str(&id)
+            .map(OrganizationId)
+            .map_err(|_| SqliteDeviceError::InvalidUlid(id))
+    })
+    .transpose()
+}
+
+fn map_row_to_actuator(row: SqliteRow) -> Result<Actuator, SqliteDeviceError> {
+    let id_str: String = row.try_get("id")?;
+    let ulid = Ulid::from_str(&id_str).map_err(|_| SqliteDeviceError::InvalidUlid(id_str))?;
+
+    Ok(Actuator {
+        id: ActuatorId(ulid),
+        kind: decode_actuator_kind(row.try_get("kind")?)
+            .map_err(SqliteDeviceError::InvalidActuatorKind)?,
+        max_run_secs: row.try_get::<i64, _>("max_run_secs")? as u32,
+    })
+}
+
+fn map_row_to_command(row: SqliteRow) -> Result<CommandRecord, SqliteDeviceError> {
+    let ulid = |column: &str| -> Result<Ulid, SqliteDeviceError> {
+        let id_str: String = row.try_get(column)?;
+        Ulid::from_str(&id_str).map_err(|_| SqliteDeviceError::InvalidUlid(id_str))
+    };
+    let timestamp = |secs: i64| {
+        jiff::Timestamp::from_second(secs).map_err(|_| SqliteDeviceError::InvalidTimestamp(secs))
+    };
+
+    let action = ActionColumns {
+        action: row.try_get("action")?,
+        duration_secs: row.try_get::<i64, _>("duration_secs")? as u32,
+    }
+    .decode()
+    .map_err(SqliteDeviceError::InvalidCommandAction)?;
+
+    let result = match row.try_get::<Option<i32>, _>("result")? {
+        Some(result) => Some(
+            ResultColumns {
+                result,
+                open_until: row.try_get("open_until")?,
+            }
+            .decode()
+            .map_err(SqliteDeviceError::InvalidCommandResult)?,
+        ),
+        None => None,
+    };
+
+    Ok(CommandRecord {
+        command: ActuatorCommand {
+            id: CommandId(ulid("id")?),
+            device_id: DeviceId(ulid("device_id")?),
+            actuator_id: ActuatorId(ulid("actuator_id")?),
+            action,
+            issued_at: timestamp(row.try_get("issued_at")?)?,
+            expires_at: timestamp(row.try_get("expires_at")?)?,
+        },
+        result,
+        completed_at: row
+            .try_get::<Option<i64>, _>("completed_at")?
+            .map(timestamp)
+            .transpose()?,
     })
 }
 
@@ -400,33 +1079,9 @@ fn map_row_to_sensor(row: SqliteRow) -> Result<Sensor, SqliteDeviceError> {
     let metric_type: i32 = row.try_get("metric_type")?;
     let metric_value: f64 = row.try_get("metric_value")?;
 
-    let kind = match kind_int {
-        0 => SensorKind::SoilMoisture,
-        1 => SensorKind::SoilTemp,
-        2 => SensorKind::AirTemp,
-        3 => SensorKind::Humidity,
-        4 => SensorKind::Rainfall,
-        other => return Err(SqliteDeviceError::InvalidSensorKind(other)),
-    };
-
-    let metric = match metric_type {
-        0 => SensorMetric::SoilMoisture {
-            value: Percentage(metric_value as u8),
-        },
-        1 => SensorMetric::SoilTemp {
-            value: NotNan::new(metric_value).expect("database should not contain NaN"),
-        },
-        2 => SensorMetric::AirTemp {
-            value: NotNan::new(metric_value).expect("database should not contain NaN"),
-        },
-        3 => SensorMetric::Humidity {
-            value: Percentage(metric_value as u8),
-        },
-        4 => SensorMetric::Rainfall {
-            value: NotNan::new(metric_value).expect("database should not contain NaN"),
-        },
-        other => return Err(SqliteDeviceError::InvalidMetricType(other)),
-    };
+    let kind = decode_sensor_kind(kind_int).map_err(SqliteDeviceError::InvalidSensorKind)?;
+    let metric =
+        decode_metric(metric_type, metric_value).map_err(SqliteDeviceError::InvalidMetricType)?;
 
     Ok(Sensor {
         id: SensorId(ulid),
@@ -487,6 +1142,7 @@ fn filter_devices<'a>(
         for kind in kinds {
             let val = match kind {
                 DeviceKind::Sensor => 0,
+                DeviceKind::Actuator => 1,
             };
             separated.push_bind(val);
         }
@@ -505,6 +1161,14 @@ fn filter_devices<'a>(
         separated.push_unseparated(")");
     }
 
+    cells::push_spatial_filters(
+        &mut query_builder,
+        &mut prefix,
+        filter.ancestor,
+        filter.ring,
+        filter.bbox,
+    );
+
     if let Some(after) = filter.provisioned_after {
         prefix(&mut query_builder);
         query_builder
@@ -536,17 +1200,34 @@ fn filter_devices<'a>(
             .push_bind(format!("%{}%", pattern));
     }
 
-    query_builder
-}
+    if let Some(sensor_ids) = filter.sensor_ids
+        && !sensor_ids.is_empty()
+    {
+        prefix(&mut query_builder);
+        query_builder.push("id IN (SELECT device_id FROM sensors WHERE id IN (");
+        let mut separated = query_builder.separated(", ");
+        for id in sensor_ids {
+            separated.push_bind(id.0.to_string());
+        }
+        separated.push_unseparated("))");
+    }
 
-fn disect_metric(metric: SensorMetric) -> (i32, f64) {
-    match metric {
-        SensorMetric::SoilMoisture { value } => (0, value.0 as f64),
-        SensorMetric::SoilTemp { value } => (1, value.into_inner()),
-        SensorMetric::AirTemp { value } => (2, value.into_inner()),
-        SensorMetric::Humidity { value } => (3, value.0 as f64),
-        SensorMetric::Rainfall { value } => (4, value.into_inner()),
+    if let Some(organization) = filter.organization {
+        prefix(&mut query_builder);
+        query_builder
+            .push("organization_id = ")
+            .push_bind(organization.0.to_string());
+    }
+
+    if let Some(field) = filter.field {
+        prefix(&mut query_builder);
+        query_builder
+            .push("id IN (SELECT device_id FROM plot_devices WHERE plot_id IN (SELECT id FROM plots WHERE field_id = ")
+            .push_bind(field.0.to_string())
+            .push("))");
     }
+
+    query_builder
 }
 
 #[cfg(test)]
@@ -555,14 +1236,18 @@ mod tests {
     use ordered_float::NotNan;
     use ulid::Ulid;
 
+    use crate::geo;
     use crate::registry::DeviceRegistry;
     use crate::registry::filter::{
-        DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder,
+        BoundingBox, DeviceFilter, DeviceSortBy, Pagination, QueryOptions, SortOrder,
     };
     use ersha_core::{
-        Device, DeviceId, DeviceKind, DeviceState, H3Cell, Sensor, SensorId, SensorKind,
-        SensorMetric,
+        Actuator, ActuatorAction, ActuatorCommand, ActuatorId, ActuatorKind, ActuatorState,
+        Calibration, CommandId, CommandResult, Device, DeviceFirmware, DeviceId, DeviceKey,
+        DeviceKind, DeviceState, FirmwareFailure, FirmwareId, FirmwareImage, FirmwareStatus,
+        FirmwareUpdate, H3Cell, OrganizationId, Sensor, SensorId, SensorKind, SensorMetric,
     };
+    use h3o::{LatLng, Resolution};
 
     use super::SqliteDeviceRegistry;
 
@@ -582,6 +1267,8 @@ mod tests {
                 },
             }]
             .into_boxed_slice(),
+            actuators: vec![].into_boxed_slice(),
+            organization: None,
         }
     }
 
@@ -643,6 +1330,99 @@ mod tests {
         assert_eq!(results[0].manufacturer.as_deref(), Some("Apple"));
     }
 
+    #[tokio::test]
+    async fn test_filter_by_organization_and_sensor() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+        let organization = OrganizationId(Ulid::new());
+
+        let mut owned = mock_device(Ulid::new());
+        owned.organization = Some(organization);
+        let other = mock_device(Ulid::new());
+        let sensor_id = other.sensors[0].id;
+
+        registry.register(owned.clone()).await.unwrap();
+        registry.register(other.clone()).await.unwrap();
+
+        let options = |filter| QueryOptions {
+            filter,
+            sort_by: DeviceSortBy::ProvisionAt,
+            sort_order: SortOrder::Asc,
+            pagination: Pagination::Offset {
+                offset: 0,
+                limit: 10,
+            },
+        };
+
+        let results = registry
+            .list(options(
+                DeviceFilter::builder().organization(organization).build(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, owned.id);
+        assert_eq!(results[0].organization, Some(organization));
+
+        let results = registry
+            .list(options(
+                DeviceFilter::builder().sensor_ids([sensor_id]).build(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, other.id);
+
+        let filter = DeviceFilter::builder()
+            .sensor_ids([sensor_id])
+            .organization(organization)
+            .build();
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spatial_filters() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+        let near = mock_device(Ulid::new());
+        let far = Device {
+            location: H3Cell(u64::from(
+                LatLng::new(-1.29, 36.82).unwrap().to_cell(Resolution::Ten),
+            )),
+            ..mock_device(Ulid::new())
+        };
+        registry.register(near.clone()).await.unwrap();
+        registry.register(far.clone()).await.unwrap();
+
+        let district = geo::parent(near.location, 5).unwrap();
+        let filter = DeviceFilter::builder().ancestor(district).build();
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 1);
+
+        let filter = DeviceFilter::builder()
+            .ring(geo::parent(far.location, 7).unwrap(), 2)
+            .build();
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 1);
+
+        let (lat, lng) = geo::center(near.location).unwrap();
+        let bbox = BoundingBox {
+            south: lat - 0.1,
+            west: lng - 0.1,
+            north: lat + 0.1,
+            east: lng + 0.1,
+        };
+        let filter = DeviceFilter::builder().bbox(bbox).build();
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 1);
+
+        // Conditions combine, and coarser locations never match finer ancestors.
+        let filter = DeviceFilter::builder()
+            .ancestor(district)
+            .ring(far.location, 1)
+            .build();
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 0);
+        let filter = DeviceFilter::builder()
+            .ancestor(geo::parent(near.location, 10).unwrap())
+            .build();
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 1);
+    }
+
     #[tokio::test]
     async fn test_suspend_device() {
         let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
@@ -657,6 +1437,293 @@ mod tests {
         assert_eq!(fetched.state, DeviceState::Suspended);
     }
 
+    #[tokio::test]
+    async fn test_reactivate_and_retire_device() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+
+        let id = Ulid::new();
+        registry.register(mock_device(id)).await.unwrap();
+        registry.suspend(DeviceId(id)).await.unwrap();
+        registry.reactivate(DeviceId(id)).await.unwrap();
+
+        let fetched = registry.get(DeviceId(id)).await.unwrap().unwrap();
+        assert_eq!(fetched.state, DeviceState::Active);
+
+        registry.retire(DeviceId(id)).await.unwrap();
+
+        let fetched = registry.get(DeviceId(id)).await.unwrap().unwrap();
+        assert_eq!(fetched.state, DeviceState::Retired);
+        assert_eq!(fetched.sensors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_calibration_round_trip() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+
+        let device = mock_device(Ulid::new());
+        let sensor_id = device.sensors[0].id;
+        registry.register(device).await.unwrap();
+
+        let lookup = Calibration::Lookup {
+            points: vec![
+                (NotNan::new(0.0).unwrap(), NotNan::new(-1.0).unwrap()),
+                (NotNan::new(50.0).unwrap(), NotNan::new(48.5).unwrap()),
+            ]
+            .into_boxed_slice(),
+        };
+        registry
+            .set_calibration(sensor_id, Some(lookup.clone()))
+            .await
+            .unwrap();
+
+        let profile = registry.get_calibration(sensor_id).await.unwrap().unwrap();
+        assert_eq!(profile.calibration, lookup);
+
+        let linear = Calibration::Linear {
+            gain: NotNan::new(0.98).unwrap(),
+            offset: NotNan::new(0.4).unwrap(),
+        };
+        registry
+            .set_calibration(sensor_id, Some(linear.clone()))
+            .await
+            .unwrap();
+
+        let profiles = registry.list_calibrations(None).await.unwrap();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].calibration, linear);
+
+        registry.set_calibration(sensor_id, None).await.unwrap();
+        assert!(registry.get_calibration(sensor_id).await.unwrap().is_none());
+
+        let unknown = registry
+            .set_calibration(SensorId(Ulid::new()), Some(linear))
+            .await;
+        assert!(matches!(unknown, Err(super::SqliteDeviceError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_device_keys() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+
+        let id = Ulid::new();
+        registry.register(mock_device(id)).await.unwrap();
+
+        registry
+            .set_key(DeviceId(id), DeviceKey([1; 32]))
+            .await
+            .unwrap();
+        registry
+            .set_key(DeviceId(id), DeviceKey([2; 32]))
+            .await
+            .unwrap();
+
+        let keys = registry.list_keys(None).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].device_id, DeviceId(id));
+        assert_eq!(keys[0].key, DeviceKey([2; 32]));
+
+        // keys only go to the owning organization's dispatchers
+        let organization = OrganizationId(Ulid::new());
+        let mut owned = mock_device(Ulid::new());
+        owned.organization = Some(organization);
+        let owned_id = owned.id;
+        registry.register(owned).await.unwrap();
+        registry
+            .set_key(owned_id, DeviceKey([4; 32]))
+            .await
+            .unwrap();
+
+        let keys = registry.list_keys(Some(organization)).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].device_id, owned_id);
+        assert_eq!(registry.list_keys(None).await.unwrap().len(), 1);
+
+        let unknown = registry
+            .set_key(DeviceId(Ulid::new()), DeviceKey([3; 32]))
+            .await;
+        assert!(matches!(unknown, Err(super::SqliteDeviceError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_firmware_images() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+
+        let image = FirmwareImage {
+            id: FirmwareId(Ulid::new()),
+            model: "soil-v1".into(),
+            version: 2,
+            size: 10,
+            sha256: [7; 32],
+            signature: vec![1; 64].into_boxed_slice(),
+            created_at: jiff::Timestamp::from_second(1_700_000_000).unwrap(),
+        };
+        registry
+            .add_firmware(image.clone(), (0..10).collect())
+            .await
+            .unwrap();
+
+        let duplicate = FirmwareImage {
+            id: FirmwareId(Ulid::new()),
+            ..image.clone()
+        };
+        let result = registry.add_firmware(duplicate, vec![0]).await;
+        assert!(matches!(
+            result,
+            Err(super::SqliteDeviceError::AlreadyExists)
+        ));
+
+        assert_eq!(registry.list_firmware().await.unwrap(), vec![image.clone()]);
+
+        let chunk = registry.firmware_chunk(image.id, 8, 4).await.unwrap();
+        assert_eq!(chunk, Some(vec![8, 9]));
+        let unknown = registry
+            .firmware_chunk(FirmwareId(Ulid::new()), 0, 4)
+            .await
+            .unwrap();
+        assert_eq!(unknown, None);
+    }
+
+    #[tokio::test]
+    async fn test_reported_firmware() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+        let device_id = DeviceId(Ulid::new());
+        let report = |update, reported_at| DeviceFirmware {
+            device_id,
+            firmware: FirmwareStatus {
+                model: "soil-v1".into(),
+                version: 2,
+                update,
+            },
+            reported_at: jiff::Timestamp::from_second(reported_at).unwrap(),
+        };
+
+        assert_eq!(registry.get_firmware(device_id).await.unwrap(), None);
+
+        let downloading = report(
+            FirmwareUpdate::Downloading {
+                version: 3,
+                progress: Percentage(40),
+            },
+            1_700_000_000,
+        );
+        registry.record_firmware(downloading.clone()).await.unwrap();
+        assert_eq!(
+            registry.get_firmware(device_id).await.unwrap(),
+            Some(downloading)
+        );
+
+        let failed = report(
+            FirmwareUpdate::Failed {
+                version: 3,
+                reason: FirmwareFailure::HashMismatch,
+            },
+            1_700_000_060,
+        );
+        registry.record_firmware(failed.clone()).await.unwrap();
+
+        // a report delivered late does not replace a newer one
+        let late = report(FirmwareUpdate::Idle, 1_700_000_030);
+        registry.record_firmware(late).await.unwrap();
+        assert_eq!(
+            registry.get_firmware(device_id).await.unwrap(),
+            Some(failed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_actuators_and_commands() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+        let actuator = Actuator {
+            id: ActuatorId(Ulid::new()),
+            kind: ActuatorKind::Pump,
+            max_run_secs: 1800,
+        };
+        let device = Device {
+            kind: DeviceKind::Actuator,
+            actuators: vec![actuator.clone()].into_boxed_slice(),
+            ..mock_device(Ulid::new())
+        };
+        registry.register(device.clone()).await.unwrap();
+
+        let fetched = registry.get(device.id).await.unwrap().unwrap();
+        assert_eq!(fetched.kind, DeviceKind::Actuator);
+        assert_eq!(fetched.actuators.as_ref(), std::slice::from_ref(&actuator));
+
+        let now = jiff::Timestamp::from_second(1_700_000_000).unwrap();
+        let command = |action| ActuatorCommand {
+            id: CommandId(Ulid::new()),
+            device_id: device.id,
+            actuator_id: actuator.id,
+            action,
+            issued_at: now,
+            expires_at: now + jiff::SignedDuration::from_secs(60),
+        };
+        let open = command(ActuatorAction::Open { duration_secs: 600 });
+        let close = command(ActuatorAction::Close);
+        registry.add_command(open.clone()).await.unwrap();
+        registry.add_command(close.clone()).await.unwrap();
+        assert!(registry.add_command(open.clone()).await.is_err());
+
+        let pending = registry.pending_commands(now, None).await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert!(pending.contains(&open));
+
+        let result = CommandResult::Applied {
+            state: ActuatorState::Open {
+                until: now + jiff::SignedDuration::from_secs(600),
+            },
+        };
+        assert!(
+            registry
+                .complete_command(open.id, result, now)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !registry
+                .complete_command(open.id, CommandResult::Failed, now)
+                .await
+                .unwrap()
+        );
+
+        let later = now + jiff::SignedDuration::from_secs(60);
+        assert_eq!(registry.expire_commands(later).await.unwrap(), 1);
+        assert!(
+            registry
+                .pending_commands(later, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let record = registry.get_command(open.id).await.unwrap().unwrap();
+        assert_eq!(record.command, open);
+        assert_eq!(record.result, Some(result));
+        assert_eq!(record.completed_at, Some(now));
+        let record = registry.get_command(close.id).await.unwrap().unwrap();
+        assert_eq!(record.result, Some(CommandResult::TimedOut));
+        assert_eq!(registry.list_commands(device.id).await.unwrap().len(), 2);
+
+        // a late acknowledgement from the device replaces the timeout
+        let closed = CommandResult::Applied {
+            state: ActuatorState::Closed,
+        };
+        assert!(
+            registry
+                .complete_command(close.id, closed, later)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !registry
+                .complete_command(close.id, CommandResult::TimedOut, later)
+                .await
+                .unwrap()
+        );
+        let record = registry.get_command(close.id).await.unwrap().unwrap();
+        assert_eq!(record.result, Some(closed));
+    }
+
     #[tokio::test]
     async fn test_add_sensor_individually() {
         let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
@@ -684,4 +1751,26 @@ mod tests {
         assert_eq!(fetched.sensors.len(), 1);
         assert!(matches!(fetched.sensors[0].kind, SensorKind::Humidity));
     }
+
+    #[tokio::test]
+    async fn test_remove_sensor() {
+        let registry = SqliteDeviceRegistry::new_in_memory().await.unwrap();
+
+        let device = mock_device(Ulid::new());
+        let (d_id, sensor_id) = (device.id, device.sensors[0].id);
+        registry.register(device).await.unwrap();
+
+        assert!(registry.remove_sensor(d_id, sensor_id).await.unwrap());
+        assert!(!registry.remove_sensor(d_id, sensor_id).await.unwrap());
+
+        let fetched = registry.get(d_id).await.unwrap().unwrap();
+        assert!(fetched.sensors.is_empty());
+
+        assert!(
+            registry
+                .remove_sensor(DeviceId(Ulid::new()), sensor_id)
+                .await
+                .is_err()
+        );
+    }
 }
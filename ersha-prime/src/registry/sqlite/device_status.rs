@@ -1,8 +1,8 @@
 use std::str::FromStr;
 
 use ersha_core::{
-    DeviceError, DeviceErrorCode, DeviceId, DeviceStatus, DispatcherId, Percentage, SensorId,
-    SensorState, SensorStatus, StatusId,
+    DeviceError, DeviceErrorCode, DeviceId, DeviceStatus, DispatcherId, FirmwareStatus, Percentage,
+    SensorId, SensorState, SensorStatus, StatusId,
 };
 use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions};
 use ulid::Ulid;
@@ -12,6 +12,7 @@ use async_trait::async_trait;
 use crate::registry::{
     DeviceStatusRegistry,
     filter::{DeviceStatusFilter, DeviceStatusSortBy, Pagination, QueryOptions, SortOrder},
+    firmware::FirmwareUpdateColumns,
 };
 
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
@@ -30,6 +31,8 @@ pub enum SqliteDeviceStatusError {
     InvalidErrorCode(i32),
     #[error("invalid sensor state: {0}")]
     InvalidSensorState(i32),
+    #[error("invalid firmware update state: {0}")]
+    InvalidFirmwareUpdate(i32),
 }
 
 #[derive(Clone)]
@@ -62,75 +65,7 @@ impl DeviceStatusRegistry for SqliteDeviceStatusRegistry {
 
     async fn store(&self, status: DeviceStatus) -> Result<(), Self::Error> {
         let mut tx = self.pool.begin().await?;
-
-        sqlx::query(
-            r#"
-            INSERT OR REPLACE INTO device_statuses (id, device_id, dispatcher_id, battery_percent, uptime_seconds, signal_rssi, timestamp)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
-            "#,
-        )
-        .bind(status.id.0.to_string())
-        .bind(status.device_id.0.to_string())
-        .bind(status.dispatcher_id.0.to_string())
-        .bind(status.battery_percent.0 as i32)
-        .bind(status.uptime_seconds as i64)
-        .bind(status.signal_rssi as i32)
-        .bind(status.timestamp.as_second())
-        .execute(&mut *tx)
-        .await?;
-
-        sqlx::query("DELETE FROM device_status_errors WHERE status_id = ?")
-            .bind(status.id.0.to_string())
-            .execute(&mut *tx)
-            .await?;
-
-        for error in status.errors.iter() {
-            let error_code = match error.code {
-                DeviceErrorCode::LowBattery => 0,
-                DeviceErrorCode::SensorFault => 1,
-                DeviceErrorCode::RadioFault => 2,
-                DeviceErrorCode::Unknown => 3,
-            };
-
-            sqlx::query(
-                r#"
-                INSERT INTO device_status_errors (status_id, error_code, message)
-                VALUES (?, ?, ?)
-                "#,
-            )
-            .bind(status.id.0.to_string())
-            .bind(error_code)
-            .bind(error.message.as_deref())
-            .execute(&mut *tx)
-            .await?;
-        }
-
-        sqlx::query("DELETE FROM device_status_sensor_statuses WHERE status_id = ?")
-            .bind(status.id.0.to_string())
-            .execute(&mut *tx)
-            .await?;
-
-        for sensor_status in status.sensor_statuses.iter() {
-            let state = match sensor_status.state {
-                SensorState::Active => 0,
-                SensorState::Faulty => 1,
-                SensorState::Inactive => 2,
-            };
-
-            sqlx::query(
-                r#"
-                INSERT INTO device_status_sensor_statuses (status_id, sensor_id, state, last_reading)
-                VALUES (?, ?, ?, ?)
-                "#,
-            )
-            .bind(status.id.0.to_string())
-            .bind(sensor_status.sensor_id.0.to_string())
-            .bind(state)
-            .bind(sensor_status.last_reading.map(|t| t.as_second()))
-            .execute(&mut *tx)
-            .await?;
-        }
-
+        insert_status(&mut tx, &status, true).await?;
         tx.commit().await?;
         Ok(())
     }
@@ -176,9 +111,11 @@ impl DeviceStatusRegistry for SqliteDeviceStatusRegistry {
     }
 
     async fn batch_store(&self, statuses: Vec<DeviceStatus>) -> Result<(), Self::Error> {
-        for status in statuses {
-            self.store(status).await?;
+        let mut tx = self.pool.begin().await?;
+        for status in &statuses {
+            insert_status(&mut tx, status, false).await?;
         }
+        tx.commit().await?;
         Ok(())
     }
 
@@ -239,6 +176,119 @@ impl DeviceStatusRegistry for SqliteDeviceStatusRegistry {
     }
 }
 
+/// Insert a status and its errors, sensor statuses and firmware. An existing
+/// status with the same id is replaced if `replace` is set, and otherwise
+/// left as it is.
+pub(super) async fn insert_status(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    status: &DeviceStatus,
+    replace: bool,
+) -> Result<(), sqlx::Error> {
+    let insert = if replace {
+        "INSERT OR REPLACE"
+    } else {
+        "INSERT OR IGNORE"
+    };
+    let inserted = sqlx::query(&format!(
+        r#"
+        {insert} INTO device_statuses (id, device_id, dispatcher_id, battery_percent, uptime_seconds, signal_rssi, timestamp)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#
+    ))
+    .bind(status.id.0.to_string())
+    .bind(status.device_id.0.to_string())
+    .bind(status.dispatcher_id.0.to_string())
+    .bind(status.battery_percent.0 as i32)
+    .bind(status.uptime_seconds as i64)
+    .bind(status.signal_rssi as i32)
+    .bind(status.timestamp.as_second())
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        return Ok(());
+    }
+
+    sqlx::query("DELETE FROM device_status_errors WHERE status_id = ?")
+        .bind(status.id.0.to_string())
+        .execute(&mut **tx)
+        .await?;
+
+    for error in status.errors.iter() {
+        let error_code = match error.code {
+            DeviceErrorCode::LowBattery => 0,
+            DeviceErrorCode::SensorFault => 1,
+            DeviceErrorCode::RadioFault => 2,
+            DeviceErrorCode::Unknown => 3,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO device_status_errors (status_id, error_code, message)
+            VALUES (?, ?, ?)
+            "#,
+        )
+        .bind(status.id.0.to_string())
+        .bind(error_code)
+        .bind(error.message.as_deref())
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    sqlx::query("DELETE FROM device_status_sensor_statuses WHERE status_id = ?")
+        .bind(status.id.0.to_string())
+        .execute(&mut **tx)
+        .await?;
+
+    for sensor_status in status.sensor_statuses.iter() {
+        let state = match sensor_status.state {
+            SensorState::Active => 0,
+            SensorState::Faulty => 1,
+            SensorState::Inactive => 2,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO device_status_sensor_statuses (status_id, sensor_id, state, last_reading)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(status.id.0.to_string())
+        .bind(sensor_status.sensor_id.0.to_string())
+        .bind(state)
+        .bind(sensor_status.last_reading.map(|t| t.as_second()))
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    sqlx::query("DELETE FROM device_status_firmware WHERE status_id = ?")
+        .bind(status.id.0.to_string())
+        .execute(&mut **tx)
+        .await?;
+
+    if let Some(firmware) = &status.firmware {
+        let update = FirmwareUpdateColumns::from(&firmware.update);
+
+        sqlx::query(
+            r#"
+            INSERT INTO device_status_firmware (status_id, model, version, update_state, target_version, progress, failure)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(status.id.0.to_string())
+        .bind(firmware.model.as_ref())
+        .bind(firmware.version as i64)
+        .bind(update.state)
+        .bind(update.target_version as i64)
+        .bind(update.progress as i32)
+        .bind(update.failure)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
 impl SqliteDeviceStatusRegistry {
     async fn map_row_to_status(
         &self,
@@ -262,6 +312,7 @@ impl SqliteDeviceStatusRegistry {
 
         let errors = self.fetch_errors(StatusId(id)).await?;
         let sensor_statuses = self.fetch_sensor_statuses(StatusId(id)).await?;
+        let firmware = self.fetch_firmware(StatusId(id)).await?;
 
         Ok(DeviceStatus {
             id: StatusId(id),
@@ -273,9 +324,41 @@ impl SqliteDeviceStatusRegistry {
             errors,
             timestamp,
             sensor_statuses,
+            firmware,
         })
     }
 
+    async fn fetch_firmware(
+        &self,
+        status_id: StatusId,
+    ) -> Result<Option<FirmwareStatus>, SqliteDeviceStatusError> {
+        let row = sqlx::query(
+            r#"SELECT model, version, update_state, target_version, progress, failure FROM device_status_firmware WHERE status_id = ?"#,
+        )
+        .bind(status_id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let update = FirmwareUpdateColumns {
+            state: row.try_get("update_state")?,
+            target_version: row.try_get::<i64, _>("target_version")? as u32,
+            progress: row.try_get::<i32, _>("progress")? as u8,
+            failure: row.try_get("failure")?,
+        }
+        .decode()
+        .map_err(SqliteDeviceStatusError::InvalidFirmwareUpdate)?;
+
+        Ok(Some(FirmwareStatus {
+            model: row.try_get::<String, _>("model")?.into_boxed_str(),
+            version: row.try_get::<i64, _>("version")? as u32,
+            update,
+        }))
+    }
+
     async fn fetch_errors(
         &self,
         status_id: StatusId,
@@ -463,6 +546,14 @@ fn filter_statuses<'a>(
         separated.push_unseparated(")");
     }
 
+    if let Some(organization) = filter.organization {
+        prefix(&mut query_builder);
+        query_builder
+            .push("device_id IN (SELECT id FROM devices WHERE organization_id = ")
+            .push_bind(organization.0.to_string())
+            .push(")");
+    }
+
     query_builder
 }
 
@@ -476,7 +567,8 @@ mod tests {
         DeviceStatusFilter, DeviceStatusSortBy, Pagination, QueryOptions, SortOrder,
     };
     use ersha_core::{
-        DeviceError, DeviceErrorCode, DeviceId, DeviceStatus, DispatcherId, Percentage, StatusId,
+        DeviceError, DeviceErrorCode, DeviceId, DeviceStatus, DispatcherId, FirmwareStatus,
+        FirmwareUpdate, Percentage, StatusId,
     };
 
     use super::SqliteDeviceStatusRegistry;
@@ -492,6 +584,7 @@ mod tests {
             errors: vec![].into_boxed_slice(),
             timestamp: Timestamp::now(),
             sensor_statuses: vec![].into_boxed_slice(),
+            firmware: None,
         }
     }
 
@@ -519,6 +612,49 @@ mod tests {
         let fetched = registry.get(id).await.unwrap().unwrap();
         assert_eq!(fetched.id, id);
         assert_eq!(fetched.battery_percent.0, 85);
+        assert_eq!(fetched.firmware, None);
+    }
+
+    #[tokio::test]
+    async fn test_batch_store_skips_stored_ids() {
+        let registry = SqliteDeviceStatusRegistry::new_in_memory().await.unwrap();
+        let id = StatusId(Ulid::new());
+        let device_id = DeviceId(Ulid::new());
+        let error = DeviceError {
+            code: DeviceErrorCode::LowBattery,
+            message: None,
+        };
+
+        let status = mock_status_with_errors(id, device_id, 85, vec![error.clone()]);
+        registry.batch_store(vec![status]).await.unwrap();
+
+        let replayed = mock_status_with_errors(id, device_id, 10, vec![error]);
+        registry.batch_store(vec![replayed]).await.unwrap();
+
+        let fetched = registry.get(id).await.unwrap().unwrap();
+        assert_eq!(fetched.battery_percent.0, 85);
+        assert_eq!(fetched.errors.len(), 1);
+        assert_eq!(registry.count(None).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_firmware_status_round_trip() {
+        let registry = SqliteDeviceStatusRegistry::new_in_memory().await.unwrap();
+        let id = StatusId(Ulid::new());
+        let mut status = mock_status(id, DeviceId(Ulid::new()), 85);
+        status.firmware = Some(FirmwareStatus {
+            model: "soil-v1".into(),
+            version: 2,
+            update: FirmwareUpdate::Downloading {
+                version: 3,
+                progress: Percentage(25),
+            },
+        });
+
+        registry.store(status.clone()).await.unwrap();
+
+        let fetched = registry.get(id).await.unwrap().unwrap();
+        assert_eq!(fetched.firmware, status.firmware);
     }
 
     #[tokio::test]
@@ -653,6 +789,7 @@ mod tests {
                 last_reading: Some(Timestamp::now()),
             }]
             .into_boxed_slice(),
+            firmware: None,
         };
 
         registry.store(status.clone()).await.unwrap();
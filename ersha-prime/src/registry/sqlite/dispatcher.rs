@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use ersha_core::{Dispatcher, DispatcherId, DispatcherState, H3Cell};
+use ersha_core::{Dispatcher, DispatcherId, DispatcherState, H3Cell, OrganizationId};
 use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions};
 use ulid::Ulid;
 
@@ -60,14 +60,15 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
     async fn register(&self, dispatcher: Dispatcher) -> Result<(), Self::Error> {
         sqlx::query(
             r#"
-            INSERT OR REPLACE INTO dispatchers (id, state, location, provisioned_at)
-            VALUES (?, ?, ?, ?)
+            INSERT OR REPLACE INTO dispatchers (id, state, location, provisioned_at, organization_id)
+            VALUES (?, ?, ?, ?, ?)
             "#,
         )
         .bind(dispatcher.id.0.to_string())
         .bind(dispatcher.state as i32)
         .bind(dispatcher.location.0 as i64)
         .bind(dispatcher.provisioned_at.as_second())
+        .bind(dispatcher.organization.map(|o| o.0.to_string()))
         .execute(&self.pool)
         .await?;
 
@@ -77,7 +78,8 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
     async fn get(&self, id: DispatcherId) -> Result<Option<Dispatcher>, Self::Error> {
         let row = sqlx::query(
             r#"
-            SELECT id, state, location, provisioned_at FROM dispatchers WHERE id = ?
+            SELECT id, state, location, provisioned_at, organization_id
+            FROM dispatchers WHERE id = ?
             "#,
         )
         .bind(id.0.to_string())
@@ -96,6 +98,7 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
             let state = match r.try_get::<i32, _>("state")? {
                 0 => DispatcherState::Active,
                 1 => DispatcherState::Suspended,
+                2 => DispatcherState::Retired,
                 other => return Err(SqliteDispatcherError::InvalidState(other)),
             };
 
@@ -104,6 +107,7 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
                 location: H3Cell(r.try_get::<i64, _>("location")? as u64),
                 state,
                 provisioned_at,
+                organization: parse_organization(r.try_get("organization_id")?)?,
             })
         })
         .transpose()
@@ -127,20 +131,43 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
         self.register(new).await
     }
 
+    async fn reactivate(&self, id: DispatcherId) -> Result<(), Self::Error> {
+        let dispatcher = self.get(id).await?.ok_or(SqliteDispatcherError::NotFound)?;
+
+        let new = Dispatcher {
+            state: DispatcherState::Active,
+            ..dispatcher
+        };
+
+        self.register(new).await
+    }
+
+    async fn retire(&self, id: DispatcherId) -> Result<(), Self::Error> {
+        let dispatcher = self.get(id).await?.ok_or(SqliteDispatcherError::NotFound)?;
+
+        let new = Dispatcher {
+            state: DispatcherState::Retired,
+            ..dispatcher
+        };
+
+        self.register(new).await
+    }
+
     async fn batch_register(&self, dispatchers: Vec<Dispatcher>) -> Result<(), Self::Error> {
         let mut tx = self.pool.begin().await?;
 
         for dispatcher in dispatchers {
             sqlx::query(
                 r#"
-                INSERT OR REPLACE INTO dispatchers (id, state, location, provisioned_at)
-                VALUES (?, ?, ?, ?)
+                INSERT OR REPLACE INTO dispatchers (id, state, location, provisioned_at, organization_id)
+                VALUES (?, ?, ?, ?, ?)
                 "#,
             )
             .bind(dispatcher.id.0.to_string())
             .bind(dispatcher.state as i32)
             .bind(dispatcher.location.0 as i64)
             .bind(dispatcher.provisioned_at.as_second())
+            .bind(dispatcher.organization.map(|o| o.0.to_string()))
             .execute(&mut *tx)
             .await?;
         }
@@ -166,8 +193,9 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
         &self,
         options: QueryOptions<DispatcherFilter, DispatcherSortBy>,
     ) -> Result<Vec<ersha_core::Dispatcher>, Self::Error> {
-        let mut query_builder =
-            QueryBuilder::new("SELECT id, state, location, provisioned_at FROM dispatchers");
+        let mut query_builder = QueryBuilder::new(
+            "SELECT id, state, location, provisioned_at, organization_id FROM dispatchers",
+        );
 
         query_builder = filter_dispatchers(query_builder, options.filter);
 
@@ -204,6 +232,7 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
                 let state = match r.try_get::<i32, _>("state")? {
                     0 => DispatcherState::Active,
                     1 => DispatcherState::Suspended,
+                    2 => DispatcherState::Retired,
                     other => return Err(SqliteDispatcherError::InvalidState(other)),
                 };
 
@@ -212,6 +241,7 @@ impl DispatcherRegistry for SqliteDispatcherRegistry {
                     provisioned_at,
                     state,
                     location: H3Cell(r.try_get::<i64, _>("location")? as u64),
+                    organization: parse_organization(r.try_get("organization_id")?)?,
                 })
             })
             .collect()
@@ -253,11 +283,27 @@ fn filter_dispatchers(
         }
 
         separated.push_unseparated(")");
+        has_where = true;
+    }
+
+    if let Some(organization) = filter.organization {
+        query_builder.push(if has_where { " AND " } else { " WHERE " });
+        query_builder.push("organization_id = ");
+        query_builder.push_bind(organization.0.to_string());
     }
 
     query_builder
 }
 
+fn parse_organization(id: Option<String>) -> Result<Option<OrganizationId>, SqliteDispatcherError> {
+    id.map(|id| {
+        Ulid::from_str(&id)
+            .map(OrganizationId)
+            .map_err(|_| SqliteDispatcherError::InvalidUlid(id))
+    })
+    .transpose()
+}
+
 #[cfg(test)]
 mod tests {
     use jiff::Timestamp;
@@ -267,7 +313,7 @@ mod tests {
     use crate::registry::filter::{
         DispatcherFilter, DispatcherSortBy, Pagination, QueryOptions, SortOrder,
     };
-    use ersha_core::{Dispatcher, DispatcherId, DispatcherState, H3Cell};
+    use ersha_core::{Dispatcher, DispatcherId, DispatcherState, H3Cell, OrganizationId};
 
     use super::SqliteDispatcherRegistry;
 
@@ -281,6 +327,7 @@ mod tests {
             state,
             location: H3Cell(0x1337deadbeef),
             provisioned_at,
+            organization: None,
         }
     }
 
@@ -320,6 +367,7 @@ mod tests {
             state: DispatcherState::Active,
             location: H3Cell(1),
             provisioned_at: Timestamp::now(),
+            organization: None,
         };
         registry.register(d1).await.unwrap();
 
@@ -355,6 +403,7 @@ mod tests {
             filter: DispatcherFilter {
                 states: None,
                 locations: None,
+                organization: None,
             },
             sort_by: DispatcherSortBy::ProvisionAt,
             sort_order: SortOrder::Asc,
@@ -415,6 +464,43 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_sqlite_organization_filter() {
+        let registry = SqliteDispatcherRegistry::new_in_memory().await.unwrap();
+        let organization = OrganizationId(Ulid::new());
+
+        let owned = DispatcherId(Ulid::new());
+        registry
+            .batch_register(vec![
+                Dispatcher {
+                    organization: Some(organization),
+                    ..dispatcher(owned, DispatcherState::Active, Timestamp::now())
+                },
+                dispatcher(
+                    DispatcherId(Ulid::new()),
+                    DispatcherState::Active,
+                    Timestamp::now(),
+                ),
+            ])
+            .await
+            .unwrap();
+
+        let filter = DispatcherFilter::builder()
+            .organization(organization)
+            .build();
+        let results = registry
+            .list(QueryOptions {
+                filter: filter.clone(),
+                ..default_options()
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, owned);
+        assert_eq!(results[0].organization, Some(organization));
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 1);
+    }
+
     #[tokio::test]
     async fn test_sqlite_pagination_offset_logic() {
         let registry = SqliteDispatcherRegistry::new_in_memory().await.unwrap();
@@ -474,4 +560,28 @@ mod tests {
 
         assert_eq!(registry.count(Some(active_filter)).await.unwrap(), 0);
     }
+
+    #[tokio::test]
+    async fn test_sqlite_reactivate_and_retire() {
+        let registry = SqliteDispatcherRegistry::new_in_memory().await.unwrap();
+        let id = DispatcherId(Ulid::new());
+
+        registry
+            .register(dispatcher(id, DispatcherState::Active, Timestamp::now()))
+            .await
+            .unwrap();
+
+        registry.suspend(id).await.unwrap();
+        registry.reactivate(id).await.unwrap();
+        assert_eq!(
+            registry.get(id).await.unwrap().unwrap().state,
+            DispatcherState::Active
+        );
+
+        registry.retire(id).await.unwrap();
+        assert_eq!(
+            registry.get(id).await.unwrap().unwrap().state,
+            DispatcherState::Retired
+        );
+    }
 }
@@ -0,0 +1,661 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use ersha_core::{
+    Boundary, DeviceId, Farm, FarmId, Field, FieldId, GeoPoint, H3Cell, OrganizationId, Plot,
+    PlotId,
+};
+use ordered_float::NotNan;
+use sqlx::{
+    Row, SqliteConnection, SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions,
+    sqlite::SqliteRow,
+};
+use ulid::Ulid;
+
+use crate::registry::FarmRegistry;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteFarmError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    #[error("invalid ULID: {0}")]
+    InvalidUlid(String),
+    #[error("invalid timestamp: {0}")]
+    InvalidTimestamp(i64),
+    #[error("invalid date: {0}")]
+    InvalidDate(String),
+    #[error("invalid boundary point")]
+    InvalidPoint,
+    #[error("not found")]
+    NotFound,
+}
+
+#[derive(Clone)]
+pub struct SqliteFarmRegistry {
+    pool: SqlitePool,
+}
+
+impl SqliteFarmRegistry {
+    pub async fn new(path: impl AsRef<str>) -> Result<Self, SqliteFarmError> {
+        let connection_string = format!("sqlite:{}", path.as_ref());
+        let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn new_in_memory() -> Result<Self, SqliteFarmError> {
+        let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    async fn boundary(&self, owner_id: &str) -> Result<Boundary, SqliteFarmError> {
+        let points = sqlx::query(
+            "SELECT lat, lng FROM boundary_points WHERE owner_id = ? ORDER BY position",
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(GeoPoint {
+                lat: NotNan::new(row.try_get("lat")?).map_err(|_| SqliteFarmError::InvalidPoint)?,
+                lng: NotNan::new(row.try_get("lng")?).map_err(|_| SqliteFarmError::InvalidPoint)?,
+            })
+        })
+        .collect::<Result<Vec<_>, SqliteFarmError>>()?;
+
+        let cells: Vec<i64> =
+            sqlx::query_scalar("SELECT cell FROM boundary_cells WHERE owner_id = ? ORDER BY cell")
+                .bind(owner_id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(Boundary {
+            ring: points.into_boxed_slice(),
+            cells: cells.into_iter().map(|c| H3Cell(c as u64)).collect(),
+        })
+    }
+
+    async fn map_farm(&self, row: &SqliteRow) -> Result<Farm, SqliteFarmError> {
+        let id: String = row.try_get("id")?;
+        Ok(Farm {
+            id: FarmId(parse_ulid(&id)?),
+            name: row.try_get::<String, _>("name")?.into_boxed_str(),
+            boundary: self.boundary(&id).await?,
+            organization: row
+                .try_get::<Option<String>, _>("organization_id")?
+                .map(|id| parse_ulid(&id).map(OrganizationId))
+                .transpose()?,
+            created_at: parse_timestamp(row.try_get("created_at")?)?,
+        })
+    }
+
+    async fn map_field(&self, row: &SqliteRow) -> Result<Field, SqliteFarmError> {
+        let id: String = row.try_get("id")?;
+        Ok(Field {
+            id: FieldId(parse_ulid(&id)?),
+            farm_id: FarmId(parse_ulid(&row.try_get::<String, _>("farm_id")?)?),
+            name: row.try_get::<String, _>("name")?.into_boxed_str(),
+            boundary: self.boundary(&id).await?,
+            crop: row
+                .try_get::<Option<String>, _>("crop")?
+                .map(String::into_boxed_str),
+            planted_on: parse_date(row.try_get("planted_on")?)?,
+            created_at: parse_timestamp(row.try_get("created_at")?)?,
+        })
+    }
+
+    async fn map_plot(&self, row: &SqliteRow) -> Result<Plot, SqliteFarmError> {
+        let id: String = row.try_get("id")?;
+        Ok(Plot {
+            id: PlotId(parse_ulid(&id)?),
+            field_id: FieldId(parse_ulid(&row.try_get::<String, _>("field_id")?)?),
+            name: row.try_get::<String, _>("name")?.into_boxed_str(),
+            boundary: self.boundary(&id).await?,
+            crop: row
+                .try_get::<Option<String>, _>("crop")?
+                .map(String::into_boxed_str),
+            planted_on: parse_date(row.try_get("planted_on")?)?,
+            created_at: parse_timestamp(row.try_get("created_at")?)?,
+        })
+    }
+}
+
+#[async_trait]
+impl FarmRegistry for SqliteFarmRegistry {
+    type Error = SqliteFarmError;
+
+    async fn add_farm(&self, farm: Farm) -> Result<(), Self::Error> {
+        let id = farm.id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO farms (id, name, organization_id, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(farm.name.as_ref())
+        .bind(farm.organization.map(|o| o.0.to_string()))
+        .bind(farm.created_at.as_second())
+        .execute(&mut *tx)
+        .await?;
+        write_boundary(&mut tx, &id, &farm.boundary).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_farm(&self, id: FarmId) -> Result<Option<Farm>, Self::Error> {
+        let row =
+            sqlx::query("SELECT id, name, organization_id, created_at FROM farms WHERE id = ?")
+                .bind(id.0.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+
+        match row {
+            Some(row) => Ok(Some(self.map_farm(&row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_farms(
+        &self,
+        organization: Option<OrganizationId>,
+    ) -> Result<Vec<Farm>, Self::Error> {
+        let rows = match organization {
+            Some(organization) => {
+                sqlx::query(
+                    "SELECT id, name, organization_id, created_at FROM farms WHERE organization_id = ? ORDER BY name",
+                )
+                .bind(organization.0.to_string())
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query("SELECT id, name, organization_id, created_at FROM farms ORDER BY name")
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+
+        let mut farms = Vec::with_capacity(rows.len());
+        for row in &rows {
+            farms.push(self.map_farm(row).await?);
+        }
+        Ok(farms)
+    }
+
+    async fn update_farm(&self, farm: Farm) -> Result<(), Self::Error> {
+        let id = farm.id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let updated = sqlx::query("UPDATE farms SET name = ?, organization_id = ? WHERE id = ?")
+            .bind(farm.name.as_ref())
+            .bind(farm.organization.map(|o| o.0.to_string()))
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+        if updated.rows_affected() == 0 {
+            return Err(SqliteFarmError::NotFound);
+        }
+        write_boundary(&mut tx, &id, &farm.boundary).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_farm(&self, id: FarmId) -> Result<(), Self::Error> {
+        let id = id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let field_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM fields WHERE farm_id = ?")
+            .bind(&id)
+            .fetch_all(&mut *tx)
+            .await?;
+        for field_id in &field_ids {
+            delete_field(&mut tx, field_id).await?;
+        }
+
+        delete_boundary(&mut tx, &id).await?;
+        let deleted = sqlx::query("DELETE FROM farms WHERE id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(SqliteFarmError::NotFound);
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn add_field(&self, field: Field) -> Result<(), Self::Error> {
+        let id = field.id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO fields (id, farm_id, name, crop, planted_on, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(field.farm_id.0.to_string())
+        .bind(field.name.as_ref())
+        .bind(field.crop.as_deref())
+        .bind(field.planted_on.map(|d| d.to_string()))
+        .bind(field.created_at.as_second())
+        .execute(&mut *tx)
+        .await?;
+        write_boundary(&mut tx, &id, &field.boundary).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_field(&self, id: FieldId) -> Result<Option<Field>, Self::Error> {
+        let row = sqlx::query(
+            "SELECT id, farm_id, name, crop, planted_on, created_at FROM fields WHERE id = ?",
+        )
+        .bind(id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(self.map_field(&row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_fields(&self, farm_id: FarmId) -> Result<Vec<Field>, Self::Error> {
+        let rows = sqlx::query(
+            "SELECT id, farm_id, name, crop, planted_on, created_at FROM fields WHERE farm_id = ? ORDER BY name",
+        )
+        .bind(farm_id.0.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut fields = Vec::with_capacity(rows.len());
+        for row in &rows {
+            fields.push(self.map_field(row).await?);
+        }
+        Ok(fields)
+    }
+
+    async fn update_field(&self, field: Field) -> Result<(), Self::Error> {
+        let id = field.id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let updated =
+            sqlx::query("UPDATE fields SET name = ?, crop = ?, planted_on = ? WHERE id = ?")
+                .bind(field.name.as_ref())
+                .bind(field.crop.as_deref())
+                .bind(field.planted_on.map(|d| d.to_string()))
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        if updated.rows_affected() == 0 {
+            return Err(SqliteFarmError::NotFound);
+        }
+        write_boundary(&mut tx, &id, &field.boundary).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_field(&self, id: FieldId) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        if !delete_field(&mut tx, &id.0.to_string()).await? {
+            return Err(SqliteFarmError::NotFound);
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn add_plot(&self, plot: Plot) -> Result<(), Self::Error> {
+        let id = plot.id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO plots (id, field_id, name, crop, planted_on, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(plot.field_id.0.to_string())
+        .bind(plot.name.as_ref())
+        .bind(plot.crop.as_deref())
+        .bind(plot.planted_on.map(|d| d.to_string()))
+        .bind(plot.created_at.as_second())
+        .execute(&mut *tx)
+        .await?;
+        write_boundary(&mut tx, &id, &plot.boundary).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_plot(&self, id: PlotId) -> Result<Option<Plot>, Self::Error> {
+        let row = sqlx::query(
+            "SELECT id, field_id, name, crop, planted_on, created_at FROM plots WHERE id = ?",
+        )
+        .bind(id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(self.map_plot(&row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_plots(&self, field_id: FieldId) -> Result<Vec<Plot>, Self::Error> {
+        let rows = sqlx::query(
+            "SELECT id, field_id, name, crop, planted_on, created_at FROM plots WHERE field_id = ? ORDER BY name",
+        )
+        .bind(field_id.0.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut plots = Vec::with_capacity(rows.len());
+        for row in &rows {
+            plots.push(self.map_plot(row).await?);
+        }
+        Ok(plots)
+    }
+
+    async fn update_plot(&self, plot: Plot) -> Result<(), Self::Error> {
+        let id = plot.id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let updated =
+            sqlx::query("UPDATE plots SET name = ?, crop = ?, planted_on = ? WHERE id = ?")
+                .bind(plot.name.as_ref())
+                .bind(plot.crop.as_deref())
+                .bind(plot.planted_on.map(|d| d.to_string()))
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        if updated.rows_affected() == 0 {
+            return Err(SqliteFarmError::NotFound);
+        }
+        write_boundary(&mut tx, &id, &plot.boundary).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_plot(&self, id: PlotId) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        if !delete_plot(&mut tx, &id.0.to_string()).await? {
+            return Err(SqliteFarmError::NotFound);
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn assign_device(&self, device_id: DeviceId, plot_id: PlotId) -> Result<(), Self::Error> {
+        let plot_id = plot_id.0.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let known: Option<i64> = sqlx::query_scalar("SELECT 1 FROM plots WHERE id = ?")
+            .bind(&plot_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if known.is_none() {
+            return Err(SqliteFarmError::NotFound);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO plot_devices (device_id, plot_id) VALUES (?, ?)
+            ON CONFLICT(device_id) DO UPDATE SET plot_id = excluded.plot_id
+            "#,
+        )
+        .bind(device_id.0.to_string())
+        .bind(&plot_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn unassign_device(&self, device_id: DeviceId) -> Result<bool, Self::Error> {
+        let deleted = sqlx::query("DELETE FROM plot_devices WHERE device_id = ?")
+            .bind(device_id.0.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn plot_devices(&self, plot_id: PlotId) -> Result<Vec<DeviceId>, Self::Error> {
+        let ids: Vec<String> = sqlx::query_scalar(
+            "SELECT device_id FROM plot_devices WHERE plot_id = ? ORDER BY device_id",
+        )
+        .bind(plot_id.0.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        ids.iter().map(|id| parse_ulid(id).map(DeviceId)).collect()
+    }
+}
+
+async fn write_boundary(
+    conn: &mut SqliteConnection,
+    owner_id: &str,
+    boundary: &Boundary,
+) -> Result<(), SqliteFarmError> {
+    delete_boundary(conn, owner_id).await?;
+
+    for (position, point) in boundary.ring.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO boundary_points (owner_id, position, lat, lng) VALUES (?, ?, ?, ?)",
+        )
+        .bind(owner_id)
+        .bind(position as i64)
+        .bind(point.lat.into_inner())
+        .bind(point.lng.into_inner())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for cell in boundary.cells.iter() {
+        sqlx::query("INSERT INTO boundary_cells (owner_id, cell) VALUES (?, ?)")
+            .bind(owner_id)
+            .bind(cell.0 as i64)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn delete_boundary(
+    conn: &mut SqliteConnection,
+    owner_id: &str,
+) -> Result<(), SqliteFarmError> {
+    sqlx::query("DELETE FROM boundary_points WHERE owner_id = ?")
+        .bind(owner_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM boundary_cells WHERE owner_id = ?")
+        .bind(owner_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Delete a field with its plots. Returns `false` if it did not exist.
+async fn delete_field(
+    conn: &mut SqliteConnection,
+    field_id: &str,
+) -> Result<bool, SqliteFarmError> {
+    let plot_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM plots WHERE field_id = ?")
+        .bind(field_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    for plot_id in &plot_ids {
+        delete_plot(conn, plot_id).await?;
+    }
+
+    delete_boundary(conn, field_id).await?;
+    let deleted = sqlx::query("DELETE FROM fields WHERE id = ?")
+        .bind(field_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// Delete a plot with its device assignments. Returns `false` if it did not
+/// exist.
+async fn delete_plot(conn: &mut SqliteConnection, plot_id: &str) -> Result<bool, SqliteFarmError> {
+    sqlx::query("DELETE FROM plot_devices WHERE plot_id = ?")
+        .bind(plot_id)
+        .execute(&mut *conn)
+        .await?;
+
+    delete_boundary(conn, plot_id).await?;
+    let deleted = sqlx::query("DELETE FROM plots WHERE id = ?")
+        .bind(plot_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+fn parse_ulid(id: &str) -> Result<Ulid, SqliteFarmError> {
+    Ulid::from_str(id).map_err(|_| SqliteFarmError::InvalidUlid(id.to_string()))
+}
+
+fn parse_timestamp(seconds: i64) -> Result<jiff::Timestamp, SqliteFarmError> {
+    jiff::Timestamp::from_second(seconds).map_err(|_| SqliteFarmError::InvalidTimestamp(seconds))
+}
+
+fn parse_date(date: Option<String>) -> Result<Option<jiff::civil::Date>, SqliteFarmError> {
+    date.map(|date| date.parse().map_err(|_| SqliteFarmError::InvalidDate(date)))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{
+        Boundary, DeviceId, Farm, FarmId, Field, FieldId, GeoPoint, H3Cell, OrganizationId, Plot,
+        PlotId,
+    };
+    use ordered_float::NotNan;
+    use ulid::Ulid;
+
+    use crate::registry::FarmRegistry;
+
+    use super::SqliteFarmRegistry;
+
+    fn boundary(offset: u64) -> Boundary {
+        let point = |lat: f64, lng: f64| GeoPoint {
+            lat: NotNan::new(lat).unwrap(),
+            lng: NotNan::new(lng).unwrap(),
+        };
+        Boundary {
+            ring: vec![
+                point(-0.3, 36.08),
+                point(-0.3, 36.085),
+                point(-0.305, 36.08),
+            ]
+            .into_boxed_slice(),
+            cells: vec![
+                H3Cell(0x8b7a6e5a1c21fff + offset),
+                H3Cell(0x8b7a6e5a1c22fff),
+            ]
+            .into_boxed_slice(),
+        }
+    }
+
+    fn timestamp() -> jiff::Timestamp {
+        jiff::Timestamp::from_second(1_700_000_000).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_farm_hierarchy_round_trip() {
+        let registry = SqliteFarmRegistry::new_in_memory().await.unwrap();
+        let organization = OrganizationId(Ulid::new());
+        let farm = Farm {
+            id: FarmId(Ulid::new()),
+            name: "Kilimo".into(),
+            boundary: boundary(0),
+            organization: Some(organization),
+            created_at: timestamp(),
+        };
+        let field = Field {
+            id: FieldId(Ulid::new()),
+            farm_id: farm.id,
+            name: "North".into(),
+            boundary: boundary(0),
+            crop: Some("maize".into()),
+            planted_on: Some(jiff::civil::date(2025, 3, 15)),
+            created_at: timestamp(),
+        };
+        let plot = Plot {
+            id: PlotId(Ulid::new()),
+            field_id: field.id,
+            name: "A".into(),
+            boundary: boundary(0),
+            crop: None,
+            planted_on: None,
+            created_at: timestamp(),
+        };
+        registry.add_farm(farm.clone()).await.unwrap();
+        registry.add_field(field.clone()).await.unwrap();
+        registry.add_plot(plot.clone()).await.unwrap();
+
+        assert_eq!(
+            registry.get_farm(farm.id).await.unwrap(),
+            Some(farm.clone())
+        );
+        assert_eq!(
+            registry.list_fields(farm.id).await.unwrap(),
+            vec![field.clone()]
+        );
+        assert_eq!(
+            registry.list_plots(field.id).await.unwrap(),
+            vec![plot.clone()]
+        );
+        assert_eq!(
+            registry.list_farms(Some(organization)).await.unwrap().len(),
+            1
+        );
+        assert!(
+            registry
+                .list_farms(Some(OrganizationId(Ulid::new())))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let replanted = Field {
+            crop: Some("beans".into()),
+            boundary: boundary(1),
+            ..field.clone()
+        };
+        registry.update_field(replanted.clone()).await.unwrap();
+        assert_eq!(registry.get_field(field.id).await.unwrap(), Some(replanted));
+
+        let device = DeviceId(Ulid::new());
+        registry.assign_device(device, plot.id).await.unwrap();
+        assert_eq!(registry.plot_devices(plot.id).await.unwrap(), vec![device]);
+
+        registry.remove_farm(farm.id).await.unwrap();
+        assert!(registry.get_field(field.id).await.unwrap().is_none());
+        assert!(registry.get_plot(plot.id).await.unwrap().is_none());
+        assert!(!registry.unassign_device(device).await.unwrap());
+        assert!(registry.remove_farm(farm.id).await.is_err());
+    }
+}
@@ -0,0 +1,676 @@
+use std::str::FromStr;
+
+use ersha_core::{AlertId, DeviceId, DispatcherId, OrganizationId};
+use sqlx::{
+    Row, SqlitePool,
+    migrate::Migrator,
+    sqlite::{SqlitePoolOptions, SqliteRow},
+};
+use ulid::Ulid;
+
+use async_trait::async_trait;
+
+use crate::{
+    health::{
+        AlertSubject, ConnectionChange, ConnectionEvent, DeviceHealth, DispatcherHealth,
+        DispatcherStatusRecord, OfflineAlert, ReportedAlert,
+    },
+    registry::{
+        HealthRegistry,
+        health::{AlertColumns, ConnectionColumns},
+    },
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteHealthError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    #[error("invalid ULID: {0}")]
+    InvalidUlid(String),
+    #[error("invalid timestamp: {0}")]
+    InvalidTimestamp(i64),
+    #[error("invalid connection change code: {0}")]
+    InvalidConnectionChange(i32),
+    #[error("invalid alert code: {0}")]
+    InvalidAlert(i32),
+}
+
+#[derive(Clone)]
+pub struct SqliteHealthRegistry {
+    pool: SqlitePool,
+}
+
+impl SqliteHealthRegistry {
+    pub async fn new(path: impl AsRef<str>) -> Result<Self, SqliteHealthError> {
+        let connection_string = format!("sqlite:{}", path.as_ref());
+        let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn new_in_memory() -> Result<Self, SqliteHealthError> {
+        let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl HealthRegistry for SqliteHealthRegistry {
+    type Error = SqliteHealthError;
+
+    async fn record_status(&self, record: DispatcherStatusRecord) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO dispatcher_status_reports (dispatcher_id, connected_devices, uptime_seconds, pending_uploads, duplicates_dropped, reported_at, received_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(record.dispatcher_id.0.to_string())
+        .bind(record.connected_devices as i64)
+        .bind(record.uptime_seconds as i64)
+        .bind(record.pending_uploads as i64)
+        .bind(record.duplicates_dropped as i64)
+        .bind(record.reported_at.as_second())
+        .bind(record.received_at.as_second())
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO dispatcher_health (dispatcher_id, last_seen, online)
+            VALUES (?, ?, 1)
+            ON CONFLICT(dispatcher_id) DO UPDATE SET last_seen = excluded.last_seen, online = 1
+            "#,
+        )
+        .bind(record.dispatcher_id.0.to_string())
+        .bind(record.received_at.as_second())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_statuses(
+        &self,
+        dispatcher_id: DispatcherId,
+        limit: usize,
+    ) -> Result<Vec<DispatcherStatusRecord>, Self::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT dispatcher_id, connected_devices, uptime_seconds, pending_uploads, duplicates_dropped, reported_at, received_at
+            FROM dispatcher_status_reports WHERE dispatcher_id = ?
+            ORDER BY id DESC LIMIT ?
+            "#,
+        )
+        .bind(dispatcher_id.0.to_string())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(map_row_to_status).collect()
+    }
+
+    async fn dispatcher_health(
+        &self,
+        id: DispatcherId,
+    ) -> Result<Option<DispatcherHealth>, Self::Error> {
+        let row = sqlx::query(
+            "SELECT dispatcher_id, last_seen, online FROM dispatcher_health WHERE dispatcher_id = ?",
+        )
+        .bind(id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|r| map_row_to_dispatcher_health(&r)).transpose()
+    }
+
+    async fn record_seen(
+        &self,
+        dispatcher_id: DispatcherId,
+        devices: Vec<DeviceId>,
+        at: jiff::Timestamp,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut events = Vec::new();
+
+        for device_id in devices {
+            let online: Option<bool> =
+                sqlx::query_scalar("SELECT online FROM device_health WHERE device_id = ?")
+                    .bind(device_id.0.to_string())
+                    .fetch_optional(&mut *tx)
+                    .await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO device_health (device_id, dispatcher_id, last_seen, online)
+                VALUES (?, ?, ?, 1)
+                ON CONFLICT(device_id) DO UPDATE SET
+                    dispatcher_id = excluded.dispatcher_id,
+                    last_seen = excluded.last_seen,
+                    online = 1
+                "#,
+            )
+            .bind(device_id.0.to_string())
+            .bind(dispatcher_id.0.to_string())
+            .bind(at.as_second())
+            .execute(&mut *tx)
+            .await?;
+
+            if online != Some(true) {
+                let event = ConnectionEvent {
+                    device_id,
+                    dispatcher_id,
+                    change: ConnectionChange::Connected,
+                    timestamp: at,
+                };
+                insert_connection(&mut tx, &event).await?;
+                events.push(event);
+            }
+        }
+
+        tx.commit().await?;
+        Ok(events)
+    }
+
+    async fn record_disconnection(&self, event: ConnectionEvent) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE device_health SET online = 0 WHERE device_id = ?")
+            .bind(event.device_id.0.to_string())
+            .execute(&mut *tx)
+            .await?;
+        insert_connection(&mut tx, &event).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_connections(
+        &self,
+        device_id: DeviceId,
+        limit: usize,
+    ) -> Result<Vec<ConnectionEvent>, Self::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT device_id, dispatcher_id, kind, reason, message, timestamp
+            FROM device_connection_events WHERE device_id = ?
+            ORDER BY id DESC LIMIT ?
+            "#,
+        )
+        .bind(device_id.0.to_string())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(map_row_to_connection).collect()
+    }
+
+    async fn device_health(&self, id: DeviceId) -> Result<Option<DeviceHealth>, Self::Error> {
+        let row = sqlx::query(
+            "SELECT device_id, dispatcher_id, last_seen, online FROM device_health WHERE device_id = ?",
+        )
+        .bind(id.0.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|r| map_row_to_device_health(&r)).transpose()
+    }
+
+    async fn silent_dispatchers(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DispatcherHealth>, Self::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT dispatcher_id, last_seen, online FROM dispatcher_health
+            WHERE online = 1 AND last_seen < ?
+            "#,
+        )
+        .bind(cutoff.as_second())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(map_row_to_dispatcher_health).collect()
+    }
+
+    async fn silent_devices(
+        &self,
+        cutoff: jiff::Timestamp,
+    ) -> Result<Vec<DeviceHealth>, Self::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT device_id, dispatcher_id, last_seen, online FROM device_health
+            WHERE online = 1 AND last_seen < ?
+            "#,
+        )
+        .bind(cutoff.as_second())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(map_row_to_device_health).collect()
+    }
+
+    async fn raise_alert(&self, alert: OfflineAlert) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO offline_alerts (id, dispatcher_id, device_id, last_seen, raised_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(alert.id.0.to_string())
+        .bind(alert.dispatcher_id.0.to_string())
+        .bind(alert.device_id.map(|id| id.0.to_string()))
+        .bind(alert.last_seen.as_second())
+        .bind(alert.raised_at.as_second())
+        .execute(&mut *tx)
+        .await?;
+
+        match alert.device_id {
+            Some(device_id) => {
+                sqlx::query("UPDATE device_health SET online = 0 WHERE device_id = ?")
+                    .bind(device_id.0.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            None => {
+                sqlx::query("UPDATE dispatcher_health SET online = 0 WHERE dispatcher_id = ?")
+                    .bind(alert.dispatcher_id.0.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_alerts(
+        &self,
+        subject: AlertSubject,
+        limit: usize,
+    ) -> Result<Vec<OfflineAlert>, Self::Error> {
+        let (column, id) = match subject {
+            AlertSubject::Dispatcher(id) => ("dispatcher_id", id.0),
+            AlertSubject::Device(id) => ("device_id", id.0),
+        };
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT id, dispatcher_id, device_id, last_seen, raised_at
+            FROM offline_alerts WHERE {column} = ?
+            ORDER BY raised_at DESC, id DESC LIMIT ?
+            "#
+        ))
+        .bind(id.to_string())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(map_row_to_alert).collect()
+    }
+
+    async fn record_reported_alert(&self, alert: ReportedAlert) -> Result<(), Self::Error> {
+        let columns = AlertColumns::new(alert.severity, &alert.alert_type);
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO reported_alerts (id, dispatcher_id, device_id, organization_id, severity, kind, custom, message, raised_at, received_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(alert.id.0.to_string())
+        .bind(alert.dispatcher_id.0.to_string())
+        .bind(alert.device_id.map(|id| id.0.to_string()))
+        .bind(alert.organization.map(|id| id.0.to_string()))
+        .bind(columns.severity)
+        .bind(columns.kind)
+        .bind(columns.custom)
+        .bind(alert.message.as_ref())
+        .bind(alert.raised_at.as_second())
+        .bind(alert.received_at.as_second())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_reported_alerts(
+        &self,
+        subject: AlertSubject,
+        organization: Option<OrganizationId>,
+        limit: usize,
+    ) -> Result<Vec<ReportedAlert>, Self::Error> {
+        let (column, id) = match subject {
+            AlertSubject::Dispatcher(id) => ("dispatcher_id", id.0),
+            AlertSubject::Device(id) => ("device_id", id.0),
+        };
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT id, dispatcher_id, device_id, organization_id, severity, kind, custom, message, raised_at, received_at
+            FROM reported_alerts WHERE {column} = ? AND (? IS NULL OR organization_id = ?)
+            ORDER BY raised_at DESC, id DESC LIMIT ?
+            "#
+        ))
+        .bind(id.to_string())
+        .bind(organization.map(|id| id.0.to_string()))
+        .bind(organization.map(|id| id.0.to_string()))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(map_row_to_reported_alert).collect()
+    }
+}
+
+async fn insert_connection(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    event: &ConnectionEvent,
+) -> Result<(), sqlx::Error> {
+    let columns = ConnectionColumns::from(&event.change);
+
+    sqlx::query(
+        r#"
+        INSERT INTO device_connection_events (device_id, dispatcher_id, kind, reason, message, timestamp)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(event.device_id.0.to_string())
+    .bind(event.dispatcher_id.0.to_string())
+    .bind(columns.kind)
+    .bind(columns.reason)
+    .bind(columns.message)
+    .bind(event.timestamp.as_second())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+fn parse_ulid(id: String) -> Result<Ulid, SqliteHealthError> {
+    Ulid::from_str(&id).map_err(|_| SqliteHealthError::InvalidUlid(id))
+}
+
+fn parse_timestamp(secs: i64) -> Result<jiff::Timestamp, SqliteHealthError> {
+    jiff::Timestamp::from_second(secs).map_err(|_| SqliteHealthError::InvalidTimestamp(secs))
+}
+
+fn map_row_to_dispatcher_health(r: &SqliteRow) -> Result<DispatcherHealth, SqliteHealthError> {
+    Ok(DispatcherHealth {
+        dispatcher_id: DispatcherId(parse_ulid(r.try_get("dispatcher_id")?)?),
+        last_seen: parse_timestamp(r.try_get("last_seen")?)?,
+        online: r.try_get("online")?,
+    })
+}
+
+fn map_row_to_device_health(r: &SqliteRow) -> Result<DeviceHealth, SqliteHealthError> {
+    Ok(DeviceHealth {
+        device_id: DeviceId(parse_ulid(r.try_get("device_id")?)?),
+        dispatcher_id: DispatcherId(parse_ulid(r.try_get("dispatcher_id")?)?),
+        last_seen: parse_timestamp(r.try_get("last_seen")?)?,
+        online: r.try_get("online")?,
+    })
+}
+
+fn map_row_to_status(r: &SqliteRow) -> Result<DispatcherStatusRecord, SqliteHealthError> {
+    Ok(DispatcherStatusRecord {
+        dispatcher_id: DispatcherId(parse_ulid(r.try_get("dispatcher_id")?)?),
+        connected_devices: r.try_get::<i64, _>("connected_devices")? as u32,
+        uptime_seconds: r.try_get::<i64, _>("uptime_seconds")? as u64,
+        pending_uploads: r.try_get::<i64, _>("pending_uploads")? as u32,
+        duplicates_dropped: r.try_get::<i64, _>("duplicates_dropped")? as u64,
+        reported_at: parse_timestamp(r.try_get("reported_at")?)?,
+        received_at: parse_timestamp(r.try_get("received_at")?)?,
+    })
+}
+
+fn map_row_to_connection(r: &SqliteRow) -> Result<ConnectionEvent, SqliteHealthError> {
+    let columns = ConnectionColumns {
+        kind: r.try_get("kind")?,
+        reason: r.try_get("reason")?,
+        message: r.try_get("message")?,
+    };
+
+    Ok(ConnectionEvent {
+        device_id: DeviceId(parse_ulid(r.try_get("device_id")?)?),
+        dispatcher_id: DispatcherId(parse_ulid(r.try_get("dispatcher_id")?)?),
+        change: columns
+            .decode()
+            .map_err(SqliteHealthError::InvalidConnectionChange)?,
+        timestamp: parse_timestamp(r.try_get("timestamp")?)?,
+    })
+}
+
+fn map_row_to_alert(r: &SqliteRow) -> Result<OfflineAlert, SqliteHealthError> {
+    Ok(OfflineAlert {
+        id: AlertId(parse_ulid(r.try_get("id")?)?),
+        dispatcher_id: DispatcherId(parse_ulid(r.try_get("dispatcher_id")?)?),
+        device_id: r
+            .try_get::<Option<String>, _>("device_id")?
+            .map(|id| parse_ulid(id).map(DeviceId))
+            .transpose()?,
+        last_seen: parse_timestamp(r.try_get("last_seen")?)?,
+        raised_at: parse_timestamp(r.try_get("raised_at")?)?,
+    })
+}
+
+fn map_row_to_reported_alert(r: &SqliteRow) -> Result<ReportedAlert, SqliteHealthError> {
+    let columns = AlertColumns {
+        severity: r.try_get("severity")?,
+        kind: r.try_get("kind")?,
+        custom: r.try_get("custom")?,
+    };
+    let (severity, alert_type) = columns.decode().map_err(SqliteHealthError::InvalidAlert)?;
+
+    Ok(ReportedAlert {
+        id: AlertId(parse_ulid(r.try_get("id")?)?),
+        dispatcher_id: DispatcherId(parse_ulid(r.try_get("dispatcher_id")?)?),
+        device_id: r
+            .try_get::<Option<String>, _>("device_id")?
+            .map(|id| parse_ulid(id).map(DeviceId))
+            .transpose()?,
+        organization: r
+            .try_get::<Option<String>, _>("organization_id")?
+            .map(|id| parse_ulid(id).map(OrganizationId))
+            .transpose()?,
+        severity,
+        alert_type,
+        message: r.try_get::<String, _>("message")?.into_boxed_str(),
+        raised_at: parse_timestamp(r.try_get("raised_at")?)?,
+        received_at: parse_timestamp(r.try_get("received_at")?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{
+        AlertId, AlertSeverity, AlertType, DeviceId, DisconnectionReason, DispatcherId,
+        OrganizationId,
+    };
+    use ulid::Ulid;
+
+    use crate::{
+        health::{
+            AlertSubject, ConnectionChange, ConnectionEvent, DispatcherStatusRecord, OfflineAlert,
+            ReportedAlert,
+        },
+        registry::HealthRegistry,
+    };
+
+    use super::SqliteHealthRegistry;
+
+    fn now() -> jiff::Timestamp {
+        jiff::Timestamp::from_second(1_700_000_000).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_status_history_and_last_seen() {
+        let registry = SqliteHealthRegistry::new_in_memory().await.unwrap();
+        let dispatcher_id = DispatcherId(Ulid::new());
+
+        for pending_uploads in [3, 1] {
+            registry
+                .record_status(DispatcherStatusRecord {
+                    dispatcher_id,
+                    connected_devices: 2,
+                    uptime_seconds: 600,
+                    pending_uploads,
+                    duplicates_dropped: 4,
+                    reported_at: now(),
+                    received_at: now(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let statuses = registry.list_statuses(dispatcher_id, 10).await.unwrap();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].pending_uploads, 1);
+        assert_eq!(statuses[0].duplicates_dropped, 4);
+
+        let health = registry
+            .dispatcher_health(dispatcher_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(health.online);
+        assert_eq!(health.last_seen, now());
+
+        let cutoff = now() + jiff::SignedDuration::from_secs(1);
+        assert_eq!(registry.silent_dispatchers(cutoff).await.unwrap().len(), 1);
+        registry
+            .raise_alert(OfflineAlert {
+                id: AlertId(Ulid::new()),
+                dispatcher_id,
+                device_id: None,
+                last_seen: health.last_seen,
+                raised_at: cutoff,
+            })
+            .await
+            .unwrap();
+        assert!(
+            registry
+                .silent_dispatchers(cutoff)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            registry
+                .list_alerts(AlertSubject::Dispatcher(dispatcher_id), 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_events() {
+        let registry = SqliteHealthRegistry::new_in_memory().await.unwrap();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        let device_id = DeviceId(Ulid::new());
+
+        let events = registry
+            .record_seen(dispatcher_id, vec![device_id], now())
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        // Already online, nothing to record
+        let events = registry
+            .record_seen(dispatcher_id, vec![device_id], now())
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+
+        let reason = DisconnectionReason::Error("link reset".into());
+        registry
+            .record_disconnection(ConnectionEvent {
+                device_id,
+                dispatcher_id,
+                change: ConnectionChange::Disconnected(Some(reason.clone())),
+                timestamp: now(),
+            })
+            .await
+            .unwrap();
+        assert!(
+            !registry
+                .device_health(device_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .online
+        );
+
+        let events = registry.list_connections(device_id, 10).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].change,
+            ConnectionChange::Disconnected(Some(reason))
+        );
+        assert_eq!(events[1].change, ConnectionChange::Connected);
+    }
+
+    #[tokio::test]
+    async fn test_reported_alerts_are_scoped_to_their_organization() {
+        let registry = SqliteHealthRegistry::new_in_memory().await.unwrap();
+        let dispatcher_id = DispatcherId(Ulid::new());
+        let device_id = DeviceId(Ulid::new());
+        let organization = OrganizationId(Ulid::new());
+
+        let alert = ReportedAlert {
+            id: AlertId(Ulid::new()),
+            dispatcher_id,
+            device_id: Some(device_id),
+            organization: Some(organization),
+            severity: AlertSeverity::Critical,
+            alert_type: AlertType::CriticalBattery,
+            message: "battery at 4%".into(),
+            raised_at: now(),
+            received_at: now(),
+        };
+        registry.record_reported_alert(alert.clone()).await.unwrap();
+        // a retried alert is stored once
+        registry.record_reported_alert(alert.clone()).await.unwrap();
+
+        let subject = AlertSubject::Device(device_id);
+        assert_eq!(
+            registry
+                .list_reported_alerts(subject, Some(organization), 10)
+                .await
+                .unwrap(),
+            vec![alert.clone()]
+        );
+        assert_eq!(
+            registry
+                .list_reported_alerts(AlertSubject::Dispatcher(dispatcher_id), None, 10)
+                .await
+                .unwrap(),
+            vec![alert]
+        );
+
+        let other = Some(OrganizationId(Ulid::new()));
+        assert!(
+            registry
+                .list_reported_alerts(subject, other, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use ersha_core::{DeviceStatus, SensorReading};
+use sqlx::{SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions};
+
+use async_trait::async_trait;
+
+use crate::registry::IngestRegistry;
+
+use super::{device_status::insert_status, reading::insert_readings};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteIngestError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+}
+
+/// Stores the readings and statuses of a batch in a single transaction.
+#[derive(Clone)]
+pub struct SqliteIngestRegistry {
+    pool: SqlitePool,
+}
+
+impl SqliteIngestRegistry {
+    pub async fn new(path: impl AsRef<str>) -> Result<Self, SqliteIngestError> {
+        let connection_string = format!("sqlite:{}", path.as_ref());
+        let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn new_in_memory() -> Result<Self, SqliteIngestError> {
+        let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl IngestRegistry for SqliteIngestRegistry {
+    type Error = SqliteIngestError;
+
+    async fn store_batch(
+        &self,
+        readings: Vec<SensorReading>,
+        statuses: Vec<DeviceStatus>,
+    ) -> Result<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        insert_readings(&mut tx, &readings).await?;
+        for status in &statuses {
+            insert_status(&mut tx, status, false).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{
+        DeviceId, DeviceStatus, DispatcherId, H3Cell, Percentage, ReadingId, SensorId,
+        SensorMetric, SensorReading, StatusId,
+    };
+    use jiff::Timestamp;
+    use ulid::Ulid;
+
+    use crate::registry::IngestRegistry;
+
+    use super::SqliteIngestRegistry;
+
+    fn mock_reading() -> SensorReading {
+        SensorReading {
+            id: ReadingId(Ulid::new()),
+            device_id: DeviceId(Ulid::new()),
+            dispatcher_id: DispatcherId(Ulid::new()),
+            metric: SensorMetric::SoilMoisture {
+                value: Percentage(40),
+            },
+            location: H3Cell(0x8a2a1072b59ffff),
+            confidence: Percentage(100),
+            timestamp: Timestamp::now(),
+            sensor_id: SensorId(Ulid::new()),
+            raw_value: None,
+        }
+    }
+
+    fn mock_status() -> DeviceStatus {
+        DeviceStatus {
+            id: StatusId(Ulid::new()),
+            device_id: DeviceId(Ulid::new()),
+            dispatcher_id: DispatcherId(Ulid::new()),
+            battery_percent: Percentage(80),
+            uptime_seconds: 60,
+            signal_rssi: -70,
+            errors: vec![].into_boxed_slice(),
+            timestamp: Timestamp::now(),
+            sensor_statuses: vec![].into_boxed_slice(),
+            firmware: None,
+        }
+    }
+
+    async fn count(registry: &SqliteIngestRegistry, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(&registry.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_store_batch_skips_stored_ids() {
+        let registry = SqliteIngestRegistry::new_in_memory().await.unwrap();
+        let readings = vec![mock_reading(), mock_reading()];
+        let statuses = vec![mock_status()];
+
+        registry
+            .store_batch(readings.clone(), statuses.clone())
+            .await
+            .unwrap();
+        registry.store_batch(readings, statuses).await.unwrap();
+
+        assert_eq!(count(&registry, "readings").await, 2);
+        assert_eq!(count(&registry, "device_statuses").await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_store_batch_rolls_back_on_failure() {
+        let registry = SqliteIngestRegistry::new_in_memory().await.unwrap();
+        sqlx::query("DROP TABLE device_status_errors")
+            .execute(&registry.pool)
+            .await
+            .unwrap();
+
+        let result = registry
+            .store_batch(vec![mock_reading()], vec![mock_status()])
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(count(&registry, "readings").await, 0);
+        assert_eq!(count(&registry, "device_statuses").await, 0);
+    }
+}
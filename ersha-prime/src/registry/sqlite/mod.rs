@@ -1,9 +1,22 @@
+mod api_key;
+mod batch;
+mod cells;
 mod device;
 mod device_status;
 mod dispatcher;
+mod farm;
+mod health;
+mod ingest;
+mod organization;
 mod reading;
 
+pub use api_key::SqliteApiKeyRegistry;
+pub use batch::SqliteBatchRegistry;
 pub use device::SqliteDeviceRegistry;
 pub use device_status::SqliteDeviceStatusRegistry;
 pub use dispatcher::SqliteDispatcherRegistry;
+pub use farm::SqliteFarmRegistry;
+pub use health::SqliteHealthRegistry;
+pub use ingest::SqliteIngestRegistry;
+pub use organization::SqliteOrganizationRegistry;
 pub use reading::SqliteReadingRegistry;
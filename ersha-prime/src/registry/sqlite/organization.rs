@@ -0,0 +1,135 @@
+use std::str::FromStr;
+
+use ersha_core::{Organization, OrganizationId};
+use sqlx::{Row, SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions, sqlite::SqliteRow};
+use ulid::Ulid;
+
+use async_trait::async_trait;
+
+use crate::registry::OrganizationRegistry;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteOrganizationError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    #[error("invalid ULID: {0}")]
+    InvalidUlid(String),
+    #[error("invalid timestamp: {0}")]
+    InvalidTimestamp(i64),
+}
+
+#[derive(Clone)]
+pub struct SqliteOrganizationRegistry {
+    pool: SqlitePool,
+}
+
+impl SqliteOrganizationRegistry {
+    pub async fn new(path: impl AsRef<str>) -> Result<Self, SqliteOrganizationError> {
+        let connection_string = format!("sqlite:{}", path.as_ref());
+        let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn new_in_memory() -> Result<Self, SqliteOrganizationError> {
+        let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
+
+        MIGRATOR.run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl OrganizationRegistry for SqliteOrganizationRegistry {
+    type Error = SqliteOrganizationError;
+
+    async fn add(&self, organization: Organization) -> Result<(), Self::Error> {
+        sqlx::query("INSERT INTO organizations (id, name, created_at) VALUES (?, ?, ?)")
+            .bind(organization.id.0.to_string())
+            .bind(organization.name.as_ref())
+            .bind(organization.created_at.as_second())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, id: OrganizationId) -> Result<Option<Organization>, Self::Error> {
+        let row = sqlx::query("SELECT id, name, created_at FROM organizations WHERE id = ?")
+            .bind(id.0.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(map_row_to_organization).transpose()
+    }
+
+    async fn list(&self) -> Result<Vec<Organization>, Self::Error> {
+        let rows = sqlx::query("SELECT id, name, created_at FROM organizations ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(map_row_to_organization).collect()
+    }
+}
+
+fn map_row_to_organization(row: &SqliteRow) -> Result<Organization, SqliteOrganizationError> {
+    let id_str: String = row.try_get("id")?;
+    let ulid = Ulid::from_str(&id_str).map_err(|_| SqliteOrganizationError::InvalidUlid(id_str))?;
+
+    let created_at: i64 = row.try_get("created_at")?;
+    let created_at = jiff::Timestamp::from_second(created_at)
+        .map_err(|_| SqliteOrganizationError::InvalidTimestamp(created_at))?;
+
+    Ok(Organization {
+        id: OrganizationId(ulid),
+        name: row.try_get::<String, _>("name")?.into_boxed_str(),
+        created_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ersha_core::{Organization, OrganizationId};
+    use ulid::Ulid;
+
+    use crate::registry::OrganizationRegistry;
+
+    use super::SqliteOrganizationRegistry;
+
+    fn organization(name: &str) -> Organization {
+        Organization {
+            id: OrganizationId(Ulid::new()),
+            name: name.into(),
+            created_at: jiff::Timestamp::from_second(1_700_000_000).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_get_and_list() {
+        let registry = SqliteOrganizationRegistry::new_in_memory().await.unwrap();
+        let coop = organization("Coop");
+        let ngo = organization("Aid NGO");
+        registry.add(coop.clone()).await.unwrap();
+        registry.add(ngo.clone()).await.unwrap();
+
+        // names are unique
+        assert!(registry.add(organization("Coop")).await.is_err());
+
+        assert_eq!(registry.get(coop.id).await.unwrap(), Some(coop.clone()));
+        assert!(
+            registry
+                .get(OrganizationId(Ulid::new()))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(registry.list().await.unwrap(), vec![ngo, coop]);
+    }
+}
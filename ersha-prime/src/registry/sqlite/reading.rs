@@ -1,19 +1,24 @@
 use std::str::FromStr;
 
-use ersha_core::{
-    DeviceId, DispatcherId, H3Cell, Percentage, ReadingId, SensorId, SensorMetric, SensorReading,
-};
+use ersha_core::{DeviceId, DispatcherId, H3Cell, Percentage, ReadingId, SensorId, SensorReading};
 use ordered_float::NotNan;
 use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool, migrate::Migrator, sqlite::SqlitePoolOptions};
 use ulid::Ulid;
 
 use async_trait::async_trait;
 
-use crate::registry::{
-    ReadingRegistry,
-    filter::{Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SensorMetricType, SortOrder},
+use crate::{
+    geo,
+    registry::{
+        ReadingRegistry,
+        filter::{Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SortOrder},
+        metric::{decode_metric, decode_metric_type, disect_metric, metric_type_code},
+        rollup::CellRollup,
+    },
 };
 
+use super::cells;
+
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
 #[derive(Debug, thiserror::Error)]
@@ -41,6 +46,7 @@ impl SqliteReadingRegistry {
         let pool = SqlitePoolOptions::new().connect(&connection_string).await?;
 
         MIGRATOR.run(&pool).await?;
+        cells::index_existing(&pool).await?;
 
         Ok(Self { pool })
     }
@@ -49,6 +55,7 @@ impl SqliteReadingRegistry {
         let pool = SqlitePoolOptions::new().connect("sqlite::memory:").await?;
 
         MIGRATOR.run(&pool).await?;
+        cells::index_existing(&pool).await?;
 
         Ok(Self { pool })
     }
@@ -63,8 +70,8 @@ impl ReadingRegistry for SqliteReadingRegistry {
 
         sqlx::query(
             r#"
-            INSERT OR REPLACE INTO readings (id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT OR REPLACE INTO readings (id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp, raw_value)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(reading.id.0.to_string())
@@ -76,8 +83,10 @@ impl ReadingRegistry for SqliteReadingRegistry {
         .bind(reading.location.0 as i64)
         .bind(reading.confidence.0 as i32)
         .bind(reading.timestamp.as_second())
+        .bind(reading.raw_value.map(|v| v.into_inner()))
         .execute(&self.pool)
         .await?;
+        cells::index_cells(&mut *self.pool.acquire().await?, [reading.location]).await?;
 
         Ok(())
     }
@@ -85,7 +94,7 @@ impl ReadingRegistry for SqliteReadingRegistry {
     async fn get(&self, id: ReadingId) -> Result<Option<SensorReading>, Self::Error> {
         let row = sqlx::query(
             r#"
-            SELECT id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp
+            SELECT id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp, raw_value
             FROM readings WHERE id = ?
             "#,
         )
@@ -98,29 +107,7 @@ impl ReadingRegistry for SqliteReadingRegistry {
 
     async fn batch_store(&self, readings: Vec<SensorReading>) -> Result<(), Self::Error> {
         let mut tx = self.pool.begin().await?;
-
-        for reading in readings {
-            let (metric_type, metric_value) = disect_metric(&reading.metric);
-
-            sqlx::query(
-                r#"
-                INSERT OR REPLACE INTO readings (id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-                "#,
-            )
-            .bind(reading.id.0.to_string())
-            .bind(reading.device_id.0.to_string())
-            .bind(reading.dispatcher_id.0.to_string())
-            .bind(reading.sensor_id.0.to_string())
-            .bind(metric_type)
-            .bind(metric_value)
-            .bind(reading.location.0 as i64)
-            .bind(reading.confidence.0 as i32)
-            .bind(reading.timestamp.as_second())
-            .execute(&mut *tx)
-            .await?;
-        }
-
+        insert_readings(&mut tx, &readings).await?;
         tx.commit().await?;
         Ok(())
     }
@@ -143,7 +130,7 @@ impl ReadingRegistry for SqliteReadingRegistry {
         options: QueryOptions<ReadingFilter, ReadingSortBy>,
     ) -> Result<Vec<SensorReading>, Self::Error> {
         let mut query_builder = QueryBuilder::new(
-            "SELECT id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp FROM readings ",
+            "SELECT id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp, raw_value FROM readings ",
         );
 
         query_builder = filter_readings(query_builder, options.filter);
@@ -174,6 +161,75 @@ impl ReadingRegistry for SqliteReadingRegistry {
 
         rows.iter().map(map_row_to_reading).collect()
     }
+
+    async fn rollup(
+        &self,
+        resolution: u8,
+        filter: ReadingFilter,
+    ) -> Result<Vec<CellRollup>, Self::Error> {
+        if resolution > geo::MAX_RESOLUTION {
+            return Ok(Vec::new());
+        }
+
+        // h3_cells holds the ancestor of each location at every resolution
+        // at least as coarse as its own, NULL otherwise.
+        let mut query_builder = QueryBuilder::new(format!(
+            "SELECT h3_cells.r{resolution} AS rollup_cell, metric_type, COUNT(*) AS readings, COUNT(DISTINCT device_id) AS devices, AVG(metric_value) AS mean, MIN(metric_value) AS min, MAX(metric_value) AS max FROM readings JOIN h3_cells ON h3_cells.cell = readings.location AND h3_cells.r{resolution} IS NOT NULL "
+        ));
+        query_builder = filter_readings(query_builder, filter);
+        query_builder.push(" GROUP BY rollup_cell, metric_type ORDER BY rollup_cell, metric_type");
+
+        let rows = query_builder.build().fetch_all(&self.pool).await?;
+        rows.iter().map(map_row_to_rollup).collect()
+    }
+}
+
+fn map_row_to_rollup(r: &sqlx::sqlite::SqliteRow) -> Result<CellRollup, SqliteReadingError> {
+    let metric_type: i32 = r.try_get("metric_type")?;
+
+    Ok(CellRollup {
+        cell: H3Cell(r.try_get::<i64, _>("rollup_cell")? as u64),
+        metric_type: decode_metric_type(metric_type)
+            .map_err(SqliteReadingError::InvalidMetricType)?,
+        readings: r.try_get::<i64, _>("readings")? as u64,
+        devices: r.try_get::<i64, _>("devices")? as u64,
+        mean: r.try_get("mean")?,
+        min: r.try_get("min")?,
+        max: r.try_get("max")?,
+    })
+}
+
+/// Insert readings within `tx`, skipping ids that are already stored.
+pub(super) async fn insert_readings(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    readings: &[SensorReading],
+) -> Result<(), sqlx::Error> {
+    cells::index_cells(&mut **tx, readings.iter().map(|reading| reading.location)).await?;
+
+    for reading in readings {
+        let (metric_type, metric_value) = disect_metric(&reading.metric);
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO readings (id, device_id, dispatcher_id, sensor_id, metric_type, metric_value, location, confidence, timestamp, raw_value)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(reading.id.0.to_string())
+        .bind(reading.device_id.0.to_string())
+        .bind(reading.dispatcher_id.0.to_string())
+        .bind(reading.sensor_id.0.to_string())
+        .bind(metric_type)
+        .bind(metric_value)
+        .bind(reading.location.0 as i64)
+        .bind(reading.confidence.0 as i32)
+        .bind(reading.timestamp.as_second())
+        .bind(reading.raw_value.map(|v| v.into_inner()))
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
 }
 
 fn map_row_to_reading(r: &sqlx::sqlite::SqliteRow) -> Result<SensorReading, SqliteReadingError> {
@@ -195,24 +251,8 @@ fn map_row_to_reading(r: &sqlx::sqlite::SqliteRow) -> Result<SensorReading, Sqli
     let metric_type: i32 = r.try_get("metric_type")?;
     let metric_value: f64 = r.try_get("metric_value")?;
 
-    let metric = match metric_type {
-        0 => SensorMetric::SoilMoisture {
-            value: Percentage(metric_value as u8),
-        },
-        1 => SensorMetric::SoilTemp {
-            value: NotNan::new(metric_value).expect("database should not contain NaN"),
-        },
-        2 => SensorMetric::AirTemp {
-            value: NotNan::new(metric_value).expect("database should not contain NaN"),
-        },
-        3 => SensorMetric::Humidity {
-            value: Percentage(metric_value as u8),
-        },
-        4 => SensorMetric::Rainfall {
-            value: NotNan::new(metric_value).expect("database should not contain NaN"),
-        },
-        other => return Err(SqliteReadingError::InvalidMetricType(other)),
-    };
+    let metric =
+        decode_metric(metric_type, metric_value).map_err(SqliteReadingError::InvalidMetricType)?;
 
     let timestamp_sec: i64 = r.try_get("timestamp")?;
     let timestamp = jiff::Timestamp::from_second(timestamp_sec)
@@ -227,6 +267,9 @@ fn map_row_to_reading(r: &sqlx::sqlite::SqliteRow) -> Result<SensorReading, Sqli
         location: H3Cell(r.try_get::<i64, _>("location")? as u64),
         confidence: Percentage(r.try_get::<i32, _>("confidence")? as u8),
         timestamp,
+        raw_value: r
+            .try_get::<Option<f64>, _>("raw_value")?
+            .and_then(|v| NotNan::new(v).ok()),
     })
 }
 
@@ -300,14 +343,7 @@ fn filter_readings<'a>(
         query_builder.push("metric_type IN (");
         let mut separated = query_builder.separated(", ");
         for mt in metric_types {
-            let val = match mt {
-                SensorMetricType::SoilMoisture => 0,
-                SensorMetricType::SoilTemp => 1,
-                SensorMetricType::AirTemp => 2,
-                SensorMetricType::Humidity => 3,
-                SensorMetricType::Rainfall => 4,
-            };
-            separated.push_bind(val);
+            separated.push_bind(metric_type_code(mt));
         }
         separated.push_unseparated(")");
     }
@@ -324,6 +360,14 @@ fn filter_readings<'a>(
         separated.push_unseparated(")");
     }
 
+    cells::push_spatial_filters(
+        &mut query_builder,
+        &mut prefix,
+        filter.ancestor,
+        filter.ring,
+        filter.bbox,
+    );
+
     if let Some(after) = filter.timestamp_after {
         prefix(&mut query_builder);
         query_builder
@@ -347,17 +391,23 @@ fn filter_readings<'a>(
             .push_bind(*range.end() as i32);
     }
 
-    query_builder
-}
+    if let Some(organization) = filter.organization {
+        prefix(&mut query_builder);
+        query_builder
+            .push("device_id IN (SELECT id FROM devices WHERE organization_id = ")
+            .push_bind(organization.0.to_string())
+            .push(")");
+    }
 
-fn disect_metric(metric: &SensorMetric) -> (i32, f64) {
-    match metric {
-        SensorMetric::SoilMoisture { value } => (0, value.0 as f64),
-        SensorMetric::SoilTemp { value } => (1, value.into_inner()),
-        SensorMetric::AirTemp { value } => (2, value.into_inner()),
-        SensorMetric::Humidity { value } => (3, value.0 as f64),
-        SensorMetric::Rainfall { value } => (4, value.into_inner()),
+    if let Some(field) = filter.field {
+        prefix(&mut query_builder);
+        query_builder
+            .push("device_id IN (SELECT device_id FROM plot_devices WHERE plot_id IN (SELECT id FROM plots WHERE field_id = ")
+            .push_bind(field.0.to_string())
+            .push("))");
     }
+
+    query_builder
 }
 
 #[cfg(test)]
@@ -366,12 +416,14 @@ mod tests {
     use ordered_float::NotNan;
     use ulid::Ulid;
 
+    use crate::geo;
     use crate::registry::ReadingRegistry;
     use crate::registry::filter::{
         Pagination, QueryOptions, ReadingFilter, ReadingSortBy, SensorMetricType, SortOrder,
     };
+    use crate::registry::rollup::CellRollup;
     use ersha_core::{
-        DeviceId, DispatcherId, H3Cell, Percentage, ReadingId, SensorId, SensorMetric,
+        DeviceId, DispatcherId, FieldId, H3Cell, Percentage, ReadingId, SensorId, SensorMetric,
         SensorReading,
     };
 
@@ -387,6 +439,7 @@ mod tests {
             confidence: Percentage(confidence),
             timestamp: Timestamp::now(),
             sensor_id: SensorId(Ulid::new()),
+            raw_value: None,
         }
     }
 
@@ -448,6 +501,40 @@ mod tests {
         assert_eq!(registry.count(Some(filter)).await.unwrap(), 2);
     }
 
+    #[tokio::test]
+    async fn test_batch_store_is_idempotent() {
+        let registry = SqliteReadingRegistry::new_in_memory().await.unwrap();
+        let id = ReadingId(Ulid::new());
+        let original = mock_reading(
+            id,
+            SensorMetric::AirTemp {
+                value: NotNan::new(20.0).unwrap(),
+            },
+            80,
+        );
+        let mut resent = original.clone();
+        resent.confidence = Percentage(10);
+
+        registry.batch_store(vec![original.clone()]).await.unwrap();
+        registry
+            .batch_store(vec![
+                resent,
+                mock_reading(
+                    ReadingId(Ulid::new()),
+                    SensorMetric::Humidity {
+                        value: Percentage(60),
+                    },
+                    90,
+                ),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(registry.count(None).await.unwrap(), 2);
+        let stored = registry.get(id).await.unwrap().unwrap();
+        assert_eq!(stored.confidence.0, 80);
+    }
+
     #[tokio::test]
     async fn test_list_with_sorting() {
         let registry = SqliteReadingRegistry::new_in_memory().await.unwrap();
@@ -532,4 +619,132 @@ mod tests {
 
         assert_eq!(registry.count(Some(filter)).await.unwrap(), 1);
     }
+
+    #[tokio::test]
+    async fn test_filter_by_field() {
+        let registry = SqliteReadingRegistry::new_in_memory().await.unwrap();
+        let metric = SensorMetric::AirTemp {
+            value: NotNan::new(20.0).unwrap(),
+        };
+        let assigned = mock_reading(ReadingId(Ulid::new()), metric.clone(), 90);
+        let other = mock_reading(ReadingId(Ulid::new()), metric, 90);
+        registry
+            .batch_store(vec![assigned.clone(), other])
+            .await
+            .unwrap();
+
+        let field = FieldId(Ulid::new());
+        let plot = Ulid::new().to_string();
+        let farm = Ulid::new().to_string();
+        sqlx::query("INSERT INTO farms (id, name, created_at) VALUES (?, 'Kilimo', 0)")
+            .bind(&farm)
+            .execute(&registry.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO fields (id, farm_id, name, created_at) VALUES (?, ?, 'North', 0)")
+            .bind(field.0.to_string())
+            .bind(&farm)
+            .execute(&registry.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO plots (id, field_id, name, created_at) VALUES (?, ?, 'A', 0)")
+            .bind(&plot)
+            .bind(field.0.to_string())
+            .execute(&registry.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO plot_devices (device_id, plot_id) VALUES (?, ?)")
+            .bind(assigned.device_id.0.to_string())
+            .bind(&plot)
+            .execute(&registry.pool)
+            .await
+            .unwrap();
+
+        let results = registry
+            .list(QueryOptions {
+                filter: ReadingFilter::builder().field(field).build(),
+                sort_by: ReadingSortBy::Timestamp,
+                sort_order: SortOrder::Asc,
+                pagination: Pagination::Offset {
+                    offset: 0,
+                    limit: 10,
+                },
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, assigned.id);
+
+        let filter = ReadingFilter::builder().field(FieldId(Ulid::new())).build();
+        assert_eq!(registry.count(Some(filter)).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rollup() {
+        let registry = SqliteReadingRegistry::new_in_memory().await.unwrap();
+        let moisture = |value| SensorMetric::SoilMoisture {
+            value: Percentage(value),
+        };
+
+        let first = mock_reading(ReadingId(Ulid::new()), moisture(20), 90);
+        let mut second = mock_reading(ReadingId(Ulid::new()), moisture(40), 90);
+        second.device_id = first.device_id;
+        let third = mock_reading(ReadingId(Ulid::new()), moisture(60), 90);
+        let rainfall = mock_reading(
+            ReadingId(Ulid::new()),
+            SensorMetric::Rainfall {
+                value: NotNan::new(3.5).unwrap(),
+            },
+            90,
+        );
+        let district = geo::parent(first.location, 5).unwrap();
+        let mut coarse = mock_reading(ReadingId(Ulid::new()), moisture(0), 90);
+        coarse.location = geo::parent(first.location, 3).unwrap();
+
+        registry
+            .batch_store(vec![first, second, third, rainfall, coarse])
+            .await
+            .unwrap();
+
+        let rollups = registry.rollup(5, ReadingFilter::default()).await.unwrap();
+        assert_eq!(
+            rollups,
+            vec![
+                CellRollup {
+                    cell: district,
+                    metric_type: SensorMetricType::SoilMoisture,
+                    readings: 3,
+                    devices: 2,
+                    mean: 40.0,
+                    min: 20.0,
+                    max: 60.0,
+                },
+                CellRollup {
+                    cell: district,
+                    metric_type: SensorMetricType::Rainfall,
+                    readings: 1,
+                    devices: 1,
+                    mean: 3.5,
+                    min: 3.5,
+                    max: 3.5,
+                },
+            ]
+        );
+
+        let filter = ReadingFilter::builder()
+            .metric_types([SensorMetricType::Rainfall])
+            .build();
+        let rollups = registry.rollup(3, filter).await.unwrap();
+        assert_eq!(rollups.len(), 1);
+        assert_eq!(rollups[0].cell, geo::parent(district, 3).unwrap());
+        assert_eq!(rollups[0].readings, 1);
+
+        assert!(
+            registry
+                .rollup(16, ReadingFilter::default())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
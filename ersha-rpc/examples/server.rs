@@ -93,6 +93,8 @@ async fn main() {
                     readings_rejected: 0,
                     statuses_stored: statuses_count,
                     statuses_rejected: 0,
+                    rejections: vec![].into_boxed_slice(),
+                    failed: false,
                 }
             }
         });
@@ -1,7 +1,11 @@
 use ersha_core::{
-    AlertRequest, AlertResponse, BatchUploadRequest, BatchUploadResponse,
-    DeviceDisconnectionRequest, DeviceDisconnectionResponse, DispatcherStatusRequest,
-    DispatcherStatusResponse, HelloRequest, HelloResponse,
+    ActuatorCommandsRequest, ActuatorCommandsResponse, AlertRequest, AlertResponse,
+    BatchUploadRequest, BatchUploadResponse, CalibrationRequest, CalibrationResponse,
+    CommandOutcomeRequest, CommandOutcomeResponse, DeviceDisconnectionRequest,
+    DeviceDisconnectionResponse, DeviceKeysRequest, DeviceKeysResponse, DispatcherStatusRequest,
+    DispatcherStatusResponse, FirmwareChunkRequest, FirmwareChunkResponse, FirmwareManifestRequest,
+    FirmwareManifestResponse, FirmwareReportRequest, FirmwareReportResponse, HelloRequest,
+    HelloResponse,
 };
 use std::time::Duration;
 use thiserror::Error;
@@ -135,4 +139,116 @@ impl Client {
             _ => Err(ClientError::UnexpectedResponse),
         }
     }
+
+    pub async fn calibration(
+        &self,
+        request: CalibrationRequest,
+    ) -> Result<CalibrationResponse, ClientError> {
+        let response = self
+            .rpc
+            .call(WireMessage::CalibrationRequest(request), self.timeout)
+            .await?;
+
+        match response.payload {
+            WireMessage::CalibrationResponse(resp) => Ok(resp),
+            WireMessage::Error(err) => Err(ClientError::ErrorResponse(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn device_keys(
+        &self,
+        request: DeviceKeysRequest,
+    ) -> Result<DeviceKeysResponse, ClientError> {
+        let response = self
+            .rpc
+            .call(WireMessage::DeviceKeysRequest(request), self.timeout)
+            .await?;
+
+        match response.payload {
+            WireMessage::DeviceKeysResponse(resp) => Ok(resp),
+            WireMessage::Error(err) => Err(ClientError::ErrorResponse(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn firmware_manifest(
+        &self,
+        request: FirmwareManifestRequest,
+    ) -> Result<FirmwareManifestResponse, ClientError> {
+        let response = self
+            .rpc
+            .call(WireMessage::FirmwareManifestRequest(request), self.timeout)
+            .await?;
+
+        match response.payload {
+            WireMessage::FirmwareManifestResponse(resp) => Ok(resp),
+            WireMessage::Error(err) => Err(ClientError::ErrorResponse(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn firmware_chunk(
+        &self,
+        request: FirmwareChunkRequest,
+    ) -> Result<FirmwareChunkResponse, ClientError> {
+        let response = self
+            .rpc
+            .call(WireMessage::FirmwareChunkRequest(request), self.timeout)
+            .await?;
+
+        match response.payload {
+            WireMessage::FirmwareChunkResponse(resp) => Ok(resp),
+            WireMessage::Error(err) => Err(ClientError::ErrorResponse(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn actuator_commands(
+        &self,
+        request: ActuatorCommandsRequest,
+    ) -> Result<ActuatorCommandsResponse, ClientError> {
+        let response = self
+            .rpc
+            .call(WireMessage::ActuatorCommandsRequest(request), self.timeout)
+            .await?;
+
+        match response.payload {
+            WireMessage::ActuatorCommandsResponse(resp) => Ok(resp),
+            WireMessage::Error(err) => Err(ClientError::ErrorResponse(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn command_outcome(
+        &self,
+        request: CommandOutcomeRequest,
+    ) -> Result<CommandOutcomeResponse, ClientError> {
+        let response = self
+            .rpc
+            .call(WireMessage::CommandOutcomeRequest(request), self.timeout)
+            .await?;
+
+        match response.payload {
+            WireMessage::CommandOutcomeResponse(resp) => Ok(resp),
+            WireMessage::Error(err) => Err(ClientError::ErrorResponse(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn firmware_report(
+        &self,
+        request: FirmwareReportRequest,
+    ) -> Result<FirmwareReportResponse, ClientError> {
+        let response = self
+            .rpc
+            .call(WireMessage::FirmwareReportRequest(request), self.timeout)
+            .await?;
+
+        match response.payload {
+            WireMessage::FirmwareReportResponse(resp) => Ok(resp),
+            WireMessage::Error(err) => Err(ClientError::ErrorResponse(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
 }
@@ -218,6 +218,7 @@ mod tests {
             WireErrorCode::BadRequest,
             WireErrorCode::Unsupported,
             WireErrorCode::Internal,
+            WireErrorCode::Unauthenticated,
         ];
 
         for code in error_codes {
@@ -1,7 +1,11 @@
 use ersha_core::{
-    AlertRequest, AlertResponse, BatchUploadRequest, BatchUploadResponse,
-    DeviceDisconnectionRequest, DeviceDisconnectionResponse, DispatcherStatusRequest,
-    DispatcherStatusResponse, HelloRequest, HelloResponse,
+    ActuatorCommandsRequest, ActuatorCommandsResponse, AlertRequest, AlertResponse,
+    BatchUploadRequest, BatchUploadResponse, CalibrationRequest, CalibrationResponse,
+    CommandOutcomeRequest, CommandOutcomeResponse, DeviceDisconnectionRequest,
+    DeviceDisconnectionResponse, DeviceKeysRequest, DeviceKeysResponse, DispatcherId,
+    DispatcherStatusRequest, DispatcherStatusResponse, FirmwareChunkRequest, FirmwareChunkResponse,
+    FirmwareManifestRequest, FirmwareManifestResponse, FirmwareReportRequest,
+    FirmwareReportResponse, HelloRequest, HelloResponse,
 };
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
@@ -42,9 +46,45 @@ pub enum WireMessage {
     DispatcherStatusResponse(DispatcherStatusResponse),
     DeviceDisconnectionRequest(DeviceDisconnectionRequest),
     DeviceDisconnectionResponse(DeviceDisconnectionResponse),
+    CalibrationRequest(CalibrationRequest),
+    CalibrationResponse(CalibrationResponse),
+    DeviceKeysRequest(DeviceKeysRequest),
+    DeviceKeysResponse(DeviceKeysResponse),
+    FirmwareManifestRequest(FirmwareManifestRequest),
+    FirmwareManifestResponse(FirmwareManifestResponse),
+    FirmwareChunkRequest(FirmwareChunkRequest),
+    FirmwareChunkResponse(FirmwareChunkResponse),
+    ActuatorCommandsRequest(ActuatorCommandsRequest),
+    ActuatorCommandsResponse(ActuatorCommandsResponse),
+    CommandOutcomeRequest(CommandOutcomeRequest),
+    CommandOutcomeResponse(CommandOutcomeResponse),
+    FirmwareReportRequest(FirmwareReportRequest),
+    FirmwareReportResponse(FirmwareReportResponse),
     Error(WireError),
 }
 
+impl WireMessage {
+    /// The dispatcher a request claims to come from, or `None` for
+    /// messages that are not dispatcher requests.
+    pub fn dispatcher_id(&self) -> Option<DispatcherId> {
+        match self {
+            WireMessage::HelloRequest(r) => Some(r.dispatcher_id),
+            WireMessage::BatchUploadRequest(r) => Some(r.dispatcher_id),
+            WireMessage::AlertRequest(r) => Some(r.dispatcher_id),
+            WireMessage::DispatcherStatusRequest(r) => Some(r.dispatcher_id),
+            WireMessage::DeviceDisconnectionRequest(r) => Some(r.dispatcher_id),
+            WireMessage::CalibrationRequest(r) => Some(r.dispatcher_id),
+            WireMessage::DeviceKeysRequest(r) => Some(r.dispatcher_id),
+            WireMessage::FirmwareManifestRequest(r) => Some(r.dispatcher_id),
+            WireMessage::FirmwareChunkRequest(r) => Some(r.dispatcher_id),
+            WireMessage::ActuatorCommandsRequest(r) => Some(r.dispatcher_id),
+            WireMessage::CommandOutcomeRequest(r) => Some(r.dispatcher_id),
+            WireMessage::FirmwareReportRequest(r) => Some(r.dispatcher_id),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct WireError {
     pub code: WireErrorCode,
@@ -56,4 +96,7 @@ pub enum WireErrorCode {
     BadRequest,
     Unsupported,
     Internal,
+    /// The request did not come from the dispatcher the connection's hello
+    /// accepted.
+    Unauthenticated,
 }
@@ -5,11 +5,15 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::{TlsAcceptor, server::TlsStream};
 use tokio_util::sync::CancellationToken;
 
-use crate::{MessageId, RpcTcp, WireMessage};
+use crate::{MessageId, RpcTcp, WireError, WireErrorCode, WireMessage};
 use ersha_core::{
-    AlertRequest, AlertResponse, BatchUploadRequest, BatchUploadResponse,
-    DeviceDisconnectionRequest, DeviceDisconnectionResponse, DispatcherStatusRequest,
-    DispatcherStatusResponse, HelloRequest, HelloResponse,
+    ActuatorCommandsRequest, ActuatorCommandsResponse, AlertRequest, AlertResponse,
+    BatchUploadRequest, BatchUploadResponse, CalibrationRequest, CalibrationResponse,
+    CommandOutcomeRequest, CommandOutcomeResponse, DeviceDisconnectionRequest,
+    DeviceDisconnectionResponse, DeviceKeysRequest, DeviceKeysResponse, DispatcherId,
+    DispatcherStatusRequest, DispatcherStatusResponse, FirmwareChunkRequest, FirmwareChunkResponse,
+    FirmwareManifestRequest, FirmwareManifestResponse, FirmwareReportRequest,
+    FirmwareReportResponse, HelloRequest, HelloResponse,
 };
 
 pub type HandlerFn<Req, Res, S> = Box<
@@ -32,6 +36,13 @@ struct ServerHandlers<S> {
     on_dispatcher_status: Option<HandlerFn<DispatcherStatusRequest, DispatcherStatusResponse, S>>,
     on_device_disconnection:
         Option<HandlerFn<DeviceDisconnectionRequest, DeviceDisconnectionResponse, S>>,
+    on_calibration: Option<HandlerFn<CalibrationRequest, CalibrationResponse, S>>,
+    on_device_keys: Option<HandlerFn<DeviceKeysRequest, DeviceKeysResponse, S>>,
+    on_firmware_manifest: Option<HandlerFn<FirmwareManifestRequest, FirmwareManifestResponse, S>>,
+    on_firmware_chunk: Option<HandlerFn<FirmwareChunkRequest, FirmwareChunkResponse, S>>,
+    on_actuator_commands: Option<HandlerFn<ActuatorCommandsRequest, ActuatorCommandsResponse, S>>,
+    on_command_outcome: Option<HandlerFn<CommandOutcomeRequest, CommandOutcomeResponse, S>>,
+    on_firmware_report: Option<HandlerFn<FirmwareReportRequest, FirmwareReportResponse, S>>,
 }
 
 impl<S: Send + Sync + 'static> Server<S> {
@@ -48,6 +59,13 @@ impl<S: Send + Sync + 'static> Server<S> {
                 on_alert: None,
                 on_dispatcher_status: None,
                 on_device_disconnection: None,
+                on_calibration: None,
+                on_device_keys: None,
+                on_firmware_manifest: None,
+                on_firmware_chunk: None,
+                on_actuator_commands: None,
+                on_command_outcome: None,
+                on_firmware_report: None,
             },
         }
     }
@@ -124,6 +142,83 @@ impl<S: Send + Sync + 'static> Server<S> {
         self
     }
 
+    pub fn on_calibration<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(CalibrationRequest, MessageId, &RpcTcp, &S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = CalibrationResponse> + Send + 'static,
+    {
+        self.handlers.on_calibration = Some(Box::new(move |request, msg_id, rpc, state| {
+            Box::pin(handler(request, msg_id, rpc, state))
+        }));
+        self
+    }
+
+    pub fn on_device_keys<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(DeviceKeysRequest, MessageId, &RpcTcp, &S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = DeviceKeysResponse> + Send + 'static,
+    {
+        self.handlers.on_device_keys = Some(Box::new(move |request, msg_id, rpc, state| {
+            Box::pin(handler(request, msg_id, rpc, state))
+        }));
+        self
+    }
+
+    pub fn on_firmware_manifest<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(FirmwareManifestRequest, MessageId, &RpcTcp, &S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = FirmwareManifestResponse> + Send + 'static,
+    {
+        self.handlers.on_firmware_manifest = Some(Box::new(move |request, msg_id, rpc, state| {
+            Box::pin(handler(request, msg_id, rpc, state))
+        }));
+        self
+    }
+
+    pub fn on_firmware_chunk<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(FirmwareChunkRequest, MessageId, &RpcTcp, &S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = FirmwareChunkResponse> + Send + 'static,
+    {
+        self.handlers.on_firmware_chunk = Some(Box::new(move |request, msg_id, rpc, state| {
+            Box::pin(handler(request, msg_id, rpc, state))
+        }));
+        self
+    }
+
+    pub fn on_actuator_commands<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(ActuatorCommandsRequest, MessageId, &RpcTcp, &S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActuatorCommandsResponse> + Send + 'static,
+    {
+        self.handlers.on_actuator_commands = Some(Box::new(move |request, msg_id, rpc, state| {
+            Box::pin(handler(request, msg_id, rpc, state))
+        }));
+        self
+    }
+
+    pub fn on_command_outcome<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(CommandOutcomeRequest, MessageId, &RpcTcp, &S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = CommandOutcomeResponse> + Send + 'static,
+    {
+        self.handlers.on_command_outcome = Some(Box::new(move |request, msg_id, rpc, state| {
+            Box::pin(handler(request, msg_id, rpc, state))
+        }));
+        self
+    }
+
+    pub fn on_firmware_report<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(FirmwareReportRequest, MessageId, &RpcTcp, &S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = FirmwareReportResponse> + Send + 'static,
+    {
+        self.handlers.on_firmware_report = Some(Box::new(move |request, msg_id, rpc, state| {
+            Box::pin(handler(request, msg_id, rpc, state))
+        }));
+        self
+    }
+
     async fn handle_connection(
         handlers: Arc<ServerHandlers<S>>,
         state: Arc<S>,
@@ -131,6 +226,8 @@ impl<S: Send + Sync + 'static> Server<S> {
         buffer_size: usize,
     ) {
         let mut rpc = RpcTcp::new(stream, buffer_size);
+        // Dispatcher the hello on this connection accepted
+        let mut dispatcher: Option<DispatcherId> = None;
 
         loop {
             let envelope = match rpc.recv().await {
@@ -144,6 +241,30 @@ impl<S: Send + Sync + 'static> Server<S> {
             let msg_id = envelope.msg_id;
             let payload = envelope.payload;
 
+            // Requests may only speak for the dispatcher that said hello
+            if let Some(claimed) = payload.dispatcher_id() {
+                let allowed = match dispatcher {
+                    Some(id) => id == claimed,
+                    None => matches!(payload, WireMessage::HelloRequest(_)),
+                };
+                if !allowed {
+                    tracing::warn!(
+                        ?claimed,
+                        accepted = ?dispatcher,
+                        "rejecting request for another dispatcher than the connection's"
+                    );
+                    let error = WireError {
+                        code: WireErrorCode::Unauthenticated,
+                        message: "request does not match the dispatcher of this connection"
+                            .to_string(),
+                    };
+                    if let Err(e) = rpc.reply(msg_id, WireMessage::Error(error)).await {
+                        tracing::error!("failed to send Error reply: {:?}", e);
+                    }
+                    continue;
+                }
+            }
+
             match payload {
                 WireMessage::Ping => {
                     if let Some(handler) = &handlers.on_ping {
@@ -156,7 +277,13 @@ impl<S: Send + Sync + 'static> Server<S> {
                 WireMessage::HelloRequest(hello) => {
                     if let Some(handler) = &handlers.on_hello {
                         let response = handler(hello, msg_id, &rpc, &state).await;
-                        let should_close = matches!(response, HelloResponse::Rejected { .. });
+                        let should_close = match response {
+                            HelloResponse::Accepted { dispatcher_id } => {
+                                dispatcher = Some(dispatcher_id);
+                                false
+                            }
+                            HelloResponse::Rejected { .. } => true,
+                        };
                         if let Err(e) = rpc
                             .reply(msg_id, WireMessage::HelloResponse(response))
                             .await
@@ -255,6 +382,138 @@ impl<S: Send + Sync + 'static> Server<S> {
                         "received DeviceDisconnectionResponse (unexpected on server): {res:?}"
                     );
                 }
+                WireMessage::CalibrationRequest(request) => {
+                    if let Some(handler) = &handlers.on_calibration {
+                        let response = handler(request, msg_id, &rpc, &state).await;
+                        if let Err(e) = rpc
+                            .reply(msg_id, WireMessage::CalibrationResponse(response))
+                            .await
+                        {
+                            tracing::error!("failed to send CalibrationResponse reply: {:?}", e);
+                        }
+                    } else {
+                        tracing::warn!("received CalibrationRequest but no handler registered");
+                    }
+                }
+                WireMessage::CalibrationResponse(res) => {
+                    tracing::debug!("received CalibrationResponse (unexpected on server): {res:?}");
+                }
+                WireMessage::DeviceKeysRequest(request) => {
+                    if let Some(handler) = &handlers.on_device_keys {
+                        let response = handler(request, msg_id, &rpc, &state).await;
+                        if let Err(e) = rpc
+                            .reply(msg_id, WireMessage::DeviceKeysResponse(response))
+                            .await
+                        {
+                            tracing::error!("failed to send DeviceKeysResponse reply: {:?}", e);
+                        }
+                    } else {
+                        tracing::warn!("received DeviceKeysRequest but no handler registered");
+                    }
+                }
+                WireMessage::DeviceKeysResponse(res) => {
+                    tracing::debug!("received DeviceKeysResponse (unexpected on server): {res:?}");
+                }
+                WireMessage::FirmwareManifestRequest(request) => {
+                    if let Some(handler) = &handlers.on_firmware_manifest {
+                        let response = handler(request, msg_id, &rpc, &state).await;
+                        if let Err(e) = rpc
+                            .reply(msg_id, WireMessage::FirmwareManifestResponse(response))
+                            .await
+                        {
+                            tracing::error!(
+                                "failed to send FirmwareManifestResponse reply: {:?}",
+                                e
+                            );
+                        }
+                    } else {
+                        tracing::warn!(
+                            "received FirmwareManifestRequest but no handler registered"
+                        );
+                    }
+                }
+                WireMessage::FirmwareManifestResponse(res) => {
+                    tracing::debug!(
+                        "received FirmwareManifestResponse (unexpected on server): {res:?}"
+                    );
+                }
+                WireMessage::FirmwareChunkRequest(request) => {
+                    if let Some(handler) = &handlers.on_firmware_chunk {
+                        let response = handler(request, msg_id, &rpc, &state).await;
+                        if let Err(e) = rpc
+                            .reply(msg_id, WireMessage::FirmwareChunkResponse(response))
+                            .await
+                        {
+                            tracing::error!("failed to send FirmwareChunkResponse reply: {:?}", e);
+                        }
+                    } else {
+                        tracing::warn!("received FirmwareChunkRequest but no handler registered");
+                    }
+                }
+                WireMessage::FirmwareChunkResponse(res) => {
+                    tracing::debug!(
+                        "received FirmwareChunkResponse (unexpected on server): {res:?}"
+                    );
+                }
+                WireMessage::ActuatorCommandsRequest(request) => {
+                    if let Some(handler) = &handlers.on_actuator_commands {
+                        let response = handler(request, msg_id, &rpc, &state).await;
+                        if let Err(e) = rpc
+                            .reply(msg_id, WireMessage::ActuatorCommandsResponse(response))
+                            .await
+                        {
+                            tracing::error!(
+                                "failed to send ActuatorCommandsResponse reply: {:?}",
+                                e
+                            );
+                        }
+                    } else {
+                        tracing::warn!(
+                            "received ActuatorCommandsRequest but no handler registered"
+                        );
+                    }
+                }
+                WireMessage::ActuatorCommandsResponse(res) => {
+                    tracing::debug!(
+                        "received ActuatorCommandsResponse (unexpected on server): {res:?}"
+                    );
+                }
+                WireMessage::CommandOutcomeRequest(request) => {
+                    if let Some(handler) = &handlers.on_command_outcome {
+                        let response = handler(request, msg_id, &rpc, &state).await;
+                        if let Err(e) = rpc
+                            .reply(msg_id, WireMessage::CommandOutcomeResponse(response))
+                            .await
+                        {
+                            tracing::error!("failed to send CommandOutcomeResponse reply: {:?}", e);
+                        }
+                    } else {
+                        tracing::warn!("received CommandOutcomeRequest but no handler registered");
+                    }
+                }
+                WireMessage::CommandOutcomeResponse(res) => {
+                    tracing::debug!(
+                        "received CommandOutcomeResponse (unexpected on server): {res:?}"
+                    );
+                }
+                WireMessage::FirmwareReportRequest(request) => {
+                    if let Some(handler) = &handlers.on_firmware_report {
+                        let response = handler(request, msg_id, &rpc, &state).await;
+                        if let Err(e) = rpc
+                            .reply(msg_id, WireMessage::FirmwareReportResponse(response))
+                            .await
+                        {
+                            tracing::error!("failed to send FirmwareReportResponse reply: {:?}", e);
+                        }
+                    } else {
+                        tracing::warn!("received FirmwareReportRequest but no handler registered");
+                    }
+                }
+                WireMessage::FirmwareReportResponse(res) => {
+                    tracing::debug!(
+                        "received FirmwareReportResponse (unexpected on server): {res:?}"
+                    );
+                }
                 WireMessage::Error(err) => {
                     tracing::warn!("received error: {:?}", err);
                 }